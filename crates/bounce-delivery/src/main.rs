@@ -2,16 +2,20 @@ use std::collections::hash_map::DefaultHasher;
 use std::fs::{self, OpenOptions};
 use std::hash::{Hash, Hasher};
 use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{fmt, process};
 
+use bouncer_proto::{Header, encode_header_json, read_ack_sync, write_frame_sync};
+
 const EX_USAGE: u8 = 64;
 const EX_TEMPFAIL: u8 = 75;
 const MAX_BODY_BYTES: usize = 25 * 1024 * 1024;
 const MAX_QUEUE_ID_LEN: usize = 64;
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
 
 static NONCE_COUNTER: AtomicU64 = AtomicU64::new(0);
 
@@ -31,7 +35,28 @@ fn main() -> ExitCode {
 
 fn run() -> Result<()> {
     let args = Cli::parse(std::env::args().skip(1))?;
-    let body = read_body(&mut io::stdin(), MAX_BODY_BYTES)?;
+    run_with_cli(&args, &mut io::stdin())
+}
+
+/// Forwards the mail to `--server` when set; the local incoming dir takes it
+/// when there is no server or the forward fails. Either way the mail carries
+/// the envelope block from [`envelope_headers`].
+fn run_with_cli<R: Read>(
+    args: &Cli,
+    stdin: &mut R
+) -> Result<()> {
+    let mut body = envelope_headers(args);
+    body.extend_from_slice(&read_body(stdin, MAX_BODY_BYTES)?);
+    if let Some(server) = &args.server {
+        match forward_to_server(args, server, &body) {
+            Ok(()) => return Ok(()),
+            Err(err) => {
+                eprintln!(
+                    "bounce-delivery: forward failed, spooling locally: server={server}, {err}"
+                )
+            }
+        }
+    }
     write_incoming_mail(&args.incoming_dir, args.queue_id.as_deref(), &body)?;
     Ok(())
 }
@@ -56,6 +81,84 @@ fn read_body<R: Read>(
     Ok(body)
 }
 
+/// `X-Bouncer-*` header lines for the Postfix envelope arguments that were
+/// given, so the server can take the hash and recipient from the VERP
+/// envelope recipient even when the report itself lost them.
+fn envelope_headers(args: &Cli) -> Vec<u8> {
+    let fields = [
+        ("X-Bouncer-Envelope-From", args.from.clone()),
+        ("X-Bouncer-Envelope-To", args.to.clone()),
+        ("X-Bouncer-Original-To", args.original_to.clone()),
+        ("X-Bouncer-Queue-Id", args.queue_id.clone()),
+        ("X-Bouncer-Size", args.size.map(|size| size.to_string()))
+    ];
+
+    let mut block = String::new();
+    for (name, value) in fields {
+        let Some(value) = value else {
+            continue;
+        };
+        let value: String = value.chars().filter(|ch| !ch.is_control()).collect();
+        block.push_str(&format!("{name}: {}\r\n", value.trim()));
+    }
+    block.into_bytes()
+}
+
+/// Sends one mail frame to the first address of `server` that ACKs it.
+fn forward_to_server(
+    args: &Cli,
+    server: &str,
+    body: &[u8]
+) -> Result<()> {
+    let header = Header {
+        from: args.from.clone().unwrap_or_default(),
+        to: args.to.clone().or_else(|| args.original_to.clone()).unwrap_or_default(),
+        kind: None,
+        source: None,
+        traceparent: None,
+        queue_id: args.queue_id.clone(),
+        tenant: None,
+        checksum: None
+    };
+    let header_bytes =
+        encode_header_json(&header).map_err(|err| runtime_err("failed to encode header", err))?;
+    let timeout = Duration::from_secs(args.timeout_secs);
+
+    let addrs = server
+        .to_socket_addrs()
+        .map_err(|err| runtime_err(format!("failed to resolve server address: {server}"), err))?;
+    let mut last_err = None;
+    for addr in addrs {
+        match send_frame_and_wait_ack(addr, timeout, &header_bytes, body) {
+            Ok(()) => return Ok(()),
+            Err(err) => last_err = Some(err)
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        DeliveryError::Runtime(format!("no address resolved for server: {server}"))
+    }))
+}
+
+fn send_frame_and_wait_ack(
+    addr: SocketAddr,
+    timeout: Duration,
+    header_bytes: &[u8],
+    body: &[u8]
+) -> Result<()> {
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)
+        .map_err(|err| runtime_err(format!("failed to connect to {addr}"), err))?;
+    stream.set_nodelay(true).ok();
+    stream
+        .set_write_timeout(Some(timeout))
+        .and_then(|()| stream.set_read_timeout(Some(timeout)))
+        .map_err(|err| runtime_err("failed to set socket timeouts", err))?;
+
+    write_frame_sync(&mut stream, header_bytes, body)
+        .map_err(|err| runtime_err(format!("failed to send frame to {addr}"), err))?;
+    read_ack_sync(&mut stream)
+        .map_err(|err| runtime_err(format!("invalid/missing ACK from {addr}"), err))
+}
+
 fn write_incoming_mail(
     incoming_dir: &Path,
     queue_id: Option<&str>,
@@ -142,8 +245,16 @@ fn build_nonce_hex(
 
 #[derive(Debug)]
 struct Cli {
+    /// Where mail is written, or the fallback when `server` is set.
     incoming_dir: PathBuf,
-    queue_id: Option<String>
+    /// Forward to this bouncer-server (`host:port`) instead.
+    server: Option<String>,
+    queue_id: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    original_to: Option<String>,
+    size: Option<u64>,
+    timeout_secs: u64
 }
 
 impl Cli {
@@ -152,7 +263,13 @@ impl Cli {
         I: Iterator<Item = String>
     {
         let mut incoming_dir: Option<PathBuf> = None;
+        let mut server: Option<String> = None;
         let mut queue_id: Option<String> = None;
+        let mut from: Option<String> = None;
+        let mut to: Option<String> = None;
+        let mut original_to: Option<String> = None;
+        let mut size: Option<u64> = None;
+        let mut timeout_secs = DEFAULT_TIMEOUT_SECS;
 
         while let Some(arg) = args.next() {
             match arg.as_str() {
@@ -167,14 +284,48 @@ impl Cli {
                         DeliveryError::Usage("missing value for --queue-id".to_string())
                     })?);
                 }
-                "--from" | "--to" | "--original-to" | "--size" => {
-                    let _ = args
-                        .next()
-                        .ok_or_else(|| DeliveryError::Usage(format!("missing value for {arg}")))?;
+                "--server" => {
+                    server = Some(args.next().ok_or_else(|| {
+                        DeliveryError::Usage("missing value for --server".to_string())
+                    })?);
+                }
+                "--timeout-secs" => {
+                    let raw = args.next().ok_or_else(|| {
+                        DeliveryError::Usage("missing value for --timeout-secs".to_string())
+                    })?;
+                    timeout_secs =
+                        raw.parse::<u64>().ok().filter(|secs| *secs > 0).ok_or_else(|| {
+                            DeliveryError::Usage(
+                                "--timeout-secs must be a positive integer".to_string()
+                            )
+                        })?;
+                }
+                "--from" => {
+                    from = Some(args.next().ok_or_else(|| {
+                        DeliveryError::Usage("missing value for --from".to_string())
+                    })?);
+                }
+                "--to" => {
+                    to = Some(args.next().ok_or_else(|| {
+                        DeliveryError::Usage("missing value for --to".to_string())
+                    })?);
+                }
+                "--original-to" => {
+                    original_to = Some(args.next().ok_or_else(|| {
+                        DeliveryError::Usage("missing value for --original-to".to_string())
+                    })?);
+                }
+                "--size" => {
+                    let raw = args.next().ok_or_else(|| {
+                        DeliveryError::Usage("missing value for --size".to_string())
+                    })?;
+                    size = Some(raw.parse::<u64>().map_err(|_| {
+                        DeliveryError::Usage("--size must be a non-negative integer".to_string())
+                    })?);
                 }
                 "-h" | "--help" => {
                     return Err(DeliveryError::Usage(
-                        "usage: bounce-delivery --incoming-dir PATH [--queue-id QUEUE_ID] [--from SENDER] [--to RECIPIENT] [--original-to RECIPIENT] [--size BYTES]"
+                        "usage: bounce-delivery --incoming-dir PATH [--server HOST:PORT] [--timeout-secs 10] [--queue-id QUEUE_ID] [--from SENDER] [--to RECIPIENT] [--original-to RECIPIENT] [--size BYTES]"
                             .to_string(),
                     ));
                 }
@@ -188,7 +339,13 @@ impl Cli {
             incoming_dir: incoming_dir.ok_or_else(|| {
                 DeliveryError::Usage("missing required argument --incoming-dir".to_string())
             })?,
-            queue_id
+            server,
+            queue_id,
+            from,
+            to,
+            original_to,
+            size,
+            timeout_secs
         })
     }
 }
@@ -224,6 +381,12 @@ type Result<T> = std::result::Result<T, DeliveryError>;
 
 #[cfg(test)]
 mod tests {
+    use std::io::Cursor;
+    use std::net::TcpListener;
+    use std::thread;
+
+    use bouncer_proto::{ACK, MAGIC, decode_header_json};
+
     use super::*;
 
     #[test]
@@ -244,4 +407,91 @@ mod tests {
         assert_eq!(nonce.len(), 16);
         assert!(nonce.chars().all(|c| c.is_ascii_hexdigit()));
     }
+
+    #[test]
+    fn forwards_to_server_with_queue_metadata() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind test listener");
+        let addr = listener.local_addr().expect("local addr");
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let (header, body) = read_frame(&mut stream).expect("frame");
+            stream.write_all(ACK).expect("ack write");
+            (decode_header_json(&header).expect("decode header"), body)
+        });
+
+        let incoming = temp_incoming("forward");
+        let cli = cli(&incoming, addr.to_string());
+        run_with_cli(&cli, &mut Cursor::new(b"bounce".to_vec())).expect("forward");
+
+        let (header, body) = handle.join().expect("server thread join");
+        assert_eq!(header.queue_id.as_deref(), Some("4F2A1B3C"));
+        assert_eq!(header.from, "MAILER-DAEMON");
+        assert_eq!(header.to, "bounces+4a22@example.com");
+        assert!(body.ends_with(b"\r\nbounce"));
+        assert!(!incoming.exists());
+    }
+
+    #[test]
+    fn spools_locally_when_server_is_unreachable() {
+        let dead = TcpListener::bind("127.0.0.1:0").expect("bind test listener");
+        let dead_addr = dead.local_addr().expect("local addr");
+        drop(dead);
+
+        let incoming = temp_incoming("fallback");
+        let cli = cli(&incoming, dead_addr.to_string());
+        run_with_cli(&cli, &mut Cursor::new(b"bounce".to_vec())).expect("local fallback");
+
+        let files: Vec<_> = fs::read_dir(&incoming)
+            .expect("read incoming")
+            .map(|entry| entry.expect("entry").path())
+            .collect();
+        assert_eq!(files.len(), 1, "{files:?}");
+        assert!(files[0].to_string_lossy().contains("-4F2A1B3C-"));
+        let spooled = String::from_utf8(fs::read(&files[0]).expect("read spooled")).unwrap();
+        assert_eq!(
+            spooled,
+            concat!(
+                "X-Bouncer-Envelope-From: MAILER-DAEMON\r\n",
+                "X-Bouncer-Envelope-To: bounces+4a22@example.com\r\n",
+                "X-Bouncer-Original-To: bounce-4a22@example.com\r\n",
+                "X-Bouncer-Queue-Id: 4F2A1B3C\r\n",
+                "X-Bouncer-Size: 6\r\n",
+                "bounce"
+            )
+        );
+        fs::remove_dir_all(&incoming).expect("cleanup");
+    }
+
+    fn cli(
+        incoming_dir: &Path,
+        server: String
+    ) -> Cli {
+        Cli {
+            incoming_dir: incoming_dir.to_path_buf(),
+            server: Some(server),
+            queue_id: Some("4F2A1B3C".to_string()),
+            from: Some("MAILER-DAEMON".to_string()),
+            to: Some("bounces+4a22@example.com".to_string()),
+            original_to: Some("bounce-4a22@example.com".to_string()),
+            size: Some(6),
+            timeout_secs: 1
+        }
+    }
+
+    fn temp_incoming(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("bounce-delivery-{name}-{}", process::id()))
+    }
+
+    fn read_frame<R: Read>(reader: &mut R) -> io::Result<(Vec<u8>, Vec<u8>)> {
+        let mut prefix = [0u8; 16];
+        reader.read_exact(&mut prefix)?;
+        assert_eq!(prefix[..4], MAGIC);
+        let header_len = u32::from_be_bytes(prefix[4..8].try_into().unwrap()) as usize;
+        let body_len = u64::from_be_bytes(prefix[8..16].try_into().unwrap()) as usize;
+        let mut header = vec![0u8; header_len];
+        reader.read_exact(&mut header)?;
+        let mut body = vec![0u8; body_len];
+        reader.read_exact(&mut body)?;
+        Ok((header, body))
+    }
 }
@@ -1,14 +1,26 @@
-use std::fmt;
-use std::io::{self, Read};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, ErrorKind, IsTerminal, Read, Write};
 use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fmt, process, thread};
 
-use bouncer_proto::{Header, encode_header_json, read_ack_sync, write_frame_sync};
+use bouncer_proto::{
+    Header, ProtoError, Reply, encode_header_json, read_ack_sync, read_reply_sync, write_frame_sync
+};
 
 const EX_TEMPFAIL: u8 = 75;
 const EX_USAGE: u8 = 64;
-const MAX_BODY_BYTES: usize = 50 * 1024;
+const EX_DATAERR: u8 = 65;
+/// Defaults match the server's `max_header_bytes` / `max_body_bytes`.
+const DEFAULT_MAX_HEADER_BYTES: usize = 64 * 1024;
+const DEFAULT_MAX_BODY_BYTES: usize = 25 * 1024 * 1024;
+/// Pause before the second round of attempts; doubles every round.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
 
 type Result<T> = std::result::Result<T, ClientError>;
 
@@ -18,6 +30,8 @@ fn main() -> ExitCode {
         Err(err) => {
             let code = match err {
                 ClientError::Usage(_) => EX_USAGE,
+                // Retrying cannot make the mail fit; let the MTA give up.
+                ClientError::TooLarge(_) => EX_DATAERR,
                 ClientError::Runtime(_) => EX_TEMPFAIL
             };
             eprintln!("bouncer-client error: {err}");
@@ -27,19 +41,147 @@ fn main() -> ExitCode {
 }
 
 fn run() -> Result<()> {
-    let args = Cli::parse(std::env::args().skip(1))?;
-    run_with_cli(args, &mut io::stdin())
+    let mut args = Cli::parse(std::env::args().skip(1))?;
+    // W3C trace context handed down by the caller (e.g. an instrumented MTA
+    // wrapper); forwarded so the server joins the caller's trace.
+    args.traceparent = std::env::var("TRACEPARENT").ok().filter(|value| !value.is_empty());
+    if args.interactive {
+        let stdin = io::stdin();
+        let prompt = stdin.is_terminal();
+        return run_interactive(&args, &mut stdin.lock(), prompt);
+    }
+    match args.file.clone() {
+        Some(path) => {
+            let mut file = File::open(&path)
+                .map_err(|err| runtime_err(format!("failed to open {}", path.display()), err))?;
+            run_with_cli(args, &mut file)
+        }
+        None => run_with_cli(args, &mut io::stdin())
+    }
 }
 
 fn run_with_cli<R: Read>(
     args: Cli,
     stdin: &mut R
 ) -> Result<()> {
-    let body = read_body(stdin, MAX_BODY_BYTES)?;
-    let header_bytes = build_header_bytes(&args)?;
+    let body = read_body(stdin, args.max_body_bytes)?;
+    let header_bytes = build_header_bytes(&args, &body)?;
+    let timeout = Duration::from_secs(args.timeout_secs);
+
+    // One round tries every address of every `--server` in order; after a
+    // failed round the client backs off and resolves again. The server
+    // dedups replays by content, so resending after a lost ACK is safe.
+    let mut last_err = None;
+    for round in 0..=args.retries {
+        if round > 0 {
+            thread::sleep(backoff_delay(round));
+        }
+        for server in &args.servers {
+            let addrs = match resolve_socket_addrs(server) {
+                Ok(addrs) => addrs,
+                Err(err) => {
+                    eprintln!("bouncer-client: attempt failed: round={round}, {err}");
+                    last_err = Some(err);
+                    continue;
+                }
+            };
+            for addr in addrs {
+                let sent = connect(addr, timeout).and_then(|mut stream| {
+                    exchange(&mut stream, addr, &args, &header_bytes, &body)
+                });
+                match sent {
+                    Ok(reply) => {
+                        if args.wait_response {
+                            println!("{}", describe_reply(&reply));
+                        }
+                        return Ok(());
+                    }
+                    Err(err @ ClientError::TooLarge(_)) => return Err(err),
+                    Err(err) => {
+                        eprintln!("bouncer-client: attempt failed: round={round}, {err}");
+                        last_err = Some(err);
+                    }
+                }
+            }
+        }
+    }
+
+    let last_err = last_err.map_or_else(|| "no server".to_string(), |err| err.to_string());
+    let err = format!("all servers failed after {} round(s): {last_err}", args.retries + 1);
+    let Some(spool_dir) = &args.spool_dir else {
+        return Err(ClientError::Runtime(err));
+    };
+
+    // Same host as the server: hand the mail to its spool instead of letting
+    // Postfix defer it while the listener is down.
+    match write_spool_fallback(spool_dir, args.tenant.as_deref(), &body) {
+        Ok(path) => {
+            eprintln!("bouncer-client: {err}; spooled to {}", path.display());
+            Ok(())
+        }
+        Err(spool_err) => {
+            Err(ClientError::Runtime(format!("{err}; spool fallback failed: {spool_err}")))
+        }
+    }
+}
+
+/// `--interactive`: sends every input line as the body of one frame over a
+/// single connection and prints each reply, in place of hand-crafting frames
+/// with netcat. A dropped connection is reopened for the next line.
+fn run_interactive<R: BufRead>(
+    args: &Cli,
+    input: &mut R,
+    prompt: bool
+) -> Result<()> {
     let timeout = Duration::from_secs(args.timeout_secs);
-    let addr = resolve_socket_addr(&args.server)?;
-    send_frame_and_wait_ack(addr, timeout, &header_bytes, &body)
+    let mut connection: Option<(TcpStream, SocketAddr)> = None;
+    let mut line = String::new();
+    loop {
+        if prompt {
+            eprint!("bouncer> ");
+            io::stderr().flush().ok();
+        }
+        line.clear();
+        if input.read_line(&mut line).map_err(|err| runtime_err("failed to read input", err))? == 0
+        {
+            return Ok(());
+        }
+        let body = line.trim_end_matches(['\r', '\n']);
+        if body.is_empty() {
+            continue;
+        }
+        let header_bytes = match build_header_bytes(args, body.as_bytes()) {
+            Ok(header_bytes) => header_bytes,
+            Err(err) => {
+                eprintln!("bouncer-client: {err}");
+                continue;
+            }
+        };
+
+        let (mut stream, addr) = match connection.take() {
+            Some(open) => open,
+            None => match connect_any(&args.servers, timeout) {
+                Ok(open) => open,
+                Err(err) => {
+                    eprintln!("bouncer-client: {err}");
+                    continue;
+                }
+            }
+        };
+        match exchange(&mut stream, addr, args, &header_bytes, body.as_bytes()) {
+            Ok(reply) => {
+                println!("{}", describe_reply(&reply));
+                connection = Some((stream, addr));
+            }
+            Err(err) => eprintln!("bouncer-client: {err}")
+        }
+    }
+}
+
+/// Delay before retry round `round` (1-based): 250ms, 500ms, 1s, ... capped
+/// at 5s.
+fn backoff_delay(round: u32) -> Duration {
+    INITIAL_BACKOFF.saturating_mul(1 << round.saturating_sub(1).min(16)).min(MAX_BACKOFF)
 }
 
 fn read_body<R: Read>(
@@ -52,7 +194,7 @@ fn read_body<R: Read>(
         .read_to_end(&mut body)
         .map_err(|err| runtime_err("failed to read mail from stdin", err))?;
     if body.len() > max_body_bytes {
-        return Err(ClientError::Runtime(format!(
+        return Err(ClientError::TooLarge(format!(
             "mail body too large: max {} bytes",
             max_body_bytes
         )));
@@ -60,20 +202,64 @@ fn read_body<R: Read>(
     Ok(body)
 }
 
-fn build_header_bytes(args: &Cli) -> Result<Vec<u8>> {
-    let header = Header { from: args.from.clone(), to: args.to.clone(), kind: None, source: None };
+/// Header of a frame carrying `body`; with `--checksum` it names the CRC32
+/// of `body`.
+fn build_header_bytes(
+    args: &Cli,
+    body: &[u8]
+) -> Result<Vec<u8>> {
+    let header = Header {
+        from: args.from.clone(),
+        to: args.to.clone(),
+        kind: args.kind.clone(),
+        source: args.source.clone(),
+        traceparent: args.traceparent.clone(),
+        queue_id: None,
+        tenant: args.tenant.clone(),
+        checksum: None
+    };
+    let header = if args.checksum { header.with_checksum(body) } else { header };
     let header_bytes = encode_header_json(&header)
         .map_err(|err| runtime_err("failed to serialize header", err))?;
+    if header_bytes.len() > args.max_header_bytes {
+        return Err(ClientError::TooLarge(format!(
+            "frame header too large: {} bytes, max {} bytes",
+            header_bytes.len(),
+            args.max_header_bytes
+        )));
+    }
     Ok(header_bytes)
 }
 
-fn send_frame_and_wait_ack(
+/// First address of `servers` that accepts a connection.
+fn connect_any(
+    servers: &[String],
+    timeout: Duration
+) -> Result<(TcpStream, SocketAddr)> {
+    let mut last_err = ClientError::Runtime("no server".to_string());
+    for server in servers {
+        let addrs = match resolve_socket_addrs(server) {
+            Ok(addrs) => addrs,
+            Err(err) => {
+                last_err = err;
+                continue;
+            }
+        };
+        for addr in addrs {
+            match connect(addr, timeout) {
+                Ok(stream) => return Ok((stream, addr)),
+                Err(err) => last_err = err
+            }
+        }
+    }
+    Err(last_err)
+}
+
+fn connect(
     addr: SocketAddr,
-    timeout: Duration,
-    header_bytes: &[u8],
-    body: &[u8]
-) -> Result<()> {
-    let mut stream = TcpStream::connect_timeout(&addr, timeout)
+    timeout: Duration
+) -> Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(&addr, timeout)
         .map_err(|err| runtime_err(format!("failed to connect to {}", addr), err))?;
     stream.set_nodelay(true).ok();
 
@@ -85,29 +271,136 @@ fn send_frame_and_wait_ack(
         .set_read_timeout(Some(timeout))
         .map_err(|err| runtime_err("failed to set read timeout", err))?;
 
-    write_frame_sync(&mut stream, header_bytes, body)
+    Ok(stream)
+}
+
+/// Sends one frame and reads the server's ACK, or with `--wait-response`
+/// any reply, response frames included.
+fn exchange(
+    stream: &mut TcpStream,
+    addr: SocketAddr,
+    args: &Cli,
+    header_bytes: &[u8],
+    body: &[u8]
+) -> Result<Reply> {
+    write_frame_sync(stream, header_bytes, body)
         .map_err(|err| runtime_err("failed to send frame", err))?;
 
-    read_ack_sync(&mut stream)
-        .map_err(|err| runtime_err("invalid/missing ACK from server", err))?;
+    let reply = if args.wait_response {
+        read_reply_sync(stream, args.max_header_bytes as u32, args.max_body_bytes as u64)
+    } else {
+        read_ack_sync(stream).map(|()| Reply::Ack)
+    };
+    reply.map_err(|err| match err {
+        ProtoError::RejectedTooLarge => {
+            ClientError::TooLarge(format!("server {addr} rejected the frame as too large"))
+        }
+        // Corrupted on the way; worth another attempt.
+        ProtoError::RejectedChecksum => {
+            ClientError::Runtime(format!("server {addr} rejected the frame checksum"))
+        }
+        // The server closes the connection on a frame it cannot handle
+        // instead of answering it; the reason is only in its log.
+        ProtoError::Io(err) if args.wait_response && err.kind() == ErrorKind::UnexpectedEof => {
+            ClientError::Runtime(format!(
+                "server {addr} closed the connection without a reply; see the server log"
+            ))
+        }
+        err => runtime_err("invalid/missing ACK from server", err)
+    })
+}
+
+/// `OK` for an ACK; the header and body of a response frame.
+fn describe_reply(reply: &Reply) -> String {
+    match reply {
+        Reply::Ack => "OK".to_string(),
+        Reply::Frame { header, body } => {
+            format!("{}\n{}", String::from_utf8_lossy(header), String::from_utf8_lossy(body))
+        }
+    }
+}
+
+/// Writes `body` into the server's `incoming/` directory as a hidden temp
+/// file, fsyncs it and renames it to `.eml`, so the server never picks up a
+/// partial file. The directory must exist; the server owns the spool layout.
+/// `tenant` goes into the name as `.tenant-<tenant>`, where the server reads
+/// it back.
+fn write_spool_fallback(
+    incoming_dir: &Path,
+    tenant: Option<&str>,
+    body: &[u8]
+) -> Result<PathBuf> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let pid = process::id();
+    let mut hasher = DefaultHasher::new();
+    now.as_nanos().hash(&mut hasher);
+    pid.hash(&mut hasher);
+    body.hash(&mut hasher);
+    let mut base = format!("{}-{pid}-client-{:016x}", now.as_millis(), hasher.finish());
+    if let Some(tenant) = tenant {
+        base.push_str(&format!(".tenant-{tenant}"));
+    }
+    let tmp_path = incoming_dir.join(format!(".{base}.tmp"));
+    let final_path = incoming_dir.join(format!("{base}.eml"));
+
+    let mut file = OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(&tmp_path)
+        .map_err(|err| runtime_err(format!("failed to create {}", tmp_path.display()), err))?;
+    if let Err(err) = file.write_all(body).and_then(|()| file.sync_all()) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(runtime_err(format!("failed to write {}", tmp_path.display()), err));
+    }
+    drop(file);
 
-    Ok(())
+    fs::rename(&tmp_path, &final_path).map_err(|err| {
+        let _ = fs::remove_file(&tmp_path);
+        runtime_err(format!("failed to move temp file into {}", incoming_dir.display()), err)
+    })?;
+    Ok(final_path)
 }
 
-fn resolve_socket_addr(server: &str) -> Result<SocketAddr> {
-    server
+/// All addresses `server` resolves to, so a multi-A name fails over between
+/// its hosts.
+fn resolve_socket_addrs(server: &str) -> Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = server
         .to_socket_addrs()
         .map_err(|err| runtime_err(format!("failed to resolve server address: {server}"), err))?
-        .next()
-        .ok_or_else(|| ClientError::Runtime(format!("no address resolved for server: {server}")))
+        .collect();
+    if addrs.is_empty() {
+        return Err(ClientError::Runtime(format!("no address resolved for server: {server}")));
+    }
+    Ok(addrs)
 }
 
 #[derive(Debug)]
 struct Cli {
-    server: String,
+    /// Tried in order; repeat `--server` to add failover endpoints.
+    servers: Vec<String>,
     from: String,
     to: String,
-    timeout_secs: u64
+    kind: Option<String>,
+    /// Frame `source`, e.g. to pose as an agent.
+    source: Option<String>,
+    /// Frame `tenant`; the bounce then only matches messages of that tenant.
+    tenant: Option<String>,
+    traceparent: Option<String>,
+    timeout_secs: u64,
+    /// Extra rounds over all servers after the first one failed.
+    retries: u32,
+    /// Server `incoming/` directory written to when every server failed.
+    spool_dir: Option<PathBuf>,
+    max_header_bytes: usize,
+    /// Larger mail fails permanently instead of being sent.
+    max_body_bytes: usize,
+    /// Read the body from this file instead of stdin.
+    file: Option<PathBuf>,
+    /// Print the server's reply instead of only checking for an ACK.
+    wait_response: bool,
+    interactive: bool,
+    /// Send a body checksum; the server rejects a corrupted frame.
+    checksum: bool
 }
 
 impl Cli {
@@ -115,16 +408,48 @@ impl Cli {
     where
         I: Iterator<Item = String>
     {
-        let mut server = None;
+        let mut servers = Vec::new();
         let mut from = None;
         let mut to = None;
+        let mut kind = None;
+        let mut source = None;
+        let mut tenant = None;
+        let mut file = None;
+        let mut wait_response = false;
+        let mut interactive = false;
+        let mut checksum = false;
         let mut timeout_secs = 10_u64;
+        let mut retries = 2_u32;
+        let mut spool_dir = None;
+        let mut max_header_bytes = DEFAULT_MAX_HEADER_BYTES;
+        let mut max_body_bytes = DEFAULT_MAX_BODY_BYTES;
 
         while let Some(arg) = args.next() {
             match arg.as_str() {
-                "--server" => server = args.next(),
+                "--server" => servers.extend(args.next()),
                 "--from" => from = args.next(),
                 "--to" => to = args.next(),
+                "--kind" => kind = args.next(),
+                "--source" => source = args.next(),
+                "--tenant" => {
+                    let raw = args.next().ok_or_else(|| {
+                        ClientError::Usage("missing value for --tenant".to_string())
+                    })?;
+                    if !bouncer_proto::valid_tenant(&raw) {
+                        return Err(ClientError::Usage(
+                            "--tenant may only hold letters, digits, `_` and `-`".to_string()
+                        ));
+                    }
+                    tenant = Some(raw);
+                }
+                "--file" => file = args.next().map(PathBuf::from),
+                "--wait-response" => wait_response = true,
+                "--checksum" => checksum = true,
+                // Replies are printed for every line.
+                "--interactive" => {
+                    interactive = true;
+                    wait_response = true;
+                }
                 "--timeout-secs" => {
                     let raw = args.next().ok_or_else(|| {
                         ClientError::Usage("missing value for --timeout-secs".to_string())
@@ -133,9 +458,45 @@ impl Cli {
                         ClientError::Usage("--timeout-secs must be a positive integer".to_string())
                     })?;
                 }
+                "--retries" => {
+                    let raw = args.next().ok_or_else(|| {
+                        ClientError::Usage("missing value for --retries".to_string())
+                    })?;
+                    retries = raw.parse::<u32>().map_err(|_| {
+                        ClientError::Usage("--retries must be a non-negative integer".to_string())
+                    })?;
+                }
+                "--spool-dir" => {
+                    let raw = args.next().ok_or_else(|| {
+                        ClientError::Usage("missing value for --spool-dir".to_string())
+                    })?;
+                    spool_dir = Some(PathBuf::from(raw));
+                }
+                "--max-header-bytes" => {
+                    let raw = args.next().ok_or_else(|| {
+                        ClientError::Usage("missing value for --max-header-bytes".to_string())
+                    })?;
+                    max_header_bytes =
+                        raw.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| {
+                            ClientError::Usage(
+                                "--max-header-bytes must be a positive integer".to_string()
+                            )
+                        })?;
+                }
+                "--max-body-bytes" => {
+                    let raw = args.next().ok_or_else(|| {
+                        ClientError::Usage("missing value for --max-body-bytes".to_string())
+                    })?;
+                    max_body_bytes =
+                        raw.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| {
+                            ClientError::Usage(
+                                "--max-body-bytes must be a positive integer".to_string()
+                            )
+                        })?;
+                }
                 "-h" | "--help" => {
                     return Err(ClientError::Usage(
-                        "usage: bouncer-client --server host:port --from sender --to recipient [--timeout-secs 10]"
+                        "usage: bouncer-client --server host:port [--server host:port ...] --from sender --to recipient [--kind backfill] [--timeout-secs 10] [--retries 2] [--spool-dir /var/spool/bouncer/incoming] [--max-header-bytes 65536] [--max-body-bytes 26214400] [--source name] [--tenant name] [--file mail.eml | --interactive] [--wait-response] [--checksum]"
                             .to_string(),
                     ));
                 }
@@ -145,16 +506,36 @@ impl Cli {
             }
         }
 
+        if servers.is_empty() {
+            return Err(ClientError::Usage("missing required argument --server".to_string()));
+        }
+        if interactive && file.is_some() {
+            return Err(ClientError::Usage(
+                "--interactive reads frames from stdin and cannot be combined with --file"
+                    .to_string()
+            ));
+        }
+
         Ok(Self {
-            server: server.ok_or_else(|| {
-                ClientError::Usage("missing required argument --server".to_string())
-            })?,
+            servers,
             from: from.ok_or_else(|| {
                 ClientError::Usage("missing required argument --from".to_string())
             })?,
             to: to
                 .ok_or_else(|| ClientError::Usage("missing required argument --to".to_string()))?,
-            timeout_secs
+            kind,
+            source,
+            tenant,
+            traceparent: None,
+            timeout_secs,
+            retries,
+            spool_dir,
+            max_header_bytes,
+            max_body_bytes,
+            file,
+            wait_response,
+            interactive,
+            checksum
         })
     }
 }
@@ -162,6 +543,8 @@ impl Cli {
 #[derive(Debug)]
 enum ClientError {
     Usage(String),
+    /// The mail or its header exceeds a local or server limit; permanent.
+    TooLarge(String),
     Runtime(String)
 }
 
@@ -172,6 +555,7 @@ impl fmt::Display for ClientError {
     ) -> fmt::Result {
         match self {
             ClientError::Usage(msg) => write!(f, "{msg}"),
+            ClientError::TooLarge(msg) => write!(f, "{msg}"),
             ClientError::Runtime(msg) => write!(f, "{msg}")
         }
     }
@@ -192,9 +576,13 @@ mod tests {
     use std::net::TcpListener;
     use std::thread;
 
-    use bouncer_proto::{ACK, MAGIC, decode_header_json};
+    use bouncer_proto::{ACK, MAGIC, Reply, TOO_LARGE, decode_header_json, write_frame_sync};
 
-    use super::{Cli, ClientError, build_header_bytes, read_body, run_with_cli};
+    use super::{
+        Cli, ClientError, DEFAULT_MAX_BODY_BYTES, DEFAULT_MAX_HEADER_BYTES, INITIAL_BACKOFF,
+        MAX_BACKOFF, backoff_delay, build_header_bytes, connect, describe_reply, exchange,
+        read_body, run_interactive, run_with_cli
+    };
 
     #[test]
     fn cli_parse_success() {
@@ -207,12 +595,17 @@ mod tests {
             "bounces@example.com".to_string(),
             "--timeout-secs".to_string(),
             "3".to_string(),
+            "--max-body-bytes".to_string(),
+            "1048576".to_string(),
         ];
         let cli = Cli::parse(args.into_iter()).expect("parse should succeed");
-        assert_eq!(cli.server, "127.0.0.1:2147");
+        assert_eq!(cli.servers, ["127.0.0.1:2147"]);
         assert_eq!(cli.from, "sender@example.com");
         assert_eq!(cli.to, "bounces@example.com");
         assert_eq!(cli.timeout_secs, 3);
+        assert_eq!(cli.retries, 2);
+        assert_eq!(cli.max_body_bytes, 1_048_576);
+        assert_eq!(cli.max_header_bytes, DEFAULT_MAX_HEADER_BYTES);
     }
 
     #[test]
@@ -236,32 +629,81 @@ mod tests {
         }
     }
 
+    #[test]
+    fn cli_parse_debugging_flags() {
+        let args = [
+            "--server",
+            "127.0.0.1:2147",
+            "--from",
+            "observer",
+            "--to",
+            "server",
+            "--kind",
+            "observer_event",
+            "--source",
+            "mx-01",
+            "--tenant",
+            "shop",
+            "--file",
+            "event.json",
+            "--wait-response"
+        ];
+        let cli = Cli::parse(args.into_iter().map(String::from)).expect("parse should succeed");
+        assert_eq!(cli.kind.as_deref(), Some("observer_event"));
+        assert_eq!(cli.source.as_deref(), Some("mx-01"));
+        assert_eq!(cli.tenant.as_deref(), Some("shop"));
+        assert_eq!(cli.file.as_deref(), Some(std::path::Path::new("event.json")));
+        assert!(cli.wait_response && !cli.interactive);
+
+        let err = Cli::parse(args.into_iter().chain(["--interactive"]).map(String::from))
+            .expect_err("--file and --interactive conflict");
+        assert!(matches!(err, ClientError::Usage(msg) if msg.contains("--interactive")));
+    }
+
     #[test]
     fn read_body_respects_limit() {
         let mut input = Cursor::new(b"012345".to_vec());
         let err = read_body(&mut input, 5).expect_err("should fail on limit");
         match err {
-            ClientError::Runtime(msg) => {
+            ClientError::TooLarge(msg) => {
                 assert!(msg.contains("mail body too large: max 5 bytes"));
             }
-            _ => panic!("expected runtime error")
+            _ => panic!("expected too large error")
         }
     }
 
     #[test]
     fn build_header_bytes_contains_expected_fields() {
         let cli = Cli {
-            server: "127.0.0.1:2147".to_string(),
+            servers: vec!["127.0.0.1:2147".to_string()],
             from: "sender@example.com".to_string(),
             to: "bounces@example.com".to_string(),
-            timeout_secs: 10
+            kind: None,
+            source: None,
+            tenant: None,
+            traceparent: None,
+            timeout_secs: 10,
+            retries: 0,
+            spool_dir: None,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            file: None,
+            wait_response: false,
+            interactive: false,
+            checksum: false
         };
-        let encoded = build_header_bytes(&cli).expect("header build");
+        let encoded = build_header_bytes(&cli, b"").expect("header build");
         let decoded = decode_header_json(&encoded).expect("header decode");
         assert_eq!(decoded.from, "sender@example.com");
         assert_eq!(decoded.to, "bounces@example.com");
         assert!(decoded.kind.is_none());
         assert!(decoded.source.is_none());
+        assert!(decoded.checksum.is_none());
+
+        let cli = Cli { checksum: true, ..cli };
+        let encoded = build_header_bytes(&cli, b"mail").expect("header build");
+        let decoded = decode_header_json(&encoded).expect("header decode");
+        assert_eq!(decoded.checksum, Some(bouncer_proto::body_checksum(b"mail")));
     }
 
     #[test]
@@ -283,10 +725,22 @@ mod tests {
         });
 
         let cli = Cli {
-            server: addr.to_string(),
+            servers: vec![addr.to_string()],
             from: "sender@example.com".to_string(),
             to: "bounces@example.com".to_string(),
-            timeout_secs: 3
+            kind: None,
+            source: None,
+            tenant: None,
+            traceparent: None,
+            timeout_secs: 3,
+            retries: 0,
+            spool_dir: None,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            file: None,
+            wait_response: false,
+            interactive: false,
+            checksum: false
         };
         let mut stdin = Cursor::new(fixture_bytes());
         run_with_cli(cli, &mut stdin).expect("client run should succeed");
@@ -307,10 +761,22 @@ mod tests {
         });
 
         let cli = Cli {
-            server: addr.to_string(),
+            servers: vec![addr.to_string()],
             from: "sender@example.com".to_string(),
             to: "bounces@example.com".to_string(),
-            timeout_secs: 1
+            kind: None,
+            source: None,
+            tenant: None,
+            traceparent: None,
+            timeout_secs: 1,
+            retries: 0,
+            spool_dir: None,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            file: None,
+            wait_response: false,
+            interactive: false,
+            checksum: false
         };
         let mut stdin = Cursor::new(fixture_bytes());
         let err = run_with_cli(cli, &mut stdin).expect_err("must fail");
@@ -323,6 +789,226 @@ mod tests {
         handle.join().expect("server thread join");
     }
 
+    #[test]
+    fn run_with_cli_stops_when_server_rejects_as_too_large() {
+        let Some(listener) = bind_local_listener_or_skip() else {
+            return;
+        };
+        let addr = listener.local_addr().expect("local addr");
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let _ = read_frame_sync(&mut stream).expect("frame");
+            stream.write_all(TOO_LARGE).expect("reply write");
+        });
+
+        let cli = Cli {
+            servers: vec![addr.to_string()],
+            from: "sender@example.com".to_string(),
+            to: "bounces@example.com".to_string(),
+            kind: None,
+            source: None,
+            tenant: None,
+            traceparent: None,
+            timeout_secs: 1,
+            retries: 2,
+            spool_dir: None,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            file: None,
+            wait_response: false,
+            interactive: false,
+            checksum: false
+        };
+        let mut stdin = Cursor::new(fixture_bytes());
+        let err = run_with_cli(cli, &mut stdin).expect_err("must fail");
+        match err {
+            ClientError::TooLarge(msg) => assert!(msg.contains("rejected the frame as too large")),
+            _ => panic!("expected too large error")
+        }
+        handle.join().expect("server thread join");
+    }
+
+    #[test]
+    fn exchange_returns_response_frames() {
+        let Some(listener) = bind_local_listener_or_skip() else {
+            return;
+        };
+        let addr = listener.local_addr().expect("local addr");
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let (header, _) = read_frame_sync(&mut stream).expect("frame");
+            let decoded = decode_header_json(&header).expect("decode header");
+            assert_eq!(decoded.kind.as_deref(), Some("status"));
+            write_frame_sync(&mut stream, br#"{"kind":"status_response"}"#, b"{}")
+                .expect("response write");
+        });
+
+        let cli = Cli {
+            servers: vec![addr.to_string()],
+            from: "sender@example.com".to_string(),
+            to: "bounces@example.com".to_string(),
+            kind: Some("status".to_string()),
+            source: None,
+            tenant: None,
+            traceparent: None,
+            timeout_secs: 3,
+            retries: 0,
+            spool_dir: None,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            file: None,
+            wait_response: true,
+            interactive: false,
+            checksum: false
+        };
+        let header = build_header_bytes(&cli, b"").expect("header build");
+        let mut stream = connect(addr, std::time::Duration::from_secs(3)).expect("connect");
+        let reply = exchange(&mut stream, addr, &cli, &header, b"").expect("reply");
+        assert_eq!(describe_reply(&reply), "{\"kind\":\"status_response\"}\n{}");
+        assert_eq!(describe_reply(&Reply::Ack), "OK");
+        handle.join().expect("server thread join");
+    }
+
+    #[test]
+    fn interactive_sends_one_frame_per_line() {
+        let Some(listener) = bind_local_listener_or_skip() else {
+            return;
+        };
+        let addr = listener.local_addr().expect("local addr");
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let mut bodies = Vec::new();
+            for _ in 0..2 {
+                let (header, body) = read_frame_sync(&mut stream).expect("frame");
+                let decoded = decode_header_json(&header).expect("decode header");
+                assert_eq!(decoded.source.as_deref(), Some("mx-01"));
+                bodies.push(body);
+                stream.write_all(ACK).expect("ack write");
+            }
+            bodies
+        });
+
+        let cli = Cli {
+            servers: vec![addr.to_string()],
+            from: "observer".to_string(),
+            to: "server".to_string(),
+            kind: Some("observer_event".to_string()),
+            source: Some("mx-01".to_string()),
+            tenant: None,
+            traceparent: None,
+            timeout_secs: 3,
+            retries: 0,
+            spool_dir: None,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            file: None,
+            wait_response: true,
+            interactive: true,
+            checksum: false
+        };
+        let mut input = Cursor::new(b"{\"hash\":\"a\"}\n\n{\"hash\":\"b\"}\r\n".to_vec());
+        run_interactive(&cli, &mut input, false).expect("interactive run");
+        assert_eq!(
+            handle.join().expect("server thread join"),
+            [br#"{"hash":"a"}"#.to_vec(), br#"{"hash":"b"}"#.to_vec()]
+        );
+    }
+
+    #[test]
+    fn run_with_cli_fails_over_to_next_server() {
+        let Some(dead) = bind_local_listener_or_skip() else {
+            return;
+        };
+        let dead_addr = dead.local_addr().expect("local addr");
+        drop(dead);
+        let Some(listener) = bind_local_listener_or_skip() else {
+            return;
+        };
+        let addr = listener.local_addr().expect("local addr");
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let _ = read_frame_sync(&mut stream).expect("frame");
+            stream.write_all(ACK).expect("ack write");
+        });
+
+        let cli = Cli {
+            servers: vec![dead_addr.to_string(), addr.to_string()],
+            from: "sender@example.com".to_string(),
+            to: "bounces@example.com".to_string(),
+            kind: None,
+            source: None,
+            tenant: None,
+            traceparent: None,
+            timeout_secs: 1,
+            retries: 0,
+            spool_dir: None,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            file: None,
+            wait_response: false,
+            interactive: false,
+            checksum: false
+        };
+        let mut stdin = Cursor::new(fixture_bytes());
+        run_with_cli(cli, &mut stdin).expect("second server should take the frame");
+        handle.join().expect("server thread join");
+    }
+
+    #[test]
+    fn run_with_cli_spools_when_servers_are_down() {
+        let Some(dead) = bind_local_listener_or_skip() else {
+            return;
+        };
+        let dead_addr = dead.local_addr().expect("local addr");
+        drop(dead);
+        let incoming =
+            std::env::temp_dir().join(format!("bouncer-client-spool-{}", std::process::id()));
+        std::fs::create_dir_all(&incoming).expect("create incoming");
+
+        let cli = Cli {
+            servers: vec![dead_addr.to_string()],
+            from: "sender@example.com".to_string(),
+            to: "bounces@example.com".to_string(),
+            kind: None,
+            source: None,
+            tenant: Some("shop".to_string()),
+            traceparent: None,
+            timeout_secs: 1,
+            retries: 0,
+            spool_dir: Some(incoming.clone()),
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            file: None,
+            wait_response: false,
+            interactive: false,
+            checksum: false
+        };
+        let mut stdin = Cursor::new(fixture_bytes());
+        run_with_cli(cli, &mut stdin).expect("spool fallback should succeed");
+
+        let files: Vec<_> = std::fs::read_dir(&incoming)
+            .expect("read incoming")
+            .map(|entry| entry.expect("entry").path())
+            .collect();
+        assert_eq!(files.len(), 1, "{files:?}");
+        assert_eq!(files[0].extension().and_then(|ext| ext.to_str()), Some("eml"));
+        assert!(files[0].to_string_lossy().ends_with(".tenant-shop.eml"), "{files:?}");
+        assert_eq!(std::fs::read(&files[0]).expect("read spooled"), fixture_bytes());
+        std::fs::remove_dir_all(&incoming).expect("cleanup");
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_cap() {
+        assert_eq!(backoff_delay(1), INITIAL_BACKOFF);
+        assert_eq!(backoff_delay(2), INITIAL_BACKOFF * 2);
+        assert_eq!(backoff_delay(3), INITIAL_BACKOFF * 4);
+        assert_eq!(backoff_delay(40), MAX_BACKOFF);
+    }
+
     fn fixture_bytes() -> Vec<u8> {
         include_bytes!("../../../tests/bounces/notification.eml").to_vec()
     }
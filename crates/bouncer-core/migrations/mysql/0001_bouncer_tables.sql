@@ -0,0 +1,42 @@
+-- Tables written by bouncer. `mail_messages` belongs to the application and
+-- is only read and status-updated, so it is not created here.
+-- IF NOT EXISTS keeps this a no-op for deployments that created them by hand.
+
+CREATE TABLE IF NOT EXISTS mail_message_bounces (
+    message_id INT UNSIGNED NOT NULL PRIMARY KEY,
+    action VARCHAR(32) NULL,
+    status_code VARCHAR(20) NOT NULL,
+    description TEXT NULL,
+    created_at DATETIME NOT NULL,
+    KEY mail_message_bounces_created_at_idx (created_at)
+);
+
+CREATE TABLE IF NOT EXISTS mail_bounces (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    hash VARCHAR(64) NOT NULL,
+    recipient VARCHAR(320) NULL,
+    action VARCHAR(32) NULL,
+    status_code VARCHAR(20) NOT NULL,
+    description TEXT NULL,
+    created_at DATETIME NOT NULL,
+    UNIQUE KEY mail_bounces_hash_unique (hash),
+    KEY mail_bounces_created_at_idx (created_at)
+);
+
+CREATE TABLE IF NOT EXISTS processed_spool_messages (
+    idempotency_key CHAR(64) NOT NULL PRIMARY KEY,
+    hash VARCHAR(64) NOT NULL,
+    processed_at DATETIME NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS suppressions (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    recipient VARCHAR(320) NOT NULL,
+    reason VARCHAR(32) NOT NULL,
+    status_code VARCHAR(20) NOT NULL,
+    description TEXT NULL,
+    hash VARCHAR(64) NULL,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    UNIQUE KEY suppressions_recipient_unique (recipient)
+);
@@ -0,0 +1,5 @@
+-- Short human-readable reason (`mailbox full`, `user unknown`, ...) derived
+-- from the diagnostic when the bounce is recorded. Older rows stay NULL.
+
+ALTER TABLE mail_message_bounces ADD COLUMN reason VARCHAR(64) NULL;
+ALTER TABLE mail_bounces ADD COLUMN reason VARCHAR(64) NULL;
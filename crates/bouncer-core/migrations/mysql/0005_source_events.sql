@@ -0,0 +1,12 @@
+-- Source registry transitions (registered, connected, disconnected, expired,
+-- recovered), written when `sources.persist_events` is enabled so an
+-- observer outage can be alerted on and reviewed from SQL.
+
+CREATE TABLE IF NOT EXISTS source_events (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    source VARCHAR(128) NOT NULL,
+    transition VARCHAR(16) NOT NULL,
+    detail VARCHAR(255) NULL,
+    occurred_at DATETIME NOT NULL,
+    KEY source_events_source_idx (source, occurred_at)
+);
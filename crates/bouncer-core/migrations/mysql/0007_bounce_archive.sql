@@ -0,0 +1,18 @@
+-- Copies of applied bounce mails, written while `archive.destination` is
+-- `database` or `s3`. `body` holds the mail for the database destination,
+-- `object_key` its bucket key for s3.
+
+CREATE TABLE IF NOT EXISTS bounce_archive (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    idempotency_key CHAR(64) NOT NULL,
+    hash VARCHAR(64) NOT NULL,
+    storage VARCHAR(16) NOT NULL,
+    content_encoding VARCHAR(16) NULL,
+    size_bytes BIGINT NOT NULL,
+    body LONGBLOB NULL,
+    object_key VARCHAR(1024) NULL,
+    archived_at DATETIME NOT NULL,
+    UNIQUE KEY bounce_archive_idempotency_key_idx (idempotency_key),
+    KEY bounce_archive_hash_idx (hash),
+    KEY bounce_archive_archived_at_idx (archived_at)
+);
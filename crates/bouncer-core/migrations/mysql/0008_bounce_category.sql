@@ -0,0 +1,5 @@
+-- Normalized bounce category (`hard`, `soft`, `block`, `full`, `spam`) from
+-- the built-in and configured classification rules. Older rows stay NULL.
+
+ALTER TABLE mail_message_bounces ADD COLUMN category VARCHAR(16) NULL;
+ALTER TABLE mail_bounces ADD COLUMN category VARCHAR(16) NULL;
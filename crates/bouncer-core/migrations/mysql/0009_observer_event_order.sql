@@ -0,0 +1,12 @@
+-- Latest applied `sequence` of observer events per publisher source and
+-- postfix queue id. Events that are not newer are skipped, so a late or
+-- resent event cannot downgrade a final status. Rows are pruned after a week.
+
+CREATE TABLE IF NOT EXISTS observer_event_order (
+    source VARCHAR(255) NOT NULL,
+    queue_id VARCHAR(64) NOT NULL,
+    sequence BIGINT NOT NULL,
+    updated_at DATETIME NOT NULL,
+    PRIMARY KEY (source, queue_id),
+    KEY observer_event_order_updated_at_idx (updated_at)
+);
@@ -0,0 +1,17 @@
+-- Provenance of every processed bounce: the ingest path (tcp, smtp, imap,
+-- spool_scan), the spool file name or IMAP UID it came from, when it was
+-- received and processed, and the parser scans that found it.
+
+CREATE TABLE IF NOT EXISTS mail_bounce_audit (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    hash VARCHAR(64) NOT NULL,
+    ingest_path VARCHAR(16) NOT NULL,
+    source VARCHAR(255) NULL,
+    origin VARCHAR(512) NOT NULL,
+    outcome VARCHAR(16) NOT NULL,
+    scan_labels TEXT NULL,
+    received_at DATETIME NOT NULL,
+    processed_at DATETIME NOT NULL,
+    KEY mail_bounce_audit_hash_idx (hash),
+    KEY mail_bounce_audit_processed_at_idx (processed_at)
+);
@@ -0,0 +1,10 @@
+-- Tenant of an unknown hash's bounce, when the frame or event carried one.
+-- Hashes are only unique within a tenant, so orphan rows are keyed by both.
+-- `mail_messages` is owned by the application: bounces and observer events
+-- with a tenant only match messages of that tenant once it adds a
+-- `tenant VARCHAR(64) NULL` column there, ideally indexed with the hash.
+
+ALTER TABLE mail_bounces
+    ADD COLUMN tenant VARCHAR(64) NULL,
+    DROP INDEX mail_bounces_hash_unique,
+    ADD UNIQUE KEY mail_bounces_hash_tenant_unique (hash, tenant);
@@ -0,0 +1,5 @@
+-- `processed_spool_messages` keys are pruned after 30 days; index the
+-- column the hourly prune filters on.
+
+ALTER TABLE processed_spool_messages
+    ADD KEY processed_spool_messages_processed_at_idx (processed_at);
@@ -0,0 +1,4 @@
+-- `source_events` rows past `sources.events_retention` are pruned hourly;
+-- index the column the prune filters on.
+
+ALTER TABLE source_events ADD KEY source_events_occurred_at_idx (occurred_at);
@@ -0,0 +1,46 @@
+-- A standalone SQLite deployment has no application database, so the
+-- application-owned `mail_messages` table is created alongside the tables
+-- bouncer writes.
+
+CREATE TABLE IF NOT EXISTS mail_messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    hash VARCHAR(64) NOT NULL UNIQUE,
+    status INTEGER NOT NULL DEFAULT 0,
+    updated_at DATETIME NULL
+);
+
+CREATE TABLE IF NOT EXISTS mail_message_bounces (
+    message_id INTEGER NOT NULL UNIQUE REFERENCES mail_messages (id) ON DELETE CASCADE,
+    action VARCHAR(32) NULL,
+    status_code VARCHAR(20) NOT NULL,
+    description TEXT NULL,
+    created_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS mail_message_bounces_created_at_idx ON mail_message_bounces (created_at);
+
+CREATE TABLE IF NOT EXISTS mail_bounces (
+    hash VARCHAR(64) NOT NULL UNIQUE,
+    recipient VARCHAR(320) NULL,
+    action VARCHAR(32) NULL,
+    status_code VARCHAR(20) NOT NULL,
+    description TEXT NULL,
+    created_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS mail_bounces_created_at_idx ON mail_bounces (created_at);
+
+CREATE TABLE IF NOT EXISTS processed_spool_messages (
+    idempotency_key CHAR(64) NOT NULL PRIMARY KEY,
+    hash VARCHAR(64) NOT NULL,
+    processed_at DATETIME NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS suppressions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    recipient VARCHAR(320) NOT NULL UNIQUE,
+    reason VARCHAR(32) NOT NULL,
+    status_code VARCHAR(20) NOT NULL,
+    description TEXT NULL,
+    hash VARCHAR(64) NULL,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL
+);
@@ -0,0 +1,10 @@
+-- Repeated identical bounces bump `last_seen_at`/`occurrence_count` instead of
+-- rewriting `created_at`, which now keeps the first-seen time.
+
+ALTER TABLE mail_message_bounces ADD COLUMN last_seen_at DATETIME NULL;
+ALTER TABLE mail_message_bounces ADD COLUMN occurrence_count INTEGER NOT NULL DEFAULT 1;
+UPDATE mail_message_bounces SET last_seen_at = created_at WHERE last_seen_at IS NULL;
+
+ALTER TABLE mail_bounces ADD COLUMN last_seen_at DATETIME NULL;
+ALTER TABLE mail_bounces ADD COLUMN occurrence_count INTEGER NOT NULL DEFAULT 1;
+UPDATE mail_bounces SET last_seen_at = created_at WHERE last_seen_at IS NULL;
@@ -0,0 +1,10 @@
+-- Keys of bounces applied within `bounce_dedup_window`, so the same bounce
+-- arriving again via another ingest path (observer event, piped DSN, IMAP)
+-- is skipped. Rows older than the window are pruned by the server.
+
+CREATE TABLE IF NOT EXISTS bounce_dedup (
+    dedup_key CHAR(64) NOT NULL PRIMARY KEY,
+    hash VARCHAR(64) NOT NULL,
+    seen_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS bounce_dedup_seen_at_idx ON bounce_dedup (seen_at);
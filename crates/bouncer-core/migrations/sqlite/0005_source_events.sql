@@ -0,0 +1,12 @@
+-- Source registry transitions (registered, connected, disconnected, expired,
+-- recovered), written when `sources.persist_events` is enabled so an
+-- observer outage can be alerted on and reviewed from SQL.
+
+CREATE TABLE IF NOT EXISTS source_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source VARCHAR(128) NOT NULL,
+    transition VARCHAR(16) NOT NULL,
+    detail VARCHAR(255) NULL,
+    occurred_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS source_events_source_idx ON source_events (source, occurred_at);
@@ -0,0 +1,15 @@
+-- Result of the `authentication` checks for each spooled bounce, written
+-- while `authentication.mode` is `log` or `reject`. Join on `hash` to see
+-- why a bounce was or was not applied.
+
+CREATE TABLE IF NOT EXISTS bounce_authentication (
+    idempotency_key CHAR(64) NOT NULL PRIMARY KEY,
+    hash VARCHAR(64) NOT NULL,
+    verdict VARCHAR(8) NOT NULL,
+    dkim VARCHAR(16) NOT NULL,
+    dkim_domain VARCHAR(255) NULL,
+    relay VARCHAR(16) NOT NULL,
+    relay_host VARCHAR(320) NULL,
+    checked_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS bounce_authentication_hash_idx ON bounce_authentication (hash);
@@ -0,0 +1,17 @@
+-- Copies of applied bounce mails, written while `archive.destination` is
+-- `database` or `s3`. `body` holds the mail for the database destination,
+-- `object_key` its bucket key for s3.
+
+CREATE TABLE IF NOT EXISTS bounce_archive (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    idempotency_key CHAR(64) NOT NULL UNIQUE,
+    hash VARCHAR(64) NOT NULL,
+    storage VARCHAR(16) NOT NULL,
+    content_encoding VARCHAR(16) NULL,
+    size_bytes INTEGER NOT NULL,
+    body BLOB NULL,
+    object_key VARCHAR(1024) NULL,
+    archived_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS bounce_archive_hash_idx ON bounce_archive (hash);
+CREATE INDEX IF NOT EXISTS bounce_archive_archived_at_idx ON bounce_archive (archived_at);
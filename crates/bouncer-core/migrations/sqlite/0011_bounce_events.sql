@@ -0,0 +1,14 @@
+-- Append-only history of the outcomes applied to a local message: delayed
+-- warnings, the final failure or a later success, each with the time it was
+-- logged. `mail_message_bounces` keeps holding the latest state only.
+
+CREATE TABLE IF NOT EXISTS mail_message_bounce_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL REFERENCES mail_messages (id) ON DELETE CASCADE,
+    action VARCHAR(32) NULL,
+    status_code VARCHAR(20) NOT NULL,
+    description TEXT NULL,
+    occurred_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS mail_message_bounce_events_message_idx ON mail_message_bounce_events (message_id, occurred_at);
+CREATE INDEX IF NOT EXISTS mail_message_bounce_events_occurred_at_idx ON mail_message_bounce_events (occurred_at);
@@ -0,0 +1,30 @@
+-- Tenant (application) of a message when several share one server. Message
+-- hashes are only unique within a tenant; bounces and observer events that
+-- carry a tenant only match messages of that tenant, and orphan bounces are
+-- keyed by hash and tenant. Older rows stay NULL.
+
+ALTER TABLE mail_messages ADD COLUMN tenant VARCHAR(64) NULL;
+CREATE INDEX IF NOT EXISTS mail_messages_tenant_hash_idx ON mail_messages (tenant, hash);
+
+-- SQLite cannot drop the inline UNIQUE (hash), so the table is rebuilt.
+CREATE TABLE mail_bounces_tenant (
+    hash VARCHAR(64) NOT NULL,
+    recipient VARCHAR(320) NULL,
+    action VARCHAR(32) NULL,
+    status_code VARCHAR(20) NOT NULL,
+    description TEXT NULL,
+    created_at DATETIME NOT NULL,
+    last_seen_at DATETIME NULL,
+    occurrence_count INTEGER NOT NULL DEFAULT 1,
+    reason TEXT NULL,
+    category TEXT NULL,
+    tenant VARCHAR(64) NULL,
+    UNIQUE (hash, tenant)
+);
+INSERT INTO mail_bounces_tenant
+    (hash, recipient, action, status_code, description, created_at, last_seen_at, occurrence_count, reason, category)
+SELECT hash, recipient, action, status_code, description, created_at, last_seen_at, occurrence_count, reason, category
+FROM mail_bounces;
+DROP TABLE mail_bounces;
+ALTER TABLE mail_bounces_tenant RENAME TO mail_bounces;
+CREATE INDEX IF NOT EXISTS mail_bounces_created_at_idx ON mail_bounces (created_at);
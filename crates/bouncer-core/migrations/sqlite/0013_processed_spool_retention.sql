@@ -0,0 +1,4 @@
+-- `processed_spool_messages` keys are pruned after 30 days; index the
+-- column the hourly prune filters on.
+
+CREATE INDEX IF NOT EXISTS processed_spool_messages_processed_at_idx ON processed_spool_messages (processed_at);
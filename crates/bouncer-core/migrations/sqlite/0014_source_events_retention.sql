@@ -0,0 +1,4 @@
+-- `source_events` rows past `sources.events_retention` are pruned hourly;
+-- index the column the prune filters on.
+
+CREATE INDEX IF NOT EXISTS source_events_occurred_at_idx ON source_events (occurred_at);
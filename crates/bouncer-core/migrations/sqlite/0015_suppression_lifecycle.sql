@@ -0,0 +1,23 @@
+-- Suppression entries are soft-deleted (`removed_at`) when an operator
+-- removes or expires them, or when a soft-bounce entry passes `expires_at`
+-- (`suppression.soft_bounce_ttl`); the next qualifying bounce re-activates
+-- the same row. `suppression_audit` keeps each removal, expiry and
+-- re-activation with the entry as it was before.
+
+ALTER TABLE suppressions ADD COLUMN removed_at DATETIME NULL;
+ALTER TABLE suppressions ADD COLUMN expires_at DATETIME NULL;
+CREATE INDEX IF NOT EXISTS suppressions_expires_at_idx ON suppressions (expires_at);
+
+CREATE TABLE IF NOT EXISTS suppression_audit (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    recipient VARCHAR(320) NOT NULL,
+    action VARCHAR(16) NOT NULL,
+    actor VARCHAR(255) NOT NULL,
+    reason VARCHAR(32) NOT NULL,
+    status_code VARCHAR(20) NOT NULL,
+    hash VARCHAR(64) NULL,
+    suppressed_at DATETIME NOT NULL,
+    created_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS suppression_audit_recipient_idx ON suppression_audit (recipient);
+CREATE INDEX IF NOT EXISTS suppression_audit_created_at_idx ON suppression_audit (created_at);
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use bouncer_helpers::clock::SharedClock;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{AuditConfig, DispatcherConfig, RetentionConfig};
+use crate::core::{
+    AdminTriggers, BounceArchive, BounceAuthenticator, ClientTimeouts, ConnectionStats, Database,
+    DbRetries, Faults, FrameAudit, InFlightPaths, Live, MissingMessageRetries, ParserChain,
+    PayloadCapture, RuntimeStatus, SourceRegistry, Spool, SpoolOrigins, SpoolTraces
+};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub spool: Arc<Spool>,
+    pub db: Arc<Database>,
+    /// Swapped on config reload.
+    pub parsers: Arc<Live<ParserChain>>,
+    pub shutdown: CancellationToken,
+    pub clock: SharedClock,
+    pub faults: Arc<Faults>,
+    pub connections: Arc<ConnectionStats>,
+    pub sources: Arc<SourceRegistry>,
+    pub dispatcher: Arc<DispatcherConfig>,
+    pub traces: Arc<SpoolTraces>,
+    pub retries: Arc<MissingMessageRetries>,
+    pub db_retries: Arc<DbRetries>,
+    pub status: Arc<RuntimeStatus>,
+    pub capture: Arc<PayloadCapture>,
+    /// `frame_audit`; toggled through the admin API.
+    pub frame_audit: Arc<FrameAudit>,
+    pub authenticator: Arc<BounceAuthenticator>,
+    pub archive: Arc<BounceArchive>,
+    pub triggers: Arc<AdminTriggers>,
+    /// `spool_retention`; swapped on config reload.
+    pub retention: Arc<Live<RetentionConfig>>,
+    /// `ignore_delivered_events`.
+    pub ignore_delivered_events: bool,
+    /// `client_idle_secs`, `client_heartbeat_misses`, `tcp_keepalive_secs`;
+    /// swapped on config reload, read once per accepted connection.
+    pub clients: Arc<Live<ClientTimeouts>>,
+    pub audit: AuditConfig,
+    pub origins: Arc<SpoolOrigins>,
+    /// Spool paths queued for the workers; see [`InFlightPaths`].
+    pub in_flight: Arc<InFlightPaths>,
+    pub started_at: Instant
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State over a fresh spool and migrated sqlite database under `root`.
+    pub async fn for_tests(root: &std::path::Path) -> Self {
+        use std::time::Duration;
+
+        use bouncer_helpers::clock::system_clock;
+
+        use crate::config::{MigrateMode, SuppressionConfig};
+
+        let faults = Arc::new(Faults::default());
+        let spool = Spool::new(root.join("spool"), system_clock(), faults.clone());
+        spool.ensure_dirs().await.unwrap();
+        let db = Database::connect(
+            &format!("sqlite:{}", root.join("bouncer.sqlite").display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            faults.clone()
+        )
+        .await
+        .unwrap();
+        let clock = system_clock();
+        Self {
+            spool: Arc::new(spool),
+            db: Arc::new(db),
+            parsers: Arc::new(Live::new(ParserChain::default())),
+            shutdown: CancellationToken::new(),
+            connections: Arc::new(ConnectionStats::new(clock.now())),
+            sources: Arc::new(SourceRegistry::new(Duration::from_secs(300), clock.clone())),
+            dispatcher: Arc::new(DispatcherConfig::default()),
+            traces: Arc::new(SpoolTraces::default()),
+            retries: Arc::new(MissingMessageRetries::new(Vec::new())),
+            db_retries: Arc::new(DbRetries::new(Vec::new())),
+            status: Arc::new(RuntimeStatus::default()),
+            capture: Arc::new(PayloadCapture::default()),
+            frame_audit: Arc::new(FrameAudit::default()),
+            authenticator: Arc::new(BounceAuthenticator::default()),
+            archive: Arc::new(BounceArchive::default()),
+            triggers: Arc::new(AdminTriggers::default()),
+            retention: Arc::new(Live::new(RetentionConfig::default())),
+            ignore_delivered_events: false,
+            clients: Arc::new(Live::new(ClientTimeouts::default())),
+            audit: AuditConfig::default(),
+            origins: Arc::new(SpoolOrigins::default()),
+            in_flight: Arc::new(InFlightPaths::default()),
+            started_at: clock.now(),
+            clock,
+            faults
+        }
+    }
+}
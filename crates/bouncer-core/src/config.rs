@@ -0,0 +1,2413 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use bouncer_helpers::config_file::{self, ConfigSnapshot};
+use bouncer_helpers::glob::glob_match;
+use bouncer_helpers::logging;
+use bouncer_helpers::message_hash::{HashFormat, HashFormatConfig};
+use bouncer_helpers::oauth2::OAuth2Config;
+use bouncer_proto::verp::VerpTemplate;
+use serde::{Deserialize, Serialize};
+use sqlx::mysql::MySqlConnectOptions;
+use sqlx::sqlite::SqliteConnectOptions;
+
+use crate::core::{
+    ClientTimeouts, DEFAULT_HASH_HEADERS, DEFAULT_MAX_BODY_BYTES, DEFAULT_MAX_HEADER_BYTES,
+    DEFAULT_PARSER_CHAIN, FrameLimits, HashRules, Lane, PeerAllowlist, partition_name
+};
+
+const MAX_PAYLOAD_CAPTURE_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Ingest addresses; a single string or a list.
+    #[serde(default = "default_listen", deserialize_with = "deserialize_one_or_many")]
+    pub listen: Vec<String>,
+    /// CIDR ranges or addresses allowed to connect to `listen`; empty allows
+    /// every peer.
+    #[serde(default)]
+    pub listen_allow: Vec<String>,
+    /// Largest frame header accepted on `listen`.
+    #[serde(default = "default_max_header_bytes")]
+    pub max_header_bytes: u32,
+    /// Largest frame body (raw mail or agent payload) accepted on `listen`;
+    /// gzipped mail must also fit once unpacked.
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: u64,
+    /// Rejects frames without a body `checksum` on `listen`; a checksum a
+    /// frame carries is verified either way.
+    #[serde(default)]
+    pub require_frame_checksum: bool,
+    /// Per-address overrides of the frame limits, keyed by a `listen` entry.
+    #[serde(default)]
+    pub listen_limits: BTreeMap<String, ListenLimitsOverride>,
+    #[serde(default = "default_spool")]
+    pub spool: PathBuf,
+    pub database_url: String,
+    #[serde(default)]
+    pub migrate: MigrateMode,
+    #[serde(default = "default_worker_concurrency")]
+    pub worker_concurrency: usize,
+    #[serde(default = "default_process_queue_per_worker")]
+    pub process_queue_per_worker: usize,
+    #[serde(default = "default_incoming_scan_secs")]
+    pub incoming_scan_secs: u64,
+    /// One account, or a list of them; empty disables IMAP polling.
+    #[serde(default, deserialize_with = "deserialize_imap_accounts")]
+    pub imap: Vec<ImapConfig>,
+    #[serde(default)]
+    pub imap_limits: ImapLimitsConfig,
+    /// Address of the embedded SMTP listener; unset keeps it off.
+    #[serde(default)]
+    pub smtp_listen: Option<String>,
+    #[serde(default)]
+    pub smtp: SmtpConfig,
+    /// Address of the admin HTTP API; unset keeps it off.
+    #[serde(default)]
+    pub admin_listen: Option<String>,
+    /// Bearer token of the admin HTTP API. Falls back to `BOUNCER_ADMIN_TOKEN`.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// Smallest count the admin API's aggregate exports report; smaller
+    /// ones are withheld.
+    #[serde(default = "default_admin_min_reported_count")]
+    pub admin_min_reported_count: u32,
+    #[serde(default)]
+    pub suppression: SuppressionConfig,
+    #[serde(default)]
+    pub recipients: RecipientsConfig,
+    #[serde(default)]
+    pub classification: ClassificationConfig,
+    /// Skip a bounce seen from any ingest path within this window.
+    #[serde(
+        default,
+        deserialize_with = "bouncer_helpers::de::deserialize_optional_duration",
+        serialize_with = "bouncer_helpers::de::serialize_optional_duration"
+    )]
+    pub bounce_dedup_window: Option<Duration>,
+    #[serde(default)]
+    pub database_resilience: DatabaseResilienceConfig,
+    #[serde(default)]
+    pub database_batch: DatabaseBatchConfig,
+    #[serde(default)]
+    pub message_cache: MessageCacheConfig,
+    /// ACK observer events whose action is `delivered` without writing them;
+    /// they are only counted per source.
+    #[serde(default)]
+    pub ignore_delivered_events: bool,
+    /// Close an ingest connection with no frame for this long; 0 keeps it open.
+    #[serde(default = "default_client_idle_secs")]
+    pub client_idle_secs: u64,
+    /// Close a registered agent connection after this many missed heartbeat
+    /// periods (the `heartbeat_secs` it registered with); 0 disables it.
+    #[serde(default = "default_client_heartbeat_misses")]
+    pub client_heartbeat_misses: u32,
+    /// Idle time before TCP keepalive probes start on ingest connections; 0
+    /// leaves keepalive off.
+    #[serde(default = "default_tcp_keepalive_secs")]
+    pub tcp_keepalive_secs: u64,
+    #[serde(default)]
+    pub parser: ParserConfig,
+    #[serde(default)]
+    pub sources: SourcesConfig,
+    #[serde(default)]
+    pub dispatcher: DispatcherConfig,
+    /// Spool each frame under `<state>/<source>/` using its `source` header.
+    #[serde(default)]
+    pub spool_partition_by_source: bool,
+    /// Gzip files as they are moved to `done/`.
+    #[serde(default)]
+    pub spool_compress_done: bool,
+    #[serde(default)]
+    pub spool_layout: SpoolLayout,
+    #[serde(default)]
+    pub spool_storage: SpoolStorageConfig,
+    #[serde(default)]
+    pub spool_retention: RetentionConfig,
+    /// Refuse to start when a startup diagnostics check fails.
+    #[serde(default)]
+    pub strict_startup: bool,
+    #[serde(default)]
+    pub diagnostics: DiagnosticsConfig,
+    #[serde(default)]
+    pub missing_message_retry: MissingMessageRetryConfig,
+    #[serde(default)]
+    pub db_retry: DbRetryConfig,
+    #[serde(default)]
+    pub payload_capture: PayloadCaptureConfig,
+    #[serde(default)]
+    pub frame_audit: FrameAuditConfig,
+    #[serde(default)]
+    pub authentication: AuthenticationConfig,
+    #[serde(default)]
+    pub archive: ArchiveConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    /// Commands run for every processed bounce; see [`crate::core::BounceHooks`].
+    #[serde(default)]
+    pub hooks: Vec<HookConfig>,
+    /// `EnvFilter` directives replacing `BOUNCER_LOG`; reloaded on SIGHUP.
+    #[serde(default)]
+    pub log_filter: Option<String>,
+    /// File this config was read from; enables reloading on SIGHUP.
+    #[serde(skip)]
+    pub(crate) snapshot: Option<ConfigSnapshot>
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        Self::load_from(parse_config_path_arg(env::args().skip(1))?)
+    }
+
+    /// Loads `path`, or else the first config found via `BOUNCER_CONFIG_PATH`
+    /// and the default locations.
+    pub fn load_from(path: Option<PathBuf>) -> Result<Self> {
+        let config_path = path.or_else(resolve_server_config_path).context(
+            "server config path not found (BOUNCER_CONFIG_PATH or bouncer.yaml/bouncer.yaml)"
+        )?;
+
+        Self::from_path(&config_path)
+    }
+
+    /// Reads, normalizes and validates a YAML config file.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let (mut config, snapshot) = config_file::load_yaml_snapshot::<Self>(path)?;
+        config.snapshot = Some(snapshot);
+        config.normalize()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The config as YAML with secrets redacted, for `--check-config`.
+    pub fn dump(&self) -> Result<String> {
+        config_file::dump_yaml(self).context("failed to render server config")
+    }
+
+    pub(crate) fn client_timeouts(&self) -> ClientTimeouts {
+        let secs = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+        ClientTimeouts {
+            idle: secs(self.client_idle_secs),
+            heartbeat_misses: self.client_heartbeat_misses,
+            keepalive: secs(self.tcp_keepalive_secs)
+        }
+    }
+
+    /// Every `listen` address with the frame limits that apply to it.
+    pub(crate) fn listeners(&self) -> Vec<(String, FrameLimits)> {
+        self.listen
+            .iter()
+            .map(|address| {
+                let limits = self.listen_limits.get(address);
+                let limits = FrameLimits {
+                    header: limits
+                        .and_then(|o| o.max_header_bytes)
+                        .unwrap_or(self.max_header_bytes),
+                    body: limits.and_then(|o| o.max_body_bytes).unwrap_or(self.max_body_bytes),
+                    require_checksum: limits
+                        .and_then(|o| o.require_frame_checksum)
+                        .unwrap_or(self.require_frame_checksum)
+                };
+                (address.clone(), limits)
+            })
+            .collect()
+    }
+
+    pub(crate) fn normalize(&mut self) -> Result<()> {
+        self.listen = std::mem::take(&mut self.listen)
+            .into_iter()
+            .map(trim_owned)
+            .filter(|listen| !listen.is_empty())
+            .collect();
+        self.listen_allow = std::mem::take(&mut self.listen_allow)
+            .into_iter()
+            .map(trim_owned)
+            .filter(|allow| !allow.is_empty())
+            .collect();
+        self.listen_limits = std::mem::take(&mut self.listen_limits)
+            .into_iter()
+            .map(|(address, limits)| (trim_owned(address), limits))
+            .collect();
+        self.database_url = trim_owned(self.database_url.clone());
+
+        if self.listen.is_empty() {
+            self.listen = default_listen();
+        }
+        if self.spool.as_os_str().is_empty() {
+            self.spool = default_spool();
+        }
+        if self.database_url.is_empty() {
+            bail!("server config missing `database_url`");
+        }
+
+        self.worker_concurrency = self.worker_concurrency.max(1);
+        self.process_queue_per_worker = self.process_queue_per_worker.max(1);
+        self.incoming_scan_secs = self.incoming_scan_secs.max(1);
+        for imap in &mut self.imap {
+            imap.normalize();
+        }
+        self.imap_limits.normalize();
+        self.smtp_listen = normalize_opt(self.smtp_listen.take());
+        self.smtp.normalize();
+        self.admin_listen = normalize_opt(self.admin_listen.take());
+        self.admin_token =
+            normalize_opt(self.admin_token.take()).or_else(|| non_empty_env("BOUNCER_ADMIN_TOKEN"));
+        self.suppression.normalize();
+        self.classification.normalize();
+        self.parser.normalize();
+        self.sources.normalize();
+        self.spool_retention.sources = std::mem::take(&mut self.spool_retention.sources)
+            .into_iter()
+            .filter_map(|(source, retention)| Some((partition_name(&source)?, retention)))
+            .collect();
+        self.dispatcher.normalize();
+        self.database_resilience.normalize();
+        self.database_batch.normalize();
+        self.message_cache.normalize();
+        self.payload_capture.normalize();
+        self.frame_audit.normalize();
+        self.authentication.normalize();
+        self.spool_storage.normalize();
+        self.archive.normalize();
+        self.alerts.normalize();
+        for hook in &mut self.hooks {
+            hook.normalize();
+        }
+        self.log_filter = normalize_opt(self.log_filter.take());
+
+        Ok(())
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        let mut listen = BTreeSet::new();
+        for address in &self.listen {
+            if !listen.insert(address.as_str()) {
+                bail!("server config `listen` address listed twice: {address}");
+            }
+        }
+        let addresses = self.listen.iter().map(|address| ("listen", address));
+        for (key, address) in addresses
+            .chain(self.smtp_listen.iter().map(|address| ("smtp_listen", address)))
+            .chain(self.admin_listen.iter().map(|address| ("admin_listen", address)))
+        {
+            if !config_file::is_host_port(address) {
+                bail!("server config `{key}` is not a `host:port` address: {address}");
+            }
+        }
+        check_database_url(&self.database_url)?;
+        PeerAllowlist::parse(&self.listen_allow)?;
+        if self.max_header_bytes == 0 || self.max_body_bytes == 0 {
+            bail!("server config `max_header_bytes` and `max_body_bytes` must be greater than 0");
+        }
+        for (address, limits) in &self.listen_limits {
+            if !listen.contains(address.as_str()) {
+                bail!("server config `listen_limits` address is not in `listen`: {address}");
+            }
+            if limits.max_header_bytes == Some(0) || limits.max_body_bytes == Some(0) {
+                bail!("server config `listen_limits` for {address} must be greater than 0");
+            }
+        }
+        if !self.spool_retention.sources.is_empty() && !self.spool_partition_by_source {
+            bail!("`spool_retention.sources` requires `spool_partition_by_source: true`");
+        }
+        if self.spool_layout == SpoolLayout::Maildir {
+            if self.spool_partition_by_source {
+                bail!("`spool_layout: maildir` does not support `spool_partition_by_source`");
+            }
+            if self.spool_compress_done {
+                bail!("`spool_layout: maildir` does not support `spool_compress_done`");
+            }
+        }
+        let mut mailboxes = BTreeSet::new();
+        for (index, imap) in self.imap.iter().enumerate() {
+            imap.validate().with_context(|| format!("invalid imap account {index}"))?;
+            for mailbox in &imap.mailboxes {
+                if !mailboxes.insert((imap.label(), mailbox.as_str())) {
+                    bail!(
+                        "server config imap mailbox listed twice: account={}, mailbox={mailbox}",
+                        imap.label()
+                    );
+                }
+            }
+        }
+        if self.smtp_listen.is_some() {
+            self.smtp.validate()?;
+        }
+        if self.admin_listen.is_some() && self.admin_token.is_none() {
+            bail!(
+                "server config `admin_listen` set but `admin_token` (or BOUNCER_ADMIN_TOKEN) is missing"
+            );
+        }
+        if self.admin_min_reported_count < 2 {
+            bail!("server config `admin_min_reported_count` must be at least 2");
+        }
+        self.classification.validate()?;
+        self.parser.validate()?;
+        self.spool_storage.validate()?;
+        self.archive.validate()?;
+        self.alerts.validate()?;
+        let mut hooks = BTreeSet::new();
+        for (index, hook) in self.hooks.iter().enumerate() {
+            hook.validate(index)?;
+            if !hooks.insert(hook.name.as_str()) {
+                bail!("server config `hooks` name listed twice: {}", hook.name);
+            }
+        }
+        if let Some(filter) = &self.log_filter {
+            logging::check_log_filter(filter).context("invalid `log_filter`")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `database_url` the way the pool does, without connecting.
+fn check_database_url(url: &str) -> Result<()> {
+    let parsed = if url.starts_with("sqlite:") {
+        SqliteConnectOptions::from_str(url).map(drop)
+    } else if url.starts_with("mysql://") || url.starts_with("mariadb://") {
+        MySqlConnectOptions::from_str(url).map(drop)
+    } else {
+        bail!("server config `database_url` must start with `mysql://`, `mariadb://` or `sqlite:`");
+    };
+    parsed.context("server config `database_url` is invalid")
+}
+
+fn parse_config_path_arg<I>(mut args: I) -> Result<Option<PathBuf>>
+where
+    I: Iterator<Item = String>
+{
+    let first = args.next();
+    let second = args.next();
+
+    if let Some(arg) = second {
+        bail!(
+            "too many arguments: {arg} (usage: bouncer-server [--check-config] [config-path] | bouncer-server check <dir> [config-path])"
+        );
+    }
+
+    if matches!(first.as_deref(), Some("-h" | "--help")) {
+        bail!(
+            "usage: bouncer-server [--check-config] [config-path] | bouncer-server check <dir> [config-path]"
+        );
+    }
+
+    Ok(first.map(PathBuf::from))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImapConfig {
+    /// Account label in logs and the status frame; defaults to `user@host`.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default = "default_imap_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub pass: Option<String>,
+    /// Logs in with SASL XOAUTH2 instead of `pass`.
+    #[serde(default)]
+    pub oauth2: Option<OAuth2Config>,
+    /// Polled concurrently, each over its own session. `mailbox` is
+    /// accepted for a single one.
+    #[serde(
+        default = "default_imap_mailboxes",
+        alias = "mailbox",
+        deserialize_with = "deserialize_one_or_many"
+    )]
+    pub mailboxes: Vec<String>,
+    #[serde(default = "default_imap_poll_secs")]
+    pub poll_secs: u64,
+    #[serde(default = "default_imap_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    #[serde(default = "default_imap_max_messages_per_poll")]
+    pub max_messages_per_poll: usize,
+    #[serde(
+        default,
+        deserialize_with = "bouncer_helpers::de::deserialize_optional_duration",
+        serialize_with = "bouncer_helpers::de::serialize_optional_duration"
+    )]
+    pub max_history: Option<Duration>,
+    #[serde(default)]
+    pub mark_seen_if_not_exist: bool,
+    /// Folder applied bounces are moved to after being marked seen; unset
+    /// leaves them in the polled mailbox.
+    #[serde(default)]
+    pub move_processed_to: Option<String>,
+    /// Folder for discarded and unparsable messages; unparsable ones stay
+    /// unseen in the polled mailbox while it is unset.
+    #[serde(default)]
+    pub move_failed_to: Option<String>
+}
+
+impl Default for ImapConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            host: None,
+            port: default_imap_port(),
+            user: None,
+            pass: None,
+            oauth2: None,
+            mailboxes: default_imap_mailboxes(),
+            poll_secs: default_imap_poll_secs(),
+            connect_timeout_secs: default_imap_connect_timeout_secs(),
+            max_messages_per_poll: default_imap_max_messages_per_poll(),
+            max_history: None,
+            mark_seen_if_not_exist: false,
+            move_processed_to: None,
+            move_failed_to: None
+        }
+    }
+}
+
+impl ImapConfig {
+    pub fn enabled(&self) -> bool {
+        self.host.is_some()
+    }
+
+    /// `name`, or else `user@host`.
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!(
+                "{}@{}",
+                self.user.as_deref().unwrap_or_default(),
+                self.host.as_deref().unwrap_or_default()
+            )
+        }
+    }
+
+    fn normalize(&mut self) {
+        self.name = normalize_opt(self.name.take());
+        self.host = normalize_opt(self.host.clone());
+        self.user = normalize_opt(self.user.clone());
+        self.pass = normalize_opt(self.pass.clone());
+        if let Some(oauth2) = self.oauth2.as_mut() {
+            oauth2.token_url = normalize_opt(oauth2.token_url.take());
+            oauth2.client_id = normalize_opt(oauth2.client_id.take());
+            oauth2.client_secret = normalize_opt(oauth2.client_secret.take());
+            oauth2.refresh_token = normalize_opt(oauth2.refresh_token.take());
+            oauth2.scope = normalize_opt(oauth2.scope.take());
+            oauth2.token_command = normalize_opt(oauth2.token_command.take());
+        }
+        self.move_processed_to = normalize_opt(self.move_processed_to.take());
+        self.move_failed_to = normalize_opt(self.move_failed_to.take());
+        self.mailboxes = self
+            .mailboxes
+            .drain(..)
+            .map(trim_owned)
+            .filter(|mailbox| !mailbox.is_empty())
+            .collect();
+
+        if self.mailboxes.is_empty() {
+            self.mailboxes = default_imap_mailboxes();
+        }
+
+        self.poll_secs = self.poll_secs.max(1);
+        self.connect_timeout_secs = self.connect_timeout_secs.max(1);
+        self.max_messages_per_poll = self.max_messages_per_poll.max(1);
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.host.is_none() {
+            bail!("server config imap present but `imap.host` is missing");
+        }
+
+        if self.user.is_none() {
+            bail!("server config imap present but `imap.user` is missing");
+        }
+
+        match &self.oauth2 {
+            Some(oauth2) => {
+                if let Some(problem) = oauth2.problem() {
+                    bail!("server config `imap.oauth2` {problem}");
+                }
+            }
+            None if self.pass.is_none() => {
+                bail!("server config imap present but `imap.pass` (or `imap.oauth2`) is missing");
+            }
+            None => {}
+        }
+
+        for folder in [&self.move_processed_to, &self.move_failed_to].into_iter().flatten() {
+            if self.mailboxes.contains(folder) {
+                bail!("server config imap move folder `{folder}` is also a polled mailbox");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Limits shared by the poll loops of every IMAP account and mailbox.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImapLimitsConfig {
+    /// Mailboxes polled at the same time; each poll holds one session.
+    #[serde(default = "default_imap_max_sessions")]
+    pub max_sessions: usize,
+    /// Fetched messages parsed and written to the database at the same time.
+    #[serde(default = "default_imap_max_processing")]
+    pub max_processing: usize
+}
+
+impl Default for ImapLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_sessions: default_imap_max_sessions(),
+            max_processing: default_imap_max_processing()
+        }
+    }
+}
+
+impl ImapLimitsConfig {
+    fn normalize(&mut self) {
+        self.max_sessions = self.max_sessions.max(1);
+        self.max_processing = self.max_processing.max(1);
+    }
+}
+
+/// Embedded SMTP listener settings, used when `smtp_listen` is set.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpConfig {
+    /// Name in the greeting, EHLO reply and `Received` header.
+    #[serde(default)]
+    pub hostname: String,
+    #[serde(default = "default_smtp_max_message_bytes")]
+    pub max_message_bytes: u64,
+    /// Concurrent sessions; further connections are answered `421` and closed.
+    #[serde(default = "default_smtp_max_connections")]
+    pub max_connections: usize,
+    /// PEM certificate chain; with `tls_key`, enables STARTTLS.
+    #[serde(default)]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM PKCS#8 private key (`BEGIN PRIVATE KEY`).
+    #[serde(default)]
+    pub tls_key: Option<PathBuf>,
+    /// Recipient domains to accept; empty accepts every domain.
+    #[serde(default)]
+    pub recipient_domains: Vec<String>
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            hostname: String::new(),
+            max_message_bytes: default_smtp_max_message_bytes(),
+            max_connections: default_smtp_max_connections(),
+            tls_cert: None,
+            tls_key: None,
+            recipient_domains: Vec::new()
+        }
+    }
+}
+
+impl SmtpConfig {
+    /// True when `recipient_domains` is empty or lists the domain of `address`.
+    pub fn accepts_recipient(
+        &self,
+        address: &str
+    ) -> bool {
+        if self.recipient_domains.is_empty() {
+            return true;
+        }
+        address.rsplit_once('@').is_some_and(|(_, domain)| {
+            self.recipient_domains.iter().any(|listed| listed.eq_ignore_ascii_case(domain))
+        })
+    }
+
+    fn normalize(&mut self) {
+        self.hostname = trim_owned(self.hostname.clone());
+        self.max_message_bytes = self.max_message_bytes.max(1);
+        self.max_connections = self.max_connections.max(1);
+        self.recipient_domains = self
+            .recipient_domains
+            .iter()
+            .map(|domain| domain.trim().trim_start_matches('@').to_ascii_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect();
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.hostname.is_empty() {
+            bail!("server config `smtp_listen` set but `smtp.hostname` is missing");
+        }
+
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            bail!("server config `smtp.tls_cert` and `smtp.tls_key` must be set together");
+        }
+
+        Ok(())
+    }
+}
+
+/// What the server does with its schema migrations on startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrateMode {
+    /// Apply pending migrations.
+    #[default]
+    Auto,
+    /// Refuse to start while migrations are pending or were modified.
+    Check,
+    /// Leave the schema alone (managed elsewhere).
+    Off
+}
+
+impl MigrateMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Check => "check",
+            Self::Off => "off"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SuppressionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_suppression_status_codes")]
+    pub status_codes: Vec<String>,
+    /// Also suppress on soft bounces (4.x.x, `delayed`), for this long after
+    /// the last one; unset leaves soft bounces unsuppressed.
+    #[serde(
+        default,
+        deserialize_with = "bouncer_helpers::de::deserialize_optional_duration",
+        serialize_with = "bouncer_helpers::de::serialize_optional_duration"
+    )]
+    pub soft_bounce_ttl: Option<Duration>
+}
+
+impl Default for SuppressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            status_codes: default_suppression_status_codes(),
+            soft_bounce_ttl: None
+        }
+    }
+}
+
+impl SuppressionConfig {
+    /// Returns true when `status_code` is listed in `status_codes`.
+    ///
+    /// Entries ending in `*` match by prefix (`5.1.*` covers `5.1.1`, `5.1.10`).
+    pub fn matches_status_code(
+        &self,
+        status_code: &str
+    ) -> bool {
+        matches_status_code(&self.status_codes, status_code)
+    }
+
+    fn normalize(&mut self) {
+        self.status_codes = normalize_status_codes(&self.status_codes);
+    }
+}
+
+/// How bounce recipients are normalized before they are stored. Display
+/// names and angle brackets are always stripped and the domain lowercased;
+/// the local part keeps its case.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecipientsConfig {
+    /// Drop the `+tag` of `user+tag@example.com`.
+    #[serde(default)]
+    pub fold_plus_tags: bool,
+    /// Separator of the folded tag.
+    #[serde(default = "default_recipient_tag_separator")]
+    pub tag_separator: char,
+    /// Address to store instead of a normalized address, matched
+    /// case-insensitively.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>
+}
+
+impl Default for RecipientsConfig {
+    fn default() -> Self {
+        Self {
+            fold_plus_tags: false,
+            tag_separator: default_recipient_tag_separator(),
+            aliases: BTreeMap::new()
+        }
+    }
+}
+
+/// Operator rules for the bounce `category`, checked before the built-in
+/// ones.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClassificationConfig {
+    #[serde(default)]
+    pub rules: Vec<ClassificationRule>
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClassificationRule {
+    pub category: BounceCategory,
+    /// Enhanced status codes; entries ending in `*` match by prefix.
+    #[serde(default)]
+    pub status_codes: Vec<String>,
+    /// Case-insensitive substrings of the diagnostic.
+    #[serde(default)]
+    pub phrases: Vec<String>
+}
+
+/// Normalized bounce category, stored in the `category` column of the
+/// bounce tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BounceCategory {
+    /// Permanent: the address or domain cannot receive mail.
+    Hard,
+    /// Temporary; a later delivery may succeed.
+    Soft,
+    /// Refused by receiver policy, reputation or relay rules.
+    Block,
+    /// Mailbox over quota.
+    Full,
+    /// Rejected as spam, or a spam complaint.
+    Spam
+}
+
+impl BounceCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hard => "hard",
+            Self::Soft => "soft",
+            Self::Block => "block",
+            Self::Full => "full",
+            Self::Spam => "spam"
+        }
+    }
+}
+
+impl ClassificationRule {
+    pub fn matches_status_code(
+        &self,
+        status_code: &str
+    ) -> bool {
+        matches_status_code(&self.status_codes, status_code)
+    }
+}
+
+impl ClassificationConfig {
+    fn normalize(&mut self) {
+        for rule in &mut self.rules {
+            rule.status_codes = normalize_status_codes(&rule.status_codes);
+            rule.phrases = rule
+                .phrases
+                .iter()
+                .map(|phrase| phrase.trim().to_lowercase())
+                .filter(|phrase| !phrase.is_empty())
+                .collect();
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (index, rule) in self.rules.iter().enumerate() {
+            if rule.status_codes.is_empty() && rule.phrases.is_empty() {
+                bail!(
+                    "server config `classification.rules[{index}]` needs `status_codes` or `phrases`"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Entries ending in `*` match by prefix.
+fn matches_status_code(
+    patterns: &[String],
+    status_code: &str
+) -> bool {
+    patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+        Some(prefix) => status_code.starts_with(prefix),
+        None => status_code == pattern
+    })
+}
+
+fn normalize_status_codes(codes: &[String]) -> Vec<String> {
+    codes.iter().map(|code| trim_owned(code.clone())).filter(|code| !code.is_empty()).collect()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParserConfig {
+    /// Bounce parser stages in the order they run; earlier stages win.
+    #[serde(default = "default_parser_chain")]
+    pub chain: Vec<String>,
+    /// VERP return path template such as `bounce-{hash}@example.com`; the
+    /// hash is then read from the envelope recipient first.
+    #[serde(default)]
+    pub verp_template: Option<String>,
+    #[serde(default)]
+    pub status_codes: StatusCodeMode,
+    /// Headers searched for the message hash, highest priority first.
+    #[serde(default = "default_hash_headers")]
+    pub hash_headers: Vec<String>,
+    /// Which Message-ID local parts count as a hash.
+    #[serde(default)]
+    pub hash: HashFormatConfig,
+    /// Per-message limits on parts, bytes and time spent parsing.
+    #[serde(default)]
+    pub budget: ParseBudget
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            chain: default_parser_chain(),
+            verp_template: None,
+            status_codes: StatusCodeMode::default(),
+            hash_headers: default_hash_headers(),
+            hash: HashFormatConfig::default(),
+            budget: ParseBudget::default()
+        }
+    }
+}
+
+/// Hard limits on the work one message may cost the parser, so a
+/// pathological mail fails with `BUDGET_EXCEEDED` instead of stalling a
+/// worker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParseBudget {
+    /// MIME parts the parser walks, nested messages included; more fail the
+    /// message.
+    #[serde(default = "default_parse_max_candidates")]
+    pub max_candidates: usize,
+    /// Bytes of each part, and of the whole message for the full-text
+    /// fallback, that the stages scan; the rest is ignored.
+    #[serde(default = "default_parse_max_candidate_bytes")]
+    pub max_candidate_bytes: usize,
+    /// Wall time after which the remaining stages are skipped and the
+    /// message fails.
+    #[serde(
+        default = "default_parse_max_scan_time",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub max_scan_time: Duration
+}
+
+impl Default for ParseBudget {
+    fn default() -> Self {
+        Self {
+            max_candidates: default_parse_max_candidates(),
+            max_candidate_bytes: default_parse_max_candidate_bytes(),
+            max_scan_time: default_parse_max_scan_time()
+        }
+    }
+}
+
+/// Directory layout of the spool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpoolLayout {
+    /// `incoming/`, `processing/`, `done/` and `failed/`.
+    #[default]
+    Classic,
+    /// A Maildir that MUAs and backup tools can browse: mail arrives through
+    /// `tmp/` in `new/`, is processed in `cur/`, and ends up in the `.Done`
+    /// and `.Failed` Maildir++ folders flagged seen (`S`) or flagged (`F`).
+    Maildir
+}
+
+/// Where spool files are kept besides the local `spool` directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpoolBackend {
+    /// Local disk only; state transitions are renames.
+    #[default]
+    Local,
+    /// A `spool` directory shared over NFS by several servers: files move
+    /// between state directories by copy and delete instead of a rename,
+    /// and a `<name>.lock` file claims each one while it moves.
+    Nfs,
+    /// Every spool file is mirrored to an S3-compatible bucket, and files
+    /// not processed yet are restored from it on start, so the spool
+    /// survives a container rescheduled onto an empty disk.
+    S3
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpoolStorageConfig {
+    #[serde(default)]
+    pub backend: SpoolBackend,
+    /// With `backend: nfs`, a lock file older than this is left over by a
+    /// server that died while moving the file, and is broken.
+    #[serde(
+        default = "default_spool_lock_stale_after",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub lock_stale_after: Duration,
+    /// Required for `backend: s3`; object keys are `<prefix><path in spool>`.
+    #[serde(default)]
+    pub s3: Option<S3Config>
+}
+
+impl Default for SpoolStorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: SpoolBackend::Local,
+            lock_stale_after: default_spool_lock_stale_after(),
+            s3: None
+        }
+    }
+}
+
+impl SpoolStorageConfig {
+    fn normalize(&mut self) {
+        self.lock_stale_after = self.lock_stale_after.max(Duration::from_secs(1));
+        if let Some(s3) = self.s3.as_mut() {
+            s3.normalize();
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.backend != SpoolBackend::S3 {
+            return Ok(());
+        }
+        let Some(s3) = self.s3.as_ref() else {
+            bail!("server config `spool_storage.backend: s3` requires a `spool_storage.s3` block");
+        };
+        s3.validate("spool_storage.s3")
+    }
+}
+
+/// How parsed status codes are checked against the RFC 3463 grammar
+/// (`class.subject.detail`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusCodeMode {
+    /// Keep any dotted digit string, as older releases did; non-conforming
+    /// codes are logged.
+    #[default]
+    Lenient,
+    /// Strip leading zeros from conforming codes and drop the others, so a
+    /// later parser stage may still supply a valid one.
+    Strict
+}
+
+impl ParserConfig {
+    fn normalize(&mut self) {
+        self.chain = self
+            .chain
+            .iter()
+            .map(|name| trim_owned(name.clone()).to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+        self.verp_template = self.verp_template.take().map(trim_owned).filter(|t| !t.is_empty());
+        self.hash_headers = self
+            .hash_headers
+            .iter()
+            .map(|name| trim_owned(name.clone()).trim_end_matches(':').to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+    }
+
+    pub fn verp(&self) -> Result<Option<VerpTemplate>> {
+        self.verp_template
+            .as_deref()
+            .map(VerpTemplate::parse)
+            .transpose()
+            .context("server config `parser.verp_template` is invalid")
+    }
+
+    pub fn hash_rules(&self) -> Result<HashRules> {
+        let format = HashFormat::new(&self.hash)
+            .context("server config `parser.hash.pattern` is invalid")?;
+        Ok(HashRules::new(self.hash_headers.clone(), format))
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.chain.is_empty() {
+            bail!("server config `parser.chain` must list at least one parser");
+        }
+        if self.budget.max_candidates == 0
+            || self.budget.max_candidate_bytes == 0
+            || self.budget.max_scan_time.is_zero()
+        {
+            bail!("server config `parser.budget` limits must be greater than 0");
+        }
+
+        for (idx, name) in self.chain.iter().enumerate() {
+            if self.chain[..idx].contains(name) {
+                bail!("server config `parser.chain` lists `{name}` more than once");
+            }
+        }
+        self.verp()?;
+
+        if self.hash_headers.is_empty() {
+            bail!("server config `parser.hash_headers` must list at least one header");
+        }
+        self.hash_rules()?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourcesConfig {
+    /// A registered source with no frame for this long is reported as silent.
+    #[serde(default = "default_source_silent_after_secs")]
+    pub silent_after_secs: u64,
+    /// Also write registry transitions to the `source_events` table.
+    #[serde(default)]
+    pub persist_events: bool,
+    /// `source_events` rows older than this are pruned hourly.
+    #[serde(
+        default = "default_source_events_retention",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub events_retention: Duration,
+    /// A `register` for a source another connection registered closes that
+    /// connection, e.g. the dead one of an observer that restarted.
+    #[serde(default = "default_true")]
+    pub takeover: bool
+}
+
+impl Default for SourcesConfig {
+    fn default() -> Self {
+        Self {
+            silent_after_secs: default_source_silent_after_secs(),
+            persist_events: false,
+            events_retention: default_source_events_retention(),
+            takeover: true
+        }
+    }
+}
+
+impl SourcesConfig {
+    fn normalize(&mut self) {
+        self.silent_after_secs = self.silent_after_secs.max(10);
+    }
+}
+
+/// How long processed spool files are kept; `None` keeps them forever.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionConfig {
+    #[serde(
+        default,
+        deserialize_with = "bouncer_helpers::de::deserialize_optional_duration",
+        serialize_with = "bouncer_helpers::de::serialize_optional_duration"
+    )]
+    pub done: Option<Duration>,
+    #[serde(
+        default,
+        deserialize_with = "bouncer_helpers::de::deserialize_optional_duration",
+        serialize_with = "bouncer_helpers::de::serialize_optional_duration"
+    )]
+    pub failed: Option<Duration>,
+    /// Per-source overrides for partitioned spools; unset fields fall back
+    /// to the defaults above.
+    #[serde(default)]
+    pub sources: BTreeMap<String, RetentionOverride>
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenLimitsOverride {
+    #[serde(default)]
+    pub max_header_bytes: Option<u32>,
+    #[serde(default)]
+    pub max_body_bytes: Option<u64>,
+    #[serde(default)]
+    pub require_frame_checksum: Option<bool>
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionOverride {
+    #[serde(
+        default,
+        deserialize_with = "bouncer_helpers::de::deserialize_optional_duration",
+        serialize_with = "bouncer_helpers::de::serialize_optional_duration"
+    )]
+    pub done: Option<Duration>,
+    #[serde(
+        default,
+        deserialize_with = "bouncer_helpers::de::deserialize_optional_duration",
+        serialize_with = "bouncer_helpers::de::serialize_optional_duration"
+    )]
+    pub failed: Option<Duration>
+}
+
+impl RetentionConfig {
+    pub fn enabled(&self) -> bool {
+        self.done.is_some()
+            || self.failed.is_some()
+            || self.sources.values().any(|o| o.done.is_some() || o.failed.is_some())
+    }
+
+    /// Retention for `done/` (`failed == false`) or `failed/` files of
+    /// `partition` (`None` for files outside a source subdirectory).
+    pub fn max_age(
+        &self,
+        partition: Option<&str>,
+        failed: bool
+    ) -> Option<Duration> {
+        let pick = |done: Option<Duration>, failed_age: Option<Duration>| {
+            if failed { failed_age } else { done }
+        };
+        partition
+            .and_then(|partition| self.sources.get(partition))
+            .and_then(|o| pick(o.done, o.failed))
+            .or_else(|| pick(self.done, self.failed))
+    }
+}
+
+/// Thresholds for the startup diagnostics (see `strict_startup`).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiagnosticsConfig {
+    /// Log in to IMAP once at startup; off by default to keep boots offline-safe.
+    #[serde(default)]
+    pub imap_login: bool,
+    #[serde(default = "default_min_free_spool_mb")]
+    pub min_free_spool_mb: u64,
+    /// Allowed difference between the host clock and the database clock.
+    #[serde(default = "default_max_clock_skew_secs")]
+    pub max_clock_skew_secs: u64
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            imap_login: false,
+            min_free_spool_mb: default_min_free_spool_mb(),
+            max_clock_skew_secs: default_max_clock_skew_secs()
+        }
+    }
+}
+
+/// Delays before a spooled bounce with an unknown hash is looked up again;
+/// empty (the default) writes the orphan `mail_bounces` row right away.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MissingMessageRetryConfig {
+    #[serde(
+        default,
+        deserialize_with = "bouncer_helpers::de::deserialize_durations",
+        serialize_with = "bouncer_helpers::de::serialize_durations"
+    )]
+    pub delays: Vec<Duration>
+}
+
+/// Delays before a spool file that failed on a database error is tried
+/// again; once they are used up the file moves to `failed/`. Empty sends it
+/// there right away.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DbRetryConfig {
+    #[serde(
+        default = "default_db_retry_delays",
+        deserialize_with = "bouncer_helpers::de::deserialize_durations",
+        serialize_with = "bouncer_helpers::de::serialize_durations"
+    )]
+    pub delays: Vec<Duration>
+}
+
+impl Default for DbRetryConfig {
+    fn default() -> Self {
+        Self { delays: default_db_retry_delays() }
+    }
+}
+
+fn default_db_retry_delays() -> Vec<Duration> {
+    [60, 600, 3_600].map(Duration::from_secs).to_vec()
+}
+
+/// Spool priority lanes.
+///
+/// Frames whose `kind` or `source` header is listed here are spooled to the
+/// low lane; everything else goes to the high lane.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DispatcherConfig {
+    /// High lane capacity; defaults to `worker_concurrency * process_queue_per_worker`.
+    #[serde(default)]
+    pub high_queue_size: Option<usize>,
+    #[serde(default = "default_low_queue_size")]
+    pub low_queue_size: usize,
+    /// High-lane files served before a waiting low-lane file gets a turn.
+    #[serde(default = "default_high_weight")]
+    pub high_weight: u32,
+    #[serde(default)]
+    pub low_priority_kinds: Vec<String>,
+    #[serde(default)]
+    pub low_priority_sources: Vec<String>,
+    /// Paths the periodic scan reads from `incoming/` per batch.
+    #[serde(default = "default_scan_batch_size")]
+    pub scan_batch_size: usize,
+    /// Paths the periodic scan queues per tick at most; the rest of a large
+    /// backlog waits for the next tick.
+    #[serde(default = "default_scan_max_per_tick")]
+    pub scan_max_per_tick: usize,
+    /// Files a worker spends longer on are logged as `SLOW_SPOOL_FILE` with
+    /// their stage timings; `null` turns the warning off.
+    #[serde(
+        default = "default_slow_file_threshold",
+        deserialize_with = "bouncer_helpers::de::deserialize_optional_duration",
+        serialize_with = "bouncer_helpers::de::serialize_optional_duration"
+    )]
+    pub slow_file_threshold: Option<Duration>
+}
+
+impl Default for DispatcherConfig {
+    fn default() -> Self {
+        Self {
+            high_queue_size: None,
+            low_queue_size: default_low_queue_size(),
+            high_weight: default_high_weight(),
+            low_priority_kinds: Vec::new(),
+            low_priority_sources: Vec::new(),
+            scan_batch_size: default_scan_batch_size(),
+            scan_max_per_tick: default_scan_max_per_tick(),
+            slow_file_threshold: default_slow_file_threshold()
+        }
+    }
+}
+
+impl DispatcherConfig {
+    pub fn lane_for(
+        &self,
+        kind: Option<&str>,
+        source: Option<&str>
+    ) -> Lane {
+        let listed = |values: &[String], value: Option<&str>| {
+            value
+                .is_some_and(|value| values.iter().any(|listed| listed.eq_ignore_ascii_case(value)))
+        };
+
+        if listed(&self.low_priority_kinds, kind) || listed(&self.low_priority_sources, source) {
+            Lane::Low
+        } else {
+            Lane::High
+        }
+    }
+
+    fn normalize(&mut self) {
+        self.high_queue_size = self.high_queue_size.map(|size| size.max(1));
+        self.low_queue_size = self.low_queue_size.max(1);
+        self.high_weight = self.high_weight.max(1);
+        self.scan_batch_size = self.scan_batch_size.max(1);
+        self.scan_max_per_tick = self.scan_max_per_tick.max(1);
+        for values in [&mut self.low_priority_kinds, &mut self.low_priority_sources] {
+            *values = values
+                .iter()
+                .map(|value| trim_owned(value.clone()))
+                .filter(|value| !value.is_empty())
+                .collect();
+        }
+    }
+}
+
+/// Retries of transient database errors (lost connections, pool timeouts,
+/// deadlocks) and the circuit breaker that pauses spool workers while the
+/// database is down.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseResilienceConfig {
+    /// Attempts after the first one; 0 gives up on the first transient error.
+    #[serde(default = "default_db_retries")]
+    pub retries: u32,
+    /// Bounds of the decorrelated backoff between attempts.
+    #[serde(
+        default = "default_db_retry_base",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub retry_base: Duration,
+    #[serde(
+        default = "default_db_retry_max",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub retry_max: Duration,
+    /// Operations in a row that exhaust their retries before the breaker opens.
+    #[serde(default = "default_db_breaker_threshold")]
+    pub breaker_threshold: u32,
+    /// Interval of the `SELECT 1` health check; a success closes the breaker.
+    #[serde(
+        default = "default_db_health_interval",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub health_interval: Duration
+}
+
+impl Default for DatabaseResilienceConfig {
+    fn default() -> Self {
+        Self {
+            retries: default_db_retries(),
+            retry_base: default_db_retry_base(),
+            retry_max: default_db_retry_max(),
+            breaker_threshold: default_db_breaker_threshold(),
+            health_interval: default_db_health_interval()
+        }
+    }
+}
+
+impl DatabaseResilienceConfig {
+    fn normalize(&mut self) {
+        self.retry_base = self.retry_base.max(Duration::from_millis(1));
+        self.retry_max = self.retry_max.max(self.retry_base);
+        self.breaker_threshold = self.breaker_threshold.max(1);
+        self.health_interval = self.health_interval.max(Duration::from_secs(1));
+    }
+}
+
+/// Write-behind batching of observer events: up to `max_size` queued events
+/// share one transaction, flushed `flush_interval` after the first one at
+/// the latest. A `max_size` of 1 writes every event on its own.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseBatchConfig {
+    #[serde(default = "default_db_batch_max_size")]
+    pub max_size: usize,
+    #[serde(
+        default = "default_db_batch_flush_interval",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub flush_interval: Duration
+}
+
+impl Default for DatabaseBatchConfig {
+    fn default() -> Self {
+        Self {
+            max_size: default_db_batch_max_size(),
+            flush_interval: default_db_batch_flush_interval()
+        }
+    }
+}
+
+impl DatabaseBatchConfig {
+    pub fn enabled(&self) -> bool {
+        self.max_size > 1
+    }
+
+    fn normalize(&mut self) {
+        self.max_size = self.max_size.clamp(1, 1_000);
+        self.flush_interval =
+            self.flush_interval.clamp(Duration::from_millis(1), Duration::from_secs(1));
+    }
+}
+
+/// Cache of `mail_messages` ids by hash, in front of the lookup every bounce
+/// and observer event makes. Off while `max_entries` is 0.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MessageCacheConfig {
+    /// Hashes kept; the least recently used entry is dropped once full.
+    #[serde(default)]
+    pub max_entries: usize,
+    /// How long a found id is reused.
+    #[serde(
+        default = "default_message_cache_ttl",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub ttl: Duration,
+    /// How long a hash without a message is remembered as missing; kept
+    /// short, since the application may insert the message any moment. 0
+    /// never caches a miss.
+    #[serde(
+        default = "default_message_cache_negative_ttl",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub negative_ttl: Duration
+}
+
+impl Default for MessageCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 0,
+            ttl: default_message_cache_ttl(),
+            negative_ttl: default_message_cache_negative_ttl()
+        }
+    }
+}
+
+impl MessageCacheConfig {
+    pub fn enabled(&self) -> bool {
+        self.max_entries > 0 && !self.ttl.is_zero()
+    }
+
+    fn normalize(&mut self) {
+        self.max_entries = self.max_entries.min(10_000_000);
+        self.negative_ttl = self.negative_ttl.min(self.ttl);
+    }
+}
+
+/// Sampled capture of ingested payload heads into the `ingest` span and logs,
+/// for spotting format changes without pulling spool files. Off unless a
+/// sample rate is set.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PayloadCaptureConfig {
+    /// Share of frames captured, `0.0..=1.0`, for sources not listed below.
+    #[serde(default)]
+    pub sample_rate: f64,
+    /// Per-source sample rates.
+    #[serde(default)]
+    pub sources: BTreeMap<String, f64>,
+    #[serde(default = "default_payload_capture_max_bytes")]
+    pub max_bytes: usize,
+    #[serde(default)]
+    pub redact: RedactionConfig
+}
+
+impl Default for PayloadCaptureConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.0,
+            sources: BTreeMap::new(),
+            max_bytes: default_payload_capture_max_bytes(),
+            redact: RedactionConfig::default()
+        }
+    }
+}
+
+impl PayloadCaptureConfig {
+    pub fn enabled(&self) -> bool {
+        self.sample_rate > 0.0 || self.sources.values().any(|rate| *rate > 0.0)
+    }
+
+    /// Sample rate of `source`.
+    pub fn rate_for(
+        &self,
+        source: &str
+    ) -> f64 {
+        self.sources.get(source).copied().unwrap_or(self.sample_rate)
+    }
+
+    fn normalize(&mut self) {
+        let clamp = |rate: f64| if rate.is_nan() { 0.0 } else { rate.clamp(0.0, 1.0) };
+        self.sample_rate = clamp(self.sample_rate);
+        self.sources = std::mem::take(&mut self.sources)
+            .into_iter()
+            .map(|(source, rate)| (trim_owned(source), clamp(rate)))
+            .filter(|(source, _)| !source.is_empty())
+            .collect();
+        self.max_bytes = self.max_bytes.clamp(1, MAX_PAYLOAD_CAPTURE_BYTES);
+        self.redact.headers = self
+            .redact
+            .headers
+            .iter()
+            .map(|name| trim_owned(name.clone()))
+            .filter(|name| !name.is_empty())
+            .collect();
+    }
+}
+
+/// Records every inbound TCP frame as it arrived: peer, raw header, body
+/// size and the first `max_body_bytes` of the body, unredacted. Meant for
+/// debugging client integrations; the admin API turns it on and off.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FrameAuditConfig {
+    /// Audit from startup instead of waiting for the admin API.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directory that keeps the newest `max_frames` frames as
+    /// `<id>.json` and `<id>.body`; without it frames are logged.
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+    /// 0 records no body.
+    #[serde(default = "default_frame_audit_max_body_bytes")]
+    pub max_body_bytes: usize,
+    #[serde(default = "default_frame_audit_max_frames")]
+    pub max_frames: usize
+}
+
+impl Default for FrameAuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: None,
+            max_body_bytes: default_frame_audit_max_body_bytes(),
+            max_frames: default_frame_audit_max_frames()
+        }
+    }
+}
+
+impl FrameAuditConfig {
+    fn normalize(&mut self) {
+        self.max_frames = self.max_frames.max(1);
+    }
+}
+
+/// Applied to captured payloads before they reach spans or logs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactionConfig {
+    /// Mask the local part of email addresses (`***@example.com`).
+    #[serde(default = "default_true")]
+    pub emails: bool,
+    /// Header names whose values are replaced with `[redacted]`.
+    #[serde(default = "default_redacted_headers")]
+    pub headers: Vec<String>
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self { emails: true, headers: default_redacted_headers() }
+    }
+}
+
+/// Checks that a spooled bounce was signed by the sender's domain or handed
+/// to us by a trusted relay before it is applied, so a forged DSN cannot
+/// fail messages or suppress recipients.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthenticationConfig {
+    #[serde(default)]
+    pub mode: AuthenticationMode,
+    /// Verify DKIM signatures; a signature passes when its `d=` domain is the
+    /// `From` domain or a parent of it.
+    #[serde(default = "default_true")]
+    pub dkim: bool,
+    /// Relays whose bounces pass without checks, matched against the peer
+    /// address our MTA recorded in the topmost `Received`: addresses, CIDR
+    /// ranges, or host names resolved at check time. `local` matches bounces
+    /// generated on this host (no `from` in the topmost `Received`).
+    #[serde(default = "default_trusted_relays")]
+    pub trusted_relays: Vec<String>,
+    /// Limit for the DKIM key lookups of one message, and for each
+    /// `trusted_relays` host name lookup.
+    #[serde(default = "default_dns_timeout_secs")]
+    pub dns_timeout_secs: u64
+}
+
+impl Default for AuthenticationConfig {
+    fn default() -> Self {
+        Self {
+            mode: AuthenticationMode::default(),
+            dkim: true,
+            trusted_relays: default_trusted_relays(),
+            dns_timeout_secs: default_dns_timeout_secs()
+        }
+    }
+}
+
+/// What happens to a bounce no enabled check vouches for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthenticationMode {
+    /// Run no checks.
+    #[default]
+    Off,
+    /// Record and log the result, apply the bounce anyway.
+    Log,
+    /// Record the result and move the bounce to `failed/` unapplied.
+    Reject
+}
+
+impl AuthenticationConfig {
+    pub fn enabled(&self) -> bool {
+        self.mode != AuthenticationMode::Off
+    }
+
+    fn normalize(&mut self) {
+        self.trusted_relays = self
+            .trusted_relays
+            .iter()
+            .map(|relay| trim_owned(relay.clone()).to_ascii_lowercase())
+            .filter(|relay| !relay.is_empty())
+            .collect();
+        self.dns_timeout_secs = self.dns_timeout_secs.max(1);
+    }
+}
+
+/// Copies of applied bounce mails kept as evidence and indexed by hash in
+/// `bounce_archive`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArchiveConfig {
+    #[serde(default)]
+    pub destination: ArchiveDestination,
+    /// Gzip the copy before storing it.
+    #[serde(default = "default_true")]
+    pub compress: bool,
+    /// Delete archived copies older than this; unset keeps them forever.
+    #[serde(
+        default,
+        deserialize_with = "bouncer_helpers::de::deserialize_optional_duration",
+        serialize_with = "bouncer_helpers::de::serialize_optional_duration"
+    )]
+    pub retention: Option<Duration>,
+    /// Required for `destination: s3`.
+    #[serde(default)]
+    pub s3: Option<S3Config>
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            destination: ArchiveDestination::default(),
+            compress: true,
+            retention: None,
+            s3: None
+        }
+    }
+}
+
+/// Provenance of processed bounces in `mail_bounce_audit` and, for spooled
+/// mail, as `X-Bouncer-Audit-*` headers on the `done/` copy.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Prepend the audit headers to files moved to `done/`.
+    #[serde(default = "default_true")]
+    pub done_headers: bool,
+    /// `mail_bounce_audit` rows processed longer ago are pruned hourly.
+    #[serde(
+        default = "default_audit_retention",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub retention: Duration
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self { enabled: true, done_headers: true, retention: default_audit_retention() }
+    }
+}
+
+/// Bounce-rate alerts evaluated on every applied outcome; see
+/// [`crate::core::BounceAlerts`].
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertsConfig {
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+    /// Where fired alerts go; a `log` sink when empty.
+    #[serde(default)]
+    pub sinks: Vec<AlertSink>
+}
+
+/// Fires when, for one recipient domain, at least `threshold_pct` of the
+/// outcomes within `window` have a status code in `status_codes`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub name: String,
+    /// Recipient domains the rule watches (`*` and `?` allowed); empty
+    /// watches every domain. Each domain is counted on its own.
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// Enhanced status codes counted against the threshold; entries ending
+    /// in `*` match by prefix.
+    #[serde(default = "default_alert_status_codes")]
+    pub status_codes: Vec<String>,
+    pub threshold_pct: f64,
+    #[serde(
+        default = "default_alert_window",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub window: Duration,
+    /// Outcomes a domain needs within `window` before the rate is checked.
+    #[serde(default = "default_alert_min_messages")]
+    pub min_messages: u64,
+    /// Quiet time after an alert for the same rule and domain; defaults to
+    /// `window`.
+    #[serde(
+        default,
+        deserialize_with = "bouncer_helpers::de::deserialize_optional_duration",
+        serialize_with = "bouncer_helpers::de::serialize_optional_duration"
+    )]
+    pub cooldown: Option<Duration>
+}
+
+impl AlertRule {
+    pub fn matches_status_code(
+        &self,
+        status_code: &str
+    ) -> bool {
+        matches_status_code(&self.status_codes, status_code)
+    }
+
+    pub fn matches_domain(
+        &self,
+        domain: &str
+    ) -> bool {
+        self.domains.is_empty() || self.domains.iter().any(|pattern| glob_match(pattern, domain))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum AlertSink {
+    /// `ERROR_CODE=BOUNCE_RATE_ALERT` warning.
+    Log,
+    /// POSTs the alert as JSON.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        #[serde(default = "default_alert_timeout_secs")]
+        timeout_secs: u64
+    },
+    /// Runs `command[0]` with the remaining arguments, the alert as JSON on
+    /// stdin and as `BOUNCER_ALERT_*` variables.
+    Exec {
+        command: Vec<String>,
+        #[serde(default = "default_alert_timeout_secs")]
+        timeout_secs: u64
+    }
+}
+
+impl AlertSink {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Log => "log",
+            Self::Webhook { .. } => "webhook",
+            Self::Exec { .. } => "exec"
+        }
+    }
+}
+
+impl AlertsConfig {
+    pub fn enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    fn normalize(&mut self) {
+        for rule in &mut self.rules {
+            rule.name = trim_owned(rule.name.clone());
+            rule.domains = rule
+                .domains
+                .iter()
+                .map(|domain| domain.trim().trim_start_matches('@').to_ascii_lowercase())
+                .filter(|domain| !domain.is_empty())
+                .collect();
+            rule.status_codes = normalize_status_codes(&rule.status_codes);
+            rule.min_messages = rule.min_messages.max(1);
+        }
+        for sink in &mut self.sinks {
+            match sink {
+                AlertSink::Log => {}
+                AlertSink::Webhook { url, timeout_secs, .. } => {
+                    *url = trim_owned(url.clone());
+                    *timeout_secs = (*timeout_secs).max(1);
+                }
+                AlertSink::Exec { timeout_secs, .. } => *timeout_secs = (*timeout_secs).max(1)
+            }
+        }
+        if self.enabled() && self.sinks.is_empty() {
+            self.sinks.push(AlertSink::Log);
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut names = BTreeSet::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            if rule.name.is_empty() {
+                bail!("server config `alerts.rules[{index}].name` must not be empty");
+            }
+            if !names.insert(rule.name.as_str()) {
+                bail!("server config `alerts.rules` name listed twice: {}", rule.name);
+            }
+            if !(rule.threshold_pct > 0.0 && rule.threshold_pct <= 100.0) {
+                bail!(
+                    "server config `alerts.rules[{index}].threshold_pct` must be in (0, 100]: {}",
+                    rule.threshold_pct
+                );
+            }
+            if rule.status_codes.is_empty() {
+                bail!("server config `alerts.rules[{index}].status_codes` must not be empty");
+            }
+            if rule.window.is_zero() {
+                bail!("server config `alerts.rules[{index}].window` must not be zero");
+            }
+        }
+        for (index, sink) in self.sinks.iter().enumerate() {
+            match sink {
+                AlertSink::Webhook { url, .. }
+                    if !(url.starts_with("https://") || url.starts_with("http://")) =>
+                {
+                    bail!("server config `alerts.sinks[{index}].url` must be an http(s) URL");
+                }
+                AlertSink::Exec { command, .. } if command.is_empty() => {
+                    bail!("server config `alerts.sinks[{index}].command` must not be empty");
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs `command[0]` with the remaining arguments for every processed
+/// bounce, with the bounce as JSON on stdin and as `BOUNCER_HOOK_*`
+/// variables.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookConfig {
+    /// Names the hook in logs.
+    pub name: String,
+    pub command: Vec<String>,
+    /// Runs of one bounce that take longer are killed and count as failed.
+    #[serde(
+        default = "default_hook_timeout",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub timeout: Duration,
+    /// Runs of the command at the same time.
+    #[serde(default = "default_hook_concurrency")]
+    pub concurrency: usize,
+    /// Bounces waiting for a run; further ones are dropped with a warning,
+    /// so a slow command never holds up processing.
+    #[serde(default = "default_hook_queue_size")]
+    pub queue_size: usize,
+    #[serde(default)]
+    pub on_failure: HookFailurePolicy,
+    /// With `on_failure: retry`, runs after the first one.
+    #[serde(default = "default_hook_retries")]
+    pub retries: u32,
+    /// With `on_failure: retry`, the delay before the first retry; it
+    /// doubles for each further one.
+    #[serde(
+        default = "default_hook_retry_delay",
+        deserialize_with = "bouncer_helpers::de::deserialize_duration",
+        serialize_with = "bouncer_helpers::de::serialize_duration"
+    )]
+    pub retry_delay: Duration
+}
+
+/// What happens to a bounce whose hook run failed or timed out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFailurePolicy {
+    /// `ERROR_CODE=HOOK_FAILED` warning; the bounce is not run again.
+    #[default]
+    Log,
+    /// Run again up to `retries` times, then log.
+    Retry
+}
+
+impl HookConfig {
+    fn normalize(&mut self) {
+        self.name = trim_owned(self.name.clone());
+        self.timeout = self.timeout.max(Duration::from_millis(100));
+        self.concurrency = self.concurrency.clamp(1, 64);
+        self.queue_size = self.queue_size.max(1);
+        self.retry_delay = self.retry_delay.max(Duration::from_millis(10));
+    }
+
+    fn validate(
+        &self,
+        index: usize
+    ) -> Result<()> {
+        if self.name.is_empty() {
+            bail!("server config `hooks[{index}].name` must not be empty");
+        }
+        if self.command.first().is_none_or(|program| program.trim().is_empty()) {
+            bail!("server config `hooks[{index}].command` must not be empty");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveDestination {
+    #[default]
+    Off,
+    /// In `bounce_archive.body`.
+    Database,
+    /// In an S3-compatible bucket; `bounce_archive` keeps the object key.
+    S3
+}
+
+/// S3-compatible bucket addressed path-style (`<endpoint>/<bucket>/<key>`),
+/// which AWS, MinIO, Ceph and Garage all accept.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct S3Config {
+    /// Such as `https://s3.eu-central-1.amazonaws.com` or `http://minio:9000`.
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    /// Prepended to every object key.
+    #[serde(default)]
+    pub prefix: String,
+    /// Falls back to `AWS_ACCESS_KEY_ID`.
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    /// Falls back to `AWS_SECRET_ACCESS_KEY`.
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+    /// Limit for opening the connection to `endpoint`.
+    #[serde(default = "default_s3_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Limit for a whole request, response body included.
+    #[serde(default = "default_s3_timeout_secs")]
+    pub timeout_secs: u64
+}
+
+impl ArchiveConfig {
+    pub fn enabled(&self) -> bool {
+        self.destination != ArchiveDestination::Off
+    }
+
+    fn normalize(&mut self) {
+        if let Some(s3) = self.s3.as_mut() {
+            s3.normalize();
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.destination != ArchiveDestination::S3 {
+            return Ok(());
+        }
+        let Some(s3) = self.s3.as_ref() else {
+            bail!("server config `archive.destination: s3` requires an `archive.s3` block");
+        };
+        s3.validate("archive.s3")
+    }
+}
+
+impl S3Config {
+    fn normalize(&mut self) {
+        self.endpoint = self.endpoint.trim().trim_end_matches('/').to_string();
+        self.bucket = trim_owned(self.bucket.clone());
+        self.region = trim_owned(self.region.clone());
+        if self.region.is_empty() {
+            self.region = default_s3_region();
+        }
+        self.prefix = self.prefix.trim().trim_start_matches('/').to_string();
+        self.access_key_id =
+            normalize_opt(self.access_key_id.take()).or_else(|| non_empty_env("AWS_ACCESS_KEY_ID"));
+        self.secret_access_key = normalize_opt(self.secret_access_key.take())
+            .or_else(|| non_empty_env("AWS_SECRET_ACCESS_KEY"));
+        self.connect_timeout_secs = self.connect_timeout_secs.max(1);
+        self.timeout_secs = self.timeout_secs.max(1);
+    }
+
+    /// `key` names the block in errors, such as `archive.s3`.
+    fn validate(
+        &self,
+        key: &str
+    ) -> Result<()> {
+        if !(self.endpoint.starts_with("https://") || self.endpoint.starts_with("http://")) {
+            bail!("server config `{key}.endpoint` must be an http(s) URL");
+        }
+        if self.bucket.is_empty() {
+            bail!("server config `{key}.bucket` must not be empty");
+        }
+        if self.access_key_id.is_none() || self.secret_access_key.is_none() {
+            bail!(
+                "server config `{key}` needs `access_key_id` and `secret_access_key` (or AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY)"
+            );
+        }
+        Ok(())
+    }
+}
+
+fn resolve_server_config_path() -> Option<PathBuf> {
+    if let Some(path) = non_empty_env("BOUNCER_CONFIG_PATH") {
+        return Some(PathBuf::from(path));
+    }
+
+    if let Some(home) = non_empty_env("HOME") {
+        let home_yaml = PathBuf::from(&home).join("bouncer.yaml");
+        if home_yaml.exists() {
+            return Some(home_yaml);
+        }
+
+        let home_yml = PathBuf::from(home).join("bouncer.yaml");
+        if home_yml.exists() {
+            return Some(home_yml);
+        }
+    }
+
+    let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let cwd_yaml = cwd.join("bouncer.yaml");
+    if cwd_yaml.exists() {
+        return Some(cwd_yaml);
+    }
+
+    let cwd_yml = cwd.join("bouncer.yaml");
+    if cwd_yml.exists() {
+        return Some(cwd_yml);
+    }
+
+    None
+}
+
+fn default_listen() -> Vec<String> {
+    vec!["0.0.0.0:2147".to_string()]
+}
+
+fn default_parse_max_candidates() -> usize {
+    256
+}
+
+fn default_parse_max_candidate_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_parse_max_scan_time() -> Duration {
+    Duration::from_secs(2)
+}
+
+fn default_max_header_bytes() -> u32 {
+    DEFAULT_MAX_HEADER_BYTES
+}
+
+fn default_max_body_bytes() -> u64 {
+    DEFAULT_MAX_BODY_BYTES
+}
+
+fn default_spool() -> PathBuf {
+    let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    cwd.join("storage/spool/bouncer")
+}
+
+fn default_worker_concurrency() -> usize {
+    4
+}
+
+fn default_process_queue_per_worker() -> usize {
+    1024
+}
+
+fn default_incoming_scan_secs() -> u64 {
+    60
+}
+
+fn default_imap_port() -> u16 {
+    993
+}
+
+fn default_imap_mailboxes() -> Vec<String> {
+    vec!["INBOX".to_string()]
+}
+
+fn default_imap_max_sessions() -> usize {
+    4
+}
+
+fn default_imap_max_processing() -> usize {
+    16
+}
+
+fn default_imap_poll_secs() -> u64 {
+    60
+}
+
+fn default_imap_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_imap_max_messages_per_poll() -> usize {
+    200
+}
+
+fn default_smtp_max_message_bytes() -> u64 {
+    25 * 1024 * 1024
+}
+
+fn default_smtp_max_connections() -> usize {
+    100
+}
+
+fn default_suppression_status_codes() -> Vec<String> {
+    ["5.1.1", "5.1.2", "5.1.3", "5.1.6", "5.1.10", "5.2.1"].map(str::to_string).to_vec()
+}
+
+fn default_min_free_spool_mb() -> u64 {
+    256
+}
+
+fn default_max_clock_skew_secs() -> u64 {
+    60
+}
+
+fn default_low_queue_size() -> usize {
+    1024
+}
+
+fn default_high_weight() -> u32 {
+    4
+}
+
+fn default_scan_batch_size() -> usize {
+    256
+}
+
+fn default_scan_max_per_tick() -> usize {
+    4096
+}
+
+fn default_slow_file_threshold() -> Option<Duration> {
+    Some(Duration::from_secs(5))
+}
+
+fn default_db_retries() -> u32 {
+    3
+}
+
+fn default_db_retry_base() -> Duration {
+    Duration::from_millis(200)
+}
+
+fn default_db_retry_max() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_db_breaker_threshold() -> u32 {
+    3
+}
+
+fn default_db_health_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_message_cache_ttl() -> Duration {
+    Duration::from_secs(600)
+}
+
+fn default_message_cache_negative_ttl() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_db_batch_max_size() -> usize {
+    1
+}
+
+fn default_db_batch_flush_interval() -> Duration {
+    Duration::from_millis(10)
+}
+
+fn default_admin_min_reported_count() -> u32 {
+    10
+}
+
+fn default_client_idle_secs() -> u64 {
+    300
+}
+
+fn default_client_heartbeat_misses() -> u32 {
+    3
+}
+
+fn default_tcp_keepalive_secs() -> u64 {
+    60
+}
+
+fn default_source_silent_after_secs() -> u64 {
+    300
+}
+
+fn default_source_events_retention() -> Duration {
+    Duration::from_secs(30 * 24 * 3600)
+}
+
+fn default_audit_retention() -> Duration {
+    Duration::from_secs(90 * 24 * 3600)
+}
+
+fn default_payload_capture_max_bytes() -> usize {
+    512
+}
+
+fn default_recipient_tag_separator() -> char {
+    '+'
+}
+
+fn default_frame_audit_max_body_bytes() -> usize {
+    4096
+}
+
+fn default_frame_audit_max_frames() -> usize {
+    1000
+}
+
+fn default_redacted_headers() -> Vec<String> {
+    ["Authorization", "Cookie", "DKIM-Signature", "X-Api-Key"].map(str::to_string).to_vec()
+}
+
+fn default_trusted_relays() -> Vec<String> {
+    ["local", "localhost", "127.0.0.1", "::1"].map(str::to_string).to_vec()
+}
+
+fn default_spool_lock_stale_after() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_s3_connect_timeout_secs() -> u64 {
+    5
+}
+
+fn default_s3_timeout_secs() -> u64 {
+    30
+}
+
+fn default_dns_timeout_secs() -> u64 {
+    5
+}
+
+fn default_alert_status_codes() -> Vec<String> {
+    vec!["5.*".to_string()]
+}
+
+fn default_alert_window() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+fn default_alert_min_messages() -> u64 {
+    20
+}
+
+fn default_hook_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_hook_concurrency() -> usize {
+    4
+}
+
+fn default_hook_queue_size() -> usize {
+    1024
+}
+
+fn default_hook_retries() -> u32 {
+    3
+}
+
+fn default_hook_retry_delay() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_alert_timeout_secs() -> u64 {
+    10
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_parser_chain() -> Vec<String> {
+    DEFAULT_PARSER_CHAIN.map(str::to_string).to_vec()
+}
+
+fn default_hash_headers() -> Vec<String> {
+    DEFAULT_HASH_HEADERS.map(str::to_string).to_vec()
+}
+
+/// `imap` as one account or a list of them. A visitor rather than an
+/// untagged enum, so unknown keys keep their precise error.
+fn deserialize_imap_accounts<'de, D>(deserializer: D) -> Result<Vec<ImapConfig>, D::Error>
+where
+    D: serde::Deserializer<'de>
+{
+    use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+    use serde::de::{MapAccess, SeqAccess, Visitor};
+
+    struct Accounts;
+
+    impl<'de> Visitor<'de> for Accounts {
+        type Value = Vec<ImapConfig>;
+
+        fn expecting(
+            &self,
+            formatter: &mut std::fmt::Formatter
+        ) -> std::fmt::Result {
+            formatter.write_str("an imap account or a list of them")
+        }
+
+        fn visit_unit<E>(self) -> Result<Self::Value, E> {
+            Ok(Vec::new())
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E> {
+            Ok(Vec::new())
+        }
+
+        fn visit_map<A: MapAccess<'de>>(
+            self,
+            map: A
+        ) -> Result<Self::Value, A::Error> {
+            ImapConfig::deserialize(MapAccessDeserializer::new(map)).map(|account| vec![account])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(
+            self,
+            seq: A
+        ) -> Result<Self::Value, A::Error> {
+            Vec::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    deserializer.deserialize_any(Accounts)
+}
+
+fn deserialize_one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>
+{
+    #[derive(Deserialize, Serialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>)
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values
+    })
+}
+
+fn normalize_opt(value: Option<String>) -> Option<String> {
+    value.and_then(|value| {
+        let trimmed = value.trim();
+        if trimmed.is_empty() { None } else { Some(trimmed.to_string()) }
+    })
+}
+
+fn trim_owned(value: String) -> String {
+    value.trim().to_string()
+}
+
+fn non_empty_env(key: &str) -> Option<String> {
+    env::var(key).ok().and_then(|value| {
+        let trimmed = value.trim();
+        if trimmed.is_empty() { None } else { Some(trimmed.to_string()) }
+    })
+}
@@ -0,0 +1,723 @@
+//! Admin HTTP API (`admin_listen`).
+//!
+//! A small JSON surface for operators: spool counts, a dry-run parse of one
+//! spooled file, requeueing a failed file, waking the incoming scan or the
+//! IMAP pollers, toggling the frame audit and debug logging, the
+//! diagnostics dump, the server and per-source counters, and
+//! un-suppressing recipients. Every request needs
+//! `Authorization: Bearer <admin_token>`.
+//!
+//! - `GET /spool`: counts per spool state.
+//! - `GET /spool/{state}/{file}`: parse result of a file in `incoming`,
+//!   `processing`, `done` or `failed`, without touching the database.
+//! - `POST /spool/failed/{file}/requeue`: moves a failed file back to
+//!   `incoming/`.
+//! - `POST /scan`: scans `incoming/` now.
+//! - `POST /imap/poll`: polls every idle IMAP mailbox now.
+//! - `GET /frame-audit`: whether inbound frames are being audited, and where.
+//! - `POST /frame-audit/enable`, `POST /frame-audit/disable`: toggles the
+//!   frame audit until the next restart.
+//! - `GET /debug-logging`: whether debug logging is on, and its filter.
+//! - `POST /debug-logging/enable`, `POST /debug-logging/disable`: switches
+//!   debug logging like SIGUSR1 does.
+//! - `GET /diagnostics`: the dump SIGUSR2 logs.
+//! - `GET /stats`: the counters of `bouncer-admin stats` under `server` and
+//!   those of `bouncer-admin sources` under `sources`.
+//! - `GET /suppressions`: the suppression list, most recently refreshed
+//!   first, up to [`SUPPRESSION_LIST_LIMIT`] entries.
+//! - `GET /suppressions/domains`: suppression counts per recipient domain
+//!   and reason, with counts below `admin_min_reported_count` withheld, for
+//!   sharing outside the team.
+//! - `GET /suppressions/audit`: removals, expiries and re-activations of
+//!   suppression entries, newest first, up to [`SUPPRESSION_LIST_LIMIT`].
+//! - `DELETE /suppressions/{recipient}`: removes a recipient from the
+//!   suppression list, e.g. after they confirmed the address again.
+//! - `POST /suppressions/{recipient}/expire`: ends a suppression as if it
+//!   had run out, e.g. a soft bounce that has since been resolved.
+//!
+//! Removals and expiries keep the entry, marked as no longer suppressing,
+//! and are audited in `suppression_audit` with the `X-Admin-Actor` header
+//! (`admin_api` without one) as the actor.
+//!
+//! `{file}` is the path relative to the state directory, e.g.
+//! `mail-01/<uuid>.eml` in a partitioned spool.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use axum::extract::{Path as UrlPath, Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use bouncer_helpers::debug_signals::apply_debug_logging;
+use bouncer_helpers::logging::{self, DEBUG_FILTER};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use super::database::{DomainSuppressions, Suppression, SuppressionChange};
+use super::debug_dump::diagnostics_dump;
+use super::query::server_stats;
+use super::server::gunzip_archived;
+use super::spool::archived_files;
+use crate::app::AppState;
+
+/// Most entries `GET /suppressions` and `GET /suppressions/audit` return.
+const SUPPRESSION_LIST_LIMIT: u32 = 1000;
+
+/// Names who un-suppressed a recipient in `suppression_audit`.
+const ACTOR_HEADER: &str = "x-admin-actor";
+const DEFAULT_ACTOR: &str = "admin_api";
+
+/// Wakes background loops ahead of their next tick.
+#[derive(Debug, Default)]
+pub struct AdminTriggers {
+    /// Picked up by the periodic incoming scan; kept until then.
+    pub incoming_scan: Notify,
+    /// Wakes the IMAP mailbox loops that are waiting for their next poll.
+    pub imap_poll: Notify
+}
+
+#[derive(Clone)]
+struct ApiState {
+    app: AppState,
+    token: String,
+    /// `admin_min_reported_count`, see [`domain_report`].
+    min_count: u32
+}
+
+/// Binds `listen` and serves the admin API until shutdown.
+pub async fn run_admin_api(
+    listen: &str,
+    token: String,
+    min_count: u32,
+    state: AppState
+) -> Result<()> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("failed to bind admin api on {listen}"))?;
+    info!("admin api enabled: listen={}", listen);
+
+    let shutdown = state.shutdown.clone();
+    axum::serve(listener, router(ApiState { app: state, token, min_count }))
+        .with_graceful_shutdown(async move { shutdown.cancelled().await })
+        .await
+        .context("admin api failed")?;
+    info!("admin api stopping");
+    Ok(())
+}
+
+fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/spool", get(spool_counts))
+        .route("/spool/{state}/{*file}", get(parse_spool_file).post(requeue_failed))
+        .route("/scan", post(trigger_scan))
+        .route("/imap/poll", post(trigger_imap_poll))
+        .route("/frame-audit", get(frame_audit))
+        .route("/frame-audit/{action}", post(toggle_frame_audit))
+        .route("/debug-logging", get(debug_logging))
+        .route("/debug-logging/{action}", post(toggle_debug_logging))
+        .route("/diagnostics", get(diagnostics))
+        .route("/stats", get(stats))
+        .route("/suppressions", get(list_suppressions))
+        .route("/suppressions/domains", get(suppressed_domains))
+        .route("/suppressions/audit", get(suppression_audit))
+        .route("/suppressions/{recipient}", delete(remove_suppression))
+        .route("/suppressions/{recipient}/expire", post(expire_suppression))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+async fn require_token(
+    State(state): State<ApiState>,
+    request: Request,
+    next: Next
+) -> Response {
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match presented {
+        Some(token) if constant_time_eq(token.trim().as_bytes(), state.token.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => error(StatusCode::UNAUTHORIZED, "missing or invalid admin token")
+    }
+}
+
+async fn spool_counts(State(state): State<ApiState>) -> Response {
+    let counts = async {
+        let counts = state.app.spool.counts().await?;
+        let quarantined = state.app.spool.quarantined_events().await?.len();
+        anyhow::Ok(json!({
+            "incoming": counts.incoming,
+            "processing": counts.processing,
+            "done": counts.done,
+            "failed": counts.failed,
+            "quarantined": quarantined
+        }))
+    };
+    match counts.await {
+        Ok(counts) => Json(counts).into_response(),
+        Err(err) => internal(err)
+    }
+}
+
+async fn parse_spool_file(
+    State(state): State<ApiState>,
+    UrlPath((spool_state, file)): UrlPath<(String, String)>
+) -> Response {
+    let Some(dir) = state_dir(&state.app, &spool_state) else {
+        return error(StatusCode::NOT_FOUND, format!("unknown spool state: {spool_state}"));
+    };
+    let path = match find_spool_file(dir, &file).await {
+        Ok(Some(path)) => path,
+        Ok(None) => return error(StatusCode::NOT_FOUND, format!("no such file: {file}")),
+        Err(err) => return internal(err)
+    };
+    let raw_mail = match tokio::fs::read(&path).await.map_err(anyhow::Error::from) {
+        Ok(raw) => gunzip_archived(raw),
+        Err(err) => Err(err)
+    };
+    let raw_mail = match raw_mail {
+        Ok(raw) => raw,
+        Err(err) => return internal(err.context(format!("failed to read {}", path.display())))
+    };
+
+    let result = match state.app.parsers.load().parse_detailed(&raw_mail) {
+        Ok(parsed) => json!({
+            "ok": true,
+            "kind": parsed.kind.as_str(),
+            "hash": parsed.hash,
+            "status_code": parsed.status_code,
+            "action": parsed.action,
+            "sender": parsed.sender,
+            "recipient": parsed.recipient,
+            "description": parsed.description,
+            "reason": parsed.reason()
+        }),
+        Err(err) => json!({ "ok": false, "code": err.code(), "error": err.to_string() })
+    };
+    let failure = if spool_state == "failed" {
+        match state.app.spool.read_failure_note(&path).await {
+            Ok(note) => note,
+            Err(err) => return internal(err)
+        }
+    } else {
+        None
+    };
+    Json(json!({
+        "path": path.display().to_string(),
+        "bytes": raw_mail.len(),
+        "parse": result,
+        "failure": failure
+    }))
+    .into_response()
+}
+
+async fn requeue_failed(
+    State(state): State<ApiState>,
+    UrlPath((spool_state, file)): UrlPath<(String, String)>
+) -> Response {
+    let Some(file) = file.strip_suffix("/requeue").filter(|_| spool_state == "failed") else {
+        return error(StatusCode::NOT_FOUND, "expected POST /spool/failed/{file}/requeue");
+    };
+    let spool = &state.app.spool;
+    let requeued = async {
+        let Some(path) = find_spool_file(&spool.failed, file).await? else {
+            return Ok(None);
+        };
+        let target = spool.relocate(&path, &spool.failed, &spool.incoming).await?;
+        spool.rename(&path, &target).await.with_context(|| {
+            format!("failed to requeue {} -> {}", path.display(), target.display())
+        })?;
+        spool.remove_failure_note(&path).await?;
+        anyhow::Ok(Some(target))
+    };
+    match requeued.await {
+        Ok(Some(target)) => {
+            info!("failed spool file requeued: path={}", target.display());
+            Json(json!({ "requeued": target.display().to_string() })).into_response()
+        }
+        Ok(None) => error(StatusCode::NOT_FOUND, format!("no such failed file: {file}")),
+        Err(err) => internal(err)
+    }
+}
+
+async fn trigger_scan(State(state): State<ApiState>) -> Response {
+    state.app.triggers.incoming_scan.notify_one();
+    info!("incoming scan requested via admin api");
+    (StatusCode::ACCEPTED, Json(json!({ "triggered": "scan" }))).into_response()
+}
+
+async fn trigger_imap_poll(State(state): State<ApiState>) -> Response {
+    state.app.triggers.imap_poll.notify_waiters();
+    info!("imap poll requested via admin api");
+    (StatusCode::ACCEPTED, Json(json!({ "triggered": "imap_poll" }))).into_response()
+}
+
+async fn frame_audit(State(state): State<ApiState>) -> Response {
+    Json(frame_audit_json(&state.app)).into_response()
+}
+
+async fn toggle_frame_audit(
+    State(state): State<ApiState>,
+    UrlPath(action): UrlPath<String>
+) -> Response {
+    let enabled = match action.as_str() {
+        "enable" => true,
+        "disable" => false,
+        _ => return error(StatusCode::NOT_FOUND, format!("unknown frame audit action: {action}"))
+    };
+    state.app.frame_audit.set_enabled(enabled);
+    info!("frame audit toggled via admin api: enabled={}", enabled);
+    Json(frame_audit_json(&state.app)).into_response()
+}
+
+fn frame_audit_json(state: &AppState) -> serde_json::Value {
+    json!({
+        "enabled": state.frame_audit.is_enabled(),
+        "dir": state.frame_audit.dir().map(|dir| dir.display().to_string())
+    })
+}
+
+async fn debug_logging() -> Response {
+    Json(debug_logging_json()).into_response()
+}
+
+async fn toggle_debug_logging(UrlPath(action): UrlPath<String>) -> Response {
+    let enabled = match action.as_str() {
+        "enable" => true,
+        "disable" => false,
+        _ => return error(StatusCode::NOT_FOUND, format!("unknown debug logging action: {action}"))
+    };
+    info!("debug logging toggled via admin api: enabled={}", enabled);
+    apply_debug_logging(enabled);
+    Json(debug_logging_json()).into_response()
+}
+
+fn debug_logging_json() -> serde_json::Value {
+    json!({ "enabled": logging::debug_logging(), "filter": DEBUG_FILTER })
+}
+
+async fn diagnostics(State(state): State<ApiState>) -> Response {
+    match diagnostics_dump(&state.app).await {
+        Ok(dump) => Json(dump).into_response(),
+        Err(err) => internal(err)
+    }
+}
+
+async fn stats(State(state): State<ApiState>) -> Response {
+    match server_stats(&state.app).await {
+        Ok(server) => Json(json!({
+            "server": server,
+            "sources": state.app.sources.snapshot(state.app.clock.now())
+        }))
+        .into_response(),
+        Err(err) => internal(err)
+    }
+}
+
+async fn list_suppressions(State(state): State<ApiState>) -> Response {
+    let listed = async {
+        let total = state.app.db.suppression_count().await?;
+        let entries = state.app.db.list_suppressions(SUPPRESSION_LIST_LIMIT).await?;
+        anyhow::Ok((total, entries))
+    };
+    match listed.await {
+        Ok((total, entries)) => Json(json!({
+            "enabled": state.app.db.suppression_enabled(),
+            "total": total,
+            "suppressions": entries.iter().map(suppression_json).collect::<Vec<_>>()
+        }))
+        .into_response(),
+        Err(err) => internal(err)
+    }
+}
+
+async fn suppressed_domains(State(state): State<ApiState>) -> Response {
+    match state.app.db.suppressions_by_domain().await {
+        Ok(counts) => Json(domain_report(&counts, i64::from(state.min_count))).into_response(),
+        Err(err) => internal(err)
+    }
+}
+
+/// Per-domain totals and reason counts of `counts`, so that no reported
+/// number narrows down fewer than `min_count` recipients. Domains with fewer
+/// than `min_count` entries are only summed into `other`, and any count below
+/// `min_count` is reported as `null`. A domain with a withheld reason count
+/// also reports its `total` as `null`, or the count could be recovered by
+/// subtracting the others from it.
+fn domain_report(
+    counts: &[DomainSuppressions],
+    min_count: i64
+) -> serde_json::Value {
+    let mut domains: BTreeMap<&str, BTreeMap<&str, i64>> = BTreeMap::new();
+    for row in counts {
+        *domains.entry(&row.domain).or_default().entry(&row.reason).or_default() += row.count;
+    }
+    let reported = |count: i64| (count >= min_count).then_some(count);
+    let (mut other_domains, mut other_total) = (0, 0);
+    let mut listed = Vec::new();
+    for (domain, reasons) in domains {
+        let total: i64 = reasons.values().sum();
+        if total < min_count {
+            other_domains += 1;
+            other_total += total;
+            continue;
+        }
+        let withheld = reasons.values().any(|count| reported(*count).is_none());
+        let reasons: serde_json::Map<_, _> = reasons
+            .into_iter()
+            .map(|(reason, count)| (reason.to_string(), json!(reported(count))))
+            .collect();
+        let total = (!withheld).then_some(total);
+        listed.push(json!({ "domain": domain, "total": total, "reasons": reasons }));
+    }
+    json!({
+        "min_count": min_count,
+        "domains": listed,
+        "other": { "domains": other_domains, "total": reported(other_total) }
+    })
+}
+
+async fn suppression_audit(State(state): State<ApiState>) -> Response {
+    match state.app.db.list_suppression_audit(SUPPRESSION_LIST_LIMIT).await {
+        Ok(rows) => Json(json!({
+            "audit": rows
+                .iter()
+                .map(|row| json!({
+                    "recipient": row.recipient,
+                    "action": row.action,
+                    "actor": row.actor,
+                    "reason": row.reason,
+                    "status_code": row.status_code,
+                    "hash": row.hash,
+                    "suppressed_at_unix": row.suppressed_at_unix,
+                    "created_at_unix": row.created_at_unix
+                }))
+                .collect::<Vec<_>>()
+        }))
+        .into_response(),
+        Err(err) => internal(err)
+    }
+}
+
+async fn remove_suppression(
+    State(state): State<ApiState>,
+    UrlPath(recipient): UrlPath<String>,
+    headers: HeaderMap
+) -> Response {
+    let actor = request_actor(&headers);
+    let removed = state.app.db.remove_suppression(&recipient, &actor).await;
+    ended_suppression(removed, SuppressionChange::Removed, &recipient, &actor)
+}
+
+async fn expire_suppression(
+    State(state): State<ApiState>,
+    UrlPath(recipient): UrlPath<String>,
+    headers: HeaderMap
+) -> Response {
+    let actor = request_actor(&headers);
+    let expired = state.app.db.expire_suppression(&recipient, &actor).await;
+    ended_suppression(expired, SuppressionChange::Expired, &recipient, &actor)
+}
+
+fn ended_suppression(
+    ended: Result<Option<Suppression>>,
+    change: SuppressionChange,
+    recipient: &str,
+    actor: &str
+) -> Response {
+    match ended {
+        Ok(Some(entry)) => {
+            info!(
+                "suppression {} via admin api: recipient={}, actor={}, reason={}, status_code={}, suppressed_since_unix={}",
+                change.as_str(),
+                entry.recipient,
+                actor,
+                entry.reason,
+                entry.status_code,
+                entry.created_at_unix
+            );
+            Json(json!({ change.as_str(): suppression_json(&entry) })).into_response()
+        }
+        Ok(None) => error(StatusCode::NOT_FOUND, format!("not suppressed: {recipient}")),
+        Err(err) => internal(err)
+    }
+}
+
+/// The `X-Admin-Actor` of a request, or [`DEFAULT_ACTOR`].
+fn request_actor(headers: &HeaderMap) -> String {
+    headers
+        .get(ACTOR_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|actor| !actor.is_empty())
+        .unwrap_or(DEFAULT_ACTOR)
+        .chars()
+        .take(255)
+        .collect()
+}
+
+fn suppression_json(entry: &Suppression) -> serde_json::Value {
+    json!({
+        "recipient": entry.recipient,
+        "reason": entry.reason,
+        "status_code": entry.status_code,
+        "hash": entry.hash,
+        "created_at_unix": entry.created_at_unix,
+        "updated_at_unix": entry.updated_at_unix,
+        "expires_at_unix": entry.expires_at_unix
+    })
+}
+
+fn state_dir<'a>(
+    state: &'a AppState,
+    name: &str
+) -> Option<&'a Path> {
+    let spool = &state.spool;
+    match name {
+        "incoming" => Some(&spool.incoming),
+        "processing" => Some(&spool.processing),
+        "done" => Some(&spool.done),
+        "failed" => Some(&spool.failed),
+        _ => None
+    }
+}
+
+/// The spool file at `relative` under `dir`. Only files the spool lists can
+/// match, so `..` or absolute paths never escape it.
+async fn find_spool_file(
+    dir: &Path,
+    relative: &str
+) -> Result<Option<PathBuf>> {
+    Ok(archived_files(dir)
+        .await?
+        .into_iter()
+        .find(|path| path.strip_prefix(dir).is_ok_and(|rel| rel == Path::new(relative))))
+}
+
+fn constant_time_eq(
+    a: &[u8],
+    b: &[u8]
+) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn error(
+    status: StatusCode,
+    message: impl Into<String>
+) -> Response {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+fn internal(err: anyhow::Error) -> Response {
+    warn!("admin api request failed: error={:#}", err);
+    error(StatusCode::INTERNAL_SERVER_ERROR, format!("{err:#}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use reqwest::{Client, StatusCode};
+    use serde_json::{Value, json};
+    use uuid::Uuid;
+
+    use super::{ApiState, domain_report, router};
+    use crate::app::AppState;
+    use crate::core::database::DomainSuppressions;
+
+    const TOKEN: &str = "s3cret";
+
+    #[tokio::test]
+    async fn serves_spool_actions_behind_the_token() {
+        let root = std::env::temp_dir().join(format!("bouncer-admin-api-{}", Uuid::now_v7()));
+        let state = AppState::for_tests(&root).await;
+        let report = include_bytes!("../../../../tests/bounces/notification.eml");
+        tokio::fs::write(state.spool.failed.join("a.eml"), report).await.unwrap();
+        let addr = serve(state.clone()).await;
+        let client = Client::new();
+        let url = |path: &str| format!("http://{addr}{path}");
+
+        let denied = client.get(url("/spool")).bearer_auth("wrong").send().await.unwrap();
+        assert_eq!(denied.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(client.get(url("/spool")).send().await.unwrap().status(), 401);
+
+        let counts = client
+            .get(url("/spool"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let counts: Value = serde_json::from_slice(&counts).unwrap();
+        assert_eq!(counts["failed"], 1);
+        assert_eq!(counts["incoming"], 0);
+
+        let parsed = client
+            .get(url("/spool/failed/a.eml"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let parsed: Value = serde_json::from_slice(&parsed).unwrap();
+        assert_eq!(parsed["parse"]["ok"], true, "{parsed}");
+        assert_eq!(parsed["bytes"], report.len());
+        let missing = client
+            .get(url("/spool/failed/%2E%2E%2Fdone%2Fa.eml"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let requeued = client
+            .post(url("/spool/failed/a.eml/requeue"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(requeued.status(), StatusCode::OK);
+        assert!(state.spool.incoming.join("a.eml").exists());
+        assert!(!state.spool.failed.join("a.eml").exists());
+
+        let scan = client.post(url("/scan")).bearer_auth(TOKEN).send().await.unwrap();
+        assert_eq!(scan.status(), StatusCode::ACCEPTED);
+        state.triggers.incoming_scan.notified().await;
+
+        let audit =
+            client.post(url("/frame-audit/enable")).bearer_auth(TOKEN).send().await.unwrap();
+        let audit: Value = serde_json::from_slice(&audit.bytes().await.unwrap()).unwrap();
+        assert_eq!((&audit["enabled"], &audit["dir"]), (&Value::Bool(true), &Value::Null));
+        assert!(state.frame_audit.is_enabled());
+        let unknown =
+            client.post(url("/frame-audit/pause")).bearer_auth(TOKEN).send().await.unwrap();
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+
+        let dump = client.get(url("/diagnostics")).bearer_auth(TOKEN).send().await.unwrap();
+        let dump: Value = serde_json::from_slice(&dump.bytes().await.unwrap()).unwrap();
+        assert_eq!(dump["spool"]["incoming"], 1);
+        assert_eq!(dump["process"]["frame_audit"], true);
+        // The test process has no reloadable subscriber, so nothing switches.
+        let debug =
+            client.post(url("/debug-logging/enable")).bearer_auth(TOKEN).send().await.unwrap();
+        let debug: Value = serde_json::from_slice(&debug.bytes().await.unwrap()).unwrap();
+        assert_eq!(debug["enabled"], false);
+
+        state.sources.record_event("mail-01", state.clock.now());
+        let stats = client.get(url("/stats")).bearer_auth(TOKEN).send().await.unwrap();
+        let stats: Value = serde_json::from_slice(&stats.bytes().await.unwrap()).unwrap();
+        assert_eq!(stats["server"]["spool_incoming"], 1);
+        assert_eq!(stats["sources"][0]["source"], "mail-01");
+        assert_eq!(stats["sources"][0]["events"], 1);
+
+        let pool =
+            sqlx::SqlitePool::connect(&format!("sqlite:{}", root.join("bouncer.sqlite").display()))
+                .await
+                .unwrap();
+        sqlx::query(
+            "INSERT INTO suppressions (recipient, reason, status_code, created_at, updated_at) VALUES ('user@example.com', 'complaint', '5.7.1', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool.close().await;
+        let domains =
+            client.get(url("/suppressions/domains")).bearer_auth(TOKEN).send().await.unwrap();
+        let domains: Value = serde_json::from_slice(&domains.bytes().await.unwrap()).unwrap();
+        assert_eq!(domains["other"], json!({ "domains": 1, "total": null }));
+        let listed = client.get(url("/suppressions")).bearer_auth(TOKEN).send().await.unwrap();
+        let listed: Value = serde_json::from_slice(&listed.bytes().await.unwrap()).unwrap();
+        assert_eq!(listed["total"], 1);
+        assert_eq!(listed["suppressions"][0]["reason"], "complaint");
+        let removed = client
+            .delete(url("/suppressions/User@Example.com"))
+            .bearer_auth(TOKEN)
+            .header("X-Admin-Actor", "jane")
+            .send()
+            .await
+            .unwrap();
+        let removed: Value = serde_json::from_slice(&removed.bytes().await.unwrap()).unwrap();
+        assert_eq!(removed["removed"]["recipient"], "user@example.com");
+        let gone = client
+            .delete(url("/suppressions/user@example.com"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(gone.status(), StatusCode::NOT_FOUND);
+        let expired = client
+            .post(url("/suppressions/user@example.com/expire"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(expired.status(), StatusCode::NOT_FOUND);
+        let audit = client.get(url("/suppressions/audit")).bearer_auth(TOKEN).send().await.unwrap();
+        let audit: Value = serde_json::from_slice(&audit.bytes().await.unwrap()).unwrap();
+        assert_eq!(
+            (&audit["audit"][0]["action"], &audit["audit"][0]["actor"]),
+            (&json!("removed"), &json!("jane"))
+        );
+
+        state.shutdown.cancel();
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+
+    #[test]
+    fn withholds_small_domain_counts() {
+        let row = |domain: &str, reason: &str, count| DomainSuppressions {
+            domain: domain.to_string(),
+            reason: reason.to_string(),
+            count
+        };
+        let report = domain_report(
+            &[
+                row("example.com", "complaint", 2),
+                row("example.com", "hard_bounce", 40),
+                row("example.de", "complaint", 12),
+                row("example.de", "hard_bounce", 30),
+                row("example.net", "hard_bounce", 3),
+                row("example.org", "hard_bounce", 9)
+            ],
+            10
+        );
+        // The total would give away the withheld complaint count.
+        assert_eq!(
+            report["domains"],
+            json!([
+                {
+                    "domain": "example.com",
+                    "total": null,
+                    "reasons": { "complaint": null, "hard_bounce": 40 }
+                },
+                {
+                    "domain": "example.de",
+                    "total": 42,
+                    "reasons": { "complaint": 12, "hard_bounce": 30 }
+                }
+            ])
+        );
+        assert_eq!(report["other"], json!({ "domains": 2, "total": 12 }));
+    }
+
+    async fn serve(state: AppState) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = router(ApiState { app: state, token: TOKEN.to_string(), min_count: 10 });
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        addr
+    }
+}
@@ -0,0 +1,442 @@
+//! Bounce-rate alerts (`alerts` in the config).
+//!
+//! Every outcome the database applies, from any ingest path, is counted per
+//! rule and recipient domain in a sliding window of [`WINDOW_BUCKETS`]
+//! buckets. When a domain's share of outcomes matching a rule's
+//! `status_codes` reaches `threshold_pct`, an [`Alert`] goes to every sink
+//! on its own task, so a slow webhook never holds up a database write.
+
+use std::collections::{HashMap, VecDeque};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use bouncer_helpers::clock::{SharedClock, system_clock};
+use bouncer_helpers::coded_warn;
+use bouncer_helpers::error_code::ErrorCode;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, info};
+
+use super::parser::{ParsedBounce, ReportKind};
+use crate::config::{AlertRule, AlertSink, AlertsConfig};
+
+/// Buckets a rule's window is split into; the window slides one bucket at a
+/// time.
+const WINDOW_BUCKETS: u32 = 30;
+
+/// Upper bound on tracked rule/domain windows; beyond it idle windows are
+/// dropped first and new domains are not counted until one frees up.
+const MAX_TRACKED: usize = 10_000;
+
+/// One fired rule, as sent to the sinks.
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub rule: String,
+    pub domain: String,
+    pub status_codes: Vec<String>,
+    /// Outcomes within the window whose status code matched the rule.
+    pub matched: u64,
+    /// Every outcome for the domain within the window.
+    pub total: u64,
+    pub rate_pct: f64,
+    pub threshold_pct: f64,
+    pub window_secs: u64,
+    pub fired_at_unix: u64
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    index: u64,
+    total: u64,
+    matched: u64
+}
+
+#[derive(Debug, Default)]
+struct DomainWindow {
+    buckets: VecDeque<Bucket>,
+    last_fired: Option<Instant>
+}
+
+impl DomainWindow {
+    fn record(
+        &mut self,
+        index: u64,
+        matched: bool
+    ) {
+        if self.buckets.back().is_none_or(|bucket| bucket.index != index) {
+            self.buckets.push_back(Bucket { index, ..Bucket::default() });
+        }
+        let bucket = self.buckets.back_mut().expect("bucket pushed above");
+        bucket.total += 1;
+        bucket.matched += u64::from(matched);
+    }
+
+    /// Drops buckets that left the window ending at bucket `index`.
+    fn expire(
+        &mut self,
+        index: u64
+    ) {
+        let oldest = index.saturating_sub(u64::from(WINDOW_BUCKETS) - 1);
+        while self.buckets.front().is_some_and(|bucket| bucket.index < oldest) {
+            self.buckets.pop_front();
+        }
+    }
+
+    fn counts(&self) -> (u64, u64) {
+        self.buckets.iter().fold((0, 0), |(matched, total), bucket| {
+            (matched + bucket.matched, total + bucket.total)
+        })
+    }
+}
+
+/// Evaluates the `alerts` rules on applied outcomes and hands fired alerts
+/// to the sinks; a default instance has no rules and does nothing.
+#[derive(Debug)]
+pub struct BounceAlerts {
+    rules: Vec<AlertRule>,
+    sinks: Arc<[AlertSink]>,
+    http: reqwest::Client,
+    clock: SharedClock,
+    started: Instant,
+    windows: Mutex<HashMap<(usize, String), DomainWindow>>
+}
+
+impl Default for BounceAlerts {
+    fn default() -> Self {
+        Self::new(AlertsConfig::default(), system_clock())
+            .expect("building a client without sinks does not fail")
+    }
+}
+
+impl BounceAlerts {
+    pub fn new(
+        config: AlertsConfig,
+        clock: SharedClock
+    ) -> Result<Self> {
+        let http = reqwest::Client::builder().build().context("failed to build alert client")?;
+        if config.enabled() {
+            info!(
+                "bounce alerts enabled: rules={}, sinks={}",
+                config.rules.iter().map(|rule| rule.name.as_str()).collect::<Vec<_>>().join(","),
+                config.sinks.iter().map(AlertSink::kind).collect::<Vec<_>>().join(",")
+            );
+        }
+        Ok(Self {
+            rules: config.rules,
+            sinks: config.sinks.into(),
+            http,
+            started: clock.now(),
+            clock,
+            windows: Mutex::new(HashMap::new())
+        })
+    }
+
+    /// Counts one applied outcome and delivers the alerts it fires.
+    /// Auto-replies and outcomes without a recipient are not counted.
+    pub fn observe(
+        &self,
+        parsed: &ParsedBounce
+    ) {
+        for alert in self.evaluate(parsed) {
+            for sink in self.sinks.iter() {
+                tokio::spawn(deliver(self.http.clone(), sink.clone(), alert.clone()));
+            }
+        }
+    }
+
+    fn evaluate(
+        &self,
+        parsed: &ParsedBounce
+    ) -> Vec<Alert> {
+        if self.rules.is_empty() || parsed.kind == ReportKind::Autoreply {
+            return Vec::new();
+        }
+        let Some(domain) = parsed.recipient.as_deref().and_then(recipient_domain) else {
+            return Vec::new();
+        };
+
+        let now = self.clock.now();
+        let elapsed = now.saturating_duration_since(self.started);
+        let mut fired = Vec::new();
+        let mut windows = self.lock();
+        for (rule_index, rule) in self.rules.iter().enumerate() {
+            if !rule.matches_domain(&domain) {
+                continue;
+            }
+            let key = (rule_index, domain.clone());
+            if !windows.contains_key(&key) && !make_room(&mut windows, &self.rules, elapsed) {
+                debug!(
+                    "alert windows full, outcome not counted: rule={}, domain={domain}",
+                    rule.name
+                );
+                continue;
+            }
+
+            let index = bucket_index(rule, elapsed);
+            let window = windows.entry(key).or_default();
+            window.expire(index);
+            window.record(index, rule.matches_status_code(&parsed.status_code));
+
+            let (matched, total) = window.counts();
+            let rate_pct = matched as f64 * 100.0 / total as f64;
+            let cooldown = rule.cooldown.unwrap_or(rule.window);
+            let cooling = window
+                .last_fired
+                .is_some_and(|fired| now.saturating_duration_since(fired) < cooldown);
+            if total < rule.min_messages || rate_pct < rule.threshold_pct || cooling {
+                continue;
+            }
+
+            window.last_fired = Some(now);
+            fired.push(Alert {
+                rule: rule.name.clone(),
+                domain: domain.clone(),
+                status_codes: rule.status_codes.clone(),
+                matched,
+                total,
+                rate_pct,
+                threshold_pct: rule.threshold_pct,
+                window_secs: rule.window.as_secs(),
+                fired_at_unix: self.clock.unix_secs()
+            });
+        }
+        fired
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(usize, String), DomainWindow>> {
+        self.windows.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn bucket_width(rule: &AlertRule) -> Duration {
+    (rule.window / WINDOW_BUCKETS).max(Duration::from_secs(1))
+}
+
+fn bucket_index(
+    rule: &AlertRule,
+    elapsed: Duration
+) -> u64 {
+    (elapsed.as_millis() / bucket_width(rule).as_millis()) as u64
+}
+
+/// Drops windows with nothing left in them once [`MAX_TRACKED`] is reached;
+/// false when every tracked window is still live.
+fn make_room(
+    windows: &mut HashMap<(usize, String), DomainWindow>,
+    rules: &[AlertRule],
+    elapsed: Duration
+) -> bool {
+    if windows.len() < MAX_TRACKED {
+        return true;
+    }
+    windows.retain(|(rule_index, _), window| {
+        window.expire(bucket_index(&rules[*rule_index], elapsed));
+        !window.buckets.is_empty()
+    });
+    windows.len() < MAX_TRACKED
+}
+
+/// Lowercased domain of a recipient address such as `<u@Example.com>`.
+fn recipient_domain(recipient: &str) -> Option<String> {
+    let (_, domain) = recipient.trim().trim_end_matches('>').rsplit_once('@')?;
+    let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    if domain.is_empty() { None } else { Some(domain) }
+}
+
+async fn deliver(
+    http: reqwest::Client,
+    sink: AlertSink,
+    alert: Alert
+) {
+    let result = match &sink {
+        AlertSink::Log => {
+            coded_warn!(
+                ErrorCode::BounceRateAlert,
+                "bounce rate alert: rule={}, domain={}, rate_pct={:.1}, threshold_pct={}, matched={}, total={}, window_secs={}, status_codes={}",
+                alert.rule,
+                alert.domain,
+                alert.rate_pct,
+                alert.threshold_pct,
+                alert.matched,
+                alert.total,
+                alert.window_secs,
+                alert.status_codes.join(",")
+            );
+            Ok(())
+        }
+        AlertSink::Webhook { url, headers, timeout_secs } => {
+            post_webhook(&http, url, headers, Duration::from_secs(*timeout_secs), &alert).await
+        }
+        AlertSink::Exec { command, timeout_secs } => {
+            run_command(command, Duration::from_secs(*timeout_secs), &alert).await
+        }
+    };
+    if let Err(err) = result {
+        coded_warn!(
+            ErrorCode::AlertSinkFailed,
+            "alert not delivered: sink={}, rule={}, domain={}, error={err:#}",
+            sink.kind(),
+            alert.rule,
+            alert.domain
+        );
+    }
+}
+
+async fn post_webhook(
+    http: &reqwest::Client,
+    url: &str,
+    headers: &std::collections::BTreeMap<String, String>,
+    timeout: Duration,
+    alert: &Alert
+) -> Result<()> {
+    let body = serde_json::to_vec(alert).context("failed to encode alert")?;
+    let mut request = http
+        .post(url)
+        .timeout(timeout)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    let response = request.send().await.context("webhook request failed")?;
+    if !response.status().is_success() {
+        bail!("webhook answered {}", response.status());
+    }
+    Ok(())
+}
+
+async fn run_command(
+    command: &[String],
+    timeout: Duration,
+    alert: &Alert
+) -> Result<()> {
+    let body = serde_json::to_vec(alert).context("failed to encode alert")?;
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .env("BOUNCER_ALERT_RULE", &alert.rule)
+        .env("BOUNCER_ALERT_DOMAIN", &alert.domain)
+        .env("BOUNCER_ALERT_RATE_PCT", format!("{:.1}", alert.rate_pct))
+        .env("BOUNCER_ALERT_MATCHED", alert.matched.to_string())
+        .env("BOUNCER_ALERT_TOTAL", alert.total.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("failed to start {}", command[0]))?;
+
+    let run = async {
+        if let Some(mut stdin) = child.stdin.take() {
+            // A command that ignores stdin may close it early.
+            let _ = stdin.write_all(&body).await;
+        }
+        child.wait().await
+    };
+    let status = tokio::time::timeout(timeout, run)
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {}s", timeout.as_secs()))?
+        .context("failed to wait for command")?;
+    if !status.success() {
+        bail!("command exited with {status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bouncer_helpers::clock::{Clock, ManualClock};
+
+    use super::BounceAlerts;
+    use crate::config::{AlertRule, AlertSink, AlertsConfig};
+    use crate::core::parser::{ParsedBounce, ReportKind};
+
+    fn outcome(
+        recipient: &str,
+        status_code: &str
+    ) -> ParsedBounce {
+        ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: "abc".to_string(),
+            status_code: status_code.to_string(),
+            action: None,
+            sender: None,
+            recipient: Some(recipient.to_string()),
+            description: None,
+            scan_labels: Vec::new(),
+            tenant: None
+        }
+    }
+
+    #[test]
+    fn fires_per_domain_once_the_rate_crosses_the_threshold() {
+        let clock = Arc::new(ManualClock::at_unix(1_700_000_000));
+        let rule = AlertRule {
+            name: "hard".to_string(),
+            domains: vec!["*.example".to_string()],
+            status_codes: vec!["5.*".to_string()],
+            threshold_pct: 50.0,
+            window: Duration::from_secs(600),
+            min_messages: 4,
+            cooldown: None
+        };
+        let alerts = BounceAlerts::new(
+            AlertsConfig { rules: vec![rule], sinks: Vec::new() },
+            clock.clone() as Arc<dyn Clock>
+        )
+        .unwrap();
+
+        assert!(alerts.evaluate(&outcome("a@d.example", "2.0.0")).is_empty());
+        assert!(alerts.evaluate(&outcome("b@d.example", "5.1.1")).is_empty());
+        assert!(alerts.evaluate(&outcome("c@other.test", "5.1.1")).is_empty());
+        assert!(alerts.evaluate(&outcome("c@e.example", "5.1.1")).is_empty());
+        assert!(alerts.evaluate(&outcome("d@d.example", "4.2.2")).is_empty());
+
+        let fired = alerts.evaluate(&outcome("<e@D.example>", "5.7.1"));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(
+            (fired[0].domain.as_str(), fired[0].matched, fired[0].total),
+            ("d.example", 2, 4)
+        );
+        assert_eq!(fired[0].rate_pct, 50.0);
+
+        // Cooling down for one window, then the old outcomes have expired.
+        assert!(alerts.evaluate(&outcome("f@d.example", "5.1.1")).is_empty());
+        clock.advance(Duration::from_secs(620));
+        for recipient in ["g@d.example", "h@d.example", "i@d.example"] {
+            assert!(alerts.evaluate(&outcome(recipient, "5.1.1")).is_empty());
+        }
+        let fired = alerts.evaluate(&outcome("j@d.example", "5.1.1"));
+        assert_eq!((fired.len(), fired[0].matched, fired[0].total), (1, 4, 4));
+    }
+
+    #[test]
+    fn reads_rules_and_tagged_sinks() {
+        let config: AlertsConfig = serde_json::from_str(
+            r#"{
+                "rules": [{"name": "gmail-hard", "domains": ["gmail.com"], "threshold_pct": 10, "window": "15m"}],
+                "sinks": [{"kind": "webhook", "url": "https://hooks.example/bounce"}, {"kind": "exec", "command": ["notify"]}]
+            }"#
+        )
+        .unwrap();
+        let rule = &config.rules[0];
+        assert_eq!(
+            (rule.status_codes.as_slice(), rule.min_messages),
+            (["5.*".to_string()].as_slice(), 20)
+        );
+        assert_eq!(rule.window, Duration::from_secs(900));
+        assert!(matches!(
+            &config.sinks[0],
+            AlertSink::Webhook { url, timeout_secs: 10, .. } if url == "https://hooks.example/bounce"
+        ));
+        assert_eq!(config.sinks[1].kind(), "exec");
+        assert!(
+            serde_json::from_str::<AlertsConfig>(r#"{"sinks": [{"kind": "webhook", "uri": "x"}]}"#)
+                .is_err()
+        );
+    }
+}
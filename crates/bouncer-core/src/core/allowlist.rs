@@ -0,0 +1,115 @@
+//! CIDR allowlist checked against peers of the ingest listener
+//! (`listen_allow`).
+
+use std::net::IpAddr;
+
+use anyhow::{Context, Result, bail};
+
+/// One `address/prefix` range; a bare address is a single host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IpCidr {
+    network: IpAddr,
+    prefix: u8
+}
+
+impl IpCidr {
+    pub(crate) fn parse(value: &str) -> Result<Self> {
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value, None)
+        };
+        let network: IpAddr =
+            address.trim().parse().with_context(|| format!("invalid address in `{value}`"))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse::<u8>()
+                .with_context(|| format!("invalid prefix in `{value}`"))?,
+            None => max
+        };
+        if prefix > max {
+            bail!("prefix /{prefix} out of range in `{value}`");
+        }
+        Ok(Self { network, prefix })
+    }
+
+    pub(crate) fn contains(
+        &self,
+        ip: IpAddr
+    ) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false
+        }
+    }
+}
+
+/// Peers allowed to connect; an empty list allows every peer.
+#[derive(Debug, Clone, Default)]
+pub struct PeerAllowlist {
+    ranges: Vec<IpCidr>
+}
+
+impl PeerAllowlist {
+    pub fn parse(entries: &[String]) -> Result<Self> {
+        let ranges = entries
+            .iter()
+            .map(|entry| IpCidr::parse(entry))
+            .collect::<Result<Vec<_>>>()
+            .context("invalid `listen_allow` entry")?;
+        Ok(Self { ranges })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// IPv4-mapped IPv6 peers (dual-stack listeners) match IPv4 ranges.
+    pub fn allows(
+        &self,
+        ip: IpAddr
+    ) -> bool {
+        self.is_empty() || self.ranges.iter().any(|range| range.contains(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PeerAllowlist;
+
+    fn allowlist(entries: &[&str]) -> PeerAllowlist {
+        let entries: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+        PeerAllowlist::parse(&entries).unwrap()
+    }
+
+    #[test]
+    fn matches_ranges_and_hosts() {
+        let allow = allowlist(&["10.1.0.0/16", "192.0.2.7", "2001:db8::/32"]);
+
+        assert!(allow.allows("10.1.200.3".parse().unwrap()));
+        assert!(!allow.allows("10.2.0.1".parse().unwrap()));
+        assert!(allow.allows("192.0.2.7".parse().unwrap()));
+        assert!(!allow.allows("192.0.2.8".parse().unwrap()));
+        assert!(allow.allows("2001:db8:1::5".parse().unwrap()));
+        assert!(allow.allows("::ffff:10.1.0.9".parse().unwrap()));
+        assert!(!allow.allows("::1".parse().unwrap()));
+
+        assert!(allowlist(&[]).allows("203.0.113.1".parse().unwrap()));
+        assert!(allowlist(&["0.0.0.0/0"]).allows("203.0.113.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        for entry in ["10.0.0.0/33", "::/129", "mx.example.com", "10.0.0.0/x"] {
+            assert!(PeerAllowlist::parse(&[entry.to_string()]).is_err(), "{entry}");
+        }
+    }
+}
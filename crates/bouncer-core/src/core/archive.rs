@@ -0,0 +1,225 @@
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use tokio::time::interval;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use super::database::Database;
+use super::s3::S3Client;
+use crate::config::{ArchiveConfig, ArchiveDestination};
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Rows expired per query during a retention sweep.
+const SWEEP_BATCH: u32 = 500;
+
+/// One stored copy, as indexed in `bounce_archive`.
+#[derive(Debug)]
+pub struct ArchivedCopy {
+    pub storage: &'static str,
+    pub content_encoding: Option<&'static str>,
+    /// Stored size, after compression.
+    pub size_bytes: u64,
+    /// The copy itself, for the database destination.
+    pub body: Option<Vec<u8>>,
+    /// Bucket key, for the s3 destination.
+    pub object_key: Option<String>
+}
+
+/// Keeps copies of applied bounce mails (see `archive` in the config); a
+/// default instance is disabled.
+#[derive(Default)]
+pub struct BounceArchive {
+    config: ArchiveConfig,
+    /// Present for the s3 destination.
+    s3: Option<S3Client>
+}
+
+impl BounceArchive {
+    pub fn new(config: ArchiveConfig) -> Result<Self> {
+        let s3 = match (&config.destination, &config.s3) {
+            (ArchiveDestination::S3, Some(s3)) => Some(S3Client::new(s3)?),
+            _ => None
+        };
+        Ok(Self { config, s3 })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled()
+    }
+
+    /// Stores `raw_mail` of the spooled bounce with `idempotency_key`.
+    pub async fn store(
+        &self,
+        db: &Database,
+        hash: &str,
+        idempotency_key: &str,
+        raw_mail: &[u8]
+    ) -> Result<()> {
+        let (body, content_encoding) = self.encode(raw_mail)?;
+        let size_bytes = body.len() as u64;
+
+        let copy = match &self.s3 {
+            Some(s3) => {
+                let key = self.object_key(hash, idempotency_key);
+                s3.put_object(&key, body, "message/rfc822", content_encoding).await?;
+                ArchivedCopy {
+                    storage: "s3",
+                    content_encoding,
+                    size_bytes,
+                    body: None,
+                    object_key: Some(key)
+                }
+            }
+            None => ArchivedCopy {
+                storage: "database",
+                content_encoding,
+                size_bytes,
+                body: Some(body),
+                object_key: None
+            }
+        };
+        db.insert_archive(idempotency_key, hash, &copy).await
+    }
+
+    /// The stored form of `raw_mail` and its `Content-Encoding`.
+    fn encode(
+        &self,
+        raw_mail: &[u8]
+    ) -> Result<(Vec<u8>, Option<&'static str>)> {
+        if !self.config.compress {
+            return Ok((raw_mail.to_vec(), None));
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(raw_mail).context("failed to compress archived bounce")?;
+        Ok((encoder.finish().context("failed to compress archived bounce")?, Some("gzip")))
+    }
+
+    /// `<prefix><hash>/<idempotency_key>.eml[.gz]`.
+    fn object_key(
+        &self,
+        hash: &str,
+        idempotency_key: &str
+    ) -> String {
+        let prefix = self.config.s3.as_ref().map_or("", |s3| s3.prefix.as_str());
+        let extension = if self.config.compress { "eml.gz" } else { "eml" };
+        format!("{prefix}{hash}/{idempotency_key}.{extension}")
+    }
+
+    /// Deletes copies older than `retention`, objects before their rows, so
+    /// a failed delete is retried on the next sweep.
+    async fn sweep(
+        &self,
+        db: &Database,
+        retention: Duration
+    ) -> Result<u64> {
+        let mut removed = 0;
+        loop {
+            let expired = db.expired_archives(retention.as_secs(), SWEEP_BATCH).await?;
+            if expired.is_empty() {
+                return Ok(removed);
+            }
+            let batch_len = expired.len();
+            let mut ids = Vec::with_capacity(batch_len);
+            for (id, object_key) in expired {
+                match (&self.s3, object_key) {
+                    (Some(s3), Some(key)) => {
+                        if let Err(err) = s3.delete_object(&key).await {
+                            warn!(
+                                "archive retention failed to delete object: key={key}, error={err:#}"
+                            );
+                            continue;
+                        }
+                    }
+                    // The destination moved off s3: the row is dropped, the object left in place.
+                    (None, Some(key)) => {
+                        warn!("archive retention dropped s3 row without deleting object: key={key}")
+                    }
+                    (_, None) => {}
+                }
+                ids.push(id);
+            }
+            removed += db.delete_archives(&ids).await?;
+            if ids.len() < batch_len || batch_len < SWEEP_BATCH as usize {
+                return Ok(removed);
+            }
+        }
+    }
+}
+
+/// Applies `archive.retention`; returns right away when it is unset.
+pub async fn run_archive_retention(
+    db: Arc<Database>,
+    archive: Arc<BounceArchive>,
+    shutdown: CancellationToken
+) {
+    let Some(retention) = archive.config.retention else {
+        return;
+    };
+    info!("archive retention enabled: max_age={}", humantime::format_duration(retention));
+    let mut ticker = interval(SWEEP_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => match archive.sweep(&db, retention).await {
+                Ok(0) => {}
+                Ok(removed) => info!("archive retention removed copies: rows={}", removed),
+                Err(err) => warn!("archive retention sweep failed: error={err:#}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use flate2::read::GzDecoder;
+    use uuid::Uuid;
+
+    use super::BounceArchive;
+    use crate::config::{ArchiveConfig, ArchiveDestination, MigrateMode, SuppressionConfig};
+    use crate::core::database::Database;
+    use crate::core::faults::Faults;
+
+    #[tokio::test]
+    async fn stores_compressed_copies_and_sweeps_expired_ones() {
+        let path = std::env::temp_dir().join(format!("bouncer-archive-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+        let archive = BounceArchive::new(ArchiveConfig {
+            destination: ArchiveDestination::Database,
+            ..ArchiveConfig::default()
+        })
+        .unwrap();
+
+        let mail = b"Subject: Undelivered Mail Returned to Sender\r\n\r\nbody\r\n";
+        archive.store(&db, "a1b2", "k1", mail).await.unwrap();
+        archive.store(&db, "a1b2", "k1", mail).await.unwrap();
+
+        let (gz, encoding) = archive.encode(mail).unwrap();
+        let mut restored = Vec::new();
+        GzDecoder::new(gz.as_slice()).read_to_end(&mut restored).unwrap();
+        assert_eq!((restored.as_slice(), encoding), (mail.as_slice(), Some("gzip")));
+
+        assert!(db.expired_archives(3600, 10).await.unwrap().is_empty());
+        // `archived_at` has second resolution.
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(db.expired_archives(0, 10).await.unwrap().len(), 1);
+        let removed = archive.sweep(&db, Duration::ZERO).await.unwrap();
+        assert_eq!(removed, 1);
+        let _ = std::fs::remove_file(path);
+    }
+}
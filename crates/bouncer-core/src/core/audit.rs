@@ -0,0 +1,213 @@
+//! Provenance of processed bounces: the ingest path that delivered a mail,
+//! its spool file or IMAP UID, when it arrived and was processed, and the
+//! parser scans that found it.
+//!
+//! Every processed message gets a `mail_bounce_audit` row. Spooled mail is
+//! also moved to `done/` with an `X-Bouncer-Audit-*` header block in front,
+//! which [`strip_audit_headers`] removes again before the mail is hashed or
+//! re-audited, so a replayed `done/` file keeps its idempotency key.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::debug;
+
+use super::database::UpsertBounceOutcome;
+
+/// Prefix of every header [`BounceAudit::headers`] writes.
+const AUDIT_HEADER_PREFIX: &str = "X-Bouncer-Audit-";
+
+/// Upper bound on remembered spool origins; beyond it new spool files are
+/// audited as `spool_scan`.
+const MAX_PENDING: usize = 65_536;
+
+/// How a mail reached the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestPath {
+    /// A framed TCP client (`bouncer-client`, `bounce-delivery --server`).
+    Tcp,
+    /// The embedded SMTP listener.
+    Smtp,
+    /// The IMAP poll loop.
+    Imap,
+    /// A file found in `incoming/` by the watcher or the periodic scan,
+    /// e.g. from the `bounce-delivery` pipe or requeued after a restart.
+    SpoolScan
+}
+
+impl IngestPath {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Tcp => "tcp",
+            Self::Smtp => "smtp",
+            Self::Imap => "imap",
+            Self::SpoolScan => "spool_scan"
+        }
+    }
+}
+
+/// Where a spool file came from, recorded when it is enqueued.
+#[derive(Debug, Clone)]
+pub struct SpoolOrigin {
+    pub path: IngestPath,
+    pub source: Option<String>,
+    pub received_at: SystemTime
+}
+
+/// Carries the origin of an enqueued mail to the worker that processes its
+/// spool file, keyed by file name like [`super::SpoolTraces`]. Files without
+/// an entry (dropped into `incoming/` directly, or pending across a restart)
+/// are audited as [`IngestPath::SpoolScan`].
+#[derive(Debug, Default)]
+pub struct SpoolOrigins {
+    pending: Mutex<HashMap<OsString, SpoolOrigin>>
+}
+
+impl SpoolOrigins {
+    pub fn record(
+        &self,
+        spool_path: &Path,
+        origin: SpoolOrigin
+    ) {
+        let Some(file_name) = spool_path.file_name() else {
+            return;
+        };
+
+        let mut pending = self.lock();
+        if pending.len() >= MAX_PENDING {
+            debug!(
+                "spool origins full, file will be audited as spool_scan: path={}",
+                spool_path.display()
+            );
+            return;
+        }
+        pending.insert(file_name.to_owned(), origin);
+    }
+
+    pub fn get(
+        &self,
+        spool_path: &Path
+    ) -> Option<SpoolOrigin> {
+        self.lock().get(spool_path.file_name()?).cloned()
+    }
+
+    /// Drops the entry once the file reached `done/` or `failed/`.
+    pub fn forget(
+        &self,
+        spool_path: &Path
+    ) {
+        if let Some(file_name) = spool_path.file_name() {
+            self.lock().remove(file_name);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<OsString, SpoolOrigin>> {
+        self.pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// One `mail_bounce_audit` row.
+#[derive(Debug, Clone)]
+pub struct BounceAudit {
+    pub hash: String,
+    pub ingest_path: IngestPath,
+    pub source: Option<String>,
+    /// Spool file name, or `<account>/<mailbox>/<uid>` for IMAP.
+    pub origin: String,
+    pub received_at: SystemTime,
+    pub processed_at: SystemTime,
+    /// [`crate::ParsedBounce::scan_labels`].
+    pub scan_labels: Vec<String>,
+    /// `applied`, `missing_message`, `duplicate` or `replayed`.
+    pub outcome: &'static str
+}
+
+impl BounceAudit {
+    /// Header block prepended to the mail in `done/`, ending in CRLF.
+    pub fn headers(&self) -> String {
+        let mut headers = String::new();
+        let mut push = |name: &str, value: &str| {
+            let value: String =
+                value.chars().map(|ch| if ch.is_control() { ' ' } else { ch }).collect();
+            headers.push_str(&format!("{AUDIT_HEADER_PREFIX}{name}: {value}\r\n"));
+        };
+        push("Path", self.ingest_path.as_str());
+        if let Some(source) = &self.source {
+            push("Source", source);
+        }
+        push("Origin", &self.origin);
+        push("Received", &humantime::format_rfc3339_seconds(self.received_at).to_string());
+        push("Processed", &humantime::format_rfc3339_seconds(self.processed_at).to_string());
+        push("Outcome", self.outcome);
+        if !self.scan_labels.is_empty() {
+            push("Scan", &self.scan_labels.join(", "));
+        }
+        headers
+    }
+}
+
+/// Audit `outcome` of a database write; `None` is a replayed spool file.
+pub fn audit_outcome(applied: Option<UpsertBounceOutcome>) -> &'static str {
+    match applied {
+        Some(UpsertBounceOutcome::UpdatedLocalMessage) => "applied",
+        Some(UpsertBounceOutcome::MissingLocalMessage) => "missing_message",
+        Some(UpsertBounceOutcome::Duplicate) => "duplicate",
+        None => "replayed"
+    }
+}
+
+pub fn unix_secs(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default()
+}
+
+/// `raw_mail` without a leading `X-Bouncer-Audit-*` header block.
+pub fn strip_audit_headers(raw_mail: &[u8]) -> &[u8] {
+    let mut rest = raw_mail;
+    while rest.len() >= AUDIT_HEADER_PREFIX.len()
+        && rest[..AUDIT_HEADER_PREFIX.len()].eq_ignore_ascii_case(AUDIT_HEADER_PREFIX.as_bytes())
+    {
+        match rest.iter().position(|&byte| byte == b'\n') {
+            Some(end) => rest = &rest[end + 1..],
+            None => return &[]
+        }
+    }
+    rest
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{BounceAudit, IngestPath, strip_audit_headers};
+
+    #[test]
+    fn headers_round_trip_through_strip() {
+        let audit = BounceAudit {
+            hash: "abc123".to_string(),
+            ingest_path: IngestPath::Tcp,
+            source: Some("mx-01".to_string()),
+            origin: "0190a1b2.eml".to_string(),
+            received_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            processed_at: UNIX_EPOCH + Duration::from_secs(1_700_000_002),
+            scan_labels: vec!["dsn".to_string(), "hash=attachment:message/rfc822@0.2".to_string()],
+            outcome: "applied"
+        };
+        let headers = audit.headers();
+        assert!(
+            headers.starts_with("X-Bouncer-Audit-Path: tcp\r\nX-Bouncer-Audit-Source: mx-01\r\n")
+        );
+        assert!(headers.contains("X-Bouncer-Audit-Received: 2023-11-14T22:13:20Z\r\n"));
+        assert!(
+            headers.contains("X-Bouncer-Audit-Scan: dsn, hash=attachment:message/rfc822@0.2\r\n")
+        );
+
+        let mail = b"From: MAILER-DAEMON\r\n\r\nbody";
+        let mut audited = headers.into_bytes();
+        audited.extend_from_slice(mail);
+        assert_eq!(strip_audit_headers(&audited), mail);
+        assert_eq!(strip_audit_headers(mail), mail);
+    }
+}
@@ -0,0 +1,329 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use mail_auth::{AuthenticatedMessage, DkimResult, MessageAuthenticator};
+use mail_parser::{Host, Message, MessageParser};
+
+use super::allowlist::IpCidr;
+use crate::config::{AuthenticationConfig, AuthenticationMode};
+
+/// `trusted_relays` entry for bounces generated on this host: the topmost
+/// `Received` header has no `from` clause, or there is none at all.
+const LOCAL_RELAY: &str = "local";
+
+/// Outcome of one check, as stored in `bounce_authentication`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckResult {
+    Pass,
+    Fail,
+    /// Nothing to check: no signature.
+    None,
+    /// DNS lookups failed or timed out.
+    TempError,
+    /// Disabled in the config, or not needed for a trusted relay.
+    Skipped
+}
+
+impl CheckResult {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Fail => "fail",
+            Self::None => "none",
+            Self::TempError => "temperror",
+            Self::Skipped => "skipped"
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthOutcome {
+    pub dkim: CheckResult,
+    /// `d=` of the aligned passing signature, or else of the first one.
+    pub dkim_domain: Option<String>,
+    pub relay: CheckResult,
+    /// Relay that handed the bounce to us, from the topmost `Received`.
+    pub relay_host: Option<String>
+}
+
+impl AuthOutcome {
+    /// True when any check vouches for the bounce.
+    pub fn passed(&self) -> bool {
+        self.dkim == CheckResult::Pass || self.relay == CheckResult::Pass
+    }
+
+    pub fn verdict(&self) -> &'static str {
+        if self.passed() { "pass" } else { "fail" }
+    }
+}
+
+/// Runs the `authentication` checks on spooled bounces; a default instance
+/// is disabled.
+pub struct BounceAuthenticator {
+    config: AuthenticationConfig,
+    trusted: TrustedRelays,
+    /// Present when DKIM is checked.
+    resolver: Option<MessageAuthenticator>
+}
+
+impl Default for BounceAuthenticator {
+    fn default() -> Self {
+        Self::new(AuthenticationConfig::default()).expect("default authentication config")
+    }
+}
+
+impl BounceAuthenticator {
+    pub fn new(config: AuthenticationConfig) -> Result<Self> {
+        let trusted = TrustedRelays::parse(&config.trusted_relays)?;
+        let resolver = if config.enabled() && config.dkim {
+            let resolver = MessageAuthenticator::new_system_conf()
+                .context("failed to load the system DNS resolver config for DKIM")?;
+            Some(resolver)
+        } else {
+            None
+        };
+        Ok(Self { config, trusted, resolver })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled()
+    }
+
+    pub fn rejects(&self) -> bool {
+        self.config.mode == AuthenticationMode::Reject
+    }
+
+    pub async fn check(
+        &self,
+        raw_mail: &[u8]
+    ) -> AuthOutcome {
+        let Some(message) = MessageParser::default().parse(raw_mail) else {
+            return AuthOutcome {
+                dkim: CheckResult::None,
+                dkim_domain: None,
+                relay: CheckResult::None,
+                relay_host: None
+            };
+        };
+
+        let relay = Relay::of(&message);
+        let limit = Duration::from_secs(self.config.dns_timeout_secs);
+        if self.trusted.contains(&relay, limit).await {
+            return AuthOutcome {
+                dkim: CheckResult::Skipped,
+                dkim_domain: None,
+                relay: CheckResult::Pass,
+                relay_host: Some(relay.display())
+            };
+        }
+
+        let (dkim, dkim_domain) = match &self.resolver {
+            Some(resolver) => verify_dkim(resolver, raw_mail, limit).await,
+            None => (CheckResult::Skipped, None)
+        };
+
+        AuthOutcome {
+            dkim,
+            dkim_domain,
+            relay: CheckResult::Fail,
+            relay_host: Some(relay.display())
+        }
+    }
+}
+
+/// Passes when a signature verifies and its domain is the `From` domain or a
+/// parent of it; a third-party signature proves nothing about the sender.
+async fn verify_dkim(
+    resolver: &MessageAuthenticator,
+    raw_mail: &[u8],
+    limit: Duration
+) -> (CheckResult, Option<String>) {
+    let Some(message) = AuthenticatedMessage::parse(raw_mail) else {
+        return (CheckResult::None, None);
+    };
+    if message.dkim_headers.is_empty() {
+        return (CheckResult::None, None);
+    }
+    let from_domains: Vec<String> = message
+        .from
+        .iter()
+        .filter_map(|address| address.rsplit_once('@'))
+        .map(|(_, domain)| domain.to_ascii_lowercase())
+        .collect();
+
+    let Ok(outputs) = tokio::time::timeout(limit, resolver.verify_dkim(&message)).await else {
+        return (CheckResult::TempError, None);
+    };
+
+    let mut result = CheckResult::Fail;
+    let mut first_domain = None;
+    for output in &outputs {
+        let domain = output.signature().map(|signature| signature.d.to_ascii_lowercase());
+        if first_domain.is_none() {
+            first_domain = domain.clone();
+        }
+        match output.result() {
+            DkimResult::Pass
+                if domain.as_deref().is_some_and(|domain| {
+                    from_domains.iter().any(|from| is_same_or_subdomain(from, domain))
+                }) =>
+            {
+                return (CheckResult::Pass, domain);
+            }
+            DkimResult::TempError(_) => result = CheckResult::TempError,
+            _ => {}
+        }
+    }
+    (result, first_domain)
+}
+
+/// The peer in the topmost `Received` header, which our own MTA (or the SMTP
+/// listener) wrote: the address in brackets is the one it saw connecting.
+/// The HELO name next to it is whatever the client sent, so it is only
+/// shown, never trusted; neither is anything further down the message.
+#[derive(Debug, Default)]
+struct Relay {
+    /// Claimed HELO name, for display.
+    helo: Option<String>,
+    ip: Option<IpAddr>,
+    /// No `from` clause at all: generated on this host.
+    local: bool
+}
+
+impl Relay {
+    fn of(message: &Message<'_>) -> Self {
+        let Some(received) = message.received() else {
+            return Self { local: true, ..Self::default() };
+        };
+        let helo = match &received.from {
+            Some(Host::Name(name)) => Some(name.to_ascii_lowercase()),
+            Some(Host::IpAddr(ip)) => Some(format!("[{ip}]")),
+            None => None
+        };
+        let local = received.from.is_none() && received.from_ip.is_none();
+        Self { helo, ip: received.from_ip, local }
+    }
+
+    fn display(&self) -> String {
+        match (&self.helo, self.ip) {
+            (Some(helo), Some(ip)) => format!("{helo} [{ip}]"),
+            (Some(helo), None) => helo.clone(),
+            (None, Some(ip)) => ip.to_string(),
+            (None, None) => LOCAL_RELAY.to_string()
+        }
+    }
+}
+
+/// `trusted_relays`: addresses and CIDR ranges, host names resolved here at
+/// check time, and `local`.
+#[derive(Debug, Default)]
+struct TrustedRelays {
+    local: bool,
+    ranges: Vec<IpCidr>,
+    hosts: Vec<String>
+}
+
+impl TrustedRelays {
+    fn parse(entries: &[String]) -> Result<Self> {
+        let mut trusted = Self::default();
+        for entry in entries {
+            if entry == LOCAL_RELAY {
+                trusted.local = true;
+            } else if entry.contains('/') || entry.parse::<IpAddr>().is_ok() {
+                let range = IpCidr::parse(entry).context("invalid `trusted_relays` entry")?;
+                trusted.ranges.push(range);
+            } else {
+                trusted.hosts.push(entry.clone());
+            }
+        }
+        Ok(trusted)
+    }
+
+    /// True for a local bounce or a peer address in a range or among the
+    /// addresses a trusted host name resolves to within `limit`.
+    async fn contains(
+        &self,
+        relay: &Relay,
+        limit: Duration
+    ) -> bool {
+        if relay.local {
+            return self.local;
+        }
+        let Some(ip) = relay.ip else {
+            return false;
+        };
+        if self.ranges.iter().any(|range| range.contains(ip)) {
+            return true;
+        }
+        for host in &self.hosts {
+            let lookup = tokio::net::lookup_host((host.as_str(), 0));
+            if let Ok(Ok(mut addrs)) = tokio::time::timeout(limit, lookup).await
+                && addrs.any(|addr| addr.ip().to_canonical() == ip.to_canonical())
+            {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+fn is_same_or_subdomain(
+    domain: &str,
+    parent: &str
+) -> bool {
+    domain == parent || domain.strip_suffix(parent).is_some_and(|rest| rest.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BounceAuthenticator, CheckResult};
+    use crate::config::{AuthenticationConfig, AuthenticationMode};
+
+    fn bounce(
+        relay: &str,
+        original_by: &str
+    ) -> String {
+        format!(
+            "Received: from {relay}\r\n\tby mx.example.com (Postfix) with ESMTPS id 4Xy; Mon, 6 Jan 2025 10:00:00 +0000\r\nFrom: MAILER-DAEMON@mx.remote.example\r\nContent-Type: multipart/report; report-type=delivery-status; boundary=\"b\"\r\n\r\n--b\r\nContent-Type: message/delivery-status\r\n\r\nFinal-Recipient: rfc822; user@remote.example\r\nAction: failed\r\nStatus: 5.1.1\r\n\r\n--b\r\nContent-Type: text/rfc822-headers\r\n\r\nReceived: from out.example.com (out.example.com [192.0.2.1])\r\n\tby {original_by} with ESMTPS; Mon, 6 Jan 2025 09:59:59 +0000\r\nMessage-ID: <a1b2c3@example.com>\r\n\r\n--b--\r\n"
+        )
+    }
+
+    #[tokio::test]
+    async fn trusts_only_the_recorded_peer_address() {
+        let authenticator = BounceAuthenticator::new(AuthenticationConfig {
+            mode: AuthenticationMode::Reject,
+            dkim: false,
+            trusted_relays: ["local", "localhost", "198.51.100.0/24"].map(str::to_string).to_vec(),
+            ..AuthenticationConfig::default()
+        })
+        .unwrap();
+
+        let relayed =
+            bounce("mx1.remote.example (mx1.remote.example [198.51.100.7])", "mx2.remote.example");
+        let outcome = authenticator.check(relayed.as_bytes()).await;
+        assert_eq!(outcome.relay, CheckResult::Pass);
+        assert_eq!(outcome.dkim, CheckResult::Skipped);
+        assert_eq!(outcome.relay_host.as_deref(), Some("mx1.remote.example [198.51.100.7]"));
+        assert!(outcome.passed());
+
+        let resolved = bounce("mx.example.com (localhost [127.0.0.1])", "mx2.remote.example");
+        assert!(authenticator.check(resolved.as_bytes()).await.passed());
+
+        // The HELO name and the returned headers are the client's to choose.
+        for helo in ["localhost", "[127.0.0.1]", "mx2.remote.example"] {
+            let forged = bounce(&format!("{helo} (unknown [203.0.113.9])"), "mx2.remote.example");
+            let outcome = authenticator.check(forged.as_bytes()).await;
+            assert_eq!(outcome.relay, CheckResult::Fail, "{helo}");
+            assert_eq!(outcome.verdict(), "fail");
+        }
+        let no_address = bounce("localhost", "mx2.remote.example");
+        assert!(!authenticator.check(no_address.as_bytes()).await.passed());
+
+        let local =
+            "Received: by mx.example.com (Postfix) id 4Xy\r\nSubject: Undelivered\r\n\r\nbody\r\n";
+        let outcome = authenticator.check(local.as_bytes()).await;
+        assert_eq!(outcome.relay, CheckResult::Pass);
+        assert_eq!(outcome.relay_host.as_deref(), Some("local"));
+    }
+}
@@ -0,0 +1,255 @@
+//! Write-behind batching of observer events.
+//!
+//! At thousands of events per minute, one transaction per event spends most
+//! of its time on database round-trips. With `database_batch.max_size`
+//! above 1, [`Database::apply_observer_event`] queues the event instead and
+//! waits for its outcome: [`run_observer_batcher`] collects queued events
+//! until `max_size` are waiting or `flush_interval` passed since the first
+//! one, and applies them in one transaction. Each caller still gets the
+//! outcome or error of its own event, so an event is only ACKed once its
+//! transaction committed.
+//!
+//! A batch that fails is applied again one event per transaction, so one
+//! bad event does not fail the others. Until the batcher runs, and once it
+//! stopped, events are applied one at a time as before.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use bouncer_proto::event::DeliveryEvent;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{Instant, timeout_at};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use super::database::{Database, ObserverEventOutcome};
+use crate::config::DatabaseBatchConfig;
+
+/// Queued events per slot of a batch; senders wait once it is full.
+const QUEUE_BATCHES: usize = 4;
+
+#[derive(Debug)]
+struct Pending {
+    event: DeliveryEvent,
+    reply: oneshot::Sender<Result<ObserverEventOutcome>>
+}
+
+/// Queue between [`Database::apply_observer_event`] and
+/// [`run_observer_batcher`]; disabled by default.
+#[derive(Debug, Default)]
+pub struct ObserverBatcher {
+    config: DatabaseBatchConfig,
+    queue: Option<mpsc::Sender<Pending>>,
+    /// Taken by [`run_observer_batcher`] when it starts.
+    receiver: Mutex<Option<mpsc::Receiver<Pending>>>,
+    running: AtomicBool
+}
+
+impl ObserverBatcher {
+    pub fn new(config: DatabaseBatchConfig) -> Self {
+        if !config.enabled() {
+            return Self { config, ..Self::default() };
+        }
+        let (queue, receiver) = mpsc::channel(config.max_size.saturating_mul(QUEUE_BATCHES));
+        Self {
+            config,
+            queue: Some(queue),
+            receiver: Mutex::new(Some(receiver)),
+            running: AtomicBool::new(false)
+        }
+    }
+
+    /// Queues `event` and waits for the outcome of its batch; `None` while
+    /// the batcher is not running, for the caller to apply it directly.
+    pub(crate) async fn submit(
+        &self,
+        event: DeliveryEvent
+    ) -> Option<Result<ObserverEventOutcome>> {
+        let queue = self.queue.as_ref().filter(|_| self.running.load(Ordering::Acquire))?;
+        let (reply, outcome) = oneshot::channel();
+        queue.send(Pending { event, reply }).await.ok()?;
+        // Dropped unanswered only when the batcher stopped before reaching it.
+        outcome.await.ok()
+    }
+
+    /// Events waiting for a batch; `None` when batching is disabled.
+    pub fn queued(&self) -> Option<usize> {
+        self.queue.as_ref().map(|queue| queue.max_capacity() - queue.capacity())
+    }
+
+    fn take_receiver(&self) -> Option<mpsc::Receiver<Pending>> {
+        self.receiver.lock().expect("observer batcher mutex poisoned").take()
+    }
+}
+
+/// Applies queued observer events in batches until `shutdown`, then flushes
+/// what is still queued. Returns at once when batching is disabled.
+pub async fn run_observer_batcher(
+    db: Arc<Database>,
+    shutdown: CancellationToken
+) {
+    let batcher = db.batcher();
+    let Some(mut receiver) = batcher.take_receiver() else {
+        return;
+    };
+    let DatabaseBatchConfig { max_size, flush_interval } = batcher.config;
+    batcher.running.store(true, Ordering::Release);
+    info!(
+        "observer event batching enabled: max_size={}, flush_interval={}",
+        max_size,
+        humantime::format_duration(flush_interval)
+    );
+
+    loop {
+        let first = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            next = receiver.recv() => match next {
+                Some(pending) => pending,
+                None => break
+            }
+        };
+        let mut batch = vec![first];
+        let deadline = Instant::now() + flush_interval;
+        while batch.len() < max_size {
+            match timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(pending)) => batch.push(pending),
+                Ok(None) | Err(_) => break
+            }
+        }
+        flush(&db, batch).await;
+    }
+
+    // New events go straight to the database from here on.
+    batcher.running.store(false, Ordering::Release);
+    receiver.close();
+    loop {
+        let mut batch = Vec::new();
+        while batch.len() < max_size
+            && let Ok(pending) = receiver.try_recv()
+        {
+            batch.push(pending);
+        }
+        if batch.is_empty() {
+            break;
+        }
+        flush(&db, batch).await;
+    }
+}
+
+/// Applies one batch and answers every event in it.
+async fn flush(
+    db: &Database,
+    batch: Vec<Pending>
+) {
+    let (events, replies): (Vec<_>, Vec<_>) =
+        batch.into_iter().map(|pending| (pending.event, pending.reply)).unzip();
+    let err = match db.apply_observer_events(&events).await {
+        Ok(outcomes) => {
+            debug!("observer event batch applied: events={}", events.len());
+            for (reply, outcome) in replies.into_iter().zip(outcomes) {
+                let _ = reply.send(Ok(outcome));
+            }
+            return;
+        }
+        Err(err) => err
+    };
+
+    let mut replies = replies.into_iter();
+    if events.len() == 1 {
+        if let Some(reply) = replies.next() {
+            let _ = reply.send(Err(err));
+        }
+        return;
+    }
+    warn!(
+        "observer event batch failed, applying its events one by one: events={}, error={err:#}",
+        events.len()
+    );
+    for (event, reply) in events.into_iter().zip(replies) {
+        let outcome = db
+            .apply_observer_events(std::slice::from_ref(&event))
+            .await
+            .map(|mut outcomes| outcomes.remove(0));
+        let _ = reply.send(outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bouncer_proto::event::DeliveryEvent;
+    use tokio_util::sync::CancellationToken;
+    use uuid::Uuid;
+
+    use super::run_observer_batcher;
+    use crate::config::{DatabaseBatchConfig, MigrateMode, SuppressionConfig};
+    use crate::core::database::{Database, ObserverEventOutcome};
+    use crate::core::faults::Faults;
+
+    #[tokio::test]
+    async fn batches_events_and_reports_each_outcome() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let url = format!("sqlite:{}", path.display());
+        let db = Arc::new(
+            Database::connect(
+                &url,
+                MigrateMode::Auto,
+                SuppressionConfig::default(),
+                Arc::new(Faults::default())
+            )
+            .await
+            .unwrap()
+            .with_batching(DatabaseBatchConfig {
+                max_size: 8,
+                flush_interval: Duration::from_millis(50)
+            })
+        );
+        let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES ('tracked', 3)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+
+        let shutdown = CancellationToken::new();
+        let batcher = tokio::spawn(run_observer_batcher(db.clone(), shutdown.clone()));
+        while !db.batcher().running.load(std::sync::atomic::Ordering::Acquire) {
+            tokio::task::yield_now().await;
+        }
+
+        let event = |hash: &str, queue_id: &str, sequence: u64| {
+            DeliveryEvent::new("observer-1", hash, queue_id, "user@example.com", 1_700_000_000)
+                .with_outcome("bounced", "5.1.1", "failed")
+                .with_sequence(sequence)
+        };
+        let (first, second) = (event("tracked", "Q1", 2), event("orphan", "Q2", 1));
+        let (tracked, orphan, stale) = tokio::join!(
+            db.apply_observer_event(&first),
+            db.apply_observer_event(&second),
+            async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                db.apply_observer_event(&event("tracked", "Q1", 1)).await
+            }
+        );
+        assert_eq!(tracked.unwrap(), ObserverEventOutcome::Applied);
+        assert_eq!(orphan.unwrap(), ObserverEventOutcome::Unlinked);
+        assert_eq!(stale.unwrap(), ObserverEventOutcome::OutOfOrder);
+        assert_eq!(db.message_state("tracked", None).await.unwrap().history.len(), 1);
+
+        shutdown.cancel();
+        batcher.await.unwrap();
+        // Stopped: applied directly.
+        assert_eq!(
+            db.apply_observer_event(&event("tracked", "Q1", 3)).await.unwrap(),
+            ObserverEventOutcome::Applied
+        );
+
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+}
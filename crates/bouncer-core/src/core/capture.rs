@@ -0,0 +1,149 @@
+use crate::config::{PayloadCaptureConfig, RedactionConfig};
+
+const REDACTED: &str = "[redacted]";
+
+/// Decides which ingested payloads are captured and renders their redacted
+/// head (see `payload_capture` in the config).
+#[derive(Debug, Default)]
+pub struct PayloadCapture {
+    config: PayloadCaptureConfig
+}
+
+impl PayloadCapture {
+    pub fn new(config: PayloadCaptureConfig) -> Self {
+        Self { config }
+    }
+
+    /// Redacted head of `body` when this frame of `source` is sampled.
+    pub fn sample(
+        &self,
+        source: &str,
+        body: &[u8]
+    ) -> Option<String> {
+        if !self.config.enabled() {
+            return None;
+        }
+        let rate = self.config.rate_for(source);
+        if rate <= 0.0 || fastrand::f64() >= rate {
+            return None;
+        }
+        Some(snippet(body, self.config.max_bytes, &self.config.redact))
+    }
+}
+
+/// First `max_bytes` of `body` as text with the redaction rules applied.
+fn snippet(
+    body: &[u8],
+    max_bytes: usize,
+    redact: &RedactionConfig
+) -> String {
+    let head = String::from_utf8_lossy(&body[..body.len().min(max_bytes)]);
+    let head = redact_headers(&head, &redact.headers);
+    if redact.emails { mask_emails(&head) } else { head }
+}
+
+/// Replaces the value of each listed header, continuation lines included.
+/// Only the header block (up to the first empty line) is touched.
+fn redact_headers(
+    text: &str,
+    names: &[String]
+) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_headers = true;
+    let mut redacting = false;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        if in_headers && content.is_empty() {
+            in_headers = false;
+        }
+        if !in_headers {
+            out.push_str(line);
+            continue;
+        }
+        if redacting && content.starts_with([' ', '\t']) {
+            continue;
+        }
+        redacting = false;
+        match content.split_once(':') {
+            Some((name, _))
+                if names.iter().any(|listed| listed.eq_ignore_ascii_case(name.trim())) =>
+            {
+                redacting = true;
+                out.push_str(name);
+                out.push_str(": ");
+                out.push_str(REDACTED);
+                out.push_str(&line[content.len()..]);
+            }
+            _ => out.push_str(line)
+        }
+    }
+    out
+}
+
+/// Masks the local part of every `local@domain` address, internationalized
+/// ones included.
+fn mask_emails(text: &str) -> String {
+    let is_local = |c: char| c.is_alphanumeric() || "._%+-=".contains(c);
+    let is_domain = |c: char| c.is_alphanumeric() || c == '.' || c == '-';
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('@') {
+        let local_start = rest[..at]
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| !is_local(c))
+            .map_or(0, |(idx, c)| idx + c.len_utf8());
+        let has_domain = rest[at + 1..].starts_with(is_domain);
+        out.push_str(&rest[..local_start]);
+        if local_start < at && has_domain {
+            out.push_str("***");
+        } else {
+            out.push_str(&rest[local_start..at]);
+        }
+        out.push('@');
+        rest = &rest[at + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{PayloadCapture, snippet};
+    use crate::config::{PayloadCaptureConfig, RedactionConfig};
+
+    #[test]
+    fn redacts_listed_headers_and_email_local_parts() {
+        let mail = b"From: MAILER-DAEMON@mx.example.com\r\nDKIM-Signature: v=1; a=rsa;\r\n\tb=abc\r\nSubject: Undelivered\r\n\r\n<user.one@example.org>: 550 5.1.1 unknown\r\nDKIM-Signature: body line\r\n";
+        let redact = RedactionConfig::default();
+
+        let text = snippet(mail, 4096, &redact);
+        assert_eq!(
+            text,
+            "From: ***@mx.example.com\r\nDKIM-Signature: [redacted]\r\nSubject: Undelivered\r\n\r\n<***@example.org>: 550 5.1.1 unknown\r\nDKIM-Signature: body line\r\n"
+        );
+        assert_eq!(snippet(mail, 30, &redact), "From: ***@mx.example");
+
+        let mail =
+            "Subject: Zustellung fehlgeschlagen\r\n\r\n«jörg@bücher.de» für→a@b.example, ü@\r\n";
+        assert_eq!(
+            snippet(mail.as_bytes(), 4096, &redact),
+            "Subject: Zustellung fehlgeschlagen\r\n\r\n«***@bücher.de» für→***@b.example, ü@\r\n"
+        );
+    }
+
+    #[test]
+    fn samples_by_per_source_rate() {
+        let capture = PayloadCapture::new(PayloadCaptureConfig {
+            sample_rate: 0.0,
+            sources: BTreeMap::from([("mail-01".to_string(), 1.0)]),
+            ..PayloadCaptureConfig::default()
+        });
+        assert!(capture.sample("mail-01", b"Subject: x\r\n").is_some());
+        assert!(capture.sample("mail-02", b"Subject: x\r\n").is_none());
+        assert!(PayloadCapture::default().sample("mail-01", b"Subject: x\r\n").is_none());
+    }
+}
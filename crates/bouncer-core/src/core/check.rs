@@ -0,0 +1,133 @@
+//! `bouncer-server check <dir>`: replays stored bounce mails through the
+//! configured parser chain and status mapping, and reports what the worker
+//! would have written. Neither the database nor the spool is touched.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_json::json;
+
+use super::classification::BounceClassifier;
+use super::database::preview_bounce;
+use super::parser::ParserChain;
+use super::server::gunzip_archived;
+use crate::config::Config;
+
+/// Totals of one [`check_dir`] run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CheckSummary {
+    pub files: usize,
+    pub parsed: usize,
+    pub failed: usize
+}
+
+/// Writes one JSON line per file under `dir` (recursively, sorted by path,
+/// dotfiles skipped) to `out`. Gzipped files are unpacked first.
+pub fn check_dir(
+    config: &Config,
+    dir: &Path,
+    out: &mut impl Write
+) -> Result<CheckSummary> {
+    let parsers = ParserChain::from_config(&config.parser)?;
+    let classifier = BounceClassifier::new(config.classification.clone());
+    let mut summary = CheckSummary::default();
+
+    for path in mail_files(dir)? {
+        let raw = std::fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(gunzip_archived)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let file = path.strip_prefix(dir).unwrap_or(&path).display().to_string();
+
+        let report = match parsers.parse_detailed(&raw) {
+            Ok(parsed) => {
+                summary.parsed += 1;
+                let preview = preview_bounce(&parsed, &classifier, &config.suppression);
+                json!({
+                    "file": file,
+                    "ok": true,
+                    "kind": parsed.kind.as_str(),
+                    "hash": parsed.hash,
+                    "status_code": parsed.status_code,
+                    "action": parsed.action,
+                    "recipient": parsed.recipient,
+                    "reason": parsed.reason(),
+                    "category": preview.category.map(|category| category.as_str()),
+                    "mail_status": preview.mail_status,
+                    "suppression": preview.suppression.map(|reason| reason.as_str())
+                })
+            }
+            Err(err) => {
+                summary.failed += 1;
+                json!({ "file": file, "ok": false, "code": err.code(), "error": err.to_string() })
+            }
+        };
+        summary.files += 1;
+        writeln!(out, "{report}").context("failed to write check report")?;
+    }
+    Ok(summary)
+}
+
+fn mail_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = std::fs::read_dir(&dir)
+            .with_context(|| format!("failed to read dir {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry.with_context(|| format!("failed to read dir {}", dir.display()))?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let kind = entry.file_type().context("failed to stat check file")?;
+            if kind.is_dir() {
+                dirs.push(entry.path());
+            } else if kind.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+    use uuid::Uuid;
+
+    use super::{CheckSummary, check_dir};
+    use crate::config::Config;
+
+    #[test]
+    fn reports_every_fixture_without_a_database() {
+        // The top-level fixtures only; `corpus/` below them holds malformed
+        // mail on purpose.
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/bounces");
+        let dir = std::env::temp_dir().join(format!("bouncer-check-{}", Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for entry in std::fs::read_dir(fixtures).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_file() {
+                std::fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+            }
+        }
+        let config: Config =
+            serde_json::from_value(json!({ "database_url": "mysql://unused" })).unwrap();
+        let mut out = Vec::new();
+
+        let summary = check_dir(&config, &dir, &mut out).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(summary, CheckSummary { files: 3, parsed: 3, failed: 0 });
+        let reports: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(reports[0]["file"], "inbox.returned.eml");
+        assert!(reports.iter().all(|report| report["ok"] == true && report["hash"].is_string()));
+        assert!(reports.iter().any(|report| report["mail_status"] == -7));
+    }
+}
@@ -0,0 +1,142 @@
+//! Normalized bounce categories (`hard`, `soft`, `block`, `full`, `spam`).
+//!
+//! Configured `classification.rules` come first, each checked by phrase and
+//! then by status code. Without a match the built-in reason (see
+//! `parser::reason`) picks the category, and the status code class decides
+//! whatever is left: `4.x.x` is soft, `5.x.x` is hard.
+
+use super::parser::{ParsedBounce, ReportKind};
+use crate::config::{BounceCategory, ClassificationConfig, ClassificationRule};
+
+/// Maps parsed bounces to a [`BounceCategory`]; a default instance only
+/// applies the built-in rules.
+#[derive(Debug, Default)]
+pub struct BounceClassifier {
+    rules: Vec<ClassificationRule>
+}
+
+impl BounceClassifier {
+    pub fn new(config: ClassificationConfig) -> Self {
+        Self { rules: config.rules }
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// `None` for auto-replies and reports that are not failures.
+    pub fn classify(
+        &self,
+        parsed: &ParsedBounce
+    ) -> Option<BounceCategory> {
+        if parsed.kind == ReportKind::Autoreply {
+            return None;
+        }
+
+        if let Some(description) = parsed.description.as_deref().map(str::to_lowercase)
+            && let Some(rule) = self.rules.iter().find(|rule| {
+                rule.phrases.iter().any(|phrase| description.contains(phrase.as_str()))
+            })
+        {
+            return Some(rule.category);
+        }
+        let status_code = parsed.status_code.trim();
+        if let Some(rule) = self.rules.iter().find(|rule| rule.matches_status_code(status_code)) {
+            return Some(rule.category);
+        }
+
+        if let Some(category) = parsed.reason().and_then(reason_category) {
+            return Some(category);
+        }
+        match status_code.as_bytes().first() {
+            Some(b'4') => Some(BounceCategory::Soft),
+            Some(b'5') => Some(BounceCategory::Hard),
+            _ => None
+        }
+    }
+}
+
+/// Category of a built-in reason.
+fn reason_category(reason: &str) -> Option<BounceCategory> {
+    match reason {
+        "mailbox full" => Some(BounceCategory::Full),
+        "user unknown" | "mailbox disabled" | "domain not found" | "message too large" => {
+            Some(BounceCategory::Hard)
+        }
+        "rejected as spam" | "spam complaint" => Some(BounceCategory::Spam),
+        "blocked by policy" => Some(BounceCategory::Block),
+        "rate limited" => Some(BounceCategory::Soft),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BounceClassifier;
+    use crate::config::{BounceCategory, ClassificationConfig, ClassificationRule};
+    use crate::core::parser::{ParsedBounce, ReportKind};
+
+    fn bounce(
+        status_code: &str,
+        description: Option<&str>
+    ) -> ParsedBounce {
+        ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: "hash".to_string(),
+            status_code: status_code.to_string(),
+            action: Some("failed".to_string()),
+            sender: None,
+            recipient: None,
+            description: description.map(str::to_string),
+            scan_labels: Vec::new(),
+            tenant: None
+        }
+    }
+
+    #[test]
+    fn classifies_by_configured_rules_then_builtin_reasons() {
+        let builtin = BounceClassifier::default();
+        let cases = [
+            (
+                "5.0.0",
+                Some("550 User unknown in virtual mailbox table"),
+                Some(BounceCategory::Hard)
+            ),
+            ("4.2.2", None, Some(BounceCategory::Full)),
+            ("5.7.1", Some("554 Message rejected as spam"), Some(BounceCategory::Spam)),
+            ("5.7.1", Some("550 5.7.1 Relaying denied"), Some(BounceCategory::Block)),
+            ("4.4.1", Some("connection timed out"), Some(BounceCategory::Soft)),
+            ("5.0.0", Some("550 rejected"), Some(BounceCategory::Hard)),
+            ("2.0.0", None, None)
+        ];
+        for (status_code, description, category) in cases {
+            assert_eq!(
+                builtin.classify(&bounce(status_code, description)),
+                category,
+                "{description:?}"
+            );
+        }
+
+        let configured = BounceClassifier::new(ClassificationConfig {
+            rules: vec![
+                ClassificationRule {
+                    category: BounceCategory::Block,
+                    status_codes: Vec::new(),
+                    phrases: vec!["listed at spamhaus".to_string()]
+                },
+                ClassificationRule {
+                    category: BounceCategory::Soft,
+                    status_codes: vec!["5.2.*".to_string()],
+                    phrases: Vec::new()
+                },
+            ]
+        });
+        let listed = bounce("5.7.1", Some("554 Client host blocked, Listed at Spamhaus"));
+        assert_eq!(configured.classify(&listed), Some(BounceCategory::Block));
+        assert_eq!(builtin.classify(&listed), Some(BounceCategory::Spam));
+        assert_eq!(configured.classify(&bounce("5.2.2", None)), Some(BounceCategory::Soft));
+
+        let autoreply = ParsedBounce { kind: ReportKind::Autoreply, ..bounce("2.0.0", None) };
+        assert_eq!(configured.classify(&autoreply), None);
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+/// Width of the window accepted connections are counted in.
+const STORM_WINDOW: Duration = Duration::from_secs(10);
+/// Accepted connections per window above which the window counts as a storm.
+const STORM_THRESHOLD: u64 = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionSnapshot {
+    pub accepted_total: u64,
+    /// Peers dropped at accept time because `listen_allow` excludes them.
+    pub rejected_total: u64,
+    pub storms: u64,
+    pub peak_per_window: u64
+}
+
+/// Tracks accepted TCP connections in fixed windows to surface reconnect
+/// storms, e.g. a whole observer fleet reconnecting after a server restart.
+#[derive(Debug)]
+pub struct ConnectionStats {
+    inner: Mutex<Inner>
+}
+
+#[derive(Debug)]
+struct Inner {
+    window_start: Instant,
+    in_window: u64,
+    storm_flagged: bool,
+    snapshot: ConnectionSnapshot
+}
+
+impl ConnectionStats {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                window_start: now,
+                in_window: 0,
+                storm_flagged: false,
+                snapshot: ConnectionSnapshot::default()
+            })
+        }
+    }
+
+    /// Counts one accepted connection; logs once per window that crosses
+    /// the storm threshold.
+    pub fn record_accept(
+        &self,
+        now: Instant
+    ) {
+        let mut inner = self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if now.duration_since(inner.window_start) >= STORM_WINDOW {
+            inner.window_start = now;
+            inner.in_window = 0;
+            inner.storm_flagged = false;
+        }
+
+        inner.in_window += 1;
+        inner.snapshot.accepted_total += 1;
+        inner.snapshot.peak_per_window = inner.snapshot.peak_per_window.max(inner.in_window);
+
+        if inner.in_window > STORM_THRESHOLD && !inner.storm_flagged {
+            inner.storm_flagged = true;
+            inner.snapshot.storms += 1;
+            warn!(
+                "connection storm detected: accepted_in_window={}, window_secs={}, storms_total={}",
+                inner.in_window,
+                STORM_WINDOW.as_secs(),
+                inner.snapshot.storms
+            );
+        }
+    }
+
+    /// Counts one peer dropped by the `listen_allow` check.
+    pub fn record_reject(&self) {
+        let mut inner = self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        inner.snapshot.rejected_total += 1;
+    }
+
+    pub fn snapshot(&self) -> ConnectionSnapshot {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).snapshot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{ConnectionStats, STORM_THRESHOLD};
+
+    #[test]
+    fn counts_one_storm_per_window() {
+        let start = Instant::now();
+        let stats = ConnectionStats::new(start);
+
+        for _ in 0..STORM_THRESHOLD * 2 {
+            stats.record_accept(start);
+        }
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.storms, 1);
+        assert_eq!(snapshot.peak_per_window, STORM_THRESHOLD * 2);
+
+        let later = start + Duration::from_secs(11);
+        stats.record_accept(later);
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.storms, 1);
+        assert_eq!(snapshot.accepted_total, STORM_THRESHOLD * 2 + 1);
+    }
+}
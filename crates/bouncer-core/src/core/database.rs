@@ -0,0 +1,3816 @@
+use std::cmp::Reverse;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bouncer_helpers::backoff::Backoff;
+use bouncer_helpers::clock::{SharedClock, system_clock};
+use bouncer_helpers::coded_warn;
+use bouncer_helpers::error_code::ErrorCode;
+use bouncer_proto::event::DeliveryEvent;
+use bouncer_proto::query::{BounceEvent, BounceRecord, MessageState, SourceEvent};
+use bouncer_proto::status::DbPoolStatus;
+use sha2::{Digest, Sha256};
+use sqlx::mysql::MySqlPoolOptions;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use sqlx::{MySql, MySqlPool, Sqlite, SqlitePool, Transaction};
+use tokio::time::interval;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use super::alerts::BounceAlerts;
+use super::archive::ArchivedCopy;
+use super::audit::{BounceAudit, unix_secs};
+use super::authentication::AuthOutcome;
+use super::batching::ObserverBatcher;
+use super::classification::BounceClassifier;
+use super::faults::Faults;
+use super::hooks::BounceHooks;
+use super::message_cache::MessageIdCache;
+use super::migrations::apply_migrations;
+use super::parser::{ParsedBounce, ReportKind};
+use super::recipients::RecipientNormalizer;
+use super::reload::Live;
+use super::resilience::{DatabaseUnavailable, DbBreaker, is_transient};
+use crate::config::{
+    BounceCategory, ClassificationConfig, DatabaseBatchConfig, DatabaseResilienceConfig,
+    MessageCacheConfig, MigrateMode, RecipientsConfig, SuppressionConfig
+};
+
+const MAIL_STATUS_SUCCESS: i32 = 7;
+const MAIL_STATUS_PENDING: i32 = 3;
+const MAIL_STATUS_SUSPENDED: i32 = -2;
+const MAIL_STATUS_FAILED: i32 = -7;
+
+/// `observer_event_order` rows untouched this long are pruned; a delayed
+/// event older than that is applied as if it were new.
+const OBSERVER_ORDER_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+/// `processed_spool_messages` keys older than this are pruned, well past any
+/// MTA retry of the same bounce; a file replayed later is applied again.
+const PROCESSED_SPOOL_RETENTION: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// How long a SQLite writer waits for the database lock before failing.
+const SQLITE_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs `$query.$method(pool)` on whichever backend `$pool` wraps.
+///
+/// `$query` is expanded once per backend, so the same SQL and binds are
+/// type-checked against both drivers. `execute` yields the affected row count.
+macro_rules! on_pool {
+    ($pool:expr, execute, $query:expr) => {
+        match $pool {
+            Pool::MySql(pool) => $query.execute(pool).await.map(|done| done.rows_affected()),
+            Pool::Sqlite(pool) => $query.execute(pool).await.map(|done| done.rows_affected())
+        }
+    };
+    ($pool:expr, $method:ident, $query:expr) => {
+        match $pool {
+            Pool::MySql(pool) => $query.$method(pool).await,
+            Pool::Sqlite(pool) => $query.$method(pool).await
+        }
+    };
+}
+
+/// Same as [`on_pool!`] for an open [`Tx`].
+macro_rules! on_tx {
+    ($tx:expr, execute, $query:expr) => {
+        match $tx {
+            Tx::MySql(tx) => $query.execute(&mut **tx).await.map(|done| done.rows_affected()),
+            Tx::Sqlite(tx) => $query.execute(&mut **tx).await.map(|done| done.rows_affected())
+        }
+    };
+    ($tx:expr, $method:ident, $query:expr) => {
+        match $tx {
+            Tx::MySql(tx) => $query.$method(&mut **tx).await,
+            Tx::Sqlite(tx) => $query.$method(&mut **tx).await
+        }
+    };
+}
+
+/// Connection pool of the backend selected by the `database_url` scheme:
+/// `sqlite:` opens (and creates) an embedded database file, anything else is
+/// handed to the MySQL driver.
+#[derive(Debug)]
+pub(super) enum Pool {
+    MySql(MySqlPool),
+    Sqlite(SqlitePool)
+}
+
+/// Open transaction on either backend.
+enum Tx {
+    MySql(Transaction<'static, MySql>),
+    Sqlite(Transaction<'static, Sqlite>)
+}
+
+impl Pool {
+    pub(super) async fn connect(database_url: &str) -> Result<Self> {
+        if !database_url.starts_with("sqlite:") {
+            return MySqlPoolOptions::new()
+                .max_connections(10)
+                .connect(database_url)
+                .await
+                .map(Self::MySql)
+                .context("failed to open mysql pool");
+        }
+
+        let options = SqliteConnectOptions::from_str(database_url)
+            .context("invalid sqlite database_url")?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(SQLITE_BUSY_TIMEOUT);
+        SqlitePoolOptions::new()
+            .max_connections(10)
+            .connect_with(options)
+            .await
+            .map(Self::Sqlite)
+            .context("failed to open sqlite database")
+    }
+
+    pub(super) fn backend(&self) -> &'static str {
+        match self {
+            Self::MySql(_) => "mysql",
+            Self::Sqlite(_) => "sqlite"
+        }
+    }
+
+    fn status(&self) -> DbPoolStatus {
+        let (connections, idle, max_connections) = match self {
+            Self::MySql(pool) => {
+                (pool.size(), pool.num_idle(), pool.options().get_max_connections())
+            }
+            Self::Sqlite(pool) => {
+                (pool.size(), pool.num_idle(), pool.options().get_max_connections())
+            }
+        };
+        DbPoolStatus {
+            backend: self.backend().to_string(),
+            connections,
+            idle: u32::try_from(idle).unwrap_or(u32::MAX),
+            max_connections,
+            breaker_open: false
+        }
+    }
+
+    async fn begin(&self) -> Result<Tx> {
+        match self {
+            Self::MySql(pool) => pool.begin().await.map(Tx::MySql),
+            // Take the write lock up front: a deferred transaction that reads
+            // before it writes fails with SQLITE_BUSY instead of waiting.
+            Self::Sqlite(pool) => pool.begin_with("BEGIN IMMEDIATE").await.map(Tx::Sqlite)
+        }
+        .context("failed to begin tx")
+    }
+
+    /// SQL expression converting the DATETIME `column` to unix seconds.
+    fn unix_secs(
+        &self,
+        column: &str
+    ) -> String {
+        match self {
+            Self::MySql(_) => format!("CAST(UNIX_TIMESTAMP({column}) AS SIGNED)"),
+            Self::Sqlite(_) => format!("CAST(strftime('%s', {column}) AS INTEGER)")
+        }
+    }
+
+    /// DATETIME expression of `unix_secs`, or `CURRENT_TIMESTAMP` for `None`.
+    fn timestamp_at(
+        &self,
+        unix_secs: Option<u64>
+    ) -> String {
+        match (self, unix_secs) {
+            (_, None) => "CURRENT_TIMESTAMP".to_string(),
+            (Self::MySql(_), Some(secs)) => format!("FROM_UNIXTIME({secs})"),
+            (Self::Sqlite(_), Some(secs)) => format!("datetime({secs}, 'unixepoch')")
+        }
+    }
+
+    /// SQL expression converting a bound unix seconds `?` to a DATETIME.
+    fn datetime_of_unix_secs(&self) -> &'static str {
+        match self {
+            Self::MySql(_) => "FROM_UNIXTIME(?)",
+            Self::Sqlite(_) => "datetime(?, 'unixepoch')"
+        }
+    }
+
+    /// SQL condition matching `column` against a bound `?`, NULL included.
+    fn null_safe_eq(
+        &self,
+        column: &str
+    ) -> String {
+        match self {
+            Self::MySql(_) => format!("{column} <=> ?"),
+            Self::Sqlite(_) => format!("{column} IS ?")
+        }
+    }
+
+    /// SQL condition matching `column` values within the last `?` seconds.
+    fn within_secs(
+        &self,
+        column: &str
+    ) -> String {
+        match self {
+            Self::MySql(_) => format!("{column} >= NOW() - INTERVAL ? SECOND"),
+            Self::Sqlite(_) => format!("{column} >= datetime('now', '-' || ? || ' seconds')")
+        }
+    }
+
+    /// SQL expression of the DATETIME a bound `?` seconds from now; NULL
+    /// when the bound value is NULL.
+    fn secs_from_now(&self) -> &'static str {
+        match self {
+            Self::MySql(_) => "NOW() + INTERVAL ? SECOND",
+            Self::Sqlite(_) => "datetime('now', '+' || ? || ' seconds')"
+        }
+    }
+
+    /// SQL expression of the part of the address `column` after its `@`.
+    fn address_domain(
+        &self,
+        column: &str
+    ) -> String {
+        match self {
+            Self::MySql(_) => format!("SUBSTRING({column}, LOCATE('@', {column}) + 1)"),
+            Self::Sqlite(_) => format!("SUBSTR({column}, INSTR({column}, '@') + 1)")
+        }
+    }
+}
+
+impl Tx {
+    async fn commit(self) -> Result<()> {
+        match self {
+            Self::MySql(tx) => tx.commit().await,
+            Self::Sqlite(tx) => tx.commit().await
+        }
+        .context("failed to commit tx")
+    }
+}
+
+#[derive(Debug)]
+pub struct Database {
+    pool: Pool,
+    schema: SchemaCapabilities,
+    suppression: SuppressionConfig,
+    /// See [`Database::with_recipients`].
+    recipients: RecipientNormalizer,
+    /// See [`Database::with_classification`].
+    classifier: Live<BounceClassifier>,
+    /// See [`Database::with_bounce_dedup`].
+    bounce_dedup_window: Option<Duration>,
+    /// See [`Database::with_resilience`].
+    resilience: DatabaseResilienceConfig,
+    breaker: DbBreaker,
+    /// See [`Database::with_alerts`].
+    alerts: Arc<BounceAlerts>,
+    /// See [`Database::with_hooks`].
+    hooks: Arc<BounceHooks>,
+    /// See [`Database::with_batching`].
+    batcher: ObserverBatcher,
+    /// See [`Database::with_message_cache`].
+    message_ids: MessageIdCache,
+    faults: Arc<Faults>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertBounceOutcome {
+    UpdatedLocalMessage,
+    MissingLocalMessage,
+    /// Already applied within `bounce_dedup_window`, nothing was written.
+    Duplicate
+}
+
+impl UpsertBounceOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::UpdatedLocalMessage => "updated_local_message",
+            Self::MissingLocalMessage => "missing_local_message",
+            Self::Duplicate => "duplicate"
+        }
+    }
+}
+
+/// What [`Database::apply_observer_event`] did with one event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObserverEventOutcome {
+    /// Written to the local message.
+    Applied,
+    /// No local message for the hash; only the suppression list was updated.
+    Unlinked,
+    /// `sequence` not newer than the last applied one; nothing was written.
+    OutOfOrder,
+    /// Already applied within `bounce_dedup_window`; nothing was written.
+    Duplicate
+}
+
+impl ObserverEventOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Applied => "applied",
+            Self::Unlinked => "unlinked",
+            Self::OutOfOrder => "out_of_order",
+            Self::Duplicate => "duplicate"
+        }
+    }
+
+    /// True when the event counts towards the `alerts` rules.
+    pub fn applied(self) -> bool {
+        matches!(self, Self::Applied | Self::Unlinked)
+    }
+}
+
+/// Logs skipped and unlinked observer events once their transaction
+/// committed.
+fn log_observer_outcome(
+    event: &DeliveryEvent,
+    outcome: ObserverEventOutcome
+) {
+    match outcome {
+        ObserverEventOutcome::Applied => {}
+        ObserverEventOutcome::Unlinked => warn!(
+            "observer event not linked to local message: hash={}, queue_id={}, source={}, smtp_status={}, observed_at_unix={}",
+            event.hash, event.queue_id, event.source, event.smtp_status, event.observed_at_unix
+        ),
+        ObserverEventOutcome::OutOfOrder => debug!(
+            "observer event skipped: hash={}, queue_id={}, source={}, sequence={}, smtp_status={}, reason=out_of_order",
+            event.hash,
+            event.queue_id,
+            event.source,
+            event.sequence.unwrap_or_default(),
+            event.smtp_status
+        ),
+        ObserverEventOutcome::Duplicate => debug!(
+            "observer event skipped: hash={}, queue_id={}, source={}, reason=duplicate",
+            event.hash, event.queue_id, event.source
+        )
+    }
+}
+
+/// `action`, `status_code` and `description` of a stored bounce row.
+type StoredDiagnostics = (Option<String>, String, Option<String>);
+
+/// How a bounce upsert changes the stored row.
+///
+/// A repeat of the stored diagnostics only bumps `last_seen_at` and
+/// `occurrence_count`, so `created_at` keeps the first-seen time. Different
+/// diagnostics replace the row and restart the count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BounceWrite {
+    Insert,
+    Repeat,
+    Replace
+}
+
+impl BounceWrite {
+    fn classify(
+        stored: Option<&StoredDiagnostics>,
+        parsed: &ParsedBounce
+    ) -> Self {
+        match stored {
+            None => Self::Insert,
+            Some((action, status_code, description))
+                if action.as_deref() == parsed.action.as_deref()
+                    && *status_code == parsed.status_code
+                    && description.as_deref() == parsed.description.as_deref() =>
+            {
+                Self::Repeat
+            }
+            Some(_) => Self::Replace
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Insert => "insert",
+            Self::Repeat => "repeat",
+            Self::Replace => "replace"
+        }
+    }
+}
+
+fn has_column(
+    columns: &[String],
+    name: &str
+) -> bool {
+    columns.iter().any(|column| column.eq_ignore_ascii_case(name))
+}
+
+/// Optional columns of one bounce table, added by later migrations.
+///
+/// An application schema managed outside bouncer (`migrate: off`) may lack
+/// them. Reads then return `NULL` in their place and writes leave them out,
+/// so a repeat rewrites `created_at` as before and no reason or category is
+/// stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BounceColumns {
+    /// `last_seen_at` and `occurrence_count`.
+    occurrences: bool,
+    reason: bool,
+    category: bool
+}
+
+impl BounceColumns {
+    const ALL: Self = Self { occurrences: true, reason: true, category: true };
+
+    fn probe(columns: &[String]) -> Self {
+        let has = |name: &str| has_column(columns, name);
+        // A missing table is reported by the startup diagnostics; assume the
+        // full schema so it surfaces there instead of as degraded mode.
+        if columns.is_empty() {
+            return Self::ALL;
+        }
+        Self {
+            occurrences: has("last_seen_at") && has("occurrence_count"),
+            reason: has("reason"),
+            category: has("category")
+        }
+    }
+
+    /// Names of the missing columns, for the degraded-mode warning.
+    fn missing(self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if !self.occurrences {
+            missing.extend(["last_seen_at", "occurrence_count"]);
+        }
+        if !self.reason {
+            missing.push("reason");
+        }
+        if !self.category {
+            missing.push("category");
+        }
+        missing
+    }
+
+    /// A repeat can only be counted when the occurrence columns exist.
+    fn adjust(
+        self,
+        write: BounceWrite
+    ) -> BounceWrite {
+        match write {
+            BounceWrite::Repeat if !self.occurrences => BounceWrite::Replace,
+            write => write
+        }
+    }
+
+    /// `reason`, `category`, last-seen unix time and `occurrence_count` select list;
+    /// missing columns read as `NULL`. `prefix` is a table alias like `b.`.
+    fn select(
+        self,
+        pool: &Pool,
+        prefix: &str
+    ) -> String {
+        let reason = if self.reason { format!("{prefix}reason") } else { "NULL".to_string() };
+        let category = if self.category { format!("{prefix}category") } else { "NULL".to_string() };
+        let (last_seen, count) = if self.occurrences {
+            (pool.unix_secs(&format!("{prefix}last_seen_at")), format!("{prefix}occurrence_count"))
+        } else {
+            ("NULL".to_string(), "NULL".to_string())
+        };
+        format!("{reason}, {category}, {last_seen}, {count}")
+    }
+
+    /// Columns after `description` in a bounce `INSERT`.
+    fn insert_columns(self) -> String {
+        let mut columns = String::new();
+        if self.reason {
+            columns.push_str(", reason");
+        }
+        if self.category {
+            columns.push_str(", category");
+        }
+        columns.push_str(", created_at");
+        if self.occurrences {
+            columns.push_str(", last_seen_at, occurrence_count");
+        }
+        columns
+    }
+
+    /// Values matching [`Self::insert_columns`]; `reason` and `category` are
+    /// bound and `at`
+    /// is the DATETIME expression of the bounce.
+    fn insert_values(
+        self,
+        at: &str
+    ) -> String {
+        let mut values = String::new();
+        if self.reason {
+            values.push_str(", ?");
+        }
+        if self.category {
+            values.push_str(", ?");
+        }
+        values.push_str(&format!(", {at}"));
+        if self.occurrences {
+            values.push_str(&format!(", {at}, 1"));
+        }
+        values
+    }
+
+    /// Assignments after `description = ?` when a bounce row is replaced.
+    fn replace_assignments(
+        self,
+        at: &str
+    ) -> String {
+        let mut assignments = String::new();
+        if self.reason {
+            assignments.push_str(", reason = ?");
+        }
+        if self.category {
+            assignments.push_str(", category = ?");
+        }
+        assignments.push_str(&format!(", created_at = {at}"));
+        if self.occurrences {
+            assignments.push_str(&format!(", last_seen_at = {at}, occurrence_count = 1"));
+        }
+        assignments
+    }
+}
+
+/// Optional bounce columns and tables found at startup.
+#[derive(Debug, Clone, Copy)]
+struct SchemaCapabilities {
+    message_bounces: BounceColumns,
+    orphan_bounces: BounceColumns,
+    /// `observer_event_order`; without it observer events apply in arrival
+    /// order.
+    observer_order: bool,
+    /// `mail_bounce_audit`; without it no audit rows are written.
+    bounce_audit: bool,
+    /// `mail_message_bounce_events`; without it no bounce history is kept.
+    bounce_events: bool,
+    /// `mail_messages.tenant`, owned by the application; without it every
+    /// tenant's bounces match messages by hash alone.
+    message_tenant: bool,
+    /// `mail_bounces.tenant`; without it orphan rows keep no tenant.
+    orphan_tenant: bool
+}
+
+impl SchemaCapabilities {
+    const FULL: Self = Self {
+        message_bounces: BounceColumns::ALL,
+        orphan_bounces: BounceColumns::ALL,
+        observer_order: true,
+        bounce_audit: true,
+        bounce_events: true,
+        message_tenant: true,
+        orphan_tenant: true
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionReason {
+    HardBounce,
+    Complaint,
+    /// Only with `suppression.soft_bounce_ttl`; the entry expires after it.
+    SoftBounce
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::HardBounce => "hard_bounce",
+            Self::Complaint => "complaint",
+            Self::SoftBounce => "soft_bounce"
+        }
+    }
+}
+
+/// One row of the `suppressions` table.
+#[derive(Debug, Clone)]
+pub struct Suppression {
+    pub recipient: String,
+    pub reason: String,
+    pub status_code: String,
+    pub hash: Option<String>,
+    pub created_at_unix: i64,
+    pub updated_at_unix: i64,
+    /// Soft-bounce entries only.
+    pub expires_at_unix: Option<i64>,
+    /// Set once the entry was removed or expired; it no longer suppresses.
+    pub removed_at_unix: Option<i64>
+}
+
+impl Suppression {
+    pub fn is_active(&self) -> bool {
+        self.removed_at_unix.is_none()
+    }
+}
+
+type SuppressionRow = (String, String, String, Option<String>, i64, i64, Option<i64>, Option<i64>);
+
+impl From<SuppressionRow> for Suppression {
+    fn from(row: SuppressionRow) -> Self {
+        let (recipient, reason, status_code, hash, created_at_unix, updated_at_unix, ..) = row;
+        Self {
+            recipient,
+            reason,
+            status_code,
+            hash,
+            created_at_unix,
+            updated_at_unix,
+            expires_at_unix: row.6,
+            removed_at_unix: row.7
+        }
+    }
+}
+
+/// What ended or restarted a suppression, as `suppression_audit.action`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionChange {
+    /// Un-suppressed by an operator, e.g. after the recipient confirmed the
+    /// address.
+    Removed,
+    /// Ended by an operator or by `suppression.soft_bounce_ttl`.
+    Expired,
+    /// Suppressed again by a bounce after it was removed or expired.
+    Reactivated
+}
+
+impl SuppressionChange {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Removed => "removed",
+            Self::Expired => "expired",
+            Self::Reactivated => "reactivated"
+        }
+    }
+}
+
+/// `suppression_audit.actor` of soft-bounce entries expired by the sweep.
+const SOFT_BOUNCE_TTL_ACTOR: &str = "soft_bounce_ttl";
+/// `suppression_audit.actor` of entries re-activated by a bounce.
+const BOUNCE_ACTOR: &str = "bounce";
+
+/// One row of the `suppression_audit` table; `reason`, `status_code`,
+/// `hash` and `suppressed_at_unix` are those of the entry before the change.
+#[derive(Debug, Clone)]
+pub struct SuppressionAudit {
+    pub recipient: String,
+    pub action: String,
+    pub actor: String,
+    pub reason: String,
+    pub status_code: String,
+    pub hash: Option<String>,
+    pub suppressed_at_unix: i64,
+    pub created_at_unix: i64
+}
+
+/// Suppression entries of one recipient domain with one reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainSuppressions {
+    pub domain: String,
+    pub reason: String,
+    pub count: i64
+}
+
+impl Database {
+    pub async fn connect(
+        database_url: &str,
+        migrate: MigrateMode,
+        suppression: SuppressionConfig,
+        faults: Arc<Faults>
+    ) -> Result<Self> {
+        let pool = Pool::connect(database_url).await?;
+
+        on_pool!(&pool, fetch_one, sqlx::query_scalar::<_, i64>("SELECT 1"))
+            .context("database ping failed")?;
+
+        info!("database connected: backend={}", pool.backend());
+        apply_migrations(&pool, migrate).await?;
+
+        let mut db = Self {
+            pool,
+            schema: SchemaCapabilities::FULL,
+            suppression,
+            recipients: RecipientNormalizer::default(),
+            classifier: Live::new(BounceClassifier::default()),
+            bounce_dedup_window: None,
+            breaker: DbBreaker::new(DatabaseResilienceConfig::default().breaker_threshold),
+            resilience: DatabaseResilienceConfig::default(),
+            alerts: Arc::new(BounceAlerts::default()),
+            hooks: Arc::new(BounceHooks::default()),
+            batcher: ObserverBatcher::default(),
+            message_ids: MessageIdCache::new(MessageCacheConfig::default(), system_clock()),
+            faults
+        };
+        db.schema = db.probe_schema().await;
+        if db.suppression.enabled {
+            info!(
+                "suppression list enabled: status_codes={}, suppressed={}",
+                db.suppression.status_codes.join(","),
+                db.suppression_count().await?
+            );
+        }
+
+        Ok(db)
+    }
+
+    /// Finds which optional bounce columns exist and logs one warning when
+    /// some are missing, instead of every insert failing on them.
+    async fn probe_schema(&self) -> SchemaCapabilities {
+        let mut schema = SchemaCapabilities::FULL;
+        let mut missing = Vec::new();
+        for (table, columns, tenant) in [
+            ("mail_message_bounces", &mut schema.message_bounces, None),
+            ("mail_bounces", &mut schema.orphan_bounces, Some(&mut schema.orphan_tenant))
+        ] {
+            match self.table_columns(table).await {
+                Ok(found) => {
+                    *columns = BounceColumns::probe(&found);
+                    if let Some(tenant) = tenant
+                        && !found.is_empty()
+                        && !has_column(&found, "tenant")
+                    {
+                        *tenant = false;
+                        missing.push(format!("{table}.tenant"));
+                    }
+                }
+                Err(err) => {
+                    warn!("schema probe failed, assuming full schema: table={table}, error={err:#}")
+                }
+            }
+            missing.extend(columns.missing().into_iter().map(|column| format!("{table}.{column}")));
+        }
+        // Optional on the application's table, so its absence is no
+        // degraded mode.
+        match self.table_columns("mail_messages").await {
+            Ok(found) if !found.is_empty() && !has_column(&found, "tenant") => {
+                schema.message_tenant = false;
+                info!("mail_messages has no tenant column; bounces match messages by hash only");
+            }
+            Ok(_) => {}
+            Err(err) => {
+                warn!(
+                    "schema probe failed, assuming full schema: table=mail_messages, error={err:#}"
+                )
+            }
+        }
+        for (table, present) in [
+            ("observer_event_order", &mut schema.observer_order),
+            ("mail_bounce_audit", &mut schema.bounce_audit),
+            ("mail_message_bounce_events", &mut schema.bounce_events)
+        ] {
+            match self.table_columns(table).await {
+                Ok(found) if found.is_empty() => {
+                    *present = false;
+                    missing.push(table.to_string());
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("schema probe failed, assuming full schema: table={table}, error={err:#}")
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            coded_warn!(
+                ErrorCode::DbSchemaDegraded,
+                "optional schema missing, running degraded: missing={}; repeated bounces rewrite created_at without counting, no reason or category is stored, observer events apply in arrival order, no audit or bounce history rows are written and orphan bounces keep no tenant until the migrations are applied (migrate: auto)",
+                missing.join(",")
+            );
+        }
+        schema
+    }
+
+    /// Skips a bounce already applied within `window` from any ingest path.
+    ///
+    /// Bounces are keyed on hash, status code, action and a hash of the
+    /// normalized diagnostic, recorded in `bounce_dedup` inside the same
+    /// transaction as the bounce itself. `None` applies every copy.
+    pub fn with_bounce_dedup(
+        mut self,
+        window: Option<Duration>
+    ) -> Self {
+        if let Some(window) = window {
+            info!("bounce dedup enabled: window={}", humantime::format_duration(window));
+        }
+        self.bounce_dedup_window = window;
+        self
+    }
+
+    /// Retries and circuit breaker of database writes; see
+    /// [`super::resilience`].
+    pub fn with_resilience(
+        mut self,
+        config: DatabaseResilienceConfig
+    ) -> Self {
+        self.breaker = DbBreaker::new(config.breaker_threshold);
+        self.resilience = config;
+        self
+    }
+
+    /// Queues observer events for [`super::batching::run_observer_batcher`]
+    /// to write in shared transactions once `config` is enabled.
+    pub fn with_batching(
+        mut self,
+        config: DatabaseBatchConfig
+    ) -> Self {
+        self.batcher = ObserverBatcher::new(config);
+        self
+    }
+
+    /// Caches the `mail_messages` id of each hash; see
+    /// [`super::message_cache`].
+    pub fn with_message_cache(
+        mut self,
+        config: MessageCacheConfig,
+        clock: SharedClock
+    ) -> Self {
+        if config.enabled() {
+            info!(
+                "message id cache enabled: max_entries={}, ttl={}, negative_ttl={}",
+                config.max_entries,
+                humantime::format_duration(config.ttl),
+                humantime::format_duration(config.negative_ttl)
+            );
+        }
+        self.message_ids = MessageIdCache::new(config, clock);
+        self
+    }
+
+    pub(crate) fn batcher(&self) -> &ObserverBatcher {
+        &self.batcher
+    }
+
+    pub(crate) fn hooks(&self) -> Arc<BounceHooks> {
+        self.hooks.clone()
+    }
+
+    /// Counts every applied outcome, whichever path it came from, towards
+    /// the `alerts` rules.
+    pub fn with_alerts(
+        mut self,
+        alerts: Arc<BounceAlerts>
+    ) -> Self {
+        self.alerts = alerts;
+        self
+    }
+
+    /// Hands every applied outcome, whichever path it came from, to the
+    /// `hooks` commands.
+    pub fn with_hooks(
+        mut self,
+        hooks: Arc<BounceHooks>
+    ) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Normalizes the recipient of every bounce and observer event before
+    /// it is written; see [`RecipientNormalizer`].
+    pub fn with_recipients(
+        mut self,
+        config: RecipientsConfig
+    ) -> Self {
+        self.recipients = RecipientNormalizer::new(config);
+        if self.recipients.folds_plus_tags() || self.recipients.alias_count() > 0 {
+            info!(
+                "recipient folding enabled: plus_tags={}, aliases={}",
+                self.recipients.folds_plus_tags(),
+                self.recipients.alias_count()
+            );
+        }
+        self
+    }
+
+    pub fn resilience(&self) -> &DatabaseResilienceConfig {
+        &self.resilience
+    }
+
+    pub fn breaker(&self) -> &DbBreaker {
+        &self.breaker
+    }
+
+    /// Adds the configured `classification.rules` to the built-in ones that
+    /// pick the stored bounce `category`.
+    pub fn with_classification(
+        self,
+        config: ClassificationConfig
+    ) -> Self {
+        self.set_classification(config);
+        self
+    }
+
+    /// Replaces the configured classification rules (config reload).
+    pub fn set_classification(
+        &self,
+        config: ClassificationConfig
+    ) {
+        let classifier = BounceClassifier::new(config);
+        if classifier.rule_count() > 0 {
+            info!("bounce classification rules loaded: rules={}", classifier.rule_count());
+        }
+        self.classifier.store(classifier);
+    }
+
+    pub fn bounce_dedup_window(&self) -> Option<Duration> {
+        self.bounce_dedup_window
+    }
+
+    /// Connection counts of the pool and breaker state, for the status frame.
+    pub fn pool_status(&self) -> DbPoolStatus {
+        DbPoolStatus { breaker_open: self.breaker.is_open(), ..self.pool.status() }
+    }
+
+    pub fn suppression_enabled(&self) -> bool {
+        self.suppression.enabled
+    }
+
+    /// True when `mail_messages` has a row for `hash`, of `tenant` when
+    /// given; see [`Self::local_message_id`].
+    pub async fn has_local_message(
+        &self,
+        hash: &str,
+        tenant: Option<&str>
+    ) -> Result<bool> {
+        let tenant = self.message_tenant(tenant);
+        let sql = match tenant {
+            Some(_) => "SELECT 1 FROM mail_messages WHERE hash = ? AND tenant = ? LIMIT 1",
+            None => "SELECT 1 FROM mail_messages WHERE hash = ? LIMIT 1"
+        };
+        self.resilient("has_local_message", || async {
+            let found = on_pool!(&self.pool, fetch_optional, {
+                let query = sqlx::query_scalar::<_, i64>(sql).bind(hash);
+                match tenant {
+                    Some(tenant) => query.bind(tenant),
+                    None => query
+                }
+            })
+            .context("failed to query mail_messages")?;
+            Ok(found.is_some())
+        })
+        .await
+        .inspect(|found| {
+            if *found {
+                self.message_ids.forget_missing(hash, tenant);
+            }
+        })
+    }
+
+    /// `SELECT 1` through the pool; used by the health check.
+    pub async fn ping(&self) -> Result<()> {
+        on_pool!(&self.pool, fetch_one, sqlx::query_scalar::<_, i64>("SELECT 1"))
+            .context("database ping failed")?;
+        Ok(())
+    }
+
+    /// Runs `attempt` until it succeeds, fails with a non-transient error or
+    /// runs out of `database_resilience.retries`. Fails fast with
+    /// [`DatabaseUnavailable`] while the breaker is open; an operation out of
+    /// retries counts towards opening it.
+    async fn resilient<T, F, Fut>(
+        &self,
+        op: &'static str,
+        mut attempt: F
+    ) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>
+    {
+        if self.breaker.is_open() {
+            return Err(DatabaseUnavailable).context(op);
+        }
+
+        let mut backoff = Backoff::new(self.resilience.retry_base, self.resilience.retry_max);
+        let mut retries = 0;
+        loop {
+            match attempt().await {
+                Ok(value) => {
+                    self.breaker.record_success();
+                    return Ok(value);
+                }
+                Err(err) if is_transient(&err) && retries < self.resilience.retries => {
+                    retries += 1;
+                    let delay = backoff.next_delay();
+                    warn!(
+                        "transient database error, retrying: op={}, retry={}/{}, delay_ms={}, error={err:#}",
+                        op,
+                        retries,
+                        self.resilience.retries,
+                        delay.as_millis()
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(err) => {
+                    if is_transient(&err) {
+                        self.breaker.record_failure(op, &err);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Returns the stored state for `hash`: the local message status plus the
+    /// latest bounce row (tracked or orphan).
+    ///
+    /// With a `tenant`, only that tenant's message and orphan row are
+    /// looked at; without one the hash alone matches, as for bounces.
+    pub async fn message_state(
+        &self,
+        hash: &str,
+        tenant: Option<&str>
+    ) -> Result<MessageState> {
+        let message_tenant = self.message_tenant(tenant);
+        let sql = format!(
+            "SELECT id, CAST(status AS SIGNED), {} FROM mail_messages WHERE hash = ?{} LIMIT 1",
+            self.pool.unix_secs("updated_at"),
+            if message_tenant.is_some() { " AND tenant = ?" } else { "" }
+        );
+        let message = on_pool!(&self.pool, fetch_optional, {
+            let query = sqlx::query_as::<_, (u32, i64, Option<i64>)>(&sql).bind(hash);
+            match message_tenant {
+                Some(tenant) => query.bind(tenant),
+                None => query
+            }
+        })
+        .context("failed to query mail_messages")?;
+        let orphan_tenant = tenant.filter(|_| self.schema.orphan_tenant);
+        let orphan_sql = format!(
+            "SELECT recipient, action, status_code, description, {}, {} FROM mail_bounces WHERE hash = ?{} LIMIT 1",
+            self.pool.unix_secs("created_at"),
+            self.schema.orphan_bounces.select(&self.pool, ""),
+            if orphan_tenant.is_some() { " AND tenant = ?" } else { "" }
+        );
+
+        let bounce = match message {
+            Some((message_id, _, _)) => on_pool!(
+                &self.pool,
+                fetch_optional,
+                sqlx::query_as::<_, (Option<String>, String, Option<String>, i64, Option<String>, Option<String>, Option<i64>, Option<u32>)>(
+                    &format!(
+                        "SELECT action, status_code, description, {}, {} FROM mail_message_bounces WHERE message_id = ? LIMIT 1",
+                        self.pool.unix_secs("created_at"),
+                        self.schema.message_bounces.select(&self.pool, "")
+                    ),
+                )
+                .bind(message_id)
+            )
+            .context("failed to query mail_message_bounces")?
+            .map(|(action, status_code, description, created_at_unix, reason, category, last_seen_unix, occurrence_count)| BounceRecord {
+                hash: hash.to_string(),
+                tracked: true,
+                recipient: None,
+                action,
+                status_code,
+                description,
+                reason,
+                category,
+                created_at_unix,
+                last_seen_unix,
+                occurrence_count
+            }),
+            None => on_pool!(&self.pool, fetch_optional, {
+                let query = sqlx::query_as::<
+                    _,
+                    (Option<String>, Option<String>, String, Option<String>, i64, Option<String>, Option<String>, Option<i64>, Option<u32>)
+                >(&orphan_sql)
+                .bind(hash);
+                match orphan_tenant {
+                    Some(tenant) => query.bind(tenant),
+                    None => query
+                }
+            })
+            .context("failed to query mail_bounces")?
+            .map(|(recipient, action, status_code, description, created_at_unix, reason, category, last_seen_unix, occurrence_count)| BounceRecord {
+                hash: hash.to_string(),
+                tracked: false,
+                recipient,
+                action,
+                status_code,
+                description,
+                reason,
+                category,
+                created_at_unix,
+                last_seen_unix,
+                occurrence_count
+            })
+        };
+
+        let history = match message {
+            Some((message_id, _, _)) => self.bounce_history(message_id).await?,
+            None => Vec::new()
+        };
+
+        Ok(MessageState {
+            hash: hash.to_string(),
+            message_id: message.map(|(id, _, _)| id),
+            mail_status: message.map(|(_, status, _)| status as i32),
+            updated_at_unix: message.and_then(|(_, _, updated_at)| updated_at),
+            bounce,
+            history
+        })
+    }
+
+    /// Returns tracked and orphan bounces recorded within the last
+    /// `since_secs` seconds, newest first, capped at `limit` rows.
+    pub async fn recent_bounces(
+        &self,
+        since_secs: u64,
+        limit: u32
+    ) -> Result<Vec<BounceRecord>> {
+        // SQLite has no unsigned 64-bit binds.
+        let since_secs = i64::try_from(since_secs).unwrap_or(i64::MAX);
+        let tracked = on_pool!(
+            &self.pool,
+            fetch_all,
+            sqlx::query_as::<
+                _,
+                (String, Option<String>, String, Option<String>, i64, Option<String>, Option<String>, Option<i64>, Option<u32>)
+            >(
+                &format!(
+                    "SELECT m.hash, b.action, b.status_code, b.description, {}, {} FROM mail_message_bounces b JOIN mail_messages m ON m.id = b.message_id WHERE {} ORDER BY b.created_at DESC LIMIT ?",
+                    self.pool.unix_secs("b.created_at"),
+                    self.schema.message_bounces.select(&self.pool, "b."),
+                    self.pool.within_secs("b.created_at")
+                ),
+            )
+            .bind(since_secs)
+            .bind(limit)
+        )
+        .context("failed to query recent mail_message_bounces")?;
+
+        let orphans = on_pool!(
+            &self.pool,
+            fetch_all,
+            sqlx::query_as::<
+                _,
+                (
+                    String,
+                    Option<String>,
+                    Option<String>,
+                    String,
+                    Option<String>,
+                    i64,
+                    Option<String>,
+                    Option<String>,
+                    Option<i64>,
+                    Option<u32>
+                )
+            >(
+                &format!(
+                    "SELECT hash, recipient, action, status_code, description, {}, {} FROM mail_bounces WHERE {} ORDER BY created_at DESC LIMIT ?",
+                    self.pool.unix_secs("created_at"),
+                    self.schema.orphan_bounces.select(&self.pool, ""),
+                    self.pool.within_secs("created_at")
+                ),
+            )
+            .bind(since_secs)
+            .bind(limit)
+        )
+        .context("failed to query recent mail_bounces")?;
+
+        let mut bounces = tracked
+            .into_iter()
+            .map(
+                |(
+                    hash,
+                    action,
+                    status_code,
+                    description,
+                    created_at_unix,
+                    reason,
+                    category,
+                    last_seen_unix,
+                    occurrence_count
+                )| BounceRecord {
+                    hash,
+                    tracked: true,
+                    recipient: None,
+                    action,
+                    status_code,
+                    description,
+                    reason,
+                    category,
+                    created_at_unix,
+                    last_seen_unix,
+                    occurrence_count
+                }
+            )
+            .chain(orphans.into_iter().map(
+                |(
+                    hash,
+                    recipient,
+                    action,
+                    status_code,
+                    description,
+                    created_at_unix,
+                    reason,
+                    category,
+                    last_seen_unix,
+                    occurrence_count
+                )| {
+                    BounceRecord {
+                        hash,
+                        tracked: false,
+                        recipient,
+                        action,
+                        status_code,
+                        description,
+                        reason,
+                        category,
+                        created_at_unix,
+                        last_seen_unix,
+                        occurrence_count
+                    }
+                }
+            ))
+            .collect::<Vec<_>>();
+
+        bounces.sort_by_key(|bounce| Reverse(bounce.created_at_unix));
+        bounces.truncate(limit as usize);
+        Ok(bounces)
+    }
+
+    /// Counts tracked and orphan bounce rows written within the last `since_secs` seconds.
+    pub async fn bounce_count_since(
+        &self,
+        since_secs: u64
+    ) -> Result<i64> {
+        let since_secs = i64::try_from(since_secs).unwrap_or(i64::MAX);
+        let window = self.pool.within_secs("created_at");
+        on_pool!(
+            &self.pool,
+            fetch_one,
+            sqlx::query_scalar::<_, i64>(
+                &format!(
+                    "SELECT CAST((SELECT COUNT(*) FROM mail_message_bounces WHERE {window}) + (SELECT COUNT(*) FROM mail_bounces WHERE {window}) AS SIGNED)"
+                ),
+            )
+            .bind(since_secs)
+            .bind(since_secs)
+        )
+        .context("failed to count recent bounces")
+    }
+
+    /// Id of the `mail_messages` row of `parsed.hash` inside `tx`. A bounce
+    /// with a tenant only matches a message of that tenant, once the table
+    /// has a `tenant` column; hashes are only unique within a tenant.
+    async fn local_message_id(
+        &self,
+        tx: &mut Tx,
+        parsed: &ParsedBounce
+    ) -> Result<Option<u32>> {
+        let tenant = self.message_tenant(parsed.tenant.as_deref());
+        if let Some(cached) = self.message_ids.get(&parsed.hash, tenant) {
+            return Ok(cached);
+        }
+        let sql = match tenant {
+            Some(_) => "SELECT id FROM mail_messages WHERE hash = ? AND tenant = ? LIMIT 1",
+            None => "SELECT id FROM mail_messages WHERE hash = ? LIMIT 1"
+        };
+        let id = on_tx!(tx, fetch_optional, {
+            let query = sqlx::query_scalar::<_, u32>(sql).bind(&parsed.hash);
+            match tenant {
+                Some(tenant) => query.bind(tenant),
+                None => query
+            }
+        })
+        .context("failed to query mail_messages")?;
+        self.message_ids.insert(&parsed.hash, tenant, id);
+        Ok(id)
+    }
+
+    /// Sets `status` on the `mail_messages` row of `parsed` inside `tx` and
+    /// returns its id; `None` without such a row. An update that touches no
+    /// row may come from a cached id whose row was deleted, so the id is
+    /// dropped and looked up again instead of losing the event.
+    async fn update_local_message(
+        &self,
+        tx: &mut Tx,
+        parsed: &ParsedBounce,
+        status: i32
+    ) -> Result<Option<u32>> {
+        let Some(message_id) = self.local_message_id(tx, parsed).await? else {
+            return Ok(None);
+        };
+        if self.set_message_status(tx, message_id, status).await? > 0 {
+            return Ok(Some(message_id));
+        }
+        self.forget_message_id(&parsed.hash, parsed.tenant.as_deref());
+        let current = self.local_message_id(tx, parsed).await?;
+        match current {
+            Some(current) if current != message_id => {
+                debug!(
+                    "db message id was stale: hash={}, stale_id={}, id={}",
+                    parsed.hash, message_id, current
+                );
+                self.set_message_status(tx, current, status).await?;
+            }
+            // Same row: MySQL counts only rows the update changed.
+            Some(_) => {}
+            None => debug!("db message id was stale: hash={}, stale_id={}", parsed.hash, message_id)
+        }
+        Ok(current)
+    }
+
+    async fn set_message_status(
+        &self,
+        tx: &mut Tx,
+        message_id: u32,
+        status: i32
+    ) -> Result<u64> {
+        on_tx!(
+            tx,
+            execute,
+            sqlx::query(
+                "UPDATE mail_messages SET status = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?"
+            )
+            .bind(status)
+            .bind(message_id)
+        )
+        .context("failed to update mail_messages")
+    }
+
+    /// Tenant messages of `tenant` are matched by; `None` without a
+    /// `mail_messages.tenant` column.
+    fn message_tenant<'a>(
+        &self,
+        tenant: Option<&'a str>
+    ) -> Option<&'a str> {
+        tenant.filter(|_| self.schema.message_tenant)
+    }
+
+    /// Drops the cached message id of `hash` after a failed write, in case
+    /// the write failed because the id is stale.
+    fn forget_message_id(
+        &self,
+        hash: &str,
+        tenant: Option<&str>
+    ) {
+        self.message_ids.forget(hash, self.message_tenant(tenant));
+    }
+
+    /// Records an auto-reply as a `mail_message_bounces` row with
+    /// `action = autoreply`.
+    ///
+    /// Never changes `mail_messages.status` and never overwrites a real bounce
+    /// row. Auto-replies for unknown hashes are skipped (no `mail_bounces` row).
+    async fn record_autoreply(
+        &self,
+        tx: &mut Tx,
+        parsed: &ParsedBounce
+    ) -> Result<UpsertBounceOutcome> {
+        let message_id = self.local_message_id(tx, parsed).await?;
+
+        let Some(message_id) = message_id else {
+            debug!("db autoreply: op=skip, hash={}, reason=missing_local_message", parsed.hash);
+            return Ok(UpsertBounceOutcome::MissingLocalMessage);
+        };
+
+        let existing_action = on_tx!(
+            tx,
+            fetch_optional,
+            sqlx::query_scalar::<_, Option<String>>(
+                "SELECT action FROM mail_message_bounces WHERE message_id = ? LIMIT 1"
+            )
+            .bind(message_id)
+        )
+        .context("failed to query mail_message_bounces")?;
+
+        match existing_action {
+            Some(action) if action.as_deref() != Some(ReportKind::Autoreply.as_str()) => {
+                debug!(
+                    "db autoreply: op=skip, message_id={}, hash={}, reason=bounce_row_exists, action={}",
+                    message_id,
+                    parsed.hash,
+                    action.as_deref().unwrap_or("-")
+                );
+            }
+            _ => self.record_message_bounce(tx, message_id, parsed, None).await?
+        }
+
+        Ok(UpsertBounceOutcome::UpdatedLocalMessage)
+    }
+
+    /// Returns the number of suppressed recipients; removed and expired
+    /// entries do not count.
+    pub async fn suppression_count(&self) -> Result<i64> {
+        on_pool!(
+            &self.pool,
+            fetch_one,
+            sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM suppressions WHERE removed_at IS NULL"
+            )
+        )
+        .context("failed to count suppressions")
+    }
+
+    /// Returns active suppression entries, most recently refreshed first,
+    /// capped at `limit` rows.
+    pub async fn list_suppressions(
+        &self,
+        limit: u32
+    ) -> Result<Vec<Suppression>> {
+        let sql = format!(
+            "SELECT {} FROM suppressions WHERE removed_at IS NULL ORDER BY updated_at DESC, id DESC LIMIT ?",
+            self.suppression_columns()
+        );
+        let rows =
+            on_pool!(&self.pool, fetch_all, sqlx::query_as::<_, SuppressionRow>(&sql).bind(limit))
+                .context("failed to list suppressions")?;
+
+        Ok(rows.into_iter().map(Suppression::from).collect())
+    }
+
+    /// Returns `suppression_audit` rows, newest first, capped at `limit`.
+    pub async fn list_suppression_audit(
+        &self,
+        limit: u32
+    ) -> Result<Vec<SuppressionAudit>> {
+        let sql = format!(
+            "SELECT recipient, action, actor, reason, status_code, hash, {}, {} FROM suppression_audit ORDER BY id DESC LIMIT ?",
+            self.pool.unix_secs("suppressed_at"),
+            self.pool.unix_secs("created_at")
+        );
+        let rows = on_pool!(
+            &self.pool,
+            fetch_all,
+            sqlx::query_as::<_, (String, String, String, String, String, Option<String>, i64, i64)>(
+                &sql
+            )
+            .bind(limit)
+        )
+        .context("failed to list suppression_audit")?;
+
+        Ok(rows
+            .into_iter()
+            .map(
+                |(
+                    recipient,
+                    action,
+                    actor,
+                    reason,
+                    status_code,
+                    hash,
+                    suppressed_at_unix,
+                    created_at_unix
+                )| SuppressionAudit {
+                    recipient,
+                    action,
+                    actor,
+                    reason,
+                    status_code,
+                    hash,
+                    suppressed_at_unix,
+                    created_at_unix
+                }
+            )
+            .collect())
+    }
+
+    /// Counts active suppression entries per recipient domain and reason.
+    pub async fn suppressions_by_domain(&self) -> Result<Vec<DomainSuppressions>> {
+        let domain = self.pool.address_domain("recipient");
+        let sql = format!(
+            "SELECT {domain}, reason, COUNT(*) FROM suppressions WHERE removed_at IS NULL GROUP BY {domain}, reason ORDER BY {domain}, reason"
+        );
+        let rows =
+            on_pool!(&self.pool, fetch_all, sqlx::query_as::<_, (String, String, i64)>(&sql))
+                .context("failed to count suppressions per domain")?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(domain, reason, count)| DomainSuppressions { domain, reason, count })
+            .collect())
+    }
+
+    /// Un-suppresses `recipient` (compared case-insensitively) on behalf of
+    /// `actor` and returns the entry as it was, or `None` when it was not
+    /// suppressed. The row is kept, marked removed, and audited.
+    pub async fn remove_suppression(
+        &self,
+        recipient: &str,
+        actor: &str
+    ) -> Result<Option<Suppression>> {
+        self.end_suppression(recipient, SuppressionChange::Removed, actor).await
+    }
+
+    /// Like [`Self::remove_suppression`], audited as an expiry.
+    pub async fn expire_suppression(
+        &self,
+        recipient: &str,
+        actor: &str
+    ) -> Result<Option<Suppression>> {
+        self.end_suppression(recipient, SuppressionChange::Expired, actor).await
+    }
+
+    async fn end_suppression(
+        &self,
+        recipient: &str,
+        change: SuppressionChange,
+        actor: &str
+    ) -> Result<Option<Suppression>> {
+        let mut tx = self.pool.begin().await.context("failed to begin transaction")?;
+        let Some(existing) =
+            self.fetch_suppression(&mut tx, recipient).await?.filter(Suppression::is_active)
+        else {
+            return Ok(None);
+        };
+        self.mark_suppression_removed(&mut tx, &existing, change, actor).await?;
+        tx.commit().await.context("failed to commit transaction")?;
+        Ok(Some(existing))
+    }
+
+    /// Expires soft-bounce entries past their `expires_at`; returns the
+    /// count.
+    pub async fn expire_soft_suppressions(&self) -> Result<u64> {
+        let mut tx = self.pool.begin().await.context("failed to begin transaction")?;
+        let sql = format!(
+            "SELECT {} FROM suppressions WHERE removed_at IS NULL AND expires_at <= CURRENT_TIMESTAMP",
+            self.suppression_columns()
+        );
+        let expired = on_tx!(&mut tx, fetch_all, sqlx::query_as::<_, SuppressionRow>(&sql))
+            .context("failed to query expired suppressions")?;
+        for entry in expired.iter().cloned().map(Suppression::from) {
+            self.mark_suppression_removed(
+                &mut tx,
+                &entry,
+                SuppressionChange::Expired,
+                SOFT_BOUNCE_TTL_ACTOR
+            )
+            .await?;
+            info!(
+                "soft-bounce suppression expired: recipient={}, status_code={}, suppressed_since_unix={}",
+                entry.recipient, entry.status_code, entry.created_at_unix
+            );
+        }
+        tx.commit().await.context("failed to commit transaction")?;
+        Ok(expired.len() as u64)
+    }
+
+    /// Column names of `table` in the connected schema; empty when the table
+    /// does not exist.
+    pub async fn table_columns(
+        &self,
+        table: &str
+    ) -> Result<Vec<String>> {
+        let sql = match self.pool {
+            Pool::MySql(_) => {
+                "SELECT CAST(COLUMN_NAME AS CHAR) FROM information_schema.COLUMNS WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ?"
+            }
+            Pool::Sqlite(_) => "SELECT name FROM pragma_table_info(?)"
+        };
+        on_pool!(&self.pool, fetch_all, sqlx::query_scalar::<_, String>(sql).bind(table))
+            .with_context(|| format!("failed to read columns of {table}"))
+    }
+
+    /// Columns that lead at least one index on `table`.
+    pub async fn indexed_columns(
+        &self,
+        table: &str
+    ) -> Result<Vec<String>> {
+        let sql = match self.pool {
+            Pool::MySql(_) => {
+                "SELECT DISTINCT CAST(COLUMN_NAME AS CHAR) FROM information_schema.STATISTICS WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND SEQ_IN_INDEX = 1"
+            }
+            Pool::Sqlite(_) => {
+                "SELECT DISTINCT info.name FROM pragma_index_list(?) AS list, pragma_index_info(list.name) AS info WHERE info.seqno = 0"
+            }
+        };
+        on_pool!(&self.pool, fetch_all, sqlx::query_scalar::<_, String>(sql).bind(table))
+            .with_context(|| format!("failed to read indexes of {table}"))
+    }
+
+    /// Current time of the database server as unix seconds.
+    pub async fn unix_time(&self) -> Result<i64> {
+        on_pool!(
+            &self.pool,
+            fetch_one,
+            sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT {}",
+                self.pool.unix_secs("CURRENT_TIMESTAMP")
+            ))
+        )
+        .context("failed to read database time")
+    }
+
+    /// Applies a delivery update emitted by observer/journal publishers.
+    ///
+    /// Behavior:
+    /// - Skips events whose `sequence` is not newer than the last one applied
+    ///   for the same source and queue id (see [`Database::claim_observer_sequence`]).
+    /// - Resolves the local `mail_messages.id` by `event.hash`.
+    /// - If no local message exists, this is a no-op (warn log + commit).
+    /// - If found, updates `mail_messages.status` and `updated_at`.
+    /// - For non-success outcomes, upserts a row in `mail_message_bounces`
+    ///   for the resolved message with latest action/status/description.
+    ///
+    /// All writes are performed in a single transaction, retried as a whole
+    /// on transient errors (see [`Database::resilient`]). With
+    /// `database_batch` set, the transaction is shared with other queued
+    /// events (see [`super::batching`]); the outcome is still this event's.
+    pub async fn apply_observer_event(
+        &self,
+        event: &DeliveryEvent
+    ) -> Result<ObserverEventOutcome> {
+        let event = self.recipients.event(event);
+        let outcome = match self.batcher.submit(event.clone()).await {
+            Some(outcome) => outcome?,
+            None => {
+                let events = std::slice::from_ref(&event);
+                self.apply_observer_events(events).await?.remove(0)
+            }
+        };
+        if outcome.applied() {
+            let parsed = ParsedBounce::from(&event);
+            self.alerts.observe(&parsed);
+            self.hooks.notify("observer", outcome.as_str(), &parsed);
+        }
+        Ok(outcome)
+    }
+
+    /// Applies `events` in one transaction, retried as a whole on transient
+    /// errors; one outcome per event, in order.
+    pub(crate) async fn apply_observer_events(
+        &self,
+        events: &[DeliveryEvent]
+    ) -> Result<Vec<ObserverEventOutcome>> {
+        let outcomes = self
+            .resilient("apply_observer_event", || self.try_apply_observer_events(events))
+            .await?;
+        for (event, outcome) in events.iter().zip(&outcomes) {
+            log_observer_outcome(event, *outcome);
+        }
+        Ok(outcomes)
+    }
+
+    async fn try_apply_observer_events(
+        &self,
+        events: &[DeliveryEvent]
+    ) -> Result<Vec<ObserverEventOutcome>> {
+        self.faults.delay_db().await;
+        self.faults.check_db_write().map_err(sqlx::Error::Io)?;
+
+        let mut tx = self.pool.begin().await?;
+        let mut outcomes = Vec::with_capacity(events.len());
+        for event in events {
+            let outcome = self.apply_observer_event_in(&mut tx, event).await.inspect_err(|_| {
+                self.forget_message_id(&event.hash, event.tenant.as_deref());
+            })?;
+            outcomes.push(outcome);
+        }
+        tx.commit().await?;
+        Ok(outcomes)
+    }
+
+    /// Writes of one observer event inside `tx`.
+    async fn apply_observer_event_in(
+        &self,
+        tx: &mut Tx,
+        event: &DeliveryEvent
+    ) -> Result<ObserverEventOutcome> {
+        let parsed = ParsedBounce::from(event);
+        let message_status = map_mail_message_status(&parsed);
+
+        if !self.claim_observer_sequence(tx, event).await? {
+            return Ok(ObserverEventOutcome::OutOfOrder);
+        }
+        if self.is_duplicate(tx, &parsed).await? {
+            return Ok(ObserverEventOutcome::Duplicate);
+        }
+
+        let message_id = self.update_local_message(tx, &parsed, message_status).await?;
+
+        let Some(message_id) = message_id else {
+            self.record_suppression(tx, &parsed, message_status).await?;
+            return Ok(ObserverEventOutcome::Unlinked);
+        };
+
+        let occurred_at = (event.observed_at_unix > 0).then_some(event.observed_at_unix);
+        self.record_bounce_event(tx, message_id, &parsed, occurred_at).await?;
+        if message_status != MAIL_STATUS_SUCCESS {
+            self.record_message_bounce(tx, message_id, &parsed, occurred_at).await?;
+        }
+
+        self.record_suppression(tx, &parsed, message_status).await?;
+        Ok(ObserverEventOutcome::Applied)
+    }
+
+    pub async fn upsert_bounce(
+        &self,
+        parsed: &ParsedBounce
+    ) -> Result<UpsertBounceOutcome> {
+        let parsed = &self.recipients.bounce(parsed);
+        let outcome = self.resilient("upsert_bounce", || self.try_upsert_bounce(parsed)).await?;
+        if outcome != UpsertBounceOutcome::Duplicate {
+            self.alerts.observe(parsed);
+            self.hooks.notify("bounce", outcome.as_str(), parsed);
+        }
+        Ok(outcome)
+    }
+
+    async fn try_upsert_bounce(
+        &self,
+        parsed: &ParsedBounce
+    ) -> Result<UpsertBounceOutcome> {
+        self.faults.delay_db().await;
+        self.faults.check_db_write().map_err(sqlx::Error::Io)?;
+
+        let mut tx = self.pool.begin().await?;
+        let outcome = self.apply_bounce(&mut tx, parsed).await.inspect_err(|_| {
+            self.forget_message_id(&parsed.hash, parsed.tenant.as_deref());
+        })?;
+        tx.commit().await?;
+        Ok(outcome)
+    }
+
+    /// Same as [`Database::upsert_bounce`], but records `idempotency_key` in
+    /// `processed_spool_messages` within the same transaction.
+    ///
+    /// Returns `None` without touching any other table when the key was
+    /// already recorded, so a spool file replayed after a crash or a failed
+    /// finalize rename is not applied twice.
+    pub async fn upsert_bounce_once(
+        &self,
+        parsed: &ParsedBounce,
+        idempotency_key: &str
+    ) -> Result<Option<UpsertBounceOutcome>> {
+        let parsed = &self.recipients.bounce(parsed);
+        let outcome = self
+            .resilient("upsert_bounce_once", || {
+                self.try_upsert_bounce_once(parsed, idempotency_key)
+            })
+            .await?;
+        if let Some(outcome) = outcome.filter(|outcome| *outcome != UpsertBounceOutcome::Duplicate)
+        {
+            self.alerts.observe(parsed);
+            self.hooks.notify("bounce", outcome.as_str(), parsed);
+        }
+        Ok(outcome)
+    }
+
+    async fn try_upsert_bounce_once(
+        &self,
+        parsed: &ParsedBounce,
+        idempotency_key: &str
+    ) -> Result<Option<UpsertBounceOutcome>> {
+        self.faults.delay_db().await;
+        self.faults.check_db_write().map_err(sqlx::Error::Io)?;
+
+        let mut tx = self.pool.begin().await?;
+
+        // SQLite transactions already hold the database write lock.
+        let sql = match self.pool {
+            Pool::MySql(_) => {
+                "SELECT 1 FROM processed_spool_messages WHERE idempotency_key = ? LIMIT 1 FOR UPDATE"
+            }
+            Pool::Sqlite(_) => {
+                "SELECT 1 FROM processed_spool_messages WHERE idempotency_key = ? LIMIT 1"
+            }
+        };
+        let seen = on_tx!(
+            &mut tx,
+            fetch_optional,
+            sqlx::query_scalar::<_, i64>(sql).bind(idempotency_key)
+        )
+        .context("failed to query processed_spool_messages")?;
+
+        if seen.is_some() {
+            tx.commit().await?;
+            debug!(
+                "db upsert: op=skip, hash={}, idempotency_key={}, reason=already_processed",
+                parsed.hash, idempotency_key
+            );
+            return Ok(None);
+        }
+
+        let outcome = self.apply_bounce(&mut tx, parsed).await.inspect_err(|_| {
+            self.forget_message_id(&parsed.hash, parsed.tenant.as_deref());
+        })?;
+
+        on_tx!(
+            &mut tx,
+            execute,
+            sqlx::query(
+                "INSERT INTO processed_spool_messages (idempotency_key, hash, processed_at) VALUES (?, ?, CURRENT_TIMESTAMP)"
+            )
+            .bind(idempotency_key)
+            .bind(&parsed.hash)
+        )
+        .context("failed to insert processed_spool_messages")?;
+
+        tx.commit().await?;
+        Ok(Some(outcome))
+    }
+
+    /// Applies one parsed bounce or auto-reply inside `tx`; the caller commits.
+    async fn apply_bounce(
+        &self,
+        tx: &mut Tx,
+        parsed: &ParsedBounce
+    ) -> Result<UpsertBounceOutcome> {
+        if self.is_duplicate(tx, parsed).await? {
+            debug!(
+                "db upsert: op=skip, hash={}, status_code={}, reason=duplicate",
+                parsed.hash, parsed.status_code
+            );
+            return Ok(UpsertBounceOutcome::Duplicate);
+        }
+        if parsed.kind == ReportKind::Autoreply {
+            return self.record_autoreply(tx, parsed).await;
+        }
+
+        let message_status = map_mail_message_status(parsed);
+        let message_id = self.update_local_message(tx, parsed, message_status).await?;
+
+        if let Some(message_id) = message_id {
+            debug!("db upsert mail_messages: op=update, hash={}, id={}", parsed.hash, message_id);
+            self.record_bounce_event(tx, message_id, parsed, None).await?;
+
+            if message_status != MAIL_STATUS_SUCCESS {
+                self.record_message_bounce(tx, message_id, parsed, None).await?;
+            }
+        } else {
+            warn!(
+                "bounce hash not found in local mail_messages: hash={}, status_code={}, action={}",
+                parsed.hash,
+                parsed.status_code,
+                parsed.action.as_deref().unwrap_or("-")
+            );
+
+            let message_status = map_mail_message_status(parsed);
+            if message_status == MAIL_STATUS_SUCCESS {
+                debug!(
+                    "db upsert mail_bounces: op=skip, hash={}, reason=missing_local_message_and_success_status",
+                    parsed.hash
+                );
+                return Ok(UpsertBounceOutcome::MissingLocalMessage);
+            }
+
+            self.record_orphan_bounce(tx, parsed).await?;
+        }
+
+        self.record_suppression(tx, parsed, map_mail_message_status(parsed)).await?;
+
+        Ok(if message_id.is_some() {
+            UpsertBounceOutcome::UpdatedLocalMessage
+        } else {
+            UpsertBounceOutcome::MissingLocalMessage
+        })
+    }
+
+    /// True when `parsed` was applied within the dedup window; otherwise
+    /// records its key in `bounce_dedup` inside `tx`. Always false without a
+    /// window.
+    async fn is_duplicate(
+        &self,
+        tx: &mut Tx,
+        parsed: &ParsedBounce
+    ) -> Result<bool> {
+        let Some(window) = self.bounce_dedup_window else {
+            return Ok(false);
+        };
+        let window_secs = i64::try_from(window.as_secs()).unwrap_or(i64::MAX);
+        let key = bounce_dedup_key(parsed);
+
+        let fresh = on_tx!(
+            tx,
+            fetch_optional,
+            sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT CAST(({}) AS SIGNED) FROM bounce_dedup WHERE dedup_key = ?",
+                self.pool.within_secs("seen_at")
+            ))
+            .bind(window_secs)
+            .bind(&key)
+        )
+        .context("failed to query bounce_dedup")?;
+
+        let duplicate = match fresh {
+            Some(1) => true,
+            Some(_) => {
+                on_tx!(
+                    tx,
+                    execute,
+                    sqlx::query(
+                        "UPDATE bounce_dedup SET hash = ?, seen_at = CURRENT_TIMESTAMP WHERE dedup_key = ?"
+                    )
+                    .bind(&parsed.hash)
+                    .bind(&key)
+                )
+                .context("failed to update bounce_dedup")?;
+                false
+            }
+            // A concurrent copy that inserted first wins; this one is the duplicate.
+            None => {
+                let sql = match self.pool {
+                    Pool::MySql(_) => {
+                        "INSERT IGNORE INTO bounce_dedup (dedup_key, hash, seen_at) VALUES (?, ?, CURRENT_TIMESTAMP)"
+                    }
+                    Pool::Sqlite(_) => {
+                        "INSERT OR IGNORE INTO bounce_dedup (dedup_key, hash, seen_at) VALUES (?, ?, CURRENT_TIMESTAMP)"
+                    }
+                };
+                let rows = on_tx!(tx, execute, sqlx::query(sql).bind(&key).bind(&parsed.hash))
+                    .context("failed to insert bounce_dedup")?;
+                rows == 0
+            }
+        };
+        Ok(duplicate)
+    }
+
+    /// Records `event.sequence` as the latest of its source and queue id
+    /// inside `tx`; false when an event with the same or a later sequence was
+    /// already applied. A retried publisher resends the same sequence, so a
+    /// replay is skipped here too.
+    ///
+    /// Events without a sequence, or without the table, always pass.
+    async fn claim_observer_sequence(
+        &self,
+        tx: &mut Tx,
+        event: &DeliveryEvent
+    ) -> Result<bool> {
+        let Some(sequence) = event.sequence else {
+            return Ok(true);
+        };
+        if !self.schema.observer_order {
+            return Ok(true);
+        }
+        let sequence = i64::try_from(sequence).unwrap_or(i64::MAX);
+
+        let sql = match self.pool {
+            Pool::MySql(_) => {
+                "INSERT IGNORE INTO observer_event_order (source, queue_id, sequence, updated_at) VALUES (?, ?, ?, CURRENT_TIMESTAMP)"
+            }
+            Pool::Sqlite(_) => {
+                "INSERT OR IGNORE INTO observer_event_order (source, queue_id, sequence, updated_at) VALUES (?, ?, ?, CURRENT_TIMESTAMP)"
+            }
+        };
+        let inserted = on_tx!(
+            tx,
+            execute,
+            sqlx::query(sql).bind(&event.source).bind(&event.queue_id).bind(sequence)
+        )
+        .context("failed to insert observer_event_order")?;
+        if inserted > 0 {
+            return Ok(true);
+        }
+
+        // The row lock taken here orders concurrent events of one queue id.
+        let advanced = on_tx!(
+            tx,
+            execute,
+            sqlx::query(
+                "UPDATE observer_event_order SET sequence = ?, updated_at = CURRENT_TIMESTAMP WHERE source = ? AND queue_id = ? AND sequence < ?"
+            )
+            .bind(sequence)
+            .bind(&event.source)
+            .bind(&event.queue_id)
+            .bind(sequence)
+        )
+        .context("failed to update observer_event_order")?;
+        Ok(advanced > 0)
+    }
+
+    /// Deletes `observer_event_order` rows untouched for
+    /// [`OBSERVER_ORDER_RETENTION`]; returns the count.
+    pub async fn prune_observer_event_order(&self) -> Result<u64> {
+        if !self.schema.observer_order {
+            return Ok(0);
+        }
+        on_pool!(
+            &self.pool,
+            execute,
+            sqlx::query(&format!(
+                "DELETE FROM observer_event_order WHERE NOT ({})",
+                self.pool.within_secs("updated_at")
+            ))
+            .bind(OBSERVER_ORDER_RETENTION.as_secs() as i64)
+        )
+        .context("failed to prune observer_event_order")
+    }
+
+    /// Deletes `processed_spool_messages` keys older than
+    /// [`PROCESSED_SPOOL_RETENTION`]; returns the count.
+    pub async fn prune_processed_spool_messages(&self) -> Result<u64> {
+        on_pool!(
+            &self.pool,
+            execute,
+            sqlx::query(&format!(
+                "DELETE FROM processed_spool_messages WHERE NOT ({})",
+                self.pool.within_secs("processed_at")
+            ))
+            .bind(PROCESSED_SPOOL_RETENTION.as_secs() as i64)
+        )
+        .context("failed to prune processed_spool_messages")
+    }
+
+    /// Deletes `source_events` rows older than `retention`; returns the count.
+    pub async fn prune_source_events(
+        &self,
+        retention: Duration
+    ) -> Result<u64> {
+        on_pool!(
+            &self.pool,
+            execute,
+            sqlx::query(&format!(
+                "DELETE FROM source_events WHERE NOT ({})",
+                self.pool.within_secs("occurred_at")
+            ))
+            .bind(i64::try_from(retention.as_secs()).unwrap_or(i64::MAX))
+        )
+        .context("failed to prune source_events")
+    }
+
+    /// Deletes `mail_bounce_audit` rows processed more than `retention` ago;
+    /// returns the count.
+    pub async fn prune_bounce_audit(
+        &self,
+        retention: Duration
+    ) -> Result<u64> {
+        if !self.schema.bounce_audit {
+            return Ok(0);
+        }
+        on_pool!(
+            &self.pool,
+            execute,
+            sqlx::query(&format!(
+                "DELETE FROM mail_bounce_audit WHERE NOT ({})",
+                self.pool.within_secs("processed_at")
+            ))
+            .bind(i64::try_from(retention.as_secs()).unwrap_or(i64::MAX))
+        )
+        .context("failed to prune mail_bounce_audit")
+    }
+
+    /// Deletes `bounce_dedup` keys older than the window; returns the count.
+    pub async fn prune_bounce_dedup(&self) -> Result<u64> {
+        let Some(window) = self.bounce_dedup_window else {
+            return Ok(0);
+        };
+        let window_secs = i64::try_from(window.as_secs()).unwrap_or(i64::MAX);
+        on_pool!(
+            &self.pool,
+            execute,
+            sqlx::query(&format!(
+                "DELETE FROM bounce_dedup WHERE NOT ({})",
+                self.pool.within_secs("seen_at")
+            ))
+            .bind(window_secs)
+        )
+        .context("failed to prune bounce_dedup")
+    }
+
+    /// Stores the `authentication` result of the spooled bounce with
+    /// `idempotency_key`; a replay overwrites its earlier row.
+    pub async fn record_authentication(
+        &self,
+        idempotency_key: &str,
+        hash: &str,
+        outcome: &AuthOutcome
+    ) -> Result<()> {
+        on_pool!(
+            &self.pool,
+            execute,
+            sqlx::query(
+                "REPLACE INTO bounce_authentication (idempotency_key, hash, verdict, dkim, dkim_domain, relay, relay_host, checked_at) VALUES (?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)"
+            )
+            .bind(idempotency_key)
+            .bind(hash)
+            .bind(outcome.verdict())
+            .bind(outcome.dkim.as_str())
+            .bind(&outcome.dkim_domain)
+            .bind(outcome.relay.as_str())
+            .bind(&outcome.relay_host)
+        )
+        .context("failed to insert bounce_authentication")?;
+        Ok(())
+    }
+
+    /// Indexes an archived copy of the spooled bounce with `idempotency_key`;
+    /// a replay keeps the first copy.
+    pub async fn insert_archive(
+        &self,
+        idempotency_key: &str,
+        hash: &str,
+        copy: &ArchivedCopy
+    ) -> Result<()> {
+        let sql = match self.pool {
+            Pool::MySql(_) => {
+                "INSERT IGNORE INTO bounce_archive (idempotency_key, hash, storage, content_encoding, size_bytes, body, object_key, archived_at) VALUES (?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)"
+            }
+            Pool::Sqlite(_) => {
+                "INSERT OR IGNORE INTO bounce_archive (idempotency_key, hash, storage, content_encoding, size_bytes, body, object_key, archived_at) VALUES (?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)"
+            }
+        };
+        on_pool!(
+            &self.pool,
+            execute,
+            sqlx::query(sql)
+                .bind(idempotency_key)
+                .bind(hash)
+                .bind(copy.storage)
+                .bind(copy.content_encoding)
+                .bind(i64::try_from(copy.size_bytes).unwrap_or(i64::MAX))
+                .bind(&copy.body)
+                .bind(&copy.object_key)
+        )
+        .context("failed to insert bounce_archive")?;
+        Ok(())
+    }
+
+    /// Archive rows older than `retention_secs`, oldest first, capped at
+    /// `limit`: `(id, object_key)`.
+    pub async fn expired_archives(
+        &self,
+        retention_secs: u64,
+        limit: u32
+    ) -> Result<Vec<(i64, Option<String>)>> {
+        let retention_secs = i64::try_from(retention_secs).unwrap_or(i64::MAX);
+        on_pool!(
+            &self.pool,
+            fetch_all,
+            sqlx::query_as::<_, (i64, Option<String>)>(&format!(
+                "SELECT id, object_key FROM bounce_archive WHERE NOT ({}) ORDER BY archived_at LIMIT ?",
+                self.pool.within_secs("archived_at")
+            ))
+            .bind(retention_secs)
+            .bind(limit)
+        )
+        .context("failed to query expired bounce_archive")
+    }
+
+    pub async fn delete_archives(
+        &self,
+        ids: &[i64]
+    ) -> Result<u64> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let sql =
+            format!("DELETE FROM bounce_archive WHERE id IN ({})", vec!["?"; ids.len()].join(", "));
+        on_pool!(
+            &self.pool,
+            execute,
+            ids.iter().fold(sqlx::query(&sql), |query, id| query.bind(id))
+        )
+        .context("failed to delete bounce_archive")
+    }
+
+    /// Appends source registry transitions to `source_events`.
+    pub async fn insert_source_events(
+        &self,
+        events: &[SourceEvent]
+    ) -> Result<()> {
+        let sql = format!(
+            "INSERT INTO source_events (source, transition, detail, occurred_at) VALUES (?, ?, ?, {})",
+            self.pool.datetime_of_unix_secs()
+        );
+        for event in events {
+            on_pool!(
+                &self.pool,
+                execute,
+                sqlx::query(&sql)
+                    .bind(&event.source)
+                    .bind(&event.transition)
+                    .bind(&event.detail)
+                    .bind(i64::try_from(event.at_unix).unwrap_or(i64::MAX))
+            )
+            .context("failed to insert source_events")?;
+        }
+        Ok(())
+    }
+
+    /// Writes one `mail_bounce_audit` row; a no-op while the table is missing.
+    pub async fn insert_bounce_audit(
+        &self,
+        audit: &BounceAudit
+    ) -> Result<()> {
+        if !self.schema.bounce_audit {
+            return Ok(());
+        }
+        let at = self.pool.datetime_of_unix_secs();
+        let sql = format!(
+            "INSERT INTO mail_bounce_audit (hash, ingest_path, source, origin, outcome, scan_labels, received_at, processed_at) VALUES (?, ?, ?, ?, ?, ?, {at}, {at})"
+        );
+        let scan_labels = (!audit.scan_labels.is_empty()).then(|| audit.scan_labels.join(","));
+        on_pool!(
+            &self.pool,
+            execute,
+            sqlx::query(&sql)
+                .bind(&audit.hash)
+                .bind(audit.ingest_path.as_str())
+                .bind(&audit.source)
+                .bind(&audit.origin)
+                .bind(audit.outcome)
+                .bind(&scan_labels)
+                .bind(i64::try_from(unix_secs(audit.received_at)).unwrap_or(i64::MAX))
+                .bind(i64::try_from(unix_secs(audit.processed_at)).unwrap_or(i64::MAX))
+        )
+        .context("failed to insert mail_bounce_audit")?;
+        Ok(())
+    }
+
+    /// Inserts or refreshes the `mail_message_bounces` row of `message_id`
+    /// inside `tx`; see [`BounceWrite`]. `occurred_at_unix` stamps
+    /// `created_at`/`last_seen_at` with the time the bounce was logged
+    /// instead of now.
+    async fn record_message_bounce(
+        &self,
+        tx: &mut Tx,
+        message_id: u32,
+        parsed: &ParsedBounce,
+        occurred_at_unix: Option<u64>
+    ) -> Result<()> {
+        let stored = on_tx!(
+            tx,
+            fetch_optional,
+            sqlx::query_as::<_, StoredDiagnostics>(
+                "SELECT action, status_code, description FROM mail_message_bounces WHERE message_id = ? LIMIT 1"
+            )
+            .bind(message_id)
+        )
+        .context("failed to query mail_message_bounces")?;
+
+        let columns = self.schema.message_bounces;
+        let category = self.classifier.load().classify(parsed).map(|category| category.as_str());
+        let at = self.pool.timestamp_at(occurred_at_unix);
+        let write = columns.adjust(BounceWrite::classify(stored.as_ref(), parsed));
+        let rows = match write {
+            BounceWrite::Insert => {
+                let sql = format!(
+                    "INSERT INTO mail_message_bounces (message_id, action, status_code, description{}) VALUES (?, ?, ?, ?{})",
+                    columns.insert_columns(),
+                    columns.insert_values(&at)
+                );
+                on_tx!(tx, execute, {
+                    let query = sqlx::query(&sql)
+                        .bind(message_id)
+                        .bind(parsed.action.as_deref())
+                        .bind(&parsed.status_code)
+                        .bind(parsed.description.as_deref());
+                    let query = if columns.reason { query.bind(parsed.reason()) } else { query };
+                    if columns.category { query.bind(category) } else { query }
+                })
+            }
+            BounceWrite::Repeat => {
+                let sql = format!(
+                    "UPDATE mail_message_bounces SET last_seen_at = {at}, occurrence_count = occurrence_count + 1 WHERE message_id = ?"
+                );
+                on_tx!(tx, execute, sqlx::query(&sql).bind(message_id))
+            }
+            BounceWrite::Replace => {
+                let sql = format!(
+                    "UPDATE mail_message_bounces SET action = ?, status_code = ?, description = ?{} WHERE message_id = ?",
+                    columns.replace_assignments(&at)
+                );
+                on_tx!(tx, execute, {
+                    let query = sqlx::query(&sql)
+                        .bind(parsed.action.as_deref())
+                        .bind(&parsed.status_code)
+                        .bind(parsed.description.as_deref());
+                    let query = if columns.reason { query.bind(parsed.reason()) } else { query };
+                    let query = if columns.category { query.bind(category) } else { query };
+                    query.bind(message_id)
+                })
+            }
+        }
+        .with_context(|| format!("failed to {} mail_message_bounces", write.as_str()))?;
+        debug!(
+            "db upsert mail_message_bounces: op={}, message_id={}, hash={}, rows_affected={}",
+            write.as_str(),
+            message_id,
+            parsed.hash,
+            rows
+        );
+        Ok(())
+    }
+
+    /// Appends the outcome to the history of `message_id` inside `tx`; a
+    /// no-op while `mail_message_bounce_events` is missing.
+    async fn record_bounce_event(
+        &self,
+        tx: &mut Tx,
+        message_id: u32,
+        parsed: &ParsedBounce,
+        occurred_at_unix: Option<u64>
+    ) -> Result<()> {
+        if !self.schema.bounce_events {
+            return Ok(());
+        }
+        let sql = format!(
+            "INSERT INTO mail_message_bounce_events (message_id, action, status_code, description, occurred_at) VALUES (?, ?, ?, ?, {})",
+            self.pool.timestamp_at(occurred_at_unix)
+        );
+        on_tx!(
+            tx,
+            execute,
+            sqlx::query(&sql)
+                .bind(message_id)
+                .bind(parsed.action.as_deref())
+                .bind(&parsed.status_code)
+                .bind(parsed.description.as_deref())
+        )
+        .context("failed to insert mail_message_bounce_events")?;
+        Ok(())
+    }
+
+    /// Oldest-first history of `message_id`; empty while the table is
+    /// missing.
+    async fn bounce_history(
+        &self,
+        message_id: u32
+    ) -> Result<Vec<BounceEvent>> {
+        if !self.schema.bounce_events {
+            return Ok(Vec::new());
+        }
+        let sql = format!(
+            "SELECT action, status_code, description, {} FROM mail_message_bounce_events WHERE message_id = ? ORDER BY occurred_at, id",
+            self.pool.unix_secs("occurred_at")
+        );
+        let rows = on_pool!(
+            &self.pool,
+            fetch_all,
+            sqlx::query_as::<_, (Option<String>, String, Option<String>, i64)>(&sql)
+                .bind(message_id)
+        )
+        .context("failed to query mail_message_bounce_events")?;
+        Ok(rows
+            .into_iter()
+            .map(|(action, status_code, description, occurred_at_unix)| BounceEvent {
+                action,
+                status_code,
+                description,
+                occurred_at_unix
+            })
+            .collect())
+    }
+
+    /// Inserts or refreshes the `mail_bounces` row of an unknown hash inside
+    /// `tx`; see [`BounceWrite`].
+    ///
+    /// Rows are keyed by hash and tenant: a bounce never updates the orphan
+    /// row of another tenant, or of no tenant, with the same hash.
+    async fn record_orphan_bounce(
+        &self,
+        tx: &mut Tx,
+        parsed: &ParsedBounce
+    ) -> Result<()> {
+        let tenant = self.schema.orphan_tenant;
+        let scope = if tenant {
+            format!(" AND {}", self.pool.null_safe_eq("tenant"))
+        } else {
+            String::new()
+        };
+        let sql = format!(
+            "SELECT action, status_code, description FROM mail_bounces WHERE hash = ?{scope} LIMIT 1"
+        );
+        let stored = on_tx!(tx, fetch_optional, {
+            let query = sqlx::query_as::<_, StoredDiagnostics>(&sql).bind(&parsed.hash);
+            if tenant { query.bind(parsed.tenant.as_deref()) } else { query }
+        })
+        .context("failed to query mail_bounces")?;
+
+        let columns = self.schema.orphan_bounces;
+        let category = self.classifier.load().classify(parsed).map(|category| category.as_str());
+        let write = columns.adjust(BounceWrite::classify(stored.as_ref(), parsed));
+        let rows = match write {
+            BounceWrite::Insert => {
+                let sql = format!(
+                    "INSERT INTO mail_bounces (hash, recipient, action, status_code, description{}{}) VALUES (?, ?, ?, ?, ?{}{})",
+                    columns.insert_columns(),
+                    if tenant { ", tenant" } else { "" },
+                    columns.insert_values("CURRENT_TIMESTAMP"),
+                    if tenant { ", ?" } else { "" }
+                );
+                on_tx!(tx, execute, {
+                    let query = sqlx::query(&sql)
+                        .bind(&parsed.hash)
+                        .bind(parsed.recipient.as_deref())
+                        .bind(parsed.action.as_deref())
+                        .bind(&parsed.status_code)
+                        .bind(parsed.description.as_deref());
+                    let query = if columns.reason { query.bind(parsed.reason()) } else { query };
+                    let query = if columns.category { query.bind(category) } else { query };
+                    if tenant { query.bind(parsed.tenant.as_deref()) } else { query }
+                })
+            }
+            BounceWrite::Repeat => {
+                let sql = format!(
+                    "UPDATE mail_bounces SET last_seen_at = CURRENT_TIMESTAMP, occurrence_count = occurrence_count + 1 WHERE hash = ?{scope}"
+                );
+                on_tx!(tx, execute, {
+                    let query = sqlx::query(&sql).bind(&parsed.hash);
+                    if tenant { query.bind(parsed.tenant.as_deref()) } else { query }
+                })
+            }
+            BounceWrite::Replace => {
+                let sql = format!(
+                    "UPDATE mail_bounces SET recipient = ?, action = ?, status_code = ?, description = ?{} WHERE hash = ?{scope}",
+                    columns.replace_assignments("CURRENT_TIMESTAMP")
+                );
+                on_tx!(tx, execute, {
+                    let query = sqlx::query(&sql)
+                        .bind(parsed.recipient.as_deref())
+                        .bind(parsed.action.as_deref())
+                        .bind(&parsed.status_code)
+                        .bind(parsed.description.as_deref());
+                    let query = if columns.reason { query.bind(parsed.reason()) } else { query };
+                    let query = if columns.category { query.bind(category) } else { query };
+                    let query = query.bind(&parsed.hash);
+                    if tenant { query.bind(parsed.tenant.as_deref()) } else { query }
+                })
+            }
+        }
+        .with_context(|| format!("failed to {} mail_bounces", write.as_str()))?;
+        debug!(
+            "db upsert mail_bounces: op={}, hash={}, rows_affected={}",
+            write.as_str(),
+            parsed.hash,
+            rows
+        );
+        Ok(())
+    }
+
+    /// Looks up the suppression entry of `recipient` inside `tx`.
+    ///
+    /// Recipients are compared case-insensitively (stored lowercase).
+    async fn fetch_suppression(
+        &self,
+        tx: &mut Tx,
+        recipient: &str
+    ) -> Result<Option<Suppression>> {
+        let sql = format!(
+            "SELECT {} FROM suppressions WHERE recipient = ? LIMIT 1",
+            self.suppression_columns()
+        );
+        let row = on_tx!(
+            tx,
+            fetch_optional,
+            sqlx::query_as::<_, SuppressionRow>(&sql).bind(suppression_key(recipient))
+        )
+        .context("failed to query suppressions")?;
+
+        Ok(row.map(Suppression::from))
+    }
+
+    /// Columns of a [`SuppressionRow`].
+    fn suppression_columns(&self) -> String {
+        format!(
+            "recipient, reason, status_code, hash, {}, {}, {}, {}",
+            self.pool.unix_secs("created_at"),
+            self.pool.unix_secs("updated_at"),
+            self.pool.unix_secs("expires_at"),
+            self.pool.unix_secs("removed_at")
+        )
+    }
+
+    /// Soft-deletes `entry` inside `tx` and audits it as `change` by `actor`.
+    async fn mark_suppression_removed(
+        &self,
+        tx: &mut Tx,
+        entry: &Suppression,
+        change: SuppressionChange,
+        actor: &str
+    ) -> Result<()> {
+        on_tx!(
+            tx,
+            execute,
+            sqlx::query(
+                "UPDATE suppressions SET removed_at = CURRENT_TIMESTAMP WHERE recipient = ?"
+            )
+            .bind(&entry.recipient)
+        )
+        .context("failed to update suppressions")?;
+        self.record_suppression_change(tx, entry, change, actor).await
+    }
+
+    /// Writes the `suppression_audit` row of `change` to `entry` inside `tx`.
+    async fn record_suppression_change(
+        &self,
+        tx: &mut Tx,
+        entry: &Suppression,
+        change: SuppressionChange,
+        actor: &str
+    ) -> Result<()> {
+        let sql = format!(
+            "INSERT INTO suppression_audit (recipient, action, actor, reason, status_code, hash, suppressed_at, created_at) VALUES (?, ?, ?, ?, ?, ?, {}, CURRENT_TIMESTAMP)",
+            self.pool.datetime_of_unix_secs()
+        );
+        on_tx!(
+            tx,
+            execute,
+            sqlx::query(&sql)
+                .bind(&entry.recipient)
+                .bind(change.as_str())
+                .bind(actor)
+                .bind(&entry.reason)
+                .bind(&entry.status_code)
+                .bind(entry.hash.as_deref())
+                .bind(entry.created_at_unix)
+        )
+        .context("failed to insert suppression_audit")?;
+        Ok(())
+    }
+
+    /// Adds, refreshes or re-activates the recipient's suppression entry
+    /// inside `tx` when the outcome is a hard bounce with a configured status
+    /// code, a complaint, or (with `soft_bounce_ttl`) a soft bounce. No-op
+    /// while suppression is disabled.
+    async fn record_suppression(
+        &self,
+        tx: &mut Tx,
+        parsed: &ParsedBounce,
+        message_status: i32
+    ) -> Result<()> {
+        if !self.suppression.enabled {
+            return Ok(());
+        }
+
+        let reason = suppression_reason(&self.suppression, parsed, message_status);
+        if reason.is_none() && message_status != MAIL_STATUS_SUCCESS {
+            return Ok(());
+        }
+        let Some(recipient) = parsed.recipient.as_deref().map(suppression_key) else {
+            debug!("suppression skipped: hash={}, reason=missing_recipient", parsed.hash);
+            return Ok(());
+        };
+
+        let existing = self.fetch_suppression(tx, &recipient).await?;
+        let active = existing.as_ref().filter(|entry| entry.is_active());
+
+        let Some(reason) = reason else {
+            // Successful delivery to a suppressed address means the sending
+            // application ignored the suppression list.
+            if let Some(existing) = active {
+                warn!(
+                    "delivery to suppressed recipient: recipient={}, hash={}, suppressed_reason={}, suppressed_status_code={}, suppressed_hash={}, suppressed_since_unix={}, suppression_updated_unix={}",
+                    existing.recipient,
+                    parsed.hash,
+                    existing.reason,
+                    existing.status_code,
+                    existing.hash.as_deref().unwrap_or("-"),
+                    existing.created_at_unix,
+                    existing.updated_at_unix
+                );
+            }
+            return Ok(());
+        };
+
+        // A soft bounce never turns a hard bounce or complaint entry into
+        // one that expires.
+        if reason == SuppressionReason::SoftBounce
+            && active.is_some_and(|entry| entry.reason != reason.as_str())
+        {
+            return Ok(());
+        }
+        let ttl_secs = match reason {
+            SuppressionReason::SoftBounce => self
+                .suppression
+                .soft_bounce_ttl
+                .map(|ttl| i64::try_from(ttl.as_secs()).unwrap_or(i64::MAX)),
+            _ => None
+        };
+
+        if let Some(existing) = existing {
+            let reactivated = !existing.is_active();
+            // A re-activated entry is suppressed anew from now on.
+            let since = if reactivated { "CURRENT_TIMESTAMP" } else { "created_at" };
+            let sql = format!(
+                "UPDATE suppressions SET reason = ?, status_code = ?, description = ?, hash = ?, expires_at = {}, created_at = {since}, removed_at = NULL, updated_at = CURRENT_TIMESTAMP WHERE recipient = ?",
+                self.pool.secs_from_now()
+            );
+            on_tx!(
+                tx,
+                execute,
+                sqlx::query(&sql)
+                    .bind(reason.as_str())
+                    .bind(&parsed.status_code)
+                    .bind(parsed.description.as_deref())
+                    .bind(&parsed.hash)
+                    .bind(ttl_secs)
+                    .bind(&recipient)
+            )
+            .context("failed to update suppressions")?;
+            if reactivated {
+                self.record_suppression_change(
+                    tx,
+                    &existing,
+                    SuppressionChange::Reactivated,
+                    BOUNCE_ACTOR
+                )
+                .await?;
+                info!(
+                    "recipient suppressed again: recipient={}, reason={}, status_code={}, hash={}, previous_reason={}",
+                    recipient,
+                    reason.as_str(),
+                    parsed.status_code,
+                    parsed.hash,
+                    existing.reason
+                );
+            } else {
+                debug!(
+                    "suppression refreshed: recipient={}, reason={}, status_code={}, suppressed_since_unix={}",
+                    recipient,
+                    reason.as_str(),
+                    parsed.status_code,
+                    existing.created_at_unix
+                );
+            }
+        } else {
+            let sql = format!(
+                "INSERT INTO suppressions (recipient, reason, status_code, description, hash, expires_at, created_at, updated_at) VALUES (?, ?, ?, ?, ?, {}, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+                self.pool.secs_from_now()
+            );
+            on_tx!(
+                tx,
+                execute,
+                sqlx::query(&sql)
+                    .bind(&recipient)
+                    .bind(reason.as_str())
+                    .bind(&parsed.status_code)
+                    .bind(parsed.description.as_deref())
+                    .bind(&parsed.hash)
+                    .bind(ttl_secs)
+            )
+            .context("failed to insert suppressions")?;
+            info!(
+                "recipient suppressed: recipient={}, reason={}, status_code={}, hash={}",
+                recipient,
+                reason.as_str(),
+                parsed.status_code,
+                parsed.hash
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn suppression_reason(
+    config: &SuppressionConfig,
+    parsed: &ParsedBounce,
+    message_status: i32
+) -> Option<SuppressionReason> {
+    if parsed.action.as_deref().is_some_and(|action| action.eq_ignore_ascii_case("complaint")) {
+        return Some(SuppressionReason::Complaint);
+    }
+
+    let hard_failure =
+        message_status == MAIL_STATUS_FAILED || message_status == MAIL_STATUS_SUSPENDED;
+    if hard_failure && config.matches_status_code(&parsed.status_code) {
+        return Some(SuppressionReason::HardBounce);
+    }
+    if message_status == MAIL_STATUS_PENDING && config.soft_bounce_ttl.is_some() {
+        return Some(SuppressionReason::SoftBounce);
+    }
+
+    None
+}
+
+/// What applying a parsed bounce would write, worked out without a database
+/// (`bouncer-server check`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BouncePreview {
+    /// `mail_messages.status`; auto-replies leave it alone.
+    pub mail_status: Option<i32>,
+    pub category: Option<BounceCategory>,
+    /// Set only while suppression is enabled and the bounce names a recipient.
+    pub suppression: Option<SuppressionReason>
+}
+
+pub fn preview_bounce(
+    parsed: &ParsedBounce,
+    classifier: &BounceClassifier,
+    suppression: &SuppressionConfig
+) -> BouncePreview {
+    if parsed.kind == ReportKind::Autoreply {
+        return BouncePreview { mail_status: None, category: None, suppression: None };
+    }
+    let mail_status = map_mail_message_status(parsed);
+    let suppression = if suppression.enabled && parsed.recipient.is_some() {
+        suppression_reason(suppression, parsed, mail_status)
+    } else {
+        None
+    };
+    BouncePreview {
+        mail_status: Some(mail_status),
+        category: classifier.classify(parsed),
+        suppression
+    }
+}
+
+fn suppression_key(recipient: &str) -> String {
+    recipient.trim().to_ascii_lowercase()
+}
+
+fn map_mail_message_status(parsed: &ParsedBounce) -> i32 {
+    if let Some(action) = parsed.action.as_deref() {
+        if action.eq_ignore_ascii_case("delivered") || action.eq_ignore_ascii_case("sent") {
+            return MAIL_STATUS_SUCCESS;
+        }
+        if action.eq_ignore_ascii_case("delayed") || action.eq_ignore_ascii_case("deferred") {
+            return MAIL_STATUS_PENDING;
+        }
+    }
+
+    match parsed.status_code.as_str() {
+        "5.7.1" | "5.7.2" | "5.7.3" | "5.7.0" => MAIL_STATUS_SUSPENDED,
+        _ if parsed.status_code.starts_with("2.") => MAIL_STATUS_SUCCESS,
+        _ if parsed.status_code.starts_with("4.") => MAIL_STATUS_PENDING,
+        _ => MAIL_STATUS_FAILED
+    }
+}
+
+/// Dedup key of a bounce: hash, tenant, status code, action and a hash of the
+/// diagnostic normalized by [`dedup_evidence`], since copies of one bounce
+/// from different ingest paths can be wrapped differently.
+fn bounce_dedup_key(parsed: &ParsedBounce) -> String {
+    let evidence = dedup_evidence(parsed.description.as_deref().unwrap_or_default());
+    let evidence_hash = Sha256::digest(evidence.as_bytes());
+
+    let mut hasher = Sha256::new();
+    for part in [
+        parsed.hash.as_bytes(),
+        parsed.status_code.trim().as_bytes(),
+        parsed.action.as_deref().unwrap_or_default().to_ascii_lowercase().as_bytes(),
+        evidence_hash.as_slice()
+    ] {
+        hasher.update(part);
+        hasher.update([0]);
+    }
+    // Tenantless keys stay as they were before tenants existed.
+    if let Some(tenant) = parsed.tenant.as_deref() {
+        hasher.update(tenant.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Diagnostic as compared for dedup: lowercase, whitespace collapsed and
+/// per-message tokens replaced by `#`. A token is an alphanumeric run of at
+/// least six characters with a digit in it: queue ids, message and session
+/// ids, which differ between the copies the observer, the DSN and the
+/// remote MTA quote, while status codes and words stay.
+fn dedup_evidence(description: &str) -> String {
+    fn flush(
+        run: &mut String,
+        evidence: &mut String
+    ) {
+        if run.chars().count() >= 6 && run.chars().any(|c| c.is_ascii_digit()) {
+            evidence.push('#');
+        } else {
+            evidence.push_str(run);
+        }
+        run.clear();
+    }
+
+    let text = description.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let mut evidence = String::with_capacity(text.len());
+    let mut run = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            run.push(c);
+        } else {
+            flush(&mut run, &mut evidence);
+            evidence.push(c);
+        }
+    }
+    flush(&mut run, &mut evidence);
+    evidence
+}
+
+/// Prunes stale `observer_event_order` rows, old `processed_spool_messages`
+/// keys, and `source_events` and `mail_bounce_audit` rows past their
+/// retention hourly until `shutdown`.
+pub async fn run_table_prune(
+    db: Arc<Database>,
+    source_events_retention: Duration,
+    audit_retention: Duration,
+    shutdown: CancellationToken
+) {
+    let mut ticker = interval(Duration::from_secs(3600));
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => {
+                for (table, pruned) in [
+                    ("observer_event_order", db.prune_observer_event_order().await),
+                    ("processed_spool_messages", db.prune_processed_spool_messages().await),
+                    ("source_events", db.prune_source_events(source_events_retention).await),
+                    ("mail_bounce_audit", db.prune_bounce_audit(audit_retention).await)
+                ] {
+                    match pruned {
+                        Ok(0) => {}
+                        Ok(rows) => debug!("table pruned: table={}, rows={}", table, rows),
+                        Err(err) => warn!("table prune failed: table={table}, error={err:#}")
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Prunes expired `bounce_dedup` keys once per window (at least hourly)
+/// until `shutdown`.
+pub async fn run_bounce_dedup_prune(
+    db: Arc<Database>,
+    shutdown: CancellationToken
+) {
+    let Some(window) = db.bounce_dedup_window() else {
+        return;
+    };
+    let mut ticker = interval(window.clamp(Duration::from_secs(60), Duration::from_secs(3600)));
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => match db.prune_bounce_dedup().await {
+                Ok(0) => {}
+                Ok(pruned) => debug!("bounce dedup keys pruned: rows={}", pruned),
+                Err(err) => warn!("bounce dedup prune failed: error={err:#}")
+            }
+        }
+    }
+}
+
+/// Expires soft-bounce suppressions past their `expires_at` once per
+/// `suppression.soft_bounce_ttl` (between a minute and an hour; hourly
+/// without one, for entries from before it was unset) until `shutdown`.
+pub async fn run_suppression_expiry(
+    db: Arc<Database>,
+    shutdown: CancellationToken
+) {
+    let period = db.suppression.soft_bounce_ttl.map_or(Duration::from_secs(3600), |ttl| {
+        ttl.clamp(Duration::from_secs(60), Duration::from_secs(3600))
+    });
+    let mut ticker = interval(period);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => match db.expire_soft_suppressions().await {
+                Ok(0) => {}
+                Ok(expired) => debug!("soft-bounce suppressions expired: rows={}", expired),
+                Err(err) => warn!("suppression expiry failed: error={err:#}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bouncer_helpers::clock::system_clock;
+    use bouncer_proto::event::DeliveryEvent;
+    use bouncer_proto::query::SourceEvent;
+    use bouncer_proto::verp::VerpTemplate;
+    use uuid::Uuid;
+
+    use super::{
+        BounceColumns, Database, MAIL_STATUS_FAILED, Pool, UpsertBounceOutcome, bounce_dedup_key,
+        dedup_evidence
+    };
+    use crate::config::{
+        DatabaseResilienceConfig, MessageCacheConfig, MigrateMode, RecipientsConfig,
+        SuppressionConfig
+    };
+    use crate::core::audit::{BounceAudit, IngestPath};
+    use crate::core::faults::Faults;
+    use crate::core::parser::{ParsedBounce, ParserChain, ReportKind};
+    use crate::core::resilience::{DatabaseUnavailable, is_transient};
+
+    #[tokio::test]
+    async fn sqlite_backend_records_bounces_and_observer_events() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let suppression = SuppressionConfig { enabled: true, ..SuppressionConfig::default() };
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            suppression,
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES ('tracked', 3)")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let bounce = |hash: &str| ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: hash.to_string(),
+            status_code: "5.1.1".to_string(),
+            action: Some("failed".to_string()),
+            sender: None,
+            recipient: Some("User@Example.com".to_string()),
+            description: Some("user unknown".to_string()),
+            scan_labels: Vec::new(),
+            tenant: None
+        };
+        assert_eq!(
+            db.upsert_bounce_once(&bounce("tracked"), "key-1").await.unwrap(),
+            Some(UpsertBounceOutcome::UpdatedLocalMessage)
+        );
+        assert_eq!(db.upsert_bounce_once(&bounce("tracked"), "key-1").await.unwrap(), None);
+        assert_eq!(db.prune_processed_spool_messages().await.unwrap(), 0);
+        sqlx::query(
+            "UPDATE processed_spool_messages SET processed_at = datetime('now', '-31 days')"
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        assert_eq!(db.prune_processed_spool_messages().await.unwrap(), 1);
+        db.upsert_bounce(&bounce("tracked")).await.unwrap();
+        let repeated = db.message_state("tracked", None).await.unwrap().bounce.unwrap();
+        assert_eq!(repeated.occurrence_count, Some(2));
+        assert_eq!(repeated.reason.as_deref(), Some("user unknown"));
+        assert_eq!(repeated.category.as_deref(), Some("hard"));
+        assert!(repeated.last_seen_unix >= Some(repeated.created_at_unix));
+        assert_eq!(
+            db.upsert_bounce(&bounce("orphan")).await.unwrap(),
+            UpsertBounceOutcome::MissingLocalMessage
+        );
+
+        let state = db.message_state("tracked", None).await.unwrap();
+        assert_eq!(state.mail_status, Some(-7));
+        assert_eq!(state.bounce.map(|bounce| bounce.status_code).as_deref(), Some("5.1.1"));
+
+        let mailbox_full = ParsedBounce {
+            status_code: "5.2.2".to_string(),
+            description: Some("552 5.2.2 Postfach voll".to_string()),
+            ..bounce("tracked")
+        };
+        db.upsert_bounce(&mailbox_full).await.unwrap();
+        let replaced = db.message_state("tracked", None).await.unwrap().bounce.unwrap();
+        assert_eq!((replaced.status_code.as_str(), replaced.occurrence_count), ("5.2.2", Some(1)));
+        assert_eq!(replaced.reason.as_deref(), Some("mailbox full"));
+        assert_eq!(replaced.category.as_deref(), Some("full"));
+        assert_eq!(db.bounce_count_since(3_600).await.unwrap(), 2);
+        assert_eq!(db.recent_bounces(3_600, 10).await.unwrap().len(), 2);
+        assert_eq!(db.suppression_count().await.unwrap(), 1);
+        let listed = db.list_suppressions(10).await.unwrap();
+        assert_eq!(
+            listed.iter().map(|entry| entry.recipient.as_str()).collect::<Vec<_>>(),
+            ["user@example.com"]
+        );
+        assert_eq!(listed[0].expires_at_unix, None);
+        let removed = db.remove_suppression("USER@example.com", "ops").await.unwrap().unwrap();
+        assert_eq!(
+            (removed.reason.as_str(), removed.status_code.as_str()),
+            ("hard_bounce", "5.1.1")
+        );
+        assert!(db.remove_suppression("user@example.com", "ops").await.unwrap().is_none());
+        assert!(db.expire_suppression("user@example.com", "ops").await.unwrap().is_none());
+        assert_eq!(db.suppression_count().await.unwrap(), 0);
+        assert!(db.list_suppressions(10).await.unwrap().is_empty());
+        let rows: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM suppressions").fetch_one(pool).await.unwrap();
+        assert_eq!(rows, 1);
+        // The next hard bounce re-activates the kept row.
+        db.upsert_bounce(&bounce("orphan")).await.unwrap();
+        assert_eq!(db.suppression_count().await.unwrap(), 1);
+        let audit = db.list_suppression_audit(10).await.unwrap();
+        assert_eq!(
+            audit
+                .iter()
+                .map(|row| (row.action.as_str(), row.actor.as_str(), row.reason.as_str()))
+                .collect::<Vec<_>>(),
+            [("reactivated", "bounce", "hard_bounce"), ("removed", "ops", "hard_bounce")]
+        );
+        assert_eq!(audit[1].suppressed_at_unix, removed.created_at_unix);
+        db.expire_suppression("user@example.com", "ops").await.unwrap().unwrap();
+        assert_eq!(db.suppression_count().await.unwrap(), 0);
+
+        let delivered = DeliveryEvent::new("mail-01", "tracked", "ABC123", "user@example.com", 0)
+            .with_outcome("sent", "2.0.0", "delivered");
+        db.apply_observer_event(&delivered).await.unwrap();
+        assert_eq!(db.message_state("tracked", None).await.unwrap().mail_status, Some(7));
+
+        assert!(db.table_columns("mail_bounces").await.unwrap().contains(&"hash".to_string()));
+        assert!(
+            db.indexed_columns("mail_message_bounces")
+                .await
+                .unwrap()
+                .contains(&"message_id".to_string())
+        );
+        assert!(db.unix_time().await.unwrap() > 1_700_000_000);
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn observer_events_older_than_the_applied_one_are_skipped() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES ('tracked', 3)")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let event = |sequence: u64, smtp_status: &str, status_code: &str| {
+            let action = if smtp_status == "deferred" { "delayed" } else { "failed" };
+            DeliveryEvent::new("observer-1", "tracked", "ABC123", "user@example.com", 0)
+                .with_outcome(smtp_status, status_code, action)
+                .with_sequence(sequence)
+        };
+        let status = async || db.message_state("tracked", None).await.unwrap().mail_status;
+
+        db.apply_observer_event(&event(20, "bounced", "5.1.1")).await.unwrap();
+        assert_eq!(status().await, Some(-7));
+        // Published earlier, delivered later; then a resend of the applied one.
+        db.apply_observer_event(&event(10, "deferred", "4.4.1")).await.unwrap();
+        db.apply_observer_event(&event(20, "deferred", "4.4.1")).await.unwrap();
+        assert_eq!(status().await, Some(-7));
+        // Publishers that predate sequencing still apply in arrival order.
+        db.apply_observer_event(&DeliveryEvent { sequence: None, ..event(0, "deferred", "4.4.1") })
+            .await
+            .unwrap();
+        assert_eq!(status().await, Some(3));
+        db.apply_observer_event(&event(30, "bounced", "5.1.1")).await.unwrap();
+        assert_eq!(status().await, Some(-7));
+        assert_eq!(db.prune_observer_event_order().await.unwrap(), 0);
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[test]
+    fn dedup_key_ignores_per_message_ids() {
+        let bounce = |description: &str| ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: "tracked".to_string(),
+            status_code: "5.1.1".to_string(),
+            action: Some("failed".to_string()),
+            sender: None,
+            recipient: None,
+            description: Some(description.to_string()),
+            scan_labels: Vec::new(),
+            tenant: None
+        };
+        let key = |description: &str| bounce_dedup_key(&bounce(description));
+
+        assert_eq!(
+            key("550 5.1.1 <user@example.com>: User unknown (in reply to RCPT TO, id 4ABC123DEF)"),
+            key("550 5.1.1 <user@example.com>: user unknown (in reply to RCPT TO, id 7F2E91C0AB)")
+        );
+        assert_eq!(
+            dedup_evidence("550 5.7.1 Message 4Xp2R15ZXfz8q3 rejected"),
+            "550 5.7.1 message # rejected"
+        );
+        assert_ne!(key("550 5.1.1 User unknown"), key("552 5.2.2 Mailbox full"));
+    }
+
+    #[tokio::test]
+    async fn dedup_skips_same_bounce_from_another_path() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap()
+        .with_bounce_dedup(Some(Duration::from_secs(600)));
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES ('tracked', 3)")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let event = DeliveryEvent::new("observer-1", "tracked", "ABC123", "user@example.com", 0)
+            .with_outcome("bounced", "5.1.1", "failed")
+            .with_diagnostic("550 5.1.1  User unknown");
+        db.apply_observer_event(&event).await.unwrap();
+
+        let mut piped = ParsedBounce::from(&event);
+        piped.description = Some("550 5.1.1 user\r\n unknown".to_string());
+        assert_eq!(
+            db.upsert_bounce_once(&piped, "key-1").await.unwrap(),
+            Some(UpsertBounceOutcome::Duplicate)
+        );
+        assert_eq!(db.upsert_bounce(&piped).await.unwrap(), UpsertBounceOutcome::Duplicate);
+        let bounce = db.message_state("tracked", None).await.unwrap().bounce.unwrap();
+        assert_eq!(bounce.occurrence_count, Some(1));
+
+        piped.status_code = "5.2.2".to_string();
+        assert_eq!(
+            db.upsert_bounce(&piped).await.unwrap(),
+            UpsertBounceOutcome::UpdatedLocalMessage
+        );
+
+        sqlx::query("UPDATE bounce_dedup SET seen_at = datetime('now', '-1 hour')")
+            .execute(pool)
+            .await
+            .unwrap();
+        assert_eq!(db.prune_bounce_dedup().await.unwrap(), 2);
+        db.apply_observer_event(&event).await.unwrap();
+        let bounce = db.message_state("tracked", None).await.unwrap().bounce.unwrap();
+        assert_eq!(bounce.status_code, "5.1.1");
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn observer_event_stamps_bounce_with_logged_time() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES ('tracked', 3)")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let mut event = DeliveryEvent::new(
+            "observer-1",
+            "tracked",
+            "ABC123",
+            "user@example.com",
+            1_700_000_000
+        )
+        .with_outcome("bounced", "5.1.1", "failed")
+        .with_diagnostic("550 5.1.1 User unknown");
+        db.apply_observer_event(&event).await.unwrap();
+        event.observed_at_unix = 1_700_000_060;
+        db.apply_observer_event(&event).await.unwrap();
+
+        let stamps = sqlx::query_as::<_, (i64, i64)>(
+            "SELECT CAST(strftime('%s', created_at) AS INTEGER), CAST(strftime('%s', last_seen_at) AS INTEGER) FROM mail_message_bounces"
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!(stamps, (1_700_000_000, 1_700_000_060));
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn stores_normalized_recipients_from_both_paths() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig { enabled: true, ..SuppressionConfig::default() },
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap()
+        .with_recipients(RecipientsConfig { fold_plus_tags: true, ..RecipientsConfig::default() });
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+
+        let bounce = ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: "orphan".to_string(),
+            status_code: "5.1.1".to_string(),
+            action: Some("failed".to_string()),
+            sender: None,
+            recipient: Some(r#""Jane Doe" <Jane+news@Example.COM>"#.to_string()),
+            description: Some("user unknown".to_string()),
+            scan_labels: Vec::new(),
+            tenant: None
+        };
+        db.upsert_bounce(&bounce).await.unwrap();
+        let stored: String =
+            sqlx::query_scalar("SELECT recipient FROM mail_bounces WHERE hash = 'orphan'")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        assert_eq!(stored, "Jane@example.com");
+
+        let event = DeliveryEvent::new("observer-1", "other", "ABC123", "<Max+x@Example.net.>", 0)
+            .with_outcome("bounced", "5.1.1", "failed");
+        db.apply_observer_event(&event).await.unwrap();
+        let mut suppressed: Vec<String> =
+            sqlx::query_scalar("SELECT recipient FROM suppressions").fetch_all(pool).await.unwrap();
+        suppressed.sort();
+        assert_eq!(suppressed, ["jane@example.com", "max@example.net"]);
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn verp_return_path_never_reaches_suppressions() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig { enabled: true, ..SuppressionConfig::default() },
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+
+        let report = concat!(
+            "X-Bouncer-Envelope-To: bounce-a1b2c3@example.com\r\n",
+            "X-Bouncer-Original-To: bounce-a1b2c3@example.com\r\n",
+            "Subject: Undelivered Mail Returned to Sender\r\n",
+            "\r\n",
+            "Your message could not be delivered.\r\n",
+            "Status: 5.1.1\r\n",
+        );
+        let parsed = ParserChain::default()
+            .with_verp(Some(VerpTemplate::parse("bounce-{hash}@example.com").unwrap()))
+            .parse_detailed(report.as_bytes())
+            .unwrap();
+        assert_eq!(parsed.hash, "a1b2c3");
+        db.upsert_bounce(&parsed).await.unwrap();
+
+        let suppressed: Vec<String> =
+            sqlx::query_scalar("SELECT recipient FROM suppressions").fetch_all(pool).await.unwrap();
+        assert!(suppressed.is_empty(), "{suppressed:?}");
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn expires_soft_bounce_suppressions_after_their_ttl() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let suppression = SuppressionConfig {
+            enabled: true,
+            soft_bounce_ttl: Some(Duration::from_secs(3600)),
+            ..SuppressionConfig::default()
+        };
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            suppression,
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        let bounce = |recipient: &str, status_code: &str| ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: "orphan".to_string(),
+            status_code: status_code.to_string(),
+            action: None,
+            sender: None,
+            recipient: Some(recipient.to_string()),
+            description: None,
+            scan_labels: Vec::new(),
+            tenant: None
+        };
+
+        db.upsert_bounce(&bounce("soft@example.com", "4.2.2")).await.unwrap();
+        db.upsert_bounce(&bounce("hard@example.com", "5.1.1")).await.unwrap();
+        // A soft bounce leaves a hard bounce entry as it is.
+        db.upsert_bounce(&bounce("hard@example.com", "4.2.2")).await.unwrap();
+        let listed = db.list_suppressions(10).await.unwrap();
+        let entry = |recipient: &str| {
+            let entry = listed.iter().find(|entry| entry.recipient == recipient).unwrap();
+            (entry.reason.as_str(), entry.expires_at_unix.is_some())
+        };
+        assert_eq!(entry("soft@example.com"), ("soft_bounce", true));
+        assert_eq!(entry("hard@example.com"), ("hard_bounce", false));
+
+        assert_eq!(db.expire_soft_suppressions().await.unwrap(), 0);
+        sqlx::query("UPDATE suppressions SET expires_at = datetime('now', '-1 minute') WHERE reason = 'soft_bounce'")
+            .execute(pool)
+            .await
+            .unwrap();
+        assert_eq!(db.expire_soft_suppressions().await.unwrap(), 1);
+        let listed = db.list_suppressions(10).await.unwrap();
+        assert_eq!(
+            listed.iter().map(|entry| entry.recipient.as_str()).collect::<Vec<_>>(),
+            ["hard@example.com"]
+        );
+        let audit = db.list_suppression_audit(10).await.unwrap();
+        assert_eq!(
+            (audit[0].recipient.as_str(), audit[0].action.as_str(), audit[0].actor.as_str()),
+            ("soft@example.com", "expired", "soft_bounce_ttl")
+        );
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_every_outcome_in_the_bounce_history() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES ('tracked', 3)")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let delayed = DeliveryEvent::new(
+            "observer-1",
+            "tracked",
+            "ABC123",
+            "user@example.com",
+            1_700_000_000
+        )
+        .with_outcome("deferred", "4.4.1", "delayed")
+        .with_diagnostic("connect to mx.example.com timed out");
+        db.apply_observer_event(&delayed).await.unwrap();
+        let failed = DeliveryEvent {
+            observed_at_unix: 1_700_007_200,
+            ..delayed.clone().with_outcome("bounced", "5.4.7", "failed")
+        };
+        db.apply_observer_event(&failed).await.unwrap();
+
+        let state = db.message_state("tracked", None).await.unwrap();
+        let history: Vec<_> = state
+            .history
+            .iter()
+            .map(|event| {
+                (event.action.as_deref(), event.status_code.as_str(), event.occurred_at_unix)
+            })
+            .collect();
+        assert_eq!(
+            history,
+            [(Some("delayed"), "4.4.1", 1_700_000_000), (Some("failed"), "5.4.7", 1_700_007_200)]
+        );
+        let latest = state.bounce.unwrap();
+        assert_eq!(
+            (latest.action.as_deref(), latest.created_at_unix),
+            (Some("failed"), 1_700_007_200)
+        );
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_admin_queries_for_known_unknown_and_empty_hashes() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES ('tracked', 3)")
+            .execute(pool)
+            .await
+            .unwrap();
+        let bounce = |hash: &str| ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: hash.to_string(),
+            status_code: "5.1.1".to_string(),
+            action: Some("failed".to_string()),
+            sender: None,
+            recipient: Some("user@example.com".to_string()),
+            description: Some("user unknown".to_string()),
+            scan_labels: Vec::new(),
+            tenant: None
+        };
+        db.upsert_bounce(&bounce("tracked")).await.unwrap();
+        db.upsert_bounce(&bounce("orphan")).await.unwrap();
+
+        let tracked = db.message_state("tracked", None).await.unwrap();
+        assert!(tracked.message_id.is_some());
+        assert_eq!(tracked.mail_status, Some(MAIL_STATUS_FAILED));
+        assert_eq!(tracked.bounce.map(|bounce| bounce.tracked), Some(true));
+        let orphan = db.message_state("orphan", None).await.unwrap();
+        assert_eq!((orphan.message_id, orphan.mail_status), (None, None));
+        let orphan = orphan.bounce.unwrap();
+        assert_eq!(
+            (orphan.tracked, orphan.recipient.as_deref()),
+            (false, Some("user@example.com"))
+        );
+        for hash in ["unknown", ""] {
+            let state = db.message_state(hash, None).await.unwrap();
+            assert_eq!(state.hash, hash);
+            assert_eq!(
+                (state.message_id, state.mail_status, state.updated_at_unix),
+                (None, None, None)
+            );
+            assert!(state.bounce.is_none() && state.history.is_empty());
+        }
+
+        let recent: Vec<_> = db
+            .recent_bounces(3_600, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|bounce| (bounce.hash, bounce.tracked))
+            .collect();
+        assert_eq!(recent.len(), 2);
+        assert!(recent.contains(&("tracked".to_string(), true)));
+        assert!(recent.contains(&("orphan".to_string(), false)));
+        assert_eq!(db.recent_bounces(3_600, 1).await.unwrap().len(), 1);
+        assert_eq!(db.bounce_count_since(3_600).await.unwrap(), 2);
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn tenant_scopes_message_lookups() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        sqlx::query(
+            "INSERT INTO mail_messages (hash, status, tenant) VALUES ('shared', 3, 'shop')"
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        assert!(db.has_local_message("shared", Some("shop")).await.unwrap());
+        assert!(!db.has_local_message("shared", Some("crm")).await.unwrap());
+        assert!(db.has_local_message("shared", None).await.unwrap());
+
+        let bounce = |tenant: Option<&str>| ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: "shared".to_string(),
+            status_code: "5.1.1".to_string(),
+            action: Some("failed".to_string()),
+            sender: None,
+            recipient: None,
+            description: Some("user unknown".to_string()),
+            scan_labels: Vec::new(),
+            tenant: tenant.map(str::to_string)
+        };
+        assert_eq!(
+            db.upsert_bounce(&bounce(Some("crm"))).await.unwrap(),
+            UpsertBounceOutcome::MissingLocalMessage
+        );
+        let orphan_tenant: Option<String> =
+            sqlx::query_scalar("SELECT tenant FROM mail_bounces WHERE hash = 'shared'")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        assert_eq!(orphan_tenant.as_deref(), Some("crm"));
+        assert_eq!(
+            db.upsert_bounce(&bounce(Some("shop"))).await.unwrap(),
+            UpsertBounceOutcome::UpdatedLocalMessage
+        );
+
+        let lonely = |tenant: Option<&str>, status_code: &str| ParsedBounce {
+            hash: "lonely".to_string(),
+            status_code: status_code.to_string(),
+            ..bounce(tenant)
+        };
+        db.upsert_bounce(&lonely(Some("crm"), "5.1.1")).await.unwrap();
+        db.upsert_bounce(&lonely(None, "4.2.2")).await.unwrap();
+        db.upsert_bounce(&lonely(Some("crm"), "5.1.1")).await.unwrap();
+        let orphans: Vec<(Option<String>, String, i64)> = sqlx::query_as(
+            "SELECT tenant, status_code, occurrence_count FROM mail_bounces WHERE hash = 'lonely' ORDER BY tenant"
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            orphans,
+            [(None, "4.2.2".to_string(), 1), (Some("crm".to_string()), "5.1.1".to_string(), 2)]
+        );
+        let state = db.message_state("lonely", Some("crm")).await.unwrap();
+        assert_eq!(state.bounce.unwrap().status_code, "5.1.1");
+        assert!(db.message_state("lonely", Some("shop")).await.unwrap().bounce.is_none());
+
+        let event = DeliveryEvent::new("observer-1", "shared", "ABC123", "user@example.com", 1)
+            .with_outcome("bounced", "5.1.1", "failed");
+        db.apply_observer_event(&event.clone().with_tenant("crm")).await.unwrap();
+        assert_eq!(db.message_state("shared", None).await.unwrap().history.len(), 1);
+        db.apply_observer_event(&event.with_tenant("shop")).await.unwrap();
+        assert_eq!(db.message_state("shared", None).await.unwrap().history.len(), 2);
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn message_cache_rechecks_misses_and_stale_ids() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap()
+        .with_message_cache(
+            MessageCacheConfig {
+                max_entries: 100,
+                ttl: Duration::from_secs(60),
+                negative_ttl: Duration::from_secs(60)
+            },
+            system_clock()
+        );
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        let bounce = |status_code: &str| ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: "late".to_string(),
+            status_code: status_code.to_string(),
+            action: Some("failed".to_string()),
+            sender: None,
+            recipient: None,
+            description: None,
+            scan_labels: Vec::new(),
+            tenant: None
+        };
+
+        assert_eq!(
+            db.upsert_bounce(&bounce("5.1.1")).await.unwrap(),
+            UpsertBounceOutcome::MissingLocalMessage
+        );
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES ('late', 3)")
+            .execute(pool)
+            .await
+            .unwrap();
+        // The miss is still cached until a retry check asks the database.
+        assert_eq!(
+            db.upsert_bounce(&bounce("5.2.2")).await.unwrap(),
+            UpsertBounceOutcome::MissingLocalMessage
+        );
+        assert!(db.has_local_message("late", None).await.unwrap());
+        assert_eq!(
+            db.upsert_bounce(&bounce("5.2.2")).await.unwrap(),
+            UpsertBounceOutcome::UpdatedLocalMessage
+        );
+
+        // A cached id whose row was replaced is dropped and looked up again.
+        sqlx::query("DELETE FROM mail_messages WHERE hash = 'late'").execute(pool).await.unwrap();
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES ('late', 3)")
+            .execute(pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.upsert_bounce(&bounce("5.1.1")).await.unwrap(),
+            UpsertBounceOutcome::UpdatedLocalMessage
+        );
+        let (status, bounces): (i64, i64) = sqlx::query_as(
+            "SELECT status, (SELECT COUNT(*) FROM mail_message_bounces WHERE message_id = mail_messages.id) FROM mail_messages WHERE hash = 'late'"
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!((status, bounces), (i64::from(MAIL_STATUS_FAILED), 1));
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn legacy_schema_without_optional_columns_runs_degraded() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let url = format!("sqlite:{}", path.display());
+        let legacy = Pool::connect(&url).await.unwrap();
+        let Pool::Sqlite(pool) = &legacy else { panic!("expected sqlite backend") };
+        for ddl in [
+            "CREATE TABLE mail_messages (id INTEGER PRIMARY KEY AUTOINCREMENT, hash VARCHAR(64) NOT NULL UNIQUE, status INTEGER NOT NULL DEFAULT 0, updated_at DATETIME NULL)",
+            "CREATE TABLE mail_message_bounces (message_id INTEGER NOT NULL UNIQUE, action VARCHAR(32) NULL, status_code VARCHAR(20) NOT NULL, description TEXT NULL, created_at DATETIME NOT NULL)",
+            "CREATE TABLE mail_bounces (hash VARCHAR(64) NOT NULL UNIQUE, recipient VARCHAR(320) NULL, action VARCHAR(32) NULL, status_code VARCHAR(20) NOT NULL, description TEXT NULL, created_at DATETIME NOT NULL, last_seen_at DATETIME NULL, occurrence_count INTEGER NOT NULL DEFAULT 1)",
+            "CREATE TABLE processed_spool_messages (idempotency_key CHAR(64) NOT NULL PRIMARY KEY, hash VARCHAR(64) NOT NULL, processed_at DATETIME NOT NULL)",
+            "INSERT INTO mail_messages (hash, status) VALUES ('tracked', 3)"
+        ] {
+            sqlx::query(ddl).execute(pool).await.unwrap();
+        }
+        pool.close().await;
+
+        let db = Database::connect(
+            &url,
+            MigrateMode::Off,
+            SuppressionConfig::default(),
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            db.schema.message_bounces,
+            BounceColumns { occurrences: false, reason: false, category: false }
+        );
+        assert_eq!(
+            db.schema.orphan_bounces,
+            BounceColumns { occurrences: true, reason: false, category: false }
+        );
+        assert!(!db.schema.observer_order);
+        assert!(!db.schema.bounce_events);
+        assert!(!db.schema.message_tenant && !db.schema.orphan_tenant);
+
+        let bounce = |hash: &str| ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: hash.to_string(),
+            status_code: "5.1.1".to_string(),
+            action: Some("failed".to_string()),
+            sender: None,
+            recipient: None,
+            description: Some("user unknown".to_string()),
+            scan_labels: Vec::new(),
+            tenant: None
+        };
+        for hash in ["tracked", "tracked", "orphan", "orphan"] {
+            db.upsert_bounce(&bounce(hash)).await.unwrap();
+        }
+
+        let tracked = db.message_state("tracked", None).await.unwrap();
+        assert!(tracked.history.is_empty());
+        let tracked = tracked.bounce.unwrap();
+        assert_eq!((tracked.reason, tracked.occurrence_count), (None, None));
+        let orphan = db.message_state("orphan", None).await.unwrap().bounce.unwrap();
+        assert_eq!((orphan.reason, orphan.occurrence_count), (None, Some(2)));
+        assert_eq!(db.recent_bounces(3_600, 10).await.unwrap().len(), 2);
+
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn stores_source_events_with_their_timestamp() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+
+        let event = SourceEvent {
+            source: "mail-01".to_string(),
+            transition: "expired".to_string(),
+            detail: Some("silent_secs=320".to_string()),
+            at_unix: 1_760_000_000
+        };
+        db.insert_source_events(&[event]).await.unwrap();
+
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        let (transition, occurred_at): (String, i64) = sqlx::query_as(
+            "SELECT transition, CAST(strftime('%s', occurred_at) AS INTEGER) FROM source_events"
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!((transition.as_str(), occurred_at), ("expired", 1_760_000_000));
+
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap();
+        let recent = SourceEvent {
+            source: "mail-01".to_string(),
+            transition: "recovered".to_string(),
+            detail: None,
+            at_unix: now.as_secs()
+        };
+        db.insert_source_events(&[recent]).await.unwrap();
+        let retention = Duration::from_secs(30 * 24 * 3600);
+        assert_eq!(db.prune_source_events(retention).await.unwrap(), 1);
+        let left: Vec<String> = sqlx::query_scalar("SELECT transition FROM source_events")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+        assert_eq!(left, ["recovered"]);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn prunes_bounce_audit_after_its_retention() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            Arc::new(Faults::default())
+        )
+        .await
+        .unwrap();
+
+        let now = std::time::SystemTime::now();
+        let audit = |hash: &str, processed_at| BounceAudit {
+            hash: hash.to_string(),
+            ingest_path: IngestPath::Tcp,
+            source: None,
+            origin: format!("{hash}.eml"),
+            received_at: processed_at,
+            processed_at,
+            scan_labels: vec!["dsn".to_string()],
+            outcome: "applied"
+        };
+        db.insert_bounce_audit(&audit("old", now - Duration::from_secs(91 * 24 * 3600)))
+            .await
+            .unwrap();
+        db.insert_bounce_audit(&audit("new", now)).await.unwrap();
+
+        let retention = Duration::from_secs(90 * 24 * 3600);
+        assert_eq!(db.prune_bounce_audit(retention).await.unwrap(), 1);
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        let left: Vec<String> =
+            sqlx::query_scalar("SELECT hash FROM mail_bounce_audit").fetch_all(pool).await.unwrap();
+        assert_eq!(left, ["new"]);
+
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_transient_write_failures_and_opens_breaker() {
+        let path = std::env::temp_dir().join(format!("bouncer-db-{}.sqlite", Uuid::now_v7()));
+        let faults = Arc::new(Faults::default());
+        let db = Database::connect(
+            &format!("sqlite:{}", path.display()),
+            MigrateMode::Auto,
+            SuppressionConfig::default(),
+            faults.clone()
+        )
+        .await
+        .unwrap()
+        .with_resilience(DatabaseResilienceConfig {
+            retries: 2,
+            retry_base: Duration::from_millis(1),
+            retry_max: Duration::from_millis(1),
+            breaker_threshold: 1,
+            ..DatabaseResilienceConfig::default()
+        });
+        let bounce = ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: "orphan".to_string(),
+            status_code: "5.1.1".to_string(),
+            action: Some("failed".to_string()),
+            sender: None,
+            recipient: None,
+            description: Some("user unknown".to_string()),
+            scan_labels: Vec::new(),
+            tenant: None
+        };
+
+        faults.fail_next_db_writes(2);
+        assert_eq!(
+            db.upsert_bounce(&bounce).await.unwrap(),
+            UpsertBounceOutcome::MissingLocalMessage
+        );
+        assert!(!db.pool_status().breaker_open);
+
+        faults.fail_next_db_writes(3);
+        assert!(is_transient(&db.upsert_bounce(&bounce).await.unwrap_err()));
+        assert!(db.pool_status().breaker_open);
+        // Open breaker: fails fast without reaching the armed fault.
+        faults.fail_next_db_writes(1);
+        let err = db.upsert_bounce(&bounce).await.unwrap_err();
+        assert!(err.chain().any(|cause| cause.is::<DatabaseUnavailable>()));
+
+        db.ping().await.unwrap();
+        db.breaker().record_success();
+        assert_eq!(
+            db.upsert_bounce(&bounce).await.unwrap(),
+            UpsertBounceOutcome::MissingLocalMessage
+        );
+
+        let Pool::Sqlite(pool) = &db.pool else { panic!("expected sqlite backend") };
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! SIGUSR1/SIGUSR2 handling and the diagnostics dump.
+//!
+//! SIGUSR1 toggles debug logging, see [`bouncer_helpers::debug_signals`].
+//! SIGUSR2 logs [`diagnostics_dump`] one section per line: queue depths,
+//! open client connections, per-source counters, the IMAP mailbox loops and
+//! the database pool. The admin API serves the same dump as
+//! `GET /diagnostics`.
+
+use anyhow::Result;
+use bouncer_helpers::debug_signals::{DebugSignal, DebugSignals, apply_debug_logging};
+use bouncer_helpers::logging;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+
+use super::status::server_status;
+use crate::app::AppState;
+
+/// Handles SIGUSR1 and SIGUSR2 until shutdown.
+pub async fn run_debug_signals(state: AppState) {
+    let mut signals = DebugSignals::install();
+    loop {
+        let signal = tokio::select! {
+            _ = state.shutdown.cancelled() => break,
+            signal = signals.recv() => signal
+        };
+        match signal {
+            DebugSignal::ToggleDebugLogging => {
+                info!("debug logging signal received: SIGUSR1");
+                apply_debug_logging(!logging::debug_logging());
+            }
+            DebugSignal::DumpDiagnostics => {
+                info!("diagnostics signal received: SIGUSR2");
+                log_diagnostics(&state).await;
+            }
+        }
+    }
+}
+
+/// Logs [`diagnostics_dump`] at warn, so it shows under the usual filters.
+async fn log_diagnostics(state: &AppState) {
+    let dump = match diagnostics_dump(state).await {
+        Ok(dump) => dump,
+        Err(err) => {
+            warn!("diagnostics dump failed: error={err:#}");
+            return;
+        }
+    };
+    if let Value::Object(sections) = dump {
+        for (section, value) in sections {
+            warn!("diagnostics: section={section}, value={value}");
+        }
+    }
+}
+
+/// What the server is doing right now, as JSON.
+pub async fn diagnostics_dump(state: &AppState) -> Result<Value> {
+    let status = server_status(state).await?;
+    let now = state.clock.now();
+    let connections = state.connections.snapshot();
+    let hooks: serde_json::Map<String, Value> = state
+        .db
+        .hooks()
+        .queued()
+        .into_iter()
+        .map(|(hook, queued)| (hook.to_string(), queued.into()))
+        .collect();
+
+    Ok(json!({
+        "process": {
+            "uptime_secs": status.uptime_secs,
+            "debug_logging": logging::debug_logging(),
+            "frame_audit": state.frame_audit.is_enabled()
+        },
+        "spool": status.spool,
+        "queues": {
+            "lanes": status.queue,
+            "in_flight": state.in_flight.len(),
+            "missing_message_retries": state.retries.pending(),
+            "db_retries": state.db_retries.pending(),
+            "observer_batch": state.db.batcher().queued(),
+            "hooks": hooks
+        },
+        "workers": status.workers,
+        "connections": {
+            "accepted_total": connections.accepted_total,
+            "rejected_total": connections.rejected_total,
+            "storms": connections.storms,
+            "open": state.sources.open_connections(now)
+        },
+        "sources": state.sources.snapshot(now),
+        "imap": status.imap,
+        "db": status.db
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Instant;
+
+    use uuid::Uuid;
+
+    use super::diagnostics_dump;
+    use crate::app::AppState;
+    use crate::core::sources::ConnectedSources;
+
+    #[tokio::test]
+    async fn dumps_queues_connections_and_imap_state() {
+        let root = std::env::temp_dir().join(format!("bouncer-debug-dump-{}", Uuid::now_v7()));
+        let state = AppState::for_tests(&root).await;
+        let peer = SocketAddr::from(([192, 0, 2, 1], 40_000));
+        let mut connected = ConnectedSources::new(state.sources.clone(), peer);
+        connected.register("mail-01", Instant::now());
+        state.status.imap_enabled("bounces@mail.example.com", "INBOX");
+
+        let dump = diagnostics_dump(&state).await.unwrap();
+        assert_eq!(dump["spool"]["incoming"], 0);
+        assert_eq!(dump["queues"]["in_flight"], 0);
+        assert!(dump["queues"]["observer_batch"].is_null());
+        assert_eq!(dump["connections"]["open"][0]["peer"], "192.0.2.1:40000");
+        assert_eq!(dump["connections"]["open"][0]["sources"][0], "mail-01");
+        assert_eq!(dump["sources"][0]["registered"], true);
+        assert_eq!(dump["imap"]["mailboxes"][0]["mailbox"], "INBOX");
+
+        drop(connected);
+        let dump = diagnostics_dump(&state).await.unwrap();
+        assert_eq!(dump["connections"]["open"].as_array().map(Vec::len), Some(0));
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+}
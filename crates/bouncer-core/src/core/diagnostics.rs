@@ -0,0 +1,383 @@
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use bouncer_helpers::clock::SharedClock;
+use bouncer_helpers::coded_warn;
+use bouncer_helpers::error_code::ErrorCode;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use super::database::Database;
+use super::imap::check_imap_login;
+use super::spool::Spool;
+use crate::config::Config;
+
+/// Tables the server writes to, their required columns and the column that
+/// must lead an index for the per-bounce lookups to stay cheap. Optional
+/// bounce columns from later migrations are probed by the database instead.
+const REQUIRED_SCHEMA: [(&str, &[&str], &str); 3] = [
+    ("mail_messages", &["id", "hash", "status", "updated_at"], "hash"),
+    (
+        "mail_message_bounces",
+        &["message_id", "action", "status_code", "description", "created_at"],
+        "message_id"
+    ),
+    (
+        "mail_bounces",
+        &["hash", "recipient", "action", "status_code", "description", "created_at"],
+        "hash"
+    )
+];
+
+/// Wall clock readings before 2024-01-01T00:00:00Z are treated as an unset RTC.
+const MIN_SANE_UNIX_SECS: i64 = 1_704_067_200;
+
+const PROBE_FILE_NAME: &str = ".bouncer-startup-probe";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CheckStatus {
+    Ok,
+    Warn,
+    Fail
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ok => "ok",
+            Self::Warn => "warn",
+            Self::Fail => "fail"
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Check {
+    name: &'static str,
+    status: CheckStatus,
+    detail: String
+}
+
+/// Outcome of the startup checks, logged line by line.
+#[derive(Debug, Default)]
+struct DiagnosticsReport {
+    checks: Vec<Check>
+}
+
+impl DiagnosticsReport {
+    fn push(
+        &mut self,
+        name: &'static str,
+        status: CheckStatus,
+        detail: impl Into<String>
+    ) {
+        self.checks.push(Check { name, status, detail: detail.into() });
+    }
+
+    /// Logs every check and, with `strict`, turns failed checks into an error.
+    fn finish(
+        self,
+        strict: bool
+    ) -> Result<()> {
+        for check in &self.checks {
+            match check.status {
+                CheckStatus::Ok => {
+                    info!("startup check passed: check={}, detail={}", check.name, check.detail)
+                }
+                status => coded_warn!(
+                    ErrorCode::StartupCheck,
+                    "startup check reported a problem: check={}, status={}, detail={}",
+                    check.name,
+                    status,
+                    check.detail
+                )
+            }
+        }
+
+        let failed: Vec<&Check> =
+            self.checks.iter().filter(|check| check.status == CheckStatus::Fail).collect();
+        let warned = self.checks.iter().filter(|check| check.status == CheckStatus::Warn).count();
+        info!(
+            "startup diagnostics finished: checks={}, failed={}, warned={}, strict_startup={}",
+            self.checks.len(),
+            failed.len(),
+            warned,
+            strict
+        );
+
+        if failed.is_empty() {
+            return Ok(());
+        }
+        if strict {
+            let summary = failed
+                .iter()
+                .map(|check| format!("{}: {}", check.name, check.detail))
+                .collect::<Vec<_>>()
+                .join("; ");
+            bail!("startup diagnostics failed (strict_startup=true): {summary}");
+        }
+        warn!(
+            "continuing despite failed startup checks: failed={} (set strict_startup: true to refuse to start)",
+            failed.len()
+        );
+        Ok(())
+    }
+}
+
+/// Checks the database schema, spool directories, listen addresses, clock and,
+/// when enabled, the IMAP login before any subsystem starts.
+///
+/// Every problem is logged with a hint on how to fix it. With
+/// `strict_startup` a failed check aborts the start; otherwise the server
+/// carries on and the problem surfaces again at runtime.
+pub async fn run_startup_diagnostics(
+    config: &Config,
+    spool: &Spool,
+    db: &Database,
+    clock: &SharedClock
+) -> Result<()> {
+    info!("startup diagnostics running: strict_startup={}", config.strict_startup);
+    let mut report = DiagnosticsReport::default();
+
+    check_schema(db, &mut report).await;
+    check_spool(spool, config.diagnostics.min_free_spool_mb, &mut report).await;
+    for listen in &config.listen {
+        check_listen("listen", listen, &mut report).await;
+    }
+    if let Some(smtp_listen) = &config.smtp_listen {
+        check_listen("smtp_listen", smtp_listen, &mut report).await;
+    }
+    if let Some(admin_listen) = &config.admin_listen {
+        check_listen("admin_listen", admin_listen, &mut report).await;
+    }
+    check_clock(db, clock, config.diagnostics.max_clock_skew_secs, &mut report).await;
+
+    for imap in config.imap.iter().filter(|imap| imap.enabled()) {
+        if !config.diagnostics.imap_login {
+            report.push("imap_login", CheckStatus::Ok, "skipped (diagnostics.imap_login=false)");
+            break;
+        }
+        match check_imap_login(imap).await {
+            Ok(()) => report.push(
+                "imap_login",
+                CheckStatus::Ok,
+                format!("login succeeded: account={}", imap.label())
+            ),
+            Err(err) => report.push(
+                "imap_login",
+                CheckStatus::Fail,
+                format!(
+                    "account={}: {err:#}; check imap.host/port/user/pass and that the host is reachable",
+                    imap.label()
+                )
+            )
+        }
+    }
+
+    report.finish(config.strict_startup)
+}
+
+async fn check_schema(
+    db: &Database,
+    report: &mut DiagnosticsReport
+) {
+    for (table, required, indexed) in REQUIRED_SCHEMA {
+        let columns = match db.table_columns(table).await {
+            Ok(columns) => columns,
+            Err(err) => {
+                report.push("db_schema", CheckStatus::Fail, format!("table={table}: {err:#}"));
+                continue;
+            }
+        };
+        if columns.is_empty() {
+            report.push(
+                "db_schema",
+                CheckStatus::Fail,
+                format!("table={table} missing; set `migrate: auto` or point database_url at the app schema")
+            );
+            continue;
+        }
+
+        let missing: Vec<&str> = required
+            .iter()
+            .copied()
+            .filter(|column| !columns.iter().any(|found| found.eq_ignore_ascii_case(column)))
+            .collect();
+        if !missing.is_empty() {
+            report.push(
+                "db_schema",
+                CheckStatus::Fail,
+                format!("table={table} missing columns: {}", missing.join(","))
+            );
+            continue;
+        }
+
+        match db.indexed_columns(table).await {
+            Ok(leading) if leading.iter().any(|column| column.eq_ignore_ascii_case(indexed)) => {
+                report.push("db_schema", CheckStatus::Ok, format!("table={table}"))
+            }
+            Ok(_) => report.push(
+                "db_schema",
+                CheckStatus::Warn,
+                format!(
+                    "table={table} has no index on `{indexed}`; lookups will scan the table (CREATE INDEX {table}_{indexed}_idx ON {table} ({indexed}))"
+                )
+            ),
+            Err(err) => report.push("db_schema", CheckStatus::Fail, format!("table={table}: {err:#}"))
+        }
+    }
+}
+
+async fn check_spool(
+    spool: &Spool,
+    min_free_mb: u64,
+    report: &mut DiagnosticsReport
+) {
+    let mut unwritable = Vec::new();
+    for dir in [&spool.incoming, &spool.processing, &spool.done, &spool.failed, &spool.quarantine] {
+        if let Err(err) = probe_writable(dir).await {
+            unwritable.push(format!("{} ({err})", dir.display()));
+        }
+    }
+    if unwritable.is_empty() {
+        report.push("spool_permissions", CheckStatus::Ok, spool.root.display().to_string());
+    } else {
+        report.push(
+            "spool_permissions",
+            CheckStatus::Fail,
+            format!(
+                "not writable: {}; fix ownership/permissions for the server user",
+                unwritable.join(", ")
+            )
+        );
+    }
+
+    match fs4::available_space(&spool.root) {
+        Ok(bytes) => {
+            let free_mb = bytes / (1024 * 1024);
+            if free_mb < min_free_mb {
+                report.push(
+                    "spool_free_space",
+                    CheckStatus::Fail,
+                    format!(
+                        "free_mb={free_mb} below diagnostics.min_free_spool_mb={min_free_mb}; free disk space or prune done/ and failed/"
+                    )
+                );
+            } else {
+                report.push("spool_free_space", CheckStatus::Ok, format!("free_mb={free_mb}"));
+            }
+        }
+        Err(err) => report.push(
+            "spool_free_space",
+            CheckStatus::Warn,
+            format!("could not read free space for {}: {err}", spool.root.display())
+        )
+    }
+}
+
+async fn probe_writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(PROBE_FILE_NAME);
+    tokio::fs::write(&probe, b"probe").await?;
+    tokio::fs::remove_file(&probe).await
+}
+
+/// Binds and releases the listen address so a port clash is reported
+/// together with the other checks instead of after every subsystem started.
+async fn check_listen(
+    name: &'static str,
+    listen: &str,
+    report: &mut DiagnosticsReport
+) {
+    match TcpListener::bind(listen).await {
+        Ok(listener) => {
+            drop(listener);
+            report.push(name, CheckStatus::Ok, listen.to_string());
+        }
+        Err(err) => report.push(
+            name,
+            CheckStatus::Fail,
+            format!(
+                "cannot bind {listen}: {err}; stop the process holding the port or change `{name}`"
+            )
+        )
+    }
+}
+
+async fn check_clock(
+    db: &Database,
+    clock: &SharedClock,
+    max_skew_secs: u64,
+    report: &mut DiagnosticsReport
+) {
+    let local = i64::try_from(clock.unix_secs()).unwrap_or(i64::MAX);
+    match db.unix_time().await {
+        Ok(db_unix) => {
+            let (status, detail) = clock_status(local, db_unix, max_skew_secs);
+            report.push("clock", status, detail);
+        }
+        Err(err) => {
+            report.push("clock", CheckStatus::Warn, format!("database time unavailable: {err:#}"))
+        }
+    }
+}
+
+fn clock_status(
+    local_unix: i64,
+    db_unix: i64,
+    max_skew_secs: u64
+) -> (CheckStatus, String) {
+    if local_unix < MIN_SANE_UNIX_SECS {
+        return (
+            CheckStatus::Fail,
+            format!("system clock reads unix={local_unix}, before 2024; enable NTP/chrony")
+        );
+    }
+
+    let skew = local_unix.abs_diff(db_unix);
+    if skew > max_skew_secs {
+        (
+            CheckStatus::Warn,
+            format!(
+                "system and database clocks differ by {skew}s (max {max_skew_secs}s); bounce timestamps and history windows will drift, sync both hosts via NTP"
+            )
+        )
+    } else {
+        (CheckStatus::Ok, format!("skew_secs={skew}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::{CheckStatus, DiagnosticsReport, MIN_SANE_UNIX_SECS, check_listen, clock_status};
+
+    #[tokio::test]
+    async fn strict_startup_fails_on_busy_listen_port() {
+        let held = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listen = held.local_addr().unwrap().to_string();
+
+        let mut report = DiagnosticsReport::default();
+        check_listen("listen", &listen, &mut report).await;
+        assert_eq!(report.checks[0].status, CheckStatus::Fail);
+
+        let err = report.finish(true).expect_err("strict startup must fail");
+        assert!(err.to_string().contains(&format!("listen: cannot bind {listen}")), "{err}");
+
+        let mut report = DiagnosticsReport::default();
+        check_listen("listen", &listen, &mut report).await;
+        assert!(report.finish(false).is_ok());
+    }
+
+    #[test]
+    fn clock_checks_unset_rtc_and_skew() {
+        let now = MIN_SANE_UNIX_SECS + 1_000;
+        assert_eq!(clock_status(now, now + 5, 60).0, CheckStatus::Ok);
+        assert_eq!(clock_status(now, now - 600, 60).0, CheckStatus::Warn);
+        assert_eq!(clock_status(86_400, 86_400, 60).0, CheckStatus::Fail);
+    }
+}
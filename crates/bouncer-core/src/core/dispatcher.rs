@@ -0,0 +1,780 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail};
+use bouncer_helpers::error_code::ErrorCode;
+use bouncer_helpers::{coded_error, coded_warn, logging};
+use notify::{Config as NotifyConfig, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use sha2::{Digest, Sha256};
+use tokio::sync::{Mutex, mpsc};
+use tokio::time::{Duration, interval, sleep};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, info, info_span, warn};
+
+use super::audit::{BounceAudit, IngestPath, audit_outcome, strip_audit_headers};
+use super::database::UpsertBounceOutcome;
+use super::failures::{FailureNote, FailureReason};
+use super::lanes::{LaneReceiver, LaneSender};
+use super::parser::ParsedBounce;
+use super::resilience::is_transient;
+use super::retries::{db_retry_attempts, with_db_retry_attempts};
+use super::spool::Spool;
+use super::status::StageTimings;
+use super::tenants;
+use crate::app::AppState;
+
+/// Delays between attempts to move a processed file out of `processing/`.
+const FINALIZE_RETRY_DELAYS: [Duration; 3] =
+    [Duration::from_millis(100), Duration::from_millis(500), Duration::from_secs(2)];
+
+/// Watches the `incoming/` spool directory (including source partitions) for
+/// new files and forwards discovered `.eml` paths to their lane of the
+/// processing queue.
+pub async fn spawn_notify_watcher(
+    state: AppState,
+    process_tx: LaneSender
+) {
+    if let Err(err) =
+        run_notify_watcher(state.spool.incoming.clone(), state.shutdown.clone(), process_tx).await
+    {
+        coded_error!(
+            ErrorCode::NotifyWatcherFailed,
+            "notify watcher stopped, relying on periodic scan: error={err}"
+        );
+    }
+}
+
+async fn run_notify_watcher(
+    incoming_dir: PathBuf,
+    shutdown: CancellationToken,
+    process_tx: LaneSender
+) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<notify::Result<Event>>();
+
+    let mut watcher = match RecommendedWatcher::new(
+        move |result| {
+            let _ = tx.send(result);
+        },
+        NotifyConfig::default()
+    ) {
+        Ok(w) => w,
+        Err(err) => {
+            return Err(anyhow::anyhow!("failed to create notify watcher: {err}"));
+        }
+    };
+
+    watcher
+        .watch(&incoming_dir, RecursiveMode::Recursive)
+        .with_context(|| format!("failed to watch incoming spool: {}", incoming_dir.display()))?;
+
+    info!("notify watcher active: path={}", incoming_dir.display());
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                info!("notify watcher stopping");
+                break;
+            }
+            maybe_event = rx.recv() => {
+                let Some(result) = maybe_event else {
+                    break;
+                };
+
+                match result {
+                    Ok(event) => {
+                        for path in event.paths {
+                            if is_eml_file(&path)
+                                && !process_tx.send(path).await {
+                                    info!("notify watcher stopping: process queue closed");
+                                    break;
+                                }
+                        }
+                    }
+                    Err(err) => warn!("watch event error: error={err}"),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Periodically scans `incoming/` as a fallback for missed filesystem events.
+///
+/// Discovered `.eml` files are pushed into the same processing queue used by
+/// the notify watcher, skipping paths still in flight. Each tick reads the
+/// spool in `scan_batch_size` batches and queues at most `scan_max_per_tick`
+/// files, so a large backlog at startup is fed in over several ticks instead
+/// of flooding the lanes. Low-lane files dropped because their lane was full
+/// are picked up again here. The admin API can ask for a scan right away.
+pub async fn spawn_periodic_scan(
+    state: AppState,
+    process_tx: LaneSender,
+    scan_secs: u64
+) {
+    let mut ticker = interval(Duration::from_secs(scan_secs.max(1)));
+
+    loop {
+        tokio::select! {
+            _ = state.shutdown.cancelled() => {
+                info!("incoming scan loop stopping");
+                break;
+            }
+            _ = state.triggers.incoming_scan.notified() => {
+                ticker.reset_immediately();
+            }
+            _ = ticker.tick() => {
+                if !scan_incoming(&state, &process_tx).await {
+                    info!("incoming scan loop stopping: process queue closed");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// One periodic scan pass; returns `false` once the process queue closed.
+async fn scan_incoming(
+    state: &AppState,
+    process_tx: &LaneSender
+) -> bool {
+    let limit = state.dispatcher.scan_max_per_tick;
+    let mut batches = state.spool.incoming_batches(state.dispatcher.scan_batch_size);
+    let mut queued = 0;
+
+    while let Some(batch) = batches.recv().await {
+        let paths = match batch {
+            Ok(paths) => paths,
+            Err(err) => {
+                warn!("incoming scan failed: error={err:#}");
+                continue;
+            }
+        };
+        for path in paths {
+            if state.in_flight.contains(&path) {
+                continue;
+            }
+            if queued >= limit {
+                info!(
+                    "incoming scan reached its per-tick cap, rest waits for the next tick: queued={}",
+                    queued
+                );
+                return true;
+            }
+            if !process_tx.send(path).await {
+                return false;
+            }
+            queued += 1;
+        }
+    }
+    true
+}
+
+/// Consumes queued spool paths and executes bounded concurrent workers.
+///
+/// Concurrency is limited by a fixed worker count to avoid unbounded task
+/// growth and to protect DB and disk I/O. Workers pull from the high lane
+/// first and give the low lane a turn every `high_weight` messages. Stage
+/// timings of every file go to the status latency histograms; files over
+/// `slow_file_threshold` are logged as `SLOW_SPOOL_FILE`. Workers stop taking
+/// files while the database circuit breaker is open.
+pub async fn spawn_worker_dispatcher(
+    state: AppState,
+    process_rx: LaneReceiver,
+    concurrency: usize
+) {
+    let workers = concurrency.max(1);
+    let shared_rx = Arc::new(Mutex::new(process_rx));
+    let mut handles = Vec::with_capacity(workers);
+
+    info!("worker dispatcher started: workers={}", workers);
+    state.status.set_workers(workers);
+
+    for worker_id in 0..workers {
+        let state = state.clone();
+        let shared_rx = shared_rx.clone();
+
+        handles.push(tokio::spawn(async move {
+            loop {
+                // Leave queued files where they are while the database is down.
+                if state.db.breaker().is_open() {
+                    tokio::select! {
+                        _ = state.shutdown.cancelled() => break,
+                        _ = state.db.breaker().wait_closed() => {}
+                    }
+                }
+
+                let recv_next = async {
+                    let mut rx = shared_rx.lock().await;
+                    rx.recv().await
+                };
+
+                tokio::select! {
+                    _ = state.shutdown.cancelled() => {
+                        break;
+                    }
+                    maybe_path = recv_next => {
+                        let Some((lane, path)) = maybe_path else {
+                            break;
+                        };
+
+                        let _busy = state.status.busy_worker();
+                        let started = state.clock.now();
+                        let mut timings = StageTimings::default();
+                        let result = process_spooled_message(state.clone(), &path, &mut timings).await;
+                        timings.total = state.clock.now().saturating_duration_since(started);
+
+                        if !matches!(result, Ok(Processed::Skipped)) {
+                            let slow = state
+                                .dispatcher
+                                .slow_file_threshold
+                                .is_some_and(|threshold| timings.total >= threshold);
+                            state.status.record_processing(worker_id, &timings, slow);
+                            if slow {
+                                coded_warn!(
+                                    ErrorCode::SlowSpoolFile,
+                                    "slow spool file: worker={}, lane={}, path={}, total_ms={}, read_ms={}, parse_ms={}, upsert_ms={}, finalize_ms={}, ok={}",
+                                    worker_id,
+                                    lane.as_str(),
+                                    path.display(),
+                                    timings.total.as_millis(),
+                                    stage_ms(timings.read),
+                                    stage_ms(timings.parse),
+                                    stage_ms(timings.upsert),
+                                    stage_ms(timings.finalize),
+                                    result.is_ok()
+                                );
+                            }
+                        }
+                        state.in_flight.release(&path);
+                        if let Err(err) = result {
+                            coded_warn!(
+                                ErrorCode::MessageProcessingFailed,
+                                "message processing failed: worker={}, lane={}, path={}, error={}",
+                                worker_id,
+                                lane.as_str(),
+                                path.display(),
+                                err
+                            );
+                        }
+                    }
+                }
+            }
+        }));
+    }
+
+    for handle in handles {
+        if let Err(err) = handle.await {
+            coded_warn!(ErrorCode::WorkerJoinFailed, "worker task join failed: error={err}");
+        }
+    }
+
+    info!("worker dispatcher stopping");
+}
+
+/// Moves a message through `incoming -> processing -> done/failed` and applies
+/// parsed bounce status to the database.
+///
+/// A bounce for a hash not in `mail_messages` yet stays in `processing/` while
+/// `missing_message_retry` has delays left (see `MissingMessageRetries`).
+/// A file that failed on a transient database error goes back to
+/// `incoming/` instead of `failed/`, and one that failed on another database
+/// error waits in `processing/` while `db_retry` has delays left (see
+/// `DbRetries`). Any other failure leaves a
+/// [`FailureNote`] next to the failed file.
+/// Each stage the file reaches is timed into `timings`.
+async fn process_spooled_message(
+    state: AppState,
+    incoming_path: &Path,
+    timings: &mut StageTimings
+) -> Result<Processed> {
+    if !is_eml_file(incoming_path) {
+        return Ok(Processed::Skipped);
+    }
+
+    let file_name = incoming_path.file_name().context("incoming path has no file name")?;
+    let process_span = info_span!("process", file = %file_name.to_string_lossy());
+    logging::set_remote_parent(&process_span, state.traces.take(incoming_path).as_deref());
+
+    let processing_path =
+        state.spool.relocate(incoming_path, &state.spool.incoming, &state.spool.processing).await?;
+
+    match state.spool.rename(incoming_path, &processing_path).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Processed::Skipped),
+        Err(err) => {
+            return Err(err).with_context(|| {
+                format!(
+                    "failed to move file into processing: {} -> {}",
+                    incoming_path.display(),
+                    processing_path.display()
+                )
+            });
+        }
+    }
+
+    let tenant = tenants::tenant_of_path(incoming_path);
+    let mut upsert_started = None;
+    let mut audit = None;
+    let result = async {
+        let stage = state.clock.now();
+        let raw_mail = tokio::fs::read(&processing_path)
+            .await
+            .with_context(|| format!("failed to read {}", processing_path.display()))?;
+        timings.read = Some(state.clock.now().saturating_duration_since(stage));
+
+        if raw_mail.is_empty() {
+            bail!(FailureReason::EmptyPayload);
+        }
+
+        let stage = state.clock.now();
+        let parsed = info_span!("parse").in_scope(|| state.parsers.load().parse(&raw_mail));
+        timings.parse = Some(state.clock.now().saturating_duration_since(stage));
+        let mut parsed = parsed?;
+        parsed.tenant = tenant.clone();
+
+        // Timed after the block so failed and early-return paths count too.
+        upsert_started = Some(state.clock.now());
+        if state.retries.enabled()
+            && !state.db.has_local_message(&parsed.hash, parsed.tenant.as_deref()).await?
+            && let Some((attempt, delay)) = state.retries.defer(&processing_path, state.clock.now())
+        {
+            info!(
+                "local message not found yet, retrying later: path={}, hash={}, attempt={}, delay={}",
+                processing_path.display(),
+                parsed.hash,
+                attempt,
+                humantime::format_duration(delay)
+            );
+            return Ok(Processed::Deferred);
+        }
+
+        let idempotency_key = idempotency_key(&raw_mail);
+        if state.authenticator.enabled() {
+            let outcome =
+                state.authenticator.check(&raw_mail).instrument(info_span!("authenticate")).await;
+            state
+                .db
+                .record_authentication(&idempotency_key, &parsed.hash, &outcome)
+                .await
+                .context("failed to record bounce authentication")?;
+            if !outcome.passed() {
+                coded_warn!(
+                    ErrorCode::BounceUnauthenticated,
+                    "bounce failed authentication: path={}, hash={}, dkim={}, dkim_domain={}, relay={}, relay_host={}, rejected={}",
+                    processing_path.display(),
+                    parsed.hash,
+                    outcome.dkim.as_str(),
+                    outcome.dkim_domain.as_deref().unwrap_or("-"),
+                    outcome.relay.as_str(),
+                    outcome.relay_host.as_deref().unwrap_or("-"),
+                    state.authenticator.rejects()
+                );
+                if state.authenticator.rejects() {
+                    bail!(FailureReason::Unauthenticated);
+                }
+            }
+        }
+        let applied = state
+            .db
+            .upsert_bounce_once(&parsed, &idempotency_key)
+            .instrument(info_span!("db", op = "upsert_bounce_once"))
+            .await
+            .context("database upsert failed")?;
+
+        if state.audit.enabled {
+            let record = spool_audit(&state, incoming_path, &processing_path, &parsed, applied).await;
+            if let Err(err) = state.db.insert_bounce_audit(&record).await {
+                warn!(
+                    "failed to record bounce audit: path={}, hash={}, error={:#}",
+                    processing_path.display(),
+                    parsed.hash,
+                    err
+                );
+            }
+            audit = Some(record);
+        }
+
+        if applied.is_none() {
+            info!(
+                "message already processed, skipping db write: path={}, hash={}, idempotency_key={}",
+                processing_path.display(),
+                parsed.hash,
+                idempotency_key
+            );
+            return Ok(Processed::Applied);
+        }
+        if applied == Some(UpsertBounceOutcome::Duplicate) {
+            info!(
+                "bounce already applied via another path, skipping db write: path={}, hash={}, status_code={}",
+                processing_path.display(),
+                parsed.hash,
+                parsed.status_code
+            );
+            return Ok(Processed::Applied);
+        }
+
+        if state.archive.enabled() {
+            // Best effort: the bounce is applied, losing its copy must not
+            // send it to `failed/`.
+            if let Err(err) = state
+                .archive
+                .store(&state.db, &parsed.hash, &idempotency_key, &raw_mail)
+                .instrument(info_span!("archive"))
+                .await
+            {
+                coded_warn!(
+                    ErrorCode::BounceArchiveFailed,
+                    "failed to archive bounce: path={}, hash={}, error={err:#}",
+                    processing_path.display(),
+                    parsed.hash
+                );
+            }
+        }
+
+        info!(
+            "processed message: path={}, bytes={}, kind={}, hash={}, status_code={}, action={}, recipient={}",
+            processing_path.display(),
+            raw_mail.len(),
+            parsed.kind.as_str(),
+            parsed.hash,
+            parsed.status_code,
+            parsed.action.as_deref().unwrap_or("-"),
+            parsed.recipient.as_deref().unwrap_or("-")
+        );
+
+        Ok::<_, anyhow::Error>(Processed::Applied)
+    }
+    .instrument(process_span)
+    .await;
+    timings.upsert = upsert_started.map(|stage| state.clock.now().saturating_duration_since(stage));
+
+    if matches!(result, Ok(Processed::Deferred)) {
+        return result;
+    }
+    if let Err(err) = &result
+        && is_transient(err)
+    {
+        state.spool.rename(&processing_path, incoming_path).await.with_context(|| {
+            format!(
+                "failed to move file back to incoming: {} -> {}",
+                processing_path.display(),
+                incoming_path.display()
+            )
+        })?;
+        warn!(
+            "database unavailable, file returned to incoming: path={}, error={:#}",
+            incoming_path.display(),
+            err
+        );
+        return Ok(Processed::Requeued);
+    }
+    state.retries.forget(&processing_path);
+    state.origins.forget(incoming_path);
+
+    if let Err(err) = &result
+        && FailureReason::of_error(err) == FailureReason::DbError
+    {
+        let attempts = db_retry_attempts(&processing_path);
+        if let Some(delay) = state.db_retries.delay(attempts) {
+            let retry_path = with_db_retry_attempts(&processing_path, attempts + 1)
+                .context("processing path has no file name")?;
+            state.spool.rename(&processing_path, &retry_path).await.with_context(|| {
+                format!(
+                    "failed to rename file for a database retry: {} -> {}",
+                    processing_path.display(),
+                    retry_path.display()
+                )
+            })?;
+            state.db_retries.schedule(retry_path.clone(), state.clock.now() + delay);
+            warn!(
+                "database error, retrying file later: path={}, attempt={}, delay={}, error={:#}",
+                retry_path.display(),
+                attempts + 1,
+                humantime::format_duration(delay),
+                err
+            );
+            return Ok(Processed::Deferred);
+        }
+    }
+
+    let stage = state.clock.now();
+    let finalized = async {
+        let target_dir = if result.is_ok() { &state.spool.done } else { &state.spool.failed };
+
+        if let Some(audit) = audit.as_ref().filter(|_| result.is_ok() && state.audit.done_headers)
+            && let Err(err) = state.spool.prepend_headers(&processing_path, &audit.headers()).await
+        {
+            warn!(
+                "done file left without audit headers: path={}, error={:#}",
+                processing_path.display(),
+                err
+            );
+        }
+
+        let final_path =
+            state.spool.relocate(&processing_path, &state.spool.processing, target_dir).await?;
+        finalize_with_retry(&state.spool, &processing_path, &final_path).await?;
+
+        if result.is_ok() {
+            state.status.count_tenant(tenant.as_deref(), |tenant| &mut tenant.processed);
+        }
+        if let Err(err) = &result {
+            let note = FailureNote::new(err, state.clock.unix_secs());
+            state.status.record_failure(note.reason);
+            state.status.count_tenant(tenant.as_deref(), |tenant| &mut tenant.failed);
+            if let Err(err) = state.spool.write_failure_note(&final_path, &note).await {
+                warn!(
+                    "failed file left without reason note: path={}, error={:#}",
+                    final_path.display(),
+                    err
+                );
+            }
+        }
+
+        if result.is_ok()
+            && state.spool.compresses_done()
+            && let Err(err) = state.spool.compress_done_file(&final_path).await
+        {
+            warn!("done file left uncompressed: path={}, error={:#}", final_path.display(), err);
+        }
+        anyhow::Ok(())
+    }
+    .await;
+    timings.finalize = Some(state.clock.now().saturating_duration_since(stage));
+    finalized?;
+
+    result
+}
+
+/// What a worker did with a queued path.
+enum Processed {
+    /// Applied (or skipped as a replay); the file moves on to `done/`.
+    Applied,
+    /// Waiting for its message row or a database retry; the retry queue moves
+    /// it back to `incoming/`.
+    Deferred,
+    /// Back in `incoming/` after a transient database error.
+    Requeued,
+    /// Not a spool file, or another worker claimed it first.
+    Skipped
+}
+
+/// `-` for a stage the file did not reach.
+fn stage_ms(elapsed: Option<Duration>) -> String {
+    elapsed.map_or_else(|| "-".to_string(), |elapsed| elapsed.as_millis().to_string())
+}
+
+/// Moves a processed file to `done/` or `failed/`, retrying transient rename
+/// failures.
+///
+/// A file that still cannot be moved stays in `processing/` and is requeued
+/// on the next start; the idempotency key keeps that replay from writing
+/// the DB twice.
+async fn finalize_with_retry(
+    spool: &Spool,
+    processing_path: &Path,
+    final_path: &Path
+) -> Result<()> {
+    let mut attempt = 0;
+    loop {
+        match spool.rename(processing_path, final_path).await {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt < FINALIZE_RETRY_DELAYS.len() => {
+                warn!(
+                    "spool finalize failed, retrying: path={}, attempt={}, error={}",
+                    processing_path.display(),
+                    attempt + 1,
+                    err
+                );
+                sleep(FINALIZE_RETRY_DELAYS[attempt]).await;
+                attempt += 1;
+            }
+            Err(err) => {
+                coded_error!(
+                    ErrorCode::SpoolFinalizeFailed,
+                    "file left in processing for requeue on restart: path={}, target={}, error={}",
+                    processing_path.display(),
+                    final_path.display(),
+                    err
+                );
+                return Err(err).with_context(|| {
+                    format!(
+                        "failed to finalize file: {} -> {}",
+                        processing_path.display(),
+                        final_path.display()
+                    )
+                });
+            }
+        }
+    }
+}
+
+/// Content hash used as the `processed_spool_messages` key, so the same
+/// payload is applied once no matter how often its spool file is replayed.
+/// The audit headers of a replayed `done/` file are not part of it. Tools
+/// that write bounces to the database directly use it too, so a mail is
+/// applied once whichever way it arrives.
+pub fn idempotency_key(raw_mail: &[u8]) -> String {
+    Sha256::digest(strip_audit_headers(raw_mail)).iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Audit record of a spooled mail; files without a recorded origin were
+/// found by the watcher or scan and count as received at their mtime.
+async fn spool_audit(
+    state: &AppState,
+    incoming_path: &Path,
+    processing_path: &Path,
+    parsed: &ParsedBounce,
+    applied: Option<UpsertBounceOutcome>
+) -> BounceAudit {
+    let origin = state.origins.get(incoming_path);
+    let received_at = match &origin {
+        Some(origin) => origin.received_at,
+        None => tokio::fs::metadata(processing_path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or_else(|_| state.clock.system_now())
+    };
+    BounceAudit {
+        hash: parsed.hash.clone(),
+        ingest_path: origin.as_ref().map_or(IngestPath::SpoolScan, |origin| origin.path),
+        source: origin.and_then(|origin| origin.source),
+        origin: processing_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        received_at,
+        processed_at: state.clock.system_now(),
+        scan_labels: parsed.scan_labels.clone(),
+        outcome: audit_outcome(applied)
+    }
+}
+
+/// Returns true when the given path ends with `.eml`.
+fn is_eml_file(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some("eml")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use bouncer_helpers::clock::system_clock;
+    use tokio::time::{Duration, timeout};
+    use tokio_util::sync::CancellationToken;
+    use uuid::Uuid;
+
+    use super::{finalize_with_retry, idempotency_key, run_notify_watcher};
+    use crate::core::lanes::LaneReceiver;
+    use crate::core::{Faults, Lane, Spool, lane_channels};
+
+    fn make_temp_dir(prefix: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{prefix}-{}", Uuid::now_v7()))
+    }
+
+    async fn wait_for_path(
+        rx: &mut LaneReceiver,
+        expected: &Path
+    ) -> bool {
+        let expected = expected.to_path_buf();
+        let receive = async {
+            loop {
+                let Some((_, path)) = rx.recv().await else {
+                    return false;
+                };
+                if path == expected {
+                    return true;
+                }
+            }
+        };
+        timeout(Duration::from_secs(3), receive).await.unwrap_or(false)
+    }
+
+    #[tokio::test]
+    async fn notify_enqueues_eml_file_on_create() {
+        let incoming = make_temp_dir("bouncer-notify-incoming");
+        tokio::fs::create_dir_all(&incoming).await.unwrap();
+
+        let (tx, mut rx) = lane_channels(8, 8, 4, Default::default());
+        let shutdown = CancellationToken::new();
+        let join = tokio::spawn(run_notify_watcher(incoming.clone(), shutdown.clone(), tx));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let eml_path = incoming.join("sample.eml");
+        tokio::fs::write(&eml_path, b"Subject: test\r\n\r\nbody").await.unwrap();
+        let low_path = incoming.join(Lane::Low.file_name("backfill"));
+        tokio::fs::write(&low_path, b"Subject: test\r\n\r\nbody").await.unwrap();
+
+        assert!(wait_for_path(&mut rx, &eml_path).await);
+        assert!(wait_for_path(&mut rx, &low_path).await);
+
+        shutdown.cancel();
+        let _ = timeout(Duration::from_secs(2), join).await;
+        let _ = tokio::fs::remove_dir_all(&incoming).await;
+    }
+
+    #[tokio::test]
+    async fn notify_ignores_non_eml_file() {
+        let incoming = make_temp_dir("bouncer-notify-incoming");
+        tokio::fs::create_dir_all(&incoming).await.unwrap();
+
+        let (tx, mut rx) = lane_channels(8, 8, 4, Default::default());
+        let shutdown = CancellationToken::new();
+        let join = tokio::spawn(run_notify_watcher(incoming.clone(), shutdown.clone(), tx));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let txt_path = incoming.join("ignore.txt");
+        tokio::fs::write(&txt_path, b"not eml").await.unwrap();
+
+        let got_any = timeout(Duration::from_millis(700), rx.recv()).await.ok().flatten().is_some();
+        assert!(!got_any);
+
+        shutdown.cancel();
+        let _ = timeout(Duration::from_secs(2), join).await;
+        let _ = tokio::fs::remove_dir_all(&incoming).await;
+    }
+
+    #[tokio::test]
+    async fn finalize_retries_failed_rename_and_requeue_recovers_leftovers() {
+        let root = make_temp_dir("bouncer-finalize");
+        let faults = Arc::new(Faults::default());
+        let spool = Spool::new(root.clone(), system_clock(), faults.clone());
+        spool.ensure_dirs().await.unwrap();
+
+        let incoming =
+            spool.enqueue_mail(b"Subject: test\r\n\r\nbody", Lane::High, None, None).await.unwrap();
+        let file_name = incoming.file_name().unwrap().to_owned();
+        let processing = spool.processing.join(&file_name);
+        spool.rename(&incoming, &processing).await.unwrap();
+
+        faults.fail_next_rename();
+        finalize_with_retry(&spool, &processing, &spool.done.join(&file_name)).await.unwrap();
+        assert_eq!(spool.counts().await.unwrap().done, 1);
+
+        let second =
+            spool.enqueue_mail(b"Subject: other\r\n\r\nbody", Lane::Low, None, None).await.unwrap();
+        spool.rename(&second, &spool.processing.join(second.file_name().unwrap())).await.unwrap();
+        assert_eq!(spool.requeue_processing().await.unwrap(), 1);
+        let counts = spool.counts().await.unwrap();
+        assert_eq!((counts.incoming, counts.processing), (1, 0));
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[test]
+    fn idempotency_key_is_content_hash() {
+        assert_eq!(idempotency_key(b"same"), idempotency_key(b"same"));
+        assert_ne!(idempotency_key(b"same"), idempotency_key(b"other"));
+        assert_eq!(idempotency_key(b"").len(), 64);
+        assert_eq!(
+            idempotency_key(
+                b"X-Bouncer-Audit-Path: tcp\r\nX-Bouncer-Audit-Outcome: applied\r\nsame"
+            ),
+            idempotency_key(b"same")
+        );
+    }
+}
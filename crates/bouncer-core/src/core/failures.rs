@@ -0,0 +1,199 @@
+//! Why spool files ended up in `failed/`.
+//!
+//! Every failed file gets a `<name>.reason.json` note (see
+//! [`Spool::failure_note_path`](super::spool::Spool::failure_note_path)) holding its [`FailureReason`] and the full
+//! error, and counts towards `processing.failed_by_reason` of the status
+//! frame. [`run_failure_summary`] logs the counts that grew since its last
+//! tick.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::interval;
+use tracing::info;
+
+use super::parser::ParserError;
+use super::resilience::DatabaseUnavailable;
+use crate::app::AppState;
+
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Bucket of a failed spool file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    EmptyPayload,
+    NotDeliveryReport,
+    MissingHash,
+    MissingStatusCode,
+    BudgetExceeded,
+    /// Rejected by `bounce_authentication` in reject mode.
+    Unauthenticated,
+    /// A database error that was not transient, so retrying would not help.
+    DbError,
+    Other
+}
+
+impl FailureReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::EmptyPayload => "empty_payload",
+            Self::NotDeliveryReport => "not_delivery_report",
+            Self::MissingHash => "missing_hash",
+            Self::MissingStatusCode => "missing_status_code",
+            Self::BudgetExceeded => "budget_exceeded",
+            Self::Unauthenticated => "unauthenticated",
+            Self::DbError => "db_error",
+            Self::Other => "other"
+        }
+    }
+
+    /// The first cause in the chain of `err` that names a bucket.
+    pub fn of_error(err: &anyhow::Error) -> Self {
+        err.chain()
+            .find_map(|cause| {
+                if let Some(reason) = cause.downcast_ref::<Self>() {
+                    return Some(*reason);
+                }
+                if let Some(err) = cause.downcast_ref::<ParserError>() {
+                    return Some(match err {
+                        ParserError::NotDeliveryReport => Self::NotDeliveryReport,
+                        ParserError::MissingHash => Self::MissingHash,
+                        ParserError::MissingStatusCode => Self::MissingStatusCode,
+                        ParserError::BudgetExceeded => Self::BudgetExceeded
+                    });
+                }
+                (cause.is::<sqlx::Error>() || cause.is::<DatabaseUnavailable>())
+                    .then_some(Self::DbError)
+            })
+            .unwrap_or(Self::Other)
+    }
+}
+
+/// The dispatcher bails with the reasons the parser and the database do not
+/// report themselves.
+impl fmt::Display for FailureReason {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        match self {
+            Self::EmptyPayload => f.write_str("empty mail payload"),
+            Self::Unauthenticated => f.write_str("bounce rejected by authentication checks"),
+            reason => f.write_str(reason.as_str())
+        }
+    }
+}
+
+impl std::error::Error for FailureReason {}
+
+/// Body of a `<name>.reason.json` note.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailureNote {
+    pub reason: FailureReason,
+    pub error: String,
+    pub failed_at_unix: u64
+}
+
+impl FailureNote {
+    pub fn new(
+        err: &anyhow::Error,
+        failed_at_unix: u64
+    ) -> Self {
+        Self { reason: FailureReason::of_error(err), error: format!("{err:#}"), failed_at_unix }
+    }
+}
+
+/// Logs how many files failed per reason since the previous summary, every
+/// ten minutes until shutdown; quiet intervals log nothing.
+pub async fn run_failure_summary(state: AppState) {
+    let mut ticker = interval(SUMMARY_INTERVAL);
+    ticker.tick().await;
+    let mut last = state.status.failures();
+
+    loop {
+        tokio::select! {
+            _ = state.shutdown.cancelled() => break,
+            _ = ticker.tick() => {
+                let current = state.status.failures();
+                if let Some(summary) = summary_line(&last, &current) {
+                    info!("failed spool files since last summary: {summary}");
+                }
+                last = current;
+            }
+        }
+    }
+}
+
+/// `total=3, missing_hash=2, other=1`, or `None` when nothing failed.
+fn summary_line(
+    last: &BTreeMap<String, u64>,
+    current: &BTreeMap<String, u64>
+) -> Option<String> {
+    let deltas: Vec<(&str, u64)> = current
+        .iter()
+        .map(|(reason, count)| {
+            (reason.as_str(), count.saturating_sub(last.get(reason).copied().unwrap_or(0)))
+        })
+        .filter(|(_, delta)| *delta > 0)
+        .collect();
+    if deltas.is_empty() {
+        return None;
+    }
+    let total: u64 = deltas.iter().map(|(_, delta)| delta).sum();
+    let buckets: Vec<String> =
+        deltas.iter().map(|(reason, delta)| format!("{reason}={delta}")).collect();
+    Some(format!("total={total}, {}", buckets.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use anyhow::{Context, anyhow};
+
+    use super::{FailureNote, FailureReason, summary_line};
+    use crate::core::ParserError;
+    use crate::core::resilience::DatabaseUnavailable;
+
+    #[test]
+    fn classifies_error_chains() {
+        let parse = anyhow::Error::new(ParserError::MissingHash).context("parse failed");
+        assert_eq!(FailureReason::of_error(&parse), FailureReason::MissingHash);
+
+        let empty = anyhow::Error::new(FailureReason::EmptyPayload);
+        assert_eq!(empty.to_string(), "empty mail payload");
+        assert_eq!(FailureReason::of_error(&empty), FailureReason::EmptyPayload);
+
+        let db: anyhow::Result<()> =
+            Err(sqlx::Error::RowNotFound).context("database upsert failed");
+        assert_eq!(FailureReason::of_error(&db.unwrap_err()), FailureReason::DbError);
+        let breaker = anyhow::Error::new(DatabaseUnavailable);
+        assert_eq!(FailureReason::of_error(&breaker), FailureReason::DbError);
+
+        assert_eq!(FailureReason::of_error(&anyhow!("disk full")), FailureReason::Other);
+    }
+
+    #[test]
+    fn notes_round_trip_with_snake_case_reasons() {
+        let err = anyhow::Error::new(ParserError::NotDeliveryReport).context("parse failed");
+        let note = FailureNote::new(&err, 1_700_000_000);
+        let body = serde_json::to_string(&note).unwrap();
+        assert!(body.contains(r#""reason":"not_delivery_report""#), "{body}");
+        assert!(body.contains("parse failed: message does not look like"), "{body}");
+        assert_eq!(serde_json::from_str::<FailureNote>(&body).unwrap(), note);
+    }
+
+    #[test]
+    fn summary_reports_growth_since_last_tick() {
+        let last = BTreeMap::from([("missing_hash".to_string(), 2)]);
+        let current = BTreeMap::from([("missing_hash".to_string(), 4), ("other".to_string(), 1)]);
+        assert_eq!(
+            summary_line(&last, &current).as_deref(),
+            Some("total=3, missing_hash=2, other=1")
+        );
+        assert_eq!(summary_line(&current, &current), None);
+    }
+}
@@ -0,0 +1,202 @@
+//! Fault injection points for exercising recovery paths.
+//!
+//! Faults are only armable in test builds or with the `fault-injection`
+//! feature. Without it [`Faults`] is a zero-sized no-op and every hook
+//! compiles away.
+//!
+//! A server built with the feature reads `BOUNCER_FAULTS` at startup, e.g.
+//! `BOUNCER_FAULTS=fail_next_rename,drop_next_ack,db_delay_ms=250,db_fail_next=3`, so
+//! integration tests driving the binary can arm faults without code changes.
+
+use std::io;
+
+pub const FAULTS_ENV: &str = "BOUNCER_FAULTS";
+
+#[cfg(not(any(test, feature = "fault-injection")))]
+pub use disabled::Faults;
+#[cfg(any(test, feature = "fault-injection"))]
+pub use enabled::Faults;
+
+#[cfg(any(test, feature = "fault-injection"))]
+mod enabled {
+    use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+    use std::time::Duration;
+
+    use anyhow::{Context, Result, bail};
+    use bouncer_helpers::coded_warn;
+    use bouncer_helpers::error_code::ErrorCode;
+
+    use super::{FAULTS_ENV, io};
+
+    #[derive(Debug, Default)]
+    pub struct Faults {
+        fail_next_rename: AtomicBool,
+        drop_next_ack: AtomicBool,
+        db_delay_ms: AtomicU64,
+        db_fail_next: AtomicU32
+    }
+
+    impl Faults {
+        pub fn from_env() -> Result<Self> {
+            let faults = Self::default();
+            let Ok(raw) = std::env::var(FAULTS_ENV) else {
+                return Ok(faults);
+            };
+
+            for item in raw.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+                match item.split_once('=') {
+                    None if item == "fail_next_rename" => faults.fail_next_rename(),
+                    None if item == "drop_next_ack" => faults.drop_next_ack(),
+                    Some(("db_delay_ms", value)) => faults.set_db_delay(Duration::from_millis(
+                        value.trim().parse().with_context(|| {
+                            format!("invalid {FAULTS_ENV} db_delay_ms: {value}")
+                        })?
+                    )),
+                    Some(("db_fail_next", value)) => {
+                        faults.fail_next_db_writes(value.trim().parse().with_context(|| {
+                            format!("invalid {FAULTS_ENV} db_fail_next: {value}")
+                        })?)
+                    }
+                    _ => bail!("unknown {FAULTS_ENV} entry: {item}")
+                }
+            }
+
+            coded_warn!(ErrorCode::FaultsArmed, "fault injection armed: {}={}", FAULTS_ENV, raw);
+            Ok(faults)
+        }
+
+        /// Makes the next spool rename fail with an I/O error.
+        pub fn fail_next_rename(&self) {
+            self.fail_next_rename.store(true, Ordering::SeqCst);
+        }
+
+        /// Makes the server close the next connection instead of writing its ACK.
+        pub fn drop_next_ack(&self) {
+            self.drop_next_ack.store(true, Ordering::SeqCst);
+        }
+
+        /// Delays every database write by `delay` (zero disables).
+        pub fn set_db_delay(
+            &self,
+            delay: Duration
+        ) {
+            self.db_delay_ms
+                .store(delay.as_millis().min(u64::MAX as u128) as u64, Ordering::SeqCst);
+        }
+
+        /// Makes the next `count` database writes fail with an I/O error, as
+        /// if the server had gone away.
+        pub fn fail_next_db_writes(
+            &self,
+            count: u32
+        ) {
+            self.db_fail_next.store(count, Ordering::SeqCst);
+        }
+
+        pub fn check_db_write(&self) -> io::Result<()> {
+            let armed = self
+                .db_fail_next
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1))
+                .is_ok();
+            if armed {
+                return Err(io::Error::other("injected database failure"));
+            }
+            Ok(())
+        }
+
+        pub fn check_rename(&self) -> io::Result<()> {
+            if self.fail_next_rename.swap(false, Ordering::SeqCst) {
+                return Err(io::Error::other("injected rename failure"));
+            }
+            Ok(())
+        }
+
+        pub fn take_ack_drop(&self) -> bool {
+            self.drop_next_ack.swap(false, Ordering::SeqCst)
+        }
+
+        pub async fn delay_db(&self) {
+            let delay_ms = self.db_delay_ms.load(Ordering::SeqCst);
+            if delay_ms > 0 {
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            }
+        }
+    }
+}
+
+#[cfg(not(any(test, feature = "fault-injection")))]
+mod disabled {
+    use anyhow::Result;
+    use tracing::warn;
+
+    use super::{FAULTS_ENV, io};
+
+    #[derive(Debug, Default)]
+    pub struct Faults;
+
+    impl Faults {
+        pub fn from_env() -> Result<Self> {
+            if std::env::var_os(FAULTS_ENV).is_some() {
+                warn!("{} ignored: built without the fault-injection feature", FAULTS_ENV);
+            }
+            Ok(Self)
+        }
+
+        #[inline(always)]
+        pub fn check_db_write(&self) -> io::Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        pub fn check_rename(&self) -> io::Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        pub fn take_ack_drop(&self) -> bool {
+            false
+        }
+
+        #[inline(always)]
+        pub async fn delay_db(&self) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bouncer_helpers::clock::system_clock;
+    use uuid::Uuid;
+
+    use super::Faults;
+    use crate::core::{Lane, Spool};
+
+    #[tokio::test]
+    async fn failed_enqueue_rename_leaves_no_file() {
+        let root = std::env::temp_dir().join(format!("bouncer-faults-{}", Uuid::now_v7()));
+        let faults = Arc::new(Faults::default());
+        let spool = Spool::new(root.clone(), system_clock(), faults.clone());
+        spool.ensure_dirs().await.unwrap();
+
+        faults.fail_next_rename();
+        assert!(
+            spool.enqueue_mail(b"Subject: test\r\n\r\nbody", Lane::High, None, None).await.is_err()
+        );
+
+        let mut names = Vec::new();
+        let mut entries = tokio::fs::read_dir(&spool.incoming).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        assert!(names.is_empty(), "{names:?}");
+        assert_eq!(spool.counts().await.unwrap().incoming, 0);
+
+        // The fault is one-shot: the next enqueue goes through.
+        let path =
+            spool.enqueue_mail(b"Subject: test\r\n\r\nbody", Lane::High, None, None).await.unwrap();
+        assert!(path.exists());
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+}
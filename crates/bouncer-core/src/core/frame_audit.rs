@@ -0,0 +1,254 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config::FrameAuditConfig;
+
+const RECORD_EXTENSION: &str = "json";
+const BODY_EXTENSION: &str = "body";
+
+/// Records inbound TCP frames while enabled (see `frame_audit` in the
+/// config); toggled at runtime through the admin API.
+#[derive(Debug, Default)]
+pub struct FrameAudit {
+    config: FrameAuditConfig,
+    enabled: AtomicBool,
+    sequence: AtomicU64,
+    /// Ids of the frames kept in `dir`, oldest first.
+    kept: Mutex<VecDeque<String>>
+}
+
+/// One frame as read off the wire.
+#[derive(Debug, Clone, Copy)]
+pub struct AuditedFrame<'a> {
+    pub peer: SocketAddr,
+    /// Header bytes as sent, before decoding.
+    pub header: &'a [u8],
+    /// The part of the body read so far; trimmed to `max_body_bytes`.
+    pub body: &'a [u8],
+    /// Declared body length.
+    pub body_bytes: u64,
+    pub received_at: SystemTime
+}
+
+/// `<id>.json` of a frame in the audit directory.
+#[derive(Debug, Serialize)]
+struct FrameRecord<'a> {
+    id: &'a str,
+    received_at_unix_ms: u64,
+    peer: String,
+    header: Cow<'a, str>,
+    header_bytes: usize,
+    body_bytes: u64,
+    /// Bytes of the body in `<id>.body`.
+    body_recorded: usize
+}
+
+impl FrameAudit {
+    /// Creates `dir` and picks up the frames a previous run left there, so
+    /// they count towards `max_frames`.
+    pub fn new(config: FrameAuditConfig) -> Result<Self> {
+        let mut kept = Vec::new();
+        if let Some(dir) = &config.dir {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create dir {}", dir.display()))?;
+            for entry in std::fs::read_dir(dir)
+                .with_context(|| format!("failed to read dir {}", dir.display()))?
+            {
+                let path = entry?.path();
+                if path.extension().and_then(|ext| ext.to_str()) == Some(RECORD_EXTENSION)
+                    && let Some(id) = path.file_stem().and_then(|stem| stem.to_str())
+                {
+                    kept.push(id.to_string());
+                }
+            }
+            kept.sort();
+        }
+        Ok(Self {
+            enabled: AtomicBool::new(config.enabled),
+            config,
+            sequence: AtomicU64::new(0),
+            kept: Mutex::new(kept.into())
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(
+        &self,
+        enabled: bool
+    ) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.config.dir.as_deref()
+    }
+
+    /// Writes `frame` to the audit directory, or logs it without one.
+    /// Failures are logged; they never fail the frame.
+    pub async fn record(
+        &self,
+        frame: AuditedFrame<'_>
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+        let body = &frame.body[..frame.body.len().min(self.config.max_body_bytes)];
+        let Some(dir) = &self.config.dir else {
+            info!(
+                "frame audit: peer={}, header={:?}, body_bytes={}, body={:?}",
+                frame.peer,
+                String::from_utf8_lossy(frame.header),
+                frame.body_bytes,
+                String::from_utf8_lossy(body)
+            );
+            return;
+        };
+
+        let received_at_unix_ms =
+            frame.received_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let id = format!("{received_at_unix_ms:013}-{sequence:06}");
+        let record = FrameRecord {
+            id: &id,
+            received_at_unix_ms,
+            peer: frame.peer.to_string(),
+            header: String::from_utf8_lossy(frame.header),
+            header_bytes: frame.header.len(),
+            body_bytes: frame.body_bytes,
+            body_recorded: body.len()
+        };
+        if let Err(err) = write_frame(dir, &id, &record, body).await {
+            warn!("failed to record audited frame: dir={}, error={err:#}", dir.display());
+            return;
+        }
+
+        let evicted: Vec<String> = {
+            let mut kept = self.kept.lock().expect("frame audit mutex poisoned");
+            kept.push_back(id);
+            let over = kept.len().saturating_sub(self.config.max_frames);
+            kept.drain(..over).collect()
+        };
+        for id in evicted {
+            for path in frame_paths(dir, &id) {
+                if let Err(err) = tokio::fs::remove_file(&path).await
+                    && err.kind() != io::ErrorKind::NotFound
+                {
+                    warn!("failed to rotate audited frame: path={}, error={err}", path.display());
+                }
+            }
+        }
+    }
+}
+
+/// Writes the body first, so every `<id>.json` has its body next to it.
+async fn write_frame(
+    dir: &Path,
+    id: &str,
+    record: &FrameRecord<'_>,
+    body: &[u8]
+) -> Result<()> {
+    let [record_path, body_path] = frame_paths(dir, id);
+    if !body.is_empty() {
+        tokio::fs::write(&body_path, body)
+            .await
+            .with_context(|| format!("failed to write {}", body_path.display()))?;
+    }
+    tokio::fs::write(&record_path, serde_json::to_vec(record)?)
+        .await
+        .with_context(|| format!("failed to write {}", record_path.display()))
+}
+
+fn frame_paths(
+    dir: &Path,
+    id: &str
+) -> [PathBuf; 2] {
+    [dir.join(format!("{id}.{RECORD_EXTENSION}")), dir.join(format!("{id}.{BODY_EXTENSION}"))]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    use super::{AuditedFrame, FrameAudit};
+    use crate::config::FrameAuditConfig;
+
+    #[tokio::test]
+    async fn keeps_the_newest_frames_while_enabled() {
+        let dir = std::env::temp_dir().join(format!("bouncer-frame-audit-{}", Uuid::now_v7()));
+        let audit = FrameAudit::new(FrameAuditConfig {
+            enabled: false,
+            dir: Some(dir.clone()),
+            max_body_bytes: 4,
+            max_frames: 2
+        })
+        .unwrap();
+        let frame = |n: u64| AuditedFrame {
+            peer: "192.0.2.1:40000".parse().unwrap(),
+            header: br#"{"from":"a","to":"b"}"#,
+            body: b"Subject: x",
+            body_bytes: 10,
+            received_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000 + n)
+        };
+
+        audit.record(frame(0)).await;
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        audit.set_enabled(true);
+        for n in 1..=3 {
+            audit.record(frame(n)).await;
+        }
+        let mut names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "1700000002000-000001.body",
+                "1700000002000-000001.json",
+                "1700000003000-000002.body",
+                "1700000003000-000002.json"
+            ]
+        );
+        let record: Value =
+            serde_json::from_slice(&std::fs::read(dir.join(&names[3])).unwrap()).unwrap();
+        assert_eq!(record["peer"], "192.0.2.1:40000");
+        assert_eq!(record["header"], r#"{"from":"a","to":"b"}"#);
+        assert_eq!(
+            (record["body_bytes"].as_u64(), record["body_recorded"].as_u64()),
+            (Some(10), Some(4))
+        );
+        assert_eq!(std::fs::read(dir.join(&names[2])).unwrap(), b"Subj");
+
+        // A restart picks up the kept frames and keeps rotating them.
+        let reopened = FrameAudit::new(FrameAuditConfig {
+            enabled: true,
+            dir: Some(dir.clone()),
+            max_body_bytes: 0,
+            max_frames: 2
+        })
+        .unwrap();
+        reopened.record(frame(4)).await;
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+        assert!(!dir.join("1700000002000-000001.json").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
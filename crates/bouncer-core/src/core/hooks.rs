@@ -0,0 +1,365 @@
+//! Commands run for every processed bounce (`hooks` in the config).
+//!
+//! Side effects such as refreshing a cache or calling an internal service
+//! belong in a command instead of a patched server. Every outcome the
+//! database applies, from any ingest path, is queued on each hook by
+//! [`BounceHooks::notify`]; [`run_bounce_hooks`] runs the command with a
+//! [`HookEvent`] as JSON on stdin, at most `concurrency` runs at a time per
+//! hook. A full queue drops the bounce with a warning instead of slowing
+//! down processing, so hooks are best effort: the database stays the
+//! record of what was processed.
+
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use bouncer_helpers::clock::{SharedClock, system_clock};
+use bouncer_helpers::coded_warn;
+use bouncer_helpers::error_code::ErrorCode;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::{Semaphore, mpsc};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+use super::parser::ParsedBounce;
+use crate::config::{HookConfig, HookFailurePolicy};
+
+/// One processed bounce, as sent to the hook commands.
+#[derive(Debug, Clone, Serialize)]
+pub struct HookEvent {
+    /// `bounce` for spooled and IMAP mail, `observer` for observer events.
+    pub origin: &'static str,
+    /// What the database did, such as `updated_local_message` or `unlinked`.
+    pub outcome: &'static str,
+    pub kind: &'static str,
+    pub hash: String,
+    pub status_code: String,
+    pub action: Option<String>,
+    pub sender: Option<String>,
+    pub recipient: Option<String>,
+    pub description: Option<String>,
+    pub reason: Option<&'static str>,
+    pub tenant: Option<String>,
+    pub processed_at_unix: u64
+}
+
+#[derive(Debug)]
+struct Hook {
+    config: HookConfig,
+    queue: mpsc::Sender<Arc<HookEvent>>,
+    /// Taken by [`run_bounce_hooks`] when it starts.
+    receiver: Mutex<Option<mpsc::Receiver<Arc<HookEvent>>>>
+}
+
+/// Queues processed bounces for the `hooks` commands; a default instance
+/// has no hooks and does nothing.
+#[derive(Debug)]
+pub struct BounceHooks {
+    hooks: Vec<Hook>,
+    clock: SharedClock
+}
+
+impl Default for BounceHooks {
+    fn default() -> Self {
+        Self::new(Vec::new(), system_clock())
+    }
+}
+
+impl BounceHooks {
+    pub fn new(
+        configs: Vec<HookConfig>,
+        clock: SharedClock
+    ) -> Self {
+        let hooks = configs
+            .into_iter()
+            .map(|config| {
+                let (queue, receiver) = mpsc::channel(config.queue_size);
+                Hook { config, queue, receiver: Mutex::new(Some(receiver)) }
+            })
+            .collect();
+        Self { hooks, clock }
+    }
+
+    /// Queues `parsed` on every hook; never waits.
+    pub fn notify(
+        &self,
+        origin: &'static str,
+        outcome: &'static str,
+        parsed: &ParsedBounce
+    ) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        let event = Arc::new(HookEvent {
+            origin,
+            outcome,
+            kind: parsed.kind.as_str(),
+            hash: parsed.hash.clone(),
+            status_code: parsed.status_code.clone(),
+            action: parsed.action.clone(),
+            sender: parsed.sender.clone(),
+            recipient: parsed.recipient.clone(),
+            description: parsed.description.clone(),
+            reason: parsed.reason(),
+            tenant: parsed.tenant.clone(),
+            processed_at_unix: self.clock.unix_secs()
+        });
+        for hook in &self.hooks {
+            if let Err(mpsc::error::TrySendError::Full(_)) = hook.queue.try_send(event.clone()) {
+                coded_warn!(
+                    ErrorCode::HookFailed,
+                    "hook queue full, bounce dropped: hook={}, hash={}, queue_size={}",
+                    hook.config.name,
+                    event.hash,
+                    hook.config.queue_size
+                );
+            }
+        }
+    }
+
+    /// Bounces waiting per hook, by hook name.
+    pub fn queued(&self) -> Vec<(&str, usize)> {
+        self.hooks
+            .iter()
+            .map(|hook| {
+                (hook.config.name.as_str(), hook.queue.max_capacity() - hook.queue.capacity())
+            })
+            .collect()
+    }
+}
+
+/// Runs the hook commands for queued bounces until `shutdown`; runs in
+/// progress are finished, bounces still queued are dropped. Returns at once
+/// without hooks.
+pub async fn run_bounce_hooks(
+    hooks: Arc<BounceHooks>,
+    shutdown: CancellationToken
+) {
+    let mut runners = JoinSet::new();
+    for hook in &hooks.hooks {
+        let Some(receiver) = hook.receiver.lock().expect("hook mutex poisoned").take() else {
+            continue;
+        };
+        info!(
+            "bounce hook enabled: hook={}, concurrency={}, timeout={}, on_failure={:?}",
+            hook.config.name,
+            hook.config.concurrency,
+            humantime::format_duration(hook.config.timeout),
+            hook.config.on_failure
+        );
+        runners.spawn(run_hook(hook.config.clone(), receiver, shutdown.clone()));
+    }
+    while runners.join_next().await.is_some() {}
+}
+
+async fn run_hook(
+    config: HookConfig,
+    mut receiver: mpsc::Receiver<Arc<HookEvent>>,
+    shutdown: CancellationToken
+) {
+    let config = Arc::new(config);
+    let slots = Arc::new(Semaphore::new(config.concurrency));
+    let mut runs = JoinSet::new();
+    loop {
+        let event = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            next = receiver.recv() => match next {
+                Some(event) => event,
+                None => break
+            }
+        };
+        let Ok(slot) = slots.clone().acquire_owned().await else {
+            break;
+        };
+        let (config, shutdown) = (config.clone(), shutdown.clone());
+        runs.spawn(async move {
+            run_with_policy(&config, &event, &shutdown).await;
+            drop(slot);
+        });
+        // Reap finished runs so the set does not grow with the backlog.
+        while runs.try_join_next().is_some() {}
+    }
+
+    receiver.close();
+    let dropped = receiver.len();
+    if dropped > 0 {
+        info!(
+            "bounce hook stopping, queued bounces dropped: hook={}, dropped={dropped}",
+            config.name
+        );
+    }
+    while runs.join_next().await.is_some() {}
+}
+
+async fn run_with_policy(
+    config: &HookConfig,
+    event: &HookEvent,
+    shutdown: &CancellationToken
+) {
+    let retries = match config.on_failure {
+        HookFailurePolicy::Log => 0,
+        HookFailurePolicy::Retry => config.retries
+    };
+    let mut delay = config.retry_delay;
+    for attempt in 0..=retries {
+        let err = match run_command(config, event).await {
+            Ok(()) => {
+                debug!("bounce hook ran: hook={}, hash={}", config.name, event.hash);
+                return;
+            }
+            Err(err) => err
+        };
+        if attempt == retries || shutdown.is_cancelled() {
+            coded_warn!(
+                ErrorCode::HookFailed,
+                "bounce hook failed: hook={}, hash={}, attempts={}, error={err:#}",
+                config.name,
+                event.hash,
+                attempt + 1
+            );
+            return;
+        }
+        debug!(
+            "bounce hook failed, retrying: hook={}, hash={}, attempt={}, error={err:#}",
+            config.name,
+            event.hash,
+            attempt + 1
+        );
+        tokio::select! {
+            _ = shutdown.cancelled() => {}
+            _ = tokio::time::sleep(delay) => {}
+        }
+        delay = delay.saturating_mul(2).min(Duration::from_secs(300));
+    }
+}
+
+async fn run_command(
+    config: &HookConfig,
+    event: &HookEvent
+) -> Result<()> {
+    let body = serde_json::to_vec(event).context("failed to encode hook event")?;
+    let mut child = Command::new(&config.command[0])
+        .args(&config.command[1..])
+        .env("BOUNCER_HOOK_NAME", &config.name)
+        .env("BOUNCER_HOOK_ORIGIN", event.origin)
+        .env("BOUNCER_HOOK_OUTCOME", event.outcome)
+        .env("BOUNCER_HOOK_HASH", &event.hash)
+        .env("BOUNCER_HOOK_STATUS_CODE", &event.status_code)
+        .env("BOUNCER_HOOK_RECIPIENT", event.recipient.as_deref().unwrap_or_default())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("failed to start {}", config.command[0]))?;
+
+    let run = async {
+        if let Some(mut stdin) = child.stdin.take() {
+            // A command that ignores stdin may close it early.
+            let _ = stdin.write_all(&body).await;
+        }
+        child.wait().await
+    };
+    let status = tokio::time::timeout(config.timeout, run)
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!("timed out after {}", humantime::format_duration(config.timeout))
+        })?
+        .context("failed to wait for command")?;
+    if !status.success() {
+        bail!("command exited with {status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bouncer_helpers::clock::ManualClock;
+    use tokio_util::sync::CancellationToken;
+    use uuid::Uuid;
+
+    use super::{BounceHooks, run_bounce_hooks};
+    use crate::config::{HookConfig, HookFailurePolicy};
+    use crate::core::parser::{ParsedBounce, ReportKind};
+
+    fn hook(
+        name: &str,
+        script: String,
+        on_failure: HookFailurePolicy
+    ) -> HookConfig {
+        HookConfig {
+            name: name.to_string(),
+            command: vec!["sh".to_string(), "-c".to_string(), script],
+            timeout: Duration::from_secs(5),
+            concurrency: 2,
+            queue_size: 16,
+            on_failure,
+            retries: 2,
+            retry_delay: Duration::from_millis(10)
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_commands_with_the_bounce_and_retries_failures() {
+        let dir = std::env::temp_dir().join(format!("bouncer-hooks-{}", Uuid::now_v7()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let (seen, attempts) = (dir.join("seen.json"), dir.join("attempts"));
+        let hooks = Arc::new(BounceHooks::new(
+            vec![
+                hook("cache", format!("cat > {}", seen.display()), HookFailurePolicy::Log),
+                hook(
+                    "flaky",
+                    format!("echo x >> {}; exit 1", attempts.display()),
+                    HookFailurePolicy::Retry
+                ),
+            ],
+            Arc::new(ManualClock::at_unix(1_700_000_000))
+        ));
+        let shutdown = CancellationToken::new();
+        let runner = tokio::spawn(run_bounce_hooks(hooks.clone(), shutdown.clone()));
+
+        hooks.notify(
+            "bounce",
+            "updated_local_message",
+            &ParsedBounce {
+                kind: ReportKind::Bounce,
+                hash: "a1b2".to_string(),
+                status_code: "5.1.1".to_string(),
+                action: Some("failed".to_string()),
+                sender: None,
+                recipient: Some("user@example.com".to_string()),
+                description: None,
+                scan_labels: Vec::new(),
+                tenant: Some("acme".to_string())
+            }
+        );
+
+        // One run plus `retries` more.
+        for _ in 0..500 {
+            let runs = tokio::fs::read_to_string(&attempts).await.unwrap_or_default();
+            if runs.lines().count() == 3 && seen.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(tokio::fs::read_to_string(&attempts).await.unwrap().lines().count(), 3);
+        let event: serde_json::Value =
+            serde_json::from_slice(&tokio::fs::read(&seen).await.unwrap()).unwrap();
+        assert_eq!(event["origin"], "bounce");
+        assert_eq!(event["outcome"], "updated_local_message");
+        assert_eq!(event["hash"], "a1b2");
+        assert_eq!(event["tenant"], "acme");
+        assert_eq!(event["processed_at_unix"], 1_700_000_000);
+
+        shutdown.cancel();
+        runner.await.unwrap();
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,1009 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration as StdDuration, SystemTime};
+
+use anyhow::{Context, Result};
+use async_imap::types::Uid;
+use async_imap::{Client, Session};
+use async_native_tls::{TlsConnector, TlsStream};
+use bouncer_helpers::clock::SharedClock;
+use bouncer_helpers::coded_warn;
+use bouncer_helpers::error_code::ErrorCode;
+use bouncer_helpers::oauth2::{TokenSource, XOAuth2};
+use bouncer_proto::status::ImapPollStatus;
+use futures_util::TryStreamExt;
+use time::{Month, OffsetDateTime};
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, interval};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, trace, warn};
+
+use super::UpsertBounceOutcome;
+use super::admin_api::AdminTriggers;
+use super::audit::{BounceAudit, IngestPath, audit_outcome};
+use super::database::Database;
+use super::parser::{ParserChain, ParserError};
+use super::reload::Live;
+use super::status::RuntimeStatus;
+use crate::config::{ImapConfig, ImapLimitsConfig};
+
+type ImapSession = Session<TlsStream<TcpStream>>;
+const IMAP_FETCH_QUERY_BODY_UID: &str = "(UID BODY.PEEK[])";
+
+/// Account and mailbox of one poll loop, rendered as `account=.., mailbox=..`
+/// in logs.
+#[derive(Debug, Clone)]
+struct PollTarget {
+    account: String,
+    mailbox: String
+}
+
+impl fmt::Display for PollTarget {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        write!(f, "account={}, mailbox={}", self.account, self.mailbox)
+    }
+}
+
+/// An account's config and OAuth2 token cache, shared by its mailboxes.
+struct ImapAccount {
+    config: ImapConfig,
+    /// Present for `oauth2` accounts.
+    tokens: Option<TokenSource>
+}
+
+impl ImapAccount {
+    fn new(config: ImapConfig) -> Result<Self> {
+        let tokens =
+            config.oauth2.clone().map(TokenSource::new).transpose().with_context(|| {
+                format!("imap oauth2 client setup failed: account={}", config.label())
+            })?;
+        Ok(Self { config, tokens })
+    }
+}
+
+/// State shared by the poll loops of every account and mailbox.
+struct PollShared {
+    db: Arc<Database>,
+    parsers: Arc<Live<ParserChain>>,
+    clock: SharedClock,
+    status: Arc<RuntimeStatus>,
+    /// `POST /imap/poll` of the admin API.
+    triggers: Arc<AdminTriggers>,
+    /// Bounds mailboxes polled at once (`imap_limits.max_sessions`).
+    sessions: Semaphore,
+    /// Bounds fetched messages in flight (`imap_limits.max_processing`).
+    processing: Arc<Semaphore>,
+    max_processing: usize,
+    /// `audit.enabled`: write a `mail_bounce_audit` row per processed message.
+    audit: bool
+}
+
+/// Runs the optional IMAP fallback polling loops, one per account mailbox,
+/// under the shared `limits`.
+///
+/// Accounts without a host are skipped; the loops exit on cancellation.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_imap_poll_loop(
+    accounts: Vec<ImapConfig>,
+    limits: ImapLimitsConfig,
+    db: Arc<Database>,
+    parsers: Arc<Live<ParserChain>>,
+    clock: SharedClock,
+    status: Arc<RuntimeStatus>,
+    triggers: Arc<AdminTriggers>,
+    audit: bool,
+    shutdown: CancellationToken
+) {
+    let accounts: Vec<ImapConfig> = accounts.into_iter().filter(ImapConfig::enabled).collect();
+    if accounts.is_empty() {
+        info!("imap fallback disabled (IMAP_HOST missing)");
+        return;
+    }
+
+    let shared = Arc::new(PollShared {
+        db,
+        parsers,
+        clock,
+        status,
+        triggers,
+        sessions: Semaphore::new(limits.max_sessions),
+        processing: Arc::new(Semaphore::new(limits.max_processing)),
+        max_processing: limits.max_processing,
+        audit
+    });
+    info!(
+        "imap fallback enabled: accounts={}, mailboxes={}, max_sessions={}, max_processing={}",
+        accounts.len(),
+        accounts.iter().map(|account| account.mailboxes.len()).sum::<usize>(),
+        limits.max_sessions,
+        limits.max_processing
+    );
+
+    let mut loops = JoinSet::new();
+    for account in accounts {
+        let account = match ImapAccount::new(account) {
+            Ok(account) => Arc::new(account),
+            Err(err) => {
+                warn!("imap account skipped: error={err:#}");
+                continue;
+            }
+        };
+        for mailbox in &account.config.mailboxes {
+            let target = PollTarget { account: account.config.label(), mailbox: mailbox.clone() };
+            shared.status.imap_enabled(&target.account, &target.mailbox);
+            loops.spawn(run_mailbox_poll_loop(
+                account.clone(),
+                target,
+                shared.clone(),
+                shutdown.clone()
+            ));
+        }
+    }
+    while let Some(result) = loops.join_next().await {
+        if let Err(err) = result {
+            coded_warn!(ErrorCode::ImapTaskJoinFailed, "imap poll loop task failed: error={err}");
+        }
+    }
+}
+
+async fn run_mailbox_poll_loop(
+    account: Arc<ImapAccount>,
+    target: PollTarget,
+    shared: Arc<PollShared>,
+    shutdown: CancellationToken
+) {
+    let config = &account.config;
+    info!(
+        "imap poll loop enabled: {}, host={}, poll_secs={}, connect_timeout_secs={}, max_messages_per_poll={}, max_history={}, mark_seen_if_not_exist={}",
+        target,
+        config.host.as_deref().unwrap_or_default(),
+        config.poll_secs,
+        config.connect_timeout_secs,
+        config.max_messages_per_poll,
+        config
+            .max_history
+            .map(|duration| humantime::format_duration(duration).to_string())
+            .unwrap_or_else(|| "none".to_string()),
+        config.mark_seen_if_not_exist
+    );
+
+    let mut ticker = interval(Duration::from_secs(config.poll_secs.max(5)));
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                info!("imap poll loop stopping: {target}");
+                break;
+            }
+            _ = shared.triggers.imap_poll.notified() => {
+                ticker.reset_immediately();
+            }
+            _ = ticker.tick() => {
+                // Never closed; holding the permit for the whole poll bounds
+                // open sessions across accounts.
+                let Ok(_session) = shared.sessions.acquire().await else {
+                    break;
+                };
+                let started = shared.clock.now();
+                let result =
+                    run_imap_poll_once(&account, &target, &shared, shared.clock.system_now()).await;
+                if let Err(err) = &result {
+                    warn!("imap poll iteration failed: {target}, error={err:#}");
+                }
+                shared.status.record_imap_poll(poll_status(
+                    &target,
+                    result,
+                    shared.clock.now().duration_since(started),
+                    shared.clock.unix_secs()
+                ));
+            }
+        }
+    }
+}
+
+/// Outcome counters of one poll, reported by the status frame.
+#[derive(Debug, Clone, Copy, Default)]
+struct PollCounts {
+    selected: usize,
+    parsed_ok: usize,
+    parse_failures: usize,
+    fetch_failures: usize,
+    db_failures: usize
+}
+
+fn poll_status(
+    target: &PollTarget,
+    result: Result<PollCounts>,
+    duration: StdDuration,
+    finished_at_unix: u64
+) -> ImapPollStatus {
+    let (counts, error) = match result {
+        Ok(counts) => (counts, None),
+        Err(err) => (PollCounts::default(), Some(format!("{err:#}")))
+    };
+    ImapPollStatus {
+        account: target.account.clone(),
+        mailbox: target.mailbox.clone(),
+        finished_at_unix,
+        duration_ms: u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+        error,
+        selected: counts.selected as u64,
+        parsed_ok: counts.parsed_ok as u64,
+        parse_failures: counts.parse_failures as u64,
+        fetch_failures: counts.fetch_failures as u64,
+        db_failures: counts.db_failures as u64
+    }
+}
+
+/// Executes one IMAP poll iteration.
+///
+/// Fetches a bounded unseen batch from IMAP, parses bounce payloads and writes
+/// status updates directly to DB (without going through spool/worker path).
+async fn run_imap_poll_once(
+    account: &ImapAccount,
+    target: &PollTarget,
+    shared: &Arc<PollShared>,
+    now: SystemTime
+) -> Result<PollCounts> {
+    trace!("imap poll started: {target}");
+    let config = &account.config;
+    let max_messages = config.max_messages_per_poll.max(1);
+    let mut session = open_imap_session(account).await?;
+
+    session
+        .select(&target.mailbox)
+        .await
+        .with_context(|| format!("imap select mailbox failed: {target}"))?;
+
+    let uid_search_query = build_uid_search_query(config.max_history, now);
+    let mut uids: Vec<Uid> = session
+        .uid_search(&uid_search_query)
+        .await
+        .with_context(|| format!("imap UID SEARCH failed: query={uid_search_query}"))?
+        .into_iter()
+        .collect();
+    let unseen_total = uids.len();
+    // Process newest mailbox UIDs first to prioritize recent delivery outcomes.
+    uids.sort_unstable_by(|a, b| b.cmp(a));
+    uids.truncate(max_messages);
+
+    debug!(
+        "imap unseen selected: {}, unseen_total={}, selected={}, max_messages_per_poll={}, search_query={}",
+        target,
+        unseen_total,
+        uids.len(),
+        max_messages,
+        uid_search_query
+    );
+
+    if uids.is_empty() {
+        session.logout().await.ok();
+        return Ok(PollCounts::default());
+    }
+
+    let mut processed_uids = Vec::with_capacity(uids.len());
+    let mut seen_uids = Vec::with_capacity(uids.len());
+    let mut failed_uids = Vec::new();
+    let mut parse_failures = 0usize;
+    let mut ignored_not_delivery = 0usize;
+    let mut ignored_missing_hash = 0usize;
+    let mut fetch_failures = 0usize;
+    let mut fetched_items = 0usize;
+    let mut fallback_fetch_attempts = 0usize;
+    let mut fallback_fetch_hits = 0usize;
+    let mut db_failures = 0usize;
+    let mut missing_in_db = 0usize;
+    let mut join_failures = 0usize;
+    let selected_total = uids.len();
+    let process_concurrency = max_messages.min(shared.max_processing);
+    let mut processing = JoinSet::new();
+
+    let uid_set = uids.iter().map(Uid::to_string).collect::<Vec<_>>().join(",");
+    let mut fetches = session
+        .uid_fetch(uid_set, IMAP_FETCH_QUERY_BODY_UID)
+        .await
+        .context("imap UID FETCH batch failed")?;
+
+    while let Some(fetch) =
+        fetches.try_next().await.context("imap UID FETCH batch stream failed")?
+    {
+        fetched_items += 1;
+
+        let Some(uid) = fetch.uid else {
+            fetch_failures += 1;
+            warn!("imap fetch item missing UID field: {target}");
+            continue;
+        };
+
+        debug!("imap processing message: {}, uid={}", target, uid);
+
+        let raw_mail = match fetch.body() {
+            Some(bytes) => {
+                debug!("imap message fetched: {}, uid={}, bytes={}", target, uid, bytes.len());
+                bytes.to_vec()
+            }
+            None => {
+                fetch_failures += 1;
+                warn!("imap message has no body: {target}, uid={uid}");
+                continue;
+            }
+        };
+        spawn_process(
+            &mut processing,
+            shared,
+            target,
+            uid,
+            raw_mail,
+            config.mark_seen_if_not_exist
+        )
+        .await?;
+
+        if processing.len() >= process_concurrency {
+            collect_one_process_result(
+                target,
+                &mut processing,
+                &mut processed_uids,
+                &mut seen_uids,
+                &mut failed_uids,
+                &mut parse_failures,
+                &mut ignored_not_delivery,
+                &mut ignored_missing_hash,
+                &mut db_failures,
+                &mut missing_in_db,
+                &mut join_failures
+            )
+            .await;
+        }
+    }
+
+    drop(fetches);
+
+    // Some IMAP servers may return UIDs in SEARCH but yield an empty stream in
+    // batched FETCH. Retry with per-UID fetch to separate "could not download"
+    // from parser/DB outcomes.
+    if selected_total > 0 && fetched_items == 0 {
+        warn!(
+            "imap batch fetch returned no messages, retrying per-uid fetch: {}, selected={}",
+            target, selected_total
+        );
+
+        for &uid in &uids {
+            fallback_fetch_attempts += 1;
+            match fetch_single_message_body(&mut session, uid).await {
+                Ok(Some(raw_mail)) => {
+                    fetched_items += 1;
+                    fallback_fetch_hits += 1;
+
+                    spawn_process(
+                        &mut processing,
+                        shared,
+                        target,
+                        uid,
+                        raw_mail,
+                        config.mark_seen_if_not_exist
+                    )
+                    .await?;
+                }
+                Ok(None) => {
+                    fetch_failures += 1;
+                    warn!("imap per-uid fetch returned no body: {}, uid={}", target, uid);
+                }
+                Err(err) => {
+                    fetch_failures += 1;
+                    warn!("imap per-uid fetch failed: {}, uid={}, error={err:#}", target, uid);
+                }
+            }
+
+            if processing.len() >= process_concurrency {
+                collect_one_process_result(
+                    target,
+                    &mut processing,
+                    &mut processed_uids,
+                    &mut seen_uids,
+                    &mut failed_uids,
+                    &mut parse_failures,
+                    &mut ignored_not_delivery,
+                    &mut ignored_missing_hash,
+                    &mut db_failures,
+                    &mut missing_in_db,
+                    &mut join_failures
+                )
+                .await;
+            }
+        }
+    }
+
+    while !processing.is_empty() {
+        collect_one_process_result(
+            target,
+            &mut processing,
+            &mut processed_uids,
+            &mut seen_uids,
+            &mut failed_uids,
+            &mut parse_failures,
+            &mut ignored_not_delivery,
+            &mut ignored_missing_hash,
+            &mut db_failures,
+            &mut missing_in_db,
+            &mut join_failures
+        )
+        .await;
+    }
+
+    let filed = file_messages(&mut session, config, &seen_uids, &failed_uids).await?;
+
+    session.logout().await.ok();
+
+    if selected_total > 0 && fetched_items == 0 {
+        warn!(
+            "imap poll selected messages but fetch stream returned none: {}, selected={}",
+            target, selected_total
+        );
+    }
+
+    info!(
+        "imap poll processed: {}, selected={}, fetched_items={}, fallback_fetch_attempts={}, fallback_fetch_hits={}, parsed_ok={}, parse_failures={}, ignored_not_delivery={}, ignored_missing_hash={}, fetch_failures={}, db_failures={}, missing_in_db={}, join_failures={}, marked_seen={}, moved_processed={}, moved_failed={}",
+        target,
+        selected_total,
+        fetched_items,
+        fallback_fetch_attempts,
+        fallback_fetch_hits,
+        processed_uids.len(),
+        parse_failures,
+        ignored_not_delivery,
+        ignored_missing_hash,
+        fetch_failures,
+        db_failures,
+        missing_in_db,
+        join_failures,
+        filed.seen,
+        filed.moved_processed,
+        filed.moved_failed
+    );
+
+    Ok(PollCounts {
+        selected: selected_total,
+        parsed_ok: processed_uids.len(),
+        parse_failures,
+        fetch_failures,
+        db_failures
+    })
+}
+
+async fn fetch_single_message_body(
+    session: &mut ImapSession,
+    uid: Uid
+) -> Result<Option<Vec<u8>>> {
+    let mut fetches = session
+        .uid_fetch(uid.to_string(), IMAP_FETCH_QUERY_BODY_UID)
+        .await
+        .with_context(|| format!("imap per-uid FETCH failed: uid={uid}"))?;
+
+    while let Some(fetch) = fetches
+        .try_next()
+        .await
+        .with_context(|| format!("imap per-uid FETCH stream failed: uid={uid}"))?
+    {
+        if let Some(bytes) = fetch.body() {
+            let fetched_uid = fetch.uid.unwrap_or(uid);
+            debug!("imap message fetched (per-uid): uid={}, bytes={}", fetched_uid, bytes.len());
+            return Ok(Some(bytes.to_vec()));
+        }
+    }
+
+    Ok(None)
+}
+
+#[derive(Debug)]
+enum ProcessResult {
+    Processed { uid: Uid },
+    MissingInDb { uid: Uid, hash: String, mark_seen: bool },
+    IgnoredNotDelivery { uid: Uid },
+    IgnoredMissingHash { uid: Uid },
+    ParseFailed { uid: Uid, code: &'static str, message: String },
+    DbFailed { uid: Uid, hash: String, message: String }
+}
+
+/// Processes one fetched message on its own task once a shared processing
+/// permit is free.
+async fn spawn_process(
+    processing: &mut JoinSet<ProcessResult>,
+    shared: &Arc<PollShared>,
+    target: &PollTarget,
+    uid: Uid,
+    raw_mail: Vec<u8>,
+    mark_seen_if_not_exist: bool
+) -> Result<()> {
+    let permit = shared
+        .processing
+        .clone()
+        .acquire_owned()
+        .await
+        .context("imap processing limiter closed")?;
+    let shared = shared.clone();
+    let target = target.clone();
+    processing.spawn(async move {
+        let _permit = permit;
+        process_fetched_message(&target, uid, raw_mail, &shared, mark_seen_if_not_exist).await
+    });
+    Ok(())
+}
+
+async fn process_fetched_message(
+    target: &PollTarget,
+    uid: Uid,
+    raw_mail: Vec<u8>,
+    shared: &PollShared,
+    mark_seen_if_not_exist: bool
+) -> ProcessResult {
+    let received_at = shared.clock.system_now();
+    let parsed = match shared.parsers.load().parse_detailed(&raw_mail) {
+        Ok(parsed) => {
+            debug!(
+                "imap message parsed: {}, uid={}, kind={}, hash={}, status_code={}, action={}, from={}, to={}",
+                target,
+                uid,
+                parsed.kind.as_str(),
+                parsed.hash,
+                parsed.status_code,
+                parsed.action.as_deref().unwrap_or("-"),
+                parsed.sender.as_deref().unwrap_or("-"),
+                parsed.recipient.as_deref().unwrap_or("-")
+            );
+            parsed
+        }
+        Err(ParserError::NotDeliveryReport) => {
+            return ProcessResult::IgnoredNotDelivery { uid };
+        }
+        Err(ParserError::MissingHash) => {
+            return ProcessResult::IgnoredMissingHash { uid };
+        }
+        Err(err) => {
+            return ProcessResult::ParseFailed { uid, code: err.code(), message: err.to_string() };
+        }
+    };
+
+    let outcome = shared.db.upsert_bounce(&parsed).await;
+    if shared.audit
+        && let Ok(applied) = &outcome
+    {
+        let audit = BounceAudit {
+            hash: parsed.hash.clone(),
+            ingest_path: IngestPath::Imap,
+            source: Some(target.account.clone()),
+            origin: format!("{}/{}/{}", target.account, target.mailbox, uid),
+            received_at,
+            processed_at: shared.clock.system_now(),
+            scan_labels: parsed.scan_labels.clone(),
+            outcome: audit_outcome(Some(*applied))
+        };
+        if let Err(err) = shared.db.insert_bounce_audit(&audit).await {
+            warn!(
+                "failed to record bounce audit: {target}, uid={uid}, hash={}, error={err:#}",
+                parsed.hash
+            );
+        }
+    }
+
+    match outcome {
+        Ok(UpsertBounceOutcome::UpdatedLocalMessage | UpsertBounceOutcome::Duplicate) => {
+            ProcessResult::Processed { uid }
+        }
+        Ok(UpsertBounceOutcome::MissingLocalMessage) => {
+            ProcessResult::MissingInDb { uid, hash: parsed.hash, mark_seen: mark_seen_if_not_exist }
+        }
+        Err(err) => ProcessResult::DbFailed { uid, hash: parsed.hash, message: format!("{err:#}") }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn collect_one_process_result(
+    target: &PollTarget,
+    processing: &mut JoinSet<ProcessResult>,
+    processed_uids: &mut Vec<Uid>,
+    seen_uids: &mut Vec<Uid>,
+    failed_uids: &mut Vec<Uid>,
+    parse_failures: &mut usize,
+    ignored_not_delivery: &mut usize,
+    ignored_missing_hash: &mut usize,
+    db_failures: &mut usize,
+    missing_in_db: &mut usize,
+    join_failures: &mut usize
+) {
+    match processing.join_next().await {
+        Some(Ok(ProcessResult::Processed { uid })) => {
+            processed_uids.push(uid);
+            seen_uids.push(uid);
+        }
+        Some(Ok(ProcessResult::MissingInDb { uid, hash, mark_seen })) => {
+            *missing_in_db += 1;
+            if mark_seen {
+                seen_uids.push(uid);
+            }
+            coded_warn!(
+                ErrorCode::ImapHashNotFoundInDb,
+                "imap message hash not found in DB: {}, uid={}, hash={}, mark_seen_if_not_exist={}",
+                target,
+                uid,
+                hash,
+                mark_seen
+            );
+        }
+        Some(Ok(ProcessResult::IgnoredNotDelivery { uid })) => {
+            *parse_failures += 1;
+            *ignored_not_delivery += 1;
+            seen_uids.push(uid);
+            failed_uids.push(uid);
+            coded_warn!(
+                ErrorCode::ImapDiscardedNotDelivery,
+                "imap message discarded and marked seen: {}, uid={}, parser_code={}, reason={}",
+                target,
+                uid,
+                ParserError::NotDeliveryReport.code(),
+                ParserError::NotDeliveryReport
+            );
+        }
+        Some(Ok(ProcessResult::IgnoredMissingHash { uid })) => {
+            *parse_failures += 1;
+            *ignored_missing_hash += 1;
+            seen_uids.push(uid);
+            failed_uids.push(uid);
+            coded_warn!(
+                ErrorCode::ImapDiscardedMissingHash,
+                "imap message discarded and marked seen: {}, uid={}, parser_code={}, reason={}",
+                target,
+                uid,
+                ParserError::MissingHash.code(),
+                ParserError::MissingHash
+            );
+        }
+        Some(Ok(ProcessResult::ParseFailed { uid, code, message })) => {
+            *parse_failures += 1;
+            failed_uids.push(uid);
+            coded_warn!(
+                ErrorCode::ImapParseFailed,
+                "imap message parse failed: {}, uid={}, parser_code={}, error={}",
+                target,
+                uid,
+                code,
+                message
+            );
+        }
+        Some(Ok(ProcessResult::DbFailed { uid, hash, message })) => {
+            *db_failures += 1;
+            coded_warn!(
+                ErrorCode::ImapDbUpsertFailed,
+                "imap message db upsert failed: {}, uid={}, hash={}, error={}",
+                target,
+                uid,
+                hash,
+                message
+            );
+        }
+        Some(Err(err)) => {
+            *join_failures += 1;
+            coded_warn!(
+                ErrorCode::ImapTaskJoinFailed,
+                "imap process task join failed: {target}, error={err}"
+            );
+        }
+        None => {}
+    }
+}
+
+fn build_uid_search_query(
+    max_history: Option<StdDuration>,
+    now: SystemTime
+) -> String {
+    match max_history {
+        Some(duration) => {
+            let since = format_imap_since_date(duration, now);
+            format!("UNSEEN SINCE {since}")
+        }
+        None => "UNSEEN".to_string()
+    }
+}
+
+fn format_imap_since_date(
+    duration: StdDuration,
+    now: SystemTime
+) -> String {
+    let seconds = duration.as_secs().min(i64::MAX as u64) as i64;
+    let cutoff = OffsetDateTime::from(now) - time::Duration::seconds(seconds);
+    format!("{:02}-{}-{}", cutoff.day(), month_short(cutoff.month()), cutoff.year())
+}
+
+fn month_short(month: Month) -> &'static str {
+    match month {
+        Month::January => "Jan",
+        Month::February => "Feb",
+        Month::March => "Mar",
+        Month::April => "Apr",
+        Month::May => "May",
+        Month::June => "Jun",
+        Month::July => "Jul",
+        Month::August => "Aug",
+        Month::September => "Sep",
+        Month::October => "Oct",
+        Month::November => "Nov",
+        Month::December => "Dec"
+    }
+}
+
+/// Logs in and out once; used by the startup diagnostics.
+pub async fn check_imap_login(config: &ImapConfig) -> Result<()> {
+    let mut session = open_imap_session(&ImapAccount::new(config.clone())?).await?;
+    session.logout().await.ok();
+    Ok(())
+}
+
+async fn open_imap_session(account: &ImapAccount) -> Result<ImapSession> {
+    let config = &account.config;
+    let host = config.host.as_deref().context("IMAP_HOST missing")?;
+    let user = config.user.as_deref().context("IMAP_USER missing")?;
+    let port = config.port;
+    let connect_timeout = Duration::from_secs(config.connect_timeout_secs.max(1));
+    // Fetched before connecting, so a broken token endpoint opens no session.
+    let access_token = match &account.tokens {
+        Some(tokens) => Some(
+            tokio::time::timeout(connect_timeout, tokens.access_token())
+                .await
+                .with_context(|| {
+                    format!(
+                        "imap oauth2 token timeout: host={host}, user={user}, timeout_secs={}",
+                        config.connect_timeout_secs
+                    )
+                })?
+                .with_context(|| format!("imap oauth2 token failed: host={host}, user={user}"))?
+        ),
+        None => None
+    };
+
+    let tcp = tokio::time::timeout(connect_timeout, TcpStream::connect((host, port)))
+        .await
+        .with_context(|| {
+            format!(
+                "imap tcp connect timeout: host={host}, port={port}, timeout_secs={}",
+                config.connect_timeout_secs
+            )
+        })?
+        .with_context(|| format!("imap tcp connect failed: host={host}, port={port}"))?;
+
+    let tls = TlsConnector::new();
+    let tls_stream = tokio::time::timeout(connect_timeout, tls.connect(host, tcp))
+        .await
+        .with_context(|| {
+            format!(
+                "imap tls handshake timeout: host={host}, port={port}, timeout_secs={}",
+                config.connect_timeout_secs
+            )
+        })?
+        .with_context(|| format!("imap tls handshake failed: host={host}, port={port}"))?;
+
+    let mut client = Client::new(tls_stream);
+    let resp = tokio::time::timeout(connect_timeout, client.read_response())
+        .await
+        .with_context(|| {
+            format!(
+                "imap greeting timeout: host={host}, port={port}, timeout_secs={}",
+                config.connect_timeout_secs
+            )
+        })?
+        .context("failed to read imap greeting")?
+        .context("unexpected end of stream while waiting imap greeting")?;
+
+    tracing::trace!("imap greeting: {resp:?}");
+
+    let login = async {
+        match access_token {
+            Some(token) => {
+                let authenticator = XOAuth2::new(user, &token);
+                let result = client.authenticate("XOAUTH2", authenticator).await;
+                if result.is_err()
+                    && let Some(tokens) = &account.tokens
+                {
+                    // Revoked or expired early; refresh on the next poll.
+                    tokens.invalidate().await;
+                }
+                result.map_err(|(err, _client)| err).with_context(|| {
+                    format!("imap XOAUTH2 authentication failed: host={host}, user={user}")
+                })
+            }
+            None => {
+                let pass = config.pass.as_deref().context("IMAP_PASS missing")?;
+                client
+                    .login(user, pass)
+                    .await
+                    .map_err(|(err, _client)| err)
+                    .with_context(|| format!("imap login failed: host={host}, user={user}"))
+            }
+        }
+    };
+    tokio::time::timeout(connect_timeout, login).await.with_context(|| {
+        format!(
+            "imap login timeout: host={host}, user={user}, timeout_secs={}",
+            config.connect_timeout_secs
+        )
+    })?
+}
+
+/// What [`file_messages`] did with the consumed messages.
+#[derive(Debug, Default)]
+struct Filed {
+    seen: usize,
+    moved_processed: usize,
+    moved_failed: usize
+}
+
+/// Marks consumed messages `\Seen` and, when configured, moves them out of
+/// the polled mailbox: applied ones to `move_processed_to`, discarded and
+/// unparsable ones to `move_failed_to`.
+///
+/// `seen_uids` are the messages marked seen without folders; `failed_uids`
+/// the discarded ones among them plus parse failures, which stay unseen for
+/// another try unless `move_failed_to` is set.
+async fn file_messages(
+    session: &mut ImapSession,
+    config: &ImapConfig,
+    seen_uids: &[Uid],
+    failed_uids: &[Uid]
+) -> Result<Filed> {
+    let (processed, failed) =
+        split_by_folder(seen_uids, failed_uids, config.move_failed_to.is_some());
+    let seen = [processed.as_slice(), failed.as_slice()].concat();
+    mark_seen_uids(session, &seen).await?;
+
+    let mut filed = Filed { seen: seen.len(), ..Filed::default() };
+    let mut capabilities = None;
+    if let Some(folder) = &config.move_processed_to {
+        filed.moved_processed = move_uids(session, &processed, folder, &mut capabilities).await?;
+    }
+    if let Some(folder) = &config.move_failed_to {
+        filed.moved_failed = move_uids(session, &failed, folder, &mut capabilities).await?;
+    }
+    Ok(filed)
+}
+
+/// Messages to mark seen as `(processed, failed)`; `failed` stays empty
+/// without a failed folder.
+fn split_by_folder(
+    seen_uids: &[Uid],
+    failed_uids: &[Uid],
+    move_failed: bool
+) -> (Vec<Uid>, Vec<Uid>) {
+    if !move_failed {
+        return (seen_uids.to_vec(), Vec::new());
+    }
+    let processed = seen_uids.iter().copied().filter(|uid| !failed_uids.contains(uid)).collect();
+    (processed, failed_uids.to_vec())
+}
+
+/// `MOVE` and `UIDPLUS` support of the session, asked once per poll.
+#[derive(Debug, Clone, Copy)]
+struct MoveCapabilities {
+    uid_move: bool,
+    uid_expunge: bool
+}
+
+/// UID MOVE when the server supports it (RFC 6851), else UID COPY, flag
+/// `\Deleted` and expunge: only these UIDs with UIDPLUS, the whole mailbox
+/// without it.
+async fn move_uids(
+    session: &mut ImapSession,
+    uids: &[Uid],
+    folder: &str,
+    capabilities: &mut Option<MoveCapabilities>
+) -> Result<usize> {
+    if uids.is_empty() {
+        return Ok(0);
+    }
+    let capabilities = match *capabilities {
+        Some(known) => known,
+        None => {
+            let caps = session.capabilities().await.context("imap CAPABILITY failed")?;
+            let known = MoveCapabilities {
+                uid_move: caps.has_str("MOVE"),
+                uid_expunge: caps.has_str("UIDPLUS")
+            };
+            debug!(
+                "imap move capabilities: move={}, uidplus={}",
+                known.uid_move, known.uid_expunge
+            );
+            *capabilities = Some(known);
+            known
+        }
+    };
+
+    let uid_set = uids.iter().map(Uid::to_string).collect::<Vec<_>>().join(",");
+    if capabilities.uid_move {
+        session
+            .uid_mv(&uid_set, folder)
+            .await
+            .with_context(|| format!("imap UID MOVE failed: folder={folder}"))?;
+        return Ok(uids.len());
+    }
+
+    session
+        .uid_copy(&uid_set, folder)
+        .await
+        .with_context(|| format!("imap UID COPY failed: folder={folder}"))?;
+    let mut updates = session
+        .uid_store(&uid_set, "+FLAGS (\\Deleted)")
+        .await
+        .context("imap UID STORE +FLAGS (\\\\Deleted) failed")?;
+    while updates.try_next().await.context("imap UID STORE response stream failed")?.is_some() {}
+    drop(updates);
+
+    if capabilities.uid_expunge {
+        let expunged = session.uid_expunge(&uid_set).await.context("imap UID EXPUNGE failed")?;
+        expunged
+            .try_collect::<Vec<_>>()
+            .await
+            .context("imap UID EXPUNGE response stream failed")?;
+    } else {
+        let expunged = session.expunge().await.context("imap EXPUNGE failed")?;
+        expunged.try_collect::<Vec<_>>().await.context("imap EXPUNGE response stream failed")?;
+    }
+    Ok(uids.len())
+}
+
+async fn mark_seen_uids(
+    session: &mut ImapSession,
+    uids: &[Uid]
+) -> Result<()> {
+    if uids.is_empty() {
+        return Ok(());
+    }
+
+    let uid_set = uids.iter().map(Uid::to_string).collect::<Vec<_>>().join(",");
+
+    let mut updates = session
+        .uid_store(uid_set, "+FLAGS (\\Seen)")
+        .await
+        .context("imap UID STORE +FLAGS (\\\\Seen) failed")?;
+
+    while updates.try_next().await.context("imap UID STORE response stream failed")?.is_some() {}
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bouncer_helpers::clock::{Clock, ManualClock};
+
+    use super::{build_uid_search_query, split_by_folder};
+
+    #[test]
+    fn uid_search_since_date_follows_clock() {
+        // 2024-03-01T12:00:00Z
+        let clock = ManualClock::at_unix(1_709_294_400);
+        let max_history = Some(Duration::from_secs(2 * 24 * 60 * 60));
+
+        assert_eq!(
+            build_uid_search_query(max_history, clock.system_now()),
+            "UNSEEN SINCE 28-Feb-2024"
+        );
+        assert_eq!(build_uid_search_query(None, clock.system_now()), "UNSEEN");
+
+        clock.advance(Duration::from_secs(24 * 60 * 60));
+        assert_eq!(
+            build_uid_search_query(max_history, clock.system_now()),
+            "UNSEEN SINCE 29-Feb-2024"
+        );
+    }
+
+    #[test]
+    fn failed_folder_takes_discarded_and_unparsable_messages() {
+        // 1 applied, 2 discarded (seen either way), 3 unparsable.
+        let (seen, failed) = ([1, 2], [2, 3]);
+        assert_eq!(split_by_folder(&seen, &failed, false), (vec![1, 2], vec![]));
+        assert_eq!(split_by_folder(&seen, &failed, true), (vec![1], vec![2, 3]));
+    }
+}
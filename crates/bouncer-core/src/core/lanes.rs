@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use bouncer_proto::status::QueueStatus;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{self, WeakSender};
+use tracing::debug;
+
+/// Spool files whose name ends with this go to the low-priority lane.
+const LOW_LANE_SUFFIX: &str = ".low.eml";
+
+/// Dispatcher priority lane of a spool file.
+///
+/// The lane is chosen when the frame is accepted (see
+/// `DispatcherConfig::lane_for`) and encoded in the spool file name, so it
+/// survives restarts and rescans without reading the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    High,
+    Low
+}
+
+impl Lane {
+    pub fn of_path(path: &Path) -> Self {
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if name.ends_with(LOW_LANE_SUFFIX) => Self::Low,
+            _ => Self::High
+        }
+    }
+
+    /// Spool file name for `id` in this lane.
+    pub fn file_name(
+        &self,
+        id: &str
+    ) -> String {
+        match self {
+            Self::High => format!("{id}.eml"),
+            Self::Low => format!("{id}{LOW_LANE_SUFFIX}")
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::High => "high",
+            Self::Low => "low"
+        }
+    }
+}
+
+/// Spool paths queued on a lane and not yet finished by a worker.
+///
+/// The notify watcher and the periodic scan both offer `incoming/` files;
+/// a path is queued once until the worker that took it releases it.
+#[derive(Debug, Default)]
+pub struct InFlightPaths {
+    paths: Mutex<HashSet<PathBuf>>
+}
+
+impl InFlightPaths {
+    /// Marks `path` as queued; `false` when it already is.
+    pub fn claim(
+        &self,
+        path: &Path
+    ) -> bool {
+        self.paths.lock().expect("in-flight mutex poisoned").insert(path.to_path_buf())
+    }
+
+    pub fn release(
+        &self,
+        path: &Path
+    ) {
+        self.paths.lock().expect("in-flight mutex poisoned").remove(path);
+    }
+
+    pub fn contains(
+        &self,
+        path: &Path
+    ) -> bool {
+        self.paths.lock().expect("in-flight mutex poisoned").contains(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.lock().expect("in-flight mutex poisoned").len()
+    }
+}
+
+/// Creates the two lane queues shared by the watcher, scanner and workers.
+/// Paths already in `in_flight` are not queued again.
+pub fn lane_channels(
+    high_capacity: usize,
+    low_capacity: usize,
+    high_weight: u32,
+    in_flight: Arc<InFlightPaths>
+) -> (LaneSender, LaneReceiver) {
+    let (high_tx, high_rx) = mpsc::channel(high_capacity.max(1));
+    let (low_tx, low_rx) = mpsc::channel(low_capacity.max(1));
+    (
+        LaneSender { high: high_tx, low: low_tx, in_flight },
+        LaneReceiver {
+            high: high_rx,
+            low: low_rx,
+            high_weight: high_weight.max(1),
+            high_streak: 0
+        }
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct LaneSender {
+    high: mpsc::Sender<PathBuf>,
+    low: mpsc::Sender<PathBuf>,
+    in_flight: Arc<InFlightPaths>
+}
+
+impl LaneSender {
+    /// Queues `path` on its lane; returns `false` once the workers are gone.
+    ///
+    /// The high lane applies backpressure. A full low lane drops the path
+    /// instead, so backfill never blocks the watcher in front of fresh mail;
+    /// the file stays in `incoming/` and the periodic scan offers it again.
+    /// A path that is still in flight is skipped; the worker that finishes
+    /// it releases it (see [`InFlightPaths`]).
+    pub async fn send(
+        &self,
+        path: PathBuf
+    ) -> bool {
+        if !self.in_flight.claim(&path) {
+            debug!("path already queued, skipping: path={}", path.display());
+            return true;
+        }
+
+        match Lane::of_path(&path) {
+            Lane::High => self.high.send(path).await.is_ok(),
+            Lane::Low => match self.low.try_send(path) {
+                Ok(()) => true,
+                Err(TrySendError::Full(path)) => {
+                    debug!("low lane full, leaving file for next scan: path={}", path.display());
+                    self.in_flight.release(&path);
+                    true
+                }
+                Err(TrySendError::Closed(_)) => false
+            }
+        }
+    }
+
+    /// Depth probe for the status frame; it does not keep the lanes open.
+    pub fn gauge(&self) -> LaneGauge {
+        LaneGauge { high: self.high.downgrade(), low: self.low.downgrade() }
+    }
+}
+
+#[derive(Debug)]
+pub struct LaneGauge {
+    high: WeakSender<PathBuf>,
+    low: WeakSender<PathBuf>
+}
+
+impl LaneGauge {
+    /// Queued paths and capacity per lane, `None` once the lanes closed.
+    pub fn snapshot(&self) -> Option<QueueStatus> {
+        let high = self.high.upgrade()?;
+        let low = self.low.upgrade()?;
+        let queued = |lane: &mpsc::Sender<PathBuf>| (lane.max_capacity() - lane.capacity()) as u64;
+        Some(QueueStatus {
+            high: queued(&high),
+            high_capacity: high.max_capacity() as u64,
+            low: queued(&low),
+            low_capacity: low.max_capacity() as u64
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct LaneReceiver {
+    high: mpsc::Receiver<PathBuf>,
+    low: mpsc::Receiver<PathBuf>,
+    high_weight: u32,
+    high_streak: u32
+}
+
+impl LaneReceiver {
+    /// Returns the next path, serving up to `high_weight` high-lane files
+    /// for every low-lane file while both lanes have work. `None` once both
+    /// lanes are closed and drained.
+    pub async fn recv(&mut self) -> Option<(Lane, PathBuf)> {
+        let order = if self.high_streak >= self.high_weight {
+            [Lane::Low, Lane::High]
+        } else {
+            [Lane::High, Lane::Low]
+        };
+
+        for lane in order {
+            let ready = match lane {
+                Lane::High => self.high.try_recv(),
+                Lane::Low => self.low.try_recv()
+            };
+            if let Ok(path) = ready {
+                self.served(lane);
+                return Some((lane, path));
+            }
+        }
+
+        let (lane, path) = tokio::select! {
+            biased;
+            Some(path) = self.high.recv() => (Lane::High, path),
+            Some(path) = self.low.recv() => (Lane::Low, path),
+            else => return None
+        };
+        self.served(lane);
+        Some((lane, path))
+    }
+
+    fn served(
+        &mut self,
+        lane: Lane
+    ) {
+        match lane {
+            Lane::High => self.high_streak = self.high_streak.saturating_add(1),
+            Lane::Low => self.high_streak = 0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use super::{InFlightPaths, Lane, lane_channels};
+
+    #[tokio::test]
+    async fn weighted_dequeue_interleaves_low_lane() {
+        let (tx, mut rx) = lane_channels(16, 16, 2, Arc::default());
+        for idx in 0..4 {
+            assert!(tx.send(PathBuf::from(Lane::Low.file_name(&format!("low{idx}")))).await);
+        }
+        for idx in 0..6 {
+            assert!(tx.send(PathBuf::from(Lane::High.file_name(&format!("high{idx}")))).await);
+        }
+
+        let mut lanes = Vec::new();
+        for _ in 0..10 {
+            lanes.push(rx.recv().await.expect("queued path").0);
+        }
+
+        use Lane::{High, Low};
+        assert_eq!(lanes, [High, High, Low, High, High, Low, High, High, Low, Low]);
+        assert_eq!(Lane::of_path(Path::new("incoming/abc.low.eml")), Low);
+        assert_eq!(Lane::of_path(Path::new("incoming/abc.eml")), High);
+    }
+
+    #[tokio::test]
+    async fn in_flight_paths_are_queued_once_until_released() {
+        let in_flight = Arc::new(InFlightPaths::default());
+        let (tx, mut rx) = lane_channels(8, 1, 2, in_flight.clone());
+        let path = PathBuf::from("incoming/a.eml");
+        assert!(tx.send(path.clone()).await);
+        assert!(tx.send(path.clone()).await);
+        assert_eq!(rx.recv().await.map(|(_, path)| path), Some(path.clone()));
+        assert!(in_flight.contains(&path));
+
+        in_flight.release(&path);
+        assert!(tx.send(path.clone()).await);
+        assert_eq!(rx.recv().await.map(|(_, path)| path), Some(path));
+
+        // A path dropped by the full low lane is offered again later.
+        let first = PathBuf::from(Lane::Low.file_name("first"));
+        let dropped = PathBuf::from(Lane::Low.file_name("dropped"));
+        assert!(tx.send(first.clone()).await);
+        assert!(tx.send(dropped.clone()).await);
+        assert!(in_flight.contains(&first) && !in_flight.contains(&dropped));
+    }
+}
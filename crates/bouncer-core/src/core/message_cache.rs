@@ -0,0 +1,207 @@
+//! Read-side cache of `mail_messages` ids by hash (`message_cache`).
+//!
+//! Every bounce and observer event looks its message up by hash, and one
+//! hash comes back many times: delayed, then bounced, then expired, often
+//! from several paths. [`MessageIdCache`] remembers the id the lookup found,
+//! and for a shorter `negative_ttl` that there was none, so repeated hashes
+//! skip the `SELECT`.
+//!
+//! `mail_messages` belongs to the application, so the cache errs towards
+//! asking again:
+//! - a miss is only remembered briefly, since the message may be inserted
+//!   right after the bounce arrived;
+//! - [`Database::has_local_message`](super::database::Database::has_local_message)
+//!   always asks the database and drops a cached miss it contradicts, so
+//!   missing-message retries see the message once it exists;
+//! - an update that touches no row, or a write that fails, after using a
+//!   cached id drops it and looks the hash up again, in case the message
+//!   row was deleted or replaced.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+use bouncer_helpers::clock::SharedClock;
+
+use crate::config::MessageCacheConfig;
+
+/// `(hash, tenant)`; the tenant is only set when messages are matched by
+/// tenant.
+type Key = (String, Option<String>);
+
+#[derive(Debug)]
+struct Entry {
+    id: Option<u32>,
+    expires_at: Instant,
+    /// Key of this entry in `Inner::order`.
+    tick: u64
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<Key, Entry>,
+    /// Keys by last use, least recent first.
+    order: BTreeMap<u64, Key>,
+    next_tick: u64
+}
+
+/// Bounded TTL cache of message ids that drops the least recently used
+/// entry when full; a disabled one stores nothing.
+#[derive(Debug)]
+pub struct MessageIdCache {
+    config: MessageCacheConfig,
+    clock: SharedClock,
+    inner: Mutex<Inner>
+}
+
+impl MessageIdCache {
+    pub fn new(
+        config: MessageCacheConfig,
+        clock: SharedClock
+    ) -> Self {
+        Self { config, clock, inner: Mutex::new(Inner::default()) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled()
+    }
+
+    /// `Some(id)` for a cached lookup, where `id` is `None` for a hash
+    /// known to have no message; `None` when the database must be asked.
+    pub fn get(
+        &self,
+        hash: &str,
+        tenant: Option<&str>
+    ) -> Option<Option<u32>> {
+        if !self.enabled() {
+            return None;
+        }
+        let key = (hash.to_string(), tenant.map(str::to_string));
+        let mut guard = self.lock();
+        let inner = &mut *guard;
+        let entry = inner.entries.get_mut(&key)?;
+        inner.order.remove(&entry.tick);
+        if entry.expires_at <= self.clock.now() {
+            inner.entries.remove(&key);
+            return None;
+        }
+        entry.tick = inner.next_tick;
+        inner.next_tick += 1;
+        inner.order.insert(entry.tick, key);
+        Some(entry.id)
+    }
+
+    /// Remembers the result of a lookup; the least recently used entry makes
+    /// room once `max_entries` are cached.
+    pub fn insert(
+        &self,
+        hash: &str,
+        tenant: Option<&str>,
+        id: Option<u32>
+    ) {
+        let ttl = if id.is_some() { self.config.ttl } else { self.config.negative_ttl };
+        if !self.enabled() || ttl.is_zero() {
+            return;
+        }
+        let key = (hash.to_string(), tenant.map(str::to_string));
+        let expires_at = self.clock.now() + ttl;
+        let mut inner = self.lock();
+        let tick = inner.next_tick;
+        inner.next_tick += 1;
+        if let Some(previous) = inner.entries.insert(key.clone(), Entry { id, expires_at, tick }) {
+            inner.order.remove(&previous.tick);
+        }
+        inner.order.insert(tick, key);
+        while inner.entries.len() > self.config.max_entries {
+            let Some((_, oldest)) = inner.order.pop_first() else {
+                break;
+            };
+            inner.entries.remove(&oldest);
+        }
+    }
+
+    /// Drops the entry of `hash`, found or missing.
+    pub fn forget(
+        &self,
+        hash: &str,
+        tenant: Option<&str>
+    ) {
+        if !self.enabled() {
+            return;
+        }
+        let key = (hash.to_string(), tenant.map(str::to_string));
+        let mut inner = self.lock();
+        if let Some(entry) = inner.entries.remove(&key) {
+            inner.order.remove(&entry.tick);
+        }
+    }
+
+    /// Drops a cached miss of `hash`; a found id is kept.
+    pub fn forget_missing(
+        &self,
+        hash: &str,
+        tenant: Option<&str>
+    ) {
+        if self.get(hash, tenant) == Some(None) {
+            self.forget(hash, tenant);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bouncer_helpers::clock::ManualClock;
+
+    use super::MessageIdCache;
+    use crate::config::MessageCacheConfig;
+
+    #[test]
+    fn expires_misses_first_and_evicts_the_least_recently_used_entry() {
+        let clock = Arc::new(ManualClock::at_unix(1_700_000_000));
+        let cache = MessageIdCache::new(
+            MessageCacheConfig {
+                max_entries: 2,
+                ttl: Duration::from_secs(60),
+                negative_ttl: Duration::from_secs(5)
+            },
+            clock.clone()
+        );
+
+        cache.insert("a", None, Some(1));
+        cache.insert("b", Some("shop"), None);
+        assert_eq!(cache.get("a", None), Some(Some(1)));
+        assert_eq!(cache.get("b", Some("shop")), Some(None));
+        assert_eq!(cache.get("b", None), None);
+
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(cache.get("b", Some("shop")), None);
+        assert_eq!(cache.get("a", None), Some(Some(1)));
+
+        cache.insert("c", None, Some(3));
+        assert_eq!(cache.get("a", None), Some(Some(1)));
+        cache.insert("d", None, Some(4));
+        assert_eq!(cache.get("c", None), None, "least recently used");
+        assert_eq!(cache.get("a", None), Some(Some(1)));
+
+        cache.insert("e", None, None);
+        cache.forget_missing("a", None);
+        cache.forget_missing("e", None);
+        assert_eq!(cache.get("d", None), None, "evicted by e");
+        assert_eq!(cache.get("a", None), Some(Some(1)));
+        assert_eq!(cache.get("e", None), None);
+
+        clock.advance(Duration::from_secs(61));
+        assert_eq!(cache.get("a", None), None);
+
+        let disabled = MessageIdCache::new(MessageCacheConfig::default(), clock);
+        disabled.insert("a", None, Some(1));
+        assert_eq!(disabled.get("a", None), None);
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::{Context, Result, bail};
+use sqlx::migrate::{Migrate, Migrator};
+use tracing::info;
+
+use super::database::Pool;
+use crate::config::MigrateMode;
+
+static MYSQL_MIGRATOR: Migrator = sqlx::migrate!("migrations/mysql");
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("migrations/sqlite");
+
+const MYSQL_HAS_MIGRATIONS_TABLE: &str = "SELECT COUNT(*) FROM information_schema.TABLES WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = '_sqlx_migrations'";
+const SQLITE_HAS_MIGRATIONS_TABLE: &str =
+    "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'";
+
+/// Applies (`auto`) or verifies (`check`) the embedded schema migrations of
+/// the connected backend. Applied versions are tracked in `_sqlx_migrations`.
+pub(super) async fn apply_migrations(
+    pool: &Pool,
+    mode: MigrateMode
+) -> Result<()> {
+    let migrator = match pool {
+        Pool::MySql(_) => &MYSQL_MIGRATOR,
+        Pool::Sqlite(_) => &SQLITE_MIGRATOR
+    };
+
+    let applied = match (mode, pool) {
+        (MigrateMode::Off, _) => {
+            info!("schema migrations skipped: backend={}, migrate=off", pool.backend());
+            return Ok(());
+        }
+        (MigrateMode::Auto, Pool::MySql(pool)) => migrator.run(pool).await,
+        (MigrateMode::Auto, Pool::Sqlite(pool)) => migrator.run(pool).await,
+        (MigrateMode::Check, Pool::MySql(pool)) => {
+            let tracked = sqlx::query_scalar::<_, i64>(MYSQL_HAS_MIGRATIONS_TABLE)
+                .fetch_one(pool)
+                .await
+                .context("failed to look up _sqlx_migrations")?
+                > 0;
+            let mut conn = pool.acquire().await.context("failed to acquire connection")?;
+            return check_applied(&mut *conn, migrator, tracked).await;
+        }
+        (MigrateMode::Check, Pool::Sqlite(pool)) => {
+            let tracked = sqlx::query_scalar::<_, i64>(SQLITE_HAS_MIGRATIONS_TABLE)
+                .fetch_one(pool)
+                .await
+                .context("failed to look up _sqlx_migrations")?
+                > 0;
+            let mut conn = pool.acquire().await.context("failed to acquire connection")?;
+            return check_applied(&mut *conn, migrator, tracked).await;
+        }
+    };
+    applied.context("failed to apply schema migrations")?;
+
+    info!(
+        "schema migrations applied: backend={}, version={}",
+        pool.backend(),
+        latest_version(migrator)
+    );
+    Ok(())
+}
+
+/// Fails when an embedded migration is not applied yet or was edited after
+/// it was applied; never writes to the database.
+async fn check_applied<C>(
+    conn: &mut C,
+    migrator: &Migrator,
+    tracked: bool
+) -> Result<()>
+where
+    C: Migrate
+{
+    let applied = if tracked {
+        if let Some(version) = conn.dirty_version().await.context("failed to read migrations")? {
+            bail!("schema migration {version} is partially applied; fix it by hand, then restart");
+        }
+        conn.list_applied_migrations().await.context("failed to read migrations")?
+    } else {
+        Vec::new()
+    };
+
+    let mut pending = Vec::new();
+    let mut modified = Vec::new();
+    for migration in migrator.iter().filter(|migration| migration.migration_type.is_up_migration())
+    {
+        let name = format!("{}_{}", migration.version, migration.description);
+        match applied.iter().find(|applied| applied.version == migration.version) {
+            None => pending.push(name),
+            Some(applied) if applied.checksum != migration.checksum => modified.push(name),
+            Some(_) => {}
+        }
+    }
+
+    if !pending.is_empty() || !modified.is_empty() {
+        bail!(
+            "database schema is not up to date (migrate=check): pending=[{}], modified=[{}]; start once with `migrate: auto` to apply them",
+            pending.join(","),
+            modified.join(",")
+        );
+    }
+    info!("schema migrations verified: version={}", latest_version(migrator));
+    Ok(())
+}
+
+fn latest_version(migrator: &Migrator) -> i64 {
+    migrator.iter().map(|migration| migration.version).max().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::apply_migrations;
+    use crate::config::MigrateMode;
+    use crate::core::database::Pool;
+
+    #[tokio::test]
+    async fn check_mode_requires_applied_migrations() {
+        let pool = Pool::connect("sqlite::memory:").await.unwrap();
+
+        let err = apply_migrations(&pool, MigrateMode::Check).await.expect_err("fresh database");
+        assert!(
+            err.to_string().contains(
+                "pending=[1_standalone schema,2_bounce occurrences,3_bounce reason,4_bounce dedup,5_source events,6_bounce authentication,7_bounce archive,8_bounce category,9_observer event order,10_bounce audit,11_bounce events,12_tenant,13_processed spool retention,14_source events retention,15_suppression lifecycle]"
+            ),
+            "{err}"
+        );
+
+        apply_migrations(&pool, MigrateMode::Off).await.unwrap();
+        apply_migrations(&pool, MigrateMode::Auto).await.unwrap();
+        apply_migrations(&pool, MigrateMode::Check).await.unwrap();
+        apply_migrations(&pool, MigrateMode::Auto).await.unwrap();
+    }
+}
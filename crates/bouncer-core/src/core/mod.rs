@@ -0,0 +1,83 @@
+mod admin_api;
+mod alerts;
+mod allowlist;
+mod archive;
+mod audit;
+mod authentication;
+mod batching;
+mod capture;
+mod check;
+mod classification;
+mod connections;
+mod database;
+mod debug_dump;
+mod diagnostics;
+mod dispatcher;
+mod failures;
+mod faults;
+mod frame_audit;
+mod hooks;
+mod imap;
+mod lanes;
+mod message_cache;
+mod migrations;
+mod parser;
+mod quarantine;
+mod query;
+mod recipients;
+mod reload;
+mod resilience;
+mod retention;
+mod retries;
+mod s3;
+mod server;
+mod smtp;
+mod sources;
+mod spool;
+mod status;
+mod storage;
+mod tenants;
+mod traces;
+
+pub use admin_api::{AdminTriggers, run_admin_api};
+pub use alerts::BounceAlerts;
+pub use allowlist::PeerAllowlist;
+pub use archive::{BounceArchive, run_archive_retention};
+pub use audit::{BounceAudit, IngestPath, SpoolOrigins};
+pub use authentication::BounceAuthenticator;
+pub use batching::run_observer_batcher;
+pub use capture::PayloadCapture;
+pub use check::{CheckSummary, check_dir};
+pub use connections::ConnectionStats;
+pub use database::{
+    Database, ObserverEventOutcome, UpsertBounceOutcome, run_bounce_dedup_prune,
+    run_suppression_expiry, run_table_prune
+};
+pub use debug_dump::run_debug_signals;
+pub use diagnostics::run_startup_diagnostics;
+pub use dispatcher::{
+    idempotency_key, spawn_notify_watcher, spawn_periodic_scan, spawn_worker_dispatcher
+};
+pub use failures::run_failure_summary;
+pub use faults::Faults;
+pub use frame_audit::FrameAudit;
+pub use hooks::{BounceHooks, run_bounce_hooks};
+pub use lanes::{InFlightPaths, Lane, lane_channels};
+pub use parser::{
+    DEFAULT_HASH_HEADERS, DEFAULT_PARSER_CHAIN, HashRules, ParsedBounce, ParserChain, ParserError,
+    ReportKind
+};
+pub use quarantine::replay_quarantine_on_start;
+pub use reload::{Live, run_config_reload};
+pub use resilience::run_db_health_check;
+pub use retention::run_spool_retention;
+pub use retries::{DbRetries, MissingMessageRetries, run_db_retries, run_missing_message_retries};
+pub use server::{
+    ClientTimeouts, DEFAULT_MAX_BODY_BYTES, DEFAULT_MAX_HEADER_BYTES, FrameLimits, run_tcp_server
+};
+pub use smtp::run_smtp_server;
+pub use sources::{SourceRegistry, run_source_monitor};
+pub use spool::{Spool, SpoolCounts, partition_name};
+pub use status::RuntimeStatus;
+pub use storage::{SharedSpoolStorage, SpoolStorage, spool_storage};
+pub use traces::SpoolTraces;
@@ -0,0 +1,1716 @@
+mod arf;
+mod autoreply;
+mod dsn;
+mod exchange;
+mod heuristic;
+mod reason;
+
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bouncer_helpers::message_hash::HashFormat;
+use bouncer_proto::event::DeliveryEvent;
+use bouncer_proto::verp::VerpTemplate;
+use flate2::read::GzDecoder;
+use mail_parser::parsers::MessageStream;
+use mail_parser::{HeaderValue, Message, MessageParser, MessagePart, MimeHeaders};
+use tracing::{debug, warn};
+
+use crate::config::{ParseBudget, ParserConfig, StatusCodeMode};
+
+/// What kind of report a parsed message is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportKind {
+    /// Delivery status or feedback report (DSN, NDR, ARF).
+    Bounce,
+    /// Out-of-office / vacation auto-response. Proves nothing about delivery
+    /// failure and never changes `mail_messages.status`.
+    Autoreply
+}
+
+impl ReportKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Bounce => "bounce",
+            Self::Autoreply => "autoreply"
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedBounce {
+    pub kind: ReportKind,
+    pub hash: String,
+    pub status_code: String,
+    pub action: Option<String>,
+    pub sender: Option<String>,
+    pub recipient: Option<String>,
+    pub description: Option<String>,
+    /// Stages that ran, then where the hash and status code were found
+    /// (`hash=<scan>`, `status=<scan>`); recorded in `mail_bounce_audit`.
+    pub scan_labels: Vec<String>,
+    /// Tenant the hash belongs to; the parser never sets it, it comes from
+    /// the frame header or the observer event.
+    pub tenant: Option<String>
+}
+
+impl ParsedBounce {
+    /// Short human-readable reason (`mailbox full`, `user unknown`, ...)
+    /// derived from the diagnostic and status code.
+    pub fn reason(&self) -> Option<&'static str> {
+        reason::classify(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParserError {
+    NotDeliveryReport,
+    MissingHash,
+    MissingStatusCode,
+    /// The message has more MIME parts than `parser.budget.max_candidates`,
+    /// or parsing ran past `parser.budget.max_scan_time`.
+    BudgetExceeded
+}
+
+impl fmt::Display for ParserError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        match self {
+            Self::NotDeliveryReport => {
+                write!(f, "message does not look like a delivery status report")
+            }
+            Self::MissingHash => {
+                write!(f, "bounce hash not found (X-Message-Id/Message-ID)")
+            }
+            Self::MissingStatusCode => write!(f, "status code not found"),
+            Self::BudgetExceeded => {
+                write!(f, "message exceeds the parse budget (parts or scan time)")
+            }
+        }
+    }
+}
+
+impl Error for ParserError {}
+
+impl ParserError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotDeliveryReport => "NOT_DELIVERY_REPORT",
+            Self::MissingHash => "MISSING_HASH",
+            Self::MissingStatusCode => "MISSING_STATUS_CODE",
+            Self::BudgetExceeded => "BUDGET_EXCEEDED"
+        }
+    }
+}
+
+/// The bounce an `observer_event` stands for.
+impl From<&DeliveryEvent> for ParsedBounce {
+    fn from(event: &DeliveryEvent) -> Self {
+        Self {
+            kind: ReportKind::Bounce,
+            hash: event.hash.clone(),
+            status_code: event.status_code.clone(),
+            action: Some(event.action.clone()),
+            sender: None,
+            recipient: Some(event.recipient.clone()),
+            description: Some(event.diagnostic.clone()),
+            scan_labels: Vec::new(),
+            tenant: event.tenant.clone()
+        }
+    }
+}
+
+/// Stage names accepted in `parser.chain`, in the default order.
+pub const DEFAULT_PARSER_CHAIN: [&str; 4] = [dsn::NAME, arf::NAME, exchange::NAME, heuristic::NAME];
+
+/// One provider-specific stage of the bounce parser chain.
+///
+/// Each stage only knows its own format: it reports whether it recognizes
+/// the message and returns the fields it found. Merging and the final
+/// required-field checks stay in [`ParserChain`].
+pub trait BounceParser: Send + Sync {
+    /// Name used in `parser.chain`.
+    fn name(&self) -> &'static str;
+
+    /// Returns true when this stage recognizes the message as a report it
+    /// handles. A message no stage detects is `NotDeliveryReport`.
+    fn detect(
+        &self,
+        input: &BounceInput<'_>
+    ) -> bool;
+
+    /// Returns `found` (what earlier stages merged) extended with the fields
+    /// this stage extracted.
+    fn parse(
+        &self,
+        input: &BounceInput<'_>,
+        found: &ParsedFields
+    ) -> ParsedFields;
+}
+
+fn parser_by_name(name: &str) -> Option<Box<dyn BounceParser>> {
+    match name {
+        dsn::NAME => Some(Box::new(dsn::DsnParser)),
+        arf::NAME => Some(Box::new(arf::ArfParser)),
+        exchange::NAME => Some(Box::new(exchange::ExchangeParser)),
+        heuristic::NAME => Some(Box::new(heuristic::HeuristicTextParser)),
+        _ => None
+    }
+}
+
+/// Parsed view of one raw mail shared by all stages.
+pub struct BounceInput<'a> {
+    raw_mail: &'a [u8],
+    hash: &'a HashRules,
+    candidates: Vec<AttachmentScanCandidate<'a>>,
+    full_text: OnceCell<String>,
+    /// `parser.budget.max_candidate_bytes`, applied to [`Self::full_text`].
+    max_text_bytes: usize
+}
+
+impl<'a> BounceInput<'a> {
+    /// Lossy UTF-8 view of the raw message, up to `max_text_bytes`, decoded
+    /// on first use.
+    fn full_text(&self) -> &str {
+        self.full_text.get_or_init(|| {
+            let scanned = &self.raw_mail[..self.raw_mail.len().min(self.max_text_bytes)];
+            String::from_utf8_lossy(scanned).into_owned()
+        })
+    }
+}
+
+/// Headers searched for the message hash by default, highest priority first.
+pub const DEFAULT_HASH_HEADERS: [&str; 5] =
+    ["X-Message-Id", "X-MS-Exchange-Parent-Message-Id", "In-Reply-To", "References", "Message-ID"];
+
+/// Where the stages look for the message hash and which values count as one.
+#[derive(Debug, Clone)]
+pub struct HashRules {
+    /// Highest priority first.
+    headers: Vec<String>,
+    format: HashFormat
+}
+
+impl Default for HashRules {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_HEADERS.map(str::to_string).to_vec(), HashFormat::default())
+    }
+}
+
+impl HashRules {
+    pub fn new(
+        headers: Vec<String>,
+        format: HashFormat
+    ) -> Self {
+        Self { headers, format }
+    }
+}
+
+/// Ordered list of [`BounceParser`] stages.
+pub struct ParserChain {
+    parsers: Vec<Box<dyn BounceParser>>,
+    verp: Option<VerpTemplate>,
+    status_codes: StatusCodeMode,
+    hash: HashRules,
+    budget: ParseBudget
+}
+
+impl Default for ParserChain {
+    fn default() -> Self {
+        Self::from_names(&DEFAULT_PARSER_CHAIN).expect("default parser chain names are valid")
+    }
+}
+
+impl ParserChain {
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self> {
+        let parsers = names
+            .iter()
+            .map(|name| {
+                parser_by_name(name.as_ref()).with_context(|| {
+                    format!(
+                        "unknown bounce parser: {} (known: {})",
+                        name.as_ref(),
+                        DEFAULT_PARSER_CHAIN.join(",")
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if parsers.is_empty() {
+            bail!("bounce parser chain is empty");
+        }
+
+        Ok(Self {
+            parsers,
+            verp: None,
+            status_codes: StatusCodeMode::default(),
+            hash: HashRules::default(),
+            budget: ParseBudget::default()
+        })
+    }
+
+    /// Chain, VERP template, status code mode and hash rules of `parser`.
+    pub fn from_config(parser: &ParserConfig) -> Result<Self> {
+        Ok(Self::from_names(&parser.chain)
+            .context("invalid parser.chain config")?
+            .with_verp(parser.verp()?)
+            .with_status_codes(parser.status_codes)
+            .with_hash_rules(parser.hash_rules()?)
+            .with_budget(parser.budget))
+    }
+
+    /// Decodes the hash from VERP envelope recipients before any stage runs;
+    /// a hash found there wins over every header inside the report.
+    pub fn with_verp(
+        mut self,
+        verp: Option<VerpTemplate>
+    ) -> Self {
+        self.verp = verp;
+        self
+    }
+
+    pub fn verp(&self) -> Option<&VerpTemplate> {
+        self.verp.as_ref()
+    }
+
+    pub fn with_hash_rules(
+        mut self,
+        hash: HashRules
+    ) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    pub fn with_budget(
+        mut self,
+        budget: ParseBudget
+    ) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub fn with_status_codes(
+        mut self,
+        mode: StatusCodeMode
+    ) -> Self {
+        self.status_codes = mode;
+        self
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.parsers.iter().map(|parser| parser.name()).collect()
+    }
+
+    pub fn parse(
+        &self,
+        raw_mail: &[u8]
+    ) -> Result<ParsedBounce> {
+        self.parse_detailed(raw_mail).map_err(anyhow::Error::new)
+    }
+
+    pub fn parse_detailed(
+        &self,
+        raw_mail: &[u8]
+    ) -> std::result::Result<ParsedBounce, ParserError> {
+        let started = Instant::now();
+        let parsed_message = message_parser().parse(raw_mail);
+        let candidates = match parsed_message.as_ref() {
+            Some(message) => collect_attachment_text_candidates(message, &self.budget, started)?,
+            None => Vec::new()
+        };
+        let input = BounceInput {
+            raw_mail,
+            hash: &self.hash,
+            candidates,
+            full_text: OnceCell::new(),
+            max_text_bytes: self.budget.max_candidate_bytes
+        };
+        check_scan_time(started, &self.budget)?;
+
+        // Auto-replies often quote "undelivered"-style text, so classify them
+        // before the report heuristics get a say; real DSN/ARF parts still win.
+        if let Some(result) = autoreply::classify(&input) {
+            return result;
+        }
+        if !self.parsers.iter().any(|parser| parser.detect(&input)) {
+            return Err(ParserError::NotDeliveryReport);
+        }
+
+        let mut merged = ParsedFields::default();
+        if let Some(hash) = self.verp.as_ref().and_then(|verp| envelope_verp_hash(verp, &input)) {
+            merged.hash = Some(hash);
+            merged.hash_priority = 0;
+            merged.hash_scan = Some("envelope".to_string());
+        }
+        let mut scan_labels = Vec::new();
+        for parser in &self.parsers {
+            if merged.has_required() {
+                break;
+            }
+            check_scan_time(started, &self.budget)?;
+            scan_labels.push(parser.name().to_string());
+            let mut parsed = parser.parse(&input, &merged);
+            parsed.status_code =
+                parsed.status_code.and_then(|code| self.check_status_code(parser.name(), code));
+            merge_missing(&mut merged, parsed);
+            debug!(
+                "bounce parser stage done: stage={}, hash={}, status_code={}",
+                parser.name(),
+                merged.hash.is_some(),
+                merged.status_code.is_some()
+            );
+        }
+
+        let hash = merged.hash.ok_or(ParserError::MissingHash)?;
+        let status_code = merged.status_code.ok_or(ParserError::MissingStatusCode)?;
+        scan_labels.extend(merged.hash_scan.map(|scan| format!("hash={scan}")));
+        scan_labels.extend(merged.status_scan.map(|scan| format!("status={scan}")));
+
+        Ok(ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash,
+            status_code,
+            action: merged.action,
+            sender: merged.sender,
+            recipient: merged.recipient,
+            description: merged.description,
+            scan_labels,
+            tenant: None
+        })
+    }
+}
+
+impl ParserChain {
+    /// Applies the configured [`StatusCodeMode`] to a code found by `stage`.
+    fn check_status_code(
+        &self,
+        stage: &str,
+        code: String
+    ) -> Option<String> {
+        match (normalize_status_code(&code), self.status_codes) {
+            (Some(normalized), StatusCodeMode::Strict) => Some(normalized),
+            (Some(_), StatusCodeMode::Lenient) => Some(code),
+            (None, StatusCodeMode::Strict) => {
+                debug!("non-conforming status code dropped: stage={}, status_code={}", stage, code);
+                None
+            }
+            (None, StatusCodeMode::Lenient) => {
+                warn!("non-conforming status code accepted: stage={}, status_code={}", stage, code);
+                Some(code)
+            }
+        }
+    }
+}
+
+/// RFC 3463 enhanced status code (`class "." subject "." detail`, class 2, 4
+/// or 5, subject and detail 1-3 digits) with leading zeros removed, e.g.
+/// `5.01.001` -> `5.1.1`.
+pub fn normalize_status_code(code: &str) -> Option<String> {
+    let mut parts = code.split('.');
+    let (class, subject, detail) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || !matches!(class, "2" | "4" | "5") {
+        return None;
+    }
+    let number = |part: &str| -> Option<u16> {
+        if part.is_empty() || part.len() > 3 || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        part.parse().ok()
+    };
+    Some(format!("{class}.{}.{}", number(subject)?, number(detail)?))
+}
+
+fn header_value<'a>(
+    line: &'a str,
+    header_name: &str
+) -> Option<&'a str> {
+    let (name, value) = line.split_once(':')?;
+    if name.trim().eq_ignore_ascii_case(header_name) { Some(value.trim()) } else { None }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedFields {
+    hash: Option<String>,
+    hash_priority: u8,
+    /// Scan label of the text the hash was found in.
+    hash_scan: Option<String>,
+    status_code: Option<String>,
+    status_scan: Option<String>,
+    action: Option<String>,
+    sender: Option<String>,
+    recipient: Option<String>,
+    description: Option<String>
+}
+
+impl Default for ParsedFields {
+    fn default() -> Self {
+        Self {
+            hash: None,
+            hash_priority: u8::MAX,
+            hash_scan: None,
+            status_code: None,
+            status_scan: None,
+            action: None,
+            sender: None,
+            recipient: None,
+            description: None
+        }
+    }
+}
+
+impl ParsedFields {
+    fn has_required(&self) -> bool {
+        self.hash.is_some() && self.status_code.is_some()
+    }
+
+    /// Drops a hash found in text that is not trusted as a hash source.
+    fn clear_hash(&mut self) {
+        self.hash = None;
+        self.hash_priority = u8::MAX;
+        self.hash_scan = None;
+    }
+}
+
+fn parse_fields_from_text(
+    text: &str,
+    scan_label: &str,
+    hash: &HashRules
+) -> ParsedFields {
+    let mut parsed = ParsedFields::default();
+    let mut current = String::new();
+    let mut logical_lines_scanned = 0usize;
+
+    for raw in text.lines() {
+        let line = raw.trim_end_matches('\r');
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if !current.is_empty() {
+                current.push(' ');
+                current.push_str(line.trim_start());
+            }
+            continue;
+        }
+
+        if !current.is_empty() {
+            logical_lines_scanned += 1;
+            apply_header_line(&mut parsed, &current, scan_label, logical_lines_scanned, hash);
+            // Lazy stop: once required fields are found, avoid scanning the
+            // rest of large MIME payloads.
+            if parsed.has_required() {
+                debug!(
+                    "bounce parser lazy stop: scan={}, scanned_lines={}, found=hash+status",
+                    scan_label, logical_lines_scanned
+                );
+                return parsed;
+            }
+        }
+
+        current.clear();
+        current.push_str(line);
+    }
+
+    if !current.is_empty() {
+        apply_header_line(
+            &mut parsed,
+            &current,
+            scan_label,
+            logical_lines_scanned.saturating_add(1),
+            hash
+        );
+    }
+
+    parsed
+}
+
+fn apply_header_line(
+    parsed: &mut ParsedFields,
+    line: &str,
+    scan_label: &str,
+    line_no: usize,
+    hash: &HashRules
+) {
+    for (priority, header_name) in hash.headers.iter().enumerate() {
+        let priority = u8::try_from(priority).unwrap_or(u8::MAX - 1);
+        try_set_hash_from_header(
+            parsed,
+            line,
+            header_name,
+            priority,
+            &hash.format,
+            scan_label,
+            line_no
+        );
+    }
+
+    if parsed.status_code.is_none()
+        && let Some(value) = header_value(line, "Status")
+    {
+        parsed.status_code = parse_status_code(value);
+        if parsed.status_code.is_some() {
+            parsed.status_scan = Some(scan_label.to_string());
+        }
+    }
+
+    if parsed.action.is_none()
+        && let Some(value) = header_value(line, "Action")
+    {
+        let word = value.split_whitespace().next().unwrap_or("").trim();
+        if !word.is_empty() {
+            parsed.action = Some(word.to_string());
+        }
+    }
+
+    if parsed.recipient.is_none()
+        && let Some(value) = header_value(line, "Original-Recipient")
+            .or_else(|| header_value(line, "Final-Recipient"))
+    {
+        let recipient = decode_recipient(value);
+        if !recipient.is_empty() {
+            parsed.recipient = Some(recipient);
+        }
+    }
+
+    if parsed.sender.is_none()
+        && let Some(value) = header_value(line, "X-Postfix-Sender")
+            .or_else(|| header_value(line, "Return-Path"))
+            .or_else(|| header_value(line, "From"))
+        && let Some(sender) = extract_mailbox(&decode_encoded_words(value))
+    {
+        parsed.sender = Some(sender);
+    }
+
+    if parsed.description.is_none()
+        && let Some(value) = header_value(line, "Diagnostic-Code")
+    {
+        let value = decode_encoded_words(value);
+        let description =
+            value.split_once(';').map(|(_, rhs)| rhs.trim()).unwrap_or_else(|| value.trim());
+        if !description.is_empty() {
+            parsed.description = Some(description.to_string());
+        }
+    }
+}
+/// Decodes RFC 2047 encoded-words (`=?utf-8?Q?...?=`) in a header value;
+/// values without any are returned as is.
+fn decode_encoded_words(value: &str) -> Cow<'_, str> {
+    if !value.contains("=?") {
+        return Cow::Borrowed(value);
+    }
+    let line = format!("{value}\n");
+    match MessageStream::new(line.as_bytes()).parse_unstructured() {
+        HeaderValue::Text(text) => Cow::Owned(text.into_owned()),
+        _ => Cow::Borrowed(value)
+    }
+}
+
+/// Address of a `Final-Recipient` or `Original-Recipient` value
+/// (`<address-type>; <address>`). `utf-8` addresses (RFC 6533) arrive raw in
+/// `message/global-delivery-status` and with `\x{HEX}` escapes in downgraded
+/// reports; both come back as plain UTF-8.
+fn decode_recipient(value: &str) -> String {
+    let (address_type, address) = value.split_once(';').unwrap_or(("", value));
+    let address = decode_encoded_words(address.trim());
+    if address_type.trim().eq_ignore_ascii_case("utf-8") {
+        unescape_utf8_addr(&address)
+    } else {
+        address.into_owned()
+    }
+}
+
+/// Replaces `\x{HEX}` escapes (RFC 6533 `utf-8-addr-xtext`) by their
+/// characters; malformed escapes are kept as they are.
+fn unescape_utf8_addr(address: &str) -> String {
+    let mut out = String::with_capacity(address.len());
+    let mut rest = address;
+    while let Some(start) = rest.find("\\x{") {
+        out.push_str(&rest[..start]);
+        let escape = &rest[start..];
+        let decoded = escape[3..].split_once('}').and_then(|(hex, tail)| {
+            Some((char::from_u32(u32::from_str_radix(hex, 16).ok()?)?, tail))
+        });
+        match decoded {
+            Some((ch, tail)) => {
+                out.push(ch);
+                rest = tail;
+            }
+            None => {
+                out.push_str(&escape[..3]);
+                rest = &escape[3..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn try_set_hash_from_header(
+    parsed: &mut ParsedFields,
+    line: &str,
+    header_name: &str,
+    priority: u8,
+    format: &HashFormat,
+    scan_label: &str,
+    line_no: usize
+) {
+    let Some(value) = header_value(line, header_name) else {
+        return;
+    };
+
+    let Some(hash) = extract_hash_from_message_id_like_header(value, format) else {
+        return;
+    };
+
+    if parsed.hash.is_some() && parsed.hash_priority <= priority {
+        return;
+    }
+
+    debug!(
+        "bounce parser hash found: scan={}, line={}, header={}, hash={}, priority={}",
+        scan_label, line_no, header_name, hash, priority
+    );
+    parsed.hash = Some(hash);
+    parsed.hash_priority = priority;
+    parsed.hash_scan = Some(scan_label.to_string());
+}
+
+/// Envelope recipients `bounce-delivery` prepends from the Postfix pipe
+/// arguments (`${recipient}`, `${original_recipient}`).
+const ENVELOPE_TO_HEADER: &str = "X-Bouncer-Envelope-To";
+const ORIGINAL_TO_HEADER: &str = "X-Bouncer-Original-To";
+
+/// Envelope recipient headers of the report itself, in the order they are
+/// trusted: `bounce-delivery` records the pipe arguments, and the MTA records
+/// the VERP address on final delivery.
+const ENVELOPE_RECIPIENT_HEADERS: [&str; 5] =
+    [ORIGINAL_TO_HEADER, ENVELOPE_TO_HEADER, "X-Original-To", "Delivered-To", "To"];
+
+/// Top-level header lines of the raw message.
+fn top_level_headers<'a>(input: &'a BounceInput<'_>) -> Vec<&'a str> {
+    input
+        .full_text()
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .take_while(|line| !line.is_empty())
+        .collect()
+}
+
+/// Hash encoded in a VERP envelope recipient among the top-level headers.
+fn envelope_verp_hash(
+    verp: &VerpTemplate,
+    input: &BounceInput<'_>
+) -> Option<String> {
+    let headers = top_level_headers(input);
+
+    ENVELOPE_RECIPIENT_HEADERS.iter().find_map(|header_name| {
+        headers.iter().find_map(|line| {
+            let value = header_value(line, header_name)?;
+            let mailbox = extract_mailbox(value)?;
+            let hash = input.hash.format.extract(verp.decode(&mailbox)?)?;
+            debug!(
+                "bounce parser hash found: scan=envelope, header={}, hash={}",
+                header_name, hash
+            );
+            Some(hash)
+        })
+    })
+}
+
+fn merge_missing(
+    target: &mut ParsedFields,
+    source: ParsedFields
+) {
+    if source.hash.is_some()
+        && (target.hash.is_none() || source.hash_priority < target.hash_priority)
+    {
+        target.hash = source.hash;
+        target.hash_priority = source.hash_priority;
+        target.hash_scan = source.hash_scan;
+    }
+    if target.status_code.is_none() {
+        target.status_code = source.status_code;
+        target.status_scan = source.status_scan;
+    }
+    if target.action.is_none() {
+        target.action = source.action;
+    }
+    if target.sender.is_none() {
+        target.sender = source.sender;
+    }
+    if target.recipient.is_none() {
+        target.recipient = source.recipient;
+    }
+    if target.description.is_none() {
+        target.description = source.description;
+    }
+}
+
+fn constrain_hash_source(
+    parsed: &mut ParsedFields,
+    kind: CandidateKind
+) {
+    if !matches!(kind, CandidateKind::OriginalHeaders | CandidateKind::OriginalMessage) {
+        parsed.clear_hash();
+    }
+}
+
+#[derive(Debug)]
+struct AttachmentScanCandidate<'a> {
+    scan_label: String,
+    text: Cow<'a, str>,
+    kind: CandidateKind,
+    priority: u8
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CandidateKind {
+    DeliveryStatus,
+    FeedbackReport,
+    OriginalHeaders,
+    OriginalMessage,
+    TextBody,
+    Other
+}
+
+/// Scan candidates of `parsed`, each cut to `budget.max_candidate_bytes`.
+/// Fails once more than `budget.max_candidates` MIME parts were walked, or
+/// once a part is reached past `budget.max_scan_time` since `started`.
+fn collect_attachment_text_candidates<'a>(
+    parsed: &'a Message<'a>,
+    budget: &ParseBudget,
+    started: Instant
+) -> std::result::Result<Vec<AttachmentScanCandidate<'a>>, ParserError> {
+    let mut collector = CandidateCollector { out: Vec::new(), budget, started, parts: 0 };
+    collector.collect_attachments(parsed, "0")?;
+    collector.collect_text_bodies(parsed, "0")?;
+    let mut out = collector.out;
+    out.sort_by_key(|candidate| candidate.priority);
+    Ok(out)
+}
+
+/// Fails once `budget.max_scan_time` has passed since `started`.
+fn check_scan_time(
+    started: Instant,
+    budget: &ParseBudget
+) -> std::result::Result<(), ParserError> {
+    let elapsed = started.elapsed();
+    if elapsed > budget.max_scan_time {
+        warn!(
+            "bounce parse budget exceeded: elapsed_ms={}, max_scan_time_ms={}",
+            elapsed.as_millis(),
+            budget.max_scan_time.as_millis()
+        );
+        return Err(ParserError::BudgetExceeded);
+    }
+    Ok(())
+}
+
+fn message_parser() -> &'static MessageParser {
+    static PARSER: OnceLock<MessageParser> = OnceLock::new();
+    PARSER.get_or_init(MessageParser::default)
+}
+
+struct CandidateCollector<'a, 'b> {
+    out: Vec<AttachmentScanCandidate<'a>>,
+    budget: &'b ParseBudget,
+    started: Instant,
+    parts: usize
+}
+
+impl<'a> CandidateCollector<'a, '_> {
+    fn visit_part(&mut self) -> std::result::Result<(), ParserError> {
+        self.parts += 1;
+        if self.parts > self.budget.max_candidates {
+            warn!(
+                "bounce parse budget exceeded: parts>{}, max_candidates={}",
+                self.parts - 1,
+                self.budget.max_candidates
+            );
+            return Err(ParserError::BudgetExceeded);
+        }
+        check_scan_time(self.started, self.budget)
+    }
+
+    fn push(
+        &mut self,
+        scan_label: String,
+        text: Cow<'a, str>,
+        kind: CandidateKind
+    ) {
+        let text = truncate_text(text, self.budget.max_candidate_bytes);
+        let priority = attachment_scan_priority(kind, &text);
+        self.out.push(AttachmentScanCandidate { scan_label, text, kind, priority });
+    }
+
+    fn collect_attachments(
+        &mut self,
+        message: &'a Message<'a>,
+        path: &str
+    ) -> std::result::Result<(), ParserError> {
+        for (idx, part) in message.attachments().enumerate() {
+            self.visit_part()?;
+            let part_path = format!("{path}.{idx}");
+            let mime = part_mime_type(part);
+
+            if let Some(text) = unwrap_encoded_payload(part.contents())
+                && !text.trim().is_empty()
+            {
+                let kind = classify_unwrapped_kind(&mime, part.attachment_name(), &text);
+                debug!(
+                    "bounce parser unwrapped encoded attachment: mime={}, path={}, bytes={}, kind={:?}",
+                    mime,
+                    part_path,
+                    text.len(),
+                    kind
+                );
+                self.push(
+                    format!("attachment:{}+decoded@{}", mime, part_path),
+                    Cow::Owned(text),
+                    kind
+                );
+            } else if should_scan_attachment_mime(&mime)
+                && let Some(text) = decoded_part_text(part)
+                && !text.trim().is_empty()
+            {
+                let kind = classify_attachment_kind(&mime);
+                self.push(format!("attachment:{}@{}", mime, part_path), Cow::Borrowed(text), kind);
+            }
+
+            if let Some(nested) = part.message() {
+                self.collect_attachments(nested, &format!("{part_path}.m"))?;
+                self.collect_text_bodies(nested, &format!("{part_path}.m"))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn collect_text_bodies(
+        &mut self,
+        message: &'a Message<'a>,
+        path: &str
+    ) -> std::result::Result<(), ParserError> {
+        for (idx, part) in message.text_bodies().enumerate() {
+            self.visit_part()?;
+            if let Some(text) = decoded_part_text(part)
+                && !text.trim().is_empty()
+            {
+                self.push(
+                    format!("text_body:text/plain@{path}.{idx}"),
+                    Cow::Borrowed(text),
+                    CandidateKind::TextBody
+                );
+            }
+        }
+
+        for (idx, part) in message.html_bodies().enumerate() {
+            self.visit_part()?;
+            if let Some(text) = decoded_part_text(part)
+                && !text.trim().is_empty()
+            {
+                self.push(
+                    format!("text_body:text/html@{path}.{idx}"),
+                    Cow::Borrowed(text),
+                    CandidateKind::TextBody
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// First `max_bytes` of `text`, cut back to a char boundary.
+fn truncate_text(
+    text: Cow<'_, str>,
+    max_bytes: usize
+) -> Cow<'_, str> {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    match text {
+        Cow::Borrowed(text) => Cow::Borrowed(&text[..end]),
+        Cow::Owned(mut text) => {
+            text.truncate(end);
+            Cow::Owned(text)
+        }
+    }
+}
+
+fn part_mime_type(part: &MessagePart<'_>) -> String {
+    if let Some(ct) = part.content_type() {
+        let ctype = ct.ctype().trim().to_ascii_lowercase();
+        if let Some(subtype) = ct.subtype() {
+            return format!("{}/{}", ctype, subtype.trim().to_ascii_lowercase());
+        }
+        return ctype;
+    }
+
+    if part.is_text_html() {
+        return "text/html".to_string();
+    }
+    if part.is_text() {
+        return "text/plain".to_string();
+    }
+    if part.is_message() {
+        return "message/rfc822".to_string();
+    }
+
+    "application/octet-stream".to_string()
+}
+
+fn should_scan_attachment_mime(mime: &str) -> bool {
+    classify_attachment_kind(mime) != CandidateKind::Other
+}
+
+/// `message/global-*` are the internationalized (RFC 6532/6533) forms of the
+/// report parts, sent for SMTPUTF8 messages.
+fn classify_attachment_kind(mime: &str) -> CandidateKind {
+    match mime {
+        "message/delivery-status" | "message/global-delivery-status" => {
+            CandidateKind::DeliveryStatus
+        }
+        "message/feedback-report" => CandidateKind::FeedbackReport,
+        "text/rfc822-headers" | "message/global-headers" => CandidateKind::OriginalHeaders,
+        "message/rfc822" | "message/global" => CandidateKind::OriginalMessage,
+        _ if mime.starts_with("text/") => CandidateKind::TextBody,
+        _ => CandidateKind::Other
+    }
+}
+
+fn attachment_scan_priority(
+    kind: CandidateKind,
+    text: &str
+) -> u8 {
+    match kind {
+        CandidateKind::DeliveryStatus | CandidateKind::FeedbackReport => 0,
+        CandidateKind::OriginalHeaders => 1,
+        CandidateKind::OriginalMessage => 2,
+        CandidateKind::TextBody => {
+            if looks_like_delivery_report(text) {
+                3
+            } else {
+                4
+            }
+        }
+        CandidateKind::Other => {
+            if looks_like_delivery_report(text) {
+                4
+            } else {
+                5
+            }
+        }
+    }
+}
+
+/// Gzip member magic bytes (RFC 1952).
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// Upper bound for decompressed attachment text.
+const MAX_UNWRAPPED_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Unwraps payloads that are still encoded after MIME transfer decoding:
+/// gzip members (`application/gzip`, `*.gz`) and bare base64 bodies sent
+/// without a `Content-Transfer-Encoding` header. Returns `None` for anything
+/// else so the regular text path handles it.
+fn unwrap_encoded_payload(bytes: &[u8]) -> Option<String> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        return gunzip_text(bytes);
+    }
+
+    let compact = bytes.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect::<Vec<_>>();
+    if compact.len() < 24
+        || !compact.iter().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'='))
+    {
+        return None;
+    }
+
+    let decoded = BASE64.decode(&compact).ok()?;
+    if decoded.starts_with(&GZIP_MAGIC) {
+        return gunzip_text(&decoded);
+    }
+    let text = String::from_utf8(decoded).ok()?;
+    // Only accept header-like text; arbitrary base64 (images, keys) stays opaque.
+    text.lines()
+        .any(|line| line.split_once(':').is_some_and(|(name, _)| is_header_name(name)))
+        .then_some(text)
+}
+
+fn gunzip_text(bytes: &[u8]) -> Option<String> {
+    let mut out = Vec::new();
+    GzDecoder::new(bytes).take(MAX_UNWRAPPED_BYTES).read_to_end(&mut out).ok()?;
+    Some(String::from_utf8_lossy(&out).into_owned())
+}
+
+fn is_header_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Picks the candidate kind for an unwrapped payload: typed MIME wins, then
+/// content and file name decide between DSN fields and an original message.
+fn classify_unwrapped_kind(
+    mime: &str,
+    file_name: Option<&str>,
+    text: &str
+) -> CandidateKind {
+    let declared = classify_attachment_kind(mime);
+    if !matches!(declared, CandidateKind::TextBody | CandidateKind::Other) {
+        return declared;
+    }
+
+    let lower = text.to_ascii_lowercase();
+    if lower.contains("final-recipient:") || lower.contains("reporting-mta:") {
+        return CandidateKind::DeliveryStatus;
+    }
+
+    let file_name = file_name.unwrap_or_default().to_ascii_lowercase();
+    if file_name.contains(".eml") || lower.lines().any(|line| line.starts_with("message-id:")) {
+        return CandidateKind::OriginalMessage;
+    }
+
+    CandidateKind::Other
+}
+
+fn decoded_part_text<'a>(part: &'a MessagePart<'a>) -> Option<&'a str> {
+    if let Some(text) = part.text_contents()
+        && !text.is_empty()
+    {
+        return Some(text);
+    }
+
+    let bytes = part.contents();
+    if bytes.is_empty() {
+        return None;
+    }
+
+    std::str::from_utf8(bytes).ok()
+}
+
+fn extract_hash_from_message_id_like_header(
+    value: &str,
+    format: &HashFormat
+) -> Option<String> {
+    // Prefer explicit RFC5322 message-id tokens enclosed in angle brackets.
+    let mut start = 0usize;
+    while let Some(open_rel) = value[start..].find('<') {
+        let open = start + open_rel;
+        if let Some(close_rel) = value[open + 1..].find('>') {
+            let close = open + 1 + close_rel;
+            if let Some(hash) = format.extract(&value[open..=close]) {
+                return Some(hash);
+            }
+            start = close + 1;
+        } else {
+            break;
+        }
+    }
+
+    // Fallback: parse whitespace-separated tokens.
+    for token in value.split_whitespace() {
+        if let Some(hash) = format.extract(token) {
+            return Some(hash);
+        }
+    }
+
+    format.extract(value)
+}
+
+fn extract_mailbox(value: &str) -> Option<String> {
+    let raw = value.split_once(';').map(|(_, rhs)| rhs.trim()).unwrap_or_else(|| value.trim());
+
+    let inner = if let Some(start) = raw.find('<') {
+        if let Some(end_rel) = raw[start + 1..].find('>') {
+            &raw[start + 1..start + 1 + end_rel]
+        } else {
+            raw
+        }
+    } else {
+        raw
+    };
+
+    let candidate = inner.trim().trim_matches(|c| c == '<' || c == '>' || c == '"' || c == '\'');
+
+    if candidate.contains('@') { Some(candidate.to_string()) } else { None }
+}
+
+fn parse_status_code(value: &str) -> Option<String> {
+    let candidate = value.split_whitespace().next().unwrap_or("").trim();
+    if is_valid_status_code(candidate) { Some(candidate.to_string()) } else { None }
+}
+
+fn is_valid_status_code(code: &str) -> bool {
+    !code.is_empty() && code.len() <= 20 && code.chars().all(|c| c.is_ascii_digit() || c == '.')
+}
+
+fn looks_like_delivery_report(text: &str) -> bool {
+    let lower = text.to_ascii_lowercase();
+    [
+        "final-recipient:",
+        "original-recipient:",
+        "diagnostic-code:",
+        "report-type=delivery-status",
+        "message/delivery-status",
+        "undelivered",
+        "mail delivery",
+        "returned mail"
+    ]
+    .iter()
+    .any(|marker| lower.contains(marker))
+}
+
+fn find_status_code_in_text(text: &str) -> Option<String> {
+    text.split(|ch: char| !(ch.is_ascii_digit() || ch == '.'))
+        .find(|token| {
+            token.len() >= 5
+                && token.matches('.').count() >= 2
+                && is_valid_status_code(token)
+                && (token.starts_with("2.") || token.starts_with("4.") || token.starts_with("5."))
+        })
+        .map(ToOwned::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use bouncer_helpers::message_hash::HashFormatConfig;
+
+    use super::*;
+
+    #[test]
+    fn parses_postfix_delivery_status_with_hash_from_rfc822_part() {
+        let raw = concat!(
+            "From: Mail Delivery System <mailer-daemon@claviron.app>\r\n",
+            "Content-Type: multipart/report; report-type=delivery-status; boundary=\"B19557E240.1761150593/claviron.app\"\r\n",
+            "\r\n",
+            "--B19557E240.1761150593/claviron.app\r\n",
+            "Content-Description: Delivery report\r\n",
+            "Content-Type: message/delivery-status\r\n",
+            "\r\n",
+            "Reporting-MTA: dns; claviron.app\r\n",
+            "X-Postfix-Queue-ID: B19557E240\r\n",
+            "X-Postfix-Sender: rfc822; noreply@claviron.app\r\n",
+            "Arrival-Date: Wed, 22 Oct 2025 19:29:52 +0300 (+03)\r\n",
+            "\r\n",
+            "Final-Recipient: rfc822; janedoe@gmail.com\r\n",
+            "Original-Recipient: rfc822;janedoe@gmail.com\r\n",
+            "Action: failed\r\n",
+            "Status: 5.7.1\r\n",
+            "Remote-MTA: dns; gmail-smtp-in.l.google.com\r\n",
+            "Diagnostic-Code: smtp; 550-5.7.1 Gmail has detected\r\n",
+            "    that this message is likely suspicious.\r\n",
+            "    550 5.7.1 https://support.google.com/mail/answer/188131\r\n",
+            "\r\n",
+            "--B19557E240.1761150593/claviron.app\r\n",
+            "Content-Type: message/rfc822\r\n",
+            "\r\n",
+            "From: noreply@claviron.app\r\n",
+            "To: janedoe@gmail.com\r\n",
+            "Message-ID: <c27335e4586d69311bb4668e9dc70bd5@claviron.app>\r\n",
+            "Subject: test\r\n",
+            "\r\n",
+            "hello\r\n",
+            "\r\n",
+            "--B19557E240.1761150593/claviron.app--\r\n",
+        );
+
+        let parsed = ParserChain::default()
+            .parse_detailed(raw.as_bytes())
+            .expect("postfix DSN sample should parse");
+
+        assert_eq!(parsed.hash, "c27335e4586d69311bb4668e9dc70bd5");
+        assert_eq!(parsed.status_code, "5.7.1");
+        assert_eq!(parsed.action.as_deref(), Some("failed"));
+        assert_eq!(parsed.recipient.as_deref(), Some("janedoe@gmail.com"));
+        assert!(parsed.description.as_deref().unwrap_or_default().contains("550-5.7.1"));
+    }
+
+    #[test]
+    fn returns_missing_hash_when_dsn_has_no_message_id_reference() {
+        let raw = concat!(
+            "Content-Type: message/delivery-status\r\n",
+            "\r\n",
+            "Final-Recipient: rfc822; user@example.com\r\n",
+            "Action: failed\r\n",
+            "Status: 5.7.1\r\n",
+            "Diagnostic-Code: smtp; 550 5.7.1 blocked\r\n",
+        );
+
+        let err = ParserChain::default()
+            .parse_detailed(raw.as_bytes())
+            .expect_err("missing hash should fail");
+        assert_eq!(err, ParserError::MissingHash);
+    }
+
+    #[test]
+    fn verp_envelope_recipient_supplies_and_overrides_hash() {
+        let report = concat!(
+            "Content-Type: multipart/report; report-type=delivery-status; boundary=\"b\"\r\n",
+            "\r\n",
+            "--b\r\n",
+            "Content-Type: message/delivery-status\r\n",
+            "\r\n",
+            "Final-Recipient: rfc822; user@example.com\r\n",
+            "Action: failed\r\n",
+            "Status: 5.1.1\r\n",
+            "\r\n",
+            "--b\r\n",
+            "Content-Type: text/rfc822-headers\r\n",
+            "\r\n",
+            "Message-ID: <0000ffff@example.com>\r\n",
+            "\r\n",
+            "--b--\r\n",
+        );
+        let chain = ParserChain::default()
+            .with_verp(Some(VerpTemplate::parse("bounce-{hash}@example.com").unwrap()));
+
+        let raw = format!(
+            "X-Original-To: <bounce-a1b2c3@example.com>\r\nTo: bounce-ffff@example.com\r\n{report}"
+        );
+        let parsed = chain.parse_detailed(raw.as_bytes()).expect("VERP bounce should parse");
+        assert_eq!(parsed.hash, "a1b2c3");
+        assert_eq!(parsed.status_code, "5.1.1");
+
+        let raw = format!("Delivered-To: postmaster@example.com\r\n{report}");
+        let parsed = chain.parse_detailed(raw.as_bytes()).expect("header hash still applies");
+        assert_eq!(parsed.hash, "0000ffff");
+    }
+
+    #[test]
+    fn bounce_delivery_envelope_supplies_hash_but_no_recipient() {
+        let report = concat!(
+            "X-Bouncer-Envelope-From: MAILER-DAEMON\r\n",
+            "X-Bouncer-Envelope-To: bounces@example.com\r\n",
+            "X-Bouncer-Original-To: bounce-a1b2c3@example.com\r\n",
+            "Delivered-To: bounce-ffff@example.com\r\n",
+            "Subject: Undelivered Mail Returned to Sender\r\n",
+            "\r\n",
+            "This is the mail system at host mx.example.net.\r\n",
+            "Your message could not be delivered.\r\n",
+            "Status: 5.1.1\r\n",
+        );
+        let chain = ParserChain::default()
+            .with_verp(Some(VerpTemplate::parse("bounce-{hash}@example.com").unwrap()));
+
+        let parsed = chain.parse_detailed(report.as_bytes()).expect("envelope bounce should parse");
+        assert_eq!(parsed.hash, "a1b2c3");
+        assert_eq!(parsed.status_code, "5.1.1");
+        // The envelope names our own return path, never the failed recipient.
+        assert_eq!(parsed.recipient, None);
+    }
+
+    #[test]
+    fn strict_status_codes_follow_rfc3463() {
+        assert_eq!(normalize_status_code("5.01.001").as_deref(), Some("5.1.1"));
+        assert_eq!(normalize_status_code("4.4.7").as_deref(), Some("4.4.7"));
+        for code in ["5...1", "5.1", "3.1.1", "5.1.1.1", "5.1234.1", "12345678901234567890"] {
+            assert_eq!(normalize_status_code(code), None, "{code}");
+        }
+
+        let report = |status: &str| {
+            format!(
+                "Content-Type: multipart/report; report-type=delivery-status; boundary=\"b\"\r\n\r\n--b\r\nContent-Type: message/delivery-status\r\n\r\nFinal-Recipient: rfc822; user@example.com\r\nAction: failed\r\nStatus: {status}\r\n\r\n--b\r\nContent-Type: text/rfc822-headers\r\n\r\nMessage-ID: <a1b2c3@example.com>\r\n\r\n--b--\r\n"
+            )
+        };
+        let strict = ParserChain::default().with_status_codes(StatusCodeMode::Strict);
+        let parsed = strict.parse_detailed(report("5.01.001").as_bytes()).expect("conforming code");
+        assert_eq!(parsed.status_code, "5.1.1");
+        assert_eq!(
+            strict.parse_detailed(report("5...1").as_bytes()).expect_err("malformed code"),
+            ParserError::MissingStatusCode
+        );
+
+        let lenient =
+            ParserChain::default().parse_detailed(report("5...1").as_bytes()).expect("compat");
+        assert_eq!(lenient.status_code, "5...1");
+    }
+
+    #[test]
+    fn hash_rules_pick_configured_headers_and_format() {
+        let report = "Content-Type: multipart/report; report-type=delivery-status; boundary=\"b\"\r\n\r\n--b\r\nContent-Type: message/delivery-status\r\n\r\nFinal-Recipient: rfc822; user@example.com\r\nAction: failed\r\nStatus: 5.1.1\r\n\r\n--b\r\nContent-Type: text/rfc822-headers\r\n\r\nMessage-ID: <trk.Ab_9-xY.42@example.com>\r\nX-Campaign-Ref: <a1b2c3d4@example.com>\r\n\r\n--b--\r\n";
+
+        let parsed =
+            ParserChain::default().parse_detailed(report.as_bytes()).expect("default headers");
+        assert_eq!(parsed.hash, "trkAb9xY42");
+
+        let format = HashFormat::new(&HashFormatConfig {
+            lengths: Vec::new(),
+            pattern: Some(r"^trk\.([A-Za-z0-9_-]+)\.".to_string())
+        })
+        .unwrap();
+        let tracked = ParserChain::default()
+            .with_hash_rules(HashRules::new(vec!["Message-ID".into()], format));
+        assert_eq!(tracked.parse_detailed(report.as_bytes()).expect("pattern").hash, "Ab_9-xY");
+
+        let custom = HashRules::new(
+            vec!["X-Campaign-Ref".into(), "Message-ID".into()],
+            HashFormat::default()
+        );
+        let parsed = ParserChain::default()
+            .with_hash_rules(custom)
+            .parse_detailed(report.as_bytes())
+            .expect("custom");
+        assert_eq!(parsed.hash, "a1b2c3d4");
+    }
+
+    #[test]
+    fn parses_notification_eml_fixture() {
+        let raw = include_bytes!("../../../../tests/bounces/notification.eml");
+        let parsed =
+            ParserChain::default().parse_detailed(raw).expect("notification fixture should parse");
+
+        assert_eq!(parsed.hash, "4a22e0f0aa194d6833c619097380befa");
+        assert_eq!(parsed.status_code, "5.5.0");
+        assert_eq!(parsed.action.as_deref(), Some("failed"));
+        assert_eq!(parsed.recipient.as_deref(), Some("dummyuser08585@hotmail.com"));
+    }
+
+    #[test]
+    fn parses_inbox_returned_eml_fixture() {
+        let raw = include_bytes!("../../../../tests/bounces/inbox.returned.eml");
+        let parsed = ParserChain::default()
+            .parse_detailed(raw)
+            .expect("imap inbox-returned fixture should parse");
+
+        assert_eq!(parsed.hash, "44b54b9b9f739ca1a82e91aab5200e0e");
+        assert_eq!(parsed.status_code, "5.7.1");
+        assert_eq!(parsed.action.as_deref(), Some("failed"));
+        assert_eq!(parsed.recipient.as_deref(), Some("member09@gmail.com"));
+    }
+
+    #[test]
+    fn parses_outlook_bounce_eml_fixture() {
+        let raw = include_bytes!("../../../../tests/bounces/outlook.bounce.eml");
+        let parsed = ParserChain::default()
+            .parse_detailed(raw)
+            .expect("outlook bounce fixture should parse");
+
+        assert_eq!(parsed.hash, "c27335e4586d69311bb4668e9dc70bd5");
+        assert_eq!(parsed.status_code, "5.2.1");
+        assert_eq!(parsed.action.as_deref(), Some("failed"));
+        assert_eq!(parsed.recipient.as_deref(), Some("sx1300624@steanne-stlouis.fr"));
+    }
+
+    #[test]
+    fn decodes_utf8_recipients_and_encoded_words() {
+        assert_eq!(
+            decode_recipient("utf-8; j\\x{F6}rg@b\\x{FC}cher.example"),
+            "jörg@bücher.example"
+        );
+        assert_eq!(decode_recipient("utf-8; jörg@bücher.example"), "jörg@bücher.example");
+        assert_eq!(decode_recipient("utf-8; a\\x{zz}b@example.com"), "a\\x{zz}b@example.com");
+        assert_eq!(decode_recipient("rfc822; user\\x{F6}@example.com"), "user\\x{F6}@example.com");
+        assert_eq!(decode_encoded_words("=?ISO-8859-1?Q?Postfach_voll?="), "Postfach voll");
+        assert_eq!(decode_encoded_words("550 5.2.2 =?x"), "550 5.2.2 =?x");
+    }
+
+    #[test]
+    fn does_not_take_hash_from_non_original_sections() {
+        let raw = concat!(
+            "Message-ID: <bounce-message-id@example.net>\r\n",
+            "References: <orig-hash-should-not-be-read-from-top-level@claviron.app>\r\n",
+            "Content-Type: message/delivery-status\r\n",
+            "\r\n",
+            "Final-Recipient: rfc822; user@example.com\r\n",
+            "Action: failed\r\n",
+            "Status: 5.7.1\r\n",
+            "Diagnostic-Code: smtp; 550 5.7.1 blocked\r\n",
+        );
+
+        let err = ParserChain::default()
+            .parse_detailed(raw.as_bytes())
+            .expect_err("hash should not be accepted outside original sections");
+        assert_eq!(err, ParserError::MissingHash);
+    }
+
+    #[test]
+    fn parses_arf_complaint_with_hash_from_original_message() {
+        let raw = concat!(
+            "From: feedback@mail.example.net\r\n",
+            "Content-Type: multipart/report; report-type=feedback-report; boundary=\"arf\"\r\n",
+            "\r\n",
+            "--arf\r\n",
+            "Content-Type: text/plain\r\n",
+            "\r\n",
+            "This is an email abuse report.\r\n",
+            "--arf\r\n",
+            "Content-Type: message/feedback-report\r\n",
+            "\r\n",
+            "Feedback-Type: abuse\r\n",
+            "User-Agent: ExampleFBL/1.0\r\n",
+            "Version: 1\r\n",
+            "Original-Mail-From: <noreply@claviron.app>\r\n",
+            "Original-Rcpt-To: <user@example.net>\r\n",
+            "--arf\r\n",
+            "Content-Type: text/rfc822-headers\r\n",
+            "\r\n",
+            "Message-ID: <0f1e2d3c4b5a69788796a5b4c3d2e1f0@claviron.app>\r\n",
+            "Subject: hello\r\n",
+            "--arf--\r\n",
+        );
+
+        let parsed =
+            ParserChain::default().parse_detailed(raw.as_bytes()).expect("ARF report should parse");
+
+        assert_eq!(parsed.hash, "0f1e2d3c4b5a69788796a5b4c3d2e1f0");
+        assert_eq!(parsed.status_code, "5.7.1");
+        assert_eq!(parsed.action.as_deref(), Some("complaint"));
+        assert_eq!(parsed.recipient.as_deref(), Some("user@example.net"));
+        assert_eq!(parsed.sender.as_deref(), Some("noreply@claviron.app"));
+    }
+
+    #[test]
+    fn parses_exchange_ndr_body_and_honors_chain_order() {
+        let raw = concat!(
+            "From: postmaster@outlook.example\r\n",
+            "Subject: Undeliverable: hello\r\n",
+            "Content-Type: multipart/mixed; boundary=\"ndr\"\r\n",
+            "\r\n",
+            "--ndr\r\n",
+            "Content-Type: text/plain\r\n",
+            "\r\n",
+            "Delivery has failed to these recipients or groups:\r\n",
+            "\r\n",
+            "Diagnostic information for administrators:\r\n",
+            "\r\n",
+            "Generating server: MBX01.outlook.example\r\n",
+            "\r\n",
+            "missing@example.org\r\n",
+            "#550 5.1.10 RESOLVER.ADR.RecipientNotFound; Recipient not found ##\r\n",
+            "--ndr\r\n",
+            "Content-Type: text/rfc822-headers\r\n",
+            "\r\n",
+            "X-Message-Id: <aa11bb22cc33dd44@claviron.app>\r\n",
+            "--ndr--\r\n",
+        );
+
+        let parsed = ParserChain::default()
+            .parse_detailed(raw.as_bytes())
+            .expect("exchange NDR should parse");
+        assert_eq!(parsed.hash, "aa11bb22cc33dd44");
+        assert_eq!(parsed.status_code, "5.1.10");
+        assert_eq!(parsed.action.as_deref(), Some("failed"));
+        assert_eq!(parsed.recipient.as_deref(), Some("missing@example.org"));
+
+        let err = ParserChain::from_names(&["arf"])
+            .unwrap()
+            .parse_detailed(raw.as_bytes())
+            .expect_err("arf-only chain should not recognize an NDR");
+        assert_eq!(err, ParserError::NotDeliveryReport);
+        assert!(ParserChain::from_names(&["dsn", "qmail"]).is_err());
+    }
+
+    #[test]
+    fn classifies_out_of_office_reply_as_autoreply() {
+        let raw = concat!(
+            "From: Jane Doe <jane@example.org>\r\n",
+            "To: noreply@claviron.app\r\n",
+            "Subject: Automatic reply: Your order\r\n",
+            "Message-ID: <autoreply-own-id@example.org>\r\n",
+            "In-Reply-To: <5d41402abc4b2a76b9719d911017c592@claviron.app>\r\n",
+            "Auto-Submitted: auto-replied\r\n",
+            "\r\n",
+            "I am out of the office. Mail delivery to my assistant is undelivered until Monday.\r\n",
+        );
+
+        let parsed = ParserChain::default()
+            .parse_detailed(raw.as_bytes())
+            .expect("auto-reply should be classified");
+
+        assert_eq!(parsed.kind, ReportKind::Autoreply);
+        assert_eq!(parsed.hash, "5d41402abc4b2a76b9719d911017c592");
+        assert_eq!(parsed.status_code, "2.0.0");
+        assert_eq!(parsed.action.as_deref(), Some("autoreply"));
+        assert_eq!(parsed.recipient.as_deref(), Some("jane@example.org"));
+    }
+
+    #[test]
+    fn parses_gzip_wrapped_delivery_status_and_original_attachments() {
+        use std::io::Write;
+
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+
+        fn gzip_base64(text: &str) -> String {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(text.as_bytes()).expect("gzip write");
+            let encoded = BASE64.encode(encoder.finish().expect("gzip finish"));
+            encoded.as_bytes().chunks(76).map(|c| String::from_utf8_lossy(c) + "\r\n").collect()
+        }
+
+        let report = gzip_base64(concat!(
+            "Reporting-MTA: dns; mx.example.net\r\n",
+            "\r\n",
+            "Final-Recipient: rfc822; user@example.com\r\n",
+            "Action: failed\r\n",
+            "Status: 5.1.1\r\n",
+            "Diagnostic-Code: smtp; 550 5.1.1 user unknown\r\n",
+        ));
+        let original = gzip_base64(concat!(
+            "From: noreply@claviron.app\r\n",
+            "To: user@example.com\r\n",
+            "Message-ID: <5d41402abc4b2a76b9719d911017c592@claviron.app>\r\n",
+            "Subject: Your order\r\n",
+            "\r\n",
+            "Hello\r\n",
+        ));
+        let raw = format!(
+            concat!(
+                "From: Mail Delivery System <mailer-daemon@example.net>\r\n",
+                "Subject: Undelivered Mail\r\n",
+                "Content-Type: multipart/mixed; boundary=\"GZ\"\r\n",
+                "\r\n",
+                "--GZ\r\n",
+                "Content-Type: text/plain\r\n",
+                "\r\n",
+                "The attached report could not be delivered.\r\n",
+                "--GZ\r\n",
+                "Content-Type: application/gzip; name=\"report.txt.gz\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "Content-Disposition: attachment; filename=\"report.txt.gz\"\r\n",
+                "\r\n",
+                "{}",
+                "--GZ\r\n",
+                "Content-Type: application/octet-stream; name=\"original.eml.gz\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "Content-Disposition: attachment; filename=\"original.eml.gz\"\r\n",
+                "\r\n",
+                "{}",
+                "--GZ--\r\n",
+            ),
+            report, original
+        );
+
+        let parsed = ParserChain::default()
+            .parse_detailed(raw.as_bytes())
+            .expect("gzip wrapped report should parse");
+
+        assert_eq!(parsed.hash, "5d41402abc4b2a76b9719d911017c592");
+        assert_eq!(parsed.status_code, "5.1.1");
+        assert_eq!(parsed.action.as_deref(), Some("failed"));
+        assert_eq!(parsed.recipient.as_deref(), Some("user@example.com"));
+    }
+
+    #[test]
+    fn budget_fails_messages_with_too_many_parts_or_scan_time() {
+        let mut raw = String::from(
+            "Content-Type: multipart/report; report-type=delivery-status; boundary=\"b\"\r\n\r\n"
+        );
+        for idx in 0..20 {
+            raw.push_str(&format!("--b\r\nContent-Type: text/plain\r\n\r\npart {idx}\r\n"));
+        }
+        raw.push_str("--b\r\nContent-Type: message/delivery-status\r\n\r\nStatus: 5.1.1\r\n");
+        raw.push_str("Action: failed\r\n\r\n--b\r\nContent-Type: text/rfc822-headers\r\n\r\n");
+        raw.push_str("Message-ID: <0123456789abcdef0123456789abcdef@example.com>\r\n\r\n--b--\r\n");
+
+        let budget = ParseBudget { max_candidates: 8, ..ParseBudget::default() };
+        let tight = ParserChain::default().with_budget(budget);
+        assert_eq!(tight.parse_detailed(raw.as_bytes()).unwrap_err(), ParserError::BudgetExceeded);
+        assert_eq!(ParserError::BudgetExceeded.code(), "BUDGET_EXCEEDED");
+        let parsed = ParserChain::default().parse_detailed(raw.as_bytes()).expect("default budget");
+        assert_eq!(parsed.status_code, "5.1.1");
+
+        let budget = ParseBudget {
+            max_scan_time: std::time::Duration::from_nanos(1),
+            ..ParseBudget::default()
+        };
+        let slow = ParserChain::default().with_budget(budget);
+        assert_eq!(slow.parse_detailed(raw.as_bytes()).unwrap_err(), ParserError::BudgetExceeded);
+
+        // The clock runs from before the MIME parse and is checked per part.
+        let message = message_parser().parse(raw.as_bytes()).expect("multipart message");
+        let budget = ParseBudget {
+            max_scan_time: std::time::Duration::from_secs(1),
+            ..ParseBudget::default()
+        };
+        let started = Instant::now() - std::time::Duration::from_secs(2);
+        assert_eq!(
+            collect_attachment_text_candidates(&message, &budget, started).err(),
+            Some(ParserError::BudgetExceeded)
+        );
+        assert!(collect_attachment_text_candidates(&message, &budget, Instant::now()).is_ok());
+    }
+
+    #[test]
+    fn budget_caps_bytes_scanned_per_part() {
+        let text = Cow::Borrowed("Status: 5.1.1 \u{fc}nknown");
+        assert_eq!(truncate_text(text.clone(), 16), "Status: 5.1.1 \u{fc}");
+        assert_eq!(truncate_text(text.clone(), 15), "Status: 5.1.1 ");
+        assert_eq!(truncate_text(Cow::Owned("abc".to_string()), 8), "abc");
+
+        let raw = format!(
+            "Content-Type: multipart/report; report-type=delivery-status; boundary=\"b\"\r\n\r\n--b\r\nContent-Type: message/delivery-status\r\n\r\nX-Padding: {}\r\nAction: failed\r\nStatus: 5.1.1\r\n\r\n--b\r\nContent-Type: text/rfc822-headers\r\n\r\nMessage-ID: <0123456789abcdef0123456789abcdef@example.com>\r\n\r\n--b--\r\n",
+            "x".repeat(4096)
+        );
+        let budget = ParseBudget { max_candidate_bytes: 1024, ..ParseBudget::default() };
+        let capped = ParserChain::default().with_budget(budget);
+        // The status line sits past the first 1024 bytes and is never seen.
+        assert!(capped.parse_detailed(raw.as_bytes()).is_err());
+        ParserChain::default().parse_detailed(raw.as_bytes()).expect("uncapped");
+    }
+
+    /// Stable text form of a parse result for the corpus snapshots.
+    fn snapshot(result: &std::result::Result<ParsedBounce, ParserError>) -> String {
+        let parsed = match result {
+            Ok(parsed) => parsed,
+            Err(err) => return format!("error: {}\n", err.code())
+        };
+        let field = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+        format!(
+            "kind: {}\nhash: {}\nstatus_code: {}\naction: {}\nsender: {}\nrecipient: {}\ndescription: {}\nreason: {}\nscan_labels: {}\n",
+            parsed.kind.as_str(),
+            parsed.hash,
+            parsed.status_code,
+            field(&parsed.action),
+            field(&parsed.sender),
+            field(&parsed.recipient),
+            field(&parsed.description),
+            parsed.reason().unwrap_or("-"),
+            parsed.scan_labels.join(","),
+        )
+    }
+
+    /// Every `tests/bounces/corpus/*.eml` must parse, or fail, as its `.snap`
+    /// file records. `BOUNCER_BLESS=1` rewrites the snapshots.
+    #[test]
+    fn corpus_matches_snapshots() {
+        let dir =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tests/bounces/corpus");
+        let bless = std::env::var_os("BOUNCER_BLESS").is_some();
+        let mut fixtures: Vec<_> = std::fs::read_dir(&dir)
+            .expect("read corpus dir")
+            .map(|entry| entry.expect("corpus entry").path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "eml"))
+            .collect();
+        fixtures.sort();
+        assert!(!fixtures.is_empty(), "no fixtures in {}", dir.display());
+
+        let mut mismatches = Vec::new();
+        for fixture in fixtures {
+            let raw = std::fs::read(&fixture).expect("read fixture");
+            let actual = snapshot(&ParserChain::default().parse_detailed(&raw));
+            let snap = fixture.with_extension("snap");
+            if bless {
+                std::fs::write(&snap, &actual).expect("write snapshot");
+                continue;
+            }
+            let expected = std::fs::read_to_string(&snap).unwrap_or_else(|_| {
+                panic!("missing snapshot {}; rerun with BOUNCER_BLESS=1", snap.display())
+            });
+            if expected != actual {
+                mismatches.push(format!(
+                    "{}\n--- expected\n{expected}--- actual\n{actual}",
+                    fixture.display()
+                ));
+            }
+        }
+        assert!(mismatches.is_empty(), "corpus snapshots differ:\n{}", mismatches.join("\n"));
+    }
+}
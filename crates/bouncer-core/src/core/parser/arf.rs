@@ -0,0 +1,85 @@
+//! RFC 5965 abuse feedback reports (`report-type=feedback-report`).
+//!
+//! ARF carries no DSN status, so complaints are recorded with action
+//! `complaint` and status `5.7.1`, which the database maps to suspended.
+
+use super::{
+    BounceInput, BounceParser, CandidateKind, ParsedFields, constrain_hash_source, extract_mailbox,
+    header_value, merge_missing, parse_fields_from_text
+};
+
+pub const NAME: &str = "arf";
+
+const COMPLAINT_STATUS_CODE: &str = "5.7.1";
+const COMPLAINT_ACTION: &str = "complaint";
+
+pub struct ArfParser;
+
+impl BounceParser for ArfParser {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn detect(
+        &self,
+        input: &BounceInput<'_>
+    ) -> bool {
+        input.candidates.iter().any(|candidate| candidate.kind == CandidateKind::FeedbackReport)
+    }
+
+    fn parse(
+        &self,
+        input: &BounceInput<'_>,
+        found: &ParsedFields
+    ) -> ParsedFields {
+        let mut merged = found.clone();
+
+        for candidate in &input.candidates {
+            if candidate.kind == CandidateKind::FeedbackReport {
+                merge_missing(&mut merged, parse_feedback_fields(&candidate.text));
+            } else {
+                // The attached original message is the only trusted hash source.
+                let mut parsed =
+                    parse_fields_from_text(&candidate.text, &candidate.scan_label, input.hash);
+                constrain_hash_source(&mut parsed, candidate.kind);
+                merge_missing(
+                    &mut merged,
+                    ParsedFields {
+                        hash: parsed.hash,
+                        hash_priority: parsed.hash_priority,
+                        hash_scan: parsed.hash_scan,
+                        ..ParsedFields::default()
+                    }
+                );
+            }
+        }
+
+        merged
+    }
+}
+
+fn parse_feedback_fields(text: &str) -> ParsedFields {
+    let mut parsed = ParsedFields::default();
+
+    for line in text.lines() {
+        if let Some(value) = header_value(line, "Feedback-Type") {
+            let feedback_type = value.split_whitespace().next().unwrap_or("").to_ascii_lowercase();
+            // `not-spam` and unknown types are not complaints.
+            if matches!(feedback_type.as_str(), "abuse" | "fraud" | "virus" | "other") {
+                parsed.status_code = Some(COMPLAINT_STATUS_CODE.to_string());
+                parsed.action = Some(COMPLAINT_ACTION.to_string());
+                parsed.description = Some(format!("feedback-type={feedback_type}"));
+            }
+        } else if parsed.recipient.is_none()
+            && let Some(value) = header_value(line, "Original-Rcpt-To")
+        {
+            parsed.recipient = extract_mailbox(value);
+        } else if parsed.sender.is_none()
+            && let Some(value) = header_value(line, "Original-Mail-From")
+        {
+            parsed.sender = extract_mailbox(value);
+        }
+    }
+
+    parsed
+}
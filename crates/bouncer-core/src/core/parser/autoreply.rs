@@ -0,0 +1,110 @@
+//! Out-of-office / auto-responder classification.
+//!
+//! Runs ahead of the bounce chain. A message is an auto-reply when its
+//! top-level headers carry `Auto-Submitted: auto-replied`, an `X-Autoreply`
+//! style header, `Precedence: auto_reply`, or a well-known auto-reply subject,
+//! and it has no DSN/ARF part (DSNs are `auto-replied` too).
+
+use super::{
+    BounceInput, CandidateKind, ParsedBounce, ParserError, ReportKind,
+    extract_hash_from_message_id_like_header, extract_mailbox, header_value
+};
+
+/// Auto-replies prove the mailbox exists; record them as a 2.x outcome.
+const AUTOREPLY_STATUS_CODE: &str = "2.0.0";
+
+const AUTOREPLY_HEADERS: [&str; 3] = ["X-Autoreply", "X-Autorespond", "X-Autoresponse"];
+
+const SUBJECT_PREFIXES: [&str; 9] = [
+    "out of office",
+    "automatic reply",
+    "auto reply",
+    "auto-reply",
+    "autoreply",
+    "auto:",
+    "abwesenheitsnotiz",
+    "réponse automatique",
+    "otomatik yanıt"
+];
+
+/// Returns `None` for anything that is not an auto-reply.
+pub fn classify(input: &BounceInput<'_>) -> Option<Result<ParsedBounce, ParserError>> {
+    if input.candidates.iter().any(|candidate| {
+        matches!(candidate.kind, CandidateKind::DeliveryStatus | CandidateKind::FeedbackReport)
+    }) {
+        return None;
+    }
+
+    let headers = top_level_headers(input.full_text());
+    let header = |name: &str| headers.iter().find_map(|line| header_value(line, name));
+
+    if header("Content-Type")
+        .is_some_and(|value| value.to_ascii_lowercase().contains("report-type="))
+    {
+        return None;
+    }
+    if header("From").and_then(extract_mailbox).is_some_and(|from| is_system_sender(&from)) {
+        return None;
+    }
+
+    let subject = header("Subject").unwrap_or_default();
+    let auto_submitted = header("Auto-Submitted")
+        .is_some_and(|value| value.to_ascii_lowercase().starts_with("auto-replied"));
+    let autoreply_header = AUTOREPLY_HEADERS.iter().any(|name| header(name).is_some());
+    let precedence =
+        header("Precedence").is_some_and(|value| value.trim().eq_ignore_ascii_case("auto_reply"));
+    let subject_match = {
+        let lower = subject.to_lowercase();
+        SUBJECT_PREFIXES.iter().any(|prefix| lower.starts_with(prefix))
+    };
+
+    if !(auto_submitted || autoreply_header || precedence || subject_match) {
+        return None;
+    }
+
+    // The reply references our original Message-ID; its own Message-ID is
+    // never our hash.
+    let extract = |value| extract_hash_from_message_id_like_header(value, &input.hash.format);
+    let hash =
+        header("In-Reply-To").and_then(extract).or_else(|| header("References").and_then(extract));
+    let Some(hash) = hash else {
+        return Some(Err(ParserError::MissingHash));
+    };
+
+    Some(Ok(ParsedBounce {
+        kind: ReportKind::Autoreply,
+        hash,
+        status_code: AUTOREPLY_STATUS_CODE.to_string(),
+        action: Some(ReportKind::Autoreply.as_str().to_string()),
+        sender: None,
+        recipient: header("From").and_then(extract_mailbox),
+        description: (!subject.is_empty()).then(|| subject.to_string()),
+        scan_labels: vec![ReportKind::Autoreply.as_str().to_string()],
+        tenant: None
+    }))
+}
+
+/// Unfolded header lines of the top-level message (up to the first blank line).
+fn top_level_headers(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in text.lines() {
+        let line = raw.trim_end_matches('\r');
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some(last) = lines.last_mut() {
+                last.push(' ');
+                last.push_str(line.trim_start());
+            }
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    lines
+}
+
+fn is_system_sender(address: &str) -> bool {
+    let local = address.split('@').next().unwrap_or_default().to_ascii_lowercase();
+    matches!(local.as_str(), "mailer-daemon" | "postmaster")
+}
@@ -0,0 +1,71 @@
+//! RFC 3464 delivery status notifications (`report-type=delivery-status`).
+//!
+//! Reads status metadata from `message/delivery-status` parts and the message
+//! hash from the attached original headers/message only.
+
+use tracing::debug;
+
+use super::{
+    BounceInput, BounceParser, CandidateKind, ParsedFields, looks_like_delivery_report,
+    merge_missing, parse_fields_from_text
+};
+
+pub const NAME: &str = "dsn";
+
+pub struct DsnParser;
+
+impl BounceParser for DsnParser {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn detect(
+        &self,
+        input: &BounceInput<'_>
+    ) -> bool {
+        input.candidates.iter().any(|candidate| {
+            candidate.kind == CandidateKind::DeliveryStatus
+                || looks_like_delivery_report(&candidate.text)
+        })
+    }
+
+    fn parse(
+        &self,
+        input: &BounceInput<'_>,
+        found: &ParsedFields
+    ) -> ParsedFields {
+        let mut merged = found.clone();
+
+        for candidate in &input.candidates {
+            let mut parsed =
+                parse_fields_from_text(&candidate.text, &candidate.scan_label, input.hash);
+            match candidate.kind {
+                CandidateKind::DeliveryStatus => {
+                    // DSN part should provide status metadata, not message hash.
+                    parsed.clear_hash();
+                }
+                CandidateKind::OriginalHeaders | CandidateKind::OriginalMessage => {
+                    // Original headers/message should provide message hash only.
+                    parsed.status_code = None;
+                    parsed.status_scan = None;
+                    parsed.action = None;
+                    parsed.recipient = None;
+                    parsed.description = None;
+                }
+                CandidateKind::FeedbackReport | CandidateKind::TextBody | CandidateKind::Other => {
+                    continue;
+                }
+            }
+            merge_missing(&mut merged, parsed);
+            if merged.has_required() {
+                debug!(
+                    "bounce parser optimization: required fields found in typed attachment scan, skipping fallback scan: scan={}",
+                    candidate.scan_label
+                );
+                break;
+            }
+        }
+
+        merged
+    }
+}
@@ -0,0 +1,93 @@
+//! Exchange/Outlook non-delivery reports.
+//!
+//! Exchange NDRs often ship without a machine-readable DSN part; the status
+//! lives in the human-readable body as `#550 5.1.1 RESOLVER.ADR...##` under
+//! the failed recipient, or as `Remote Server returned '550 5.1.1 ...'`.
+
+use super::{
+    BounceInput, BounceParser, ParsedFields, extract_mailbox, find_status_code_in_text,
+    merge_missing
+};
+
+pub const NAME: &str = "exchange";
+
+const MARKERS: [&str; 4] = [
+    "delivery has failed to these recipients",
+    "remote server returned '",
+    "diagnostic information for administrators",
+    "your message couldn't be delivered"
+];
+
+pub struct ExchangeParser;
+
+impl BounceParser for ExchangeParser {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn detect(
+        &self,
+        input: &BounceInput<'_>
+    ) -> bool {
+        input.candidates.iter().any(|candidate| has_marker(&candidate.text))
+            || has_marker(input.full_text())
+    }
+
+    fn parse(
+        &self,
+        input: &BounceInput<'_>,
+        found: &ParsedFields
+    ) -> ParsedFields {
+        let mut merged = found.clone();
+
+        let texts = input
+            .candidates
+            .iter()
+            .map(|candidate| candidate.text.as_ref())
+            .chain(std::iter::once(input.full_text()));
+        for text in texts.filter(|text| has_marker(text)) {
+            merge_missing(&mut merged, parse_diagnostic_text(text));
+            if merged.status_code.is_some() {
+                break;
+            }
+        }
+
+        merged
+    }
+}
+
+fn has_marker(text: &str) -> bool {
+    let lower = text.to_ascii_lowercase();
+    MARKERS.iter().any(|marker| lower.contains(marker))
+}
+
+fn parse_diagnostic_text(text: &str) -> ParsedFields {
+    let mut parsed = ParsedFields::default();
+    let mut previous: Option<&str> = None;
+
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let diagnostic =
+            line.strip_prefix('#').map(|rest| rest.trim_end_matches('#')).or_else(|| {
+                let lower = line.to_ascii_lowercase();
+                lower.find("remote server returned '").map(|pos| {
+                    line[pos + "remote server returned '".len()..].trim_end_matches('\'')
+                })
+            });
+
+        if let Some(diagnostic) = diagnostic
+            && let Some(code) = find_status_code_in_text(diagnostic)
+        {
+            if code.starts_with("5.") {
+                parsed.action = Some("failed".to_string());
+            }
+            parsed.status_code = Some(code);
+            parsed.description = Some(diagnostic.trim().to_string());
+            parsed.recipient = previous.and_then(extract_mailbox);
+            break;
+        }
+
+        previous = Some(line);
+    }
+
+    parsed
+}
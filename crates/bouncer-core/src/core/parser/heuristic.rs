@@ -0,0 +1,67 @@
+//! Last-resort text scan for bounces without a usable DSN part.
+//!
+//! Scans every candidate part, then the whole raw message, and finally
+//! searches for a bare `X.Y.Z` enhanced status code anywhere in the text.
+
+use tracing::debug;
+
+use super::{
+    BounceInput, BounceParser, ParsedFields, constrain_hash_source, find_status_code_in_text,
+    looks_like_delivery_report, merge_missing, parse_fields_from_text
+};
+
+pub const NAME: &str = "heuristic_text";
+
+pub struct HeuristicTextParser;
+
+impl BounceParser for HeuristicTextParser {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn detect(
+        &self,
+        input: &BounceInput<'_>
+    ) -> bool {
+        looks_like_delivery_report(input.full_text())
+    }
+
+    fn parse(
+        &self,
+        input: &BounceInput<'_>,
+        found: &ParsedFields
+    ) -> ParsedFields {
+        let mut merged = found.clone();
+
+        for candidate in &input.candidates {
+            let mut parsed =
+                parse_fields_from_text(&candidate.text, &candidate.scan_label, input.hash);
+            constrain_hash_source(&mut parsed, candidate.kind);
+            merge_missing(&mut merged, parsed);
+            if merged.has_required() {
+                debug!(
+                    "bounce parser optimization: required fields found in fallback attachment scan, skipping full_message scan: scan={}",
+                    candidate.scan_label
+                );
+                return merged;
+            }
+        }
+
+        if merged.status_code.is_none() {
+            let mut parsed = parse_fields_from_text(input.full_text(), "full_message", input.hash);
+            // Never trust the top-level bounce Message-ID as our delivery hash.
+            parsed.clear_hash();
+            merge_missing(&mut merged, parsed);
+        }
+
+        if merged.status_code.is_none() {
+            merged.status_code = input
+                .candidates
+                .iter()
+                .find_map(|candidate| find_status_code_in_text(&candidate.text))
+                .or_else(|| find_status_code_in_text(input.full_text()));
+        }
+
+        merged
+    }
+}
@@ -0,0 +1,266 @@
+//! Short human-readable bounce reasons.
+//!
+//! Turns the diagnostic text into a reason such as `mailbox full` that
+//! customer-facing UIs can show as-is. Provider phrasings are matched first,
+//! in several languages, because they are more specific than the status code
+//! (plenty of servers answer `5.0.0` or `5.5.0` for an unknown user); the
+//! enhanced status code is the fallback.
+
+use super::{ParsedBounce, ReportKind};
+
+/// Action the ARF stage stores for abuse reports.
+const COMPLAINT_ACTION: &str = "complaint";
+
+struct Rule {
+    reason: &'static str,
+    /// Enhanced status codes that imply the reason without any diagnostic.
+    status_codes: &'static [&'static str],
+    /// Lowercase substrings of the diagnostic.
+    phrases: &'static [&'static str]
+}
+
+/// Checked in order; the first rule with a matching phrase wins, then the
+/// first rule with a matching status code.
+const RULES: [Rule; 8] = [
+    Rule {
+        reason: "mailbox full",
+        status_codes: &["4.2.2", "5.2.2"],
+        phrases: &[
+            "mailbox full",
+            "mailbox is full",
+            "quota exceeded",
+            "over quota",
+            "overquota",
+            "exceeded storage",
+            "postfach voll",
+            "postfach ist voll",
+            "speicherplatz",
+            "boîte pleine",
+            "boite pleine",
+            "boîte aux lettres pleine",
+            "boite aux lettres pleine",
+            "buzón lleno",
+            "buzon lleno",
+            "cuota excedida",
+            "casella piena",
+            "caixa postal cheia",
+            "caixa de correio cheia",
+            "posta kutusu dolu",
+            "kota aşıldı",
+            "ящик переполнен"
+        ]
+    },
+    Rule {
+        reason: "user unknown",
+        status_codes: &["5.1.1", "5.1.6"],
+        phrases: &[
+            "user unknown",
+            "unknown user",
+            "no such user",
+            "no such mailbox",
+            "recipient not found",
+            "invalid recipient",
+            "recipient address rejected: user",
+            "mailbox not found",
+            "address does not exist",
+            "account does not exist",
+            "unbekannter empfänger",
+            "unbekannter benutzer",
+            "empfänger unbekannt",
+            "existiert nicht",
+            "utilisateur inconnu",
+            "destinataire inconnu",
+            "adresse inexistante",
+            "usuario desconocido",
+            "destinatario desconocido",
+            "utente sconosciuto",
+            "destinatario inesistente",
+            "usuário desconhecido",
+            "kullanıcı bulunamadı",
+            "böyle bir kullanıcı yok",
+            "нет такого пользователя",
+            "пользователь не найден"
+        ]
+    },
+    Rule {
+        reason: "mailbox disabled",
+        status_codes: &["5.2.1"],
+        phrases: &[
+            "mailbox disabled",
+            "mailbox is disabled",
+            "account disabled",
+            "account has been disabled",
+            "account is inactive",
+            "postfach deaktiviert",
+            "konto deaktiviert",
+            "compte désactivé",
+            "cuenta desactivada",
+            "account disattivato",
+            "conta desativada",
+            "hesap devre dışı"
+        ]
+    },
+    Rule {
+        reason: "domain not found",
+        status_codes: &["5.1.2", "5.4.4"],
+        phrases: &[
+            "domain not found",
+            "domain does not exist",
+            "host not found",
+            "no mx record",
+            "unrouteable address",
+            "domain existiert nicht",
+            "domaine inexistant",
+            "dominio inexistente",
+            "dominio no existe",
+            "alan adı bulunamadı"
+        ]
+    },
+    Rule {
+        reason: "message too large",
+        status_codes: &["5.2.3", "5.3.4"],
+        phrases: &[
+            "message too large",
+            "message size exceeds",
+            "exceeds size limit",
+            "nachricht zu groß",
+            "message trop volumineux",
+            "mensaje demasiado grande",
+            "messaggio troppo grande",
+            "ileti çok büyük"
+        ]
+    },
+    Rule {
+        reason: "rejected as spam",
+        status_codes: &["5.7.28"],
+        phrases: &[
+            "spam",
+            "blacklist",
+            "blocklist",
+            "listed at",
+            "als spam",
+            "comme spam",
+            "como spam",
+            "come spam",
+            "istenmeyen"
+        ]
+    },
+    Rule {
+        reason: "rate limited",
+        status_codes: &["4.7.28", "4.7.0"],
+        phrases: &[
+            "rate limit",
+            "too many messages",
+            "too many connections",
+            "throttl",
+            "zu viele nachrichten",
+            "trop de messages",
+            "demasiados mensajes"
+        ]
+    },
+    Rule {
+        reason: "blocked by policy",
+        status_codes: &["5.7.0", "5.7.1", "5.7.26"],
+        phrases: &[
+            "relay denied",
+            "relaying denied",
+            "access denied",
+            "not authorized",
+            "policy",
+            "zugriff verweigert",
+            "accès refusé",
+            "acceso denegado",
+            "erişim reddedildi"
+        ]
+    }
+];
+
+/// Returns the reason for `parsed`, or `None` when neither the diagnostic nor
+/// the status code says anything a customer could act on.
+pub fn classify(parsed: &ParsedBounce) -> Option<&'static str> {
+    if parsed.kind == ReportKind::Autoreply {
+        return Some("auto-reply");
+    }
+    if parsed.action.as_deref().is_some_and(|action| action.eq_ignore_ascii_case(COMPLAINT_ACTION))
+    {
+        return Some("spam complaint");
+    }
+
+    if let Some(description) = parsed.description.as_deref() {
+        let description = description.to_lowercase();
+        if let Some(rule) =
+            RULES.iter().find(|rule| rule.phrases.iter().any(|phrase| description.contains(phrase)))
+        {
+            return Some(rule.reason);
+        }
+    }
+
+    let status_code = parsed.status_code.trim();
+    RULES.iter().find(|rule| rule.status_codes.contains(&status_code)).map(|rule| rule.reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::classify;
+    use crate::core::parser::{ParsedBounce, ReportKind};
+
+    fn bounce(
+        status_code: &str,
+        description: Option<&str>
+    ) -> ParsedBounce {
+        ParsedBounce {
+            kind: ReportKind::Bounce,
+            hash: "hash".to_string(),
+            status_code: status_code.to_string(),
+            action: Some("failed".to_string()),
+            sender: None,
+            recipient: None,
+            description: description.map(str::to_string),
+            scan_labels: Vec::new(),
+            tenant: None
+        }
+    }
+
+    #[test]
+    fn reads_reason_from_localized_diagnostics() {
+        let cases = [
+            (
+                "5.0.0",
+                Some("550 5.0.0 <a@example.com>: User unknown in virtual mailbox table"),
+                "user unknown"
+            ),
+            (
+                "5.5.0",
+                Some("550 Requested action not taken: mailbox unavailable, Postfach voll"),
+                "mailbox full"
+            ),
+            ("4.0.0", Some("452 4.2.2 La boîte aux lettres pleine"), "mailbox full"),
+            ("5.0.0", Some("550 El usuario desconocido"), "user unknown"),
+            ("5.0.0", Some("550 Alıcı posta kutusu dolu"), "mailbox full"),
+            (
+                "5.7.1",
+                Some("554 5.7.1 Message rejected as spam by Content Filtering"),
+                "rejected as spam"
+            ),
+            ("5.7.1", Some("550 5.7.1 Relaying denied"), "blocked by policy")
+        ];
+        for (status_code, description, reason) in cases {
+            assert_eq!(
+                classify(&bounce(status_code, description)),
+                Some(reason),
+                "{description:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn falls_back_to_status_code() {
+        assert_eq!(classify(&bounce("5.1.1", None)), Some("user unknown"));
+        assert_eq!(classify(&bounce("5.2.2", Some("552 sorry"))), Some("mailbox full"));
+        assert_eq!(classify(&bounce("5.0.0", Some("550 rejected"))), None);
+
+        let mut complaint = bounce("5.7.1", None);
+        complaint.action = Some("complaint".to_string());
+        assert_eq!(classify(&complaint), Some("spam complaint"));
+    }
+}
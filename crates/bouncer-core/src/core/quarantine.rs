@@ -0,0 +1,133 @@
+use anyhow::{Context, Result};
+use bouncer_helpers::coded_warn;
+use bouncer_helpers::error_code::ErrorCode;
+use bouncer_proto::event::{EventError, decode_delivery_event};
+use bouncer_proto::query::QuarantineReplay;
+use tracing::{info, warn};
+
+use crate::app::AppState;
+
+/// Stores an `observer_event` body that failed to decode, or that carries a
+/// `schema_version` newer than this build reads, so the frame can be ACKed;
+/// publishers would otherwise resend the same body forever.
+pub async fn quarantine_observer_event(
+    state: &AppState,
+    source: &str,
+    body: &[u8],
+    error: &EventError
+) -> Result<()> {
+    let note =
+        format!("source={source}\nreceived_at_unix={}\nerror={error}\n", state.clock.unix_secs());
+    let path = state
+        .spool
+        .quarantine_event(body, &note)
+        .await
+        .context("failed to quarantine observer event")?;
+    coded_warn!(
+        ErrorCode::ObserverEventQuarantined,
+        "observer event body undecodable, quarantined: source={}, bytes={}, path={}, error={}",
+        source,
+        body.len(),
+        path.display(),
+        error
+    );
+    Ok(())
+}
+
+/// Decodes and applies every quarantined event again, e.g. after a deploy
+/// that taught the server a new event shape. Bodies that still fail stay in
+/// `quarantine/` for the next replay.
+pub async fn replay_quarantined_events(state: &AppState) -> Result<QuarantineReplay> {
+    let mut summary = QuarantineReplay::default();
+    for path in state.spool.quarantined_events().await? {
+        let body = tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let event = match decode_delivery_event(&body) {
+            Ok(event) => event,
+            Err(_) => {
+                summary.still_invalid += 1;
+                continue;
+            }
+        };
+
+        if state.ignore_delivered_events && event.is_delivered() {
+            state.spool.release_quarantined(&path).await?;
+            summary.replayed += 1;
+            continue;
+        }
+
+        match state.db.apply_observer_event(&event).await {
+            Ok(_) => {
+                state.spool.release_quarantined(&path).await?;
+                summary.replayed += 1;
+            }
+            Err(err) => {
+                summary.apply_failed += 1;
+                warn!(
+                    "quarantined observer event replay failed: path={}, hash={}, error={err:#}",
+                    path.display(),
+                    event.hash
+                );
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Replays the quarantine once at startup, so a fixed build picks up events
+/// earlier builds could not decode.
+pub async fn replay_quarantine_on_start(state: AppState) {
+    match replay_quarantined_events(&state).await {
+        Ok(summary) if summary.replayed + summary.still_invalid + summary.apply_failed == 0 => {}
+        Ok(summary) => info!(
+            "quarantine replayed: replayed={}, still_invalid={}, apply_failed={}",
+            summary.replayed, summary.still_invalid, summary.apply_failed
+        ),
+        Err(err) => warn!("quarantine replay failed: error={err:#}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{quarantine_observer_event, replay_quarantined_events};
+    use crate::app::AppState;
+
+    #[tokio::test]
+    async fn quarantines_undecodable_event_and_replays_once_it_decodes() {
+        let root = std::env::temp_dir().join(format!("bouncer-quarantine-{}", Uuid::now_v7()));
+        let state = AppState::for_tests(&root).await;
+
+        let body = br#"{"hash":"a1b2","status":5}"#;
+        let error = super::decode_delivery_event(body).unwrap_err();
+        quarantine_observer_event(&state, "observer-1", body, &error).await.unwrap();
+
+        let quarantined = state.spool.quarantined_events().await.unwrap();
+        assert_eq!(quarantined.len(), 1);
+        let note = tokio::fs::read_to_string(quarantined[0].with_extension("error")).await.unwrap();
+        assert!(note.starts_with("source=observer-1\n"), "{note}");
+
+        let summary = replay_quarantined_events(&state).await.unwrap();
+        assert_eq!((summary.replayed, summary.still_invalid, summary.apply_failed), (0, 1, 0));
+
+        // A fixed build decodes the same bytes: simulate by rewriting the body.
+        let fixed = br#"{"source":"observer-1","hash":"a1b2","queue_id":"Q1","recipient":"user@example.com","status_code":"5.1.1","action":"failed","diagnostic":"550 5.1.1 user unknown","smtp_status":"bounced","observed_at_unix":1}"#;
+        tokio::fs::write(&quarantined[0], fixed).await.unwrap();
+
+        let summary = replay_quarantined_events(&state).await.unwrap();
+        assert_eq!((summary.replayed, summary.still_invalid, summary.apply_failed), (1, 0, 0));
+        assert!(state.spool.quarantined_events().await.unwrap().is_empty());
+        assert!(!quarantined[0].with_extension("error").exists());
+
+        // Events from a publisher with a newer, incompatible schema wait too.
+        let newer = br#"{"schema_version":99,"source":"observer-1","hash":"a1b2","queue_id":"Q1","recipient":"user@example.com","status_code":"5.1.1","action":"failed","diagnostic":"","smtp_status":"bounced","observed_at_unix":1}"#;
+        let error = super::decode_delivery_event(newer).unwrap_err();
+        quarantine_observer_event(&state, "observer-1", newer, &error).await.unwrap();
+        let summary = replay_quarantined_events(&state).await.unwrap();
+        assert_eq!((summary.replayed, summary.still_invalid, summary.apply_failed), (0, 1, 0));
+
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::{Context, Result, bail};
+use bouncer_proto::query::{
+    MAX_STATUS_QUERY_HASHES, MessageState, QueryRequest, QueryResponse, ServerStats, StatusQuery,
+    StatusQueryResponse
+};
+use tracing::{info, warn};
+
+use super::quarantine::replay_quarantined_events;
+use crate::app::AppState;
+
+const MAX_RECENT_BOUNCES: u32 = 1000;
+const MAX_SOURCE_EVENTS: u32 = 256;
+const STATS_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// Decodes a `query` frame body and answers it; a status lookup is scoped
+/// to the `tenant` of the frame header.
+///
+/// Failures are reported back to the caller as [`QueryResponse::Error`]
+/// so the connection stays usable for the next query.
+pub async fn answer_query(
+    state: &AppState,
+    tenant: Option<&str>,
+    body: &[u8]
+) -> QueryResponse {
+    match run_query(state, tenant, body).await {
+        Ok(response) => response,
+        Err(err) => {
+            warn!("query failed: error={:#}", err);
+            QueryResponse::Error { message: format!("{err:#}") }
+        }
+    }
+}
+
+async fn run_query(
+    state: &AppState,
+    tenant: Option<&str>,
+    body: &[u8]
+) -> Result<QueryResponse> {
+    let request: QueryRequest =
+        serde_json::from_slice(body).context("failed to decode query body")?;
+
+    match request {
+        QueryRequest::Status { hash } => {
+            let hash = hash.trim();
+            if hash.is_empty() {
+                bail!("status query names no hash");
+            }
+            Ok(QueryResponse::Status(state.db.message_state(hash, tenant).await?))
+        }
+        QueryRequest::RecentBounces { since_secs, limit } => {
+            let limit = limit.clamp(1, MAX_RECENT_BOUNCES);
+            let bounces = state.db.recent_bounces(since_secs, limit).await?;
+            Ok(QueryResponse::RecentBounces { bounces })
+        }
+        QueryRequest::Stats => Ok(QueryResponse::Stats(server_stats(state).await?)),
+        QueryRequest::Sources => {
+            Ok(QueryResponse::Sources { sources: state.sources.snapshot(state.clock.now()) })
+        }
+        QueryRequest::SourceEvents { limit } => {
+            let limit = limit.clamp(1, MAX_SOURCE_EVENTS) as usize;
+            Ok(QueryResponse::SourceEvents { events: state.sources.recent_events(limit) })
+        }
+        QueryRequest::ReplayQuarantine => {
+            let summary = replay_quarantined_events(state).await?;
+            info!(
+                "quarantine replayed on request: replayed={}, still_invalid={}, apply_failed={}",
+                summary.replayed, summary.still_invalid, summary.apply_failed
+            );
+            Ok(QueryResponse::ReplayQuarantine(summary))
+        }
+    }
+}
+
+/// Spool, database and connection counters, as `bouncer-admin stats` and the
+/// admin API's `GET /stats` show them.
+pub(super) async fn server_stats(state: &AppState) -> Result<ServerStats> {
+    let spool = state.spool.counts().await?;
+    let connections = state.connections.snapshot();
+    let bounces_last_24h = state.db.bounce_count_since(STATS_WINDOW_SECS).await?;
+    let suppressed = if state.db.suppression_enabled() {
+        Some(state.db.suppression_count().await?)
+    } else {
+        None
+    };
+
+    Ok(ServerStats {
+        uptime_secs: state.clock.now().duration_since(state.started_at).as_secs(),
+        spool_incoming: spool.incoming,
+        spool_processing: spool.processing,
+        spool_done: spool.done,
+        spool_failed: spool.failed,
+        bounces_last_24h,
+        suppressed,
+        connections_accepted: connections.accepted_total,
+        connections_rejected: connections.rejected_total,
+        connection_storms: connections.storms,
+        peak_connections_per_window: connections.peak_per_window
+    })
+}
+
+/// Decodes a `query_status` frame body and answers it with the state of
+/// every hash it names, in order, as seen by the `tenant` of the frame header.
+///
+/// Like [`answer_query`], failures are reported back in
+/// [`StatusQueryResponse::error`].
+pub async fn answer_status_query(
+    state: &AppState,
+    tenant: Option<&str>,
+    body: &[u8]
+) -> StatusQueryResponse {
+    match run_status_query(state, tenant, body).await {
+        Ok(messages) => StatusQueryResponse { messages, error: None },
+        Err(err) => {
+            warn!("status query failed: error={:#}", err);
+            StatusQueryResponse { messages: Vec::new(), error: Some(format!("{err:#}")) }
+        }
+    }
+}
+
+async fn run_status_query(
+    state: &AppState,
+    tenant: Option<&str>,
+    body: &[u8]
+) -> Result<Vec<MessageState>> {
+    let request: StatusQuery =
+        serde_json::from_slice(body).context("failed to decode query_status body")?;
+    let hashes: Vec<&str> =
+        request.hashes.iter().map(|hash| hash.trim()).filter(|hash| !hash.is_empty()).collect();
+    if hashes.is_empty() {
+        bail!("query_status names no hash");
+    }
+    if hashes.len() > MAX_STATUS_QUERY_HASHES {
+        bail!(
+            "query_status names {} hashes, at most {MAX_STATUS_QUERY_HASHES} are answered",
+            hashes.len()
+        );
+    }
+
+    let mut messages = Vec::with_capacity(hashes.len());
+    for hash in hashes {
+        messages.push(state.db.message_state(hash, tenant).await?);
+    }
+    Ok(messages)
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use bouncer_proto::event::DeliveryEvent;
+
+use super::parser::ParsedBounce;
+use crate::config::RecipientsConfig;
+
+/// Brings bounce recipients to the shape they are stored in (see
+/// `recipients` in the config), so they join with the addresses of the
+/// application: `"Jane" <Jane+news@Example.COM.>` becomes
+/// `Jane+news@example.com`, or `Jane@example.com` with `fold_plus_tags`.
+#[derive(Debug, Default)]
+pub struct RecipientNormalizer {
+    /// Set with `fold_plus_tags`.
+    tag_separator: Option<char>,
+    /// Lowercased normalized address to the address stored instead.
+    aliases: HashMap<String, String>
+}
+
+impl RecipientNormalizer {
+    pub fn new(config: RecipientsConfig) -> Self {
+        let mut normalizer = Self {
+            tag_separator: config.fold_plus_tags.then_some(config.tag_separator),
+            aliases: HashMap::new()
+        };
+        normalizer.aliases = config
+            .aliases
+            .iter()
+            .map(|(alias, canonical)| {
+                (normalizer.address(alias).to_ascii_lowercase(), canonical.trim().to_string())
+            })
+            .collect();
+        normalizer
+    }
+
+    pub fn folds_plus_tags(&self) -> bool {
+        self.tag_separator.is_some()
+    }
+
+    pub fn alias_count(&self) -> usize {
+        self.aliases.len()
+    }
+
+    pub fn normalize(
+        &self,
+        recipient: &str
+    ) -> String {
+        let address = self.address(recipient);
+        match self.aliases.get(&address.to_ascii_lowercase()) {
+            Some(canonical) => canonical.clone(),
+            None => address
+        }
+    }
+
+    /// `parsed` with its recipient normalized.
+    pub fn bounce(
+        &self,
+        parsed: &ParsedBounce
+    ) -> ParsedBounce {
+        ParsedBounce {
+            recipient: parsed.recipient.as_deref().map(|recipient| self.normalize(recipient)),
+            ..parsed.clone()
+        }
+    }
+
+    /// `event` with its recipient normalized.
+    pub fn event(
+        &self,
+        event: &DeliveryEvent
+    ) -> DeliveryEvent {
+        DeliveryEvent { recipient: self.normalize(&event.recipient), ..event.clone() }
+    }
+
+    /// Everything but the alias lookup.
+    fn address(
+        &self,
+        recipient: &str
+    ) -> String {
+        let address = strip_display_name(recipient);
+        let Some((local, domain)) = address.rsplit_once('@') else {
+            return address.to_string();
+        };
+        let local = match self.tag_separator.and_then(|separator| local.split_once(separator)) {
+            Some((base, _)) if !base.is_empty() => base,
+            _ => local
+        };
+        format!("{local}@{}", domain.trim_end_matches('.').to_ascii_lowercase())
+    }
+}
+
+/// The address inside the last `<...>`, or the whole trimmed value.
+fn strip_display_name(recipient: &str) -> &str {
+    let recipient = recipient.trim();
+    match recipient.rsplit_once('<') {
+        Some((_, rest)) => rest.split_once('>').map_or(rest, |(address, _)| address).trim(),
+        None => recipient
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::RecipientNormalizer;
+    use crate::config::RecipientsConfig;
+
+    #[test]
+    fn strips_display_names_and_lowercases_domains() {
+        let normalizer = RecipientNormalizer::default();
+        for (raw, normalized) in [
+            (r#""Jane Doe" <Jane+news@Example.COM>"#, "Jane+news@example.com"),
+            ("<user@example.com.>", "user@example.com"),
+            ("  User@Mail.Example.com ", "User@mail.example.com"),
+            ("undisclosed-recipients", "undisclosed-recipients")
+        ] {
+            assert_eq!(normalizer.normalize(raw), normalized, "{raw}");
+        }
+    }
+
+    #[test]
+    fn folds_plus_tags_and_aliases_when_configured() {
+        let normalizer = RecipientNormalizer::new(RecipientsConfig {
+            fold_plus_tags: true,
+            aliases: BTreeMap::from([(
+                "Postmaster@Example.com".to_string(),
+                "ops@example.com".to_string()
+            )]),
+            ..RecipientsConfig::default()
+        });
+        assert_eq!(normalizer.normalize("Jane+news+2@Example.com"), "Jane@example.com");
+        assert_eq!(normalizer.normalize("+only@example.com"), "+only@example.com");
+        assert_eq!(normalizer.normalize("<postmaster+x@EXAMPLE.com>"), "ops@example.com");
+
+        let dashed = RecipientNormalizer::new(RecipientsConfig {
+            fold_plus_tags: true,
+            tag_separator: '-',
+            ..RecipientsConfig::default()
+        });
+        assert_eq!(dashed.normalize("jane-news@example.com"), "jane@example.com");
+    }
+}
@@ -0,0 +1,215 @@
+//! SIGHUP config reload.
+//!
+//! When the config came from a file, SIGHUP re-reads and validates it, then
+//! swaps `log_filter`, `parser`, `classification`, `spool_retention` and the
+//! client timeouts in place; a change to `imap` or `imap_limits` restarts
+//! the IMAP mailbox loops. Any other changed key is logged as needing a
+//! restart. A file that fails to load leaves the running config untouched.
+
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, Result};
+use bouncer_helpers::reload::{ReloadSignal, apply_log_filter, log_reload};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use super::imap::run_imap_poll_loop;
+use super::parser::ParserChain;
+use crate::app::AppState;
+use crate::config::Config;
+
+/// Top-level keys applied without a restart.
+const LIVE_KEYS: [&str; 9] = [
+    "log_filter",
+    "parser",
+    "classification",
+    "spool_retention",
+    "imap",
+    "imap_limits",
+    "client_idle_secs",
+    "client_heartbeat_misses",
+    "tcp_keepalive_secs"
+];
+
+/// [`LIVE_KEYS`] that make up [`Config::client_timeouts`]; connections
+/// already open keep the timeouts they started with.
+const CLIENT_TIMEOUT_KEYS: [&str; 3] =
+    ["client_idle_secs", "client_heartbeat_misses", "tcp_keepalive_secs"];
+
+/// A value replaced as a whole on reload; readers keep the `Arc` they loaded.
+#[derive(Debug)]
+pub struct Live<T>(RwLock<Arc<T>>);
+
+impl<T> Live<T> {
+    pub fn new(value: T) -> Self {
+        Self(RwLock::new(Arc::new(value)))
+    }
+
+    pub fn load(&self) -> Arc<T> {
+        self.0.read().expect("live value lock poisoned").clone()
+    }
+
+    pub fn store(
+        &self,
+        value: T
+    ) {
+        *self.0.write().expect("live value lock poisoned") = Arc::new(value);
+    }
+}
+
+/// Runs the IMAP loops until shutdown and, when `config` was read from a
+/// file, reloads it on every SIGHUP.
+pub async fn run_config_reload(
+    state: AppState,
+    mut config: Config
+) {
+    let mut imap = ImapLoops::start(&config, &state);
+    let mut signal = match config.snapshot {
+        Some(_) => ReloadSignal::install(),
+        None => ReloadSignal::disabled()
+    };
+
+    loop {
+        tokio::select! {
+            _ = state.shutdown.cancelled() => break,
+            _ = signal.recv() => {
+                info!("reload signal received: SIGHUP");
+                match reload(&state, &config) {
+                    Ok((next, changed)) => {
+                        if changed.iter().any(|key| key == "imap" || key == "imap_limits") {
+                            info!("restarting imap loops for new config");
+                            imap.stop().await;
+                            imap = ImapLoops::start(&next, &state);
+                        }
+                        config = next;
+                    }
+                    Err(err) => warn!("config reload failed, keeping running config: error={err:#}")
+                }
+            }
+        }
+    }
+
+    imap.stop().await;
+}
+
+/// Loads the config file again and applies its live settings. Returns the
+/// new config and its changed top-level keys.
+fn reload(
+    state: &AppState,
+    current: &Config
+) -> Result<(Config, Vec<String>)> {
+    let snapshot = current.snapshot.as_ref().context("config was not loaded from a file")?;
+    let next = Config::from_path(snapshot.path())?;
+    let changed =
+        snapshot.changed_keys(next.snapshot.as_ref().context("reloaded config lost its file")?);
+    let has = |key: &str| changed.iter().any(|changed| changed == key);
+
+    // Everything fallible runs first so a bad file applies nothing.
+    let parsers = has("parser").then(|| ParserChain::from_config(&next.parser)).transpose()?;
+
+    if has("log_filter") {
+        apply_log_filter(next.log_filter.as_deref());
+    }
+    if let Some(parsers) = parsers {
+        info!("bounce parser chain: {}", parsers.names().join(","));
+        state.parsers.store(parsers);
+    }
+    if has("classification") {
+        state.db.set_classification(next.classification.clone());
+    }
+    if has("spool_retention") {
+        state.retention.store(next.spool_retention.clone());
+    }
+    if CLIENT_TIMEOUT_KEYS.iter().any(|key| has(key)) {
+        state.clients.store(next.client_timeouts());
+    }
+    log_reload(&changed, &LIVE_KEYS);
+    Ok((next, changed))
+}
+
+/// The IMAP mailbox loops of one config, stoppable without a full shutdown.
+struct ImapLoops {
+    stop: CancellationToken,
+    task: Option<JoinHandle<()>>
+}
+
+impl ImapLoops {
+    fn start(
+        config: &Config,
+        state: &AppState
+    ) -> Self {
+        let stop = state.shutdown.child_token();
+        if config.imap.is_empty() {
+            info!("imap fallback disabled (imap config missing)");
+            return Self { stop, task: None };
+        }
+
+        let task = tokio::spawn(run_imap_poll_loop(
+            config.imap.clone(),
+            config.imap_limits.clone(),
+            state.db.clone(),
+            state.parsers.clone(),
+            state.clock.clone(),
+            state.status.clone(),
+            state.triggers.clone(),
+            state.audit.enabled,
+            stop.clone()
+        ));
+        Self { stop, task: Some(task) }
+    }
+
+    async fn stop(&mut self) {
+        self.stop.cancel();
+        if let Some(task) = self.task.take()
+            && let Err(err) = task.await
+        {
+            warn!("imap loops join failed: error={err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use uuid::Uuid;
+
+    use super::reload;
+    use crate::app::AppState;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn applies_live_keys_and_keeps_config_on_error() {
+        let root = std::env::temp_dir().join(format!("bouncer-reload-{}", Uuid::now_v7()));
+        let state = AppState::for_tests(&root).await;
+        let path = root.join("bouncer.yaml");
+        let write = |extra: &str| {
+            std::fs::write(&path, format!("database_url: \"sqlite::memory:\"\n{extra}")).unwrap()
+        };
+        write("");
+        let config = Config::from_path(&path).unwrap();
+
+        write("worker_concurrency: nope\n");
+        assert!(reload(&state, &config).is_err());
+
+        write(concat!(
+            "worker_concurrency: 8\nparser:\n  chain: [\"dsn\"]\nspool_retention:\n  done: 1d\n",
+            "client_idle_secs: 60\n"
+        ));
+        let (next, changed) = reload(&state, &config).unwrap();
+        assert_eq!(
+            changed,
+            ["client_idle_secs", "parser", "spool_retention", "worker_concurrency"]
+        );
+        assert_eq!(state.parsers.load().names(), ["dsn"]);
+        assert!(state.retention.load().done.is_some());
+        assert_eq!(state.clients.load().idle, Some(Duration::from_secs(60)));
+
+        write("parser:\n  chain: [\"nope\"]\n");
+        assert!(reload(&state, &next).is_err());
+        assert_eq!(state.parsers.load().names(), ["dsn"]);
+
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+}
@@ -0,0 +1,185 @@
+//! Retries of transient database errors and the circuit breaker that holds
+//! spool workers back while the database is unreachable.
+//!
+//! Every [`Database`] write runs through [`Database::resilient`]: lost
+//! connections, pool timeouts, deadlocks and SQLite busy errors are retried
+//! with backoff. An operation still failing after its retries counts towards
+//! `database_resilience.breaker_threshold`; once reached the breaker opens,
+//! calls fail fast with [`DatabaseUnavailable`] and workers wait in
+//! [`DbBreaker::wait_closed`]. [`run_db_health_check`] pings the pool and
+//! closes the breaker when the database answers again.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use bouncer_helpers::coded_error;
+use bouncer_helpers::error_code::ErrorCode;
+use tokio::sync::watch;
+use tokio::time::interval;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+use super::database::Database;
+
+/// Returned without touching the pool while the breaker is open.
+#[derive(Debug, Clone, Copy)]
+pub struct DatabaseUnavailable;
+
+impl fmt::Display for DatabaseUnavailable {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        f.write_str("database unavailable (circuit breaker open)")
+    }
+}
+
+impl std::error::Error for DatabaseUnavailable {}
+
+/// Open while the database stayed unreachable through
+/// `breaker_threshold` operations in a row.
+#[derive(Debug)]
+pub struct DbBreaker {
+    threshold: u32,
+    failures: AtomicU32,
+    open: watch::Sender<bool>
+}
+
+impl DbBreaker {
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            failures: AtomicU32::new(0),
+            open: watch::Sender::new(false)
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        *self.open.borrow()
+    }
+
+    /// An operation or health check reached the database.
+    pub fn record_success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+        if self.is_open() && self.open.send_replace(false) {
+            info!("database reachable again, circuit breaker closed");
+        }
+    }
+
+    /// An operation failed with a transient error after all its retries.
+    pub fn record_failure(
+        &self,
+        op: &str,
+        err: &anyhow::Error
+    ) {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed).saturating_add(1);
+        if failures >= self.threshold && !self.open.send_replace(true) {
+            coded_error!(
+                ErrorCode::DbUnavailable,
+                "database unavailable, circuit breaker open: op={}, failures={}, error={err:#}",
+                op,
+                failures
+            );
+        }
+    }
+
+    /// Resolves once the breaker is closed.
+    pub async fn wait_closed(&self) {
+        let mut open = self.open.subscribe();
+        let _ = open.wait_for(|open| !open).await;
+    }
+}
+
+/// True for errors a retry can clear: [`DatabaseUnavailable`], I/O and pool
+/// errors, SQLSTATE connection exceptions (`08`) and transaction rollbacks
+/// such as deadlocks (`40`), and SQLite `BUSY`/`LOCKED`.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if cause.is::<DatabaseUnavailable>() {
+            return true;
+        }
+        match cause.downcast_ref::<sqlx::Error>() {
+            Some(
+                sqlx::Error::Io(_)
+                | sqlx::Error::Tls(_)
+                | sqlx::Error::Protocol(_)
+                | sqlx::Error::PoolTimedOut
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::WorkerCrashed
+            ) => true,
+            Some(sqlx::Error::Database(db)) => {
+                db.code().is_some_and(|code| is_transient_code(&code))
+            }
+            _ => false
+        }
+    })
+}
+
+fn is_transient_code(code: &str) -> bool {
+    if code.len() == 5 {
+        return code.starts_with("08") || code.starts_with("40");
+    }
+    // SQLite result codes; the low byte is the primary code.
+    code.parse::<u32>().is_ok_and(|code| matches!(code & 0xff, 5 | 6))
+}
+
+/// Pings the pool every `database_resilience.health_interval` until
+/// `shutdown`. A failed ping counts towards opening the breaker, a
+/// successful one closes it.
+pub async fn run_db_health_check(
+    db: Arc<Database>,
+    shutdown: CancellationToken
+) {
+    let mut ticker = interval(db.resilience().health_interval);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => match db.ping().await {
+                Ok(()) => db.breaker().record_success(),
+                Err(err) => {
+                    debug!("database health check failed: error={err:#}");
+                    db.breaker().record_failure("health_check", &err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{DatabaseUnavailable, DbBreaker, is_transient};
+
+    #[test]
+    fn classifies_transient_errors() {
+        let io = anyhow::Error::new(sqlx::Error::Io(std::io::Error::other("reset")));
+        assert!(is_transient(&io.context("failed to begin tx")));
+        assert!(is_transient(&anyhow::Error::new(sqlx::Error::PoolTimedOut)));
+        assert!(is_transient(&anyhow::Error::new(DatabaseUnavailable)));
+        assert!(!is_transient(&anyhow::Error::new(sqlx::Error::RowNotFound)));
+        assert!(!is_transient(&anyhow::anyhow!("empty mail payload")));
+        assert!(super::is_transient_code("40001"));
+        assert!(super::is_transient_code("517"));
+        assert!(!super::is_transient_code("23000"));
+        assert!(!super::is_transient_code("2067"));
+    }
+
+    #[tokio::test]
+    async fn opens_after_threshold_and_closes_on_success() {
+        let breaker = DbBreaker::new(2);
+        let err = anyhow::anyhow!("connection refused");
+
+        breaker.record_failure("upsert_bounce", &err);
+        assert!(!breaker.is_open());
+        breaker.record_failure("upsert_bounce", &err);
+        assert!(breaker.is_open());
+
+        let waiting = tokio::time::timeout(Duration::from_millis(20), breaker.wait_closed());
+        assert!(waiting.await.is_err());
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        tokio::time::timeout(Duration::from_secs(1), breaker.wait_closed()).await.unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+use super::spool::{Spool, archived_files};
+use crate::app::AppState;
+use crate::config::RetentionConfig;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Deletes `done/` and `failed/` files older than their retention, honouring
+/// per-source overrides for files in source partitions.
+///
+/// Reads `state.retention` on every sweep so a config reload applies to the
+/// next one; sweeps are skipped while retention is off.
+pub async fn run_spool_retention(state: AppState) {
+    let retention = state.retention.load();
+    if retention.enabled() {
+        info!(
+            "spool retention enabled: done={}, failed={}, source_overrides={}",
+            format_age(retention.done),
+            format_age(retention.failed),
+            retention.sources.len()
+        );
+    }
+    let mut ticker = interval(SWEEP_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = state.shutdown.cancelled() => {
+                info!("spool retention stopping");
+                break;
+            }
+            _ = ticker.tick() => {
+                let retention = state.retention.load();
+                if !retention.enabled() {
+                    continue;
+                }
+                let now = state.clock.system_now();
+                for failed in [false, true] {
+                    let dir = if failed { &state.spool.failed } else { &state.spool.done };
+                    match sweep(&state.spool, failed, &retention, now).await {
+                        Ok(0) => {}
+                        Ok(removed) => info!(
+                            "spool retention removed files: dir={}, removed={}",
+                            dir.display(),
+                            removed
+                        ),
+                        Err(err) => warn!("spool retention sweep failed: dir={}, error={err:#}", dir.display())
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Failed files take their reason note with them.
+async fn sweep(
+    spool: &Spool,
+    failed: bool,
+    retention: &RetentionConfig,
+    now: SystemTime
+) -> Result<usize> {
+    let dir = if failed { spool.failed.as_path() } else { spool.done.as_path() };
+    let mut removed = 0;
+    for path in archived_files(dir).await? {
+        let partition = path
+            .parent()
+            .filter(|parent| *parent != dir)
+            .and_then(|parent| parent.file_name())
+            .and_then(|name| name.to_str());
+        let Some(max_age) = retention.max_age(partition, failed) else {
+            continue;
+        };
+
+        let modified = tokio::fs::metadata(&path)
+            .await
+            .and_then(|meta| meta.modified())
+            .with_context(|| format!("failed to stat {}", path.display()))?;
+        if now.duration_since(modified).unwrap_or_default() < max_age {
+            continue;
+        }
+
+        match spool.remove(&path).await {
+            Ok(()) => {
+                debug!("spool retention removed file: path={}", path.display());
+                removed += 1;
+                if failed {
+                    spool.remove_failure_note(&path).await?;
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to remove {}", path.display()));
+            }
+        }
+    }
+    Ok(removed)
+}
+
+fn format_age(age: Option<Duration>) -> String {
+    age.map_or_else(|| "keep".to_string(), |age| humantime::format_duration(age).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use bouncer_helpers::clock::system_clock;
+    use uuid::Uuid;
+
+    use super::sweep;
+    use crate::config::{RetentionConfig, RetentionOverride};
+    use crate::core::failures::FailureNote;
+    use crate::core::{Faults, Spool};
+
+    #[tokio::test]
+    async fn sweep_applies_source_override() {
+        let root = std::env::temp_dir().join(format!("bouncer-retention-{}", Uuid::now_v7()));
+        let spool = Spool::new(root.clone(), system_clock(), Arc::new(Faults::default()));
+        spool.ensure_dirs().await.unwrap();
+        let done = &spool.done;
+        tokio::fs::create_dir_all(done.join("mail-01")).await.unwrap();
+        tokio::fs::write(done.join("a.eml"), b"x").await.unwrap();
+        tokio::fs::write(done.join("mail-01").join("b.eml"), b"x").await.unwrap();
+
+        let retention = RetentionConfig {
+            done: Some(Duration::from_secs(7 * 86_400)),
+            failed: None,
+            sources: [(
+                "mail-01".to_string(),
+                RetentionOverride { done: Some(Duration::from_secs(3_600)), failed: None }
+            )]
+            .into()
+        };
+        let now = SystemTime::now() + Duration::from_secs(2 * 3_600);
+
+        assert_eq!(sweep(&spool, false, &retention, now).await.unwrap(), 1);
+        assert!(done.join("a.eml").exists());
+        assert!(!done.join("mail-01").join("b.eml").exists());
+        assert_eq!(sweep(&spool, true, &retention, now).await.unwrap(), 0);
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sweep_removes_reason_notes_with_failed_files() {
+        let root = std::env::temp_dir().join(format!("bouncer-retention-{}", Uuid::now_v7()));
+        let spool = Spool::new(root.clone(), system_clock(), Arc::new(Faults::default()));
+        spool.ensure_dirs().await.unwrap();
+        let failed = spool.failed.join("a.eml");
+        tokio::fs::write(&failed, b"x").await.unwrap();
+        let note = FailureNote::new(&anyhow::anyhow!("boom"), 1);
+        let note_path = spool.write_failure_note(&failed, &note).await.unwrap();
+
+        let retention = RetentionConfig {
+            done: None,
+            failed: Some(Duration::from_secs(3_600)),
+            sources: Default::default()
+        };
+        let now = SystemTime::now() + Duration::from_secs(2 * 3_600);
+        assert_eq!(sweep(&spool, true, &retention, now).await.unwrap(), 1);
+        assert!(!failed.exists() && !note_path.exists());
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tokio::time::interval;
+use tracing::{info, warn};
+
+use super::lanes::LaneSender;
+use crate::app::AppState;
+
+/// How often due retries are moved back to `incoming/`.
+const RETRY_TICK: Duration = Duration::from_secs(1);
+
+/// Counts the database retries of a spool file in its name,
+/// `<id>.dbretry<n>.eml`, so the count survives restarts.
+const DB_RETRY_MARKER: &str = ".dbretry";
+
+/// Delay queue for spooled bounces whose hash is not in `mail_messages` yet.
+///
+/// Under load a bounce can arrive before the application commits the
+/// message row. Instead of writing the orphan `mail_bounces` row right away,
+/// the worker leaves the file in `processing/` and asks for another lookup
+/// after each configured delay; once every delay is used the bounce is
+/// applied as usual. The queue is in memory: files still waiting at shutdown
+/// are requeued from `processing/` on the next start and begin again.
+#[derive(Debug)]
+pub struct MissingMessageRetries {
+    delays: Vec<Duration>,
+    inner: Mutex<RetryState>
+}
+
+#[derive(Debug, Default)]
+struct RetryState {
+    /// Deferrals so far, per `processing/` path.
+    attempts: HashMap<PathBuf, usize>,
+    /// `processing/` paths and when they are due for another lookup.
+    pending: Vec<(Instant, PathBuf)>
+}
+
+impl MissingMessageRetries {
+    pub fn new(delays: Vec<Duration>) -> Self {
+        Self { delays, inner: Mutex::new(RetryState::default()) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.delays.is_empty()
+    }
+
+    /// Schedules another lookup for `path` and returns the attempt number and
+    /// its delay, or `None` once every delay was used.
+    pub fn defer(
+        &self,
+        path: &Path,
+        now: Instant
+    ) -> Option<(usize, Duration)> {
+        let mut inner = self.inner.lock().expect("retry queue mutex poisoned");
+        let attempt = inner.attempts.get(path).copied().unwrap_or(0);
+        let delay = *self.delays.get(attempt)?;
+        inner.attempts.insert(path.to_path_buf(), attempt + 1);
+        inner.pending.push((now + delay, path.to_path_buf()));
+        Some((attempt + 1, delay))
+    }
+
+    /// Removes and returns the paths due at `now`.
+    pub fn take_due(
+        &self,
+        now: Instant
+    ) -> Vec<PathBuf> {
+        take_due(&mut self.inner.lock().expect("retry queue mutex poisoned").pending, now)
+    }
+
+    /// Drops the attempt count of a file that left `processing/`.
+    pub fn forget(
+        &self,
+        path: &Path
+    ) {
+        self.inner.lock().expect("retry queue mutex poisoned").attempts.remove(path);
+    }
+
+    pub fn pending(&self) -> usize {
+        self.inner.lock().expect("retry queue mutex poisoned").pending.len()
+    }
+}
+
+/// Delay queue for spool files that failed on a database error.
+///
+/// Transient errors send a file straight back to `incoming/`, where the
+/// breaker holds the workers back; any other database error would move it to `failed/` for
+/// good, although the bounce itself is fine. Instead the worker renames it
+/// in `processing/` with one more [`DB_RETRY_MARKER`] attempt and requeues
+/// it after the delay of that attempt. A file whose attempts used every
+/// delay moves to `failed/`. Like [`MissingMessageRetries`] the queue is in
+/// memory; the attempt count is not.
+#[derive(Debug)]
+pub struct DbRetries {
+    delays: Vec<Duration>,
+    pending: Mutex<Vec<(Instant, PathBuf)>>
+}
+
+impl DbRetries {
+    pub fn new(delays: Vec<Duration>) -> Self {
+        Self { delays, pending: Mutex::new(Vec::new()) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.delays.is_empty()
+    }
+
+    /// Delay before the retry after `attempts` earlier ones, `None` once
+    /// every delay was used.
+    pub fn delay(
+        &self,
+        attempts: usize
+    ) -> Option<Duration> {
+        self.delays.get(attempts).copied()
+    }
+
+    pub fn schedule(
+        &self,
+        path: PathBuf,
+        due_at: Instant
+    ) {
+        self.pending_paths().push((due_at, path));
+    }
+
+    /// Removes and returns the paths due at `now`.
+    pub fn take_due(
+        &self,
+        now: Instant
+    ) -> Vec<PathBuf> {
+        take_due(&mut self.pending_paths(), now)
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending_paths().len()
+    }
+
+    fn pending_paths(&self) -> MutexGuard<'_, Vec<(Instant, PathBuf)>> {
+        self.pending.lock().expect("retry queue mutex poisoned")
+    }
+}
+
+/// Database retries `path` went through, from its name.
+pub fn db_retry_attempts(path: &Path) -> usize {
+    path.file_name().and_then(|name| name.to_str()).map_or(0, |name| split_db_retry(name).1)
+}
+
+/// `path` renamed to carry `attempts` database retries.
+pub fn with_db_retry_attempts(
+    path: &Path,
+    attempts: usize
+) -> Option<PathBuf> {
+    let (id, _, rest) = split_db_retry(path.file_name()?.to_str()?);
+    Some(path.with_file_name(format!("{id}{DB_RETRY_MARKER}{attempts}{rest}")))
+}
+
+/// `(id, attempts, rest)` of a spool file name, `rest` starting at the
+/// extension after the retry marker.
+fn split_db_retry(name: &str) -> (&str, usize, &str) {
+    let (id, rest) = name.split_at(name.find('.').unwrap_or(name.len()));
+    let Some(after) = rest.strip_prefix(DB_RETRY_MARKER) else {
+        return (id, 0, rest);
+    };
+    let digits = after.find(|c: char| !c.is_ascii_digit()).unwrap_or(after.len());
+    match after[..digits].parse() {
+        Ok(attempts) => (id, attempts, &after[digits..]),
+        Err(_) => (id, 0, rest)
+    }
+}
+
+fn take_due(
+    pending: &mut Vec<(Instant, PathBuf)>,
+    now: Instant
+) -> Vec<PathBuf> {
+    let (due, waiting) =
+        std::mem::take(pending).into_iter().partition::<Vec<_>, _>(|(due_at, _)| *due_at <= now);
+    *pending = waiting;
+    due.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Moves due deferred files from `processing/` back to `incoming/` and
+/// queues them on their lane.
+pub async fn run_missing_message_retries(
+    state: AppState,
+    process_tx: LaneSender
+) {
+    let delays = state
+        .retries
+        .delays
+        .iter()
+        .map(|delay| humantime::format_duration(*delay).to_string())
+        .collect::<Vec<_>>();
+    info!("missing message retries enabled: delays={}", delays.join(","));
+
+    let mut ticker = interval(RETRY_TICK);
+    loop {
+        tokio::select! {
+            _ = state.shutdown.cancelled() => {
+                info!(
+                    "missing message retries stopping: pending={} (requeued on next start)",
+                    state.retries.pending()
+                );
+                break;
+            }
+            _ = ticker.tick() => {
+                for processing_path in state.retries.take_due(state.clock.now()) {
+                    match requeue(&state, &processing_path).await {
+                        Ok(incoming_path) => {
+                            if !process_tx.send(incoming_path).await {
+                                info!("missing message retries stopping: process queue closed");
+                                return;
+                            }
+                        }
+                        Err(err) => {
+                            state.retries.forget(&processing_path);
+                            warn!(
+                                "deferred file could not be requeued: path={}, error={err:#}",
+                                processing_path.display()
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Like [`run_missing_message_retries`], for files waiting in [`DbRetries`].
+pub async fn run_db_retries(
+    state: AppState,
+    process_tx: LaneSender
+) {
+    let mut ticker = interval(RETRY_TICK);
+    loop {
+        tokio::select! {
+            _ = state.shutdown.cancelled() => {
+                info!(
+                    "database retries stopping: pending={} (requeued on next start)",
+                    state.db_retries.pending()
+                );
+                break;
+            }
+            _ = ticker.tick() => {
+                for processing_path in state.db_retries.take_due(state.clock.now()) {
+                    match requeue(&state, &processing_path).await {
+                        Ok(incoming_path) => {
+                            if !process_tx.send(incoming_path).await {
+                                info!("database retries stopping: process queue closed");
+                                return;
+                            }
+                        }
+                        Err(err) => warn!(
+                            "file waiting for a database retry could not be requeued: path={}, error={err:#}",
+                            processing_path.display()
+                        )
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn requeue(
+    state: &AppState,
+    processing_path: &Path
+) -> anyhow::Result<PathBuf> {
+    let spool = &state.spool;
+    let incoming_path = spool.relocate(processing_path, &spool.processing, &spool.incoming).await?;
+    spool.rename(processing_path, &incoming_path).await?;
+    Ok(incoming_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    use super::{DbRetries, MissingMessageRetries, db_retry_attempts, with_db_retry_attempts};
+
+    #[test]
+    fn defers_once_per_delay_then_gives_up() {
+        let retries =
+            MissingMessageRetries::new(vec![Duration::from_secs(30), Duration::from_secs(120)]);
+        let path = Path::new("processing/a.eml");
+        let start = Instant::now();
+
+        assert_eq!(retries.defer(path, start), Some((1, Duration::from_secs(30))));
+        assert!(retries.take_due(start + Duration::from_secs(29)).is_empty());
+        assert_eq!(retries.take_due(start + Duration::from_secs(30)), [path]);
+
+        assert_eq!(retries.defer(path, start), Some((2, Duration::from_secs(120))));
+        assert_eq!(retries.pending(), 1);
+        assert_eq!(retries.take_due(start + Duration::from_secs(120)), [path]);
+        assert_eq!(retries.defer(path, start), None);
+
+        retries.forget(path);
+        assert_eq!(retries.defer(path, start), Some((1, Duration::from_secs(30))));
+        assert!(!MissingMessageRetries::new(Vec::new()).enabled());
+    }
+
+    #[test]
+    fn db_retry_attempts_live_in_the_file_name() {
+        let path = Path::new("processing/0190a1b2.low.eml");
+        assert_eq!(db_retry_attempts(path), 0);
+        let retried = with_db_retry_attempts(path, 1).unwrap();
+        assert_eq!(retried, Path::new("processing/0190a1b2.dbretry1.low.eml"));
+        assert_eq!(db_retry_attempts(&retried), 1);
+        let retried = with_db_retry_attempts(&retried, 12).unwrap();
+        assert_eq!(retried, Path::new("processing/0190a1b2.dbretry12.low.eml"));
+        assert_eq!(db_retry_attempts(&retried), 12);
+
+        let maildir = Path::new("cur/0190a1b2.dbretry2.eml:2,");
+        assert_eq!(db_retry_attempts(maildir), 2);
+        assert_eq!(
+            with_db_retry_attempts(maildir, 3).unwrap(),
+            Path::new("cur/0190a1b2.dbretry3.eml:2,")
+        );
+
+        let retries = DbRetries::new(vec![Duration::from_secs(60)]);
+        assert_eq!((retries.delay(0), retries.delay(1)), (Some(Duration::from_secs(60)), None));
+        let start = Instant::now();
+        retries.schedule(retried.clone(), start + Duration::from_secs(60));
+        assert!(retries.take_due(start).is_empty());
+        assert_eq!(retries.take_due(start + Duration::from_secs(60)), [retried]);
+        assert_eq!(retries.pending(), 0);
+    }
+}
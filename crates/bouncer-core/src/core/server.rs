@@ -0,0 +1,1210 @@
+use std::future::Future;
+use std::io::{self, ErrorKind, Read};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use bouncer_helpers::error_code::ErrorCode;
+use bouncer_helpers::{coded_warn, logging};
+use bouncer_proto::event::decode_delivery_event;
+use bouncer_proto::heartbeat::decode_queue_map;
+use bouncer_proto::query::{
+    QUERY_KIND, QUERY_RESPONSE_KIND, QUERY_STATUS_KIND, QUERY_STATUS_RESPONSE_KIND
+};
+use bouncer_proto::status::{STATUS_KIND, STATUS_RESPONSE_KIND};
+use bouncer_proto::{
+    ACK, BAD_CHECKSUM, BodyChecksum, ChecksummedBody, Header, ProtoError, TOO_LARGE,
+    decode_header_json, encode_header_json, read_frame_streaming_async, write_frame_async
+};
+use flate2::read::GzDecoder;
+use futures_util::future::try_join_all;
+use socket2::{SockRef, TcpKeepalive};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, Take};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout_at;
+use tracing::field::Empty;
+use tracing::{Instrument, Span, debug, info, info_span, trace, warn};
+
+use super::allowlist::PeerAllowlist;
+use super::audit::{IngestPath, SpoolOrigin};
+use super::frame_audit::AuditedFrame;
+use super::lanes::Lane;
+use super::quarantine::quarantine_observer_event;
+use super::query::{answer_query, answer_status_query};
+use super::sources::ConnectedSources;
+use super::status::server_status;
+use crate::app::AppState;
+
+pub const DEFAULT_MAX_HEADER_BYTES: u32 = 64 * 1024;
+pub const DEFAULT_MAX_BODY_BYTES: u64 = 25 * 1024 * 1024;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// Bytes of a streamed mail body read ahead into memory, to spot gzipped
+/// payloads and for payload capture.
+const STREAM_HEAD_LEN: u64 = 64 * 1024;
+
+/// Read deadlines and TCP keepalive applied to every ingest connection.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClientTimeouts {
+    /// `client_idle_secs`; `None` waits for the next frame indefinitely.
+    pub idle: Option<Duration>,
+    /// `client_heartbeat_misses`; 0 does not expect heartbeats.
+    pub heartbeat_misses: u32,
+    /// `tcp_keepalive_secs`; `None` leaves keepalive off.
+    pub keepalive: Option<Duration>
+}
+
+/// Largest frame header and body a listener accepts; bigger frames are
+/// answered with [`TOO_LARGE`] and the connection is closed. A gzipped mail
+/// body must also stay within `body` once unpacked.
+///
+/// A frame whose body fails its header `checksum`, or that has none while
+/// `require_checksum` is set, is answered with [`BAD_CHECKSUM`] the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLimits {
+    /// `max_header_bytes`.
+    pub header: u32,
+    /// `max_body_bytes`.
+    pub body: u64,
+    /// `require_frame_checksum`.
+    pub require_checksum: bool
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        Self {
+            header: DEFAULT_MAX_HEADER_BYTES,
+            body: DEFAULT_MAX_BODY_BYTES,
+            require_checksum: false
+        }
+    }
+}
+
+/// When the next frame of a connection is due.
+///
+/// Every frame must arrive within `idle` of the previous one. Once a client
+/// registered with a `heartbeat_secs` period, its next heartbeat must also
+/// arrive within `heartbeat_misses` periods of the previous one.
+#[derive(Debug)]
+struct Liveness {
+    idle: Option<Duration>,
+    heartbeat_misses: u32,
+    heartbeat: Option<(Duration, Instant)>
+}
+
+impl Liveness {
+    fn new(timeouts: ClientTimeouts) -> Self {
+        Self {
+            idle: timeouts.idle,
+            heartbeat_misses: timeouts.heartbeat_misses,
+            heartbeat: None
+        }
+    }
+
+    /// `register` bodies are `key=value` lines; agents announce their
+    /// heartbeat period as `heartbeat_secs`.
+    fn registered(
+        &mut self,
+        body: &[u8],
+        now: Instant
+    ) {
+        let heartbeat_secs = String::from_utf8_lossy(body)
+            .lines()
+            .find_map(|line| line.strip_prefix("heartbeat_secs=")?.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+        self.heartbeat = (heartbeat_secs > 0 && self.heartbeat_misses > 0).then(|| {
+            (
+                Duration::from_secs(
+                    heartbeat_secs.saturating_mul(u64::from(self.heartbeat_misses))
+                ),
+                now
+            )
+        });
+    }
+
+    fn heartbeat(
+        &mut self,
+        now: Instant
+    ) {
+        if let Some((_, last)) = self.heartbeat.as_mut() {
+            *last = now;
+        }
+    }
+
+    /// Time left for the next frame and the reason the connection is closed
+    /// when it does not arrive.
+    fn deadline(
+        &self,
+        now: Instant
+    ) -> Option<(Duration, &'static str)> {
+        let heartbeat = self.heartbeat.map(|(window, last)| {
+            (window.saturating_sub(now.saturating_duration_since(last)), "heartbeat_missed")
+        });
+        [self.idle.map(|idle| (idle, "idle")), heartbeat]
+            .into_iter()
+            .flatten()
+            .min_by_key(|(left, _)| *left)
+    }
+}
+
+/// Binds every `listen` address and runs one ingest loop per listener,
+/// spawning one task per accepted client. Each listener enforces its own
+/// [`FrameLimits`].
+///
+/// Peers outside `allow` are dropped right after accept. The loops exit only
+/// when the shared shutdown token is cancelled.
+pub async fn run_tcp_server(
+    listen: &[(String, FrameLimits)],
+    allow: &[String],
+    state: AppState
+) -> Result<()> {
+    let allow = PeerAllowlist::parse(allow)?;
+    let mut listeners = Vec::with_capacity(listen.len());
+    for (address, limits) in listen {
+        let listener = TcpListener::bind(address)
+            .await
+            .with_context(|| format!("failed to bind tcp listener on {address}"))?;
+        debug!(
+            "tcp listener bound: listen={}, max_header_bytes={}, max_body_bytes={}",
+            address, limits.header, limits.body
+        );
+        listeners.push((address.as_str(), *limits, listener));
+    }
+
+    try_join_all(
+        listeners.into_iter().map(|(address, limits, listener)| {
+            accept_loop(address, limits, listener, &allow, &state)
+        })
+    )
+    .await?;
+    Ok(())
+}
+
+async fn accept_loop(
+    address: &str,
+    limits: FrameLimits,
+    listener: TcpListener,
+    allow: &PeerAllowlist,
+    state: &AppState
+) -> Result<()> {
+    loop {
+        tokio::select! {
+            _ = state.shutdown.cancelled() => {
+                info!("tcp server stopping: listen={}", address);
+                break;
+            }
+            accepted = listener.accept() => {
+                let (stream, peer) = accepted.context("tcp accept failed")?;
+                if !allow.allows(peer.ip()) {
+                    state.connections.record_reject();
+                    coded_warn!(
+                        ErrorCode::PeerRejected,
+                        "tcp peer rejected: peer={}, listen={}, reason=not in listen_allow",
+                        peer,
+                        address
+                    );
+                    drop(stream);
+                    continue;
+                }
+                state.connections.record_accept(state.clock.now());
+                if let Some(keepalive) = state.clients.load().keepalive {
+                    let params = TcpKeepalive::new().with_time(keepalive);
+                    if let Err(err) = SockRef::from(&stream).set_tcp_keepalive(&params) {
+                        debug!("failed to enable tcp keepalive: peer={}, error={}", peer, err);
+                    }
+                }
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = handle_client(stream, peer, limits, state).await {
+                        coded_warn!(
+                            ErrorCode::ClientIngestFailed,
+                            "client ingest failed: peer={}, error={}",
+                            peer,
+                            err
+                        );
+                    }
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles a single framed client message.
+///
+/// Supported kinds:
+/// - `heartbeat` / `register`: ACK only (control plane); a `register` closes
+///   an older connection that registered the same source (`sources.takeover`)
+/// - `observer_event`: decode JSON payload and apply directly to DB
+/// - `query`: answer with a `query_response` frame instead of an ACK
+/// - everything else: treat payload as raw mail and stream it to the spool
+async fn handle_client(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    limits: FrameLimits,
+    state: AppState
+) -> Result<()> {
+    let mut connected = ConnectedSources::new(state.sources.clone(), peer);
+    let mut liveness = Liveness::new(*state.clients.load());
+    let mut last_source = None;
+    loop {
+        // The whole frame, body included, must arrive by the deadline.
+        let deadline = liveness
+            .deadline(state.clock.now())
+            .map(|(left, reason)| (tokio::time::Instant::now() + left, reason));
+        let read = tokio::select! {
+            read = within(
+                deadline,
+                read_frame_streaming_async(&mut stream, limits.header, limits.body)
+            ) => read,
+            _ = connected.taken_over() => Err("taken_over")
+        };
+        let read = match read {
+            Ok(read) => read,
+            Err(reason) => {
+                log_closed(last_source.as_deref(), reason);
+                break;
+            }
+        };
+        let (header_bytes, body_reader) = match read {
+            Ok(frame) => frame,
+            Err(ProtoError::Io(err)) if is_disconnect(&err) => {
+                warn!("client disconnected: error={}", err);
+                break;
+            }
+            Err(err @ (ProtoError::HeaderTooLarge(_) | ProtoError::BodyTooLarge(_))) => {
+                // Best effort: a client still writing the body may see the
+                // reset before it reads the reply.
+                let _ = stream.write_all(TOO_LARGE).await;
+                let _ = stream.shutdown().await;
+                coded_warn!(
+                    ErrorCode::FrameTooLarge,
+                    "frame rejected: source={}, error={}, max_header_bytes={}, max_body_bytes={}",
+                    last_source.as_deref().unwrap_or("-"),
+                    err,
+                    limits.header,
+                    limits.body
+                );
+                break;
+            }
+            Err(err) => {
+                return Err(err).context("failed to read frame");
+            }
+        };
+
+        let header = match decode_header_json(&header_bytes) {
+            Ok(header) => header,
+            Err(err) => {
+                state
+                    .frame_audit
+                    .record(audited_frame(&state, peer, &header_bytes, &[], body_reader.limit()))
+                    .await;
+                return Err(err).context("failed to decode header");
+            }
+        };
+        let checksum = match BodyChecksum::from_header(header.checksum.as_deref()) {
+            Ok(None) if limits.require_checksum => Err(ProtoError::ChecksumMissing),
+            checksum => checksum
+        };
+        let mut checksum = match checksum {
+            Ok(checksum) => checksum,
+            Err(err) => {
+                reject_checksum(&mut stream, last_source.as_deref(), &err).await;
+                break;
+            }
+        };
+        let source = header.source.as_deref().unwrap_or(&header.from);
+        let now = state.clock.now();
+        if !matches!(header.kind.as_deref(), Some(QUERY_KIND | QUERY_STATUS_KIND | STATUS_KIND)) {
+            connected.seen(source, now);
+            if last_source.as_deref() != Some(source) {
+                last_source = Some(source.to_string());
+            }
+        }
+
+        if !matches!(
+            header.kind.as_deref(),
+            Some(
+                "heartbeat"
+                    | "register"
+                    | "observer_event"
+                    | QUERY_KIND
+                    | QUERY_STATUS_KIND
+                    | STATUS_KIND
+            )
+        ) {
+            let lane = state.dispatcher.lane_for(header.kind.as_deref(), header.source.as_deref());
+            let ingest_span = ingest_span(&header, source);
+            let spooled = match within(
+                deadline,
+                spool_mail(
+                    &state,
+                    peer,
+                    &header_bytes,
+                    &header,
+                    source,
+                    lane,
+                    &ingest_span,
+                    body_reader,
+                    checksum,
+                    limits.body
+                )
+            )
+            .await
+            {
+                Ok(spooled) => spooled,
+                Err(reason) => {
+                    log_closed(last_source.as_deref(), reason);
+                    break;
+                }
+            };
+            let (written_path, bytes) = match spooled {
+                Ok(spooled) => spooled,
+                Err(err) => match err.downcast_ref::<ProtoError>() {
+                    Some(mismatch @ ProtoError::ChecksumMismatch { .. }) => {
+                        reject_checksum(&mut stream, last_source.as_deref(), mismatch).await;
+                        break;
+                    }
+                    _ => return Err(err).context("failed to enqueue payload to spool")
+                }
+            };
+            state.traces.record(&written_path, ingest_span.in_scope(logging::current_traceparent));
+            state.origins.record(
+                &written_path,
+                SpoolOrigin {
+                    path: IngestPath::Tcp,
+                    source: header.source.clone(),
+                    received_at: state.clock.system_now()
+                }
+            );
+            state.sources.record_event(source, now);
+
+            if state.faults.take_ack_drop() {
+                warn!(
+                    "injected fault: dropping mail ACK, closing connection: path={}",
+                    written_path.display()
+                );
+                break;
+            }
+            stream.write_all(ACK).await.context("failed to write ACK")?;
+
+            state.status.count_tenant(header.tenant.as_deref(), |tenant| &mut tenant.mails);
+
+            info!(
+                "bounce accepted: bytes={}, path={}, kind={}, source={}, queue_id={}, lane={}, tenant={}",
+                bytes,
+                written_path.display(),
+                header.kind.as_deref().unwrap_or("mail"),
+                header.source.as_deref().unwrap_or("-"),
+                header.queue_id.as_deref().unwrap_or("-"),
+                lane.as_str(),
+                header.tenant.as_deref().unwrap_or("-")
+            );
+            continue;
+        }
+
+        let body = match within(deadline, read_body(body_reader)).await {
+            Ok(Ok(body)) => body,
+            Ok(Err(err)) if is_disconnect(&err) => {
+                warn!("client disconnected: error={}", err);
+                break;
+            }
+            Ok(Err(err)) => {
+                return Err(err).context("failed to read frame body");
+            }
+            Err(reason) => {
+                log_closed(last_source.as_deref(), reason);
+                break;
+            }
+        };
+        if let Some(checksum) = &mut checksum {
+            checksum.update(&body);
+            if let Err(err) = checksum.verify() {
+                reject_checksum(&mut stream, last_source.as_deref(), &err).await;
+                break;
+            }
+        }
+        state
+            .frame_audit
+            .record(audited_frame(&state, peer, &header_bytes, &body, body.len() as u64))
+            .await;
+
+        if matches!(header.kind.as_deref(), Some("heartbeat")) {
+            liveness.heartbeat(now);
+            state.sources.record_heartbeat(source, now, decode_queue_map(&body));
+            trace!("client heartbeat: source={}", header.source.as_deref().unwrap_or("-"));
+            stream.write_all(ACK).await.context("failed to write ACK")?;
+            continue;
+        }
+
+        if matches!(header.kind.as_deref(), Some("register")) {
+            connected.register(source, now);
+            liveness.registered(&body, now);
+            stream.write_all(ACK).await.context("failed to write ACK")?;
+            info!(
+                "client registered: source={}, from={}",
+                header.source.as_deref().unwrap_or("-"),
+                header.from
+            );
+            continue;
+        }
+
+        if matches!(header.kind.as_deref(), Some("observer_event")) {
+            let ingest_span = ingest_span(&header, source);
+            capture_payload(&state, &ingest_span, &header, source, &body, body.len() as u64);
+            let mut event = match decode_delivery_event(&body) {
+                Ok(event) => event,
+                Err(err) => {
+                    state.sources.record_parse_failure(source, now);
+                    quarantine_observer_event(&state, source, &body, &err).await?;
+                    stream.write_all(ACK).await.context("failed to write ACK")?;
+                    continue;
+                }
+            };
+
+            if event.tenant.is_none() {
+                event.tenant = header.tenant.clone();
+            }
+
+            if event.simulated {
+                // `bouncer-observer --simulate` traffic: proves the path
+                // end to end, but must not touch messages, suppressions,
+                // alerts or hooks.
+                state.sources.record_event(source, now);
+                stream.write_all(ACK).await.context("failed to write ACK")?;
+                debug!(
+                    "observer event simulated: source={}, hash={}, queue_id={}, smtp_status={}",
+                    source, event.hash, event.queue_id, event.smtp_status
+                );
+                continue;
+            }
+
+            if state.ignore_delivered_events && event.is_delivered() {
+                state.sources.record_ignored_delivery(source, now);
+                stream.write_all(ACK).await.context("failed to write ACK")?;
+                debug!(
+                    "observer event ignored: source={}, hash={}, queue_id={}, reason=delivered",
+                    source, event.hash, event.queue_id
+                );
+                continue;
+            }
+
+            let outcome = state
+                .db
+                .apply_observer_event(&event)
+                .instrument(info_span!(parent: &ingest_span, "db", op = "apply_observer_event"))
+                .await
+                .context("failed to apply observer event")?;
+            state.sources.record_event(source, now);
+            state
+                .status
+                .count_tenant(event.tenant.as_deref(), |tenant| &mut tenant.observer_events);
+
+            if state.faults.take_ack_drop() {
+                warn!("injected fault: dropping observer event ACK, closing connection");
+                break;
+            }
+            stream.write_all(ACK).await.context("failed to write ACK")?;
+            info!(
+                "observer event accepted: source={}, hash={}, queue_id={}, recipient={}, status_code={}, action={}, tenant={}, outcome={}",
+                header.source.as_deref().unwrap_or("-"),
+                event.hash,
+                event.queue_id,
+                event.recipient,
+                event.status_code,
+                event.action,
+                event.tenant.as_deref().unwrap_or("-"),
+                outcome.as_str()
+            );
+            continue;
+        }
+
+        if matches!(header.kind.as_deref(), Some(QUERY_KIND)) {
+            let response = answer_query(&state, header.tenant.as_deref(), &body).await;
+            let body = serde_json::to_vec(&response).context("failed to encode query response")?;
+            let header_bytes =
+                encode_header_json(&reply_header(&header, QUERY_RESPONSE_KIND, &body))
+                    .context("failed to encode query header")?;
+            write_frame_async(&mut stream, &header_bytes, &body)
+                .await
+                .context("failed to write query response")?;
+            info!(
+                "query answered: source={}, from={}, bytes={}",
+                header.source.as_deref().unwrap_or("-"),
+                header.from,
+                body.len()
+            );
+            continue;
+        }
+
+        if matches!(header.kind.as_deref(), Some(QUERY_STATUS_KIND)) {
+            let response = answer_status_query(&state, header.tenant.as_deref(), &body).await;
+            let body =
+                serde_json::to_vec(&response).context("failed to encode query_status response")?;
+            let header_bytes =
+                encode_header_json(&reply_header(&header, QUERY_STATUS_RESPONSE_KIND, &body))
+                    .context("failed to encode query_status header")?;
+            write_frame_async(&mut stream, &header_bytes, &body)
+                .await
+                .context("failed to write query_status response")?;
+            info!(
+                "status query answered: source={}, from={}, hashes={}, bytes={}",
+                header.source.as_deref().unwrap_or("-"),
+                header.from,
+                response.messages.len(),
+                body.len()
+            );
+            continue;
+        }
+
+        if matches!(header.kind.as_deref(), Some(STATUS_KIND)) {
+            let status = server_status(&state).await.context("failed to collect status")?;
+            let body = serde_json::to_vec(&status).context("failed to encode status")?;
+            let header_bytes =
+                encode_header_json(&reply_header(&header, STATUS_RESPONSE_KIND, &body))
+                    .context("failed to encode status header")?;
+            write_frame_async(&mut stream, &header_bytes, &body)
+                .await
+                .context("failed to write status response")?;
+            trace!(
+                "status answered: source={}, from={}",
+                header.source.as_deref().unwrap_or("-"),
+                header.from
+            );
+            continue;
+        }
+    }
+
+    Ok(())
+}
+
+/// Header of a `kind` response to `request`; checksummed when the request
+/// was, so peers that do not know checksums get the frames they always did.
+fn reply_header(
+    request: &Header,
+    kind: &str,
+    body: &[u8]
+) -> Header {
+    let header = Header {
+        from: request.to.clone(),
+        to: request.from.clone(),
+        kind: Some(kind.to_string()),
+        source: None,
+        traceparent: None,
+        queue_id: None,
+        tenant: None,
+        checksum: None
+    };
+    match request.checksum {
+        Some(_) => header.with_checksum(body),
+        None => header
+    }
+}
+
+/// Answers a frame that failed its checksum with [`BAD_CHECKSUM`]; the
+/// connection is closed after, as its lengths may be corrupt too.
+async fn reject_checksum(
+    stream: &mut TcpStream,
+    source: Option<&str>,
+    err: &ProtoError
+) {
+    let _ = stream.write_all(BAD_CHECKSUM).await;
+    let _ = stream.shutdown().await;
+    coded_warn!(
+        ErrorCode::FrameChecksumFailed,
+        "frame rejected: source={}, error={}",
+        source.unwrap_or("-"),
+        err
+    );
+}
+
+/// Runs `read` until the frame `deadline`; `Err` carries the close reason
+/// once it passed.
+async fn within<F: Future>(
+    deadline: Option<(tokio::time::Instant, &'static str)>,
+    read: F
+) -> Result<F::Output, &'static str> {
+    match deadline {
+        Some((at, reason)) => timeout_at(at, read).await.map_err(|_| reason),
+        None => Ok(read.await)
+    }
+}
+
+fn log_closed(
+    source: Option<&str>,
+    reason: &str
+) {
+    warn!("client connection closed: source={}, reason={}", source.unwrap_or("-"), reason);
+}
+
+fn is_disconnect(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset | ErrorKind::BrokenPipe
+    )
+}
+
+/// Reads a control frame body (heartbeat, register, event, query) into memory.
+async fn read_body(mut body: Take<&mut TcpStream>) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0_u8; body.limit() as usize];
+    body.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+fn audited_frame<'a>(
+    state: &AppState,
+    peer: SocketAddr,
+    header: &'a [u8],
+    body: &'a [u8],
+    body_bytes: u64
+) -> AuditedFrame<'a> {
+    AuditedFrame { peer, header, body, body_bytes, received_at: state.clock.system_now() }
+}
+
+/// Streams a mail frame body into `incoming/` and returns the spool path and
+/// the bytes written. Only the first [`STREAM_HEAD_LEN`] bytes are held in
+/// memory, unless the payload is a gzipped archive, which is unpacked whole.
+/// `peer` and the undecoded `header_bytes` are for the frame audit.
+///
+/// A body that fails `checksum` is not spooled, audited or captured; the
+/// error is the [`ProtoError::ChecksumMismatch`].
+#[allow(clippy::too_many_arguments)]
+async fn spool_mail(
+    state: &AppState,
+    peer: SocketAddr,
+    header_bytes: &[u8],
+    header: &Header,
+    source: &str,
+    lane: Lane,
+    span: &Span,
+    mut body: Take<&mut TcpStream>,
+    mut checksum: Option<BodyChecksum>,
+    max_body_bytes: u64
+) -> Result<(PathBuf, u64)> {
+    let body_len = body.limit();
+    let mut head = vec![0_u8; body_len.min(STREAM_HEAD_LEN) as usize];
+    body.read_exact(&mut head).await.context("failed to read mail payload")?;
+    let enqueue_span = info_span!(parent: span, "spool.enqueue", lane = lane.as_str());
+
+    if head.starts_with(&GZIP_MAGIC) {
+        body.read_to_end(&mut head).await.context("failed to read mail payload")?;
+        if head.len() as u64 != body_len {
+            bail!("client disconnected after {} of {} payload bytes", head.len(), body_len);
+        }
+        if let Some(checksum) = &mut checksum {
+            checksum.update(&head);
+            checksum.verify()?;
+        }
+        state.frame_audit.record(audited_frame(state, peer, header_bytes, &head, body_len)).await;
+        let mut payload = envelope_recipient_header(state, header).unwrap_or_default().into_bytes();
+        payload.extend_from_slice(
+            &gunzip_within(head, max_body_bytes).context("failed to gunzip mail payload")?
+        );
+        let written_path = state
+            .spool
+            .enqueue_mail(&payload, lane, header.source.as_deref(), header.tenant.as_deref())
+            .instrument(enqueue_span)
+            .await?;
+        capture_payload(state, span, header, source, &payload, payload.len() as u64);
+        return Ok((written_path, payload.len() as u64));
+    }
+
+    let rest = body.limit();
+    if let Some(checksum) = &mut checksum {
+        checksum.update(&head);
+        if rest == 0 {
+            checksum.verify()?;
+        }
+    }
+    let mut prefix = envelope_recipient_header(state, header).unwrap_or_default().into_bytes();
+    prefix.extend_from_slice(&head);
+    let total = prefix.len() as u64 + rest;
+    // The read that completes the body fails on a mismatch, so the file is
+    // never published to `incoming/`.
+    let mut body = ChecksummedBody::new(body, checksum, rest);
+    let written = state
+        .spool
+        .enqueue_mail_stream(
+            &prefix,
+            &mut body,
+            rest,
+            lane,
+            header.source.as_deref(),
+            header.tenant.as_deref()
+        )
+        .instrument(enqueue_span)
+        .await;
+    if let Some(mismatch) = body.mismatch() {
+        return Err(mismatch.into());
+    }
+    let written = written?;
+    state.frame_audit.record(audited_frame(state, peer, header_bytes, &head, body_len)).await;
+    capture_payload(state, span, header, source, &prefix, total);
+    Ok((written, total))
+}
+
+/// Unpacks a gzipped payload, e.g. a `done/*.eml.gz` file replayed with
+/// `bouncer-client`. Raw mail never starts with the gzip magic bytes.
+pub(super) fn gunzip_archived(body: Vec<u8>) -> Result<Vec<u8>> {
+    gunzip_within(body, DEFAULT_MAX_BODY_BYTES)
+}
+
+/// [`gunzip_archived`] with the unpacked size capped at `max_len` bytes.
+fn gunzip_within(
+    body: Vec<u8>,
+    max_len: u64
+) -> Result<Vec<u8>> {
+    if !body.starts_with(&GZIP_MAGIC) {
+        return Ok(body);
+    }
+    let mut plain = Vec::new();
+    GzDecoder::new(body.as_slice()).take(max_len + 1).read_to_end(&mut plain)?;
+    if plain.len() as u64 > max_len {
+        bail!("gunzipped payload exceeds {max_len} bytes");
+    }
+    Ok(plain)
+}
+
+/// `X-Original-To` line recording the frame's `to` when it is a VERP
+/// address, so the hash survives spooling even if the relay dropped the
+/// header.
+fn envelope_recipient_header(
+    state: &AppState,
+    header: &Header
+) -> Option<String> {
+    let parsers = state.parsers.load();
+    parsers.verp()?.decode(&header.to)?;
+    Some(format!("X-Original-To: {}\r\n", header.to.trim()))
+}
+
+/// Records a sampled, redacted payload head on the ingest span and logs it;
+/// `head` may be a prefix of a streamed body of `bytes` bytes.
+fn capture_payload(
+    state: &AppState,
+    span: &Span,
+    header: &Header,
+    source: &str,
+    head: &[u8],
+    bytes: u64
+) {
+    let Some(snippet) = state.capture.sample(source, head) else {
+        return;
+    };
+    span.record("payload", snippet.as_str());
+    span.in_scope(|| {
+        info!(
+            "payload sampled: source={}, kind={}, bytes={}, payload={:?}",
+            source,
+            header.kind.as_deref().unwrap_or("mail"),
+            bytes,
+            snippet
+        )
+    });
+}
+
+/// Span of one ingested frame; continues the sender's trace when the header
+/// carries a `traceparent`.
+fn ingest_span(
+    header: &Header,
+    source: &str
+) -> Span {
+    let span = info_span!(
+        "ingest",
+        kind = header.kind.as_deref().unwrap_or("mail"),
+        source,
+        payload = Empty
+    );
+    logging::set_remote_parent(&span, header.traceparent.as_deref());
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use bouncer_proto::event::{DeliveryEvent, encode_delivery_event};
+    use bouncer_proto::query::{
+        QUERY_KIND, QUERY_RESPONSE_KIND, QUERY_STATUS_KIND, QUERY_STATUS_RESPONSE_KIND,
+        QueryRequest, QueryResponse, StatusQuery, StatusQueryResponse
+    };
+    use bouncer_proto::{
+        Header, ProtoError, body_checksum, decode_header_json, encode_header_json, read_ack_async,
+        read_frame_async, write_frame_async
+    };
+    use tokio::net::{TcpListener, TcpStream};
+    use uuid::Uuid;
+
+    use super::{ClientTimeouts, FrameLimits, Liveness, handle_client};
+    use crate::app::AppState;
+    use crate::config::FrameAuditConfig;
+    use crate::core::FrameAudit;
+
+    #[test]
+    fn expects_heartbeats_after_register() {
+        let start = Instant::now();
+        let timeouts = ClientTimeouts {
+            idle: Some(Duration::from_secs(300)),
+            heartbeat_misses: 3,
+            keepalive: None
+        };
+        let mut liveness = Liveness::new(timeouts);
+        assert_eq!(liveness.deadline(start), Some((Duration::from_secs(300), "idle")));
+
+        liveness.registered(b"source=mx-01\nheartbeat_secs=30\n", start);
+        let later = start + Duration::from_secs(60);
+        assert_eq!(liveness.deadline(later), Some((Duration::from_secs(30), "heartbeat_missed")));
+        liveness.heartbeat(later);
+        assert_eq!(liveness.deadline(later), Some((Duration::from_secs(90), "heartbeat_missed")));
+
+        let mut legacy = Liveness::new(ClientTimeouts::default());
+        legacy.registered(b"source=mx-01\nlisten_udp=127.0.0.1:5140\n", start);
+        assert_eq!(legacy.deadline(later), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_frames_with_too_large_reply() {
+        let root = std::env::temp_dir().join(format!("bouncer-server-{}", Uuid::now_v7()));
+        let state = AppState::for_tests(&root).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let limits = FrameLimits { header: 1024, body: 16, require_checksum: false };
+        let server = tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            handle_client(stream, peer, limits, state).await
+        });
+
+        let header = encode_header_json(&Header {
+            from: "sender@example.com".to_string(),
+            to: "bounces@example.com".to_string(),
+            kind: None,
+            source: None,
+            traceparent: None,
+            queue_id: None,
+            tenant: None,
+            checksum: None
+        })
+        .unwrap();
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        write_frame_async(&mut client, &header, &[b'x'; 17]).await.unwrap();
+        assert!(matches!(read_ack_async(&mut client).await, Err(ProtoError::RejectedTooLarge)));
+        server.await.unwrap().unwrap();
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn answers_query_status_with_one_state_per_hash() {
+        let root = std::env::temp_dir().join(format!("bouncer-server-{}", Uuid::now_v7()));
+        let state = AppState::for_tests(&root).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let limits = FrameLimits { header: 1024, body: 4096, require_checksum: false };
+            handle_client(stream, peer, limits, state).await
+        });
+
+        let header = encode_header_json(&Header {
+            from: "app".to_string(),
+            to: "bouncer".to_string(),
+            kind: Some(QUERY_STATUS_KIND.to_string()),
+            source: None,
+            traceparent: None,
+            queue_id: None,
+            tenant: None,
+            checksum: None
+        })
+        .unwrap();
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let query = |hashes: &[&str]| {
+            serde_json::to_vec(&StatusQuery {
+                hashes: hashes.iter().map(|hash| hash.to_string()).collect()
+            })
+            .unwrap()
+        };
+
+        write_frame_async(&mut client, &header, &query(&["a1", " b2 "])).await.unwrap();
+        let (reply_header, body) = read_frame_async(&mut client, 1024, 1 << 20).await.unwrap();
+        assert_eq!(
+            decode_header_json(&reply_header).unwrap().kind.as_deref(),
+            Some(QUERY_STATUS_RESPONSE_KIND)
+        );
+        let response: StatusQueryResponse = serde_json::from_slice(&body).unwrap();
+        let hashes: Vec<_> = response.messages.iter().map(|state| state.hash.as_str()).collect();
+        assert_eq!((hashes, response.error), (vec!["a1", "b2"], None));
+
+        // Errors come back in the response; the connection stays usable.
+        write_frame_async(&mut client, &header, &query(&[])).await.unwrap();
+        let (_, body) = read_frame_async(&mut client, 1024, 1 << 20).await.unwrap();
+        let response: StatusQueryResponse = serde_json::from_slice(&body).unwrap();
+        assert!(response.messages.is_empty());
+        assert_eq!(response.error.as_deref(), Some("query_status names no hash"));
+
+        // A checksummed query gets a checksummed reply.
+        let checksummed = decode_header_json(&header).unwrap();
+        let body = query(&["a1"]);
+        let checksummed = encode_header_json(&checksummed.with_checksum(&body)).unwrap();
+        write_frame_async(&mut client, &checksummed, &body).await.unwrap();
+        let (reply_header, body) = read_frame_async(&mut client, 1024, 1 << 20).await.unwrap();
+        assert_eq!(decode_header_json(&reply_header).unwrap().checksum, Some(body_checksum(&body)));
+
+        drop(client);
+        server.await.unwrap().unwrap();
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn answers_query_frames_on_one_connection() {
+        let root = std::env::temp_dir().join(format!("bouncer-server-{}", Uuid::now_v7()));
+        let state = AppState::for_tests(&root).await;
+        let pool =
+            sqlx::SqlitePool::connect(&format!("sqlite:{}", root.join("bouncer.sqlite").display()))
+                .await
+                .unwrap();
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES ('tracked', 3)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+        let bounced = DeliveryEvent::new("mail-01", "tracked", "ABC123", "user@example.com", 0)
+            .with_outcome("bounced", "5.1.1", "failed");
+        state.db.apply_observer_event(&bounced).await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let limits = FrameLimits { header: 1024, body: 4096, require_checksum: false };
+            handle_client(stream, peer, limits, state).await
+        });
+
+        let header = encode_header_json(&Header {
+            from: "bouncer-admin".to_string(),
+            to: "bouncer".to_string(),
+            kind: Some(QUERY_KIND.to_string()),
+            source: None,
+            traceparent: None,
+            queue_id: None,
+            tenant: None,
+            checksum: None
+        })
+        .unwrap();
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut ask = async |body: &[u8]| {
+            write_frame_async(&mut client, &header, body).await.unwrap();
+            let (reply_header, body) = read_frame_async(&mut client, 1024, 1 << 20).await.unwrap();
+            assert_eq!(
+                decode_header_json(&reply_header).unwrap().kind.as_deref(),
+                Some(QUERY_RESPONSE_KIND)
+            );
+            serde_json::from_slice::<QueryResponse>(&body).unwrap()
+        };
+        let query = |request: QueryRequest| serde_json::to_vec(&request).unwrap();
+
+        let QueryResponse::Status(tracked) =
+            ask(&query(QueryRequest::Status { hash: " tracked ".to_string() })).await
+        else {
+            panic!("expected a status response");
+        };
+        assert_eq!((tracked.hash.as_str(), tracked.mail_status), ("tracked", Some(-7)));
+        assert_eq!(tracked.bounce.map(|bounce| bounce.status_code).as_deref(), Some("5.1.1"));
+        let QueryResponse::Status(unknown) =
+            ask(&query(QueryRequest::Status { hash: "unknown".to_string() })).await
+        else {
+            panic!("expected a status response");
+        };
+        assert_eq!((unknown.message_id, unknown.bounce.is_none()), (None, true));
+        let QueryResponse::RecentBounces { bounces } =
+            ask(&query(QueryRequest::RecentBounces { since_secs: 3_600, limit: 10 })).await
+        else {
+            panic!("expected recent bounces");
+        };
+        assert_eq!(
+            bounces.iter().map(|bounce| bounce.hash.as_str()).collect::<Vec<_>>(),
+            ["tracked"]
+        );
+        let QueryResponse::Stats(stats) = ask(&query(QueryRequest::Stats)).await else {
+            panic!("expected stats");
+        };
+        assert_eq!(stats.bounces_last_24h, 1);
+
+        // Errors come back in the response; the connection stays usable.
+        let QueryResponse::Error { message } =
+            ask(&query(QueryRequest::Status { hash: " ".to_string() })).await
+        else {
+            panic!("expected an error response");
+        };
+        assert_eq!(message, "status query names no hash");
+        assert!(matches!(ask(b"{\"command\":\"drop\"}").await, QueryResponse::Error { .. }));
+        assert!(matches!(ask(&query(QueryRequest::Sources)).await, QueryResponse::Sources { .. }));
+
+        drop(client);
+        server.await.unwrap().unwrap();
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn scopes_query_status_to_the_header_tenant() {
+        let root = std::env::temp_dir().join(format!("bouncer-server-{}", Uuid::now_v7()));
+        let state = AppState::for_tests(&root).await;
+        let pool =
+            sqlx::SqlitePool::connect(&format!("sqlite:{}", root.join("bouncer.sqlite").display()))
+                .await
+                .unwrap();
+        sqlx::query(
+            "INSERT INTO mail_messages (hash, status, tenant) VALUES ('shared', 3, 'shop')"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool.close().await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let limits = FrameLimits { header: 1024, body: 4096, require_checksum: false };
+            handle_client(stream, peer, limits, state).await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let body = serde_json::to_vec(&StatusQuery { hashes: vec!["shared".to_string()] }).unwrap();
+        let mut status_as = async |tenant: &str| {
+            let header = encode_header_json(&Header {
+                from: "app".to_string(),
+                to: "bouncer".to_string(),
+                kind: Some(QUERY_STATUS_KIND.to_string()),
+                source: None,
+                traceparent: None,
+                queue_id: None,
+                tenant: Some(tenant.to_string()),
+                checksum: None
+            })
+            .unwrap();
+            write_frame_async(&mut client, &header, &body).await.unwrap();
+            let (_, body) = read_frame_async(&mut client, 1024, 1 << 20).await.unwrap();
+            let response: StatusQueryResponse = serde_json::from_slice(&body).unwrap();
+            response.messages[0].mail_status
+        };
+        assert_eq!(status_as("shop").await, Some(3));
+        assert_eq!(status_as("crm").await, None);
+
+        drop(client);
+        server.await.unwrap().unwrap();
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn acknowledges_simulated_observer_events_without_writing() {
+        let root = std::env::temp_dir().join(format!("bouncer-server-{}", Uuid::now_v7()));
+        let state = AppState::for_tests(&root).await;
+        let db = state.db.clone();
+        let pool =
+            sqlx::SqlitePool::connect(&format!("sqlite:{}", root.join("bouncer.sqlite").display()))
+                .await
+                .unwrap();
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES ('tracked', 3)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            let limits = FrameLimits { header: 1024, body: 4096, require_checksum: false };
+            handle_client(stream, peer, limits, state).await
+        });
+
+        let header = encode_header_json(&Header {
+            from: "observer".to_string(),
+            to: "bouncer".to_string(),
+            kind: Some("observer_event".to_string()),
+            source: Some("observer-1".to_string()),
+            traceparent: None,
+            queue_id: None,
+            tenant: None,
+            checksum: None
+        })
+        .unwrap();
+        let event = |simulated: bool| {
+            let event = DeliveryEvent::new("observer-1", "tracked", "4ABC", "user@example.com", 1)
+                .with_outcome("bounced", "5.1.1", "failed")
+                .with_simulated(simulated);
+            encode_delivery_event(&event).unwrap()
+        };
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        write_frame_async(&mut client, &header, &event(true)).await.unwrap();
+        read_ack_async(&mut client).await.unwrap();
+        let state = db.message_state("tracked", None).await.unwrap();
+        assert_eq!((state.mail_status, state.history.len()), (Some(3), 0));
+
+        write_frame_async(&mut client, &header, &event(false)).await.unwrap();
+        read_ack_async(&mut client).await.unwrap();
+        assert_eq!(db.message_state("tracked", None).await.unwrap().history.len(), 1);
+
+        drop(client);
+        server.await.unwrap().unwrap();
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_corrupted_and_unchecksummed_frames_when_required() {
+        let root = std::env::temp_dir().join(format!("bouncer-server-{}", Uuid::now_v7()));
+        let mut state = AppState::for_tests(&root).await;
+        let audit_dir = root.join("frame-audit");
+        state.frame_audit = Arc::new(
+            FrameAudit::new(FrameAuditConfig {
+                enabled: true,
+                dir: Some(audit_dir.clone()),
+                max_body_bytes: 64,
+                max_frames: 10
+            })
+            .unwrap()
+        );
+        let spool = state.spool.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let limits = FrameLimits { header: 1024, body: 4096, require_checksum: true };
+            for _ in 0..3 {
+                let (stream, peer) = listener.accept().await.unwrap();
+                handle_client(stream, peer, limits, state.clone()).await.unwrap();
+            }
+        });
+
+        let header = Header {
+            from: "sender@example.com".to_string(),
+            to: "bounces@example.com".to_string(),
+            kind: None,
+            source: None,
+            traceparent: None,
+            queue_id: None,
+            tenant: None,
+            checksum: None
+        };
+        let mail = b"Subject: bounce\r\n\r\nbody\r\n";
+        let sent = |header: Header, body: &'static [u8]| async move {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            let header = encode_header_json(&header).unwrap();
+            write_frame_async(&mut client, &header, body).await.unwrap();
+            read_ack_async(&mut client).await
+        };
+
+        let corrupted = b"Subject: bounce\r\n\r\nbodY\r\n";
+        let reply = sent(header.clone().with_checksum(mail), corrupted).await;
+        assert!(matches!(reply, Err(ProtoError::RejectedChecksum)));
+        let reply = sent(header.clone(), mail).await;
+        assert!(matches!(reply, Err(ProtoError::RejectedChecksum)));
+        assert_eq!(spool.counts().await.unwrap().incoming, 0);
+        // Rejected frames are not audited either.
+        assert_eq!(std::fs::read_dir(&audit_dir).unwrap().count(), 0);
+
+        sent(header.clone().with_checksum(mail), mail).await.unwrap();
+        assert_eq!(spool.counts().await.unwrap().incoming, 1);
+        assert_eq!(std::fs::read_dir(&audit_dir).unwrap().count(), 2);
+
+        server.await.unwrap();
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+}
@@ -0,0 +1,749 @@
+//! Embedded SMTP listener (`smtp_listen`).
+//!
+//! Lets remote MTAs deliver bounces straight to the server instead of through
+//! a Postfix pipe transport and `bouncer-client`. Every accepted message gets
+//! `Return-Path` and `Received` headers, like a pipe delivery, and is spooled
+//! exactly like a TCP `mail` frame, so the same workers parse it. The
+//! listener never relays: with `smtp.recipient_domains` set, other recipient
+//! domains are refused.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use async_native_tls::{Identity, TlsAcceptor};
+use bouncer_helpers::coded_warn;
+use bouncer_helpers::error_code::ErrorCode;
+use time::OffsetDateTime;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tracing::{Instrument, debug, info, info_span};
+
+use super::audit::{IngestPath, SpoolOrigin};
+use crate::app::AppState;
+use crate::config::SmtpConfig;
+
+/// Spool partition, lane and source-registry name of SMTP deliveries.
+pub const SMTP_SOURCE: &str = "smtp";
+
+/// RFC 5321 allows 512 octets per command line; extensions push past that.
+const MAX_COMMAND_LINE: usize = 4096;
+/// Longer DATA lines are accepted but read in chunks of this size.
+const MAX_DATA_CHUNK: usize = 64 * 1024;
+const MAX_RECIPIENTS: usize = 100;
+/// Unknown or out-of-sequence commands tolerated before the session is closed.
+const MAX_BAD_COMMANDS: usize = 20;
+/// RFC 5321 section 4.5.3.2 suggests at least five minutes per command.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// Greeting for connections over `smtp.max_connections`; the peer retries later.
+const TOO_MANY_CONNECTIONS: &[u8] = b"421 4.7.0 Too many connections, try again later\r\n";
+
+/// Binds `listen` and accepts SMTP sessions until shutdown.
+///
+/// A configured but unreadable TLS certificate or key fails here, before the
+/// port is bound.
+pub async fn run_smtp_server(
+    listen: &str,
+    config: SmtpConfig,
+    state: AppState
+) -> Result<()> {
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(load_tls_acceptor(cert, key)?),
+        _ => None
+    };
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("failed to bind smtp listener on {listen}"))?;
+    info!(
+        "smtp listener enabled: listen={}, hostname={}, max_message_bytes={}, starttls={}, recipient_domains={}",
+        listen,
+        config.hostname,
+        config.max_message_bytes,
+        tls.is_some(),
+        if config.recipient_domains.is_empty() {
+            "*".to_string()
+        } else {
+            config.recipient_domains.join(",")
+        }
+    );
+
+    serve_smtp(listener, Arc::new(config), tls, state).await
+}
+
+async fn serve_smtp(
+    listener: TcpListener,
+    config: Arc<SmtpConfig>,
+    tls: Option<TlsAcceptor>,
+    state: AppState
+) -> Result<()> {
+    let sessions = Arc::new(Semaphore::new(config.max_connections));
+    loop {
+        tokio::select! {
+            _ = state.shutdown.cancelled() => {
+                info!("smtp listener stopping");
+                break;
+            }
+            accepted = listener.accept() => {
+                let (stream, peer) = accepted.context("smtp accept failed")?;
+                let Ok(permit) = sessions.clone().try_acquire_owned() else {
+                    // Best effort on the still non-blocking socket, so a flood costs no task.
+                    if let Ok(mut stream) = stream.into_std() {
+                        let _ = std::io::Write::write(&mut stream, TOO_MANY_CONNECTIONS);
+                    }
+                    debug!(%peer, "smtp connection refused, session limit reached");
+                    continue;
+                };
+                let config = config.clone();
+                let tls = tls.clone();
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    if let Err(err) = handle_connection(stream, peer, &config, tls, &state)
+                        .instrument(info_span!("smtp", %peer))
+                        .await
+                    {
+                        coded_warn!(
+                            ErrorCode::SmtpSessionFailed,
+                            "smtp session failed: peer={}, error={:#}",
+                            peer,
+                            err
+                        );
+                    }
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn load_tls_acceptor(
+    cert: &Path,
+    key: &Path
+) -> Result<TlsAcceptor> {
+    let cert_pem =
+        std::fs::read(cert).with_context(|| format!("failed to read {}", cert.display()))?;
+    let key_pem =
+        std::fs::read(key).with_context(|| format!("failed to read {}", key.display()))?;
+    let identity = Identity::from_pkcs8(&cert_pem, &key_pem).with_context(|| {
+        format!(
+            "invalid smtp tls identity (PEM certificate chain + PKCS#8 key): cert={}, key={}",
+            cert.display(),
+            key.display()
+        )
+    })?;
+    let acceptor =
+        native_tls::TlsAcceptor::new(identity).context("failed to build smtp tls acceptor")?;
+    Ok(acceptor.into())
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    config: &SmtpConfig,
+    tls: Option<TlsAcceptor>,
+    state: &AppState
+) -> Result<()> {
+    let mut session = Session::new(config, state, peer, tls.is_some());
+    let stream = match session.run(stream, true).await? {
+        Outcome::Closed => return Ok(()),
+        Outcome::StartTls(stream) => stream
+    };
+
+    let Some(acceptor) = tls else { bail!("STARTTLS accepted without a tls acceptor") };
+    let stream = tokio::time::timeout(IDLE_TIMEOUT, acceptor.accept(stream))
+        .await
+        .context("tls handshake timed out")?
+        .context("tls handshake failed")?;
+    session.start_tls();
+    match session.run(stream, false).await? {
+        Outcome::Closed => Ok(()),
+        Outcome::StartTls(_) => bail!("STARTTLS accepted twice")
+    }
+}
+
+enum Outcome<S> {
+    Closed,
+    /// The client sent STARTTLS and got `220`; the stream is ready for the
+    /// handshake. Anything it pipelined after the command is discarded.
+    StartTls(S)
+}
+
+/// Transaction state reset by `RSET`, `HELO`/`EHLO` and each finished `DATA`.
+#[derive(Debug, Default)]
+struct Envelope {
+    /// `Some("")` for the null reverse-path every DSN is sent with.
+    mail_from: Option<String>,
+    recipients: Vec<String>
+}
+
+struct Session<'a> {
+    config: &'a SmtpConfig,
+    state: &'a AppState,
+    peer: SocketAddr,
+    starttls_available: bool,
+    tls_active: bool,
+    helo: Option<String>,
+    esmtp: bool,
+    envelope: Envelope,
+    bad_commands: usize
+}
+
+impl<'a> Session<'a> {
+    fn new(
+        config: &'a SmtpConfig,
+        state: &'a AppState,
+        peer: SocketAddr,
+        starttls_available: bool
+    ) -> Self {
+        Self {
+            config,
+            state,
+            peer,
+            starttls_available,
+            tls_active: false,
+            helo: None,
+            esmtp: false,
+            envelope: Envelope::default(),
+            bad_commands: 0
+        }
+    }
+
+    /// RFC 3207: after the handshake the client starts over with EHLO.
+    fn start_tls(&mut self) {
+        self.starttls_available = false;
+        self.tls_active = true;
+        self.helo = None;
+        self.esmtp = false;
+        self.envelope = Envelope::default();
+    }
+
+    async fn run<S>(
+        &mut self,
+        stream: S,
+        greet: bool
+    ) -> Result<Outcome<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin
+    {
+        let mut stream = BufReader::new(stream);
+        if greet {
+            let greeting = format!("220 {} ESMTP bouncer ready", self.config.hostname);
+            reply(&mut stream, &greeting).await?;
+        }
+
+        let mut line = Vec::new();
+        loop {
+            if !read_line(&mut stream, &mut line, MAX_COMMAND_LINE).await? {
+                debug!("smtp client disconnected: peer={}", self.peer);
+                return Ok(Outcome::Closed);
+            }
+            if !line.ends_with(b"\n") {
+                reply(&mut stream, "500 5.5.2 Line too long").await?;
+                return Ok(Outcome::Closed);
+            }
+
+            let command = String::from_utf8_lossy(&line);
+            let command = command.trim_end_matches(['\r', '\n']);
+            let (verb, args) = command.split_once(' ').unwrap_or((command, ""));
+            let args = args.trim();
+
+            match verb.to_ascii_uppercase().as_str() {
+                "EHLO" | "HELO" if args.is_empty() => {
+                    self.bad_command(&mut stream, "501 5.5.4 Syntax: EHLO hostname").await?
+                }
+                "EHLO" => {
+                    self.greet_client(args, true);
+                    let mut lines = vec![
+                        format!("250-{} greets {}", self.config.hostname, args),
+                        format!("250-SIZE {}", self.config.max_message_bytes),
+                        "250-8BITMIME".to_string(),
+                        "250-PIPELINING".to_string(),
+                    ];
+                    if self.starttls_available {
+                        lines.push("250-STARTTLS".to_string());
+                    }
+                    lines.push("250 ENHANCEDSTATUSCODES".to_string());
+                    reply(&mut stream, &lines.join("\r\n")).await?;
+                }
+                "HELO" => {
+                    self.greet_client(args, false);
+                    reply(&mut stream, &format!("250 {}", self.config.hostname)).await?;
+                }
+                "STARTTLS" if !self.starttls_available => {
+                    self.bad_command(&mut stream, "502 5.5.1 STARTTLS not available").await?
+                }
+                "STARTTLS" => {
+                    reply(&mut stream, "220 2.0.0 Ready to start TLS").await?;
+                    return Ok(Outcome::StartTls(stream.into_inner()));
+                }
+                "MAIL" => {
+                    let response = self.mail_from(args);
+                    reply(&mut stream, response).await?;
+                }
+                "RCPT" => {
+                    let response = self.rcpt_to(args);
+                    reply(&mut stream, response).await?;
+                }
+                "DATA" if self.envelope.recipients.is_empty() => {
+                    self.bad_command(&mut stream, "503 5.5.1 Need RCPT command").await?
+                }
+                "DATA" => {
+                    reply(&mut stream, "354 End data with <CR><LF>.<CR><LF>").await?;
+                    let response = self.receive_data(&mut stream).await?;
+                    reply(&mut stream, &response).await?;
+                }
+                "RSET" => {
+                    self.envelope = Envelope::default();
+                    reply(&mut stream, "250 2.0.0 OK").await?;
+                }
+                "NOOP" => reply(&mut stream, "250 2.0.0 OK").await?,
+                "VRFY" => {
+                    reply(&mut stream, "252 2.5.0 Cannot VRFY user, but will accept message")
+                        .await?
+                }
+                "QUIT" => {
+                    reply(&mut stream, &format!("221 2.0.0 {} closing", self.config.hostname))
+                        .await?;
+                    return Ok(Outcome::Closed);
+                }
+                _ => self.bad_command(&mut stream, "502 5.5.2 Command not recognized").await?
+            }
+
+            if self.bad_commands >= MAX_BAD_COMMANDS {
+                reply(&mut stream, "421 4.7.0 Too many errors, closing connection").await?;
+                return Ok(Outcome::Closed);
+            }
+        }
+    }
+
+    fn greet_client(
+        &mut self,
+        helo: &str,
+        esmtp: bool
+    ) {
+        self.helo = Some(helo.to_string());
+        self.esmtp = esmtp;
+        self.envelope = Envelope::default();
+    }
+
+    async fn bad_command<S>(
+        &mut self,
+        stream: &mut S,
+        response: &str
+    ) -> Result<()>
+    where
+        S: AsyncWrite + Unpin
+    {
+        self.bad_commands += 1;
+        reply(stream, response).await
+    }
+
+    fn mail_from(
+        &mut self,
+        args: &str
+    ) -> &'static str {
+        if self.helo.is_none() {
+            return "503 5.5.1 Send EHLO/HELO first";
+        }
+        if self.envelope.mail_from.is_some() {
+            return "503 5.5.1 Nested MAIL command";
+        }
+        let Some((address, params)) = parse_path(args, "FROM:") else {
+            return "501 5.5.4 Syntax: MAIL FROM:<address>";
+        };
+
+        let declared_size = params.split_whitespace().find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            name.eq_ignore_ascii_case("SIZE").then(|| value.parse::<u64>().ok())?
+        });
+        if declared_size.is_some_and(|size| size > self.config.max_message_bytes) {
+            return "552 5.3.4 Message size exceeds fixed maximum message size";
+        }
+
+        self.envelope.mail_from = Some(address);
+        "250 2.1.0 OK"
+    }
+
+    fn rcpt_to(
+        &mut self,
+        args: &str
+    ) -> &'static str {
+        if self.envelope.mail_from.is_none() {
+            return "503 5.5.1 Need MAIL command";
+        }
+        let Some((address, _)) = parse_path(args, "TO:") else {
+            return "501 5.5.4 Syntax: RCPT TO:<address>";
+        };
+        if address.is_empty() {
+            return "501 5.1.3 Bad recipient address syntax";
+        }
+        if !self.config.accepts_recipient(&address) {
+            return "550 5.7.1 Relaying denied";
+        }
+        if self.envelope.recipients.len() >= MAX_RECIPIENTS {
+            return "452 4.5.3 Too many recipients";
+        }
+
+        self.envelope.recipients.push(address);
+        "250 2.1.5 OK"
+    }
+
+    /// Reads the message up to the terminating `.` line and spools it.
+    ///
+    /// The whole message is always consumed so the session stays in sync;
+    /// an oversized one is answered with 552 and dropped.
+    async fn receive_data<S>(
+        &mut self,
+        stream: &mut S
+    ) -> Result<String>
+    where
+        S: AsyncBufRead + Unpin
+    {
+        let envelope = std::mem::take(&mut self.envelope);
+        let Some(message) = read_data(stream, self.config.max_message_bytes).await? else {
+            info!(
+                "smtp message rejected: peer={}, reason=too large, max_message_bytes={}",
+                self.peer, self.config.max_message_bytes
+            );
+            return Ok("552 5.3.4 Message size exceeds fixed maximum message size".to_string());
+        };
+
+        match self.spool(&envelope, &message).await {
+            Ok(path) => {
+                info!(
+                    "smtp message accepted: peer={}, from=<{}>, recipients={}, bytes={}, path={}",
+                    self.peer,
+                    envelope.mail_from.as_deref().unwrap_or_default(),
+                    envelope.recipients.join(","),
+                    message.len(),
+                    path.display()
+                );
+                let id = path.file_stem().map(|stem| stem.to_string_lossy().into_owned());
+                Ok(format!("250 2.0.0 OK queued as {}", id.unwrap_or_default()))
+            }
+            Err(err) => {
+                coded_warn!(
+                    ErrorCode::SmtpSessionFailed,
+                    "smtp message not spooled, sender must retry: peer={}, error={:#}",
+                    self.peer,
+                    err
+                );
+                Ok("451 4.3.0 Temporary failure writing message, try again later".to_string())
+            }
+        }
+    }
+
+    async fn spool(
+        &self,
+        envelope: &Envelope,
+        message: &[u8]
+    ) -> Result<PathBuf> {
+        let mut payload = self.trace_headers(envelope).into_bytes();
+        payload.extend_from_slice(message);
+
+        let lane = self.state.dispatcher.lane_for(None, Some(SMTP_SOURCE));
+        let path = self
+            .state
+            .spool
+            .enqueue_mail(&payload, lane, Some(SMTP_SOURCE), None)
+            .await
+            .context("failed to enqueue smtp message to spool")?;
+        self.state.sources.record_event(SMTP_SOURCE, self.state.clock.now());
+        self.state.origins.record(
+            &path,
+            SpoolOrigin {
+                path: IngestPath::Smtp,
+                source: Some(SMTP_SOURCE.to_string()),
+                received_at: self.state.clock.system_now()
+            }
+        );
+        Ok(path)
+    }
+
+    /// `Return-Path` and `Received`, as a Postfix pipe transport prepends them.
+    fn trace_headers(
+        &self,
+        envelope: &Envelope
+    ) -> String {
+        let protocol = match (self.esmtp, self.tls_active) {
+            (true, true) => "ESMTPS",
+            (true, false) => "ESMTP",
+            (false, _) => "SMTP"
+        };
+        let recipient = match envelope.recipients.as_slice() {
+            [single] => format!("\r\n\tfor <{single}>"),
+            _ => String::new()
+        };
+        // Envelope recipients, where `parser.verp_template` looks for the hash.
+        let original_to: String = envelope
+            .recipients
+            .iter()
+            .map(|recipient| format!("X-Original-To: {recipient}\r\n"))
+            .collect();
+        let now = self.state.clock.system_now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        format!(
+            "Return-Path: <{}>\r\n{}Received: from {} ([{}])\r\n\tby {} (bouncer-server) with {}{}; {}\r\n",
+            envelope.mail_from.as_deref().unwrap_or_default(),
+            original_to,
+            self.helo.as_deref().unwrap_or("unknown"),
+            self.peer.ip(),
+            self.config.hostname,
+            protocol,
+            recipient,
+            rfc5322_date(now.as_secs())
+        )
+    }
+}
+
+async fn reply<S>(
+    stream: &mut S,
+    response: &str
+) -> Result<()>
+where
+    S: AsyncWrite + Unpin
+{
+    stream.write_all(response.as_bytes()).await.context("failed to write smtp reply")?;
+    stream.write_all(b"\r\n").await.context("failed to write smtp reply")?;
+    stream.flush().await.context("failed to flush smtp reply")
+}
+
+/// Reads up to `limit` bytes through the next `\n` into `line`. Returns
+/// `false` on EOF; a line without `\n` was cut at `limit`.
+async fn read_line<R>(
+    reader: &mut R,
+    line: &mut Vec<u8>,
+    limit: usize
+) -> Result<bool>
+where
+    R: AsyncBufRead + Unpin
+{
+    line.clear();
+    let read = tokio::time::timeout(
+        IDLE_TIMEOUT,
+        (&mut *reader).take(limit as u64).read_until(b'\n', line)
+    )
+    .await
+    .context("smtp client idle timeout")?
+    .context("failed to read from smtp client")?;
+    Ok(read > 0)
+}
+
+/// Reads DATA up to the lone `.` line, undoing dot-stuffing. Returns `None`
+/// when the message exceeds `max_bytes`.
+async fn read_data<R>(
+    reader: &mut R,
+    max_bytes: u64
+) -> Result<Option<Vec<u8>>>
+where
+    R: AsyncBufRead + Unpin
+{
+    let mut message = Vec::new();
+    let mut too_large = false;
+    let mut at_line_start = true;
+    let mut line = Vec::new();
+    loop {
+        if !read_line(reader, &mut line, MAX_DATA_CHUNK).await? {
+            bail!("smtp client disconnected during DATA");
+        }
+        if at_line_start && matches!(line.as_slice(), b".\r\n" | b".\n") {
+            break;
+        }
+
+        let chunk = if at_line_start && line.starts_with(b".") { &line[1..] } else { &line[..] };
+        if message.len() as u64 + chunk.len() as u64 > max_bytes {
+            too_large = true;
+            message.clear();
+        }
+        if !too_large {
+            message.extend_from_slice(chunk);
+        }
+        at_line_start = line.ends_with(b"\n");
+    }
+
+    Ok((!too_large).then_some(message))
+}
+
+/// Splits `FROM:<a@b> SIZE=1` into the address and the parameters.
+fn parse_path<'a>(
+    args: &'a str,
+    keyword: &str
+) -> Option<(String, &'a str)> {
+    let prefix = args.get(..keyword.len())?;
+    if !prefix.eq_ignore_ascii_case(keyword) {
+        return None;
+    }
+    let rest = args[keyword.len()..].trim_start().strip_prefix('<')?;
+    let (address, params) = rest.split_once('>')?;
+    // A source route (`<@relay:user@host>`) is accepted and ignored.
+    let address = address.rsplit_once(':').map_or(address, |(_, address)| address);
+    Some((address.trim().to_string(), params.trim()))
+}
+
+/// `Mon, 2 Jan 2006 15:04:05 +0000` for `Received` headers.
+fn rfc5322_date(unix_secs: u64) -> String {
+    let at = i64::try_from(unix_secs)
+        .ok()
+        .and_then(|secs| OffsetDateTime::from_unix_timestamp(secs).ok())
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    format!(
+        "{}, {} {} {} {:02}:{:02}:{:02} +0000",
+        &at.weekday().to_string()[..3],
+        at.day(),
+        &at.month().to_string()[..3],
+        at.year(),
+        at.hour(),
+        at.minute(),
+        at.second()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+    use uuid::Uuid;
+
+    use super::{parse_path, read_data, rfc5322_date, serve_smtp};
+    use crate::app::AppState;
+    use crate::config::SmtpConfig;
+
+    async fn exchange(
+        stream: &mut BufReader<TcpStream>,
+        command: &str
+    ) -> String {
+        stream.get_mut().write_all(format!("{command}\r\n").as_bytes()).await.unwrap();
+        read_reply(stream).await
+    }
+
+    async fn read_reply(stream: &mut BufReader<TcpStream>) -> String {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            reply.push_str(&line);
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return reply;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_bounce_into_spool_and_enforces_size() {
+        let root = std::env::temp_dir().join(format!("bouncer-smtp-{}", Uuid::now_v7()));
+        let state = AppState::for_tests(&root).await;
+        let spool = state.spool.clone();
+        let config = SmtpConfig {
+            hostname: "bounces.example.com".to_string(),
+            max_message_bytes: 200,
+            recipient_domains: vec!["bounces.example.com".to_string()],
+            ..SmtpConfig::default()
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_smtp(listener, Arc::new(config), None, state.clone()));
+
+        let mut client = BufReader::new(TcpStream::connect(addr).await.unwrap());
+        assert!(read_reply(&mut client).await.starts_with("220 bounces.example.com"));
+        let ehlo = exchange(&mut client, "EHLO mx.remote.example").await;
+        assert!(ehlo.contains("250-SIZE 200") && !ehlo.contains("STARTTLS"), "{ehlo}");
+        assert!(exchange(&mut client, "MAIL FROM:<> SIZE=500").await.starts_with("552"));
+        assert!(exchange(&mut client, "MAIL FROM:<>").await.starts_with("250"));
+        assert!(exchange(&mut client, "RCPT TO:<a@elsewhere.example>").await.starts_with("550"));
+        assert!(
+            exchange(&mut client, "RCPT TO:<b+h1@Bounces.example.com>").await.starts_with("250")
+        );
+        assert!(exchange(&mut client, "DATA").await.starts_with("354"));
+        let queued = exchange(&mut client, "Subject: bounce\r\n\r\n..leading dot\r\n.").await;
+        assert!(queued.starts_with("250 2.0.0 OK queued as"), "{queued}");
+
+        assert!(exchange(&mut client, "MAIL FROM:<>").await.starts_with("250"));
+        assert!(exchange(&mut client, "RCPT TO:<b@bounces.example.com>").await.starts_with("250"));
+        assert!(exchange(&mut client, "DATA").await.starts_with("354"));
+        let body = "x".repeat(300);
+        assert!(exchange(&mut client, &format!("{body}\r\n.")).await.starts_with("552"));
+        assert!(exchange(&mut client, "QUIT").await.starts_with("221"));
+
+        let mut entries = tokio::fs::read_dir(&spool.incoming).await.unwrap();
+        let entry = entries.next_entry().await.unwrap().expect("spooled message");
+        assert!(entries.next_entry().await.unwrap().is_none());
+        let spooled = String::from_utf8(tokio::fs::read(entry.path()).await.unwrap()).unwrap();
+        assert!(spooled.starts_with(
+            "Return-Path: <>\r\nX-Original-To: b+h1@Bounces.example.com\r\nReceived: from mx.remote.example"
+        ));
+        assert!(spooled.contains("for <b+h1@Bounces.example.com>"), "{spooled}");
+        assert!(spooled.ends_with("Subject: bounce\r\n\r\n.leading dot\r\n"), "{spooled}");
+
+        state.shutdown.cancel();
+        server.await.unwrap().unwrap();
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_sessions_over_the_connection_limit() {
+        let root = std::env::temp_dir().join(format!("bouncer-smtp-{}", Uuid::now_v7()));
+        let state = AppState::for_tests(&root).await;
+        let config = SmtpConfig {
+            hostname: "bounces.example.com".to_string(),
+            max_connections: 1,
+            ..SmtpConfig::default()
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_smtp(listener, Arc::new(config), None, state.clone()));
+
+        let mut first = BufReader::new(TcpStream::connect(addr).await.unwrap());
+        assert!(read_reply(&mut first).await.starts_with("220 "));
+        let mut second = BufReader::new(TcpStream::connect(addr).await.unwrap());
+        assert!(read_reply(&mut second).await.starts_with("421 4.7.0"));
+
+        assert!(exchange(&mut first, "QUIT").await.starts_with("221"));
+        drop(first);
+        let mut third = loop {
+            let mut client = BufReader::new(TcpStream::connect(addr).await.unwrap());
+            let greeting = read_reply(&mut client).await;
+            if greeting.starts_with("220 ") {
+                break client;
+            }
+            assert!(greeting.starts_with("421"), "{greeting}");
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        };
+        assert!(exchange(&mut third, "QUIT").await.starts_with("221"));
+
+        state.shutdown.cancel();
+        server.await.unwrap().unwrap();
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn data_keeps_long_lines_and_stops_at_lone_dot() {
+        let input = format!("{}\r\n.\r\nNOOP\r\n", "y".repeat(70_000));
+        let mut reader = BufReader::new(input.as_bytes());
+        let message = read_data(&mut reader, 1 << 20).await.unwrap().unwrap();
+        assert_eq!(message.len(), 70_002);
+
+        let mut rest = String::new();
+        reader.read_line(&mut rest).await.unwrap();
+        assert_eq!(rest, "NOOP\r\n");
+    }
+
+    #[test]
+    fn parses_paths_and_dates() {
+        assert_eq!(parse_path("FROM:<> SIZE=10", "FROM:"), Some((String::new(), "SIZE=10")));
+        assert_eq!(
+            parse_path("to: <@relay:user@example.com>", "TO:"),
+            Some(("user@example.com".to_string(), ""))
+        );
+        assert_eq!(parse_path("TO:user@example.com", "TO:"), None);
+        assert_eq!(rfc5322_date(1_136_214_245), "Mon, 2 Jan 2006 15:04:05 +0000");
+    }
+}
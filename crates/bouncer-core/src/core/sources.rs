@@ -0,0 +1,646 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use bouncer_helpers::clock::SharedClock;
+use bouncer_helpers::coded_warn;
+use bouncer_helpers::error_code::ErrorCode;
+use bouncer_proto::heartbeat::QueueMapOccupancy;
+use bouncer_proto::query::{SourceEvent, SourceStats};
+use serde::Serialize;
+use tokio::time::interval;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::app::AppState;
+
+/// Transitions kept in memory for `bouncer-admin source-events`.
+const RECENT_EVENTS: usize = 256;
+
+/// Per-`source` counters fed by `handle_client`.
+///
+/// A source appears on its first frame. Registered sources that send nothing
+/// (no heartbeat, no event) for `silent_after` are reported once as silent
+/// and again as recovered when they come back.
+///
+/// Registrations, the first open and last closed connection of a source,
+/// expiry (going silent) and recovery are kept as [`SourceEvent`]s; with
+/// `sources.persist_events` they are also written to `source_events`.
+///
+/// With takeover, a source is registered by one connection at a time: a
+/// `register` on a new connection closes the one that registered before,
+/// which after a quick agent restart is usually dead but not yet timed out.
+/// The entry, and with it every counter, stays the same across the switch.
+///
+/// Open client connections are also listed, with the sources each one sent
+/// frames for, for the diagnostics dump.
+#[derive(Debug)]
+pub struct SourceRegistry {
+    silent_after: Duration,
+    persist_events: bool,
+    takeover: bool,
+    clock: SharedClock,
+    next_connection: AtomicU64,
+    inner: Mutex<RegistryState>
+}
+
+#[derive(Debug, Default)]
+struct RegistryState {
+    sources: BTreeMap<String, SourceEntry>,
+    /// Newest last, at most [`RECENT_EVENTS`].
+    recent: VecDeque<SourceEvent>,
+    /// Events not written to `source_events` yet.
+    unsaved: Vec<SourceEvent>,
+    /// Client connections by id, while they are open.
+    open: BTreeMap<u64, OpenClient>
+}
+
+impl RegistryState {
+    fn push_event(
+        &mut self,
+        event: SourceEvent,
+        persist: bool
+    ) {
+        info!(
+            "source transition: source={}, transition={}, detail={}",
+            event.source,
+            event.transition,
+            event.detail.as_deref().unwrap_or("-")
+        );
+        if persist {
+            self.unsaved.push(event.clone());
+        }
+        if self.recent.len() == RECENT_EVENTS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(event);
+    }
+}
+
+#[derive(Debug)]
+struct SourceEntry {
+    registered: bool,
+    /// Open client connections that sent frames for this source.
+    connections: u32,
+    events: u64,
+    heartbeats: u64,
+    parse_failures: u64,
+    ignored_deliveries: u64,
+    last_seen: Instant,
+    last_heartbeat: Option<Instant>,
+    last_event: Option<Instant>,
+    silent: bool,
+    queue_map: Option<QueueMapOccupancy>,
+    /// Connection that registered the source last, with takeover on.
+    owner: Option<Owner>
+}
+
+#[derive(Debug)]
+struct OpenClient {
+    peer: SocketAddr,
+    opened: Instant,
+    sources: Vec<String>
+}
+
+/// An open client connection, for the diagnostics dump.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OpenConnection {
+    pub connection: u64,
+    pub peer: SocketAddr,
+    /// Sources it sent frames for, in order.
+    pub sources: Vec<String>,
+    pub open_secs: u64
+}
+
+/// A client connection as seen by the registry.
+#[derive(Debug, Clone)]
+struct Owner {
+    connection: u64,
+    peer: SocketAddr,
+    /// Cancelled to close the connection.
+    closed: CancellationToken
+}
+
+impl SourceEntry {
+    fn new(now: Instant) -> Self {
+        Self {
+            registered: false,
+            connections: 0,
+            events: 0,
+            heartbeats: 0,
+            parse_failures: 0,
+            ignored_deliveries: 0,
+            last_seen: now,
+            last_heartbeat: None,
+            last_event: None,
+            silent: false,
+            queue_map: None,
+            owner: None
+        }
+    }
+}
+
+impl SourceRegistry {
+    pub fn new(
+        silent_after: Duration,
+        clock: SharedClock
+    ) -> Self {
+        Self {
+            silent_after,
+            persist_events: false,
+            takeover: false,
+            clock,
+            next_connection: AtomicU64::new(1),
+            inner: Mutex::new(RegistryState::default())
+        }
+    }
+
+    /// Queues transitions for [`run_source_monitor`] to write to `source_events`.
+    pub fn persisting_events(
+        mut self,
+        enabled: bool
+    ) -> Self {
+        self.persist_events = enabled;
+        self
+    }
+
+    /// Closes the previous connection of a source registered again from
+    /// another one; see the type docs.
+    pub fn with_takeover(
+        mut self,
+        enabled: bool
+    ) -> Self {
+        self.takeover = enabled;
+        self
+    }
+
+    fn record_register(
+        &self,
+        source: &str,
+        now: Instant,
+        owner: &Owner
+    ) {
+        let mut previous = None;
+        self.touch(source, now, |entry| {
+            entry.registered = true;
+            if self.takeover {
+                previous = entry
+                    .owner
+                    .replace(owner.clone())
+                    .filter(|previous| previous.connection != owner.connection);
+            }
+        });
+        if let Some(previous) = previous {
+            previous.closed.cancel();
+            self.record_transition(
+                source,
+                "taken_over",
+                Some(format!("previous_peer={}, peer={}", previous.peer, owner.peer))
+            );
+        }
+        self.record_transition(source, "registered", None);
+    }
+
+    /// Counts client connection `connection` as sending frames for `source`;
+    /// the first open connection is recorded as `connected`.
+    pub fn record_connect(
+        &self,
+        source: &str,
+        now: Instant,
+        connection: u64
+    ) {
+        let mut connected = false;
+        self.touch(source, now, |entry| {
+            entry.connections += 1;
+            connected = entry.connections == 1;
+        });
+        if let Some(open) = self.lock().open.get_mut(&connection) {
+            open.sources.push(source.to_string());
+        }
+        if connected {
+            self.record_transition(source, "connected", None);
+        }
+    }
+
+    /// Counterpart of [`Self::record_connect`]; the last closed connection is
+    /// recorded as `disconnected`.
+    pub fn record_disconnect(
+        &self,
+        source: &str,
+        connection: u64
+    ) {
+        let mut inner = self.lock();
+        let Some(entry) = inner.sources.get_mut(source) else {
+            return;
+        };
+        if entry.owner.as_ref().is_some_and(|owner| owner.connection == connection) {
+            entry.owner = None;
+        }
+        entry.connections = entry.connections.saturating_sub(1);
+        if entry.connections == 0 {
+            let event = self.event(source, "disconnected", None);
+            inner.push_event(event, self.persist_events);
+        }
+    }
+
+    /// `queue_map` is the occupancy the heartbeat reported, if any.
+    pub fn record_heartbeat(
+        &self,
+        source: &str,
+        now: Instant,
+        queue_map: Option<QueueMapOccupancy>
+    ) {
+        self.touch(source, now, |entry| {
+            entry.heartbeats += 1;
+            entry.last_heartbeat = Some(now);
+            entry.queue_map = queue_map;
+        });
+    }
+
+    pub fn record_event(
+        &self,
+        source: &str,
+        now: Instant
+    ) {
+        self.touch(source, now, |entry| {
+            entry.events += 1;
+            entry.last_event = Some(now);
+        });
+    }
+
+    /// Counts an event dropped by `ignore_delivered_events`; it still counts
+    /// as an event of `source`.
+    pub fn record_ignored_delivery(
+        &self,
+        source: &str,
+        now: Instant
+    ) {
+        self.touch(source, now, |entry| {
+            entry.events += 1;
+            entry.ignored_deliveries += 1;
+            entry.last_event = Some(now);
+        });
+    }
+
+    pub fn record_parse_failure(
+        &self,
+        source: &str,
+        now: Instant
+    ) {
+        self.touch(source, now, |entry| entry.parse_failures += 1);
+    }
+
+    /// Flags registered sources that crossed `silent_after` since their last
+    /// frame and returns their names; each silence is reported once.
+    pub fn mark_silent(
+        &self,
+        now: Instant
+    ) -> Vec<(String, Duration)> {
+        let mut inner = self.lock();
+        let mut newly_silent = Vec::new();
+
+        for (source, entry) in inner.sources.iter_mut() {
+            let idle = now.saturating_duration_since(entry.last_seen);
+            if entry.registered && !entry.silent && idle >= self.silent_after {
+                entry.silent = true;
+                newly_silent.push((source.clone(), idle));
+            }
+        }
+        for (source, idle) in &newly_silent {
+            let event =
+                self.event(source, "expired", Some(format!("silent_secs={}", idle.as_secs())));
+            inner.push_event(event, self.persist_events);
+        }
+
+        newly_silent
+    }
+
+    /// Latest transitions, newest first.
+    pub fn recent_events(
+        &self,
+        limit: usize
+    ) -> Vec<SourceEvent> {
+        self.lock().recent.iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Transitions waiting to be written to `source_events`.
+    pub fn take_unsaved_events(&self) -> Vec<SourceEvent> {
+        std::mem::take(&mut self.lock().unsaved)
+    }
+
+    /// Open client connections, oldest first.
+    pub fn open_connections(
+        &self,
+        now: Instant
+    ) -> Vec<OpenConnection> {
+        self.lock()
+            .open
+            .iter()
+            .map(|(connection, open)| OpenConnection {
+                connection: *connection,
+                peer: open.peer,
+                sources: open.sources.clone(),
+                open_secs: now.saturating_duration_since(open.opened).as_secs()
+            })
+            .collect()
+    }
+
+    pub fn snapshot(
+        &self,
+        now: Instant
+    ) -> Vec<SourceStats> {
+        let age = |at: Option<Instant>| at.map(|at| now.saturating_duration_since(at).as_secs());
+
+        self.lock()
+            .sources
+            .iter()
+            .map(|(source, entry)| SourceStats {
+                source: source.clone(),
+                registered: entry.registered,
+                connections: entry.connections,
+                events: entry.events,
+                heartbeats: entry.heartbeats,
+                parse_failures: entry.parse_failures,
+                ignored_deliveries: entry.ignored_deliveries,
+                last_heartbeat_secs_ago: age(entry.last_heartbeat),
+                last_event_secs_ago: age(entry.last_event),
+                silent: entry.silent,
+                queue_map: entry.queue_map
+            })
+            .collect()
+    }
+
+    fn touch(
+        &self,
+        source: &str,
+        now: Instant,
+        update: impl FnOnce(&mut SourceEntry)
+    ) {
+        let mut inner = self.lock();
+        let entry =
+            inner.sources.entry(source.to_string()).or_insert_with(|| SourceEntry::new(now));
+
+        let recovered = entry.silent.then(|| now.saturating_duration_since(entry.last_seen));
+        entry.silent = false;
+        entry.last_seen = now;
+        update(entry);
+
+        if let Some(silent) = recovered {
+            let event =
+                self.event(source, "recovered", Some(format!("silent_secs={}", silent.as_secs())));
+            inner.push_event(event, self.persist_events);
+        }
+    }
+
+    fn record_transition(
+        &self,
+        source: &str,
+        transition: &str,
+        detail: Option<String>
+    ) {
+        let event = self.event(source, transition, detail);
+        self.lock().push_event(event, self.persist_events);
+    }
+
+    fn event(
+        &self,
+        source: &str,
+        transition: &str,
+        detail: Option<String>
+    ) -> SourceEvent {
+        SourceEvent {
+            source: source.to_string(),
+            transition: transition.to_string(),
+            detail,
+            at_unix: self.clock.unix_secs()
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RegistryState> {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Sources seen on one client connection; they are disconnected from the
+/// registry when the connection ends, whichever way it ends.
+pub struct ConnectedSources {
+    registry: Arc<SourceRegistry>,
+    owner: Owner,
+    sources: Vec<String>
+}
+
+impl ConnectedSources {
+    pub fn new(
+        registry: Arc<SourceRegistry>,
+        peer: SocketAddr
+    ) -> Self {
+        let connection = registry.next_connection.fetch_add(1, Ordering::Relaxed);
+        let opened = registry.clock.now();
+        registry.lock().open.insert(connection, OpenClient { peer, opened, sources: Vec::new() });
+        let owner = Owner { connection, peer, closed: CancellationToken::new() };
+        Self { registry, owner, sources: Vec::new() }
+    }
+
+    /// Records a `register` frame for `source` on this connection.
+    pub fn register(
+        &mut self,
+        source: &str,
+        now: Instant
+    ) {
+        self.seen(source, now);
+        self.registry.record_register(source, now, &self.owner);
+    }
+
+    /// Completes once another connection took over a source registered on
+    /// this one; the connection should then be closed.
+    pub async fn taken_over(&self) {
+        self.owner.closed.cancelled().await;
+    }
+
+    pub fn seen(
+        &mut self,
+        source: &str,
+        now: Instant
+    ) {
+        if !self.sources.iter().any(|seen| seen == source) {
+            self.registry.record_connect(source, now, self.owner.connection);
+            self.sources.push(source.to_string());
+        }
+    }
+}
+
+impl Drop for ConnectedSources {
+    fn drop(&mut self) {
+        for source in &self.sources {
+            self.registry.record_disconnect(source, self.owner.connection);
+        }
+        self.registry.lock().open.remove(&self.owner.connection);
+    }
+}
+
+/// Periodically warns about registered sources that went silent.
+pub async fn run_source_monitor(state: AppState) {
+    let silent_after = state.sources.silent_after;
+    let mut ticker =
+        interval((silent_after / 4).clamp(Duration::from_secs(1), Duration::from_secs(60)));
+
+    loop {
+        tokio::select! {
+            _ = state.shutdown.cancelled() => {
+                save_source_events(&state).await;
+                info!("source monitor stopping");
+                break;
+            }
+            _ = ticker.tick() => {
+                for (source, idle) in state.sources.mark_silent(state.clock.now()) {
+                    coded_warn!(
+                        ErrorCode::SourceSilent,
+                        "registered source stopped reporting: source={}, silent_secs={}, threshold_secs={}",
+                        source,
+                        idle.as_secs(),
+                        silent_after.as_secs()
+                    );
+                }
+                save_source_events(&state).await;
+            }
+        }
+    }
+}
+
+/// Writes queued transitions to `source_events`. A failed write is logged
+/// and dropped; the transitions stay in the in-memory list.
+async fn save_source_events(state: &AppState) {
+    let events = state.sources.take_unsaved_events();
+    if events.is_empty() {
+        return;
+    }
+    if let Err(err) = state.db.insert_source_events(&events).await {
+        warn!("source events not saved: events={}, error={err:#}", events.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use bouncer_helpers::clock::system_clock;
+    use bouncer_proto::heartbeat::QueueMapOccupancy;
+
+    use super::{ConnectedSources, SourceRegistry};
+
+    fn peer(port: u16) -> SocketAddr {
+        SocketAddr::from(([192, 0, 2, 1], port))
+    }
+
+    #[test]
+    fn flags_silent_registered_source_once_and_recovers() {
+        let start = Instant::now();
+        let registry = Arc::new(SourceRegistry::new(Duration::from_secs(300), system_clock()));
+
+        ConnectedSources::new(registry.clone(), peer(1)).register("mail-01", start);
+        registry.record_event("mail-02", start);
+        registry.record_ignored_delivery("mail-02", start);
+        registry.record_heartbeat("mail-01", start + Duration::from_secs(30), None);
+
+        let later = start + Duration::from_secs(400);
+        let silent = registry.mark_silent(later);
+        assert_eq!(silent.len(), 1);
+        assert_eq!(silent[0].0, "mail-01");
+        assert!(registry.mark_silent(later).is_empty());
+
+        let occupancy = QueueMapOccupancy { entries: 12, capacity: 100, evicted: 0 };
+        registry.record_heartbeat("mail-01", later, Some(occupancy));
+        let stats = registry.snapshot(later);
+        let mail_01 = stats.iter().find(|s| s.source == "mail-01").expect("mail-01 tracked");
+        assert!(!mail_01.silent);
+        assert_eq!(mail_01.heartbeats, 2);
+        assert_eq!(mail_01.last_heartbeat_secs_ago, Some(0));
+        assert_eq!(mail_01.queue_map, Some(occupancy));
+        let mail_02 = stats.iter().find(|s| s.source == "mail-02").expect("mail-02 tracked");
+        assert_eq!((mail_02.events, mail_02.ignored_deliveries), (2, 1));
+        assert_eq!(mail_02.last_event_secs_ago, Some(400));
+    }
+
+    #[test]
+    fn records_connect_disconnect_expiry_and_recovery() {
+        let start = Instant::now();
+        let registry = Arc::new(
+            SourceRegistry::new(Duration::from_secs(60), system_clock()).persisting_events(true)
+        );
+
+        let mut first = ConnectedSources::new(registry.clone(), peer(1));
+        first.seen("mail-01", start);
+        first.seen("mail-01", start);
+        first.register("mail-01", start);
+        let mut second = ConnectedSources::new(registry.clone(), peer(2));
+        second.seen("mail-01", start);
+        assert_eq!(registry.snapshot(start)[0].connections, 2);
+        let open: Vec<_> = registry
+            .open_connections(Instant::now())
+            .into_iter()
+            .map(|open| (open.peer.port(), open.sources))
+            .collect();
+        assert_eq!(open, [(1, vec!["mail-01".to_string()]), (2, vec!["mail-01".to_string()])]);
+        drop(first);
+        drop(second);
+        assert!(registry.open_connections(Instant::now()).is_empty());
+
+        let later = start + Duration::from_secs(90);
+        registry.mark_silent(later);
+        registry.record_heartbeat("mail-01", later, None);
+
+        let transitions = |events: Vec<bouncer_proto::query::SourceEvent>| {
+            events.into_iter().map(|event| event.transition).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            transitions(registry.recent_events(10)),
+            ["recovered", "expired", "disconnected", "registered", "connected"]
+        );
+        assert_eq!(registry.take_unsaved_events().len(), 5);
+        assert!(registry.take_unsaved_events().is_empty());
+        assert_eq!(transitions(registry.recent_events(1)), ["recovered"]);
+    }
+
+    #[tokio::test]
+    async fn register_on_a_new_connection_takes_the_source_over() {
+        let start = Instant::now();
+        let registry = Arc::new(
+            SourceRegistry::new(Duration::from_secs(60), system_clock()).with_takeover(true)
+        );
+
+        let mut stale = ConnectedSources::new(registry.clone(), peer(1));
+        stale.register("mail-01", start);
+        registry.record_event("mail-01", start);
+        stale.register("mail-01", start);
+
+        let mut fresh = ConnectedSources::new(registry.clone(), peer(2));
+        fresh.register("mail-01", start);
+        tokio::time::timeout(Duration::from_secs(1), stale.taken_over())
+            .await
+            .expect("stale connection told to close");
+        assert_eq!(registry.snapshot(start)[0].connections, 2);
+        drop(stale);
+
+        let stats = registry.snapshot(start);
+        assert_eq!((stats[0].connections, stats[0].events), (1, 1));
+        let events = registry.recent_events(10);
+        let transitions: Vec<_> = events.iter().map(|event| event.transition.as_str()).collect();
+        assert_eq!(
+            transitions,
+            ["registered", "taken_over", "registered", "registered", "connected"]
+        );
+        assert_eq!(
+            events[1].detail.as_deref(),
+            Some("previous_peer=192.0.2.1:1, peer=192.0.2.1:2")
+        );
+
+        // The new owner is not closed by its own registrations, nor by the
+        // stale connection going away.
+        fresh.register("mail-01", start);
+        assert!(tokio::time::timeout(Duration::from_millis(20), fresh.taken_over()).await.is_err());
+    }
+}
@@ -0,0 +1,896 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use bouncer_helpers::clock::SharedClock;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use tokio::io::{AsyncRead, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use uuid::timestamp::context::NoContext;
+use uuid::{Timestamp, Uuid};
+
+use super::audit::strip_audit_headers;
+use super::failures::FailureNote;
+use super::faults::Faults;
+use super::lanes::Lane;
+use super::storage::{LocalStorage, SharedSpoolStorage};
+use super::tenants;
+use crate::config::SpoolLayout;
+
+/// Extension of quarantined `observer_event` bodies; the decode error sits
+/// next to each one with [`QUARANTINE_NOTE_EXTENSION`].
+const QUARANTINE_EXTENSION: &str = "json";
+const QUARANTINE_NOTE_EXTENSION: &str = "error";
+
+/// Suffix of the [`FailureNote`] kept for each file in `failed/`.
+pub const FAILURE_NOTE_SUFFIX: &str = ".reason.json";
+
+/// Suffix of spool files gzipped in `done/` by `spool_compress_done`.
+pub const GZIP_SUFFIX: &str = ".gz";
+
+/// Batches an incoming scan keeps buffered ahead of its consumer.
+const SCAN_BATCHES_BUFFERED: usize = 4;
+
+/// Starts the Maildir info (`:2,<flags>`) of files in `cur/`.
+const MAILDIR_INFO_SEPARATOR: &str = ":2,";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpoolCounts {
+    pub incoming: u64,
+    pub processing: u64,
+    pub done: u64,
+    pub failed: u64
+}
+
+#[derive(Debug, Clone)]
+pub struct Spool {
+    pub root: PathBuf,
+    pub incoming: PathBuf,
+    pub processing: PathBuf,
+    pub done: PathBuf,
+    pub failed: PathBuf,
+    /// Undecodable `observer_event` bodies waiting for a replay.
+    pub quarantine: PathBuf,
+    layout: SpoolLayout,
+    partition_by_source: bool,
+    compress_done: bool,
+    storage: SharedSpoolStorage,
+    clock: SharedClock,
+    faults: Arc<Faults>
+}
+
+impl Spool {
+    pub fn new(
+        root: PathBuf,
+        clock: SharedClock,
+        faults: Arc<Faults>
+    ) -> Self {
+        Self {
+            incoming: root.join("incoming"),
+            processing: root.join("processing"),
+            done: root.join("done"),
+            failed: root.join("failed"),
+            quarantine: root.join("quarantine"),
+            root,
+            layout: SpoolLayout::Classic,
+            partition_by_source: false,
+            compress_done: false,
+            storage: Arc::new(LocalStorage),
+            clock,
+            faults
+        }
+    }
+
+    /// Uses `new/`, `cur/`, `.Done/cur/` and `.Failed/cur/` for the spool
+    /// states under [`SpoolLayout::Maildir`]; `quarantine/` stays as is.
+    pub fn with_layout(
+        mut self,
+        layout: SpoolLayout
+    ) -> Self {
+        let (incoming, processing, done, failed) = match layout {
+            SpoolLayout::Classic => ("incoming", "processing", "done", "failed"),
+            SpoolLayout::Maildir => ("new", "cur", ".Done/cur", ".Failed/cur")
+        };
+        self.incoming = self.root.join(incoming);
+        self.processing = self.root.join(processing);
+        self.done = self.root.join(done);
+        self.failed = self.root.join(failed);
+        self.layout = layout;
+        self
+    }
+
+    /// Spools mail under `incoming/<source>/` instead of `incoming/`; the
+    /// same subdirectory is kept through `processing/`, `done/` and `failed/`.
+    pub fn partitioned_by_source(
+        mut self,
+        enabled: bool
+    ) -> Self {
+        self.partition_by_source = enabled;
+        self
+    }
+
+    /// Gzips files once they reach `done/` (`<name>.eml.gz`).
+    pub fn compressing_done(
+        mut self,
+        enabled: bool
+    ) -> Self {
+        self.compress_done = enabled;
+        self
+    }
+
+    /// Publishes, moves and removes spool files through `storage` instead
+    /// of plain renames (see `spool_storage` in the config).
+    pub fn with_storage(
+        mut self,
+        storage: SharedSpoolStorage
+    ) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    pub fn storage_name(&self) -> &'static str {
+        self.storage.name()
+    }
+
+    /// Restores the files of `incoming/`, `processing/` and `quarantine/`
+    /// that the storage backend holds but the local disk lost; call it
+    /// before [`Self::requeue_processing`].
+    pub async fn restore(&self) -> Result<usize> {
+        let dirs = [self.incoming.as_path(), self.processing.as_path(), self.quarantine.as_path()];
+        self.storage.restore(&dirs).await.context("failed to restore spool files from storage")
+    }
+
+    pub fn compresses_done(&self) -> bool {
+        self.compress_done
+    }
+
+    pub async fn ensure_dirs(&self) -> Result<()> {
+        for dir in [
+            &self.root,
+            &self.incoming,
+            &self.processing,
+            &self.done,
+            &self.failed,
+            &self.quarantine
+        ] {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .with_context(|| format!("failed to create dir {}", dir.display()))?;
+        }
+        if self.layout == SpoolLayout::Maildir {
+            // Every Maildir needs all of `tmp/`, `new/` and `cur/`.
+            for maildir in [&self.root, &self.root.join(".Done"), &self.root.join(".Failed")] {
+                for sub in ["tmp", "new", "cur"] {
+                    let dir = maildir.join(sub);
+                    tokio::fs::create_dir_all(&dir)
+                        .await
+                        .with_context(|| format!("failed to create dir {}", dir.display()))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves `.eml` files left in `processing/` by an interrupted run back to
+    /// `incoming/` and returns how many were requeued.
+    pub async fn requeue_processing(&self) -> Result<usize> {
+        let mut requeued = 0;
+        for path in eml_files(&self.processing).await? {
+            let target = self.relocate(&path, &self.processing, &self.incoming).await?;
+            self.rename(&path, &target).await.with_context(|| {
+                format!("failed to requeue {} -> {}", path.display(), target.display())
+            })?;
+            requeued += 1;
+        }
+        Ok(requeued)
+    }
+
+    /// Counts `.eml` files in `incoming/`, `processing/`, `done/` and `failed/`;
+    /// gzipped files count in `done/`.
+    pub async fn counts(&self) -> Result<SpoolCounts> {
+        Ok(SpoolCounts {
+            incoming: eml_files(&self.incoming).await?.len() as u64,
+            processing: eml_files(&self.processing).await?.len() as u64,
+            done: archived_files(&self.done).await?.len() as u64,
+            failed: eml_files(&self.failed).await?.len() as u64
+        })
+    }
+
+    /// Replaces a file in `done/` with `<name>.gz` and returns the new path.
+    /// The plain file is removed only after the gzipped copy is synced.
+    pub async fn compress_done_file(
+        &self,
+        path: &Path
+    ) -> Result<PathBuf> {
+        let mut gz_name = path.file_name().context("spool path has no file name")?.to_os_string();
+        gz_name.push(GZIP_SUFFIX);
+        let gz_path = path.with_file_name(gz_name);
+        let mut tmp_name = gz_path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = gz_path.with_file_name(tmp_name);
+
+        let plain = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let tmp_file = TmpFileGuard::new(&tmp_path);
+        let tmp = tmp_path.clone();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let file = std::fs::File::create(&tmp)
+                .with_context(|| format!("failed to create {}", tmp.display()))?;
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            encoder
+                .write_all(&plain)
+                .with_context(|| format!("failed to write {}", tmp.display()))?;
+            let file =
+                encoder.finish().with_context(|| format!("failed to write {}", tmp.display()))?;
+            file.sync_all().with_context(|| format!("failed to fsync {}", tmp.display()))
+        })
+        .await
+        .context("gzip task failed")??;
+
+        self.publish(&tmp_path, &gz_path).await.with_context(|| {
+            format!("failed to rename {} -> {}", tmp_path.display(), gz_path.display())
+        })?;
+        tmp_file.published();
+        self.remove(path).await.with_context(|| format!("failed to remove {}", path.display()))?;
+        Ok(gz_path)
+    }
+
+    /// Rewrites `path` with `headers` in front of its content, replacing an
+    /// audit header block an earlier pass left there.
+    pub async fn prepend_headers(
+        &self,
+        path: &Path,
+        headers: &str
+    ) -> Result<()> {
+        let dir = path.parent().context("spool path has no parent")?;
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("spool path has no file name")?;
+        let body = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut payload = headers.as_bytes().to_vec();
+        payload.extend_from_slice(strip_audit_headers(&body));
+        self.write_synced(dir, file_name, &payload).await?;
+        Ok(())
+    }
+
+    /// `.eml` files waiting in `incoming/` and its source subdirectories.
+    pub async fn incoming_files(&self) -> Result<Vec<PathBuf>> {
+        eml_files(&self.incoming).await
+    }
+
+    /// Streams the `.eml` files of `incoming/` in batches of up to
+    /// `batch_size`. The top directory and each source subdirectory are read
+    /// by their own task; the walk stops once the receiver is dropped, so a
+    /// consumer can take part of a large backlog without listing all of it.
+    pub fn incoming_batches(
+        &self,
+        batch_size: usize
+    ) -> mpsc::Receiver<Result<Vec<PathBuf>>> {
+        let (tx, rx) = mpsc::channel(SCAN_BATCHES_BUFFERED);
+        let dir = self.incoming.clone();
+        tokio::spawn(async move {
+            let batch_size = batch_size.max(1);
+            let mut subdirs = Vec::new();
+            if let Err(err) = send_eml_batches(&dir, batch_size, &tx, Some(&mut subdirs)).await {
+                let _ = tx.send(Err(err)).await;
+            }
+
+            let mut walkers = JoinSet::new();
+            for subdir in subdirs {
+                let tx = tx.clone();
+                walkers.spawn(async move {
+                    if let Err(err) = send_eml_batches(&subdir, batch_size, &tx, None).await {
+                        let _ = tx.send(Err(err)).await;
+                    }
+                });
+            }
+            while walkers.join_next().await.is_some() {}
+        });
+        rx
+    }
+
+    /// Maps `path` under `from_dir` to the same relative path under `to_dir`
+    /// (keeping a source subdirectory) and creates the target's parent. In a
+    /// Maildir the file name gets the flags of `to_dir` instead of its own.
+    pub async fn relocate(
+        &self,
+        path: &Path,
+        from_dir: &Path,
+        to_dir: &Path
+    ) -> Result<PathBuf> {
+        let relative = path
+            .strip_prefix(from_dir)
+            .with_context(|| format!("{} is not inside {}", path.display(), from_dir.display()))?;
+        let mut target = to_dir.join(relative);
+        if self.layout == SpoolLayout::Maildir {
+            let name = mail_name(&target).context("spool path has no file name")?;
+            target = target.with_file_name(format!("{name}{}", self.maildir_info(to_dir)));
+        }
+        if let Some(parent) = target.parent()
+            && parent != to_dir
+        {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("failed to create dir {}", parent.display()))?;
+        }
+        Ok(target)
+    }
+
+    pub async fn enqueue_mail(
+        &self,
+        payload: &[u8],
+        lane: Lane,
+        source: Option<&str>,
+        tenant: Option<&str>
+    ) -> Result<PathBuf> {
+        self.enqueue_mail_stream(payload, tokio::io::empty(), 0, lane, source, tenant).await
+    }
+
+    /// Enqueues `head` followed by `body_len` bytes streamed from `body`,
+    /// without holding the mail in memory. A body that ends early fails the
+    /// enqueue and leaves no file behind. `tenant` goes into the file name.
+    pub async fn enqueue_mail_stream<R: AsyncRead + Unpin>(
+        &self,
+        head: &[u8],
+        body: R,
+        body_len: u64,
+        lane: Lane,
+        source: Option<&str>,
+        tenant: Option<&str>
+    ) -> Result<PathBuf> {
+        let file_name = lane.file_name(&tenants::spool_id(&self.next_id().to_string(), tenant));
+
+        let dir = match source.filter(|_| self.partition_by_source).and_then(partition_name) {
+            Some(partition) => {
+                let dir = self.incoming.join(partition);
+                tokio::fs::create_dir_all(&dir)
+                    .await
+                    .with_context(|| format!("failed to create dir {}", dir.display()))?;
+                dir
+            }
+            None => self.incoming.clone()
+        };
+        self.write_synced_from(&dir, &file_name, head, body, body_len).await
+    }
+
+    /// Keeps an `observer_event` body that could not be decoded, with `note`
+    /// (the decode error) in a sibling `.error` file, until it is replayed.
+    pub async fn quarantine_event(
+        &self,
+        body: &[u8],
+        note: &str
+    ) -> Result<PathBuf> {
+        let id = self.next_id();
+        self.write_synced(
+            &self.quarantine,
+            &format!("{id}.{QUARANTINE_NOTE_EXTENSION}"),
+            note.as_bytes()
+        )
+        .await?;
+        self.write_synced(&self.quarantine, &format!("{id}.{QUARANTINE_EXTENSION}"), body).await
+    }
+
+    /// Quarantined event bodies, oldest first.
+    pub async fn quarantined_events(&self) -> Result<Vec<PathBuf>> {
+        let mut entries = tokio::fs::read_dir(&self.quarantine)
+            .await
+            .with_context(|| format!("failed to read dir {}", self.quarantine.display()))?;
+        let mut files = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .with_context(|| format!("failed to read dir {}", self.quarantine.display()))?
+        {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some(QUARANTINE_EXTENSION) {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Drops a replayed event body and its note.
+    pub async fn release_quarantined(
+        &self,
+        path: &Path
+    ) -> Result<()> {
+        self.remove(path).await.with_context(|| format!("failed to remove {}", path.display()))?;
+        match self.remove(&path.with_extension(QUARANTINE_NOTE_EXTENSION)).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("failed to remove note of {}", path.display()))
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Where the note of a file in `failed/` lives: next to it as
+    /// `<name>.reason.json`, or under `.Failed/reasons/` with
+    /// [`SpoolLayout::Maildir`], whose `cur/` must hold mail only.
+    pub fn failure_note_path(
+        &self,
+        failed_path: &Path
+    ) -> Result<PathBuf> {
+        let relative = failed_path.strip_prefix(&self.failed).with_context(|| {
+            format!("{} is not inside {}", failed_path.display(), self.failed.display())
+        })?;
+        let name = mail_name(failed_path).context("spool path has no file name")?;
+        let notes = match self.layout {
+            SpoolLayout::Classic => self.failed.clone(),
+            SpoolLayout::Maildir => self.root.join(".Failed").join("reasons")
+        };
+        Ok(notes.join(relative).with_file_name(format!("{name}{FAILURE_NOTE_SUFFIX}")))
+    }
+
+    pub async fn write_failure_note(
+        &self,
+        failed_path: &Path,
+        note: &FailureNote
+    ) -> Result<PathBuf> {
+        let path = self.failure_note_path(failed_path)?;
+        let dir = path.parent().context("failure note path has no parent")?;
+        tokio::fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("failed to create dir {}", dir.display()))?;
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        self.write_synced(dir, file_name, &serde_json::to_vec(note)?).await
+    }
+
+    /// `None` for files that failed before notes were written.
+    pub async fn read_failure_note(
+        &self,
+        failed_path: &Path
+    ) -> Result<Option<FailureNote>> {
+        let path = self.failure_note_path(failed_path)?;
+        match tokio::fs::read(&path).await {
+            Ok(body) => serde_json::from_slice(&body)
+                .map(Some)
+                .with_context(|| format!("invalid failure note {}", path.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display()))
+        }
+    }
+
+    /// Drops the note of a file leaving `failed/`.
+    pub async fn remove_failure_note(
+        &self,
+        failed_path: &Path
+    ) -> Result<()> {
+        let path = self.failure_note_path(failed_path)?;
+        match self.remove(&path).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("failed to remove {}", path.display()))
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Maildir info for files in `dir`: processed mail is seen, failed mail
+    /// is flagged, and `new/` files carry none.
+    fn maildir_info(
+        &self,
+        dir: &Path
+    ) -> String {
+        let flags = if dir == self.done {
+            "S"
+        } else if dir == self.failed {
+            "F"
+        } else if dir == self.processing {
+            ""
+        } else {
+            return String::new();
+        };
+        format!("{MAILDIR_INFO_SEPARATOR}{flags}")
+    }
+
+    fn next_id(&self) -> Uuid {
+        // v7 ids sort by creation time; take it from the shared clock so spool
+        // names stay deterministic under a manual clock.
+        let now = self.clock.system_now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Uuid::new_v7(Timestamp::from_unix(NoContext, now.as_secs(), now.subsec_nanos()))
+    }
+
+    /// Writes `payload` to `dir/file_name` through a fsynced `.tmp` file so
+    /// readers never see a partial file; a Maildir delivers through `tmp/`.
+    async fn write_synced(
+        &self,
+        dir: &Path,
+        file_name: &str,
+        payload: &[u8]
+    ) -> Result<PathBuf> {
+        self.write_synced_from(dir, file_name, payload, tokio::io::empty(), 0).await
+    }
+
+    /// [`Self::write_synced`] of `head` followed by `body_len` bytes of
+    /// `body`; the `.tmp` file is removed when the body falls short.
+    async fn write_synced_from<R: AsyncRead + Unpin>(
+        &self,
+        dir: &Path,
+        file_name: &str,
+        head: &[u8],
+        mut body: R,
+        body_len: u64
+    ) -> Result<PathBuf> {
+        let tmp_path = if self.layout == SpoolLayout::Maildir && dir == self.incoming {
+            self.root.join("tmp").join(file_name)
+        } else {
+            dir.join(format!("{file_name}.tmp"))
+        };
+        let final_path = dir.join(file_name);
+
+        let mut file = tokio::fs::File::create(&tmp_path)
+            .await
+            .with_context(|| format!("failed to create {}", tmp_path.display()))?;
+        // Also covers a caller that drops this future mid-write, such as an
+        // ingest deadline.
+        let tmp_file = TmpFileGuard::new(&tmp_path);
+
+        let written = async {
+            file.write_all(head).await?;
+            let copied = tokio::io::copy(&mut body, &mut file).await?;
+            if copied != body_len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("body ended after {copied} of {body_len} bytes")
+                ));
+            }
+            Ok(())
+        }
+        .await;
+        written.with_context(|| format!("failed to write {}", tmp_path.display()))?;
+
+        file.sync_all().await.with_context(|| format!("failed to fsync {}", tmp_path.display()))?;
+
+        drop(file);
+
+        self.publish(&tmp_path, &final_path).await.with_context(|| {
+            format!("failed to rename {} -> {}", tmp_path.display(), final_path.display())
+        })?;
+        tmp_file.published();
+
+        Ok(final_path)
+    }
+
+    /// Moves a file within the spool; every spool state transition goes
+    /// through here.
+    pub async fn rename(
+        &self,
+        from: &Path,
+        to: &Path
+    ) -> io::Result<()> {
+        self.faults.check_rename()?;
+        self.storage.transition(from, to).await
+    }
+
+    /// Makes a written `.tmp` file visible under its final name.
+    async fn publish(
+        &self,
+        tmp: &Path,
+        path: &Path
+    ) -> io::Result<()> {
+        self.faults.check_rename()?;
+        self.storage.publish(tmp, path).await
+    }
+
+    /// Deletes a spool file, from the storage backend too.
+    pub async fn remove(
+        &self,
+        path: &Path
+    ) -> io::Result<()> {
+        self.storage.remove(path).await
+    }
+}
+
+/// Removes a `.tmp` file when dropped before [`Self::published`], so a write
+/// that fails or is cancelled leaves no partial file behind.
+struct TmpFileGuard<'a> {
+    path: &'a Path,
+    published: bool
+}
+
+impl<'a> TmpFileGuard<'a> {
+    fn new(path: &'a Path) -> Self {
+        Self { path, published: false }
+    }
+
+    fn published(mut self) {
+        self.published = true;
+    }
+}
+
+impl Drop for TmpFileGuard<'_> {
+    fn drop(&mut self) {
+        if !self.published {
+            let _ = std::fs::remove_file(self.path);
+        }
+    }
+}
+
+/// Directory name for `source` under a spool state directory: characters
+/// outside `[A-Za-z0-9._-]` become `_`, and names that could escape the
+/// directory or hide as dotfiles are rejected.
+pub fn partition_name(source: &str) -> Option<String> {
+    let name: String = source
+        .trim()
+        .chars()
+        .take(64)
+        .map(
+            |ch| if ch.is_ascii_alphanumeric() || matches!(ch, '.' | '_' | '-') { ch } else { '_' }
+        )
+        .collect();
+    (!name.is_empty() && !name.starts_with('.')).then_some(name)
+}
+
+/// File name of a spool path without its Maildir info, if any.
+pub fn mail_name(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    Some(name.split_once(MAILDIR_INFO_SEPARATOR).map_or(name, |(name, _)| name))
+}
+
+/// `.eml` files directly in `dir` and in its immediate subdirectories (source
+/// partitions).
+pub async fn eml_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    spool_files(dir, |path| mail_name(path).is_some_and(|name| name.ends_with(".eml"))).await
+}
+
+/// Like [`eml_files`], plus files gzipped by `spool_compress_done`.
+pub async fn archived_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    spool_files(dir, |path| {
+        mail_name(path).is_some_and(|name| name.ends_with(".eml") || name.ends_with(".eml.gz"))
+    })
+    .await
+}
+
+async fn spool_files(
+    dir: &Path,
+    accept: impl Fn(&Path) -> bool
+) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![(dir.to_path_buf(), true)];
+
+    while let Some((dir, descend)) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("failed to read dir {}", dir.display()))?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .with_context(|| format!("failed to read dir {}", dir.display()))?
+        {
+            let path = entry.path();
+            if accept(&path) {
+                files.push(path);
+            } else if descend && entry.file_type().await.is_ok_and(|kind| kind.is_dir()) {
+                dirs.push((path, false));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Sends the `.eml` files directly in `dir` to `tx` in batches and, with
+/// `subdirs`, collects the subdirectories to walk next. Returns early once
+/// `tx` is closed.
+async fn send_eml_batches(
+    dir: &Path,
+    batch_size: usize,
+    tx: &mpsc::Sender<Result<Vec<PathBuf>>>,
+    mut subdirs: Option<&mut Vec<PathBuf>>
+) -> Result<()> {
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("failed to read dir {}", dir.display()))?;
+    let mut batch = Vec::with_capacity(batch_size);
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .with_context(|| format!("failed to read dir {}", dir.display()))?
+    {
+        if tx.is_closed() {
+            return Ok(());
+        }
+        let path = entry.path();
+        if mail_name(&path).is_some_and(|name| name.ends_with(".eml")) {
+            batch.push(path);
+            if batch.len() >= batch_size && tx.send(Ok(std::mem::take(&mut batch))).await.is_err() {
+                return Ok(());
+            }
+        } else if let Some(subdirs) = subdirs.as_deref_mut()
+            && entry.file_type().await.is_ok_and(|kind| kind.is_dir())
+        {
+            subdirs.push(path);
+        }
+    }
+    if !batch.is_empty() {
+        let _ = tx.send(Ok(batch)).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bouncer_helpers::clock::system_clock;
+    use flate2::read::GzDecoder;
+    use uuid::Uuid;
+
+    use super::Spool;
+    use crate::config::SpoolLayout;
+    use crate::core::failures::FailureNote;
+    use crate::core::lanes::Lane;
+    use crate::core::tenants::tenant_of_path;
+    use crate::core::{Faults, ParserError};
+
+    #[tokio::test]
+    async fn compresses_done_file_and_still_counts_it() {
+        let root = std::env::temp_dir().join(format!("bouncer-spool-{}", Uuid::now_v7()));
+        let spool = Spool::new(root.clone(), system_clock(), Arc::new(Faults::default()))
+            .compressing_done(true);
+        spool.ensure_dirs().await.unwrap();
+        let done_path = spool.done.join("a.eml");
+        let mail = b"Subject: bounce\r\n\r\nundeliverable\r\n".repeat(50);
+        tokio::fs::write(&done_path, &mail).await.unwrap();
+
+        let gz_path = spool.compress_done_file(&done_path).await.unwrap();
+        assert_eq!(gz_path, spool.done.join("a.eml.gz"));
+        assert!(!done_path.exists());
+        let compressed = tokio::fs::read(&gz_path).await.unwrap();
+        assert!(compressed.len() < mail.len() / 4);
+        let mut plain = Vec::new();
+        GzDecoder::new(compressed.as_slice()).read_to_end(&mut plain).unwrap();
+        assert_eq!(plain, mail);
+        assert_eq!(spool.counts().await.unwrap().done, 1);
+
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+
+    #[tokio::test]
+    async fn streamed_mail_is_written_whole_or_not_at_all() {
+        let root = std::env::temp_dir().join(format!("bouncer-stream-{}", Uuid::now_v7()));
+        let spool = Spool::new(root.clone(), system_clock(), Arc::new(Faults::default()));
+        spool.ensure_dirs().await.unwrap();
+
+        let body = b"undeliverable\r\n".repeat(10_000);
+        let path = spool
+            .enqueue_mail_stream(
+                b"Subject: bounce\r\n\r\n",
+                &body[..],
+                body.len() as u64,
+                Lane::High,
+                None,
+                Some("shop")
+            )
+            .await
+            .unwrap();
+        let written = tokio::fs::read(&path).await.unwrap();
+        assert!(written.starts_with(b"Subject: bounce\r\n\r\nundeliverable"));
+        assert_eq!(written.len(), 19 + body.len());
+        assert_eq!(tenant_of_path(&path).as_deref(), Some("shop"));
+
+        let err = spool
+            .enqueue_mail_stream(b"Subject: cut\r\n", &body[..100], 200, Lane::High, None, None)
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("body ended after 100 of 200 bytes"), "{err:#}");
+        assert_eq!(std::fs::read_dir(&spool.incoming).unwrap().count(), 1);
+
+        // A sender that stalls until the caller gives up.
+        let (_sender, stalled) = tokio::io::duplex(64);
+        let enqueue =
+            spool.enqueue_mail_stream(b"Subject: slow\r\n", stalled, 200, Lane::High, None, None);
+        assert!(tokio::time::timeout(Duration::from_millis(50), enqueue).await.is_err());
+        assert_eq!(std::fs::read_dir(&spool.incoming).unwrap().count(), 1);
+
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+
+    #[tokio::test]
+    async fn maildir_layout_flags_files_through_their_states() {
+        let root = std::env::temp_dir().join(format!("bouncer-maildir-{}", Uuid::now_v7()));
+        let spool = Spool::new(root.clone(), system_clock(), Arc::new(Faults::default()))
+            .with_layout(SpoolLayout::Maildir);
+        spool.ensure_dirs().await.unwrap();
+        for dir in ["tmp", "new", "cur", ".Done/tmp", ".Done/new", ".Failed/cur"] {
+            assert!(root.join(dir).is_dir(), "{dir}");
+        }
+
+        let incoming =
+            spool.enqueue_mail(b"Subject: bounce\r\n", Lane::High, None, None).await.unwrap();
+        assert_eq!(incoming.parent(), Some(root.join("new").as_path()));
+        assert_eq!(std::fs::read_dir(root.join("tmp")).unwrap().count(), 0);
+        let name = incoming.file_name().unwrap().to_str().unwrap().to_string();
+
+        let processing =
+            spool.relocate(&incoming, &spool.incoming, &spool.processing).await.unwrap();
+        assert_eq!(processing, root.join("cur").join(format!("{name}:2,")));
+        spool.rename(&incoming, &processing).await.unwrap();
+        assert_eq!(spool.counts().await.unwrap().processing, 1);
+
+        let failed = spool.relocate(&processing, &spool.processing, &spool.failed).await.unwrap();
+        assert_eq!(failed, root.join(".Failed/cur").join(format!("{name}:2,F")));
+        let done = spool.relocate(&processing, &spool.processing, &spool.done).await.unwrap();
+        assert_eq!(done, root.join(".Done/cur").join(format!("{name}:2,S")));
+
+        assert_eq!(spool.requeue_processing().await.unwrap(), 1);
+        assert_eq!(spool.incoming_files().await.unwrap(), vec![incoming]);
+
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+
+    #[tokio::test]
+    async fn failure_notes_follow_failed_files_without_counting_as_mail() {
+        let root = std::env::temp_dir().join(format!("bouncer-notes-{}", Uuid::now_v7()));
+        let spool = Spool::new(root.clone(), system_clock(), Arc::new(Faults::default()))
+            .partitioned_by_source(true);
+        spool.ensure_dirs().await.unwrap();
+        let incoming = spool
+            .enqueue_mail(b"Subject: bounce\r\n", Lane::High, Some("mail-01"), None)
+            .await
+            .unwrap();
+        let failed = spool.relocate(&incoming, &spool.incoming, &spool.failed).await.unwrap();
+        spool.rename(&incoming, &failed).await.unwrap();
+
+        let note = FailureNote::new(&anyhow::Error::new(ParserError::MissingHash), 1_700_000_000);
+        let note_path = spool.write_failure_note(&failed, &note).await.unwrap();
+        let name = failed.file_name().unwrap().to_str().unwrap();
+        assert_eq!(note_path, failed.with_file_name(format!("{name}.reason.json")));
+        assert_eq!(spool.read_failure_note(&failed).await.unwrap(), Some(note));
+        assert_eq!(spool.counts().await.unwrap().failed, 1);
+
+        spool.remove_failure_note(&failed).await.unwrap();
+        assert!(!note_path.exists());
+        assert_eq!(spool.read_failure_note(&failed).await.unwrap(), None);
+
+        let maildir = spool.clone().with_layout(SpoolLayout::Maildir);
+        let failed = maildir.failed.join("mail-01").join("a.eml:2,F");
+        assert_eq!(
+            maildir.failure_note_path(&failed).unwrap(),
+            root.join(".Failed/reasons/mail-01/a.eml.reason.json")
+        );
+
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+
+    #[tokio::test]
+    async fn streams_incoming_batches_across_partitions() {
+        let root = std::env::temp_dir().join(format!("bouncer-scan-{}", Uuid::now_v7()));
+        let spool = Spool::new(root.clone(), system_clock(), Arc::new(Faults::default()))
+            .partitioned_by_source(true);
+        spool.ensure_dirs().await.unwrap();
+        let mut expected = Vec::new();
+        for source in [None, None, None, Some("observer"), Some("observer"), Some("journal")] {
+            expected.push(
+                spool.enqueue_mail(b"Subject: bounce\r\n", Lane::High, source, None).await.unwrap()
+            );
+        }
+
+        let mut batches = spool.incoming_batches(2);
+        let mut seen = Vec::new();
+        while let Some(batch) = batches.recv().await {
+            let batch = batch.unwrap();
+            assert!(!batch.is_empty() && batch.len() <= 2);
+            seen.extend(batch);
+        }
+        seen.sort();
+        expected.sort();
+        assert_eq!(seen, expected);
+
+        // Dropping the receiver early stops the walk without errors.
+        let mut batches = spool.incoming_batches(1);
+        assert_eq!(batches.recv().await.unwrap().unwrap().len(), 1);
+        drop(batches);
+
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use bouncer_proto::status::{
+    ImapMailboxStatus, ImapPollStatus, ImapStatus, LatencyHistogram, ProcessingStatus,
+    ServerStatus, SpoolStatus, StageLatency, TenantStatus, WorkerLatency, WorkerStatus
+};
+
+use super::failures::FailureReason;
+use super::lanes::LaneGauge;
+use crate::app::AppState;
+
+/// Upper bounds of the processing latency buckets.
+const LATENCY_BOUNDS_MS: [u64; 12] = [1, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+/// Tenants counted separately; any further tenant is counted under
+/// [`OTHER_TENANTS`], so frames cannot grow the map without bound.
+const MAX_TENANTS: usize = 256;
+
+/// Key of the tenants past [`MAX_TENANTS`]; never a valid tenant name.
+const OTHER_TENANTS: &str = "(other)";
+
+/// Live counters of the dispatcher and the IMAP loop reported by
+/// `kind=status` frames. Spool and pool figures are read on demand instead.
+#[derive(Debug, Default)]
+pub struct RuntimeStatus {
+    lanes: OnceLock<LaneGauge>,
+    workers_total: AtomicU64,
+    workers_busy: AtomicU64,
+    imap: Mutex<Option<ImapStatus>>,
+    processing: Mutex<ProcessingStatus>,
+    tenants: Mutex<BTreeMap<String, TenantStatus>>
+}
+
+/// Where a worker spent its time on one spool file. Stages a file did not
+/// reach stay `None`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageTimings {
+    /// Reading the file from `processing/`.
+    pub read: Option<Duration>,
+    pub parse: Option<Duration>,
+    /// Message lookup, authentication, the bounce upsert and archiving.
+    pub upsert: Option<Duration>,
+    /// Moving the file to `done/` or `failed/`, compression included.
+    pub finalize: Option<Duration>,
+    /// From dequeue to the end, claiming the file from `incoming/` included.
+    pub total: Duration
+}
+
+impl StageTimings {
+    pub fn stages(&self) -> [(&'static str, Option<Duration>); 5] {
+        [
+            ("read", self.read),
+            ("parse", self.parse),
+            ("upsert", self.upsert),
+            ("finalize", self.finalize),
+            ("total", Some(self.total))
+        ]
+    }
+}
+
+impl RuntimeStatus {
+    /// Reports the depth of the dispatcher lanes from now on.
+    pub fn watch_lanes(
+        &self,
+        gauge: LaneGauge
+    ) {
+        let _ = self.lanes.set(gauge);
+    }
+
+    pub fn set_workers(
+        &self,
+        total: usize
+    ) {
+        self.workers_total.store(total as u64, Ordering::Relaxed);
+        let mut processing = self.processing_status();
+        processing.stages = StageTimings::default()
+            .stages()
+            .into_iter()
+            .map(|(stage, _)| StageLatency {
+                stage: stage.to_string(),
+                latency: LatencyHistogram::new(&LATENCY_BOUNDS_MS)
+            })
+            .collect();
+        processing.workers = (0..total as u64)
+            .map(|worker| WorkerLatency {
+                worker,
+                latency: LatencyHistogram::new(&LATENCY_BOUNDS_MS)
+            })
+            .collect();
+    }
+
+    /// Counts a worker as busy until the returned guard drops.
+    pub fn busy_worker(&self) -> BusyWorker<'_> {
+        self.workers_busy.fetch_add(1, Ordering::Relaxed);
+        BusyWorker { status: self }
+    }
+
+    /// Marks IMAP polling of `mailbox` as enabled, before its first poll
+    /// finishes.
+    pub fn imap_enabled(
+        &self,
+        account: &str,
+        mailbox: &str
+    ) {
+        let mut imap = self.imap_status();
+        mailbox_status(imap.get_or_insert_with(empty_imap_status), account, mailbox);
+    }
+
+    pub fn record_imap_poll(
+        &self,
+        poll: ImapPollStatus
+    ) {
+        let mut imap = self.imap_status();
+        let imap = imap.get_or_insert_with(empty_imap_status);
+        let failed = u64::from(poll.error.is_some());
+        imap.polls += 1;
+        imap.failed_polls += failed;
+
+        let mailbox = mailbox_status(imap, &poll.account, &poll.mailbox);
+        mailbox.polls += 1;
+        mailbox.failed_polls += failed;
+        mailbox.last_poll = Some(poll.clone());
+        imap.last_poll = Some(poll);
+    }
+
+    /// Adds one processed file of `worker` to the latency histograms.
+    pub fn record_processing(
+        &self,
+        worker: usize,
+        timings: &StageTimings,
+        slow: bool
+    ) {
+        let mut processing = self.processing_status();
+        for (latency, (_, elapsed)) in processing.stages.iter_mut().zip(timings.stages()) {
+            if let Some(elapsed) = elapsed {
+                latency.latency.observe(elapsed.as_millis() as u64);
+            }
+        }
+
+        if let Some(latency) = processing.workers.get_mut(worker) {
+            latency.latency.observe(timings.total.as_millis() as u64);
+        }
+        processing.slow_files += u64::from(slow);
+    }
+
+    pub fn record_failure(
+        &self,
+        reason: FailureReason
+    ) {
+        *self
+            .processing_status()
+            .failed_by_reason
+            .entry(reason.as_str().to_string())
+            .or_default() += 1;
+    }
+
+    /// Bumps a counter of `tenant`; frames and files without one are not
+    /// counted. Past [`MAX_TENANTS`] tenants, new ones share [`OTHER_TENANTS`].
+    pub fn count_tenant(
+        &self,
+        tenant: Option<&str>,
+        counter: fn(&mut TenantStatus) -> &mut u64
+    ) {
+        let Some(tenant) = tenant else {
+            return;
+        };
+        let mut tenants = self.tenant_status();
+        let key = if tenants.contains_key(tenant) || tenants.len() < MAX_TENANTS {
+            tenant
+        } else {
+            OTHER_TENANTS
+        };
+        *counter(tenants.entry(key.to_string()).or_default()) += 1;
+    }
+
+    /// Files moved to `failed/` since start, by reason.
+    pub fn failures(&self) -> BTreeMap<String, u64> {
+        self.processing_status().failed_by_reason.clone()
+    }
+
+    fn processing_status(&self) -> MutexGuard<'_, ProcessingStatus> {
+        self.processing.lock().expect("runtime status mutex poisoned")
+    }
+
+    fn tenant_status(&self) -> MutexGuard<'_, BTreeMap<String, TenantStatus>> {
+        self.tenants.lock().expect("runtime status mutex poisoned")
+    }
+
+    fn imap_status(&self) -> MutexGuard<'_, Option<ImapStatus>> {
+        self.imap.lock().expect("runtime status mutex poisoned")
+    }
+}
+
+fn empty_imap_status() -> ImapStatus {
+    ImapStatus { polls: 0, failed_polls: 0, last_poll: None, mailboxes: Vec::new() }
+}
+
+fn mailbox_status<'a>(
+    imap: &'a mut ImapStatus,
+    account: &str,
+    mailbox: &str
+) -> &'a mut ImapMailboxStatus {
+    let index = match imap
+        .mailboxes
+        .iter()
+        .position(|status| status.account == account && status.mailbox == mailbox)
+    {
+        Some(index) => index,
+        None => {
+            imap.mailboxes.push(ImapMailboxStatus {
+                account: account.to_string(),
+                mailbox: mailbox.to_string(),
+                polls: 0,
+                failed_polls: 0,
+                last_poll: None
+            });
+            imap.mailboxes.len() - 1
+        }
+    };
+    &mut imap.mailboxes[index]
+}
+
+pub struct BusyWorker<'a> {
+    status: &'a RuntimeStatus
+}
+
+impl Drop for BusyWorker<'_> {
+    fn drop(&mut self) {
+        self.status.workers_busy.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Snapshot answered to a `status` frame.
+pub async fn server_status(state: &AppState) -> Result<ServerStatus> {
+    let spool = state.spool.counts().await?;
+    let status = &state.status;
+
+    Ok(ServerStatus {
+        uptime_secs: state.clock.now().duration_since(state.started_at).as_secs(),
+        generated_at_unix: state.clock.unix_secs(),
+        spool: SpoolStatus {
+            incoming: spool.incoming,
+            processing: spool.processing,
+            done: spool.done,
+            failed: spool.failed,
+            deferred: (state.retries.pending() + state.db_retries.pending()) as u64,
+            quarantined: state.spool.quarantined_events().await?.len() as u64
+        },
+        queue: status.lanes.get().and_then(LaneGauge::snapshot),
+        workers: WorkerStatus {
+            total: status.workers_total.load(Ordering::Relaxed),
+            busy: status.workers_busy.load(Ordering::Relaxed)
+        },
+        imap: status.imap_status().clone(),
+        db: state.db.pool_status(),
+        processing: status.processing_status().clone(),
+        tenants: status.tenant_status().clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use bouncer_proto::status::ImapPollStatus;
+
+    use super::{MAX_TENANTS, OTHER_TENANTS, RuntimeStatus, StageTimings};
+    use crate::core::lane_channels;
+
+    #[tokio::test]
+    async fn tracks_queue_depth_busy_workers_and_imap_polls() {
+        let status = RuntimeStatus::default();
+        let (tx, rx) = lane_channels(4, 2, 1, Default::default());
+        status.watch_lanes(tx.gauge());
+        assert!(tx.send("incoming/a.eml".into()).await);
+        assert!(tx.send("incoming/b.low.eml".into()).await);
+
+        let queue = status.lanes.get().and_then(|gauge| gauge.snapshot()).expect("lanes open");
+        assert_eq!((queue.high, queue.high_capacity, queue.low, queue.low_capacity), (1, 4, 1, 2));
+
+        {
+            let _busy = status.busy_worker();
+            let _busy = status.busy_worker();
+            assert_eq!(status.workers_busy.load(Ordering::Relaxed), 2);
+        }
+        assert_eq!(status.workers_busy.load(Ordering::Relaxed), 0);
+
+        status.imap_enabled("bounces@mail.example.com", "INBOX");
+        status.imap_enabled("bounces@mail.example.com", "Bounces");
+        status.record_imap_poll(ImapPollStatus {
+            account: "bounces@mail.example.com".to_string(),
+            mailbox: "Bounces".to_string(),
+            finished_at_unix: 1,
+            duration_ms: 5,
+            error: Some("login failed".to_string()),
+            selected: 0,
+            parsed_ok: 0,
+            parse_failures: 0,
+            fetch_failures: 0,
+            db_failures: 0
+        });
+        let imap = status.imap_status().clone().expect("imap enabled");
+        assert_eq!((imap.polls, imap.failed_polls), (1, 1));
+        let polls: Vec<_> = imap
+            .mailboxes
+            .iter()
+            .map(|mailbox| (mailbox.mailbox.as_str(), mailbox.polls, mailbox.failed_polls))
+            .collect();
+        assert_eq!(polls, [("INBOX", 0, 0), ("Bounces", 1, 1)]);
+
+        drop((tx, rx));
+        assert!(status.lanes.get().and_then(|gauge| gauge.snapshot()).is_none());
+    }
+
+    #[test]
+    fn records_stage_and_worker_latency() {
+        let status = RuntimeStatus::default();
+        status.set_workers(2);
+        let ms = Duration::from_millis;
+        status.record_processing(
+            1,
+            &StageTimings {
+                read: Some(ms(2)),
+                parse: Some(ms(30)),
+                upsert: Some(ms(400)),
+                finalize: Some(ms(3)),
+                total: ms(440)
+            },
+            false
+        );
+        status.record_processing(
+            0,
+            &StageTimings {
+                read: Some(ms(1)),
+                parse: Some(ms(8)),
+                total: ms(12),
+                ..Default::default()
+            },
+            true
+        );
+
+        let processing = status.processing_status().clone();
+        let stages: Vec<_> = processing
+            .stages
+            .iter()
+            .map(|stage| (stage.stage.as_str(), stage.latency.count, stage.latency.max_ms))
+            .collect();
+        assert_eq!(
+            stages,
+            [
+                ("read", 2, 2),
+                ("parse", 2, 30),
+                ("upsert", 1, 400),
+                ("finalize", 1, 3),
+                ("total", 2, 440)
+            ]
+        );
+        let workers: Vec<_> =
+            processing.workers.iter().map(|worker| (worker.worker, worker.latency.count)).collect();
+        assert_eq!(workers, [(0, 1), (1, 1)]);
+        assert_eq!(processing.slow_files, 1);
+    }
+
+    #[test]
+    fn counts_traffic_per_tenant() {
+        let status = RuntimeStatus::default();
+        status.count_tenant(Some("shop"), |tenant| &mut tenant.mails);
+        status.count_tenant(Some("shop"), |tenant| &mut tenant.processed);
+        status.count_tenant(Some("crm"), |tenant| &mut tenant.observer_events);
+        status.count_tenant(None, |tenant| &mut tenant.mails);
+
+        let tenants = status.tenant_status().clone();
+        let counts: Vec<_> = tenants
+            .iter()
+            .map(|(name, t)| (name.as_str(), (t.mails, t.observer_events, t.processed, t.failed)))
+            .collect();
+        assert_eq!(counts, [("crm", (0, 1, 0, 0)), ("shop", (1, 0, 1, 0))]);
+
+        for n in 0..MAX_TENANTS + 10 {
+            status.count_tenant(Some(&format!("t{n}")), |tenant| &mut tenant.mails);
+        }
+        status.count_tenant(Some("shop"), |tenant| &mut tenant.mails);
+        let tenants = status.tenant_status().clone();
+        assert_eq!(tenants.len(), MAX_TENANTS + 1);
+        assert_eq!(tenants["shop"].mails, 2);
+        assert_eq!(tenants[OTHER_TENANTS].mails, 12);
+    }
+}
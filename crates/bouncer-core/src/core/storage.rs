@@ -0,0 +1,704 @@
+//! Backends behind the spool (`spool_storage` in the config).
+//!
+//! The spool always works on files under its local root: the parser, the
+//! watcher, retention and the admin tools read them there. A
+//! [`SpoolStorage`] owns the steps that publish, move and remove those
+//! files, which is where the backends differ:
+//!
+//! - [`LocalStorage`] renames, as the spool always did.
+//! - [`NfsStorage`] assumes nothing about renames across directories: a file
+//!   is copied into the target directory, fsynced and renamed there, then
+//!   removed from the source, under a `<name>.lock` file created with
+//!   `O_EXCL` so two servers sharing the directory never move the same file.
+//! - [`S3Storage`] keeps the local files and mirrors each of them to a
+//!   bucket before it counts as written. On start, the files of unfinished
+//!   states the local disk lost are restored from the bucket.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use std::{fmt, io};
+
+use anyhow::{Context, Result};
+use futures_util::FutureExt;
+use futures_util::future::BoxFuture;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use super::s3::S3Client;
+use super::spool::mail_name;
+use crate::config::{SpoolBackend, SpoolStorageConfig};
+
+pub trait SpoolStorage: fmt::Debug + Send + Sync {
+    /// Name used in logs, as in `spool_storage.backend`.
+    fn name(&self) -> &'static str;
+
+    /// Makes the written and fsynced `tmp` file visible as `path`.
+    fn publish<'a>(
+        &'a self,
+        tmp: &'a Path,
+        path: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>>;
+
+    /// Moves a spool file to another state. Fails with `NotFound` when the
+    /// file is gone, or is being moved by someone else.
+    fn transition<'a>(
+        &'a self,
+        from: &'a Path,
+        to: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>>;
+
+    fn remove<'a>(
+        &'a self,
+        path: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>>;
+
+    /// Brings back files under `dirs` that exist in the backend but not on
+    /// the local disk, and returns how many.
+    fn restore<'a>(
+        &'a self,
+        dirs: &'a [&'a Path]
+    ) -> BoxFuture<'a, Result<usize>> {
+        let _ = dirs;
+        async { Ok(0) }.boxed()
+    }
+}
+
+pub type SharedSpoolStorage = Arc<dyn SpoolStorage>;
+
+/// Builds the backend selected by `config` for the spool under `root`.
+pub fn spool_storage(
+    config: &SpoolStorageConfig,
+    root: &Path
+) -> Result<SharedSpoolStorage> {
+    Ok(match (config.backend, &config.s3) {
+        (SpoolBackend::Local, _) => Arc::new(LocalStorage),
+        (SpoolBackend::Nfs, _) => Arc::new(NfsStorage::new(config.lock_stale_after)),
+        (SpoolBackend::S3, Some(s3)) => Arc::new(S3Storage::new(
+            S3Client::new(s3)?,
+            root.to_path_buf(),
+            s3.prefix.clone(),
+            Duration::from_secs(s3.connect_timeout_secs + s3.timeout_secs)
+        )),
+        (SpoolBackend::S3, None) => anyhow::bail!("`spool_storage.backend: s3` needs an s3 block")
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalStorage;
+
+impl SpoolStorage for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn publish<'a>(
+        &'a self,
+        tmp: &'a Path,
+        path: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>> {
+        tokio::fs::rename(tmp, path).boxed()
+    }
+
+    fn transition<'a>(
+        &'a self,
+        from: &'a Path,
+        to: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>> {
+        tokio::fs::rename(from, to).boxed()
+    }
+
+    fn remove<'a>(
+        &'a self,
+        path: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>> {
+        tokio::fs::remove_file(path).boxed()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NfsStorage {
+    lock_stale_after: Duration
+}
+
+impl NfsStorage {
+    pub fn new(lock_stale_after: Duration) -> Self {
+        Self { lock_stale_after }
+    }
+
+    /// Creates the lock of `path`, breaking it once when it is stale.
+    async fn lock(
+        &self,
+        path: &Path
+    ) -> io::Result<PathBuf> {
+        let name = mail_name(path).ok_or_else(|| io::Error::other("spool path has no name"))?;
+        let lock = path.with_file_name(format!("{name}.lock"));
+        for _ in 0..2 {
+            match tokio::fs::OpenOptions::new().write(true).create_new(true).open(&lock).await {
+                Ok(_) => return Ok(lock),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    match lock_age(&lock).await {
+                        Ok(age) if age >= self.lock_stale_after => {
+                            if !self.break_stale_lock(&lock).await? {
+                                break;
+                            }
+                        }
+                        // Vanished meanwhile: its holder finished, try again.
+                        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                        _ => break
+                    }
+                }
+                Err(err) => return Err(err)
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is being moved by another server", path.display())
+        ))
+    }
+
+    /// Breaks the stale `lock`; false when it turned out to be held.
+    ///
+    /// Renaming it to a unique name is atomic, so of several servers
+    /// breaking it at once only one gets it. That server checks the lock it
+    /// got is still stale: another server may have broken and re-created
+    /// it in between, and a fresh lock is put back.
+    async fn break_stale_lock(
+        &self,
+        lock: &Path
+    ) -> io::Result<bool> {
+        let mut broken = lock.as_os_str().to_os_string();
+        broken.push(format!(".broken-{}", Uuid::now_v7()));
+        let broken = PathBuf::from(broken);
+        match tokio::fs::rename(lock, &broken).await {
+            Ok(()) => {}
+            // Broken by someone else: try again.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(true),
+            Err(err) => return Err(err)
+        }
+        let stale = lock_age(&broken).await.is_ok_and(|age| age >= self.lock_stale_after);
+        if stale {
+            warn!("breaking stale spool lock: path={}", lock.display());
+        } else {
+            match tokio::fs::hard_link(&broken, lock).await {
+                Err(err) if err.kind() != io::ErrorKind::AlreadyExists => {
+                    warn!("failed to restore spool lock: path={}, error={err}", lock.display());
+                }
+                _ => {}
+            }
+        }
+        remove_if_exists(&broken).await?;
+        Ok(stale)
+    }
+}
+
+async fn lock_age(lock: &Path) -> io::Result<Duration> {
+    let modified = tokio::fs::metadata(lock).await?.modified()?;
+    Ok(SystemTime::now().duration_since(modified).unwrap_or_default())
+}
+
+impl SpoolStorage for NfsStorage {
+    fn name(&self) -> &'static str {
+        "nfs"
+    }
+
+    fn publish<'a>(
+        &'a self,
+        tmp: &'a Path,
+        path: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>> {
+        // Nobody else knows `tmp`, so it needs no lock.
+        move_file(tmp, path).boxed()
+    }
+
+    fn transition<'a>(
+        &'a self,
+        from: &'a Path,
+        to: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let lock = self.lock(from).await?;
+            let moved = move_file(from, to).await;
+            remove_if_exists(&lock).await?;
+            moved
+        }
+        .boxed()
+    }
+
+    fn remove<'a>(
+        &'a self,
+        path: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>> {
+        tokio::fs::remove_file(path).boxed()
+    }
+}
+
+/// Renames within a directory; across directories, copies into a synced
+/// `<name>.tmp` next to `to`, renames that, and removes `from`.
+async fn move_file(
+    from: &Path,
+    to: &Path
+) -> io::Result<()> {
+    if from.parent() == to.parent() {
+        return tokio::fs::rename(from, to).await;
+    }
+    let name = mail_name(to).ok_or_else(|| io::Error::other("spool path has no name"))?;
+    // Without the Maildir info, so it never passes for mail in `cur/`.
+    let tmp = to.with_file_name(format!("{name}.tmp"));
+    let copied = async {
+        tokio::fs::copy(from, &tmp).await?;
+        tokio::fs::File::open(&tmp).await?.sync_all().await?;
+        tokio::fs::rename(&tmp, to).await
+    }
+    .await;
+    if let Err(err) = copied {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(err);
+    }
+    tokio::fs::remove_file(from).await
+}
+
+async fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(())
+    }
+}
+
+pub struct S3Storage {
+    client: S3Client,
+    root: PathBuf,
+    prefix: String,
+    /// Bound of every bucket call, on top of the client's own timeouts.
+    call_timeout: Duration
+}
+
+impl fmt::Debug for S3Storage {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        f.debug_struct("S3Storage").field("root", &self.root).field("prefix", &self.prefix).finish()
+    }
+}
+
+impl S3Storage {
+    pub fn new(
+        client: S3Client,
+        root: PathBuf,
+        prefix: String,
+        call_timeout: Duration
+    ) -> Self {
+        Self { client, root, prefix, call_timeout }
+    }
+
+    /// Awaits one bucket call for at most `call_timeout`, as an io error,
+    /// so a hung endpoint fails the spool step instead of stalling it.
+    async fn bounded<T>(
+        &self,
+        call: impl Future<Output = Result<T>>
+    ) -> io::Result<T> {
+        match tokio::time::timeout(self.call_timeout, call).await {
+            Ok(result) => result.map_err(|err| io::Error::other(format!("{err:#}"))),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("s3 call timed out after {:?}", self.call_timeout)
+            ))
+        }
+    }
+
+    /// `<prefix><path relative to the spool root>`, `/`-separated.
+    fn key(
+        &self,
+        path: &Path
+    ) -> io::Result<String> {
+        let relative = path.strip_prefix(&self.root).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not inside {}", path.display(), self.root.display())
+            )
+        })?;
+        let mut key = self.prefix.clone();
+        for (index, part) in relative.components().enumerate() {
+            let Component::Normal(part) = part else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unexpected spool path {}", path.display())
+                ));
+            };
+            if index > 0 {
+                key.push('/');
+            }
+            key.push_str(&part.to_string_lossy());
+        }
+        Ok(key)
+    }
+
+    /// Where the object at `key` lives on the local disk.
+    fn local_path(
+        &self,
+        key: &str
+    ) -> Option<PathBuf> {
+        let relative = key.strip_prefix(&self.prefix)?;
+        let mut path = self.root.clone();
+        for part in relative.split('/') {
+            if part.is_empty() || part == "." || part == ".." {
+                return None;
+            }
+            path.push(part);
+        }
+        Some(path)
+    }
+
+    async fn restore_object(
+        &self,
+        key: &str,
+        path: &Path
+    ) -> Result<bool> {
+        let Some(body) = self.bounded(self.client.get_object(key)).await? else {
+            return Ok(false);
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("failed to create dir {}", parent.display()))?;
+        }
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp = path.with_file_name(tmp_name);
+        let mut file = tokio::fs::File::create(&tmp)
+            .await
+            .with_context(|| format!("failed to create {}", tmp.display()))?;
+        file.write_all(&body)
+            .await
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        file.sync_all().await.with_context(|| format!("failed to fsync {}", tmp.display()))?;
+        drop(file);
+        tokio::fs::rename(&tmp, path)
+            .await
+            .with_context(|| format!("failed to rename {} -> {}", tmp.display(), path.display()))?;
+        Ok(true)
+    }
+}
+
+impl SpoolStorage for S3Storage {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    /// Uploads before the rename, so a file is never visible locally
+    /// without its copy in the bucket.
+    fn publish<'a>(
+        &'a self,
+        tmp: &'a Path,
+        path: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let key = self.key(path)?;
+            let body = tokio::fs::read(tmp).await?;
+            self.bounded(self.client.put_object(&key, body, "application/octet-stream", None))
+                .await?;
+            tokio::fs::rename(tmp, path).await
+        }
+        .boxed()
+    }
+
+    /// Renames locally first, which claims the file, then copies the object
+    /// and drops the old one. A failed copy undoes the rename.
+    fn transition<'a>(
+        &'a self,
+        from: &'a Path,
+        to: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let (from_key, to_key) = (self.key(from)?, self.key(to)?);
+            tokio::fs::rename(from, to).await?;
+            if let Err(err) = self.bounded(self.client.copy_object(&from_key, &to_key)).await {
+                if let Err(undo) = tokio::fs::rename(to, from).await {
+                    warn!(
+                        "failed to undo spool move after s3 copy failed: path={}, error={undo}",
+                        to.display()
+                    );
+                }
+                return Err(err);
+            }
+            if let Err(err) = self.bounded(self.client.delete_object(&from_key)).await {
+                // The file moved on; a stale copy only matters to a restore.
+                warn!("failed to delete moved spool object: key={from_key}, error={err}");
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn remove<'a>(
+        &'a self,
+        path: &'a Path
+    ) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let key = self.key(path)?;
+            let removed = tokio::fs::remove_file(path).await;
+            self.bounded(self.client.delete_object(&key)).await?;
+            removed
+        }
+        .boxed()
+    }
+
+    fn restore<'a>(
+        &'a self,
+        dirs: &'a [&'a Path]
+    ) -> BoxFuture<'a, Result<usize>> {
+        async move {
+            let mut restored = 0;
+            for dir in dirs {
+                let prefix = format!("{}/", self.key(dir)?);
+                for key in self.bounded(self.client.list_objects(&prefix)).await? {
+                    let Some(path) = self.local_path(&key) else {
+                        warn!("skipping spool object outside the spool: key={key}");
+                        continue;
+                    };
+                    if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+                        continue;
+                    }
+                    if self.restore_object(&key, &path).await? {
+                        debug!("restored spool file: key={key}, path={}", path.display());
+                        restored += 1;
+                    }
+                }
+            }
+            Ok(restored)
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use axum::Router;
+    use axum::body::Bytes;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, Method, StatusCode, Uri};
+    use bouncer_helpers::clock::system_clock;
+    use tokio::net::TcpListener;
+    use uuid::Uuid;
+
+    use super::{NfsStorage, S3Storage, SpoolStorage, spool_storage};
+    use crate::config::{S3Config, SpoolBackend, SpoolStorageConfig};
+    use crate::core::s3::S3Client;
+    use crate::core::{Faults, Lane, Spool};
+
+    type Objects = Arc<Mutex<BTreeMap<String, Vec<u8>>>>;
+
+    fn decode(text: &str) -> String {
+        let mut bytes = Vec::new();
+        let mut rest = text.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            if byte == b'%' && tail.len() >= 2 {
+                let hex = std::str::from_utf8(&tail[..2]).unwrap();
+                bytes.push(u8::from_str_radix(hex, 16).unwrap());
+                rest = &tail[2..];
+            } else {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+        String::from_utf8(bytes).unwrap()
+    }
+
+    /// Path-style PUT (and copy), GET, DELETE and ListObjectsV2 of bucket `b`.
+    async fn fake_s3(
+        State(objects): State<Objects>,
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+        body: Bytes
+    ) -> (StatusCode, Vec<u8>) {
+        let key = decode(uri.path().strip_prefix("/b").unwrap().trim_start_matches('/'));
+        let mut objects = objects.lock().unwrap();
+        match method {
+            Method::GET if key.is_empty() => {
+                let query = uri.query().unwrap_or_default();
+                let prefix = query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("prefix="))
+                    .map(decode)
+                    .unwrap_or_default();
+                let keys: String = objects
+                    .keys()
+                    .filter(|key| key.starts_with(&prefix))
+                    .map(|key| format!("<Contents><Key>{key}</Key></Contents>"))
+                    .collect();
+                let xml = format!(
+                    "<ListBucketResult><IsTruncated>false</IsTruncated>{keys}</ListBucketResult>"
+                );
+                (StatusCode::OK, xml.into_bytes())
+            }
+            Method::GET => match objects.get(&key) {
+                Some(body) => (StatusCode::OK, body.clone()),
+                None => (StatusCode::NOT_FOUND, Vec::new())
+            },
+            Method::PUT => {
+                let body = match headers.get("x-amz-copy-source") {
+                    Some(source) => {
+                        let source = decode(source.to_str().unwrap());
+                        let Some(body) = objects.get(source.strip_prefix("/b/").unwrap()) else {
+                            return (StatusCode::NOT_FOUND, Vec::new());
+                        };
+                        body.clone()
+                    }
+                    None => body.to_vec()
+                };
+                objects.insert(key, body);
+                (StatusCode::OK, Vec::new())
+            }
+            Method::DELETE => {
+                objects.remove(&key);
+                (StatusCode::NO_CONTENT, Vec::new())
+            }
+            _ => (StatusCode::METHOD_NOT_ALLOWED, Vec::new())
+        }
+    }
+
+    fn spool(root: &Path) -> Spool {
+        Spool::new(root.to_path_buf(), system_clock(), Arc::new(Faults::default()))
+    }
+
+    #[tokio::test]
+    async fn s3_storage_mirrors_files_and_restores_unfinished_ones() {
+        let objects = Objects::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let router = Router::new().fallback(fake_s3).with_state(objects.clone());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let root = std::env::temp_dir().join(format!("bouncer-storage-{}", Uuid::now_v7()));
+        let config = SpoolStorageConfig {
+            backend: SpoolBackend::S3,
+            s3: Some(S3Config {
+                endpoint,
+                bucket: "b".to_string(),
+                region: "us-east-1".to_string(),
+                prefix: "node-1/".to_string(),
+                access_key_id: Some("key".to_string()),
+                secret_access_key: Some("secret".to_string()),
+                connect_timeout_secs: 5,
+                timeout_secs: 30
+            }),
+            ..SpoolStorageConfig::default()
+        };
+        let spool = spool(&root).with_storage(spool_storage(&config, &root).unwrap());
+        spool.ensure_dirs().await.unwrap();
+
+        let incoming =
+            spool.enqueue_mail(b"Subject: a\r\n\r\n", Lane::High, None, None).await.unwrap();
+        let name = incoming.file_name().unwrap().to_str().unwrap().to_string();
+        assert!(objects.lock().unwrap().contains_key(&format!("node-1/incoming/{name}")));
+        let processing =
+            spool.relocate(&incoming, &spool.incoming, &spool.processing).await.unwrap();
+        spool.rename(&incoming, &processing).await.unwrap();
+        spool.quarantine_event(b"{", "bad json").await.unwrap();
+        let keys: Vec<_> = objects.lock().unwrap().keys().cloned().collect();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[0], format!("node-1/processing/{name}"));
+        assert!(keys[1..].iter().all(|key| key.starts_with("node-1/quarantine/")));
+
+        // Rescheduled onto an empty disk.
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+        spool.ensure_dirs().await.unwrap();
+        assert_eq!(spool.restore().await.unwrap(), 3);
+        assert_eq!(tokio::fs::read(&processing).await.unwrap(), b"Subject: a\r\n\r\n");
+        assert_eq!(spool.quarantined_events().await.unwrap().len(), 1);
+        assert_eq!(spool.restore().await.unwrap(), 0);
+
+        spool.remove(&processing).await.unwrap();
+        assert_eq!(objects.lock().unwrap().len(), 2);
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn nfs_storage_moves_by_copy_under_a_lock() {
+        let root = std::env::temp_dir().join(format!("bouncer-storage-{}", Uuid::now_v7()));
+        let spool = spool(&root).with_storage(Arc::new(NfsStorage::new(Duration::from_secs(60))));
+        spool.ensure_dirs().await.unwrap();
+
+        let incoming =
+            spool.enqueue_mail(b"Subject: a\r\n\r\n", Lane::High, None, None).await.unwrap();
+        let name = incoming.file_name().unwrap().to_str().unwrap().to_string();
+        let processing =
+            spool.relocate(&incoming, &spool.incoming, &spool.processing).await.unwrap();
+
+        // Another server holds the file.
+        let lock = spool.incoming.join(format!("{name}.lock"));
+        tokio::fs::write(&lock, b"").await.unwrap();
+        let err = spool.rename(&incoming, &processing).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(incoming.exists());
+
+        // Its lock outlived `lock_stale_after`.
+        let stale = spool.clone().with_storage(Arc::new(NfsStorage::new(Duration::ZERO)));
+        stale.rename(&incoming, &processing).await.unwrap();
+        assert_eq!(tokio::fs::read(&processing).await.unwrap(), b"Subject: a\r\n\r\n");
+        for dir in [&spool.incoming, &spool.processing] {
+            let mut entries = tokio::fs::read_dir(dir).await.unwrap();
+            let mut names = Vec::new();
+            while let Some(entry) = entries.next_entry().await.unwrap() {
+                names.push(entry.file_name().into_string().unwrap());
+            }
+            let expected = if *dir == spool.processing { vec![name.clone()] } else { Vec::new() };
+            assert_eq!(names, expected);
+        }
+        assert_eq!(
+            spool.rename(&incoming, &processing).await.unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        // A lock re-created by another server after the staleness check is
+        // put back, not broken.
+        let fresh = spool.incoming.join("fresh.eml.lock");
+        tokio::fs::write(&fresh, b"").await.unwrap();
+        let nfs = NfsStorage::new(Duration::from_secs(60));
+        assert!(!nfs.break_stale_lock(&fresh).await.unwrap());
+        assert!(fresh.exists());
+        assert!(NfsStorage::new(Duration::ZERO).break_stale_lock(&fresh).await.unwrap());
+        let mut entries = tokio::fs::read_dir(&spool.incoming).await.unwrap();
+        assert!(entries.next_entry().await.unwrap().is_none());
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn s3_storage_bounds_every_bucket_call() {
+        // Accepts connections and never replies.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+        let root = std::env::temp_dir().join(format!("bouncer-storage-{}", Uuid::now_v7()));
+        let client = S3Client::new(&S3Config {
+            endpoint,
+            bucket: "b".to_string(),
+            region: "us-east-1".to_string(),
+            prefix: String::new(),
+            access_key_id: Some("key".to_string()),
+            secret_access_key: Some("secret".to_string()),
+            connect_timeout_secs: 30,
+            timeout_secs: 30
+        })
+        .unwrap();
+        let storage =
+            S3Storage::new(client, root.clone(), String::new(), Duration::from_millis(100));
+
+        let err = storage.remove(&root.join("incoming").join("a.eml")).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,56 @@
+//! Tenants (applications) sharing one server.
+//!
+//! Message hashes are only unique within a tenant, so the tenant sent in a
+//! frame header scopes the message lookups of the bounces and observer
+//! events it carries. Frames without a tenant match by hash alone, as
+//! before. The tenant of a spooled mail is encoded in its spool file name,
+//! like its lane, so it survives restarts and rescans without reading the
+//! file.
+
+use std::path::Path;
+
+use bouncer_proto::valid_tenant;
+
+use super::spool::mail_name;
+
+/// Marks the tenant in a spool file name: `<id>.tenant-<tenant>.eml`.
+const TENANT_TAG: &str = ".tenant-";
+
+/// Spool id of a mail of `tenant`, before the lane suffix is added.
+pub fn spool_id(
+    id: &str,
+    tenant: Option<&str>
+) -> String {
+    match tenant {
+        Some(tenant) => format!("{id}{TENANT_TAG}{tenant}"),
+        None => id.to_string()
+    }
+}
+
+/// Tenant encoded in a spool path by [`spool_id`].
+pub fn tenant_of_path(path: &Path) -> Option<String> {
+    let (_, tagged) = mail_name(path)?.split_once(TENANT_TAG)?;
+    let tenant = tagged.split('.').next()?;
+    valid_tenant(tenant).then(|| tenant.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{spool_id, tenant_of_path};
+    use crate::core::lanes::Lane;
+
+    #[test]
+    fn tenant_round_trips_through_spool_file_names() {
+        let name = Lane::Low.file_name(&spool_id("0190-a", Some("shop-eu")));
+        assert_eq!(name, "0190-a.tenant-shop-eu.low.eml");
+        let path = Path::new("done").join(format!("{name}:2,S"));
+        assert_eq!(tenant_of_path(&path).as_deref(), Some("shop-eu"));
+        assert_eq!(Lane::of_path(Path::new(&name)), Lane::Low);
+
+        assert_eq!(Lane::High.file_name(&spool_id("0190-b", None)), "0190-b.eml");
+        assert_eq!(tenant_of_path(Path::new("incoming/0190-b.eml")), None);
+        assert_eq!(tenant_of_path(Path::new("incoming/0190-c.tenant-.eml")), None);
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::Mutex;
+
+use tracing::debug;
+
+/// Upper bound on remembered trace contexts; beyond it new spool files are
+/// processed as fresh traces instead of growing the map.
+const MAX_PENDING: usize = 65_536;
+
+/// Carries the trace context of an accepted frame to the worker that later
+/// processes its spool file.
+///
+/// Kept in memory and keyed by spool file name, so the mail bytes (and their
+/// idempotency key) stay untouched. Files requeued after a restart start a
+/// new trace.
+#[derive(Debug, Default)]
+pub struct SpoolTraces {
+    pending: Mutex<HashMap<OsString, String>>
+}
+
+impl SpoolTraces {
+    pub fn record(
+        &self,
+        spool_path: &Path,
+        traceparent: Option<String>
+    ) {
+        let (Some(file_name), Some(traceparent)) = (spool_path.file_name(), traceparent) else {
+            return;
+        };
+
+        let mut pending = self.lock();
+        if pending.len() >= MAX_PENDING {
+            debug!(
+                "trace carry-over full, spool file starts a new trace: path={}",
+                spool_path.display()
+            );
+            return;
+        }
+        pending.insert(file_name.to_owned(), traceparent);
+    }
+
+    pub fn take(
+        &self,
+        spool_path: &Path
+    ) -> Option<String> {
+        self.lock().remove(spool_path.file_name()?)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<OsString, String>> {
+        self.pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,325 @@
+//! Embeddable bounce-processing pipeline: TCP/SMTP/IMAP ingest, spool,
+//! parser chain, database and spool workers.
+//!
+//! The `bouncer-server` binary is a thin wrapper around [`run`]. A supervisor
+//! process can call [`run`] with its own shutdown token, or build a [`Server`]
+//! first to fail fast on spool/database errors before spawning it.
+//!
+//! The building blocks are public for hosts that drive parts of the pipeline
+//! themselves:
+//! - [`Spool`]: the on-disk queue; [`Spool::enqueue_mail`] is what every ingest
+//!   path calls before it acknowledges a bounce.
+//! - [`ParserChain`]: turns a raw bounce mail into a [`ParsedBounce`].
+//! - [`Database`]: the MySQL/SQLite writer the workers apply bounces with.
+//!
+//! A config read from a file ([`Config::from_path`], [`Config::load`]) is
+//! reloaded on SIGHUP; see the README for which settings apply live.
+
+mod app;
+pub mod config;
+mod core;
+
+pub use core::{
+    BounceAudit, BounceAuthenticator, CheckSummary, Database, Faults, HashRules, IngestPath, Lane,
+    ObserverEventOutcome, ParsedBounce, ParserChain, ParserError, ReportKind, SharedSpoolStorage,
+    Spool, SpoolCounts, SpoolStorage, UpsertBounceOutcome, check_dir, idempotency_key
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use app::AppState;
+use bouncer_helpers::clock::{self, SharedClock};
+use bouncer_helpers::reload::apply_log_filter;
+pub use config::Config;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::core::{
+    AdminTriggers, BounceAlerts, BounceArchive, BounceHooks, ConnectionStats, DbRetries,
+    FrameAudit, InFlightPaths, Live, MissingMessageRetries, PayloadCapture, RuntimeStatus,
+    SourceRegistry, SpoolOrigins, SpoolTraces, lane_channels, replay_quarantine_on_start,
+    run_admin_api, run_archive_retention, run_bounce_dedup_prune, run_bounce_hooks,
+    run_config_reload, run_db_health_check, run_db_retries, run_debug_signals, run_failure_summary,
+    run_missing_message_retries, run_observer_batcher, run_smtp_server, run_source_monitor,
+    run_spool_retention, run_startup_diagnostics, run_suppression_expiry, run_table_prune,
+    run_tcp_server, spawn_notify_watcher, spawn_periodic_scan, spawn_worker_dispatcher,
+    spool_storage
+};
+
+/// Builds a [`Server`] from `config` and runs it until `shutdown` is cancelled.
+pub async fn run(
+    config: Config,
+    shutdown: CancellationToken
+) -> Result<()> {
+    Server::build(config, shutdown).await?.run().await
+}
+
+/// A fully constructed server: spool directories exist, the database pool is
+/// connected, startup diagnostics ran and the parser chain is resolved. Nothing runs until [`Server::run`].
+pub struct Server {
+    config: Config,
+    state: AppState
+}
+
+impl Server {
+    /// Normalizes and validates `config`, then prepares every subsystem.
+    pub async fn build(
+        config: Config,
+        shutdown: CancellationToken
+    ) -> Result<Self> {
+        Self::build_with_clock(config, shutdown, clock::system_clock()).await
+    }
+
+    /// Same as [`Server::build`] with an explicit clock (tests, simulations).
+    pub async fn build_with_clock(
+        mut config: Config,
+        shutdown: CancellationToken,
+        clock: SharedClock
+    ) -> Result<Self> {
+        config.normalize()?;
+        config.validate()?;
+        if config.log_filter.is_some() {
+            apply_log_filter(config.log_filter.as_deref());
+        }
+
+        let faults = Arc::new(Faults::from_env()?);
+        let spool = Arc::new(
+            Spool::new(config.spool.clone(), clock.clone(), faults.clone())
+                .with_layout(config.spool_layout)
+                .partitioned_by_source(config.spool_partition_by_source)
+                .compressing_done(config.spool_compress_done)
+                .with_storage(
+                    spool_storage(&config.spool_storage, &config.spool)
+                        .context("invalid spool_storage config")?
+                )
+        );
+        spool.ensure_dirs().await?;
+        let restored = spool.restore().await?;
+        if restored > 0 {
+            info!(
+                "restored spool files from storage: backend={}, count={}",
+                spool.storage_name(),
+                restored
+            );
+        }
+        let requeued = spool.requeue_processing().await?;
+        if requeued > 0 {
+            info!("requeued interrupted spool files: count={}", requeued);
+        }
+
+        let alerts = BounceAlerts::new(config.alerts.clone(), clock.clone())
+            .context("invalid alerts config")?;
+        let db = Arc::new(
+            Database::connect(
+                &config.database_url,
+                config.migrate,
+                config.suppression.clone(),
+                faults.clone()
+            )
+            .await
+            .context("failed to connect database")?
+            .with_bounce_dedup(config.bounce_dedup_window)
+            .with_resilience(config.database_resilience.clone())
+            .with_batching(config.database_batch)
+            .with_message_cache(config.message_cache, clock.clone())
+            .with_recipients(config.recipients.clone())
+            .with_classification(config.classification.clone())
+            .with_alerts(Arc::new(alerts))
+            .with_hooks(Arc::new(BounceHooks::new(config.hooks.clone(), clock.clone())))
+        );
+        run_startup_diagnostics(&config, &spool, &db, &clock).await?;
+
+        let parsers = ParserChain::from_config(&config.parser)?;
+        info!("bounce parser chain: {}", parsers.names().join(","));
+
+        let started_at = clock.now();
+        let sources = SourceRegistry::new(
+            Duration::from_secs(config.sources.silent_after_secs),
+            clock.clone()
+        )
+        .persisting_events(config.sources.persist_events)
+        .with_takeover(config.sources.takeover);
+        let authenticator = BounceAuthenticator::new(config.authentication.clone())
+            .context("invalid authentication config")?;
+        let archive =
+            BounceArchive::new(config.archive.clone()).context("invalid archive config")?;
+        let frame_audit =
+            FrameAudit::new(config.frame_audit.clone()).context("invalid frame_audit config")?;
+        let state = AppState {
+            spool,
+            db,
+            parsers: Arc::new(Live::new(parsers)),
+            shutdown,
+            clock,
+            faults,
+            connections: Arc::new(ConnectionStats::new(started_at)),
+            sources: Arc::new(sources),
+            dispatcher: Arc::new(config.dispatcher.clone()),
+            traces: Arc::new(SpoolTraces::default()),
+            retries: Arc::new(MissingMessageRetries::new(
+                config.missing_message_retry.delays.clone()
+            )),
+            db_retries: Arc::new(DbRetries::new(config.db_retry.delays.clone())),
+            status: Arc::new(RuntimeStatus::default()),
+            capture: Arc::new(PayloadCapture::new(config.payload_capture.clone())),
+            frame_audit: Arc::new(frame_audit),
+            authenticator: Arc::new(authenticator),
+            archive: Arc::new(archive),
+            triggers: Arc::new(AdminTriggers::default()),
+            retention: Arc::new(Live::new(config.spool_retention.clone())),
+            ignore_delivered_events: config.ignore_delivered_events,
+            clients: Arc::new(Live::new(config.client_timeouts())),
+            audit: config.audit,
+            origins: Arc::new(SpoolOrigins::default()),
+            in_flight: Arc::new(InFlightPaths::default()),
+            started_at
+        };
+
+        Ok(Self { config, state })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Spool the server reads from; mail enqueued here is picked up by the
+    /// workers once [`Server::run`] is running.
+    pub fn spool(&self) -> Arc<Spool> {
+        self.state.spool.clone()
+    }
+
+    /// Connected and migrated database shared with the workers.
+    pub fn database(&self) -> Arc<Database> {
+        self.state.db.clone()
+    }
+
+    /// Parser chain currently in use; replaced when `parser` is reloaded.
+    pub fn parsers(&self) -> Arc<ParserChain> {
+        self.state.parsers.load()
+    }
+
+    /// Token that stops every subsystem when cancelled.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.state.shutdown.clone()
+    }
+
+    /// Runs the TCP listener, spool watcher, periodic scan, workers, source
+    /// monitor, config reload and the optional IMAP loop, SMTP listener and
+    /// admin API until shutdown.
+    ///
+    /// Returns once every subsystem has stopped. A listener failure cancels
+    /// the shutdown token so the remaining subsystems stop as well.
+    pub async fn run(self) -> Result<()> {
+        let Self { config, state } = self;
+
+        info!(
+            "server starting: listen={}, listen_allow={}, spool={}, spool_storage={}",
+            config.listen.join(","),
+            if config.listen_allow.is_empty() {
+                "any".to_string()
+            } else {
+                config.listen_allow.join(",")
+            },
+            config.spool.display(),
+            state.spool.storage_name()
+        );
+
+        let high_capacity = config.dispatcher.high_queue_size.unwrap_or_else(|| {
+            config.worker_concurrency.max(1).saturating_mul(config.process_queue_per_worker)
+        });
+        let (process_tx, process_rx) = lane_channels(
+            high_capacity,
+            config.dispatcher.low_queue_size,
+            config.dispatcher.high_weight,
+            state.in_flight.clone()
+        );
+        state.status.watch_lanes(process_tx.gauge());
+        info!(
+            "process queues configured: high_capacity={}, low_capacity={}, high_weight={}",
+            high_capacity, config.dispatcher.low_queue_size, config.dispatcher.high_weight
+        );
+
+        let mut tasks = JoinSet::new();
+        tasks.spawn(spawn_notify_watcher(state.clone(), process_tx.clone()));
+        if state.retries.enabled() {
+            tasks.spawn(run_missing_message_retries(state.clone(), process_tx.clone()));
+        }
+        if state.db_retries.enabled() {
+            tasks.spawn(run_db_retries(state.clone(), process_tx.clone()));
+        }
+        tasks.spawn(spawn_periodic_scan(state.clone(), process_tx, config.incoming_scan_secs));
+        tasks.spawn(spawn_worker_dispatcher(state.clone(), process_rx, config.worker_concurrency));
+        tasks.spawn(run_source_monitor(state.clone()));
+        tasks.spawn(replay_quarantine_on_start(state.clone()));
+        tasks.spawn(run_table_prune(
+            state.db.clone(),
+            config.sources.events_retention,
+            config.audit.retention,
+            state.shutdown.clone()
+        ));
+        tasks.spawn(run_db_health_check(state.db.clone(), state.shutdown.clone()));
+        if config.database_batch.enabled() {
+            tasks.spawn(run_observer_batcher(state.db.clone(), state.shutdown.clone()));
+        }
+        if !config.hooks.is_empty() {
+            tasks.spawn(run_bounce_hooks(state.db.hooks(), state.shutdown.clone()));
+        }
+        if config.bounce_dedup_window.is_some() {
+            tasks.spawn(run_bounce_dedup_prune(state.db.clone(), state.shutdown.clone()));
+        }
+        if config.suppression.enabled {
+            tasks.spawn(run_suppression_expiry(state.db.clone(), state.shutdown.clone()));
+        }
+        if config.archive.enabled() && config.archive.retention.is_some() {
+            tasks.spawn(run_archive_retention(
+                state.db.clone(),
+                state.archive.clone(),
+                state.shutdown.clone()
+            ));
+        }
+        tasks.spawn(run_spool_retention(state.clone()));
+        tasks.spawn(run_failure_summary(state.clone()));
+        tasks.spawn(run_config_reload(state.clone(), config.clone()));
+        tasks.spawn(run_debug_signals(state.clone()));
+
+        let smtp = async {
+            match config.smtp_listen.as_deref() {
+                Some(smtp_listen) => {
+                    run_smtp_server(smtp_listen, config.smtp.clone(), state.clone()).await
+                }
+                None => Ok(())
+            }
+        };
+        let admin = async {
+            match (config.admin_listen.as_deref(), config.admin_token.clone()) {
+                (Some(admin_listen), Some(token)) => {
+                    let min_count = config.admin_min_reported_count;
+                    run_admin_api(admin_listen, token, min_count, state.clone()).await
+                }
+                _ => Ok(())
+            }
+        };
+        let listeners = config.listeners();
+        let result = tokio::try_join!(
+            run_tcp_server(&listeners, &config.listen_allow, state.clone()),
+            smtp,
+            admin
+        )
+        .map(|_| ());
+        if let Err(err) = &result {
+            warn!("listener failed, stopping subsystems: error={err:#}");
+        }
+        state.shutdown.cancel();
+
+        while let Some(joined) = tasks.join_next().await {
+            if let Err(err) = joined {
+                warn!("server subsystem join failed: error={err}");
+            }
+        }
+
+        info!("server stopped");
+        result
+    }
+}
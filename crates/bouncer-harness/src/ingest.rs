@@ -0,0 +1,126 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result, bail};
+use bouncer_proto::{ACK, Header, decode_header_json, read_frame_async};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{Instant, sleep};
+use tokio_util::sync::CancellationToken;
+
+use crate::{POLL_INTERVAL, WAIT_TIMEOUT};
+
+const MAX_HEADER_LEN: u32 = 64 * 1024;
+const MAX_BODY_LEN: u64 = 16 * 1024 * 1024;
+
+/// One frame received by [`MockIngest`].
+#[derive(Debug, Clone)]
+pub struct ReceivedFrame {
+    pub header: Header,
+    pub body: Vec<u8>
+}
+
+impl ReceivedFrame {
+    pub fn kind(&self) -> Option<&str> {
+        self.header.kind.as_deref()
+    }
+
+    pub fn json(&self) -> Result<serde_json::Value> {
+        serde_json::from_slice(&self.body).context("frame body is not json")
+    }
+}
+
+/// Ingest server that ACKs every frame and keeps it for inspection.
+pub struct MockIngest {
+    addr: SocketAddr,
+    frames: Arc<Mutex<Vec<ReceivedFrame>>>,
+    shutdown: CancellationToken
+}
+
+impl MockIngest {
+    /// Listens on a free local port until dropped.
+    pub async fn start() -> Result<Self> {
+        let listener =
+            TcpListener::bind("127.0.0.1:0").await.context("failed to bind mock ingest")?;
+        let addr = listener.local_addr().context("mock ingest has no local addr")?;
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let shutdown = CancellationToken::new();
+        tokio::spawn(accept_loop(listener, frames.clone(), shutdown.clone()));
+        Ok(Self { addr, frames, shutdown })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Every frame received so far, in arrival order.
+    pub fn frames(&self) -> Vec<ReceivedFrame> {
+        self.frames.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Waits until `count` frames of `kind` arrived and returns them.
+    pub async fn wait_for_kind(
+        &self,
+        kind: &str,
+        count: usize
+    ) -> Result<Vec<ReceivedFrame>> {
+        let deadline = Instant::now() + WAIT_TIMEOUT;
+        loop {
+            let frames: Vec<_> =
+                self.frames().into_iter().filter(|frame| frame.kind() == Some(kind)).collect();
+            if frames.len() >= count {
+                return Ok(frames);
+            }
+            if Instant::now() >= deadline {
+                bail!("expected {count} {kind} frames, got {}", frames.len());
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+impl Drop for MockIngest {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    frames: Arc<Mutex<Vec<ReceivedFrame>>>,
+    shutdown: CancellationToken
+) {
+    loop {
+        let stream = tokio::select! {
+            _ = shutdown.cancelled() => return,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(_) => continue
+            }
+        };
+        let frames = frames.clone();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = shutdown.cancelled() => {}
+                _ = serve(stream, frames) => {}
+            }
+        });
+    }
+}
+
+/// Reads frames until the peer closes the connection.
+async fn serve(
+    mut stream: TcpStream,
+    frames: Arc<Mutex<Vec<ReceivedFrame>>>
+) -> Result<()> {
+    loop {
+        let (header, body) = read_frame_async(&mut stream, MAX_HEADER_LEN, MAX_BODY_LEN).await?;
+        let header = decode_header_json(&header)?;
+        frames
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(ReceivedFrame { header, body });
+        stream.write_all(ACK).await?;
+    }
+}
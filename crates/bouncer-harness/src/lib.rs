@@ -0,0 +1,35 @@
+//! End-to-end harness for checking changes without a staging Postfix or MySQL.
+//!
+//! - [`TestServer`] runs the real `bouncer-server` on a free port over a
+//!   scratch spool and sqlite database, and reads results back through the
+//!   same `query` frames as `bouncer-admin`.
+//! - [`MockIngest`] stands in for the server and records the frames an agent
+//!   sends, e.g. to test `bouncer-observer` on its own.
+//! - [`FakeObserver`] publishes scripted `observer_event` frames.
+//! - [`FakeMta`] sends postfix syslog lines to an observer's UDP port.
+//! - [`Scenario`] reads the fixtures under `fixtures/` and drives a
+//!   [`TestServer`] through them: client mail frames are spooled, parsed and
+//!   written to the database, observer events are applied directly.
+
+mod ingest;
+mod mta;
+mod observer;
+mod scenario;
+mod server;
+
+use std::time::Duration;
+
+pub use ingest::{MockIngest, ReceivedFrame};
+pub use mta::FakeMta;
+pub use observer::{FakeObserver, ObserverEvent};
+pub use scenario::{Expectation, Scenario};
+pub use server::TestServer;
+
+/// Directory of the harness fixtures (`scenarios.yaml`, `postfix.log`).
+pub const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures");
+/// Directory of the `.eml` bounce fixtures shared with the parser tests.
+pub const BOUNCES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/bounces");
+
+/// How long the `wait_*` helpers poll before giving up.
+pub const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
@@ -0,0 +1,82 @@
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use tokio::net::UdpSocket;
+use tokio::time::{Instant, sleep, timeout};
+
+use crate::{POLL_INTERVAL, WAIT_TIMEOUT};
+
+/// Ignored by the observer's parser (no `postfix/` tag).
+const PROBE_LINE: &[u8] = b"<13>Jan  1 00:00:00 harness probe: ready?";
+
+/// Plays the part of Postfix plus rsyslog: sends log lines to an observer's
+/// UDP port, one datagram per line.
+pub struct FakeMta {
+    socket: UdpSocket
+}
+
+impl FakeMta {
+    pub async fn connect(target: SocketAddr) -> Result<Self> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.context("failed to bind fake mta")?;
+        socket.connect(target).await.with_context(|| format!("failed to connect to {target}"))?;
+        Ok(Self { socket })
+    }
+
+    /// Waits until something listens on the target port, so the first lines
+    /// of a script are not lost while the observer is still binding.
+    ///
+    /// A closed port answers a probe with ICMP unreachable, which shows up as
+    /// `ConnectionRefused` on the connected socket.
+    pub async fn wait_ready(&self) -> Result<()> {
+        let deadline = Instant::now() + WAIT_TIMEOUT;
+        let mut buf = [0_u8; 64];
+        loop {
+            match self.socket.send(PROBE_LINE).await {
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::ConnectionRefused => {}
+                Err(err) => return Err(err).context("fake mta probe failed")
+            }
+            match timeout(Duration::from_millis(100), self.socket.recv(&mut buf)).await {
+                Err(_) => return Ok(()),
+                Ok(Err(err)) if err.kind() == ErrorKind::ConnectionRefused => {}
+                Ok(Err(err)) => return Err(err).context("fake mta probe failed"),
+                Ok(Ok(_)) => return Ok(())
+            }
+            if Instant::now() >= deadline {
+                bail!("nothing listens on the observer udp port");
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    pub async fn send_line(
+        &self,
+        line: &str
+    ) -> Result<()> {
+        self.socket.send(line.as_bytes()).await.context("fake mta send failed")?;
+        Ok(())
+    }
+
+    /// Sends every non-empty, non-`#` line of a log fixture in order; returns
+    /// the number of lines sent.
+    pub async fn replay(
+        &self,
+        path: &Path
+    ) -> Result<usize> {
+        let script = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut sent = 0;
+        for line in script.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.send_line(line).await?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+}
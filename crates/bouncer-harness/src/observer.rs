@@ -0,0 +1,92 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use bouncer_proto::event::{DeliveryEvent, encode_delivery_event};
+use bouncer_proto::{Header, encode_header_json, read_ack_async, write_frame_async};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+
+/// One scripted delivery outcome, as `bouncer-observer` reports it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObserverEvent {
+    pub hash: String,
+    pub queue_id: String,
+    pub recipient: String,
+    pub status_code: String,
+    pub action: String,
+    #[serde(default)]
+    pub diagnostic: String,
+    pub smtp_status: String,
+    /// Overrides the publisher sequence, e.g. to replay events out of order.
+    #[serde(default)]
+    pub sequence: Option<u64>
+}
+
+/// Publishes scripted `observer_event` frames over one connection, the way
+/// the observer's publisher does: a `register` frame first, then one frame
+/// per event with a growing `sequence`, each waiting for its ACK.
+pub struct FakeObserver {
+    source: String,
+    stream: TcpStream,
+    sequence: u64
+}
+
+impl FakeObserver {
+    pub async fn connect(
+        server: &str,
+        source: &str
+    ) -> Result<Self> {
+        let stream = TcpStream::connect(server)
+            .await
+            .with_context(|| format!("fake observer connect failed: {server}"))?;
+        let mut observer = Self { source: source.to_string(), stream, sequence: 0 };
+        let register = format!("source={source}\n");
+        observer.send_frame("register", register.as_bytes()).await?;
+        Ok(observer)
+    }
+
+    /// Returns once the server applied the event and ACKed it.
+    pub async fn send(
+        &mut self,
+        event: &ObserverEvent
+    ) -> Result<()> {
+        self.sequence = event.sequence.unwrap_or(self.sequence + 1);
+        let observed_at_unix =
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs())?;
+        let payload = DeliveryEvent::new(
+            &self.source,
+            &event.hash,
+            &event.queue_id,
+            &event.recipient,
+            observed_at_unix
+        )
+        .with_outcome(&event.smtp_status, &event.status_code, &event.action)
+        .with_diagnostic(&event.diagnostic)
+        .with_sequence(self.sequence);
+        let body = encode_delivery_event(&payload).context("failed to encode observer event")?;
+        self.send_frame("observer_event", &body).await
+    }
+
+    async fn send_frame(
+        &mut self,
+        kind: &str,
+        body: &[u8]
+    ) -> Result<()> {
+        let header = Header {
+            from: self.source.clone(),
+            to: "bouncer@ingest".to_string(),
+            kind: Some(kind.to_string()),
+            source: Some(self.source.clone()),
+            traceparent: None,
+            queue_id: None,
+            tenant: None,
+            checksum: None
+        };
+        let header = encode_header_json(&header).context("failed to encode header")?;
+        write_frame_async(&mut self.stream, &header, body)
+            .await
+            .with_context(|| format!("failed to send {kind} frame"))?;
+        read_ack_async(&mut self.stream).await.with_context(|| format!("no ACK for {kind} frame"))
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use bouncer_proto::query::MessageState;
+use serde::Deserialize;
+
+use crate::{BOUNCES_DIR, FakeObserver, ObserverEvent, TestServer};
+
+const SCENARIO_SOURCE: &str = "harness-observer";
+const MAIL_FROM: &str = "MAILER-DAEMON@localhost";
+const MAIL_TO: &str = "bounces@localhost";
+
+/// One end-to-end case of a fixture file, run against a fresh server:
+/// `messages` are seeded, `mails` go through client → spool → parser → DB,
+/// `observer` events are applied in order, then every `expect` entry must
+/// hold.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default)]
+    pub messages: Vec<SeedMessage>,
+    /// `.eml` fixture names under [`BOUNCES_DIR`].
+    #[serde(default)]
+    pub mails: Vec<String>,
+    #[serde(default)]
+    pub observer: Vec<ObserverEvent>,
+    pub expect: Vec<Expectation>
+}
+
+/// A local `mail_messages` row.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeedMessage {
+    pub hash: String,
+    pub status: i32
+}
+
+/// Expected state of one hash; unset fields are not checked.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectation {
+    pub hash: String,
+    #[serde(default)]
+    pub mail_status: Option<i32>,
+    /// Whether a bounce row exists at all.
+    #[serde(default)]
+    pub bounced: Option<bool>,
+    #[serde(default)]
+    pub tracked: Option<bool>,
+    #[serde(default)]
+    pub status_code: Option<String>,
+    #[serde(default)]
+    pub action: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>
+}
+
+impl Scenario {
+    /// Reads a YAML list of scenarios.
+    pub fn load(path: &Path) -> Result<Vec<Self>> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_yaml::from_str(&raw).with_context(|| format!("invalid scenarios {}", path.display()))
+    }
+
+    pub async fn run(
+        &self,
+        server: &TestServer
+    ) -> Result<()> {
+        for message in &self.messages {
+            server.seed_message(&message.hash, message.status).await?;
+        }
+        for mail in &self.mails {
+            let path = Path::new(BOUNCES_DIR).join(mail);
+            let body = tokio::fs::read(&path)
+                .await
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            server.send_mail(MAIL_FROM, MAIL_TO, &body).await?;
+        }
+        if !self.observer.is_empty() {
+            let mut observer = FakeObserver::connect(server.addr(), SCENARIO_SOURCE).await?;
+            for event in &self.observer {
+                observer.send(event).await?;
+            }
+        }
+        for expectation in &self.expect {
+            server
+                .wait_for_state(&expectation.hash, |state| expectation.matches(state))
+                .await
+                .with_context(|| format!("scenario failed: {}", self.name))?;
+        }
+        Ok(())
+    }
+}
+
+impl Expectation {
+    pub fn matches(
+        &self,
+        state: &MessageState
+    ) -> bool {
+        fn same<T: PartialEq>(
+            expected: &Option<T>,
+            actual: Option<&T>
+        ) -> bool {
+            expected.as_ref().is_none_or(|expected| actual == Some(expected))
+        }
+
+        let bounce = state.bounce.as_ref();
+        let checks_bounce = self.tracked.is_some()
+            || self.status_code.is_some()
+            || self.action.is_some()
+            || self.reason.is_some()
+            || self.category.is_some();
+        if checks_bounce && bounce.is_none() {
+            return false;
+        }
+
+        same(&self.mail_status, state.mail_status.as_ref())
+            && same(&self.bounced, Some(&bounce.is_some()))
+            && same(&self.tracked, bounce.map(|bounce| &bounce.tracked))
+            && same(&self.status_code, bounce.map(|bounce| &bounce.status_code))
+            && same(&self.action, bounce.and_then(|bounce| bounce.action.as_ref()))
+            && same(&self.reason, bounce.and_then(|bounce| bounce.reason.as_ref()))
+            && same(&self.category, bounce.and_then(|bounce| bounce.category.as_ref()))
+    }
+}
@@ -0,0 +1,223 @@
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use bouncer_core::{Config, Server};
+use bouncer_proto::query::{
+    MessageState, QUERY_KIND, QUERY_RESPONSE_KIND, QueryRequest, QueryResponse
+};
+use bouncer_proto::{
+    Header, decode_header_json, encode_header_json, read_ack_async, read_frame_async,
+    write_frame_async
+};
+use serde_yaml::{Mapping, Value};
+use sqlx::SqlitePool;
+use sqlx::sqlite::SqliteConnectOptions;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, sleep};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{POLL_INTERVAL, WAIT_TIMEOUT};
+
+const MAX_HEADER_LEN: u32 = 64 * 1024;
+const MAX_BODY_LEN: u64 = 16 * 1024 * 1024;
+
+/// `bouncer-server` running in-process on a free local port, over a spool
+/// and sqlite database in a fresh temp directory.
+pub struct TestServer {
+    addr: String,
+    root: PathBuf,
+    db: SqlitePool,
+    shutdown: CancellationToken,
+    task: JoinHandle<Result<()>>
+}
+
+impl TestServer {
+    pub async fn start() -> Result<Self> {
+        Self::start_with("").await
+    }
+
+    /// Same as [`TestServer::start`]; top-level keys of the `overrides` YAML
+    /// replace the generated config, e.g. `suppression: {enabled: true}`.
+    pub async fn start_with(overrides: &str) -> Result<Self> {
+        let root = std::env::temp_dir().join(format!("bouncer-harness-{}", Uuid::now_v7()));
+        let addr = free_local_addr()?;
+        let database_url = format!("sqlite:{}", root.join("bouncer.sqlite").display());
+
+        let mut config = Mapping::new();
+        config.insert("listen".into(), addr.clone().into());
+        config.insert("spool".into(), root.join("spool").display().to_string().into());
+        config.insert("database_url".into(), database_url.clone().into());
+        // The notify watcher picks files up first; the scan is a backstop.
+        config.insert("incoming_scan_secs".into(), 1.into());
+        if !overrides.trim().is_empty() {
+            let overrides: Mapping =
+                serde_yaml::from_str(overrides).context("invalid harness config overrides")?;
+            config.extend(overrides);
+        }
+        let config: Config =
+            serde_yaml::from_value(Value::Mapping(config)).context("invalid harness config")?;
+
+        let shutdown = CancellationToken::new();
+        let server = Server::build(config, shutdown.clone()).await?;
+        // Opened after `build`, so the migrations already ran.
+        let options = SqliteConnectOptions::from_str(&database_url)
+            .context("invalid harness database_url")?
+            .busy_timeout(Duration::from_secs(5));
+        let db = SqlitePool::connect_with(options).await.context("failed to open harness db")?;
+        let task = tokio::spawn(server.run());
+
+        let server = Self { addr, root, db, shutdown, task };
+        server.wait_listening().await?;
+        Ok(server)
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Temp directory holding `spool/` and `bouncer.sqlite`.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Adds a local `mail_messages` row, as the sending application would.
+    pub async fn seed_message(
+        &self,
+        hash: &str,
+        status: i32
+    ) -> Result<()> {
+        sqlx::query("INSERT INTO mail_messages (hash, status) VALUES (?, ?)")
+            .bind(hash)
+            .bind(status)
+            .execute(&self.db)
+            .await
+            .with_context(|| format!("failed to seed mail_messages: hash={hash}"))?;
+        Ok(())
+    }
+
+    /// Sends `body` as a mail frame, like `bouncer-client` does for postfix.
+    pub async fn send_mail(
+        &self,
+        from: &str,
+        to: &str,
+        body: &[u8]
+    ) -> Result<()> {
+        let mut stream = self.connect().await?;
+        let header = Header {
+            from: from.to_string(),
+            to: to.to_string(),
+            kind: None,
+            source: None,
+            traceparent: None,
+            queue_id: None,
+            tenant: None,
+            checksum: None
+        };
+        let header = encode_header_json(&header).context("failed to encode header")?;
+        write_frame_async(&mut stream, &header, body).await.context("failed to send mail")?;
+        read_ack_async(&mut stream).await.context("no ACK for mail frame")
+    }
+
+    pub async fn query(
+        &self,
+        request: &QueryRequest
+    ) -> Result<QueryResponse> {
+        let mut stream = self.connect().await?;
+        let header = Header {
+            from: "bouncer-harness".to_string(),
+            to: self.addr.clone(),
+            kind: Some(QUERY_KIND.to_string()),
+            source: None,
+            traceparent: None,
+            queue_id: None,
+            tenant: None,
+            checksum: None
+        };
+        let header = encode_header_json(&header).context("failed to encode header")?;
+        let body = serde_json::to_vec(request).context("failed to encode query")?;
+        write_frame_async(&mut stream, &header, &body).await.context("failed to send query")?;
+
+        let (header, body) = read_frame_async(&mut stream, MAX_HEADER_LEN, MAX_BODY_LEN)
+            .await
+            .context("failed to read query response")?;
+        let header = decode_header_json(&header).context("failed to decode response header")?;
+        if header.kind.as_deref() != Some(QUERY_RESPONSE_KIND) {
+            bail!("unexpected response kind: {}", header.kind.as_deref().unwrap_or("-"));
+        }
+        serde_json::from_slice(&body).context("failed to decode query response")
+    }
+
+    pub async fn message_state(
+        &self,
+        hash: &str
+    ) -> Result<MessageState> {
+        match self.query(&QueryRequest::Status { hash: hash.to_string() }).await? {
+            QueryResponse::Status(state) => Ok(state),
+            QueryResponse::Error { message } => bail!("status query failed: {message}"),
+            other => bail!("unexpected status response: {other:?}")
+        }
+    }
+
+    /// Polls the state of `hash` until `done` accepts it; returns the last
+    /// state seen on timeout as the error.
+    pub async fn wait_for_state(
+        &self,
+        hash: &str,
+        done: impl Fn(&MessageState) -> bool
+    ) -> Result<MessageState> {
+        let deadline = Instant::now() + WAIT_TIMEOUT;
+        loop {
+            let state = self.message_state(hash).await?;
+            if done(&state) {
+                return Ok(state);
+            }
+            if Instant::now() >= deadline {
+                bail!("timed out waiting for hash={hash}, last state: {state:?}");
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Stops the server and removes its temp directory.
+    pub async fn stop(mut self) -> Result<()> {
+        self.shutdown.cancel();
+        let result = (&mut self.task).await.context("server task join failed")?;
+        self.db.close().await;
+        tokio::fs::remove_dir_all(&self.root).await.ok();
+        result
+    }
+
+    async fn connect(&self) -> Result<TcpStream> {
+        TcpStream::connect(&self.addr)
+            .await
+            .with_context(|| format!("tcp connect failed: {}", self.addr))
+    }
+
+    async fn wait_listening(&self) -> Result<()> {
+        let deadline = Instant::now() + WAIT_TIMEOUT;
+        while self.connect().await.is_err() {
+            if self.task.is_finished() || Instant::now() >= deadline {
+                bail!("server did not start listening on {}", self.addr);
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// A local address that was free a moment ago; the server binds it itself.
+fn free_local_addr() -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").context("failed to find a free port")?;
+    Ok(listener.local_addr().context("free port has no local addr")?.to_string())
+}
@@ -0,0 +1,84 @@
+use std::net::UdpSocket;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use bouncer_harness::{FIXTURES_DIR, FakeMta, MockIngest, Scenario, TestServer};
+use bouncer_observer::ObserverConfig;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+const BOUNCED_HASH: &str = "cccccccccccccccccccccccccccccccc";
+const DELIVERED_HASH: &str = "dddddddddddddddddddddddddddddddd";
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fixture_scenarios_reach_the_database() -> Result<()> {
+    let scenarios = Scenario::load(&Path::new(FIXTURES_DIR).join("scenarios.yaml"))?;
+    assert!(!scenarios.is_empty());
+    for scenario in scenarios {
+        let server = TestServer::start().await?;
+        scenario.run(&server).await?;
+        server.stop().await?;
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn observer_publishes_replayed_postfix_log() -> Result<()> {
+    let ingest = MockIngest::start().await?;
+    let (mta, observer, shutdown) = start_observer(&ingest.addr().to_string()).await?;
+
+    assert_eq!(mta.replay(&Path::new(FIXTURES_DIR).join("postfix.log")).await?, 4);
+    let events = ingest.wait_for_kind("observer_event", 2).await?;
+    let events = events.iter().map(|frame| frame.json()).collect::<Result<Vec<_>>>()?;
+    assert_eq!(events[0]["hash"], BOUNCED_HASH);
+    assert_eq!(events[0]["queue_id"], "4F2A1B3C");
+    assert_eq!(events[0]["status_code"], "5.1.1");
+    assert_eq!(events[0]["observed_at_unix"], 1_772_611_203);
+    assert_eq!(events[1]["hash"], DELIVERED_HASH);
+    assert_eq!(events[1]["status_code"], "2.0.0");
+    assert!(events[1]["sequence"].as_u64() > events[0]["sequence"].as_u64());
+    assert_eq!(ingest.frames()[0].kind(), Some("register"));
+
+    shutdown.cancel();
+    observer.await??;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn postfix_log_updates_messages_through_observer_and_server() -> Result<()> {
+    let server = TestServer::start().await?;
+    server.seed_message(BOUNCED_HASH, 3).await?;
+    server.seed_message(DELIVERED_HASH, 3).await?;
+    let (mta, observer, shutdown) = start_observer(server.addr()).await?;
+
+    mta.replay(&Path::new(FIXTURES_DIR).join("postfix.log")).await?;
+    let bounced =
+        server.wait_for_state(BOUNCED_HASH, |state| state.mail_status == Some(-7)).await?;
+    let bounce = bounced.bounce.context("bounce row missing")?;
+    assert!(bounce.tracked);
+    assert_eq!(bounce.status_code, "5.1.1");
+    assert_eq!(bounce.category.as_deref(), Some("hard"));
+    let delivered =
+        server.wait_for_state(DELIVERED_HASH, |state| state.mail_status == Some(7)).await?;
+    assert!(delivered.bounce.is_none());
+
+    shutdown.cancel();
+    observer.await??;
+    server.stop().await
+}
+
+/// Runs `bouncer-observer` against `server` on a free UDP port, and a fake
+/// MTA that feeds it once it listens.
+async fn start_observer(
+    server: &str
+) -> Result<(FakeMta, JoinHandle<Result<()>>, CancellationToken)> {
+    let listen_udp = UdpSocket::bind("127.0.0.1:0")?.local_addr()?;
+    let config: ObserverConfig = serde_yaml::from_str(&format!(
+        "listen_udp: \"{listen_udp}\"\nserver: \"{server}\"\nsource: harness-mx\nheartbeat_secs: 0\n"
+    ))?;
+    let shutdown = CancellationToken::new();
+    let observer = tokio::spawn(bouncer_observer::run_observer(config, shutdown.clone()));
+    let mta = FakeMta::connect(listen_udp).await?;
+    mta.wait_ready().await?;
+    Ok((mta, observer, shutdown))
+}
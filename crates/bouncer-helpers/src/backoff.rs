@@ -0,0 +1,185 @@
+//! Jittered heartbeat intervals, decorrelated reconnect backoff and outage
+//! tracking.
+//!
+//! Agents deployed with identical `heartbeat_secs` tick in lockstep and, after
+//! a server restart, all reconnect within the same few milliseconds. Spreading
+//! heartbeats with jitter and drawing reconnect delays from a decorrelated
+//! backoff keeps a fleet from hitting the server as one burst.
+
+use std::time::{Duration, Instant};
+
+use crate::clock::SharedClock;
+
+/// Upper bound for heartbeat jitter, as a percentage of the base interval.
+pub const MAX_JITTER_PCT: u8 = 50;
+
+/// Returns `base` shifted by a uniform random offset of up to `jitter_pct`
+/// percent in either direction.
+pub fn jittered(
+    base: Duration,
+    jitter_pct: u8
+) -> Duration {
+    let pct = u64::from(jitter_pct.min(MAX_JITTER_PCT));
+    let base_ms = duration_millis(base);
+    let spread = base_ms * pct / 100;
+    if spread == 0 {
+        return base;
+    }
+
+    Duration::from_millis(fastrand::u64(base_ms - spread..=base_ms + spread))
+}
+
+/// Returns a uniform random delay in `[0, base)` for the first tick, so
+/// agents started together do not share a heartbeat phase.
+pub fn initial_delay(base: Duration) -> Duration {
+    let base_ms = duration_millis(base);
+    if base_ms == 0 {
+        return Duration::ZERO;
+    }
+
+    Duration::from_millis(fastrand::u64(0..base_ms))
+}
+
+/// Decorrelated jitter backoff (`sleep = min(cap, rand(base, prev * 3))`).
+///
+/// Each delay depends on the previous one rather than on an attempt counter,
+/// so clients that failed together drift apart instead of retrying in waves.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,
+    cap: Duration,
+    previous: Duration
+}
+
+impl Backoff {
+    pub fn new(
+        base: Duration,
+        cap: Duration
+    ) -> Self {
+        let base = base.max(Duration::from_millis(1));
+        Self { base, cap: cap.max(base), previous: base }
+    }
+
+    /// Draws the next delay and remembers it for the following draw.
+    pub fn next_delay(&mut self) -> Duration {
+        let low = duration_millis(self.base);
+        let high = duration_millis(self.previous).saturating_mul(3).max(low);
+        let delay = Duration::from_millis(fastrand::u64(low..=high)).min(self.cap);
+        self.previous = delay;
+        delay
+    }
+
+    /// Starts over from `base` after a successful connect or send.
+    pub fn reset(&mut self) {
+        self.previous = self.base;
+    }
+}
+
+/// How long an agent has been without a server connection: the current
+/// outage, its failed connects, and the total time disconnected since start.
+#[derive(Debug, Clone)]
+pub struct Outage {
+    clock: SharedClock,
+    since: Option<Instant>,
+    failed_connects: u32,
+    total: Duration
+}
+
+impl Outage {
+    pub fn new(clock: SharedClock) -> Self {
+        Self { clock, since: None, failed_connects: 0, total: Duration::ZERO }
+    }
+
+    /// Marks the connection lost, unless an outage is already running.
+    pub fn begin(&mut self) {
+        if self.since.is_none() {
+            self.since = Some(self.clock.now());
+        }
+    }
+
+    /// Counts a failed connect and returns the count within this outage.
+    pub fn connect_failed(&mut self) -> u32 {
+        self.begin();
+        self.failed_connects += 1;
+        self.failed_connects
+    }
+
+    /// Ends the outage on reconnect; returns its length and failed connects,
+    /// or `None` when the connection was not down.
+    pub fn end(&mut self) -> Option<(Duration, u32)> {
+        let since = self.since.take()?;
+        let length = self.clock.now().saturating_duration_since(since);
+        self.total += length;
+        Some((length, std::mem::take(&mut self.failed_connects)))
+    }
+
+    /// Time disconnected since start, the running outage included.
+    pub fn total(&self) -> Duration {
+        self.total
+            + self
+                .since
+                .map_or(Duration::ZERO, |since| self.clock.now().saturating_duration_since(since))
+    }
+}
+
+fn duration_millis(duration: Duration) -> u64 {
+    duration.as_millis().min(u128::from(u64::MAX)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{Backoff, Outage, initial_delay, jittered};
+    use crate::clock::{ManualClock, SharedClock};
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let base = Duration::from_secs(30);
+        for _ in 0..1000 {
+            let delay = jittered(base, 20);
+            assert!(delay >= Duration::from_secs(24) && delay <= Duration::from_secs(36));
+            assert!(initial_delay(base) < base);
+        }
+        assert_eq!(jittered(base, 0), base);
+    }
+
+    #[test]
+    fn backoff_grows_up_to_cap_and_resets() {
+        let base = Duration::from_millis(250);
+        let cap = Duration::from_secs(5);
+        let mut backoff = Backoff::new(base, cap);
+
+        let mut previous = base;
+        for _ in 0..100 {
+            let delay = backoff.next_delay();
+            assert!(delay >= base && delay <= cap);
+            assert!(delay <= previous * 3);
+            previous = delay;
+        }
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= base * 3);
+    }
+
+    #[test]
+    fn outage_measures_time_disconnected() {
+        let clock = Arc::new(ManualClock::at_unix(1_700_000_000));
+        let mut outage = Outage::new(clock.clone() as SharedClock);
+        assert_eq!(outage.end(), None);
+
+        outage.begin();
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(outage.connect_failed(), 1);
+        assert_eq!(outage.connect_failed(), 2);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(outage.total(), Duration::from_secs(15));
+        assert_eq!(outage.end(), Some((Duration::from_secs(15), 2)));
+
+        assert_eq!(outage.connect_failed(), 1);
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(outage.end(), Some((Duration::from_secs(3), 1)));
+        assert_eq!(outage.total(), Duration::from_secs(18));
+    }
+}
@@ -0,0 +1,86 @@
+//! Time source shared by the server and the agents.
+//!
+//! Production code uses [`SystemClock`]. Tests swap in a [`ManualClock`] and
+//! advance it explicitly, so TTL expiry, retention and backoff can be
+//! exercised without sleeping.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Monotonic time, for elapsed/TTL comparisons.
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, for timestamps and calendar math.
+    fn system_now(&self) -> SystemTime;
+
+    /// Wall-clock time as whole seconds since the unix epoch (0 before 1970).
+    fn unix_secs(&self) -> u64 {
+        self.system_now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+    }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Returns the process clock backed by [`SystemClock`].
+pub fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Clock that only moves when [`ManualClock::advance`] is called.
+///
+/// Both the monotonic and the wall-clock readings move by the same offset.
+#[derive(Debug)]
+pub struct ManualClock {
+    instant: Instant,
+    system: SystemTime,
+    offset: Mutex<Duration>
+}
+
+impl ManualClock {
+    /// Starts the clock at wall-clock time `system`.
+    pub fn new(system: SystemTime) -> Self {
+        Self { instant: Instant::now(), system, offset: Mutex::new(Duration::ZERO) }
+    }
+
+    /// Starts the clock at `secs` seconds after the unix epoch.
+    pub fn at_unix(secs: u64) -> Self {
+        Self::new(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    pub fn advance(
+        &self,
+        by: Duration
+    ) {
+        let mut offset = self.offset.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        *offset += by;
+    }
+
+    fn offset(&self) -> Duration {
+        *self.offset.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.instant + self.offset()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.system + self.offset()
+    }
+}
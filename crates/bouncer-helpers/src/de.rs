@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use serde::de::Error as _;
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serializer};
 
 pub fn deserialize_optional_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
 where
@@ -29,6 +29,29 @@ where
     }
 }
 
+/// A list of durations, each given as seconds or as text like `"2m"`.
+pub fn deserialize_durations<'de, D>(deserializer: D) -> Result<Vec<Duration>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawDuration {
+        Seconds(u64),
+        Text(String)
+    }
+
+    Vec::<RawDuration>::deserialize(deserializer)?
+        .into_iter()
+        .map(|raw| match raw {
+            RawDuration::Seconds(secs) => Ok(Duration::from_secs(secs)),
+            RawDuration::Text(value) => {
+                humantime::parse_duration(value.trim()).map_err(D::Error::custom)
+            }
+        })
+        .collect()
+}
+
 pub fn deserialize_duration<'de, D, T>(deserializer: D) -> Result<T, D::Error>
 where
     D: Deserializer<'de>,
@@ -42,3 +65,40 @@ where
         None => Ok(T::default())
     }
 }
+
+/// Writes a duration the way [`deserialize_duration`] reads it (`"1h 30m"`),
+/// for configs printed back as YAML.
+pub fn serialize_duration<S>(
+    duration: &Duration,
+    serializer: S
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer
+{
+    serializer.collect_str(&humantime::format_duration(*duration))
+}
+
+pub fn serialize_optional_duration<S>(
+    duration: &Option<Duration>,
+    serializer: S
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer
+{
+    match duration {
+        Some(duration) => serialize_duration(duration, serializer),
+        None => serializer.serialize_none()
+    }
+}
+
+pub fn serialize_durations<S>(
+    durations: &[Duration],
+    serializer: S
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer
+{
+    serializer.collect_seq(
+        durations.iter().map(|duration| humantime::format_duration(*duration).to_string())
+    )
+}
@@ -0,0 +1,86 @@
+//! SIGUSR1/SIGUSR2 for looking into a running process.
+//!
+//! During an incident a restart loses the state worth looking at, so the
+//! server switches to [`DEBUG_FILTER`](crate::logging::DEBUG_FILTER) on
+//! SIGUSR1 and back to its configured filter on the next one, and logs its
+//! diagnostics on SIGUSR2.
+
+use tracing::{debug, warn};
+
+use crate::logging::{self, DEBUG_FILTER, LogFilterError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugSignal {
+    /// SIGUSR1.
+    ToggleDebugLogging,
+    /// SIGUSR2.
+    DumpDiagnostics
+}
+
+/// SIGUSR1 and SIGUSR2 streams; never fire where signals are unavailable.
+pub struct DebugSignals {
+    #[cfg(unix)]
+    usr1: Option<tokio::signal::unix::Signal>,
+    #[cfg(unix)]
+    usr2: Option<tokio::signal::unix::Signal>
+}
+
+impl DebugSignals {
+    pub fn install() -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+
+            let install = |kind: SignalKind, name: &str| {
+                signal(kind)
+                    .inspect_err(|err| warn!("failed to install {name} handler: error={err}"))
+                    .ok()
+            };
+            Self {
+                usr1: install(SignalKind::user_defined1(), "SIGUSR1"),
+                usr2: install(SignalKind::user_defined2(), "SIGUSR2")
+            }
+        }
+
+        #[cfg(not(unix))]
+        Self {}
+    }
+
+    /// Waits for the next SIGUSR1 or SIGUSR2.
+    pub async fn recv(&mut self) -> DebugSignal {
+        #[cfg(unix)]
+        {
+            async fn next(signal: &mut Option<tokio::signal::unix::Signal>) -> Option<()> {
+                match signal {
+                    Some(signal) => signal.recv().await,
+                    None => None
+                }
+            }
+
+            let Self { usr1, usr2 } = self;
+            tokio::select! {
+                Some(()) = next(usr1) => return DebugSignal::ToggleDebugLogging,
+                Some(()) = next(usr2) => return DebugSignal::DumpDiagnostics,
+                else => {}
+            }
+        }
+
+        std::future::pending().await
+    }
+}
+
+/// Switches debug logging on or off and logs the switch, at warn so it shows
+/// under the configured filter too.
+pub fn apply_debug_logging(debug: bool) {
+    if !debug {
+        warn!("debug logging disabled, restoring configured log filter");
+    }
+    match logging::set_debug_logging(debug) {
+        Ok(()) if debug => warn!("debug logging enabled: filter={DEBUG_FILTER}"),
+        Ok(()) => {}
+        Err(LogFilterError::NotInstalled) => {
+            debug!("debug logging not switched: subscriber not installed by bouncer")
+        }
+        Err(err) => warn!("debug logging not switched: error={err}")
+    }
+}
@@ -0,0 +1,221 @@
+//! Stable `ERROR_CODE` values shared by the server and the agents.
+//!
+//! Notable warnings and errors are logged through [`coded_warn!`] and
+//! [`coded_error!`]. The code leads the message as `ERROR_CODE=<CODE>`, which
+//! grep-based alerts and `bouncer-admin support-bundle` match, and is attached
+//! as the `error_code` field, which journald stores as `ERROR_CODE`.
+//!
+//! Codes are part of the operator contract: never rename or reuse one, add a
+//! new variant instead.
+
+use std::fmt;
+
+#[doc(hidden)]
+pub use tracing as __tracing;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// A startup diagnostics check warned or failed.
+    StartupCheck,
+    /// `BOUNCER_FAULTS` armed fault injection.
+    FaultsArmed,
+    /// The database lacks optional columns; some features are off.
+    DbSchemaDegraded,
+    /// A TCP client connection failed before its frame was acknowledged.
+    ClientIngestFailed,
+    /// The incoming/ file watcher stopped; only the periodic scan remains.
+    NotifyWatcherFailed,
+    /// A spooled message could not be processed and stays for retry.
+    MessageProcessingFailed,
+    /// A spool file could not be moved out of processing/.
+    SpoolFinalizeFailed,
+    /// A worker task panicked.
+    WorkerJoinFailed,
+    /// A registered observer/journal source stopped reporting.
+    SourceSilent,
+    /// A bounce from IMAP references a hash unknown to `mail_messages`.
+    ImapHashNotFoundInDb,
+    /// An IMAP message was not a delivery report and was marked seen.
+    ImapDiscardedNotDelivery,
+    /// An IMAP delivery report had no hash and was marked seen.
+    ImapDiscardedMissingHash,
+    /// An IMAP message could not be parsed.
+    ImapParseFailed,
+    /// A parsed IMAP bounce could not be written to the database.
+    ImapDbUpsertFailed,
+    /// An IMAP processing task panicked.
+    ImapTaskJoinFailed,
+    /// An agent dropped a delivery event because its queue was full.
+    EventQueueFull,
+    /// An agent could not encode a delivery event.
+    EventEncodeFailed,
+    /// An agent gave up publishing a delivery event to the server.
+    EventPublishFailed,
+    /// An agent lost its server connection; events stay queued while it
+    /// keeps reconnecting.
+    ServerUnreachable,
+    /// bouncer-journal could not read the systemd journal.
+    JournalReadFailed,
+    /// An embedded SMTP session failed or could not spool its message.
+    SmtpSessionFailed,
+    /// An `observer_event` body could not be decoded and was quarantined.
+    ObserverEventQuarantined,
+    /// A spooled bounce failed the `authentication` checks.
+    BounceUnauthenticated,
+    /// An applied bounce could not be copied to the `archive`.
+    BounceArchiveFailed,
+    /// An agent evicted queue mappings to stay within `mapping_capacity`.
+    QueueMapFull,
+    /// A worker took longer than `dispatcher.slow_file_threshold` on a spool file.
+    SlowSpoolFile,
+    /// The database stayed unreachable through all retries; the circuit
+    /// breaker paused spool workers.
+    DbUnavailable,
+    /// The ingest listener dropped a peer outside `listen_allow`.
+    PeerRejected,
+    /// An `alerts` rule fired: a domain's bounce rate crossed its threshold.
+    BounceRateAlert,
+    /// An alert could not be delivered to a webhook or exec sink.
+    AlertSinkFailed,
+    /// The ingest listener rejected a frame over `max_header_bytes` or
+    /// `max_body_bytes`.
+    FrameTooLarge,
+    /// The ingest listener rejected a frame whose body failed its checksum,
+    /// or that had none under `require_frame_checksum`.
+    FrameChecksumFailed,
+    /// A `hooks` command failed, timed out or was dropped on a full queue.
+    HookFailed
+}
+
+impl ErrorCode {
+    pub const ALL: [Self; 33] = [
+        Self::StartupCheck,
+        Self::FaultsArmed,
+        Self::DbSchemaDegraded,
+        Self::ClientIngestFailed,
+        Self::NotifyWatcherFailed,
+        Self::MessageProcessingFailed,
+        Self::SpoolFinalizeFailed,
+        Self::WorkerJoinFailed,
+        Self::SourceSilent,
+        Self::ImapHashNotFoundInDb,
+        Self::ImapDiscardedNotDelivery,
+        Self::ImapDiscardedMissingHash,
+        Self::ImapParseFailed,
+        Self::ImapDbUpsertFailed,
+        Self::ImapTaskJoinFailed,
+        Self::EventQueueFull,
+        Self::EventEncodeFailed,
+        Self::EventPublishFailed,
+        Self::ServerUnreachable,
+        Self::JournalReadFailed,
+        Self::SmtpSessionFailed,
+        Self::ObserverEventQuarantined,
+        Self::BounceUnauthenticated,
+        Self::BounceArchiveFailed,
+        Self::QueueMapFull,
+        Self::SlowSpoolFile,
+        Self::DbUnavailable,
+        Self::PeerRejected,
+        Self::BounceRateAlert,
+        Self::AlertSinkFailed,
+        Self::FrameTooLarge,
+        Self::FrameChecksumFailed,
+        Self::HookFailed
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::StartupCheck => "STARTUP_CHECK",
+            Self::FaultsArmed => "FAULTS_ARMED",
+            Self::DbSchemaDegraded => "DB_SCHEMA_DEGRADED",
+            Self::ClientIngestFailed => "CLIENT_INGEST_FAILED",
+            Self::NotifyWatcherFailed => "NOTIFY_WATCHER_FAILED",
+            Self::MessageProcessingFailed => "MESSAGE_PROCESSING_FAILED",
+            Self::SpoolFinalizeFailed => "SPOOL_FINALIZE_FAILED",
+            Self::WorkerJoinFailed => "WORKER_JOIN_FAILED",
+            Self::SourceSilent => "SOURCE_SILENT",
+            Self::ImapHashNotFoundInDb => "IMAP_HASH_NOT_FOUND_IN_DB",
+            Self::ImapDiscardedNotDelivery => "IMAP_DISCARDED_NOT_DELIVERY",
+            Self::ImapDiscardedMissingHash => "IMAP_DISCARDED_MISSING_HASH",
+            Self::ImapParseFailed => "IMAP_PARSE_FAILED",
+            Self::ImapDbUpsertFailed => "IMAP_DB_UPSERT_FAILED",
+            Self::ImapTaskJoinFailed => "IMAP_TASK_JOIN_FAILED",
+            Self::EventQueueFull => "EVENT_QUEUE_FULL",
+            Self::EventEncodeFailed => "EVENT_ENCODE_FAILED",
+            Self::EventPublishFailed => "EVENT_PUBLISH_FAILED",
+            Self::ServerUnreachable => "SERVER_UNREACHABLE",
+            Self::JournalReadFailed => "JOURNAL_READ_FAILED",
+            Self::SmtpSessionFailed => "SMTP_SESSION_FAILED",
+            Self::ObserverEventQuarantined => "OBSERVER_EVENT_QUARANTINED",
+            Self::BounceUnauthenticated => "BOUNCE_UNAUTHENTICATED",
+            Self::BounceArchiveFailed => "BOUNCE_ARCHIVE_FAILED",
+            Self::QueueMapFull => "QUEUE_MAP_FULL",
+            Self::SlowSpoolFile => "SLOW_SPOOL_FILE",
+            Self::DbUnavailable => "DB_UNAVAILABLE",
+            Self::PeerRejected => "PEER_REJECTED",
+            Self::BounceRateAlert => "BOUNCE_RATE_ALERT",
+            Self::AlertSinkFailed => "ALERT_SINK_FAILED",
+            Self::FrameTooLarge => "FRAME_TOO_LARGE",
+            Self::FrameChecksumFailed => "FRAME_CHECKSUM_FAILED",
+            Self::HookFailed => "HOOK_FAILED"
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `warn!` with an [`ErrorCode`]: `coded_warn!(ErrorCode::X, "text: key={}", value)`.
+#[macro_export]
+macro_rules! coded_warn {
+    ($code:expr, $($arg:tt)+) => {{
+        let code: $crate::error_code::ErrorCode = $code;
+        $crate::error_code::__tracing::warn!(
+            error_code = code.as_str(),
+            "ERROR_CODE={} {}",
+            code.as_str(),
+            format_args!($($arg)+)
+        )
+    }};
+}
+
+/// `error!` with an [`ErrorCode`]; see [`coded_warn!`].
+#[macro_export]
+macro_rules! coded_error {
+    ($code:expr, $($arg:tt)+) => {{
+        let code: $crate::error_code::ErrorCode = $code;
+        $crate::error_code::__tracing::error!(
+            error_code = code.as_str(),
+            "ERROR_CODE={} {}",
+            code.as_str(),
+            format_args!($($arg)+)
+        )
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::ErrorCode;
+
+    #[test]
+    fn codes_are_unique_screaming_snake_case() {
+        let mut seen = HashSet::new();
+        for code in ErrorCode::ALL {
+            let text = code.as_str();
+            assert!(seen.insert(text), "duplicate code {text}");
+            assert!(
+                text.chars().all(|ch| ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '_'),
+                "{text}"
+            );
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! `*`/`?` wildcard matching for journald units, syslog identifiers, tailed
+//! log file names and alert domains.
+
+pub fn is_glob(value: &str) -> bool {
+    value.contains(['*', '?'])
+}
+
+/// Matches `*` (any run) and `?` (one char), ignoring ASCII case.
+pub fn glob_match(
+    pattern: &str,
+    value: &str
+) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c.eq_ignore_ascii_case(&value[v]) => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, consumed)) => {
+                    p = star + 1;
+                    v = consumed + 1;
+                    backtrack = Some((star, consumed + 1));
+                }
+                None => return false
+            }
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, is_glob};
+
+    #[test]
+    fn matches_stars_and_single_chars() {
+        assert!(glob_match("postfix@*.service", "postfix@out1.service"));
+        assert!(glob_match("postfix@out?.service", "postfix@out2.service"));
+        assert!(glob_match("postfix-*/smtp", "Postfix-Out1/smtp"));
+        assert!(glob_match("mail.log*", "mail.log"));
+        assert!(!glob_match("postfix@*.service", "postfix.service"));
+        assert!(!glob_match("postfix@out?.service", "postfix@out10.service"));
+        assert!(is_glob("mail*.log") && !is_glob("mail.log"));
+    }
+}
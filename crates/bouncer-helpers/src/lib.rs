@@ -1,3 +1,16 @@
+pub mod backoff;
+pub mod clock;
+pub mod config_file;
 pub mod de;
+pub mod debug_signals;
+pub mod error_code;
+pub mod glob;
 pub mod logging;
+pub mod maillog;
+pub mod message_hash;
+pub mod oauth2;
+pub mod queue_map;
+pub mod reload;
+pub mod sequence;
 pub mod shutdown;
+pub mod state_store;
@@ -1,29 +1,121 @@
-#[cfg(target_os = "linux")]
+use std::collections::HashMap;
 use std::env;
+use std::sync::{Mutex, OnceLock};
 
-use tracing_subscriber::EnvFilter;
-#[cfg(target_os = "linux")]
+use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+use opentelemetry::{Context, global};
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use thiserror::Error;
+use tracing::{Span, Subscriber};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::filter::ParseError;
 use tracing_subscriber::layer::SubscriberExt;
-#[cfg(target_os = "linux")]
+use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+/// Header key of the W3C trace context carried in frame headers.
+const TRACEPARENT: &str = "traceparent";
 
+/// Environment variables that turn on OTLP span export; the exporter reads
+/// the endpoint (and `OTEL_EXPORTER_OTLP_HEADERS` etc.) from them itself.
+const OTLP_ENDPOINT_ENV: [&str; 2] =
+    ["OTEL_EXPORTER_OTLP_TRACES_ENDPOINT", "OTEL_EXPORTER_OTLP_ENDPOINT"];
+
+/// Filter installed while debug logging is switched on at runtime: debug
+/// for the bouncer crates, warnings for their dependencies.
+pub const DEBUG_FILTER: &str = "warn,bouncer=debug";
+
+/// Swaps the installed env filter; set once by [`init_logging`].
+static FILTER_RELOAD: OnceLock<FilterReload> = OnceLock::new();
+
+struct FilterReload {
+    handle: reload::Handle<EnvFilter, Registry>,
+    default_filter: String,
+    env_key: String,
+    state: Mutex<FilterState>
+}
+
+/// The filter asked for by the config, and whether [`DEBUG_FILTER`]
+/// overrides it.
+#[derive(Default)]
+struct FilterState {
+    configured: Option<String>,
+    debug: bool
+}
+
+impl FilterReload {
+    fn build(
+        &self,
+        directives: Option<&str>
+    ) -> Result<EnvFilter, LogFilterError> {
+        Ok(match directives {
+            Some(directives) => EnvFilter::try_new(directives)?,
+            None => build_env_filter(&self.default_filter, &self.env_key)
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LogFilterError {
+    #[error("invalid log filter: {0}")]
+    Invalid(#[from] ParseError),
+    #[error("log filter is not reloadable (logging not initialized by init_logging)")]
+    NotInstalled,
+    #[error("failed to swap log filter: {0}")]
+    Reload(#[from] reload::Error)
+}
+
+/// Flushes and shuts down the OTLP exporter when dropped; keep it alive in
+/// `main` for the lifetime of the process.
+#[must_use = "dropping the guard stops span export"]
+pub struct LoggingGuard {
+    provider: Option<SdkTracerProvider>
+}
+
+impl Drop for LoggingGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take()
+            && let Err(err) = provider.shutdown()
+        {
+            eprintln!("otlp exporter shutdown failed: {err}");
+        }
+    }
+}
+
+/// Installs the global subscriber: env filter, optional OTLP span export and
+/// journald (under systemd) or the stderr formatter.
 pub fn init_logging(
     default_filter: &str,
     env_key: &str,
     service_name: &str
-) {
-    #[cfg(not(target_os = "linux"))]
-    let _ = service_name;
+) -> LoggingGuard {
+    global::set_text_map_propagator(TraceContextPropagator::new());
 
-    let env_filter = build_env_filter(default_filter, env_key);
+    let (env_filter, handle) = reload::Layer::new(build_env_filter(default_filter, env_key));
+    let _ = FILTER_RELOAD.set(FilterReload {
+        handle,
+        default_filter: default_filter.to_string(),
+        env_key: env_key.to_string(),
+        state: Mutex::new(FilterState::default())
+    });
+    let (otel_layer, provider) = match otlp_layer(service_name) {
+        Some((layer, provider)) => (Some(layer), Some(provider)),
+        None => (None, None)
+    };
+    let guard = LoggingGuard { provider };
+    let registry = tracing_subscriber::registry().with(env_filter).with(otel_layer);
 
     #[cfg(target_os = "linux")]
     {
         if is_running_under_systemd() {
             match tracing_journald::layer() {
                 Ok(layer) => {
-                    tracing_subscriber::registry().with(env_filter).with(layer).init();
-                    return;
+                    registry.with(layer).init();
+                    return guard;
                 }
                 Err(err) => {
                     eprintln!(
@@ -34,7 +126,111 @@ pub fn init_logging(
         }
     }
 
-    tracing_subscriber::fmt().with_env_filter(env_filter).init();
+    registry.with(tracing_subscriber::fmt::layer()).init();
+    guard
+}
+
+/// Replaces the filter installed by [`init_logging`] with `directives`
+/// (`EnvFilter` syntax); `None` restores the startup filter from the env
+/// variable or default. While debug logging is on, the filter is only
+/// remembered and installed when it is switched off.
+pub fn set_log_filter(directives: Option<&str>) -> Result<(), LogFilterError> {
+    let reload = FILTER_RELOAD.get().ok_or(LogFilterError::NotInstalled)?;
+    let filter = reload.build(directives)?;
+    let mut state = reload.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if !state.debug {
+        reload.handle.reload(filter)?;
+    }
+    state.configured = directives.map(str::to_string);
+    Ok(())
+}
+
+/// Switches between [`DEBUG_FILTER`] and the configured filter.
+pub fn set_debug_logging(debug: bool) -> Result<(), LogFilterError> {
+    let reload = FILTER_RELOAD.get().ok_or(LogFilterError::NotInstalled)?;
+    let mut state = reload.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let filter = if debug {
+        EnvFilter::try_new(DEBUG_FILTER)?
+    } else {
+        reload.build(state.configured.as_deref())?
+    };
+    reload.handle.reload(filter)?;
+    state.debug = debug;
+    Ok(())
+}
+
+/// Whether [`set_debug_logging`] switched debug logging on.
+pub fn debug_logging() -> bool {
+    FILTER_RELOAD.get().is_some_and(|reload| {
+        reload.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).debug
+    })
+}
+
+/// Checks `directives` without installing them, for config validation.
+pub fn check_log_filter(directives: &str) -> Result<(), LogFilterError> {
+    EnvFilter::try_new(directives)?;
+    Ok(())
+}
+
+/// Builds the OTLP/HTTP span layer when an OTLP endpoint is configured.
+fn otlp_layer<S>(
+    service_name: &str
+) -> Option<(OpenTelemetryLayer<S, SdkTracer>, SdkTracerProvider)>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>
+{
+    if !OTLP_ENDPOINT_ENV.iter().any(|key| env::var_os(key).is_some_and(|value| !value.is_empty()))
+    {
+        return None;
+    }
+
+    let exporter = match SpanExporter::builder().with_http().build() {
+        Ok(exporter) => exporter,
+        Err(err) => {
+            eprintln!("{service_name}: otlp exporter init failed, span export disabled: {err}");
+            return None;
+        }
+    };
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name.to_string()).build())
+        .build();
+    let tracer = provider.tracer(service_name.to_string());
+
+    Some((tracing_opentelemetry::layer().with_tracer(tracer), provider))
+}
+
+/// W3C `traceparent` of the current span, if it belongs to a sampled trace.
+///
+/// Senders put it into the frame header so the server can continue the trace.
+pub fn current_traceparent() -> Option<String> {
+    let context = Span::current().context();
+    if !context.span().span_context().is_valid() {
+        return None;
+    }
+
+    let mut carrier = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut carrier));
+    carrier.remove(TRACEPARENT)
+}
+
+/// Makes `span` a child of the remote span described by `traceparent`.
+///
+/// Malformed values are ignored and `span` stays a root span.
+pub fn set_remote_parent(
+    span: &Span,
+    traceparent: Option<&str>
+) {
+    let Some(traceparent) = traceparent else {
+        return;
+    };
+
+    let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+    let context: Context =
+        global::get_text_map_propagator(|propagator| propagator.extract(&carrier));
+    if context.span().span_context().is_valid() {
+        let _ = span.set_parent(context);
+    }
 }
 
 fn build_env_filter(
@@ -51,3 +247,42 @@ fn build_env_filter(
 fn is_running_under_systemd() -> bool {
     env::var_os("JOURNAL_STREAM").is_some() || env::var_os("INVOCATION_ID").is_some()
 }
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::global;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use tracing::info_span;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::{current_traceparent, set_remote_parent};
+
+    #[test]
+    fn traceparent_round_trips_between_spans() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert_eq!(current_traceparent(), None);
+
+            let sender = info_span!("send");
+            let traceparent = sender.in_scope(current_traceparent).expect("sampled span");
+            let trace_id = traceparent.split('-').nth(1).unwrap().to_string();
+
+            let receiver = info_span!("ingest");
+            set_remote_parent(&receiver, Some(&traceparent));
+            let continued = receiver.in_scope(current_traceparent).expect("continued span");
+            assert_eq!(continued.split('-').nth(1), Some(trace_id.as_str()));
+            assert_ne!(continued, traceparent);
+
+            let orphan = info_span!("orphan");
+            set_remote_parent(&orphan, Some("garbage"));
+            let fresh = orphan.in_scope(current_traceparent).expect("root span");
+            assert_ne!(fresh.split('-').nth(1), Some(trace_id.as_str()));
+        });
+    }
+}
@@ -0,0 +1,318 @@
+//! Mail log records shared by the log agents (`bouncer-observer`,
+//! `bouncer-journal`): the [`ParsedSyslog`] records the correlators consume,
+//! the postfix message parser both agents use, and the field helpers of the
+//! observer's Exim and sendmail parsers.
+
+use crate::message_hash::HashFormat;
+
+const MAX_DIAGNOSTIC_LEN: usize = 512;
+const RELAY_HANDOFF_HOSTS: &[&str] = &["mxbg.nxmango.com"];
+
+/// One per-recipient outcome of an MTA delivery line.
+#[derive(Debug, Clone)]
+pub struct DeliveryLine {
+    /// Delivery agent that logged the line: `smtp`, `local`, `error`, ...
+    /// (the transport or mailer for Exim and sendmail).
+    pub service: String,
+    pub queue_id: String,
+    pub recipient: String,
+    pub smtp_status: String,
+    pub status_code: String,
+    pub action: String,
+    pub diagnostic: String
+}
+
+pub enum ParsedSyslog {
+    Cleanup {
+        queue_id: String,
+        hash: String
+    },
+    Delivery(DeliveryLine),
+    /// The MTA queued `notice_queue_id` as the sender notification for
+    /// `queue_id` (`postfix/bounce`, an Exim `<= <> R=` arrival, a sendmail
+    /// `DSN:` line).
+    Notification {
+        queue_id: String,
+        notice_queue_id: String
+    }
+}
+
+/// Postfix delivery agents whose per-recipient lines carry `status=`.
+const DELIVERY_SERVICES: &[&str] = &["smtp", "lmtp", "local", "virtual", "pipe", "error", "retry"];
+
+/// Parses the message of one postfix syslog line, logged by the delivery
+/// agent or daemon `service` (see [`postfix_service`]), into one of:
+/// - `ParsedSyslog::Cleanup { queue_id, hash }`
+/// - `ParsedSyslog::Delivery(DeliveryLine)`
+/// - `ParsedSyslog::Notification { queue_id, notice_queue_id }`
+///
+/// Correlation model of the log agents:
+/// 1. `postfix/cleanup` line provides `queue_id` + `message-id`.
+/// 2. A delivery agent line (`smtp`, `lmtp`, `local`, `virtual`, `pipe`,
+///    `error`, `retry`) provides delivery status for the same `queue_id`.
+/// 3. The agent joins both using `queue_id` and publishes the final event with
+///    your application hash.
+/// 4. `postfix/bounce` names the queue id of the notification sent back for
+///    `queue_id`; its own deliveries are not outcomes of the original message.
+///
+/// Example flow:
+/// - cleanup: `ABC123...: message-id=<9f...32chars...@example>`
+/// - smtp: `ABC123...: to=<u@d>, dsn=5.1.1, status=bounced (...)`
+/// - local: `ABC123...: to=<u@host>, relay=local, dsn=5.1.1, status=bounced (unknown user: "u")`
+/// - bounce: `ABC123...: sender non-delivery notification: DEF456...`
+pub fn parse_postfix_message(
+    service: &str,
+    message: &str,
+    hash_format: &HashFormat
+) -> Option<ParsedSyslog> {
+    if service.eq_ignore_ascii_case("cleanup") {
+        let (queue_id, hash) = parse_cleanup_message(message, hash_format)?;
+        return Some(ParsedSyslog::Cleanup { queue_id, hash });
+    }
+
+    if service.eq_ignore_ascii_case("bounce") {
+        let (queue_id, notice_queue_id) = parse_bounce_message(message)?;
+        return Some(ParsedSyslog::Notification { queue_id, notice_queue_id });
+    }
+
+    if DELIVERY_SERVICES.iter().any(|agent| service.eq_ignore_ascii_case(agent)) {
+        return parse_delivery_message(service, message).map(ParsedSyslog::Delivery);
+    }
+
+    None
+}
+
+/// Service of a postfix syslog program: `smtp` for `postfix/smtp`, and for
+/// instances such as `postfix-out1/smtp` or `postfix/submission/smtpd`.
+pub fn postfix_service(program: &str) -> Option<&str> {
+    let (name, service) = program.split_once('/')?;
+    name.starts_with("postfix").then(|| service.rsplit('/').next().unwrap_or(service))
+}
+
+/// Parses `postfix/cleanup` message and extracts:
+/// - postfix `queue_id`
+/// - application hash derived from `message-id=<...>`
+///
+/// This stage does not contain delivery outcome; it only builds correlation key
+/// (`queue_id -> hash`) for later `smtp` lines.
+fn parse_cleanup_message(
+    message: &str,
+    hash_format: &HashFormat
+) -> Option<(String, String)> {
+    let (queue_id, detail) = message.split_once(": ")?;
+    if !is_queue_id(queue_id) {
+        return None;
+    }
+
+    let marker = "message-id=<";
+    let start = detail.find(marker)? + marker.len();
+    let tail = &detail[start..];
+    let end = tail.find('>')?;
+    let message_id = &tail[..end];
+    let hash = hash_format.extract(message_id)?;
+
+    Some((queue_id.to_string(), hash))
+}
+
+/// Parses a delivery agent message and extracts recipient + status fields.
+///
+/// Returned event still carries `queue_id`; final hash is attached later by the
+/// queue map populated from `cleanup` lines.
+fn parse_delivery_message(
+    service: &str,
+    message: &str
+) -> Option<DeliveryLine> {
+    let (queue_id, detail) = message.split_once(": ")?;
+    if !is_queue_id(queue_id) {
+        return None;
+    }
+
+    let recipient = extract_between(detail, "to=<", ">")?.to_string();
+    let smtp_status = extract_token(detail, "status=")?.to_ascii_lowercase();
+    let relay_handoff =
+        extract_relay_host(detail).map(|host| is_relay_handoff_host(&host)).unwrap_or(false);
+
+    let default_status = default_status_code(&smtp_status, relay_handoff);
+    let status_code = extract_token(detail, "dsn=")
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| default_status.to_string());
+
+    let action = map_action(&smtp_status, relay_handoff).to_string();
+    let diagnostic = build_diagnostic(queue_id, detail);
+
+    Some(DeliveryLine {
+        service: service.to_ascii_lowercase(),
+        queue_id: queue_id.to_string(),
+        recipient,
+        smtp_status,
+        status_code,
+        action,
+        diagnostic
+    })
+}
+
+/// Parses `postfix/bounce` lines such as
+/// `ABC: sender non-delivery notification: DEF` into the original queue id and
+/// the queue id of the notification postfix generated for it.
+fn parse_bounce_message(message: &str) -> Option<(String, String)> {
+    let (queue_id, detail) = message.split_once(": ")?;
+    let (_, notice_queue_id) = detail.split_once("notification: ")?;
+    let notice_queue_id = notice_queue_id.trim();
+    if !is_queue_id(queue_id) || !is_queue_id(notice_queue_id) {
+        return None;
+    }
+
+    Some((queue_id.to_string(), notice_queue_id.to_string()))
+}
+
+pub fn extract_between<'a>(
+    text: &'a str,
+    start: &str,
+    end: &str
+) -> Option<&'a str> {
+    let start_idx = text.find(start)? + start.len();
+    let rem = &text[start_idx..];
+    let end_idx = rem.find(end)?;
+    Some(rem[..end_idx].trim())
+}
+
+pub fn extract_token<'a>(
+    text: &'a str,
+    key: &str
+) -> Option<&'a str> {
+    let start_idx = text.find(key)? + key.len();
+    let rem = &text[start_idx..];
+    let token_len = rem
+        .char_indices()
+        .take_while(|(_, c)| c.is_ascii_alphanumeric() || *c == '.' || *c == '_' || *c == '-')
+        .last()
+        .map(|(idx, c)| idx + c.len_utf8())
+        .unwrap_or(0);
+
+    if token_len == 0 { None } else { Some(rem[..token_len].trim()) }
+}
+
+pub fn map_action(
+    smtp_status: &str,
+    relay_handoff: bool
+) -> &'static str {
+    if smtp_status == "sent" && relay_handoff {
+        // "sent" to an internal relay is not final mailbox delivery yet.
+        return "delayed";
+    }
+
+    match smtp_status {
+        "sent" => "delivered",
+        "deferred" => "delayed",
+        "bounced" | "expired" => "failed",
+        _ => "failed"
+    }
+}
+
+pub fn default_status_code(
+    smtp_status: &str,
+    relay_handoff: bool
+) -> &'static str {
+    if smtp_status == "sent" && relay_handoff {
+        return "4.0.0";
+    }
+
+    match smtp_status {
+        "sent" => "2.0.0",
+        "deferred" => "4.0.0",
+        "bounced" | "expired" => "5.0.0",
+        _ => "5.0.0"
+    }
+}
+
+pub fn build_diagnostic(
+    queue_id: &str,
+    detail: &str
+) -> String {
+    let mut collapsed = String::with_capacity(detail.len());
+    let mut prev_space = false;
+
+    for ch in detail.chars() {
+        if ch.is_whitespace() {
+            if !prev_space {
+                collapsed.push(' ');
+                prev_space = true;
+            }
+        } else {
+            collapsed.push(ch);
+            prev_space = false;
+        }
+    }
+
+    let collapsed = collapsed.trim();
+    let mut diagnostic = format!("queue_id={queue_id}; {collapsed}");
+
+    if diagnostic.len() > MAX_DIAGNOSTIC_LEN {
+        diagnostic.truncate(MAX_DIAGNOSTIC_LEN);
+    }
+
+    diagnostic
+}
+
+pub fn is_queue_id(queue_id: &str) -> bool {
+    !queue_id.is_empty()
+        && queue_id.len() <= 32
+        && queue_id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+pub fn extract_relay_host(detail: &str) -> Option<String> {
+    let marker = "relay=";
+    let start = detail.find(marker)? + marker.len();
+    let rem = &detail[start..];
+
+    let end = rem
+        .find(|c: char| c == '[' || c == ':' || c == ',' || c.is_whitespace())
+        .unwrap_or(rem.len());
+
+    let host = rem[..end].trim().to_ascii_lowercase();
+    if host.is_empty() { None } else { Some(host) }
+}
+
+pub fn is_relay_handoff_host(host: &str) -> bool {
+    RELAY_HANDOFF_HOSTS.iter().any(|relay| host.eq_ignore_ascii_case(relay))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParsedSyslog, parse_postfix_message, postfix_service};
+    use crate::message_hash::HashFormat;
+
+    #[test]
+    fn parses_messages_by_service() {
+        let hash_format = HashFormat::default();
+        let cleanup = "4ABC: message-id=<9f0123456789abcdef0123456789abcd@example.com>";
+        assert!(matches!(
+            parse_postfix_message("cleanup", cleanup, &hash_format),
+            Some(ParsedSyslog::Cleanup { queue_id, hash })
+                if queue_id == "4ABC" && hash == "9f0123456789abcdef0123456789abcd"
+        ));
+
+        // Brackets and `]: ` inside the message no longer confuse the parser.
+        let smtp = "4ABC: to=<u@d.example>, relay=mx.d.example[192.0.2.1]:25, dsn=5.1.1, status=bounced (host mx.d.example[192.0.2.1] said: 550 5.1.1 unknown)";
+        let Some(ParsedSyslog::Delivery(delivery)) =
+            parse_postfix_message("smtp", smtp, &hash_format)
+        else {
+            panic!("delivery not parsed");
+        };
+        assert_eq!(
+            (delivery.service.as_str(), delivery.recipient.as_str(), delivery.action.as_str()),
+            ("smtp", "u@d.example", "failed")
+        );
+
+        assert!(parse_postfix_message("qmgr", "4ABC: removed", &hash_format).is_none());
+    }
+
+    #[test]
+    fn names_the_service_of_postfix_instances() {
+        assert_eq!(postfix_service("postfix/smtp"), Some("smtp"));
+        assert_eq!(postfix_service("postfix-out1/smtp"), Some("smtp"));
+        assert_eq!(postfix_service("postfix/submission/smtpd"), Some("smtpd"));
+        assert_eq!(postfix_service("sm-mta"), None);
+        assert_eq!(postfix_service("opendkim/smtp"), None);
+    }
+}
@@ -0,0 +1,92 @@
+//! Message hashes the sending application encodes in its Message-IDs, e.g.
+//! `<9f0c...@example.com>`.
+//!
+//! By default the hash is the Message-ID local part with everything but
+//! ASCII letters and digits removed, so a UUID local part yields its 32 hex
+//! digits. Deployments with other tracking schemes restrict the accepted
+//! lengths or supply a regex instead.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HashFormatConfig {
+    /// Accepted hash lengths; empty accepts any length.
+    #[serde(default)]
+    pub lengths: Vec<usize>,
+    /// Regex matched against the Message-ID local part. The first capture
+    /// group, or else the whole match, is taken verbatim as the hash.
+    #[serde(default)]
+    pub pattern: Option<String>
+}
+
+/// Compiled [`HashFormatConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct HashFormat {
+    lengths: Vec<usize>,
+    pattern: Option<Regex>
+}
+
+impl HashFormat {
+    pub fn new(config: &HashFormatConfig) -> Result<Self, regex::Error> {
+        let pattern = config
+            .pattern
+            .as_deref()
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .map(Regex::new)
+            .transpose()?;
+        Ok(Self { lengths: config.lengths.clone(), pattern })
+    }
+
+    /// Hash in a Message-ID like `<local@domain>` (angle brackets optional).
+    pub fn extract(
+        &self,
+        message_id: &str
+    ) -> Option<String> {
+        let trimmed = message_id.trim().trim_matches(|c| c == '<' || c == '>');
+        let local_part = trimmed.split('@').next().unwrap_or("").trim();
+
+        let hash = match &self.pattern {
+            Some(pattern) => {
+                let captures = pattern.captures(local_part)?;
+                captures.get(1).or_else(|| captures.get(0))?.as_str().to_string()
+            }
+            None => local_part.chars().filter(|c| c.is_ascii_alphanumeric()).collect()
+        };
+
+        let length_ok = self.lengths.is_empty() || self.lengths.contains(&hash.len());
+        if hash.is_empty() || !length_ok { None } else { Some(hash) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashFormat, HashFormatConfig};
+
+    #[test]
+    fn extracts_hash_by_length_or_pattern() {
+        let any = HashFormat::default();
+        assert_eq!(any.extract("<a1-b2@example.com>").as_deref(), Some("a1b2"));
+        assert_eq!(any.extract("<@example.com>"), None);
+
+        let md5 = HashFormat::new(&HashFormatConfig { lengths: vec![32], pattern: None }).unwrap();
+        let uuid = "<550e8400-e29b-41d4-a716-446655440000@example.com>";
+        assert_eq!(md5.extract(uuid).as_deref(), Some("550e8400e29b41d4a716446655440000"));
+        assert_eq!(md5.extract("<a1b2@example.com>"), None);
+
+        let tracked = HashFormat::new(&HashFormatConfig {
+            lengths: Vec::new(),
+            pattern: Some(r"^trk\.([A-Za-z0-9_-]{6,})\.".to_string())
+        })
+        .unwrap();
+        assert_eq!(tracked.extract("<trk.Ab_9-xY.42@example.com>").as_deref(), Some("Ab_9-xY"));
+        assert_eq!(tracked.extract("<a1b2c3d4@example.com>"), None);
+
+        assert!(
+            HashFormat::new(&HashFormatConfig { lengths: Vec::new(), pattern: Some("(".into()) })
+                .is_err()
+        );
+    }
+}
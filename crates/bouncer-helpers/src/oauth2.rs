@@ -0,0 +1,245 @@
+//! OAuth2 access tokens for IMAP `AUTHENTICATE XOAUTH2` (Gmail, Office 365).
+//!
+//! A token comes either from a refresh-token grant against `token_url`, and
+//! is cached until shortly before it expires, or from `token_command`, whose
+//! standard output is taken as a fresh token on every login.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+/// Tokens are refreshed this long before `expires_in` runs out.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+/// Assumed lifetime when the token endpoint omits `expires_in`.
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(3600);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Whole token request, or `token_command` run, including the connect.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OAuth2Config {
+    /// Such as `https://oauth2.googleapis.com/token`.
+    #[serde(default)]
+    pub token_url: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// Omitted for public clients.
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    /// Sent with the refresh grant when set.
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Shell command printing an access token; replaces the refresh grant.
+    #[serde(default)]
+    pub token_command: Option<String>
+}
+
+impl OAuth2Config {
+    /// Problem with the config, if any: a command or a complete grant.
+    pub fn problem(&self) -> Option<&'static str> {
+        if self.token_command.is_some() {
+            return None;
+        }
+        match (&self.token_url, &self.client_id, &self.refresh_token) {
+            (Some(_), Some(_), Some(_)) => None,
+            _ => Some("needs `token_command`, or `token_url`, `client_id` and `refresh_token`")
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OAuth2Error {
+    #[error("{0}")]
+    Config(&'static str),
+    #[error("token request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("token endpoint returned {status}: {body}")]
+    Status { status: u16, body: String },
+    #[error("invalid token response: {0}")]
+    Response(#[from] serde_json::Error),
+    #[error("token command failed to run: {0}")]
+    CommandIo(#[from] std::io::Error),
+    #[error("token command exited with {status}: {stderr}")]
+    Command { status: std::process::ExitStatus, stderr: String },
+    #[error("token command printed no token")]
+    EmptyToken,
+    #[error("token command timed out after {}s", REQUEST_TIMEOUT.as_secs())]
+    CommandTimeout
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>
+}
+
+struct CachedToken {
+    token: String,
+    refresh_at: Instant
+}
+
+/// Hands out access tokens for one account; share it between the sessions
+/// of that account so they reuse one cached token.
+pub struct TokenSource {
+    config: OAuth2Config,
+    http: reqwest::Client,
+    cached: Mutex<Option<CachedToken>>
+}
+
+impl TokenSource {
+    /// Fails when the HTTP client, i.e. its TLS backend, cannot be set up.
+    pub fn new(config: OAuth2Config) -> Result<Self, OAuth2Error> {
+        let http = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        Ok(Self { config, http, cached: Mutex::new(None) })
+    }
+
+    /// A token valid for at least [`EXPIRY_MARGIN`], refreshed when needed.
+    pub async fn access_token(&self) -> Result<String, OAuth2Error> {
+        if let Some(command) = &self.config.token_command {
+            return run_token_command(command).await;
+        }
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref().filter(|token| Instant::now() < token.refresh_at) {
+            return Ok(token.token.clone());
+        }
+        let (token, lifetime) = self.refresh().await?;
+        *cached = Some(CachedToken {
+            token: token.clone(),
+            refresh_at: Instant::now() + lifetime.saturating_sub(EXPIRY_MARGIN)
+        });
+        Ok(token)
+    }
+
+    /// Drops the cached token, e.g. after the server rejected it.
+    pub async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+
+    async fn refresh(&self) -> Result<(String, Duration), OAuth2Error> {
+        let config = &self.config;
+        let (Some(token_url), Some(client_id), Some(refresh_token)) =
+            (&config.token_url, &config.client_id, &config.refresh_token)
+        else {
+            return Err(OAuth2Error::Config(
+                "oauth2 needs `token_url`, `client_id` and `refresh_token`"
+            ));
+        };
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+            ("client_id", client_id.as_str()),
+        ];
+        if let Some(secret) = &config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        if let Some(scope) = &config.scope {
+            form.push(("scope", scope.as_str()));
+        }
+
+        let response = self.http.post(token_url).form(&form).send().await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        if !status.is_success() {
+            return Err(OAuth2Error::Status {
+                status: status.as_u16(),
+                body: String::from_utf8_lossy(&body).into_owned()
+            });
+        }
+        let token: TokenResponse = serde_json::from_slice(&body)?;
+        let lifetime = token.expires_in.map_or(DEFAULT_LIFETIME, Duration::from_secs);
+        Ok((token.access_token, lifetime))
+    }
+}
+
+async fn run_token_command(command: &str) -> Result<String, OAuth2Error> {
+    let output = Command::new("sh").arg("-c").arg(command).kill_on_drop(true).output();
+    let output = tokio::time::timeout(REQUEST_TIMEOUT, output)
+        .await
+        .map_err(|_| OAuth2Error::CommandTimeout)??;
+    if !output.status.success() {
+        return Err(OAuth2Error::Command {
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string()
+        });
+    }
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if token.is_empty() { Err(OAuth2Error::EmptyToken) } else { Ok(token) }
+}
+
+/// Initial client response of SASL XOAUTH2, before base64 encoding.
+pub fn xoauth2_response(
+    user: &str,
+    access_token: &str
+) -> String {
+    format!("user={user}\x01auth=Bearer {access_token}\x01\x01")
+}
+
+/// SASL XOAUTH2 for `async_imap::Client::authenticate`: the initial response,
+/// then the empty reply a server expects after its error challenge for a
+/// rejected token.
+pub struct XOAuth2 {
+    response: Option<String>
+}
+
+impl XOAuth2 {
+    pub fn new(
+        user: &str,
+        access_token: &str
+    ) -> Self {
+        Self { response: Some(xoauth2_response(user, access_token)) }
+    }
+}
+
+impl async_imap::Authenticator for XOAuth2 {
+    type Response = String;
+
+    fn process(
+        &mut self,
+        _challenge: &[u8]
+    ) -> Self::Response {
+        self.response.take().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_imap::Authenticator;
+
+    use super::{OAuth2Config, TokenSource, XOAuth2, xoauth2_response};
+
+    #[tokio::test]
+    async fn builds_xoauth2_response_from_command_token() {
+        let tokens = TokenSource::new(OAuth2Config {
+            token_command: Some("printf 'ya29.token\\n'".to_string()),
+            ..OAuth2Config::default()
+        })
+        .unwrap();
+        let token = tokens.access_token().await.unwrap();
+        assert_eq!(
+            xoauth2_response("bounces@example.com", &token),
+            "user=bounces@example.com\x01auth=Bearer ya29.token\x01\x01"
+        );
+
+        let mut authenticator = XOAuth2::new("bounces@example.com", &token);
+        assert_eq!(authenticator.process(b""), xoauth2_response("bounces@example.com", &token));
+        assert_eq!(authenticator.process(b"{\"status\":\"400\"}"), "");
+
+        assert!(OAuth2Config::default().problem().is_some());
+        let failing = TokenSource::new(OAuth2Config {
+            token_command: Some("exit 3".to_string()),
+            ..OAuth2Config::default()
+        })
+        .unwrap();
+        assert!(failing.access_token().await.is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! Capacity-bounded `queue_id -> message hash` map of the log agents.
+//!
+//! `cleanup` lines add a mapping that the later `smtp` lines of the same
+//! queue id look up. Entries expire after the mapping TTL, and once the map
+//! holds `capacity` entries the least recently used one is evicted, so memory
+//! stays bounded on busy relays between prune ticks.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Occupancy of a [`QueueMap`], readable from other tasks (the publisher
+/// reports it in heartbeats).
+#[derive(Debug, Default)]
+pub struct QueueMapGauge {
+    entries: AtomicU64,
+    capacity: AtomicU64,
+    evicted: AtomicU64
+}
+
+impl QueueMapGauge {
+    pub fn entries(&self) -> u64 {
+        self.entries.load(Ordering::Relaxed)
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Entries evicted for capacity since start; expired ones do not count.
+    pub fn evicted(&self) -> u64 {
+        self.evicted.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct Slot {
+    hash: String,
+    updated_at: Instant,
+    /// Key of this entry in `QueueMap::order`.
+    tick: u64
+}
+
+#[derive(Debug)]
+pub struct QueueMap<K> {
+    entries: HashMap<K, Slot>,
+    /// Keys by last use, least recent first.
+    order: BTreeMap<u64, K>,
+    next_tick: u64,
+    capacity: usize,
+    gauge: Arc<QueueMapGauge>
+}
+
+impl<K: Clone + Eq + Hash> QueueMap<K> {
+    pub fn new(
+        capacity: usize,
+        gauge: Arc<QueueMapGauge>
+    ) -> Self {
+        let capacity = capacity.max(1);
+        gauge.capacity.store(capacity as u64, Ordering::Relaxed);
+        Self { entries: HashMap::new(), order: BTreeMap::new(), next_tick: 0, capacity, gauge }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Stores the mapping of `key`; returns true when the least recently used
+    /// entry was evicted to make room.
+    pub fn insert(
+        &mut self,
+        key: K,
+        hash: String,
+        now: Instant
+    ) -> bool {
+        let tick = self.tick();
+        let mut evicted = false;
+        match self.entries.get_mut(&key) {
+            Some(slot) => {
+                self.order.remove(&slot.tick);
+                *slot = Slot { hash, updated_at: now, tick };
+            }
+            None => {
+                if self.entries.len() >= self.capacity
+                    && let Some((_, oldest)) = self.order.pop_first()
+                {
+                    self.entries.remove(&oldest);
+                    self.gauge.evicted.fetch_add(1, Ordering::Relaxed);
+                    evicted = true;
+                }
+                self.entries.insert(key.clone(), Slot { hash, updated_at: now, tick });
+            }
+        }
+        self.order.insert(tick, key);
+        self.publish();
+        evicted
+    }
+
+    /// Hash mapped to `key`, refreshing its TTL and LRU position.
+    pub fn touch(
+        &mut self,
+        key: &K,
+        now: Instant
+    ) -> Option<String> {
+        let tick = self.tick();
+        let slot = self.entries.get_mut(key)?;
+        self.order.remove(&slot.tick);
+        self.order.insert(tick, key.clone());
+        slot.tick = tick;
+        slot.updated_at = now;
+        Some(slot.hash.clone())
+    }
+
+    /// Drops the mapping of `key`, returning its hash.
+    pub fn remove(
+        &mut self,
+        key: &K
+    ) -> Option<String> {
+        let slot = self.entries.remove(key)?;
+        self.order.remove(&slot.tick);
+        self.publish();
+        Some(slot.hash)
+    }
+
+    /// Removes mappings not used within `ttl` of `now`; returns the count.
+    pub fn prune(
+        &mut self,
+        ttl: Duration,
+        now: Instant
+    ) -> usize {
+        let mut removed = 0;
+        while let Some((_, key)) = self.order.first_key_value() {
+            let expired = self
+                .entries
+                .get(key)
+                .is_none_or(|slot| now.saturating_duration_since(slot.updated_at) > ttl);
+            if !expired {
+                break;
+            }
+            if let Some((_, key)) = self.order.pop_first() {
+                self.entries.remove(&key);
+                removed += 1;
+            }
+        }
+        self.publish();
+        removed
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    fn publish(&self) {
+        self.gauge.entries.store(self.entries.len() as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use super::{QueueMap, QueueMapGauge};
+
+    #[test]
+    fn evicts_least_recently_used_and_prunes_expired() {
+        let gauge = Arc::new(QueueMapGauge::default());
+        let mut map = QueueMap::new(2, gauge.clone());
+        let start = Instant::now();
+
+        assert!(!map.insert("A", "hash-a".to_string(), start));
+        assert!(!map.insert("B", "hash-b".to_string(), start));
+        // Using A makes B the eviction candidate.
+        assert_eq!(map.touch(&"A", start + Duration::from_secs(10)).as_deref(), Some("hash-a"));
+        assert!(map.insert("C", "hash-c".to_string(), start + Duration::from_secs(20)));
+        assert_eq!(map.touch(&"B", start), None);
+        assert_eq!((gauge.entries(), gauge.capacity(), gauge.evicted()), (2, 2, 1));
+
+        assert_eq!(map.prune(Duration::from_secs(15), start + Duration::from_secs(30)), 1);
+        assert_eq!(map.touch(&"C", start + Duration::from_secs(30)).as_deref(), Some("hash-c"));
+        assert_eq!((map.len(), gauge.entries()), (1, 1));
+
+        assert_eq!(map.remove(&"C").as_deref(), Some("hash-c"));
+        assert_eq!(map.remove(&"C"), None);
+        assert_eq!((map.len(), gauge.entries()), (0, 0));
+    }
+}
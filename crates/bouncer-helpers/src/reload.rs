@@ -0,0 +1,223 @@
+//! SIGHUP-triggered config reloads.
+//!
+//! The server and the agents re-read their YAML file on SIGHUP, apply the
+//! settings that can change at runtime and log the rest as waiting for a
+//! restart. What changed is decided per top-level key, see
+//! [`ConfigSnapshot::changed_keys`]. The agents share [`run_config_reload`];
+//! the server has its own, as it restarts tasks on some keys.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::config_file::ConfigSnapshot;
+use crate::logging::{self, LogFilterError};
+
+/// An agent config [`run_config_reload`] re-reads on SIGHUP.
+pub trait ReloadableConfig: Clone + Send + Sync + 'static {
+    /// Top-level keys [`Self::apply_reloadable`] takes over.
+    const RELOADABLE_KEYS: &'static [&'static str];
+
+    /// Reads and normalizes the config file at `path`.
+    fn read(path: &Path) -> Result<Self>;
+
+    /// The file the config was read from; without one there is nothing to
+    /// reload.
+    fn snapshot(&self) -> Option<&ConfigSnapshot>;
+
+    fn log_filter(&self) -> Option<&str>;
+
+    /// Copies the [`Self::RELOADABLE_KEYS`] settings of a reloaded config.
+    fn apply_reloadable(
+        &mut self,
+        next: &Self
+    );
+}
+
+/// Re-reads the config file on SIGHUP and publishes its reloadable settings
+/// to `config_tx`. Other changed keys are logged as needing a restart; a
+/// file that fails to load keeps the running config.
+pub async fn run_config_reload<C: ReloadableConfig>(
+    config_tx: watch::Sender<C>,
+    shutdown: CancellationToken
+) {
+    let mut signal = ReloadSignal::install();
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = signal.recv() => {
+                info!("reload signal received: SIGHUP");
+                if let Err(err) = reload(&config_tx) {
+                    warn!("config reload failed, keeping running config: error={err:#}");
+                }
+            }
+        }
+    }
+}
+
+fn reload<C: ReloadableConfig>(config_tx: &watch::Sender<C>) -> Result<()> {
+    let mut config = config_tx.borrow().clone();
+    let snapshot = config.snapshot().context("config was not loaded from a file")?;
+    let next = C::read(snapshot.path())?;
+    let changed = snapshot.changed_keys(next.snapshot().context("reloaded config lost its file")?);
+
+    if changed.iter().any(|key| key == "log_filter") {
+        apply_log_filter(next.log_filter());
+    }
+    config.apply_reloadable(&next);
+    config_tx.send_replace(config);
+    log_reload(&changed, C::RELOADABLE_KEYS);
+    Ok(())
+}
+
+/// SIGHUP stream; never fires where signals are unavailable.
+pub struct ReloadSignal {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>
+}
+
+impl ReloadSignal {
+    pub fn install() -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+
+            let signal = signal(SignalKind::hangup())
+                .inspect_err(|err| warn!("failed to install SIGHUP handler: error={err}"))
+                .ok();
+            Self { signal }
+        }
+
+        #[cfg(not(unix))]
+        Self {}
+    }
+
+    /// A stream that never fires, for configs not read from a file.
+    pub fn disabled() -> Self {
+        Self {
+            #[cfg(unix)]
+            signal: None
+        }
+    }
+
+    /// Waits for the next SIGHUP.
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal
+            && signal.recv().await.is_some()
+        {
+            return;
+        }
+
+        std::future::pending::<()>().await
+    }
+}
+
+/// Installs `directives` as the process log filter; `None` restores the
+/// startup filter. Embedders with their own subscriber keep theirs.
+pub fn apply_log_filter(directives: Option<&str>) {
+    match logging::set_log_filter(directives) {
+        Ok(()) if logging::debug_logging() => info!(
+            "log filter kept for when debug logging is disabled: filter={}",
+            directives.unwrap_or("default")
+        ),
+        Ok(()) => info!("log filter applied: filter={}", directives.unwrap_or("default")),
+        Err(LogFilterError::NotInstalled) => {
+            debug!("log filter not applied: subscriber not installed by bouncer")
+        }
+        Err(err) => warn!("log filter not applied: error={err}")
+    }
+}
+
+/// Logs a reload: changed keys listed in `live` took effect, every other
+/// changed key only applies after a restart.
+pub fn log_reload(
+    changed: &[String],
+    live: &[&str]
+) {
+    let (applied, restart): (Vec<&str>, Vec<&str>) =
+        changed.iter().map(String::as_str).partition(|key| live.contains(key));
+    if changed.is_empty() {
+        info!("config reloaded: no changes");
+        return;
+    }
+    info!("config reloaded: applied=[{}]", applied.join(","));
+    if !restart.is_empty() {
+        warn!("config changes need a restart to take effect: keys=[{}]", restart.join(","));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use anyhow::Result;
+    use serde::Deserialize;
+    use tokio::sync::watch;
+
+    use super::{ReloadableConfig, reload};
+    use crate::config_file::{self, ConfigSnapshot};
+
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct AgentConfig {
+        heartbeat_secs: u64,
+        #[serde(default)]
+        queue_capacity: usize,
+        #[serde(skip)]
+        snapshot: Option<ConfigSnapshot>
+    }
+
+    impl ReloadableConfig for AgentConfig {
+        const RELOADABLE_KEYS: &'static [&'static str] = &["heartbeat_secs"];
+
+        fn read(path: &Path) -> Result<Self> {
+            let (mut config, snapshot) = config_file::load_yaml_snapshot::<Self>(path)?;
+            config.snapshot = Some(snapshot);
+            Ok(config)
+        }
+
+        fn snapshot(&self) -> Option<&ConfigSnapshot> {
+            self.snapshot.as_ref()
+        }
+
+        fn log_filter(&self) -> Option<&str> {
+            None
+        }
+
+        fn apply_reloadable(
+            &mut self,
+            next: &Self
+        ) {
+            self.heartbeat_secs = next.heartbeat_secs;
+            self.snapshot = next.snapshot.clone();
+        }
+    }
+
+    #[test]
+    fn publishes_reloadable_keys_and_keeps_config_on_error() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let dir =
+            std::env::temp_dir().join(format!("bouncer-reload-{}-{nanos}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("agent.yaml");
+        std::fs::write(&path, "heartbeat_secs: 30\nqueue_capacity: 10\n").unwrap();
+        let (config_tx, config_rx) = watch::channel(AgentConfig::read(&path).unwrap());
+
+        std::fs::write(&path, "heartbeat_secs: 5\nqueue_capacity: 99\n").unwrap();
+        reload(&config_tx).unwrap();
+        let applied = config_rx.borrow().clone();
+        assert_eq!((applied.heartbeat_secs, applied.queue_capacity), (5, 10));
+
+        std::fs::write(&path, "heartbeat_secs: soon\n").unwrap();
+        assert!(reload(&config_tx).is_err());
+        assert_eq!(config_rx.borrow().heartbeat_secs, 5);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,44 @@
+//! `sequence` of published delivery events.
+//!
+//! The server keeps the latest sequence applied per source and queue id and
+//! skips anything not newer, so an event delivered late or resent by a retry
+//! cannot overwrite a later outcome.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Hands out strictly increasing sequences: the publish time in unix
+/// microseconds, bumped past the previous value when the clock stalls or
+/// steps back. They keep growing across restarts as long as the clock does.
+#[derive(Debug, Default)]
+pub struct EventSequence {
+    last: u64
+}
+
+impl EventSequence {
+    pub fn next(
+        &mut self,
+        now: SystemTime
+    ) -> u64 {
+        let micros = now.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_micros());
+        let micros = u64::try_from(micros).unwrap_or(u64::MAX);
+        self.last = micros.max(self.last.saturating_add(1));
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::EventSequence;
+
+    #[test]
+    fn stays_strictly_increasing_when_the_clock_does_not() {
+        let mut sequence = EventSequence::default();
+        let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(sequence.next(at), 1_700_000_000_000_000);
+        assert_eq!(sequence.next(at), 1_700_000_000_000_001);
+        assert_eq!(sequence.next(at - Duration::from_secs(5)), 1_700_000_000_000_002);
+        assert_eq!(sequence.next(at + Duration::from_secs(1)), 1_700_000_001_000_000);
+    }
+}
@@ -0,0 +1,309 @@
+//! Tiny single-file key-value store for agent state (cursors, queue maps,
+//! unacked events).
+//!
+//! The file is an append-only log: a 4-byte magic followed by records of
+//! `crc32 (u32 LE) | body_len (u32 LE) | body`, where the body is
+//! `op (u8) | key_len (u16 LE) | key | value`. Every write is fsynced before
+//! returning. On open the log is replayed into memory; a torn or corrupt tail
+//! (crash mid-append) is cut off at the last valid record. The log is
+//! rewritten via tmp file + rename once dead records outnumber live ones.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+const MAGIC: [u8; 4] = *b"BKV1";
+const RECORD_HEADER_LEN: usize = 8;
+const OP_PUT: u8 = 1;
+const OP_DELETE: u8 = 2;
+const MAX_RECORD_LEN: usize = 16 * 1024 * 1024;
+const COMPACT_MIN_RECORDS: usize = 64;
+
+#[derive(Debug)]
+pub struct StateStore {
+    path: PathBuf,
+    file: File,
+    entries: BTreeMap<String, Vec<u8>>,
+    log_records: usize
+}
+
+impl StateStore {
+    /// Opens (or creates) the store at `path` and replays its log.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(&path)?;
+        let mut raw = Vec::new();
+        file.read_to_end(&mut raw)?;
+
+        if raw.is_empty() {
+            file.write_all(&MAGIC)?;
+            file.sync_all()?;
+            return Ok(Self { path, file, entries: BTreeMap::new(), log_records: 0 });
+        }
+
+        if raw.len() < MAGIC.len() || raw[..MAGIC.len()] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("not a state store file: {}", path.display())
+            ));
+        }
+
+        let mut entries = BTreeMap::new();
+        let mut log_records = 0;
+        let mut offset = MAGIC.len();
+        while let Some((op, key, value, next)) = decode_record(&raw, offset) {
+            match op {
+                OP_PUT => {
+                    entries.insert(key, value.to_vec());
+                }
+                _ => {
+                    entries.remove(&key);
+                }
+            }
+            log_records += 1;
+            offset = next;
+        }
+
+        if offset < raw.len() {
+            warn!(
+                "state store tail discarded: path={}, valid_bytes={}, discarded_bytes={}",
+                path.display(),
+                offset,
+                raw.len() - offset
+            );
+            file.set_len(offset as u64)?;
+            file.sync_all()?;
+        }
+
+        Ok(Self { path, file, entries, log_records })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(
+        &self,
+        key: &str
+    ) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value.as_slice()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Stores `value` under `key` and fsyncs before returning.
+    pub fn put(
+        &mut self,
+        key: &str,
+        value: &[u8]
+    ) -> io::Result<()> {
+        self.append(OP_PUT, key, value)?;
+        self.entries.insert(key.to_string(), value.to_vec());
+        self.maybe_compact()
+    }
+
+    /// Removes `key`; returns false when it was not present.
+    pub fn remove(
+        &mut self,
+        key: &str
+    ) -> io::Result<bool> {
+        if !self.entries.contains_key(key) {
+            return Ok(false);
+        }
+        self.append(OP_DELETE, key, &[])?;
+        self.entries.remove(key);
+        self.maybe_compact()?;
+        Ok(true)
+    }
+
+    /// Rewrites the log with one record per live key.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("compact.tmp");
+        let mut buf = MAGIC.to_vec();
+        for (key, value) in &self.entries {
+            encode_record(&mut buf, OP_PUT, key, value)?;
+        }
+
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&buf)?;
+        tmp.sync_all()?;
+        drop(tmp);
+
+        fs::rename(&tmp_path, &self.path)?;
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            File::open(parent)?.sync_all()?;
+        }
+
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.log_records = self.entries.len();
+        Ok(())
+    }
+
+    fn append(
+        &mut self,
+        op: u8,
+        key: &str,
+        value: &[u8]
+    ) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(RECORD_HEADER_LEN + 3 + key.len() + value.len());
+        encode_record(&mut buf, op, key, value)?;
+        self.file.write_all(&buf)?;
+        self.file.sync_data()?;
+        self.log_records += 1;
+        Ok(())
+    }
+
+    fn maybe_compact(&mut self) -> io::Result<()> {
+        if self.log_records >= COMPACT_MIN_RECORDS && self.log_records > self.entries.len() * 2 {
+            self.compact()?;
+        }
+        Ok(())
+    }
+}
+
+fn encode_record(
+    buf: &mut Vec<u8>,
+    op: u8,
+    key: &str,
+    value: &[u8]
+) -> io::Result<()> {
+    let key_len = u16::try_from(key.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "state store key too long"))?;
+    let body_len = 1 + 2 + key.len() + value.len();
+    if body_len > MAX_RECORD_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "state store value too large"));
+    }
+
+    let start = buf.len();
+    buf.extend_from_slice(&[0; RECORD_HEADER_LEN]);
+    buf.push(op);
+    buf.extend_from_slice(&key_len.to_le_bytes());
+    buf.extend_from_slice(key.as_bytes());
+    buf.extend_from_slice(value);
+
+    let crc = crc32fast::hash(&buf[start + RECORD_HEADER_LEN..]);
+    buf[start..start + 4].copy_from_slice(&crc.to_le_bytes());
+    buf[start + 4..start + 8].copy_from_slice(&(body_len as u32).to_le_bytes());
+    Ok(())
+}
+
+/// Decodes the record at `offset`; `None` on a truncated or corrupt record.
+fn decode_record(
+    raw: &[u8],
+    offset: usize
+) -> Option<(u8, String, &[u8], usize)> {
+    let header = raw.get(offset..offset + RECORD_HEADER_LEN)?;
+    let crc = u32::from_le_bytes(header[..4].try_into().ok()?);
+    let body_len = u32::from_le_bytes(header[4..].try_into().ok()?) as usize;
+    if !(3..=MAX_RECORD_LEN).contains(&body_len) {
+        return None;
+    }
+
+    let body_start = offset + RECORD_HEADER_LEN;
+    let body = raw.get(body_start..body_start + body_len)?;
+    if crc32fast::hash(body) != crc {
+        return None;
+    }
+
+    let op = body[0];
+    if op != OP_PUT && op != OP_DELETE {
+        return None;
+    }
+    let key_len = u16::from_le_bytes([body[1], body[2]]) as usize;
+    let key = std::str::from_utf8(body.get(3..3 + key_len)?).ok()?.to_string();
+    Some((op, key, &body[3 + key_len..], body_start + body_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::StateStore;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        std::env::temp_dir()
+            .join(format!("bouncer-state-{name}-{}-{nanos}", std::process::id()))
+            .join("state.db")
+    }
+
+    #[test]
+    fn replays_puts_and_removes_after_reopen() {
+        let path = temp_path("replay");
+        {
+            let mut store = StateStore::open(&path).unwrap();
+            store.put("cursor", b"s=abc;i=1").unwrap();
+            store.put("queue:4ABC", b"hash-1").unwrap();
+            store.put("cursor", b"s=abc;i=2").unwrap();
+            assert!(store.remove("queue:4ABC").unwrap());
+            assert!(!store.remove("queue:missing").unwrap());
+        }
+
+        let store = StateStore::open(&path).unwrap();
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get("cursor"), Some(&b"s=abc;i=2"[..]));
+        assert_eq!(store.get("queue:4ABC"), None);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn torn_tail_is_discarded_and_store_stays_writable() {
+        let path = temp_path("torn");
+        {
+            let mut store = StateStore::open(&path).unwrap();
+            store.put("a", b"1").unwrap();
+            store.put("b", b"2").unwrap();
+        }
+        let valid_len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[7, 0, 0, 0, 9]).unwrap();
+
+        let mut store = StateStore::open(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), valid_len);
+        assert_eq!(store.get("a"), Some(&b"1"[..]));
+        assert_eq!(store.get("b"), Some(&b"2"[..]));
+
+        store.put("c", b"3").unwrap();
+        drop(store);
+        assert_eq!(StateStore::open(&path).unwrap().len(), 3);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn compaction_keeps_only_live_keys() {
+        let path = temp_path("compact");
+        let mut store = StateStore::open(&path).unwrap();
+        for idx in 0..200u32 {
+            store.put("cursor", &idx.to_le_bytes()).unwrap();
+        }
+        // 200 uncompacted records would be 200 * 21 bytes.
+        assert!(std::fs::metadata(&path).unwrap().len() < 64 * 21);
+        drop(store);
+
+        let store = StateStore::open(&path).unwrap();
+        assert_eq!(store.get("cursor"), Some(&199u32.to_le_bytes()[..]));
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
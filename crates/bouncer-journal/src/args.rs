@@ -2,27 +2,29 @@ use std::path::PathBuf;
 
 use anyhow::{Result, bail};
 
+const USAGE: &str = "usage: bouncer-journal [--check-config] [config-path]";
+
 #[derive(Debug, Default)]
 pub struct JournalArgs {
-    pub config_path: Option<PathBuf>
+    pub config_path: Option<PathBuf>,
+    /// Load and validate the config, print it and exit.
+    pub check_config: bool
 }
 
 impl JournalArgs {
-    pub fn parse<I>(mut args: I) -> Result<Self>
+    pub fn parse<I>(args: I) -> Result<Self>
     where
         I: Iterator<Item = String>
     {
-        let first = args.next();
-        let second = args.next();
-
-        if let Some(arg) = second {
-            bail!("too many arguments: {arg} (usage: bouncer-journal [config-path])");
+        let mut parsed = Self::default();
+        for arg in args {
+            match arg.as_str() {
+                "-h" | "--help" => bail!(USAGE),
+                "--check-config" if !parsed.check_config => parsed.check_config = true,
+                _ if parsed.config_path.is_none() => parsed.config_path = Some(PathBuf::from(arg)),
+                _ => bail!("too many arguments: {arg} ({USAGE})")
+            }
         }
-
-        if matches!(first.as_deref(), Some("-h" | "--help")) {
-            bail!("usage: bouncer-journal [config-path]");
-        }
-
-        Ok(Self { config_path: first.map(PathBuf::from) })
+        Ok(parsed)
     }
 }
@@ -2,17 +2,28 @@ use std::env;
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result, bail};
-use serde::Deserialize;
+use bouncer_helpers::backoff::MAX_JITTER_PCT;
+use bouncer_helpers::config_file::{self, ConfigSnapshot};
+use bouncer_helpers::logging;
+use bouncer_helpers::message_hash::{HashFormat, HashFormatConfig};
+use bouncer_helpers::reload::ReloadableConfig;
+use serde::{Deserialize, Serialize};
 
-use crate::args::JournalArgs;
-
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct JournalConfig {
     #[serde(default = "default_server")]
     pub server: String,
     #[serde(default = "default_source")]
     pub source: String,
+    /// Tenant sent with every frame; events then only match messages of
+    /// that tenant.
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Sends a body checksum with every frame, verified by the server;
+    /// servers that predate checksums ignore it.
+    #[serde(default)]
+    pub frame_checksum: bool,
     #[serde(default = "default_queue_capacity")]
     pub queue_capacity: usize,
     #[serde(default = "default_connect_timeout_secs")]
@@ -21,46 +32,117 @@ pub struct JournalConfig {
     pub io_timeout_secs: u64,
     #[serde(default = "default_heartbeat_secs")]
     pub heartbeat_secs: u64,
+    #[serde(default = "default_heartbeat_jitter_pct")]
+    pub heartbeat_jitter_pct: u8,
+    #[serde(default = "default_reconnect_base_ms")]
+    pub reconnect_base_ms: u64,
+    #[serde(default = "default_reconnect_max_secs")]
+    pub reconnect_max_secs: u64,
     #[serde(default = "default_mapping_ttl_secs")]
     pub mapping_ttl_secs: u64,
-    #[serde(default = "default_unit")]
-    pub unit: String,
+    /// Queue-id mappings kept at most; the least recently used is evicted.
+    #[serde(default = "default_mapping_capacity")]
+    pub mapping_capacity: usize,
+    /// Publish `delivered` outcomes; false drops them before they are queued.
+    #[serde(default = "default_publish_delivered")]
+    pub publish_delivered: bool,
+    /// Single-unit form kept for older configs; merged into `units`.
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Units to follow, e.g. `postfix@out1.service`, or glob patterns like
+    /// `postfix@*.service`.
+    #[serde(default)]
+    pub units: Vec<String>,
     #[serde(default = "default_identifiers")]
     pub identifiers: Vec<String>,
     #[serde(default = "default_seek_tail")]
-    pub seek_tail: bool
+    pub seek_tail: bool,
+    /// Message hash format read from `cleanup` message-ids.
+    #[serde(default = "default_hash_format")]
+    pub hash: HashFormatConfig,
+    /// `EnvFilter` directives replacing `JOURNAL_LOG`; reloaded on SIGHUP.
+    #[serde(default)]
+    pub log_filter: Option<String>,
+    /// File this config was read from; enables reloading on SIGHUP.
+    #[serde(skip)]
+    pub snapshot: Option<ConfigSnapshot>
 }
 
+/// Prefix of the environment variables overriding config fields
+/// (`JOURNAL_SERVER`, `JOURNAL_UNITS`, ...).
+const ENV_PREFIX: &str = "JOURNAL";
+
+/// Top-level keys applied on SIGHUP; see [`JournalConfig::apply_reloadable`].
+pub const RELOADABLE_KEYS: [&str; 9] = [
+    "connect_timeout_secs",
+    "io_timeout_secs",
+    "heartbeat_secs",
+    "heartbeat_jitter_pct",
+    "reconnect_base_ms",
+    "reconnect_max_secs",
+    "mapping_ttl_secs",
+    "publish_delivered",
+    "log_filter"
+];
+
 impl JournalConfig {
-    pub fn load() -> Result<Self> {
-        let args = JournalArgs::parse(env::args().skip(1))?;
-        let config_path = args
-            .config_path
-            .or_else(resolve_journal_config_path)
-            .context(
-                "journal config path not found (JOURNAL_CONFIG_PATH or bouncer-journal.yaml/bouncer-journal.yaml)",
-            )?;
-
-        let mut config = load_config_yaml(&config_path)?;
+    /// Reads `path` or the first config file found, or else only
+    /// `JOURNAL_<FIELD>` variables when at least one of them is set.
+    pub fn load_from(path: Option<PathBuf>) -> Result<Self> {
+        if let Some(config_path) = path.or_else(resolve_journal_config_path) {
+            return Self::from_path(&config_path);
+        }
+
+        let mut config = config_file::load_env::<Self>(ENV_PREFIX)?.context(
+            "journal config path not found (JOURNAL_CONFIG_PATH or bouncer-journal.yaml/bouncer-journal.yaml) and no JOURNAL_* settings",
+        )?;
         config.normalize()?;
         Ok(config)
     }
 
+    /// Reads and normalizes a YAML config file; set `JOURNAL_<FIELD>`
+    /// variables replace its top-level fields.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let (mut config, snapshot) =
+            config_file::load_yaml_snapshot_with_env::<Self>(path, ENV_PREFIX)?;
+        config.snapshot = Some(snapshot);
+        config.normalize()?;
+        Ok(config)
+    }
+
+    /// The config as YAML, for `--check-config`.
+    pub fn dump(&self) -> Result<String> {
+        config_file::dump_yaml(self).context("failed to render journal config")
+    }
+
     fn normalize(&mut self) -> Result<()> {
         self.server = trim_owned(self.server.clone());
         self.source = trim_owned(self.source.clone());
-        self.unit = trim_owned(self.unit.clone());
 
         if self.server.is_empty() {
             bail!("journal config missing `server`");
         }
+        if !config_file::is_host_port(&self.server) {
+            bail!("journal config `server` is not a `host:port` address: {}", self.server);
+        }
         if self.source.is_empty() {
             self.source = default_source();
         }
-        if self.unit.is_empty() {
-            self.unit = default_unit();
+        self.tenant = self.tenant.take().map(trim_owned).filter(|tenant| !tenant.is_empty());
+        if let Some(tenant) = self.tenant.as_deref().filter(|t| !bouncer_proto::valid_tenant(t)) {
+            bail!("journal config `tenant` may only hold letters, digits, `_` and `-`: {tenant}");
         }
 
+        let legacy_unit = self.unit.take();
+        let mut units = Vec::new();
+        for unit in self.units.iter().chain(legacy_unit.iter()) {
+            let unit = trim_owned(unit.clone());
+            if !unit.is_empty() && !units.contains(&unit) {
+                units.push(unit);
+            }
+        }
+        self.units = if units.is_empty() { default_units() } else { units };
+
         self.identifiers = self
             .identifiers
             .iter()
@@ -74,15 +156,52 @@ impl JournalConfig {
         self.queue_capacity = self.queue_capacity.max(1);
         self.connect_timeout_secs = self.connect_timeout_secs.max(1);
         self.io_timeout_secs = self.io_timeout_secs.max(1);
+        self.heartbeat_jitter_pct = self.heartbeat_jitter_pct.min(MAX_JITTER_PCT);
+        self.reconnect_base_ms = self.reconnect_base_ms.max(10);
+        self.reconnect_max_secs = self.reconnect_max_secs.max(1);
         self.mapping_ttl_secs = self.mapping_ttl_secs.max(60);
+        self.mapping_capacity = self.mapping_capacity.max(1);
+        HashFormat::new(&self.hash).context("journal config `hash.pattern` is invalid")?;
+        self.log_filter =
+            self.log_filter.take().map(trim_owned).filter(|filter| !filter.is_empty());
+        if let Some(filter) = &self.log_filter {
+            logging::check_log_filter(filter).context("journal config `log_filter` is invalid")?;
+        }
 
         Ok(())
     }
 }
 
-fn load_config_yaml(path: &Path) -> Result<JournalConfig> {
-    let raw = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
-    serde_yaml::from_slice(&raw).with_context(|| format!("failed to parse yaml {}", path.display()))
+impl ReloadableConfig for JournalConfig {
+    const RELOADABLE_KEYS: &'static [&'static str] = &RELOADABLE_KEYS;
+
+    fn read(path: &Path) -> Result<Self> {
+        Self::from_path(path)
+    }
+
+    fn snapshot(&self) -> Option<&ConfigSnapshot> {
+        self.snapshot.as_ref()
+    }
+
+    fn log_filter(&self) -> Option<&str> {
+        self.log_filter.as_deref()
+    }
+
+    fn apply_reloadable(
+        &mut self,
+        next: &Self
+    ) {
+        self.connect_timeout_secs = next.connect_timeout_secs;
+        self.io_timeout_secs = next.io_timeout_secs;
+        self.heartbeat_secs = next.heartbeat_secs;
+        self.heartbeat_jitter_pct = next.heartbeat_jitter_pct;
+        self.reconnect_base_ms = next.reconnect_base_ms;
+        self.reconnect_max_secs = next.reconnect_max_secs;
+        self.mapping_ttl_secs = next.mapping_ttl_secs;
+        self.publish_delivered = next.publish_delivered;
+        self.log_filter = next.log_filter.clone();
+        self.snapshot = next.snapshot.clone();
+    }
 }
 
 fn resolve_journal_config_path() -> Option<PathBuf> {
@@ -135,8 +254,11 @@ fn default_server() -> String {
     "127.0.0.1:2147".to_string()
 }
 
+/// `$HOSTNAME-journal`, so a journal agent and an observer on one host do
+/// not take each other's registration.
 fn default_source() -> String {
-    non_empty_env("HOSTNAME").unwrap_or_else(|| "journal".to_string())
+    non_empty_env("HOSTNAME")
+        .map_or_else(|| "journal".to_string(), |host| format!("{host}-journal"))
 }
 
 fn default_queue_capacity() -> usize {
@@ -155,18 +277,52 @@ fn default_heartbeat_secs() -> u64 {
     30
 }
 
+fn default_heartbeat_jitter_pct() -> u8 {
+    20
+}
+
+fn default_reconnect_base_ms() -> u64 {
+    250
+}
+
+fn default_reconnect_max_secs() -> u64 {
+    30
+}
+
 fn default_mapping_ttl_secs() -> u64 {
     86_400
 }
 
-fn default_unit() -> String {
-    "postfix.service".to_string()
+fn default_mapping_capacity() -> usize {
+    100_000
+}
+
+fn default_publish_delivered() -> bool {
+    true
+}
+
+fn default_units() -> Vec<String> {
+    vec!["postfix.service".to_string()]
 }
 
 fn default_identifiers() -> Vec<String> {
-    vec!["postfix/cleanup".to_string(), "postfix/smtp".to_string(), "postfix/qmgr".to_string()]
+    [
+        "postfix/cleanup",
+        "postfix/smtp",
+        "postfix/qmgr",
+        "postfix/bounce",
+        "postfix/error",
+        "postfix/local"
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
 }
 
 fn default_seek_tail() -> bool {
     true
 }
+
+fn default_hash_format() -> HashFormatConfig {
+    HashFormatConfig { lengths: vec![32], pattern: None }
+}
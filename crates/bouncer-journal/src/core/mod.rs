@@ -1,6 +1,6 @@
-mod parser;
 mod publisher;
 mod types;
+mod units;
 mod watcher;
 
 pub use publisher::run_publisher;
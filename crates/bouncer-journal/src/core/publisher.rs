@@ -1,26 +1,48 @@
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
+use bouncer_helpers::backoff::{Backoff, Outage, initial_delay, jittered};
+use bouncer_helpers::clock::{Clock, SharedClock};
+use bouncer_helpers::error_code::ErrorCode;
+use bouncer_helpers::queue_map::QueueMapGauge;
+use bouncer_helpers::sequence::EventSequence;
+use bouncer_helpers::{coded_warn, logging};
+use bouncer_proto::event::encode_delivery_event;
+use bouncer_proto::heartbeat::{QueueMapOccupancy, encode_heartbeat};
 use bouncer_proto::{Header, encode_header_json, read_ack_async, write_frame_async};
 use tokio::net::TcpStream;
-use tokio::sync::mpsc;
-use tokio::time::{interval, sleep, timeout};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{Instant, sleep, timeout};
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, info, warn};
+use tracing::{Instrument, debug, info, info_span};
 
-use super::types::{DeliveryEvent, DeliveryEventPayload};
+use super::types::DeliveryEvent;
 use crate::config::JournalConfig;
 
+/// Sends of one frame over an open connection before it is dropped;
+/// reconnecting does not count against it.
 const RETRY_ATTEMPTS: usize = 3;
 const FRAME_TO: &str = "bouncer@ingest";
 
+/// Runs the TCP publisher loop. Timeouts, heartbeat and backoff settings
+/// follow reloads sent on `config_rx`.
 pub async fn run_publisher(
-    config: JournalConfig,
+    mut config_rx: watch::Receiver<JournalConfig>,
     mut events_rx: mpsc::Receiver<DeliveryEvent>,
+    queue_map: Arc<QueueMapGauge>,
+    clock: SharedClock,
     shutdown: CancellationToken
 ) -> Result<()> {
+    let mut config = config_rx.borrow_and_update().clone();
     let mut connection: Option<TcpStream> = None;
-    let mut heartbeat_tick = interval(Duration::from_secs(config.heartbeat_secs.max(1)));
+    let mut backoff = publisher_backoff(&config);
+    let mut outage = Outage::new(clock.clone());
+    let mut heartbeat_base = Duration::from_secs(config.heartbeat_secs.max(1));
+    // Start at a random phase so agents restarted together do not heartbeat in lockstep.
+    let heartbeat = sleep(initial_delay(heartbeat_base));
+    tokio::pin!(heartbeat);
+    let mut sequence = EventSequence::default();
 
     loop {
         tokio::select! {
@@ -30,15 +52,29 @@ pub async fn run_publisher(
                 info!("publisher stopping");
                 break;
             }
+            Ok(()) = config_rx.changed() => {
+                config = config_rx.borrow_and_update().clone();
+                backoff = publisher_backoff(&config);
+                heartbeat_base = Duration::from_secs(config.heartbeat_secs.max(1));
+                let next = jittered(heartbeat_base, config.heartbeat_jitter_pct);
+                heartbeat.as_mut().reset(Instant::now() + next);
+                debug!("publisher settings reloaded");
+            }
             maybe_event = events_rx.recv() => {
                 let Some(event) = maybe_event else {
                     break;
                 };
 
-                let payload = match build_delivery_payload(&config, &event) {
+                let payload = match build_delivery_payload(
+                    &config,
+                    &event,
+                    sequence.next(clock.system_now()),
+                    clock.as_ref()
+                ) {
                     Ok(payload) => payload,
                     Err(err) => {
-                        warn!(
+                        coded_warn!(
+                            ErrorCode::EventEncodeFailed,
                             "failed to serialize journal event: hash={}, queue_id={}, error={}",
                             event.hash,
                             event.queue_id,
@@ -47,13 +83,26 @@ pub async fn run_publisher(
                         continue;
                     }
                 };
+                // The span's trace context travels in the frame header, so
+                // the server-side ingest/parse/DB spans join this trace.
+                let publish_span = info_span!(
+                    "publish",
+                    kind = "observer_event",
+                    hash = %event.hash,
+                    queue_id = %event.queue_id,
+                    unit = %event.unit
+                );
                 if let Err(err) = send_with_retry(
                     &config,
                     &mut connection,
+                    &mut backoff,
+                    &mut outage,
+                    &shutdown,
                     "observer_event",
                     &payload,
-                ).await {
-                    warn!(
+                ).instrument(publish_span).await {
+                    coded_warn!(
+                        ErrorCode::EventPublishFailed,
                         "failed to publish journal event: hash={}, queue_id={}, smtp_status={}, error={}",
                         event.hash,
                         event.queue_id,
@@ -62,7 +111,8 @@ pub async fn run_publisher(
                     );
                 } else {
                     info!(
-                        "journal event published: hash={}, queue_id={}, recipient={}, smtp_status={}, status_code={}, action={}",
+                        "journal event published: unit={}, hash={}, queue_id={}, recipient={}, smtp_status={}, status_code={}, action={}",
+                        event.unit,
                         event.hash,
                         event.queue_id,
                         event.recipient,
@@ -72,16 +122,30 @@ pub async fn run_publisher(
                     );
                 }
             }
-            _ = heartbeat_tick.tick(), if config.heartbeat_secs > 0 => {
-                let payload = build_heartbeat_payload();
-                if let Err(err) = send_with_retry(
+            _ = &mut heartbeat, if config.heartbeat_secs > 0 => {
+                let payload = build_heartbeat_payload(&queue_map, clock.as_ref());
+                let next = match send_with_retry(
                     &config,
                     &mut connection,
+                    &mut backoff,
+                    &mut outage,
+                    &shutdown,
                     "heartbeat",
                     &payload,
                 ).await {
-                    debug!("heartbeat send failed: error={err}");
-                }
+                    Ok(()) => jittered(heartbeat_base, config.heartbeat_jitter_pct),
+                    Err(err) => {
+                        // Probe again on the backoff schedule instead of the fixed
+                        // heartbeat period so reconnects spread out after an outage.
+                        let delay = backoff.next_delay().min(heartbeat_base);
+                        debug!(
+                            "heartbeat send failed: next_probe_ms={}, error={err}",
+                            delay.as_millis()
+                        );
+                        delay
+                    }
+                };
+                heartbeat.as_mut().reset(Instant::now() + next);
             }
         }
     }
@@ -89,38 +153,76 @@ pub async fn run_publisher(
     Ok(())
 }
 
+fn publisher_backoff(config: &JournalConfig) -> Backoff {
+    Backoff::new(
+        Duration::from_millis(config.reconnect_base_ms),
+        Duration::from_secs(config.reconnect_max_secs)
+    )
+}
+
+/// Sends a frame, reconnecting for as long as the server is unreachable and
+/// retrying a frame that fails on an open connection [`RETRY_ATTEMPTS`] times.
 async fn send_with_retry(
     config: &JournalConfig,
     connection: &mut Option<TcpStream>,
+    backoff: &mut Backoff,
+    outage: &mut Outage,
+    shutdown: &CancellationToken,
     kind: &str,
     payload: &[u8]
 ) -> Result<()> {
     let mut last_error: Option<anyhow::Error> = None;
+    let mut attempt = 0;
 
-    for attempt in 1..=RETRY_ATTEMPTS {
-        if connection.is_none() {
-            match connect_and_register(config).await {
+    while attempt < RETRY_ATTEMPTS {
+        let stream = match connection {
+            Some(stream) => stream,
+            None => match connect_and_register(config).await {
                 Ok(stream) => {
-                    *connection = Some(stream);
+                    if let Some((down, failed_connects)) = outage.end() {
+                        info!(
+                            "publisher reconnected: server={}, disconnected_ms={}, failed_connects={}, disconnected_total_ms={}",
+                            config.server,
+                            down.as_millis(),
+                            failed_connects,
+                            outage.total().as_millis()
+                        );
+                    }
+                    connection.insert(stream)
                 }
                 Err(err) => {
-                    last_error = Some(err);
-                    sleep(Duration::from_millis((attempt * 250) as u64)).await;
+                    // Never give up on connecting: the frame and everything
+                    // queued behind it wait until the server is back.
+                    let failed_connects = outage.connect_failed();
+                    if failed_connects == 1 {
+                        coded_warn!(
+                            ErrorCode::ServerUnreachable,
+                            "server unreachable, events stay queued while reconnecting: server={}, error={:#}",
+                            config.server,
+                            err
+                        );
+                    }
+                    if !wait_before_retry(backoff, shutdown, kind, failed_connects as usize).await {
+                        return Err(err);
+                    }
                     continue;
                 }
             }
-        }
-
-        let Some(stream) = connection.as_mut() else {
-            continue;
         };
 
+        attempt += 1;
         match send_frame(config, stream, kind, payload).await {
-            Ok(()) => return Ok(()),
+            Ok(()) => {
+                backoff.reset();
+                return Ok(());
+            }
             Err(err) => {
                 *connection = None;
+                outage.begin();
                 last_error = Some(err);
-                sleep(Duration::from_millis((attempt * 250) as u64)).await;
+                if !wait_before_retry(backoff, shutdown, kind, attempt).await {
+                    break;
+                }
             }
         }
     }
@@ -128,6 +230,27 @@ async fn send_with_retry(
     Err(last_error.unwrap_or_else(|| anyhow::anyhow!("send failed")))
 }
 
+/// Sleeps for the next backoff delay; returns `false` when shutdown wins.
+async fn wait_before_retry(
+    backoff: &mut Backoff,
+    shutdown: &CancellationToken,
+    kind: &str,
+    attempt: usize
+) -> bool {
+    let delay = backoff.next_delay();
+    debug!(
+        "publisher retry scheduled: kind={}, attempt={}, delay_ms={}",
+        kind,
+        attempt,
+        delay.as_millis()
+    );
+
+    tokio::select! {
+        _ = shutdown.cancelled() => false,
+        _ = sleep(delay) => true
+    }
+}
+
 async fn connect_and_register(config: &JournalConfig) -> Result<TcpStream> {
     let timeout_window = Duration::from_secs(config.connect_timeout_secs.max(1));
     let mut stream = timeout(timeout_window, TcpStream::connect(&config.server))
@@ -138,9 +261,10 @@ async fn connect_and_register(config: &JournalConfig) -> Result<TcpStream> {
     stream.set_nodelay(true).ok();
 
     let register_payload = format!(
-        "source={}\ninput=journald\nunit={}\n",
+        "source={}\ninput=journald\nunit={}\nheartbeat_secs={}\n",
         sanitize_header_value(&config.source),
-        sanitize_header_value(&config.unit)
+        sanitize_header_value(&config.units.join(",")),
+        config.heartbeat_secs
     );
 
     send_frame(config, &mut stream, "register", register_payload.as_bytes())
@@ -161,8 +285,13 @@ async fn send_frame(
         from: format!("journal@{}", sanitize_header_value(&config.source)),
         to: FRAME_TO.to_string(),
         kind: Some(kind.to_string()),
-        source: Some(config.source.clone())
+        source: Some(config.source.clone()),
+        traceparent: logging::current_traceparent(),
+        queue_id: None,
+        tenant: config.tenant.clone(),
+        checksum: None
     };
+    let header = if config.frame_checksum { header.with_checksum(payload) } else { header };
 
     let header_bytes = encode_header_json(&header).context("failed to encode frame header")?;
 
@@ -183,29 +312,37 @@ async fn send_frame(
 
 fn build_delivery_payload(
     config: &JournalConfig,
-    event: &DeliveryEvent
+    event: &DeliveryEvent,
+    sequence: u64,
+    clock: &dyn Clock
 ) -> Result<Vec<u8>> {
-    let payload = DeliveryEventPayload {
-        source: sanitize_header_value(&config.source),
-        hash: sanitize_header_value(&event.hash),
-        queue_id: sanitize_header_value(&event.queue_id),
-        recipient: sanitize_header_value(&event.recipient),
-        status_code: sanitize_header_value(&event.status_code),
-        action: sanitize_header_value(&event.action),
-        diagnostic: sanitize_header_value(&event.diagnostic),
-        smtp_status: sanitize_header_value(&event.smtp_status),
-        observed_at_unix: SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0)
-    };
+    let payload = bouncer_proto::event::DeliveryEvent::new(
+        &config.source,
+        &event.hash,
+        &event.queue_id,
+        &event.recipient,
+        event.occurred_at_unix.unwrap_or_else(|| clock.unix_secs())
+    )
+    .with_outcome(&event.smtp_status, &event.status_code, &event.action)
+    .with_service(&event.service)
+    .with_diagnostic(&event.diagnostic)
+    .with_unit(&event.unit)
+    .with_sequence(sequence)
+    .sanitized();
 
-    serde_json::to_vec(&payload).context("failed to encode journal delivery event")
+    encode_delivery_event(&payload).context("failed to encode journal delivery event")
 }
 
-fn build_heartbeat_payload() -> Vec<u8> {
-    let ts = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
-    format!("ts={ts}\n").into_bytes()
+fn build_heartbeat_payload(
+    queue_map: &QueueMapGauge,
+    clock: &dyn Clock
+) -> Vec<u8> {
+    let occupancy = QueueMapOccupancy {
+        entries: queue_map.entries(),
+        capacity: queue_map.capacity(),
+        evicted: queue_map.evicted()
+    };
+    encode_heartbeat(clock.unix_secs(), Some(occupancy))
 }
 
 fn sanitize_header_value(value: &str) -> String {
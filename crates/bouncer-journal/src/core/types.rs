@@ -1,48 +1,29 @@
-use std::time::Instant;
-
-use serde::Serialize;
-
-#[derive(Debug, Clone)]
-pub struct QueueEntry {
-    pub hash: String,
-    pub updated_at: Instant
-}
-
-#[derive(Debug, Clone)]
-pub struct SmtpEvent {
-    pub queue_id: String,
-    pub recipient: String,
-    pub smtp_status: String,
-    pub status_code: String,
-    pub action: String,
-    pub diagnostic: String
-}
-
 #[derive(Debug, Clone)]
 pub struct DeliveryEvent {
+    /// systemd unit that logged the delivery.
+    pub unit: String,
     pub hash: String,
     pub queue_id: String,
-    pub recipient: String,
-    pub status_code: String,
-    pub action: String,
-    pub diagnostic: String,
-    pub smtp_status: String
-}
-
-#[derive(Debug, Serialize)]
-pub struct DeliveryEventPayload {
-    pub source: String,
-    pub hash: String,
-    pub queue_id: String,
+    /// Delivery agent that logged the outcome.
+    pub service: String,
     pub recipient: String,
     pub status_code: String,
     pub action: String,
     pub diagnostic: String,
     pub smtp_status: String,
-    pub observed_at_unix: u64
+    /// `__REALTIME_TIMESTAMP` of the journal entry.
+    pub occurred_at_unix: Option<u64>
 }
 
-pub enum ParsedSyslog {
-    Cleanup { queue_id: String, hash: String },
-    Smtp(SmtpEvent)
+/// The fields of a postfix journal entry the watcher parses.
+#[derive(Debug)]
+pub struct JournalLine {
+    /// `_SYSTEMD_UNIT`.
+    pub unit: String,
+    /// Last component of `SYSLOG_IDENTIFIER`: `smtp` for `postfix/smtp` and
+    /// `postfix-out1/smtp`.
+    pub service: String,
+    /// `MESSAGE`.
+    pub message: String,
+    pub realtime_unix: Option<u64>
 }
@@ -0,0 +1,47 @@
+use bouncer_helpers::glob::{glob_match, is_glob};
+
+/// The configured `units`: exact names become journald matches, entries with
+/// `*` or `?` are glob patterns checked against each entry's `_SYSTEMD_UNIT`.
+#[derive(Debug, Clone)]
+pub struct UnitFilter {
+    exact: Vec<String>,
+    patterns: Vec<String>
+}
+
+impl UnitFilter {
+    pub fn new(units: &[String]) -> Self {
+        let (patterns, exact) = units.iter().cloned().partition(|unit| is_glob(unit));
+        Self { exact, patterns }
+    }
+
+    /// Units journald can match itself, or `None` when a glob pattern needs
+    /// every entry of the system journal.
+    pub fn journald_matches(&self) -> Option<&[String]> {
+        if self.patterns.is_empty() { Some(&self.exact) } else { None }
+    }
+
+    pub fn matches(
+        &self,
+        unit: &str
+    ) -> bool {
+        self.exact.iter().any(|exact| exact == unit)
+            || self.patterns.iter().any(|pattern| glob_match(pattern, unit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnitFilter;
+
+    #[test]
+    fn matches_exact_units_and_instance_patterns() {
+        let exact = UnitFilter::new(&["postfix.service".to_string()]);
+        assert_eq!(exact.journald_matches(), Some(&["postfix.service".to_string()][..]));
+
+        let filter = UnitFilter::new(&["postfix.service".into(), "postfix@*.service".into()]);
+        assert!(filter.journald_matches().is_none());
+        assert!(filter.matches("postfix.service"));
+        assert!(filter.matches("postfix@out2.service"));
+        assert!(!filter.matches("dovecot.service"));
+    }
+}
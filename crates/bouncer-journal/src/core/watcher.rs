@@ -1,26 +1,39 @@
-use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, UNIX_EPOCH};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use bouncer_helpers::clock::SharedClock;
+use bouncer_helpers::coded_warn;
+use bouncer_helpers::error_code::ErrorCode;
+use bouncer_helpers::glob::glob_match;
+use bouncer_helpers::maillog::{ParsedSyslog, parse_postfix_message};
+use bouncer_helpers::message_hash::HashFormat;
+use bouncer_helpers::queue_map::{QueueMap, QueueMapGauge};
 use systemd::{JournalSeek, journal};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio::time::interval;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, info, trace, warn};
+use tracing::{debug, info, trace};
 
-use super::parser::parse_postfix_line;
-use super::types::{DeliveryEvent, ParsedSyslog, QueueEntry};
+use super::types::{DeliveryEvent, JournalLine};
+use super::units::UnitFilter;
 use crate::config::JournalConfig;
 
+/// `mapping_ttl_secs` is read from `config_rx` on every cleanup and
+/// `publish_delivered` on every delivery line, so a reload applies to the
+/// next one.
 pub async fn run_journal_watcher(
-    config: JournalConfig,
+    config_rx: watch::Receiver<JournalConfig>,
     events_tx: mpsc::Sender<DeliveryEvent>,
+    gauge: Arc<QueueMapGauge>,
+    clock: SharedClock,
     shutdown: CancellationToken,
 ) -> Result<()> {
-    let (lines_tx, mut lines_rx) = mpsc::unbounded_channel::<String>();
+    let config = config_rx.borrow().clone();
+    let hash_format = HashFormat::new(&config.hash).context("invalid `hash.pattern`")?;
+    let (lines_tx, mut lines_rx) = mpsc::unbounded_channel::<JournalLine>();
     let stop = Arc::new(AtomicBool::new(false));
 
     let thread_config = config.clone();
@@ -29,13 +42,15 @@ pub async fn run_journal_watcher(
         run_reader_thread(thread_config, lines_tx, thread_stop);
     });
 
-    let mut queue_map: HashMap<String, QueueEntry> = HashMap::new();
-    let ttl = Duration::from_secs(config.mapping_ttl_secs.max(60));
+    // Keyed by (unit, queue_id): postfix instances keep separate queues.
+    let mut queue_map: QueueMap<(String, String)> =
+        QueueMap::new(config.mapping_capacity, gauge.clone());
+    let mut reported_evictions = 0;
     let mut cleanup_tick = interval(Duration::from_secs(300));
 
     info!(
-        "journal listener ready: unit={}, identifiers={}",
-        config.unit,
+        "journal listener ready: units={}, identifiers={}",
+        config.units.join(","),
         config.identifiers.join(",")
     );
 
@@ -46,7 +61,18 @@ pub async fn run_journal_watcher(
                 break;
             }
             _ = cleanup_tick.tick() => {
-                let removed = prune_queue_map(&mut queue_map, ttl);
+                let ttl = Duration::from_secs(config_rx.borrow().mapping_ttl_secs.max(60));
+                let removed = queue_map.prune(ttl, clock.now());
+                let evicted = gauge.evicted();
+                if evicted > reported_evictions {
+                    coded_warn!(
+                        ErrorCode::QueueMapFull,
+                        "queue map at capacity, evicted oldest mappings: evicted={}, capacity={}",
+                        evicted - reported_evictions,
+                        config.mapping_capacity
+                    );
+                    reported_evictions = evicted;
+                }
                 if removed > 0 {
                     debug!(
                         "cleaned stale queue mappings: removed={}, tracked={}",
@@ -56,49 +82,67 @@ pub async fn run_journal_watcher(
                 }
             }
             maybe_line = lines_rx.recv() => {
-                let Some(line) = maybe_line else {
+                let Some(JournalLine { unit, service, message, realtime_unix }) = maybe_line else {
                     break;
                 };
 
-                let Some(parsed) = parse_postfix_line(line.trim()) else {
+                let Some(parsed) = parse_postfix_message(&service, message.trim(), &hash_format) else {
                     continue;
                 };
 
                 match parsed {
                     ParsedSyslog::Cleanup { queue_id, hash } => {
                         debug!(
-                            "queue mapping stored: queue_id={}, hash={}",
-                            queue_id, hash
-                        );
-                        queue_map.insert(
-                            queue_id,
-                            QueueEntry {
-                                hash,
-                                updated_at: Instant::now(),
-                            },
+                            "queue mapping stored: unit={}, queue_id={}, hash={}",
+                            unit, queue_id, hash
                         );
+                        queue_map.insert((unit, queue_id), hash, clock.now());
+                    }
+                    ParsedSyslog::Notification { queue_id, notice_queue_id } => {
+                        // The notification is a new message; keep its deliveries
+                        // from being reported as outcomes of a tracked hash.
+                        let key = (unit, notice_queue_id);
+                        if let Some(hash) = queue_map.remove(&key) {
+                            debug!(
+                                "queue mapping dropped for sender notification: unit={}, queue_id={}, notice_queue_id={}, hash={}",
+                                key.0, queue_id, key.1, hash
+                            );
+                        }
                     }
-                    ParsedSyslog::Smtp(smtp) => {
-                        let Some(entry) = queue_map.get_mut(&smtp.queue_id) else {
+                    ParsedSyslog::Delivery(delivery) => {
+                        let key = (unit, delivery.queue_id);
+                        let Some(hash) = queue_map.touch(&key, clock.now()) else {
                             trace!(
-                                "smtp log without known queue mapping: queue_id={}",
-                                smtp.queue_id
+                                "delivery log without known queue mapping: unit={}, service={}, queue_id={}",
+                                key.0, delivery.service, key.1
                             );
                             continue;
                         };
+                        if delivery.action == "delivered" && !config_rx.borrow().publish_delivered {
+                            trace!(
+                                "delivered event dropped: unit={}, service={}, queue_id={}, hash={}",
+                                key.0, delivery.service, key.1, hash
+                            );
+                            continue;
+                        }
 
-                        entry.updated_at = Instant::now();
+                        let (unit, queue_id) = key;
                         let event = DeliveryEvent {
-                            hash: entry.hash.clone(),
-                            queue_id: smtp.queue_id,
-                            recipient: smtp.recipient,
-                            status_code: smtp.status_code,
-                            action: smtp.action,
-                            diagnostic: smtp.diagnostic,
-                            smtp_status: smtp.smtp_status,
+                            unit,
+                            hash,
+                            queue_id,
+                            service: delivery.service,
+                            recipient: delivery.recipient,
+                            status_code: delivery.status_code,
+                            action: delivery.action,
+                            diagnostic: delivery.diagnostic,
+                            smtp_status: delivery.smtp_status,
+                            occurred_at_unix: realtime_unix,
                         };
                         debug!(
-                            "smtp log matched queue mapping: queue_id={}, hash={}, smtp_status={}, status_code={}, action={}, recipient={}",
+                            "delivery log matched queue mapping: unit={}, service={}, queue_id={}, hash={}, smtp_status={}, status_code={}, action={}, recipient={}",
+                            event.unit,
+                            event.service,
                             event.queue_id,
                             event.hash,
                             event.smtp_status,
@@ -108,7 +152,8 @@ pub async fn run_journal_watcher(
                         );
 
                         if let Err(err) = events_tx.try_send(event) {
-                            warn!(
+                            coded_warn!(
+                                ErrorCode::EventQueueFull,
                                 "journal event queue is full, dropping event: error={err}"
                             );
                         }
@@ -130,18 +175,22 @@ pub async fn run_journal_watcher(
 
 fn run_reader_thread(
     config: JournalConfig,
-    lines_tx: mpsc::UnboundedSender<String>,
+    lines_tx: mpsc::UnboundedSender<JournalLine>,
     stop: Arc<AtomicBool>,
 ) {
+    let units = UnitFilter::new(&config.units);
     loop {
         if stop.load(Ordering::Relaxed) {
             return;
         }
 
-        let mut reader = match open_reader(&config) {
+        let mut reader = match open_reader(&units) {
             Ok(reader) => reader,
             Err(err) => {
-                warn!("failed to open journald reader: error={err}");
+                coded_warn!(
+                    ErrorCode::JournalReadFailed,
+                    "failed to open journald reader: error={err}"
+                );
                 thread::sleep(Duration::from_secs(1));
                 continue;
             }
@@ -149,7 +198,10 @@ fn run_reader_thread(
 
         if config.seek_tail {
             if let Err(err) = reader.seek(JournalSeek::Tail) {
-                warn!("failed to seek journald tail: error={err}");
+                coded_warn!(
+                    ErrorCode::JournalReadFailed,
+                    "failed to seek journald tail: error={err}"
+                );
             } else {
                 let _ = reader.next();
             }
@@ -165,14 +217,18 @@ fn run_reader_thread(
                     let _ = reader.wait(Some(Duration::from_millis(500)));
                 }
                 Ok(_) => {
-                    if let Some(line) = extract_postfix_line(&mut reader, &config.identifiers)
+                    if let Some(line) =
+                        extract_postfix_entry(&mut reader, &units, &config.identifiers)
                         && lines_tx.send(line).is_err()
                     {
                         return;
                     }
                 }
                 Err(err) => {
-                    warn!("journald next() failed: error={err}");
+                    coded_warn!(
+                        ErrorCode::JournalReadFailed,
+                        "journald next() failed: error={err}"
+                    );
                     break;
                 }
             }
@@ -180,26 +236,51 @@ fn run_reader_thread(
     }
 }
 
-fn open_reader(config: &JournalConfig) -> Result<journal::Journal> {
+/// Adds one `_SYSTEMD_UNIT` match per exact unit; journald ORs matches on the
+/// same field. Glob patterns cannot be expressed as matches, so then every
+/// entry is read and `extract_postfix_entry` filters by unit.
+fn open_reader(units: &UnitFilter) -> Result<journal::Journal> {
     let mut reader = journal::OpenOptions::default().system(true).local_only(true).open()?;
-    reader.match_add("_SYSTEMD_UNIT", config.unit.clone())?;
+    match units.journald_matches() {
+        Some(exact) => {
+            for unit in exact {
+                reader.match_add("_SYSTEMD_UNIT", unit.clone())?;
+            }
+        }
+        None => debug!("unit glob patterns configured, filtering journal entries by unit"),
+    }
     Ok(reader)
 }
 
-fn extract_postfix_line(
+/// Reads the fields of the current entry when it comes from a postfix
+/// identifier of a watched unit.
+fn extract_postfix_entry(
     reader: &mut journal::Journal,
+    units: &UnitFilter,
     identifiers: &[String],
-) -> Option<String> {
+) -> Option<JournalLine> {
+    let unit = get_data_string(reader, "_SYSTEMD_UNIT")?;
+    if !units.matches(&unit) {
+        return None;
+    }
+
     let message = get_data_string(reader, "MESSAGE")?;
     let identifier = get_data_string(reader, "SYSLOG_IDENTIFIER")
         .or_else(|| get_data_string(reader, "_COMM"))?;
 
-    let matched = identifiers.iter().any(|needle| identifier.eq_ignore_ascii_case(needle));
+    let matched = identifiers.iter().any(|needle| glob_match(needle, &identifier));
     if !matched {
         return None;
     }
 
-    Some(format!("{identifier}[0]: {message}"))
+    // Instances log as `<syslog_name>/<service>`, e.g. `postfix-out1/smtp`.
+    let service = identifier.rsplit('/').next().unwrap_or(&identifier).to_string();
+    let realtime_unix = reader
+        .timestamp()
+        .ok()
+        .and_then(|at| at.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_secs());
+    Some(JournalLine { unit, service, message, realtime_unix })
 }
 
 fn get_data_string(
@@ -211,13 +292,3 @@ fn get_data_string(
         .ok()?
         .and_then(|field| field.value().map(|value| String::from_utf8_lossy(value).into_owned()))
 }
-
-fn prune_queue_map(
-    queue_map: &mut HashMap<String, QueueEntry>,
-    ttl: Duration,
-) -> usize {
-    let before = queue_map.len();
-    let now = Instant::now();
-    queue_map.retain(|_, entry| now.duration_since(entry.updated_at) <= ttl);
-    before.saturating_sub(queue_map.len())
-}
@@ -7,15 +7,23 @@ mod core;
 
 #[cfg(target_os = "linux")]
 use core::{run_journal_watcher, run_publisher};
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
 
 #[cfg(target_os = "linux")]
 use anyhow::{Context, Result};
 #[cfg(target_os = "linux")]
-use bouncer_helpers::{logging, shutdown};
+use args::JournalArgs;
+#[cfg(target_os = "linux")]
+use bouncer_helpers::queue_map::QueueMapGauge;
+#[cfg(target_os = "linux")]
+use bouncer_helpers::reload::{apply_log_filter, run_config_reload};
+#[cfg(target_os = "linux")]
+use bouncer_helpers::{clock, logging, shutdown};
 #[cfg(target_os = "linux")]
 use config::JournalConfig;
 #[cfg(target_os = "linux")]
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 #[cfg(target_os = "linux")]
 use tokio_util::sync::CancellationToken;
 #[cfg(target_os = "linux")]
@@ -24,12 +32,21 @@ use tracing::{info, warn};
 #[cfg(target_os = "linux")]
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
-    logging::init_logging("bouncer_journal=info,tokio=warn", "JOURNAL_LOG", "bouncer-journal");
+    let _logging =
+        logging::init_logging("bouncer_journal=info,tokio=warn", "JOURNAL_LOG", "bouncer-journal");
 
-    let config = JournalConfig::load()?;
+    let args = JournalArgs::parse(std::env::args().skip(1))?;
+    let config = JournalConfig::load_from(args.config_path)?;
+    if args.check_config {
+        print!("{}", config.dump()?);
+        return Ok(());
+    }
+    if config.log_filter.is_some() {
+        apply_log_filter(config.log_filter.as_deref());
+    }
     info!(
-        "journal watcher starting: unit={}, server={}, source={}, identifiers={}",
-        config.unit,
+        "journal watcher starting: units={}, server={}, source={}, identifiers={}",
+        config.units.join(","),
         config.server,
         config.source,
         config.identifiers.join(",")
@@ -37,12 +54,22 @@ async fn main() -> Result<()> {
 
     let (events_tx, events_rx) = mpsc::channel(config.queue_capacity.max(1));
     let shutdown = CancellationToken::new();
+    let clock = clock::system_clock();
     tokio::spawn(shutdown::listen_shutdown(shutdown.clone()));
+    let queue_map = Arc::new(QueueMapGauge::default());
+    let (config_tx, config_rx) = watch::channel(config);
+    tokio::spawn(run_config_reload(config_tx, shutdown.clone()));
 
-    let watcher_task =
-        tokio::spawn(run_journal_watcher(config.clone(), events_tx, shutdown.clone()));
+    let watcher_task = tokio::spawn(run_journal_watcher(
+        config_rx.clone(),
+        events_tx,
+        queue_map.clone(),
+        clock.clone(),
+        shutdown.clone()
+    ));
 
-    let publisher_task = tokio::spawn(run_publisher(config.clone(), events_rx, shutdown.clone()));
+    let publisher_task =
+        tokio::spawn(run_publisher(config_rx, events_rx, queue_map, clock, shutdown.clone()));
 
     shutdown.cancelled().await;
 
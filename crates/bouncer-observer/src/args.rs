@@ -2,27 +2,52 @@ use std::path::PathBuf;
 
 use anyhow::{Result, bail};
 
+use crate::core::MAX_SIMULATE_RATE;
+
+const USAGE: &str =
+    "usage: bouncer-observer [--check-config] [--simulate[=<messages-per-sec>]] [config-path]";
+
+/// Messages per second `--simulate` sends without a rate.
+const DEFAULT_SIMULATE_RATE: u32 = 1;
+
 #[derive(Debug, Default)]
 pub struct ObserverArgs {
-    pub config_path: Option<PathBuf>
+    pub config_path: Option<PathBuf>,
+    /// Load and validate the config, print it and exit.
+    pub check_config: bool,
+    /// Also send this many synthetic postfix messages per second to
+    /// `listen_udp`, to verify a deployment before real traffic.
+    pub simulate: Option<u32>
 }
 
 impl ObserverArgs {
-    pub fn parse<I>(mut args: I) -> Result<Self>
+    pub fn parse<I>(args: I) -> Result<Self>
     where
         I: Iterator<Item = String>
     {
-        let first = args.next();
-        let second = args.next();
-
-        if let Some(arg) = second {
-            bail!("too many arguments: {arg} (usage: bouncer-observer [config-path])");
+        let mut parsed = Self::default();
+        for arg in args {
+            match arg.as_str() {
+                "-h" | "--help" => bail!(USAGE),
+                "--check-config" if !parsed.check_config => parsed.check_config = true,
+                "--simulate" if parsed.simulate.is_none() => {
+                    parsed.simulate = Some(DEFAULT_SIMULATE_RATE);
+                }
+                _ if arg.starts_with("--simulate=") && parsed.simulate.is_none() => {
+                    let rate = &arg["--simulate=".len()..];
+                    match rate.parse::<u32>() {
+                        Ok(rate) if (1..=MAX_SIMULATE_RATE).contains(&rate) => {
+                            parsed.simulate = Some(rate);
+                        }
+                        _ => bail!(
+                            "invalid --simulate rate: {rate}, expected 1 to {MAX_SIMULATE_RATE} ({USAGE})"
+                        )
+                    }
+                }
+                _ if parsed.config_path.is_none() => parsed.config_path = Some(PathBuf::from(arg)),
+                _ => bail!("too many arguments: {arg} ({USAGE})")
+            }
         }
-
-        if matches!(first.as_deref(), Some("-h" | "--help")) {
-            bail!("usage: bouncer-observer [config-path]");
-        }
-
-        Ok(Self { config_path: first.map(PathBuf::from) })
+        Ok(parsed)
     }
 }
@@ -3,19 +3,45 @@ use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
-use serde::Deserialize;
+use bouncer_helpers::backoff::MAX_JITTER_PCT;
+use bouncer_helpers::config_file::{self, ConfigSnapshot};
+use bouncer_helpers::logging;
+use bouncer_helpers::message_hash::{HashFormat, HashFormatConfig};
+use bouncer_helpers::reload::ReloadableConfig;
+use serde::{Deserialize, Serialize};
 
 use crate::args::ObserverArgs;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct ObserverConfig {
+    /// Where MTA log lines come from.
+    #[serde(default)]
+    pub input: ObserverInput,
+    /// MTA that writes the log lines.
+    #[serde(default)]
+    pub log_format: LogFormat,
     #[serde(default = "default_listen_udp")]
     pub listen_udp: SocketAddr,
+    /// Log files followed with `input: file`; `*` and `?` are allowed in the
+    /// file name, e.g. `/var/log/mail*.log`.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// How often followed files are checked for new lines and rotation.
+    #[serde(default = "default_file_poll_ms")]
+    pub file_poll_ms: u64,
     #[serde(default = "default_server")]
     pub server: String,
     #[serde(default = "default_source")]
     pub source: String,
+    /// Tenant sent with every frame; events then only match messages of
+    /// that tenant.
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Sends a body checksum with every frame, verified by the server;
+    /// servers that predate checksums ignore it.
+    #[serde(default)]
+    pub frame_checksum: bool,
     #[serde(default = "default_queue_capacity")]
     pub queue_capacity: usize,
     #[serde(default = "default_connect_timeout_secs")]
@@ -24,39 +50,198 @@ pub struct ObserverConfig {
     pub io_timeout_secs: u64,
     #[serde(default = "default_heartbeat_secs")]
     pub heartbeat_secs: u64,
+    #[serde(default = "default_heartbeat_jitter_pct")]
+    pub heartbeat_jitter_pct: u8,
+    #[serde(default = "default_reconnect_base_ms")]
+    pub reconnect_base_ms: u64,
+    #[serde(default = "default_reconnect_max_secs")]
+    pub reconnect_max_secs: u64,
     #[serde(default = "default_mapping_ttl_secs")]
-    pub mapping_ttl_secs: u64
+    pub mapping_ttl_secs: u64,
+    /// Queue-id mappings kept at most; the least recently used is evicted.
+    #[serde(default = "default_mapping_capacity")]
+    pub mapping_capacity: usize,
+    /// Publish `delivered` outcomes; false drops them before they are queued.
+    #[serde(default = "default_publish_delivered")]
+    pub publish_delivered: bool,
+    /// Message hash format read from `cleanup` message-ids.
+    #[serde(default = "default_hash_format")]
+    pub hash: HashFormatConfig,
+    /// `EnvFilter` directives replacing `OBSERVER_LOG`; reloaded on SIGHUP.
+    #[serde(default)]
+    pub log_filter: Option<String>,
+    /// File this config was read from; enables reloading on SIGHUP.
+    #[serde(skip)]
+    pub(crate) snapshot: Option<ConfigSnapshot>
 }
 
+/// Source of the MTA log lines the observer correlates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ObserverInput {
+    /// Syslog datagrams forwarded to `listen_udp` (rsyslog, syslog-ng).
+    #[default]
+    Udp,
+    /// Log files written by the MTA or the local syslog daemon, tailed.
+    File
+}
+
+impl ObserverInput {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Udp => "udp",
+            Self::File => "file"
+        }
+    }
+}
+
+/// Log format of the MTA the observer watches; see `core::parser`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `postfix/*` syslog lines.
+    #[default]
+    Postfix,
+    /// Exim mainlog lines, from the log file or syslog.
+    Exim,
+    /// `sendmail`/`sm-mta` syslog lines.
+    Sendmail
+}
+
+impl LogFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Postfix => "postfix",
+            Self::Exim => "exim",
+            Self::Sendmail => "sendmail"
+        }
+    }
+}
+
+/// Prefix of the environment variables overriding config fields
+/// (`OBSERVER_SERVER`, `OBSERVER_SOURCE`, ...).
+const ENV_PREFIX: &str = "OBSERVER";
+
+/// Top-level keys applied on SIGHUP; see [`ObserverConfig::apply_reloadable`].
+pub(crate) const RELOADABLE_KEYS: [&str; 9] = [
+    "connect_timeout_secs",
+    "io_timeout_secs",
+    "heartbeat_secs",
+    "heartbeat_jitter_pct",
+    "reconnect_base_ms",
+    "reconnect_max_secs",
+    "mapping_ttl_secs",
+    "publish_delivered",
+    "log_filter"
+];
+
 impl ObserverConfig {
+    /// Reads the config file, or only `OBSERVER_<FIELD>` variables when no
+    /// file is found and at least one of them is set.
     pub fn load() -> Result<Self> {
-        let args = ObserverArgs::parse(env::args().skip(1))?;
-        let config_path = args
-            .config_path
-            .or_else(resolve_observer_config_path)
-            .context("observer config path not found (OBSERVER_CONFIG_PATH or observer.yaml)")?;
-        let mut config = load_observer_config_yaml(&config_path)?;
+        Self::load_from(ObserverArgs::parse(env::args().skip(1))?.config_path)
+    }
+
+    /// [`Self::load`] with `path` in place of the command line argument.
+    pub fn load_from(path: Option<PathBuf>) -> Result<Self> {
+        if let Some(config_path) = path.or_else(resolve_observer_config_path) {
+            return Self::from_path(&config_path);
+        }
+        let mut config = config_file::load_env::<Self>(ENV_PREFIX)?.context(
+            "observer config path not found (OBSERVER_CONFIG_PATH or observer.yaml) and no OBSERVER_* settings"
+        )?;
+        config.normalize()?;
+        Ok(config)
+    }
+
+    /// Reads and normalizes a YAML config file; set `OBSERVER_<FIELD>`
+    /// variables replace its top-level fields.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let (mut config, snapshot) =
+            config_file::load_yaml_snapshot_with_env::<Self>(path, ENV_PREFIX)?;
+        config.snapshot = Some(snapshot);
         config.normalize()?;
         Ok(config)
     }
 
-    fn normalize(&mut self) -> Result<()> {
+    pub(crate) fn normalize(&mut self) -> Result<()> {
         self.server = trim_owned(self.server.clone());
         self.source = trim_owned(self.source.clone());
 
         if self.server.is_empty() {
             anyhow::bail!("observer config missing `server`");
         }
+        if !config_file::is_host_port(&self.server) {
+            anyhow::bail!("observer config `server` is not a `host:port` address: {}", self.server);
+        }
         if self.source.is_empty() {
             self.source = default_source();
         }
+        self.tenant = self.tenant.take().map(trim_owned).filter(|tenant| !tenant.is_empty());
+        if let Some(tenant) = self.tenant.as_deref().filter(|t| !bouncer_proto::valid_tenant(t)) {
+            anyhow::bail!(
+                "observer config `tenant` may only hold letters, digits, `_` and `-`: {tenant}"
+            );
+        }
 
+        self.files = self.files.drain(..).map(trim_owned).filter(|file| !file.is_empty()).collect();
+        if self.input == ObserverInput::File && self.files.is_empty() {
+            anyhow::bail!("observer config `input: file` needs at least one path in `files`");
+        }
+        self.file_poll_ms = self.file_poll_ms.max(50);
         self.queue_capacity = self.queue_capacity.max(1);
+        self.mapping_capacity = self.mapping_capacity.max(1);
         self.connect_timeout_secs = self.connect_timeout_secs.max(1);
         self.io_timeout_secs = self.io_timeout_secs.max(1);
+        self.heartbeat_jitter_pct = self.heartbeat_jitter_pct.min(MAX_JITTER_PCT);
+        self.reconnect_base_ms = self.reconnect_base_ms.max(10);
+        self.reconnect_max_secs = self.reconnect_max_secs.max(1);
+        HashFormat::new(&self.hash).context("observer config `hash.pattern` is invalid")?;
+        self.log_filter =
+            self.log_filter.take().map(trim_owned).filter(|filter| !filter.is_empty());
+        if let Some(filter) = &self.log_filter {
+            logging::check_log_filter(filter).context("observer config `log_filter` is invalid")?;
+        }
 
         Ok(())
     }
+
+    /// The config as YAML, for `--check-config`.
+    pub fn dump(&self) -> Result<String> {
+        config_file::dump_yaml(self).context("failed to render observer config")
+    }
+}
+
+impl ReloadableConfig for ObserverConfig {
+    const RELOADABLE_KEYS: &'static [&'static str] = &RELOADABLE_KEYS;
+
+    fn read(path: &Path) -> Result<Self> {
+        Self::from_path(path)
+    }
+
+    fn snapshot(&self) -> Option<&ConfigSnapshot> {
+        self.snapshot.as_ref()
+    }
+
+    fn log_filter(&self) -> Option<&str> {
+        self.log_filter.as_deref()
+    }
+
+    fn apply_reloadable(
+        &mut self,
+        next: &Self
+    ) {
+        self.connect_timeout_secs = next.connect_timeout_secs;
+        self.io_timeout_secs = next.io_timeout_secs;
+        self.heartbeat_secs = next.heartbeat_secs;
+        self.heartbeat_jitter_pct = next.heartbeat_jitter_pct;
+        self.reconnect_base_ms = next.reconnect_base_ms;
+        self.reconnect_max_secs = next.reconnect_max_secs;
+        self.mapping_ttl_secs = next.mapping_ttl_secs;
+        self.publish_delivered = next.publish_delivered;
+        self.log_filter = next.log_filter.clone();
+        self.snapshot = next.snapshot.clone();
+    }
 }
 
 fn resolve_observer_config_path() -> Option<PathBuf> {
@@ -92,11 +277,6 @@ fn home_dir() -> Option<PathBuf> {
     non_empty_env("HOME").map(PathBuf::from)
 }
 
-fn load_observer_config_yaml(path: &Path) -> Result<ObserverConfig> {
-    let raw = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
-    serde_yaml::from_slice(&raw).with_context(|| format!("failed to parse yaml {}", path.display()))
-}
-
 fn non_empty_env(key: &str) -> Option<String> {
     env::var(key).ok().and_then(|value| {
         let trimmed = value.trim();
@@ -112,12 +292,19 @@ fn default_listen_udp() -> SocketAddr {
     SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5140)
 }
 
+fn default_file_poll_ms() -> u64 {
+    1000
+}
+
 fn default_server() -> String {
     "127.0.0.1:2147".to_string()
 }
 
+/// `$HOSTNAME-observer`, so an observer and a journal agent on one host do
+/// not take each other's registration.
 fn default_source() -> String {
-    non_empty_env("HOSTNAME").unwrap_or_else(|| "observer".to_string())
+    non_empty_env("HOSTNAME")
+        .map_or_else(|| "observer".to_string(), |host| format!("{host}-observer"))
 }
 
 fn default_queue_capacity() -> usize {
@@ -136,6 +323,30 @@ fn default_heartbeat_secs() -> u64 {
     30
 }
 
+fn default_heartbeat_jitter_pct() -> u8 {
+    20
+}
+
+fn default_reconnect_base_ms() -> u64 {
+    250
+}
+
+fn default_reconnect_max_secs() -> u64 {
+    30
+}
+
 fn default_mapping_ttl_secs() -> u64 {
     86_400
 }
+
+fn default_mapping_capacity() -> usize {
+    100_000
+}
+
+fn default_publish_delivered() -> bool {
+    true
+}
+
+fn default_hash_format() -> HashFormatConfig {
+    HashFormatConfig { lengths: vec![32], pattern: None }
+}
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use bouncer_helpers::clock::SharedClock;
+use bouncer_helpers::coded_warn;
+use bouncer_helpers::error_code::ErrorCode;
+use bouncer_helpers::maillog::ParsedSyslog;
+use bouncer_helpers::message_hash::HashFormat;
+use bouncer_helpers::queue_map::{QueueMap, QueueMapGauge};
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, trace};
+
+use super::parser::{line_timestamp, parse_line};
+use super::simulator::is_simulated_cleanup;
+use super::types::DeliveryEvent;
+use crate::config::{LogFormat, ObserverConfig};
+
+/// How often inputs prune stale queue mappings.
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Mappings kept for `--simulate` messages, apart from real ones.
+const SIMULATED_MAPPINGS: usize = 1024;
+
+/// Turns MTA log lines into delivery events for the publisher queue,
+/// whichever input (UDP syslog, tailed files) and `log_format` they come
+/// from.
+///
+/// It keeps an in-memory `queue_id -> message hash` map using `cleanup`
+/// (or arrival) lines and enriches delivery agent lines with that mapping. The map holds
+/// at most `mapping_capacity` entries; its occupancy is published to the
+/// gauge. `mapping_ttl_secs` is read from `config_rx` on every prune and
+/// `publish_delivered` on every delivery line, so a reload applies to the
+/// next one.
+///
+/// Messages of `--simulate` are mapped in a map of their own, so they
+/// never evict real mappings, and their events are marked simulated.
+pub struct LineCorrelator {
+    config_rx: watch::Receiver<ObserverConfig>,
+    events_tx: mpsc::Sender<DeliveryEvent>,
+    hash_format: HashFormat,
+    log_format: LogFormat,
+    queue_map: QueueMap<String>,
+    simulated: QueueMap<String>,
+    gauge: Arc<QueueMapGauge>,
+    clock: SharedClock,
+    mapping_capacity: usize,
+    reported_evictions: u64
+}
+
+impl LineCorrelator {
+    pub fn new(
+        config_rx: watch::Receiver<ObserverConfig>,
+        events_tx: mpsc::Sender<DeliveryEvent>,
+        hash_format: HashFormat,
+        gauge: Arc<QueueMapGauge>,
+        clock: SharedClock
+    ) -> Self {
+        let (mapping_capacity, log_format) = {
+            let config = config_rx.borrow();
+            (config.mapping_capacity, config.log_format)
+        };
+        Self {
+            config_rx,
+            events_tx,
+            hash_format,
+            log_format,
+            queue_map: QueueMap::new(mapping_capacity, gauge.clone()),
+            simulated: QueueMap::new(SIMULATED_MAPPINGS, Arc::default()),
+            gauge,
+            clock,
+            mapping_capacity,
+            reported_evictions: 0
+        }
+    }
+
+    /// Drops mappings unused for `mapping_ttl_secs` and reports evictions.
+    pub fn prune(&mut self) {
+        let ttl = Duration::from_secs(self.config_rx.borrow().mapping_ttl_secs.max(60));
+        let removed = self.queue_map.prune(ttl, self.clock.now());
+        self.simulated.prune(ttl, self.clock.now());
+        let evicted = self.gauge.evicted();
+        if evicted > self.reported_evictions {
+            coded_warn!(
+                ErrorCode::QueueMapFull,
+                "queue map at capacity, evicted oldest mappings: evicted={}, capacity={}",
+                evicted - self.reported_evictions,
+                self.mapping_capacity
+            );
+            self.reported_evictions = evicted;
+        }
+        if removed > 0 {
+            debug!(
+                "cleaned stale queue mappings: removed={}, tracked={}",
+                removed,
+                self.queue_map.len()
+            );
+        }
+    }
+
+    /// Handles one log line; lines not in `log_format` are ignored.
+    pub fn handle_line(
+        &mut self,
+        line: &str
+    ) {
+        for parsed in parse_line(self.log_format, line, &self.hash_format) {
+            self.handle_parsed(parsed, line);
+        }
+    }
+
+    fn handle_parsed(
+        &mut self,
+        parsed: ParsedSyslog,
+        line: &str
+    ) {
+        match parsed {
+            ParsedSyslog::Cleanup { queue_id, hash } if is_simulated_cleanup(line) => {
+                trace!("simulated queue mapping stored: queue_id={}, hash={}", queue_id, hash);
+                self.simulated.insert(queue_id, hash, self.clock.now());
+            }
+            ParsedSyslog::Cleanup { queue_id, hash } => {
+                // First stage: remember which app hash belongs to this postfix queue id.
+                debug!("queue mapping stored: queue_id={}, hash={}", queue_id, hash);
+                self.queue_map.insert(queue_id, hash, self.clock.now());
+            }
+            ParsedSyslog::Notification { queue_id, notice_queue_id } => {
+                // The notification is a new message; keep its deliveries
+                // from being reported as outcomes of a tracked hash.
+                if let Some(hash) = self.queue_map.remove(&notice_queue_id) {
+                    debug!(
+                        "queue mapping dropped for sender notification: queue_id={}, notice_queue_id={}, hash={}",
+                        queue_id, notice_queue_id, hash
+                    );
+                }
+            }
+            ParsedSyslog::Delivery(delivery) => {
+                // Second stage: delivery agents log status fields; join with cached hash via queue id.
+                let now = self.clock.now();
+                let (hash, simulated) = match self.queue_map.touch(&delivery.queue_id, now) {
+                    Some(hash) => (hash, false),
+                    None => match self.simulated.touch(&delivery.queue_id, now) {
+                        Some(hash) => (hash, true),
+                        None => {
+                            trace!(
+                                "delivery log without known queue mapping: service={}, queue_id={}",
+                                delivery.service, delivery.queue_id
+                            );
+                            return;
+                        }
+                    }
+                };
+                if delivery.action == "delivered" && !self.config_rx.borrow().publish_delivered {
+                    trace!(
+                        "delivered event dropped: service={}, queue_id={}, hash={}",
+                        delivery.service, delivery.queue_id, hash
+                    );
+                    return;
+                }
+
+                let event = DeliveryEvent {
+                    hash,
+                    queue_id: delivery.queue_id,
+                    service: delivery.service,
+                    recipient: delivery.recipient,
+                    status_code: delivery.status_code,
+                    action: delivery.action,
+                    diagnostic: delivery.diagnostic,
+                    smtp_status: delivery.smtp_status,
+                    occurred_at_unix: line_timestamp(self.log_format, line),
+                    simulated
+                };
+                debug!(
+                    "delivery log matched queue mapping: service={}, queue_id={}, hash={}, smtp_status={}, status_code={}, action={}, recipient={}",
+                    event.service,
+                    event.queue_id,
+                    event.hash,
+                    event.smtp_status,
+                    event.status_code,
+                    event.action,
+                    event.recipient
+                );
+
+                if let Err(err) = self.events_tx.try_send(event) {
+                    coded_warn!(
+                        ErrorCode::EventQueueFull,
+                        "observer event queue is full, dropping event: error={err}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! `input: file`: follows MTA log files like `tail -F`.
+//!
+//! Files found at startup are read from their end, files that appear later
+//! from their start. A file renamed away by logrotate is drained before it
+//! is let go (or followed under its new name while it still matches a
+//! pattern), and the new file at the old path is picked up on the next poll.
+//! Files are told apart by device and inode, so on platforms without inodes
+//! only a truncated file (copytruncate) is noticed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::Metadata;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bouncer_helpers::clock::SharedClock;
+use bouncer_helpers::glob::{glob_match, is_glob};
+use bouncer_helpers::message_hash::HashFormat;
+use bouncer_helpers::queue_map::QueueMapGauge;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::{mpsc, watch};
+use tokio::time::interval;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use super::correlator::{LineCorrelator, PRUNE_INTERVAL};
+use super::types::DeliveryEvent;
+use crate::config::ObserverConfig;
+
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+/// Longest line kept; the rest of a longer line is dropped.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// Device and inode of a file; `None` where the platform has no inodes.
+type FileId = Option<(u64, u64)>;
+
+/// Tails the `files` patterns every `file_poll_ms` and converts MTA log
+/// lines into delivery events for the publisher queue; see
+/// [`LineCorrelator`].
+pub async fn run_file_tailer(
+    config_rx: watch::Receiver<ObserverConfig>,
+    events_tx: mpsc::Sender<DeliveryEvent>,
+    gauge: Arc<QueueMapGauge>,
+    clock: SharedClock,
+    shutdown: CancellationToken
+) -> Result<()> {
+    let config = config_rx.borrow().clone();
+    let hash_format = HashFormat::new(&config.hash).context("invalid `hash.pattern`")?;
+    let mut correlator = LineCorrelator::new(config_rx, events_tx, hash_format, gauge, clock);
+    let mut tailer = FileTailer::new(config.files.clone());
+    let mut poll_tick = interval(Duration::from_millis(config.file_poll_ms));
+    let mut cleanup_tick = interval(PRUNE_INTERVAL);
+    let mut first = true;
+
+    info!("file tailer ready: files={}, poll_ms={}", config.files.join(","), config.file_poll_ms);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                info!("file tailer stopping");
+                break;
+            }
+            _ = cleanup_tick.tick() => {
+                correlator.prune();
+            }
+            _ = poll_tick.tick() => {
+                tailer.poll(&mut correlator, first).await;
+                first = false;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The tailed files of a set of path patterns.
+struct FileTailer {
+    patterns: Vec<String>,
+    files: BTreeMap<PathBuf, TailedFile>
+}
+
+impl FileTailer {
+    fn new(patterns: Vec<String>) -> Self {
+        Self { patterns, files: BTreeMap::new() }
+    }
+
+    /// Follows renames and truncation, opens new files (from their end on
+    /// the `first` poll) and hands every complete new line to `correlator`.
+    async fn poll(
+        &mut self,
+        correlator: &mut LineCorrelator,
+        first: bool
+    ) {
+        let mut current = BTreeMap::new();
+        for path in matching_paths(&self.patterns).await {
+            if let Ok(metadata) = tokio::fs::metadata(&path).await {
+                current.insert(path, metadata);
+            }
+        }
+
+        let mut next: BTreeMap<PathBuf, TailedFile> = BTreeMap::new();
+        for (path, mut tailed) in std::mem::take(&mut self.files) {
+            if let Some(metadata) = current.get(&path).filter(|metadata| tailed.is(metadata)) {
+                if metadata.len() < tailed.offset {
+                    info!("tailed file truncated, reading from start: path={}", path.display());
+                    if let Err(err) = tailed.rewind().await {
+                        warn!(
+                            "failed to rewind tailed file: path={}, error={}",
+                            path.display(),
+                            err
+                        );
+                    }
+                }
+                next.insert(path, tailed);
+                continue;
+            }
+
+            let moved = current
+                .iter()
+                .find(|(other, metadata)| !next.contains_key(*other) && tailed.same_file(metadata))
+                .map(|(other, _)| other.clone());
+            if let Some(moved) = moved {
+                debug!("tailed file renamed: from={}, to={}", path.display(), moved.display());
+                next.insert(moved, tailed);
+                continue;
+            }
+
+            // Rotated away: read what was written before the switch.
+            if let Err(err) = tailed.read_lines(correlator).await {
+                warn!("failed to drain rotated file: path={}, error={}", path.display(), err);
+            }
+            tailed.finish(correlator);
+            info!("tailed file rotated: path={}, read_bytes={}", path.display(), tailed.offset);
+        }
+
+        for (path, metadata) in &current {
+            if next.contains_key(path) || next.values().any(|tailed| tailed.same_file(metadata)) {
+                continue;
+            }
+            match TailedFile::open(path, first).await {
+                Ok(tailed) => {
+                    info!(
+                        "tailing file: path={}, from={}",
+                        path.display(),
+                        if first { "end" } else { "start" }
+                    );
+                    next.insert(path.clone(), tailed);
+                }
+                Err(err) => {
+                    warn!("failed to open tailed file: path={}, error={}", path.display(), err)
+                }
+            }
+        }
+
+        for (path, tailed) in &mut next {
+            if let Err(err) = tailed.read_lines(correlator).await {
+                warn!("failed to read tailed file: path={}, error={}", path.display(), err);
+            }
+        }
+        self.files = next;
+    }
+}
+
+/// One open log file and how far it was read.
+struct TailedFile {
+    file: File,
+    id: FileId,
+    offset: u64,
+    /// Bytes after the last newline, completed by a later read.
+    partial: Vec<u8>
+}
+
+impl TailedFile {
+    async fn open(
+        path: &Path,
+        from_end: bool
+    ) -> io::Result<Self> {
+        let mut file = File::open(path).await?;
+        let id = file_id(&file.metadata().await?);
+        let offset = if from_end { file.seek(SeekFrom::End(0)).await? } else { 0 };
+        Ok(Self { file, id, offset, partial: Vec::new() })
+    }
+
+    /// Whether `metadata` at this file's path still describes it; always
+    /// true without inodes.
+    fn is(
+        &self,
+        metadata: &Metadata
+    ) -> bool {
+        self.id.is_none() || file_id(metadata) == self.id
+    }
+
+    /// Whether `metadata` at another path describes this file; never true
+    /// without inodes.
+    fn same_file(
+        &self,
+        metadata: &Metadata
+    ) -> bool {
+        self.id.is_some() && file_id(metadata) == self.id
+    }
+
+    async fn rewind(&mut self) -> io::Result<()> {
+        self.offset = self.file.seek(SeekFrom::Start(0)).await?;
+        self.partial.clear();
+        Ok(())
+    }
+
+    /// Reads everything appended since the last call and hands each complete
+    /// line to `correlator`.
+    async fn read_lines(
+        &mut self,
+        correlator: &mut LineCorrelator
+    ) -> io::Result<()> {
+        let mut buf = vec![0_u8; READ_CHUNK_BYTES];
+        loop {
+            let read = self.file.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.offset += read as u64;
+
+            let mut chunk = &buf[..read];
+            while let Some(end) = chunk.iter().position(|&byte| byte == b'\n') {
+                self.push_partial(&chunk[..end]);
+                self.finish(correlator);
+                chunk = &chunk[end + 1..];
+            }
+            self.push_partial(chunk);
+        }
+    }
+
+    fn push_partial(
+        &mut self,
+        bytes: &[u8]
+    ) {
+        let room = MAX_LINE_BYTES.saturating_sub(self.partial.len());
+        self.partial.extend_from_slice(&bytes[..bytes.len().min(room)]);
+    }
+
+    /// Hands the buffered line to `correlator`, complete or not.
+    fn finish(
+        &mut self,
+        correlator: &mut LineCorrelator
+    ) {
+        if let Ok(line) = std::str::from_utf8(&self.partial) {
+            let line = line.trim();
+            if !line.is_empty() {
+                correlator.handle_line(line);
+            }
+        }
+        self.partial.clear();
+    }
+}
+
+/// Existing files matching `patterns`; `*` and `?` are allowed in the file
+/// name, not in directories.
+async fn matching_paths(patterns: &[String]) -> BTreeSet<PathBuf> {
+    let mut paths = BTreeSet::new();
+    for pattern in patterns {
+        let path = Path::new(pattern);
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !is_glob(name) {
+            if tokio::fs::metadata(path).await.is_ok_and(|metadata| metadata.is_file()) {
+                paths.insert(path.to_path_buf());
+            }
+            continue;
+        }
+
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let matches = entry.file_name().to_str().is_some_and(|file| glob_match(name, file));
+            if matches && entry.file_type().await.is_ok_and(|kind| kind.is_file()) {
+                paths.insert(entry.path());
+            }
+        }
+    }
+    paths
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> FileId {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use bouncer_helpers::clock::system_clock;
+    use bouncer_helpers::config_file;
+    use bouncer_helpers::message_hash::HashFormat;
+    use bouncer_helpers::queue_map::QueueMapGauge;
+    use tokio::io::AsyncWriteExt;
+    use tokio::sync::{mpsc, watch};
+
+    use super::FileTailer;
+    use crate::config::ObserverConfig;
+    use crate::core::correlator::LineCorrelator;
+
+    const HASH_A: &str = "5d41402abc4b2a76b9719d911017c592";
+    const HASH_B: &str = "7d793037a0760186574b0282f2f435e7";
+    const HASH_C: &str = "b10a8db164e0754105b7a99be72e3fe5";
+
+    fn lines(
+        queue_id: &str,
+        hash: &str
+    ) -> String {
+        format!(
+            "Oct 16 10:00:00 mx postfix/cleanup[100]: {queue_id}: message-id=<{hash}@claviron.app>\n\
+             Oct 16 10:00:01 mx postfix/smtp[101]: {queue_id}: to=<user@example.com>, relay=mx.example.com[192.0.2.1]:25, dsn=5.1.1, status=bounced (user unknown)\n"
+        )
+    }
+
+    async fn append(
+        path: &Path,
+        text: &str
+    ) {
+        let mut file =
+            tokio::fs::OpenOptions::new().create(true).append(true).open(path).await.unwrap();
+        file.write_all(text.as_bytes()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn follows_appends_and_rotation() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let dir: PathBuf = std::env::temp_dir().join(format!("bouncer-tail-{nanos}"));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let log = dir.join("mail.log");
+        append(&log, &lines("1AAA", HASH_A)).await;
+
+        let raw = format!("input: file\nfiles: [\"{}\"]\n", dir.join("mail*.log").display());
+        let config: ObserverConfig =
+            config_file::parse_yaml(raw.as_bytes(), Path::new("observer.yaml")).unwrap();
+        let hash_format = HashFormat::new(&config.hash).unwrap();
+        let (_config_tx, config_rx) = watch::channel(config.clone());
+        let (events_tx, mut events_rx) = mpsc::channel(16);
+        let gauge = Arc::new(QueueMapGauge::default());
+        let mut correlator =
+            LineCorrelator::new(config_rx, events_tx, hash_format, gauge, system_clock());
+        let mut tailer = FileTailer::new(config.files);
+
+        // Lines already in the file at startup are skipped.
+        tailer.poll(&mut correlator, true).await;
+        assert!(events_rx.try_recv().is_err());
+
+        // A delivery split across two polls is read once complete.
+        let b = lines("2BBB", HASH_B);
+        let (head, tail) = b.split_at(b.len() - 20);
+        append(&log, head).await;
+        tailer.poll(&mut correlator, false).await;
+        assert!(events_rx.try_recv().is_err());
+        append(&log, tail).await;
+        tailer.poll(&mut correlator, false).await;
+        assert_eq!(events_rx.try_recv().unwrap().hash, HASH_B);
+
+        // Rotated away: the last lines of the old file and the new file are both read.
+        let rotated = dir.join("mail.log.1");
+        tokio::fs::rename(&log, &rotated).await.unwrap();
+        append(&rotated, &lines("3AAA", HASH_A)).await;
+        append(&log, &lines("4CCC", HASH_C)).await;
+        tailer.poll(&mut correlator, false).await;
+        assert_eq!(events_rx.try_recv().unwrap().hash, HASH_A);
+        assert_eq!(events_rx.try_recv().unwrap().hash, HASH_C);
+        assert!(events_rx.try_recv().is_err());
+
+        tokio::fs::remove_dir_all(&dir).await.ok();
+    }
+}
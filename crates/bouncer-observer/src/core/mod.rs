@@ -1,7 +1,13 @@
+mod correlator;
+mod file_tailer;
 mod parser;
 mod publisher;
+mod simulator;
 mod types;
 mod udp_listener;
 
+pub use file_tailer::run_file_tailer;
+pub use parser::{line_timestamp, parse_line};
 pub use publisher::run_publisher;
+pub use simulator::{MAX_SIMULATE_RATE, run_simulator, simulator_target};
 pub use udp_listener::run_udp_listener;
@@ -0,0 +1,225 @@
+use bouncer_helpers::maillog::{
+    DeliveryLine, ParsedSyslog, build_diagnostic, default_status_code, is_relay_handoff_host,
+    map_action
+};
+use bouncer_helpers::message_hash::HashFormat;
+
+use super::parse_rfc3339;
+
+/// Parses one Exim mainlog line, with or without a syslog header, into one of:
+/// - `ParsedSyslog::Cleanup { queue_id, hash }` from an arrival (`<=`) line
+/// - `ParsedSyslog::Delivery(DeliveryLine)` from a delivery (`=>`, `->`),
+///   failure (`**`) or deferral (`==`) line
+/// - `ParsedSyslog::Notification { queue_id, notice_queue_id }` from the
+///   arrival of a bounce Exim generated itself (`<= <> R=<queue_id>`)
+///
+/// The Exim message id (`1rhABC-0001Xy-2Z`) is the queue id; the hash comes
+/// from the `id=` field of the arrival line.
+///
+/// Example flow:
+/// - arrival: `2024-03-04 10:00:00 1rhABC-0001Xy-2Z <= s@example.com H=app [10.0.0.5] P=esmtp S=1234 id=9f...32chars...@example`
+/// - failure: `2024-03-04 10:00:05 1rhABC-0001Xy-2Z ** u@d.example R=dnslookup T=remote_smtp H=mx.d.example [192.0.2.1]: SMTP error from remote mail server after RCPT TO:<u@d.example>: 550 5.1.1 User unknown`
+/// - bounce: `2024-03-04 10:00:05 1rhDEF-0001Xy-3A <= <> R=1rhABC-0001Xy-2Z U=Debian-exim P=local S=2345`
+pub fn parse_exim_line(
+    line: &str,
+    hash_format: &HashFormat
+) -> Option<ParsedSyslog> {
+    let (queue_id, flag, detail) = split_message(line)?;
+
+    match flag {
+        "<=" => parse_arrival(queue_id, detail, hash_format),
+        "=>" | "->" => parse_delivery(queue_id, flag, detail, "sent").map(ParsedSyslog::Delivery),
+        "**" => parse_delivery(queue_id, flag, detail, "bounced").map(ParsedSyslog::Delivery),
+        "==" => parse_delivery(queue_id, flag, detail, "deferred").map(ParsedSyslog::Delivery),
+        _ => None
+    }
+}
+
+/// Unix time of a mainlog line written with `log_timezone = true`
+/// (`2024-03-04 10:00:05 +0100 ...`). Without the zone the local time cannot
+/// be placed and `None` is returned.
+pub(super) fn mainlog_timestamp(line: &str) -> Option<u64> {
+    let mut fields = line.split_ascii_whitespace();
+    let (date, time, zone) = (fields.next()?, fields.next()?, fields.next()?);
+    let time = time.split_once('.').map(|(seconds, _)| seconds).unwrap_or(time);
+    let zone = match zone.as_bytes() {
+        [sign @ (b'+' | b'-'), digits @ ..]
+            if digits.len() == 4 && digits.iter().all(u8::is_ascii_digit) =>
+        {
+            format!("{}{}:{}", *sign as char, &zone[1..3], &zone[3..5])
+        }
+        _ => return None
+    };
+    parse_rfc3339(&format!("{date}T{time}{zone}"))
+}
+
+/// Finds the message id and the flag after it: `(queue_id, flag, detail)`.
+fn split_message(line: &str) -> Option<(&str, &str, &str)> {
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (token, after) = rest.split_at(end);
+        let after = after.trim_start();
+        if is_message_id(token) {
+            let (flag, detail) = after.split_once(' ').unwrap_or((after, ""));
+            return Some((token, flag, detail.trim()));
+        }
+        rest = after;
+    }
+    None
+}
+
+fn parse_arrival(
+    queue_id: &str,
+    detail: &str,
+    hash_format: &HashFormat
+) -> Option<ParsedSyslog> {
+    if detail.starts_with("<> ")
+        && let Some(original) = field(detail, "R").filter(|id| is_message_id(id))
+    {
+        return Some(ParsedSyslog::Notification {
+            queue_id: original.to_string(),
+            notice_queue_id: queue_id.to_string()
+        });
+    }
+
+    let message_id = field(detail, "id")?.trim_start_matches('<').trim_end_matches('>');
+    let hash = hash_format.extract(message_id)?;
+    Some(ParsedSyslog::Cleanup { queue_id: queue_id.to_string(), hash })
+}
+
+fn parse_delivery(
+    queue_id: &str,
+    flag: &str,
+    detail: &str,
+    smtp_status: &str
+) -> Option<DeliveryLine> {
+    let recipient = detail
+        .split_ascii_whitespace()
+        .next()?
+        .trim_end_matches(':')
+        .trim_start_matches('<')
+        .trim_end_matches('>');
+    if !recipient.contains('@') {
+        return None;
+    }
+
+    let relay_handoff = field(detail, "H").map(is_relay_handoff_host).unwrap_or(false);
+    let status_code = enhanced_status_code(detail)
+        .unwrap_or_else(|| default_status_code(smtp_status, relay_handoff))
+        .to_string();
+
+    Some(DeliveryLine {
+        service: field(detail, "T").unwrap_or("exim").to_ascii_lowercase(),
+        queue_id: queue_id.to_string(),
+        recipient: recipient.to_string(),
+        smtp_status: smtp_status.to_string(),
+        status_code,
+        action: map_action(smtp_status, relay_handoff).to_string(),
+        diagnostic: build_diagnostic(queue_id, &format!("{flag} {detail}"))
+    })
+}
+
+/// Value of a space separated `name=value` field.
+fn field<'a>(
+    detail: &'a str,
+    name: &str
+) -> Option<&'a str> {
+    detail.split_ascii_whitespace().find_map(|token| {
+        let value = token.strip_prefix(name)?.strip_prefix('=')?;
+        if value.is_empty() { None } else { Some(value) }
+    })
+}
+
+/// First RFC 3463 code (`5.1.1`) in the remote server's reply.
+fn enhanced_status_code(detail: &str) -> Option<&str> {
+    detail.split_ascii_whitespace().find_map(|token| {
+        let code = token.trim_matches(|c: char| !c.is_ascii_digit());
+        let mut parts = code.split('.');
+        let class = parts.next()?;
+        let (subject, detail) = (parts.next()?, parts.next()?);
+        let valid = matches!(class, "2" | "4" | "5")
+            && parts.next().is_none()
+            && [subject, detail].iter().all(|part| {
+                (1..=3).contains(&part.len()) && part.bytes().all(|b| b.is_ascii_digit())
+            });
+        valid.then_some(code)
+    })
+}
+
+/// `1rhABC-0001Xy-2Z`, or the longer Exim 4.97 form `1rhABC-00000001Xy-00Z2`.
+fn is_message_id(token: &str) -> bool {
+    let mut parts = token.split('-');
+    let lengths = (
+        parts.next().map(str::len),
+        parts.next().map(str::len),
+        parts.next().map(str::len),
+        parts.next()
+    );
+    matches!(lengths, (Some(6), Some(6), Some(2), None) | (Some(6), Some(11), Some(4), None))
+        && token.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+#[cfg(test)]
+mod tests {
+    use bouncer_helpers::maillog::ParsedSyslog;
+    use bouncer_helpers::message_hash::HashFormat;
+
+    use super::{mainlog_timestamp, parse_exim_line};
+
+    const HASH: &str = "9f0123456789abcdef0123456789abcd";
+
+    #[test]
+    fn parses_arrival_outcomes_and_generated_bounces() {
+        let hash_format = HashFormat::default();
+        let arrival = format!(
+            "2024-03-04 10:00:00 1rhABC-0001Xy-2Z <= s@example.com H=app [10.0.0.5] P=esmtp S=1234 id={HASH}@example.com"
+        );
+        assert!(matches!(
+            parse_exim_line(&arrival, &hash_format),
+            Some(ParsedSyslog::Cleanup { queue_id, hash })
+                if queue_id == "1rhABC-0001Xy-2Z" && hash == HASH
+        ));
+
+        let failure = "2024-03-04 10:00:05 1rhABC-0001Xy-2Z ** u@d.example R=dnslookup T=remote_smtp H=mx.d.example [192.0.2.1]: SMTP error from remote mail server after RCPT TO:<u@d.example>: 550 5.1.1 User unknown";
+        let Some(ParsedSyslog::Delivery(delivery)) = parse_exim_line(failure, &hash_format) else {
+            panic!("failure line not parsed");
+        };
+        assert_eq!(
+            (delivery.service.as_str(), delivery.recipient.as_str(), delivery.status_code.as_str()),
+            ("remote_smtp", "u@d.example", "5.1.1")
+        );
+        assert_eq!(
+            (delivery.smtp_status.as_str(), delivery.action.as_str()),
+            ("bounced", "failed")
+        );
+
+        let deferral = "exim[812]: 1rhABC-0001Xy-2Z == v@d.example R=dnslookup T=remote_smtp defer (-44): SMTP error from remote mail server after RCPT TO:<v@d.example>: 451 Try again later";
+        let Some(ParsedSyslog::Delivery(delivery)) = parse_exim_line(deferral, &hash_format) else {
+            panic!("deferral line not parsed");
+        };
+        assert_eq!((delivery.status_code.as_str(), delivery.action.as_str()), ("4.0.0", "delayed"));
+
+        let delivered = "2024-03-04 10:00:05 1rhABC-0001Xy-2Z => w@d.example R=dnslookup T=remote_smtp H=mx.d.example [192.0.2.1] C=\"250 2.0.0 OK\"";
+        assert!(matches!(
+            parse_exim_line(delivered, &hash_format),
+            Some(ParsedSyslog::Delivery(delivery)) if delivery.action == "delivered"
+        ));
+
+        let bounce = "2024-03-04 10:00:05 1rhDEF-0001Xy-3A <= <> R=1rhABC-0001Xy-2Z U=Debian-exim P=local S=2345";
+        assert!(matches!(
+            parse_exim_line(bounce, &hash_format),
+            Some(ParsedSyslog::Notification { queue_id, notice_queue_id })
+                if queue_id == "1rhABC-0001Xy-2Z" && notice_queue_id == "1rhDEF-0001Xy-3A"
+        ));
+        let completed = "2024-03-04 10:00:06 1rhABC-0001Xy-2Z Completed";
+        assert!(parse_exim_line(completed, &hash_format).is_none());
+    }
+
+    #[test]
+    fn reads_zoned_mainlog_timestamps() {
+        let line = "2024-03-04 10:00:05.123 +0200 1rhABC-0001Xy-2Z Completed";
+        assert_eq!(mainlog_timestamp(line), Some(1_709_539_205));
+        assert_eq!(mainlog_timestamp("2024-03-04 10:00:05 1rhABC-0001Xy-2Z Completed"), None);
+    }
+}
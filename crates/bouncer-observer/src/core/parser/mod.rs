@@ -0,0 +1,219 @@
+//! Per-MTA log formats. Each one turns a log line into the same
+//! [`ParsedSyslog`] records, so the correlator joins queue ids with message
+//! hashes the same way whichever MTA wrote the line:
+//! - [`LogFormat::Postfix`]: `postfix/*` syslog lines, see
+//!   [`parse_postfix_message`], shared with `bouncer-journal`.
+//! - [`LogFormat::Exim`]: Exim mainlog lines, from the file or syslog, see
+//!   [`parse_exim_line`].
+//! - [`LogFormat::Sendmail`]: `sendmail`/`sm-mta` syslog lines, see
+//!   [`parse_sendmail_message`].
+//!
+//! Postfix and sendmail parse the syslog program and message separately
+//! ([`parse_message`]); [`parse_line`] splits them off the syslog header.
+
+mod exim;
+mod sendmail;
+
+use bouncer_helpers::maillog::{ParsedSyslog, parse_postfix_message, postfix_service};
+use bouncer_helpers::message_hash::HashFormat;
+pub use exim::parse_exim_line;
+pub use sendmail::parse_sendmail_message;
+
+use crate::config::LogFormat;
+
+/// Parses one log line in `format`. Most lines yield at most one record; a
+/// sendmail delivery line yields one per recipient.
+pub fn parse_line(
+    format: LogFormat,
+    line: &str,
+    hash_format: &HashFormat
+) -> Vec<ParsedSyslog> {
+    match format {
+        LogFormat::Exim => parse_exim_line(line, hash_format).into_iter().collect(),
+        LogFormat::Postfix | LogFormat::Sendmail => split_syslog_line(line)
+            .map(|(program, message)| parse_message(format, program, message, hash_format))
+            .unwrap_or_default()
+    }
+}
+
+/// Like [`parse_line`] for a line already split into its syslog program
+/// (`postfix/smtp`, `sm-mta`, ...) and message, as journald stores them.
+pub fn parse_message(
+    format: LogFormat,
+    program: &str,
+    message: &str,
+    hash_format: &HashFormat
+) -> Vec<ParsedSyslog> {
+    match format {
+        LogFormat::Postfix => postfix_service(program)
+            .and_then(|service| parse_postfix_message(service, message, hash_format))
+            .into_iter()
+            .collect(),
+        LogFormat::Exim => parse_exim_line(message, hash_format).into_iter().collect(),
+        LogFormat::Sendmail => parse_sendmail_message(program, message, hash_format)
+    }
+}
+
+/// `(program, message)` of a `<header> <program>[<pid>]: <message>` syslog
+/// line.
+fn split_syslog_line(line: &str) -> Option<(&str, &str)> {
+    let (head, message) = line.split_once("]: ")?;
+    let (head, _pid) = head.rsplit_once('[')?;
+    let program = head.rsplit(' ').next()?;
+    Some((program, message))
+}
+
+/// Unix time `line` was logged at, if its timestamp carries a zone; see
+/// [`syslog_timestamp`] and [`exim::mainlog_timestamp`].
+pub fn line_timestamp(
+    format: LogFormat,
+    line: &str
+) -> Option<u64> {
+    match format {
+        LogFormat::Exim => exim::mainlog_timestamp(line).or_else(|| syslog_timestamp(line)),
+        LogFormat::Postfix | LogFormat::Sendmail => syslog_timestamp(line)
+    }
+}
+
+/// Unix time from the syslog header of `line` when it carries an RFC 3339
+/// timestamp (RFC 5424, or rsyslog's `RSYSLOG_ForwardFormat`).
+///
+/// Traditional BSD stamps (`Mar  4 10:00:00`) have no year or zone and
+/// return `None`; callers fall back to the receive time.
+pub fn syslog_timestamp(line: &str) -> Option<u64> {
+    let rest = line.trim_start();
+    let rest = match rest.strip_prefix('<') {
+        Some(rest) => &rest[rest.find('>')? + 1..],
+        None => rest
+    };
+    let rest = rest.strip_prefix("1 ").unwrap_or(rest);
+    let stamp = rest.split_ascii_whitespace().next()?;
+    parse_rfc3339(stamp)
+}
+
+/// `YYYY-MM-DDTHH:MM:SS[.frac](Z|±HH:MM)` as unix seconds.
+fn parse_rfc3339(stamp: &str) -> Option<u64> {
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = stamp.get(range)?;
+        if digits.bytes().all(|b| b.is_ascii_digit()) { digits.parse().ok() } else { None }
+    };
+    let bytes = stamp.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let mut zone = &stamp[19..];
+    if let Some(fraction) = zone.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        zone = &fraction[digits..];
+    }
+    let offset = match zone.as_bytes() {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2] => {
+            let hours = i64::from((*h1 as char).to_digit(10)? * 10 + (*h2 as char).to_digit(10)?);
+            let minutes = i64::from((*m1 as char).to_digit(10)? * 10 + (*m2 as char).to_digit(10)?);
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' { -offset } else { offset }
+        }
+        _ => return None
+    };
+
+    let secs =
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    u64::try_from(secs).ok()
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(
+    year: i64,
+    month: i64,
+    day: i64
+) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use bouncer_helpers::maillog::ParsedSyslog;
+    use bouncer_helpers::message_hash::HashFormat;
+
+    use super::{parse_line, syslog_timestamp};
+    use crate::config::LogFormat;
+
+    fn parse_postfix_line(line: &str) -> Option<ParsedSyslog> {
+        parse_line(LogFormat::Postfix, line, &HashFormat::default()).pop()
+    }
+
+    fn delivery(line: &str) -> Option<(String, String, String, String)> {
+        match parse_postfix_line(line)? {
+            ParsedSyslog::Delivery(d) => Some((d.service, d.recipient, d.status_code, d.action)),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn attributes_local_error_and_bounce_lines() {
+        let local = "Mar  4 10:00:05 mx1 postfix/local[42]: 4ABC: to=<u@mx1.example>, orig_to=<alias@mx1.example>, relay=local, delay=0.1, dsn=5.1.1, status=bounced (unknown user: \"u\")";
+        assert_eq!(
+            delivery(local),
+            Some(("local".into(), "u@mx1.example".into(), "5.1.1".into(), "failed".into()))
+        );
+        let error = "Mar  4 10:00:05 mx1 postfix/error[43]: 4ABC: to=<v@d.example>, relay=none, delay=30, status=deferred (delivery temporarily suspended)";
+        assert_eq!(
+            delivery(error),
+            Some(("error".into(), "v@d.example".into(), "4.0.0".into(), "delayed".into()))
+        );
+        let smtp = "Mar  4 10:00:05 mx1 postfix/smtp[44]: 4ABC: to=<w@d.example>, relay=mx.d.example[192.0.2.1]:25, dsn=2.0.0, status=sent (250 ok)";
+        assert_eq!(delivery(smtp).map(|d| d.0), Some("smtp".into()));
+
+        let bounce =
+            "Mar  4 10:00:06 mx1 postfix/bounce[45]: 4ABC: sender non-delivery notification: 4DEF";
+        assert!(matches!(
+            parse_postfix_line(bounce),
+            Some(ParsedSyslog::Notification { queue_id, notice_queue_id })
+                if queue_id == "4ABC" && notice_queue_id == "4DEF"
+        ));
+        let qmgr = "Mar  4 10:00:06 mx1 postfix/qmgr[46]: 4ABC: removed";
+        assert!(parse_postfix_line(qmgr).is_none());
+    }
+
+    #[test]
+    fn parses_lines_of_postfix_instances() {
+        let multi = "Mar  4 10:00:05 mx1 postfix-out1/smtp[44]: 4ABC: to=<w@d.example>, relay=mx.d.example[192.0.2.1]:25, dsn=5.1.1, status=bounced (user unknown)";
+        assert_eq!(delivery(multi).map(|d| d.3), Some("failed".into()));
+    }
+
+    #[test]
+    fn reads_rfc3339_syslog_timestamps() {
+        let forward = "<22>2024-03-04T10:00:05.123456+02:00 mx1 postfix/smtp[42]: 4ABC: to=<u@d>";
+        assert_eq!(syslog_timestamp(forward), Some(1_709_539_205));
+        let rfc5424 = "<22>1 2024-03-04T08:00:05Z mx1 postfix/smtp 42 - - 4ABC: to=<u@d>";
+        assert_eq!(syslog_timestamp(rfc5424), Some(1_709_539_205));
+        assert_eq!(syslog_timestamp("2000-02-29T23:59:59-00:30 mx1 x"), Some(951_870_599));
+
+        let traditional = "<22>Mar  4 10:00:05 mx1 postfix/smtp[42]: 4ABC: to=<u@d>";
+        assert_eq!(syslog_timestamp(traditional), None);
+        assert_eq!(syslog_timestamp("<22>2024-13-04T10:00:05Z mx1 x"), None);
+    }
+}
@@ -0,0 +1,176 @@
+use bouncer_helpers::maillog::{
+    DeliveryLine, ParsedSyslog, build_diagnostic, default_status_code, extract_between,
+    extract_relay_host, extract_token, is_queue_id, is_relay_handoff_host, map_action
+};
+use bouncer_helpers::message_hash::HashFormat;
+
+/// Syslog program names sendmail logs under.
+const PROGRAMS: &[&str] = &["sendmail", "sm-mta", "sm-msp-queue"];
+
+/// Parses the message of one sendmail syslog line logged by `program` (one
+/// of `PROGRAMS`, possibly with a path) into:
+/// - `ParsedSyslog::Cleanup { queue_id, hash }` from the `from=` line, whose
+///   `msgid=<...>` carries the hash
+/// - one `ParsedSyslog::Delivery(DeliveryLine)` per recipient of a `to=` line
+///   with a final `stat=` (sendmail lists recipients of one delivery attempt
+///   on a single line)
+/// - `ParsedSyslog::Notification { queue_id, notice_queue_id }` from the
+///   `<queue_id>: <notice_queue_id>: DSN: ...` line of a generated bounce
+///
+/// Example flow:
+/// - from: `424A00Q1012345: from=<s@example.com>, size=1234, class=0, nrcpts=1, msgid=<9f...32chars...@example>, proto=ESMTP, relay=app [10.0.0.5]`
+/// - to: `424A00Q1012345: to=<u@d.example>,<v@d.example>, delay=00:00:05, mailer=esmtp, relay=mx.d.example. [192.0.2.1], dsn=5.1.1, stat=User unknown`
+/// - dsn: `424A00Q1012345: 424A05Q1012346: DSN: User unknown`
+pub fn parse_sendmail_message(
+    program: &str,
+    message: &str,
+    hash_format: &HashFormat
+) -> Vec<ParsedSyslog> {
+    let program = program.rsplit('/').next().unwrap_or(program);
+    if !PROGRAMS.contains(&program) {
+        return Vec::new();
+    }
+    let Some((queue_id, detail)) =
+        message.split_once(": ").filter(|(queue_id, _)| is_queue_id(queue_id))
+    else {
+        return Vec::new();
+    };
+
+    if detail.starts_with("from=") {
+        return extract_between(detail, "msgid=<", ">")
+            .and_then(|message_id| hash_format.extract(message_id))
+            .map(|hash| ParsedSyslog::Cleanup { queue_id: queue_id.to_string(), hash })
+            .into_iter()
+            .collect();
+    }
+
+    if detail.starts_with("to=") {
+        return parse_delivery(queue_id, detail)
+            .map(|lines| lines.into_iter().map(ParsedSyslog::Delivery).collect())
+            .unwrap_or_default();
+    }
+
+    if let Some((notice_queue_id, notice)) = detail.split_once(": ")
+        && notice.starts_with("DSN:")
+        && is_queue_id(notice_queue_id)
+    {
+        return vec![ParsedSyslog::Notification {
+            queue_id: queue_id.to_string(),
+            notice_queue_id: notice_queue_id.to_string()
+        }];
+    }
+
+    Vec::new()
+}
+
+fn parse_delivery(
+    queue_id: &str,
+    detail: &str
+) -> Option<Vec<DeliveryLine>> {
+    let (_, stat) = detail.split_once("stat=")?;
+    let stat = stat.trim();
+    let status_code = extract_token(detail, "dsn=");
+    let smtp_status = match status_code.and_then(|code| code.chars().next()) {
+        Some('2') => "sent",
+        Some('4') => "deferred",
+        Some(_) => "bounced",
+        None if stat.starts_with("Sent") => "sent",
+        None if stat.starts_with("Deferred") => "deferred",
+        None if stat.starts_with("queued") => return None,
+        None => "bounced"
+    };
+
+    let relay_handoff = extract_relay_host(detail)
+        .map(|host| is_relay_handoff_host(host.trim_end_matches('.')))
+        .unwrap_or(false);
+    let status_code =
+        status_code.unwrap_or_else(|| default_status_code(smtp_status, relay_handoff)).to_string();
+    let service = extract_token(detail, "mailer=").unwrap_or("sendmail").to_ascii_lowercase();
+    let action = map_action(smtp_status, relay_handoff);
+    let diagnostic = build_diagnostic(queue_id, detail);
+
+    let recipients = detail["to=".len()..].split(", ").next()?;
+    let lines: Vec<DeliveryLine> = recipients
+        .split(',')
+        .map(|recipient| recipient.trim().trim_start_matches('<').trim_end_matches('>'))
+        .filter(|recipient| !recipient.is_empty())
+        .map(|recipient| DeliveryLine {
+            service: service.clone(),
+            queue_id: queue_id.to_string(),
+            recipient: recipient.to_string(),
+            smtp_status: smtp_status.to_string(),
+            status_code: status_code.clone(),
+            action: action.to_string(),
+            diagnostic: diagnostic.clone()
+        })
+        .collect();
+    Some(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use bouncer_helpers::maillog::ParsedSyslog;
+    use bouncer_helpers::message_hash::HashFormat;
+
+    use crate::config::LogFormat;
+    use crate::core::parse_line;
+
+    fn parse_sendmail_line(
+        line: &str,
+        hash_format: &HashFormat
+    ) -> Vec<ParsedSyslog> {
+        parse_line(LogFormat::Sendmail, line, hash_format)
+    }
+
+    const HASH: &str = "9f0123456789abcdef0123456789abcd";
+
+    #[test]
+    fn parses_from_to_and_dsn_lines() {
+        let hash_format = HashFormat::default();
+        let from = format!(
+            "Mar  4 10:00:00 mx1 sendmail[1234]: 424A00Q1012345: from=<s@example.com>, size=1234, class=0, nrcpts=2, msgid=<{HASH}@example.com>, proto=ESMTP, daemon=MTA, relay=app [10.0.0.5]"
+        );
+        assert!(matches!(
+            parse_sendmail_line(&from, &hash_format).as_slice(),
+            [ParsedSyslog::Cleanup { queue_id, hash }] if queue_id == "424A00Q1012345" && hash == HASH
+        ));
+
+        let to = "Mar  4 10:00:05 mx1 sm-mta[1235]: 424A00Q1012345: to=<u@d.example>,<v@d.example>, delay=00:00:05, xdelay=00:00:04, mailer=esmtp, pri=120, relay=mx.d.example. [192.0.2.1], dsn=5.1.1, stat=User unknown";
+        let deliveries: Vec<_> = parse_sendmail_line(to, &hash_format)
+            .into_iter()
+            .filter_map(|parsed| match parsed {
+                ParsedSyslog::Delivery(delivery) => Some((
+                    delivery.service,
+                    delivery.recipient,
+                    delivery.status_code,
+                    delivery.action
+                )),
+                _ => None
+            })
+            .collect();
+        assert_eq!(
+            deliveries,
+            [
+                ("esmtp".into(), "u@d.example".into(), "5.1.1".into(), "failed".into()),
+                ("esmtp".into(), "v@d.example".into(), "5.1.1".into(), "failed".into())
+            ]
+        );
+
+        let deferred = "Mar  4 10:00:05 mx1 sendmail[1235]: 424A00Q1012345: to=w@d.example, delay=00:00:05, mailer=esmtp, relay=mx.d.example., stat=Deferred: Connection refused by mx.d.example.";
+        assert!(matches!(
+            parse_sendmail_line(deferred, &hash_format).as_slice(),
+            [ParsedSyslog::Delivery(delivery)]
+                if delivery.status_code == "4.0.0" && delivery.action == "delayed"
+        ));
+
+        let dsn =
+            "Mar  4 10:00:06 mx1 sendmail[1235]: 424A00Q1012345: 424A06Q1012346: DSN: User unknown";
+        assert!(matches!(
+            parse_sendmail_line(dsn, &hash_format).as_slice(),
+            [ParsedSyslog::Notification { queue_id, notice_queue_id }]
+                if queue_id == "424A00Q1012345" && notice_queue_id == "424A06Q1012346"
+        ));
+        let postfix = "Mar  4 10:00:06 mx1 postfix/qmgr[46]: 4ABC: removed";
+        assert!(parse_sendmail_line(postfix, &hash_format).is_empty());
+    }
+}
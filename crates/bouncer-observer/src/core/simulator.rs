@@ -0,0 +1,237 @@
+//! Synthetic postfix traffic for `bouncer-observer --simulate`.
+//!
+//! A new deployment can be checked end to end (UDP listener, correlator,
+//! publisher, server, dashboards) before real mail flows: [`run_simulator`]
+//! sends a `postfix/cleanup` and a `postfix/smtp` line per message to the
+//! observer's own `listen_udp`, like rsyslog forwards them. Their
+//! Message-IDs end in the reserved `.invalid` domain, so the correlator
+//! marks their events simulated and the server writes nothing for them.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use bouncer_helpers::clock::SharedClock;
+use tokio::net::UdpSocket;
+use tokio::time::{Instant, MissedTickBehavior, interval_at};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::config::{LogFormat, ObserverConfig, ObserverInput};
+
+/// Lets the listener bind before the first line is sent.
+const START_DELAY: Duration = Duration::from_secs(1);
+
+/// Syslog host and Message-ID domain of the simulated lines; `.invalid` is
+/// reserved (RFC 2606), so no real message carries it.
+const HOST: &str = "simulate.invalid";
+
+/// Ends the `message-id=` of every simulated cleanup line.
+const MESSAGE_ID_SUFFIX: &str = "@simulate.invalid>";
+
+/// Highest `rate` [`run_simulator`] sends; one message per microsecond is
+/// far past any UDP listener anyway.
+pub const MAX_SIMULATE_RATE: u32 = 1_000_000;
+const RELAY: &str = "mx.example.com[192.0.2.10]:25";
+
+/// `(status, dsn, relay reply)` per simulated delivery, weighted by
+/// repetition: mostly delivered, some bounced or deferred.
+const OUTCOMES: &[(&str, &str, &str)] = &[
+    ("sent", "2.0.0", "250 2.0.0 Ok: queued"),
+    ("sent", "2.0.0", "250 2.0.0 Ok: queued"),
+    ("sent", "2.0.0", "250 2.0.0 Ok: queued"),
+    ("sent", "2.0.0", "250 2.0.0 Ok: queued"),
+    ("sent", "2.0.0", "250 2.0.0 Ok: queued"),
+    ("sent", "2.0.0", "250 2.0.0 Ok: queued"),
+    ("bounced", "5.1.1", "550 5.1.1 <{rcpt}>: Recipient address rejected: User unknown"),
+    ("bounced", "5.2.2", "552 5.2.2 <{rcpt}>: Mailbox full"),
+    ("deferred", "4.2.0", "450 4.2.0 <{rcpt}>: Greylisted, try again later"),
+    ("deferred", "4.4.1", "connect to mx.example.net[192.0.2.25]:25: Connection timed out")
+];
+
+/// Where `--simulate` sends its lines: `listen_udp`, with an unspecified
+/// address replaced by loopback. Only postfix over UDP is simulated.
+pub fn simulator_target(config: &ObserverConfig) -> Result<SocketAddr> {
+    if config.input != ObserverInput::Udp {
+        bail!("--simulate needs `input: udp`");
+    }
+    if config.log_format != LogFormat::Postfix {
+        bail!("--simulate only generates `log_format: postfix` lines");
+    }
+    let mut target = config.listen_udp;
+    if target.ip().is_unspecified() {
+        target.set_ip(match target.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST)
+        });
+    }
+    Ok(target)
+}
+
+/// True for the cleanup line of a simulated message.
+pub fn is_simulated_cleanup(line: &str) -> bool {
+    line.contains(MESSAGE_ID_SUFFIX)
+}
+
+/// Sends `rate` simulated messages per second to `target` until `shutdown`;
+/// `rate` must be within 1 and [`MAX_SIMULATE_RATE`].
+pub async fn run_simulator(
+    target: SocketAddr,
+    rate: u32,
+    clock: SharedClock,
+    shutdown: CancellationToken
+) -> Result<()> {
+    let bind: SocketAddr = match target {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    if !(1..=MAX_SIMULATE_RATE).contains(&rate) {
+        bail!("--simulate rate must be within 1 and {MAX_SIMULATE_RATE}, got {rate}");
+    }
+    let socket = UdpSocket::bind(bind).await.context("failed to bind simulator udp socket")?;
+    let period = Duration::from_secs(1) / rate;
+    let mut tick = interval_at(Instant::now() + START_DELAY, period);
+    tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    info!("simulator sending synthetic postfix lines: target={target}, rate={rate}/s");
+
+    let mut sent = 0_u64;
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tick.tick() => {}
+        }
+        let message = SimulatedMessage::random(sent);
+        for line in message.lines(clock.unix_secs()) {
+            socket
+                .send_to(line.as_bytes(), target)
+                .await
+                .with_context(|| format!("failed to send simulated line to {target}"))?;
+        }
+        sent += 1;
+    }
+    info!("simulator stopping: messages={sent}");
+    Ok(())
+}
+
+/// One simulated message: a cleanup line naming its hash and one delivery.
+#[derive(Debug)]
+struct SimulatedMessage {
+    queue_id: String,
+    hash: String,
+    recipient: String,
+    outcome: (&'static str, &'static str, &'static str)
+}
+
+impl SimulatedMessage {
+    fn random(sequence: u64) -> Self {
+        let queue_id =
+            (0..10).map(|_| char::from(b"0123456789ABCDEF"[fastrand::usize(..16)])).collect();
+        let hash = format!("{:032x}", fastrand::u128(..));
+        Self {
+            queue_id,
+            hash,
+            recipient: format!("user{}@example.com", sequence % 1000),
+            outcome: OUTCOMES[fastrand::usize(..OUTCOMES.len())]
+        }
+    }
+
+    /// The cleanup and smtp syslog lines, stamped `now` in RFC 3339 as
+    /// `RSYSLOG_ForwardFormat` does.
+    fn lines(
+        &self,
+        now: u64
+    ) -> [String; 2] {
+        let stamp = rfc3339(now);
+        let (status, dsn, reply) = self.outcome;
+        let reply = reply.replace("{rcpt}", &self.recipient);
+        [
+            format!(
+                "<22>{stamp} {HOST} postfix/cleanup[1001]: {}: message-id=<{}{MESSAGE_ID_SUFFIX}",
+                self.queue_id, self.hash
+            ),
+            format!(
+                "<22>{stamp} {HOST} postfix/smtp[1002]: {}: to=<{}>, relay={RELAY}, delay=0.4, \
+                 dsn={dsn}, status={status} ({reply})",
+                self.queue_id, self.recipient
+            )
+        ]
+    }
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ` of unix seconds.
+fn rfc3339(unix: u64) -> String {
+    let (days, secs) = (unix / 86_400, unix % 86_400);
+    // Civil date of a day count since 1970-01-01 (proleptic Gregorian).
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bouncer_helpers::clock;
+    use bouncer_helpers::maillog::ParsedSyslog;
+    use bouncer_helpers::message_hash::HashFormat;
+    use tokio_util::sync::CancellationToken;
+
+    use super::{
+        MAX_SIMULATE_RATE, SimulatedMessage, is_simulated_cleanup, rfc3339, run_simulator
+    };
+    use crate::config::LogFormat;
+    use crate::core::parser::{line_timestamp, parse_line};
+
+    #[test]
+    fn simulated_lines_parse_as_a_correlated_delivery() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(1_709_546_405), "2024-03-04T10:00:05Z");
+
+        let hash_format = HashFormat::default();
+        for sequence in 0..50 {
+            let message = SimulatedMessage::random(sequence);
+            let [cleanup, smtp] = message.lines(1_709_546_405);
+            assert!(is_simulated_cleanup(&cleanup) && !is_simulated_cleanup(&smtp));
+            assert_eq!(line_timestamp(LogFormat::Postfix, &smtp), Some(1_709_546_405));
+
+            match parse_line(LogFormat::Postfix, &cleanup, &hash_format).as_slice() {
+                [ParsedSyslog::Cleanup { queue_id, hash }] => {
+                    assert_eq!(queue_id, &message.queue_id);
+                    assert_eq!(hash, &message.hash);
+                }
+                _ => panic!("cleanup line not parsed: {cleanup}")
+            }
+            match parse_line(LogFormat::Postfix, &smtp, &hash_format).as_slice() {
+                [ParsedSyslog::Delivery(delivery)] => {
+                    assert_eq!(delivery.queue_id, message.queue_id);
+                    assert_eq!(delivery.recipient, message.recipient);
+                    assert_eq!(delivery.smtp_status, message.outcome.0);
+                    assert_eq!(delivery.status_code, message.outcome.1);
+                }
+                _ => panic!("smtp line not parsed: {smtp}")
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_rates_without_a_usable_period() {
+        let target = "127.0.0.1:9".parse().unwrap();
+        for rate in [0, MAX_SIMULATE_RATE + 1, u32::MAX] {
+            let shutdown = CancellationToken::new();
+            shutdown.cancel();
+            let result = run_simulator(target, rate, clock::system_clock(), shutdown).await;
+            assert!(result.is_err(), "rate {rate} accepted");
+        }
+    }
+}
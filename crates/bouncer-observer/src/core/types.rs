@@ -1,48 +1,16 @@
-use std::time::Instant;
-
-use serde::Serialize;
-
-#[derive(Debug, Clone)]
-pub struct QueueEntry {
-    pub hash: String,
-    pub updated_at: Instant
-}
-
-#[derive(Debug, Clone)]
-pub struct SmtpEvent {
-    pub queue_id: String,
-    pub recipient: String,
-    pub smtp_status: String,
-    pub status_code: String,
-    pub action: String,
-    pub diagnostic: String
-}
-
 #[derive(Debug, Clone)]
 pub struct DeliveryEvent {
     pub hash: String,
     pub queue_id: String,
-    pub recipient: String,
-    pub status_code: String,
-    pub action: String,
-    pub diagnostic: String,
-    pub smtp_status: String
-}
-
-#[derive(Debug, Serialize)]
-pub struct DeliveryEventPayload {
-    pub source: String,
-    pub hash: String,
-    pub queue_id: String,
+    /// Delivery agent that logged the outcome.
+    pub service: String,
     pub recipient: String,
     pub status_code: String,
     pub action: String,
     pub diagnostic: String,
     pub smtp_status: String,
-    pub observed_at_unix: u64
-}
-
-pub enum ParsedSyslog {
-    Cleanup { queue_id: String, hash: String },
-    Smtp(SmtpEvent)
+    /// When the MTA logged the delivery, if the log line says so.
+    pub occurred_at_unix: Option<u64>,
+    /// A message of `--simulate`, see [`super::simulator`].
+    pub simulated: bool
 }
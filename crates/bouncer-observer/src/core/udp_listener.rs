@@ -1,37 +1,39 @@
-use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use std::sync::Arc;
 
 use anyhow::{Context, Result};
+use bouncer_helpers::clock::SharedClock;
+use bouncer_helpers::message_hash::HashFormat;
+use bouncer_helpers::queue_map::QueueMapGauge;
 use tokio::net::UdpSocket;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio::time::interval;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, info, trace, warn};
+use tracing::info;
 
-use super::parser::parse_postfix_line;
-use super::types::{DeliveryEvent, ParsedSyslog, QueueEntry};
+use super::correlator::{LineCorrelator, PRUNE_INTERVAL};
+use super::types::DeliveryEvent;
 use crate::config::ObserverConfig;
 
 const UDP_PACKET_BYTES: usize = 8192;
 
-/// Runs the UDP syslog listener and converts postfix log lines into delivery
-/// events for the publisher queue.
-///
-/// The listener keeps an in-memory `queue_id -> message hash` map using
-/// `cleanup` lines and enriches `smtp` lines with that mapping.
+/// Runs the UDP syslog listener and converts MTA log lines into delivery
+/// events for the publisher queue; see [`LineCorrelator`].
 pub async fn run_udp_listener(
-    config: ObserverConfig,
+    config_rx: watch::Receiver<ObserverConfig>,
     events_tx: mpsc::Sender<DeliveryEvent>,
+    gauge: Arc<QueueMapGauge>,
+    clock: SharedClock,
     shutdown: CancellationToken
 ) -> Result<()> {
+    let config = config_rx.borrow().clone();
+    let hash_format = HashFormat::new(&config.hash).context("invalid `hash.pattern`")?;
     let socket = UdpSocket::bind(config.listen_udp)
         .await
         .with_context(|| format!("failed to bind udp socket {}", config.listen_udp))?;
 
     let mut buf = [0_u8; UDP_PACKET_BYTES];
-    let mut queue_map: HashMap<String, QueueEntry> = HashMap::new();
-    let ttl = Duration::from_secs(config.mapping_ttl_secs.max(60));
-    let mut cleanup_tick = interval(Duration::from_secs(300));
+    let mut correlator = LineCorrelator::new(config_rx, events_tx, hash_format, gauge, clock);
+    let mut cleanup_tick = interval(PRUNE_INTERVAL);
 
     info!("udp listener ready: listen_udp={}", config.listen_udp);
 
@@ -42,14 +44,7 @@ pub async fn run_udp_listener(
                 break;
             }
             _ = cleanup_tick.tick() => {
-                let removed = prune_queue_map(&mut queue_map, ttl);
-                if removed > 0 {
-                    debug!(
-                        "cleaned stale queue mappings: removed={}, tracked={}",
-                        removed,
-                        queue_map.len()
-                    );
-                }
+                correlator.prune();
             }
             recv = socket.recv_from(&mut buf) => {
                 let (len, _addr) = recv.context("udp recv failed")?;
@@ -61,77 +56,10 @@ pub async fn run_udp_listener(
                     Ok(text) => text.trim(),
                     Err(_) => continue,
                 };
-
-                let Some(parsed) = parse_postfix_line(line) else {
-                    continue;
-                };
-
-                match parsed {
-                    ParsedSyslog::Cleanup { queue_id, hash } => {
-                        // First stage: remember which app hash belongs to this postfix queue id.
-                        debug!(
-                            "queue mapping stored: queue_id={}, hash={}",
-                            queue_id, hash
-                        );
-                        queue_map.insert(
-                            queue_id,
-                            QueueEntry {
-                                hash,
-                                updated_at: Instant::now(),
-                            },
-                        );
-                    }
-                    ParsedSyslog::Smtp(smtp) => {
-                        // Second stage: smtp has status fields; join with cached hash via queue id.
-                        let Some(entry) = queue_map.get_mut(&smtp.queue_id) else {
-                            trace!(
-                                "smtp log without known queue mapping: queue_id={}",
-                                smtp.queue_id
-                            );
-                            continue;
-                        };
-
-                        entry.updated_at = Instant::now();
-                        let event = DeliveryEvent {
-                            hash: entry.hash.clone(),
-                            queue_id: smtp.queue_id,
-                            recipient: smtp.recipient,
-                            status_code: smtp.status_code,
-                            action: smtp.action,
-                            diagnostic: smtp.diagnostic,
-                            smtp_status: smtp.smtp_status,
-                        };
-                        debug!(
-                            "smtp log matched queue mapping: queue_id={}, hash={}, smtp_status={}, status_code={}, action={}, recipient={}",
-                            event.queue_id,
-                            event.hash,
-                            event.smtp_status,
-                            event.status_code,
-                            event.action,
-                            event.recipient
-                        );
-
-                        if let Err(err) = events_tx.try_send(event) {
-                            warn!(
-                                "observer event queue is full, dropping event: error={err}"
-                            );
-                        }
-                    }
-                }
+                correlator.handle_line(line);
             }
         }
     }
 
     Ok(())
 }
-
-/// Removes stale queue-id mappings that were not refreshed within `ttl`.
-fn prune_queue_map(
-    queue_map: &mut HashMap<String, QueueEntry>,
-    ttl: Duration
-) -> usize {
-    let before = queue_map.len();
-    let now = Instant::now();
-    queue_map.retain(|_, entry| now.duration_since(entry.updated_at) <= ttl);
-    before.saturating_sub(queue_map.len())
-}
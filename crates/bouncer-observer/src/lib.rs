@@ -0,0 +1,222 @@
+//! Embeddable MTA log observer (postfix, Exim, sendmail).
+//!
+//! [`run_observer`] runs the input (UDP syslog listener or log file tailer)
+//! and the TCP publisher that the `bouncer-observer` binary runs, so an agent can correlate MTA queue ids
+//! with message hashes in-process, and tests can drive the pipeline without
+//! spawning the binary.
+//!
+//! [`run_simulator`] feeds the UDP listener synthetic postfix lines for
+//! `bouncer-observer --simulate`.
+//!
+//! A config read from a file is reloaded on SIGHUP: publisher timeouts,
+//! heartbeat, reconnect backoff, `mapping_ttl_secs` and `log_filter` apply
+//! live, other changes are logged as needing a restart.
+
+mod args;
+pub mod config;
+mod core;
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+pub use args::ObserverArgs;
+use bouncer_helpers::clock::{self, SharedClock};
+use bouncer_helpers::message_hash::HashFormat;
+use bouncer_helpers::queue_map::QueueMapGauge;
+use bouncer_helpers::reload::{apply_log_filter, run_config_reload};
+pub use config::{LogFormat, ObserverConfig, ObserverInput};
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::core::{run_file_tailer, run_publisher, run_udp_listener, simulator_target};
+
+/// Runs the input and publisher until `shutdown` is cancelled.
+///
+/// If either task fails (e.g. the UDP bind), the other is stopped and the
+/// error is returned; the caller's `shutdown` token is left untouched.
+pub async fn run_observer(
+    config: ObserverConfig,
+    shutdown: CancellationToken
+) -> Result<()> {
+    run_observer_with_clock(config, shutdown, clock::system_clock()).await
+}
+
+/// Sends `rate` synthetic postfix messages per second (a cleanup and an
+/// smtp line each) to the `listen_udp` of `config` until `shutdown`, so a
+/// deployment can be checked end to end before real traffic. Fails at once
+/// unless `config` reads postfix lines over UDP.
+pub async fn run_simulator(
+    config: &ObserverConfig,
+    rate: u32,
+    shutdown: CancellationToken
+) -> Result<()> {
+    let target = simulator_target(config)?;
+    core::run_simulator(target, rate, clock::system_clock(), shutdown).await
+}
+
+/// Runs one log line through the `format` parser and its timestamp reader
+/// and returns how many records it yielded; the entry point of the
+/// `observer_line` fuzz target, which only checks that no input panics.
+#[doc(hidden)]
+pub fn fuzz_parse_line(
+    format: LogFormat,
+    line: &str
+) -> usize {
+    let records = core::parse_line(format, line, &HashFormat::default()).len();
+    records + usize::from(core::line_timestamp(format, line).is_some())
+}
+
+/// Same as [`run_observer`] with an explicit clock.
+pub async fn run_observer_with_clock(
+    mut config: ObserverConfig,
+    shutdown: CancellationToken,
+    clock: SharedClock
+) -> Result<()> {
+    config.normalize()?;
+    if config.log_filter.is_some() {
+        apply_log_filter(config.log_filter.as_deref());
+    }
+
+    match config.input {
+        ObserverInput::Udp => info!(
+            "observer starting: input=udp, log_format={}, listen_udp={}, server={}, source={}",
+            config.log_format.as_str(),
+            config.listen_udp,
+            config.server,
+            config.source
+        ),
+        ObserverInput::File => info!(
+            "observer starting: input=file, log_format={}, files={}, server={}, source={}",
+            config.log_format.as_str(),
+            config.files.join(","),
+            config.server,
+            config.source
+        )
+    }
+
+    let (events_tx, events_rx) = mpsc::channel(config.queue_capacity.max(1));
+    let stop = shutdown.child_token();
+    let queue_map = Arc::new(QueueMapGauge::default());
+    let reloadable = config.snapshot.is_some();
+    let (config_tx, config_rx) = watch::channel(config);
+    if reloadable {
+        tokio::spawn(run_config_reload(config_tx, stop.clone()));
+    }
+
+    let input = config_rx.borrow().input;
+    let mut listener_task = match input {
+        ObserverInput::Udp => tokio::spawn(run_udp_listener(
+            config_rx.clone(),
+            events_tx,
+            queue_map.clone(),
+            clock.clone(),
+            stop.clone()
+        )),
+        ObserverInput::File => tokio::spawn(run_file_tailer(
+            config_rx.clone(),
+            events_tx,
+            queue_map.clone(),
+            clock.clone(),
+            stop.clone()
+        ))
+    };
+    let mut publisher_task =
+        tokio::spawn(run_publisher(config_rx, events_rx, queue_map, clock, stop.clone()));
+
+    let (first, joined) = tokio::select! {
+        joined = &mut listener_task => ("listener", joined),
+        joined = &mut publisher_task => ("publisher", joined)
+    };
+    stop.cancel();
+
+    let result = joined.with_context(|| format!("{first} task join failed"))?;
+    if let Err(err) = &result {
+        warn!("{first} task stopped with error: error={err:#}");
+    }
+
+    let remaining = if first == "listener" { publisher_task } else { listener_task };
+    if let Err(err) = remaining.await.context("observer task join failed")? {
+        warn!("observer task stopped with error: error={err:#}");
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, UdpSocket};
+    use std::path::Path;
+    use std::time::Duration;
+
+    use bouncer_helpers::config_file;
+    use bouncer_proto::{ACK, decode_header_json, read_frame_async};
+    use serde_json::Value;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+    use tokio_util::sync::CancellationToken;
+
+    use super::{ObserverConfig, run_observer};
+
+    #[tokio::test]
+    async fn publishes_correlated_event_to_server() {
+        let server = TcpListener::bind("127.0.0.1:0").await.expect("bind tcp");
+        let server_addr = server.local_addr().expect("tcp addr");
+        let (frames_tx, mut frames_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = server.accept().await.expect("accept");
+            while let Ok((header, body)) = read_frame_async(&mut stream, 64 * 1024, 1 << 20).await {
+                let kind = decode_header_json(&header).expect("header").kind.unwrap_or_default();
+                stream.write_all(ACK).await.expect("ack");
+                frames_tx.send((kind, body)).ok();
+            }
+        });
+
+        let listen_udp: SocketAddr = {
+            let probe = UdpSocket::bind("127.0.0.1:0").expect("probe udp");
+            probe.local_addr().expect("udp addr")
+        };
+        let raw = format!(
+            "listen_udp: \"{listen_udp}\"\nserver: \"{server_addr}\"\nsource: \"test-mta\"\nheartbeat_secs: 0\n"
+        );
+        let config: ObserverConfig =
+            config_file::parse_yaml(raw.as_bytes(), Path::new("observer.yaml")).expect("config");
+
+        let shutdown = CancellationToken::new();
+        let observer = tokio::spawn(run_observer(config, shutdown.clone()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let sender = UdpSocket::bind("127.0.0.1:0").expect("sender");
+        for line in [
+            "<22>Oct 16 10:00:00 mx postfix/cleanup[100]: 4ABC123: message-id=<5d41402abc4b2a76b9719d911017c592@claviron.app>",
+            "<22>Oct 16 10:00:01 mx postfix/smtp[101]: 4ABC123: to=<user@example.com>, relay=mx.example.com[192.0.2.1]:25, dsn=5.1.1, status=bounced (user unknown)"
+        ] {
+            sender.send_to(line.as_bytes(), listen_udp).expect("send syslog line");
+        }
+
+        let event = timeout(Duration::from_secs(5), async {
+            loop {
+                let (kind, body) = frames_rx.recv().await.expect("server closed");
+                if kind == "observer_event" {
+                    break serde_json::from_slice::<Value>(&body).expect("event json");
+                }
+            }
+        })
+        .await
+        .expect("observer event within timeout");
+
+        assert_eq!(event["hash"], "5d41402abc4b2a76b9719d911017c592");
+        assert_eq!(event["source"], "test-mta");
+        assert_eq!(event["status_code"], "5.1.1");
+        assert_eq!(event["recipient"], "user@example.com");
+
+        shutdown.cancel();
+        timeout(Duration::from_secs(5), observer)
+            .await
+            .expect("observer stops")
+            .expect("join")
+            .expect("observer result");
+    }
+}
@@ -1,44 +1,31 @@
-mod args;
-mod config;
-mod core;
-
-use core::{run_publisher, run_udp_listener};
-
-use anyhow::{Context, Result};
+use anyhow::Result;
 use bouncer_helpers::{logging, shutdown};
-use config::ObserverConfig;
-use tokio::sync::mpsc;
+use bouncer_observer::{ObserverArgs, ObserverConfig, run_observer, run_simulator};
 use tokio_util::sync::CancellationToken;
-use tracing::{info, warn};
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
-    logging::init_logging("bouncer_observer=info,tokio=warn", "OBSERVER_LOG", "bouncer-observer");
-
-    let config = ObserverConfig::load()?;
-
-    info!(
-        "observer starting: listen_udp={}, server={}, source={}",
-        config.listen_udp, config.server, config.source
+    let _logging = logging::init_logging(
+        "bouncer_observer=info,tokio=warn",
+        "OBSERVER_LOG",
+        "bouncer-observer"
     );
 
-    let (events_tx, events_rx) = mpsc::channel(config.queue_capacity.max(1));
+    let args = ObserverArgs::parse(std::env::args().skip(1))?;
+    let config = ObserverConfig::load_from(args.config_path)?;
+    if args.check_config {
+        print!("{}", config.dump()?);
+        return Ok(());
+    }
     let shutdown = CancellationToken::new();
     tokio::spawn(shutdown::listen_shutdown(shutdown.clone()));
 
-    let listener_task = tokio::spawn(run_udp_listener(config.clone(), events_tx, shutdown.clone()));
-
-    let publisher_task = tokio::spawn(run_publisher(config.clone(), events_rx, shutdown.clone()));
-
-    shutdown.cancelled().await;
-
-    if let Err(err) = listener_task.await.context("listener task join failed")? {
-        warn!("listener task stopped with error: error={err}");
+    match args.simulate {
+        Some(rate) => {
+            let simulator = run_simulator(&config, rate, shutdown.clone());
+            tokio::try_join!(run_observer(config.clone(), shutdown.clone()), simulator)?;
+            Ok(())
+        }
+        None => run_observer(config, shutdown).await
     }
-
-    if let Err(err) = publisher_task.await.context("publisher task join failed")? {
-        warn!("publisher task stopped with error: error={err}");
-    }
-
-    Ok(())
 }
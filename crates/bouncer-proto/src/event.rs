@@ -0,0 +1,314 @@
+//! Body of `kind=observer_event` frames: one delivery outcome as JSON,
+//! published by `bouncer-observer` and `bouncer-journal` and applied by the
+//! server. [`DeliveryEvent`] is the only definition of the shape; enable the
+//! `event` feature to use it.
+//!
+//! Compatibility policy:
+//! - Adding a field is compatible and keeps [`SCHEMA_VERSION`]. New fields
+//!   are `#[serde(default)]`, so events from older publishers still decode,
+//!   and decoders ignore fields they do not know, so older servers accept
+//!   events from newer publishers.
+//! - Removing or renaming a field, or changing its type or meaning, is not.
+//!   Such a change bumps [`SCHEMA_VERSION`]; [`decode_delivery_event`]
+//!   refuses newer versions and the server quarantines those events until
+//!   it is upgraded.
+//! - Publishers that predate `schema_version` decode as version 0.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Version of the event shape this build writes and the newest it reads.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeliveryEvent {
+    #[serde(default)]
+    pub schema_version: u32,
+    pub source: String,
+    /// systemd unit of the journal entry (`bouncer-journal` only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    pub hash: String,
+    pub queue_id: String,
+    /// Delivery agent that logged the outcome.
+    #[serde(default)]
+    pub service: String,
+    pub recipient: String,
+    pub status_code: String,
+    pub action: String,
+    pub diagnostic: String,
+    /// `sent`, `deferred` or `bounced`.
+    pub smtp_status: String,
+    pub observed_at_unix: u64,
+    /// Strictly increasing per publisher; updates older than the stored one
+    /// for the same source and queue id are skipped. Missing from publishers
+    /// that predate it, whose events apply in arrival order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+    /// Tenant of the message; the frame header's tenant applies when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Synthetic traffic of `bouncer-observer --simulate`: the server
+    /// counts and acknowledges it but writes nothing and fires no alert or
+    /// hook.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub simulated: bool
+}
+
+impl DeliveryEvent {
+    /// An event at [`SCHEMA_VERSION`] for one recipient of `queue_id`; the
+    /// outcome fields start empty, see [`DeliveryEvent::with_outcome`].
+    pub fn new(
+        source: &str,
+        hash: &str,
+        queue_id: &str,
+        recipient: &str,
+        observed_at_unix: u64
+    ) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            source: source.to_string(),
+            unit: None,
+            hash: hash.to_string(),
+            queue_id: queue_id.to_string(),
+            service: String::new(),
+            recipient: recipient.to_string(),
+            status_code: String::new(),
+            action: String::new(),
+            diagnostic: String::new(),
+            smtp_status: String::new(),
+            observed_at_unix,
+            sequence: None,
+            tenant: None,
+            simulated: false
+        }
+    }
+
+    pub fn with_outcome(
+        mut self,
+        smtp_status: &str,
+        status_code: &str,
+        action: &str
+    ) -> Self {
+        self.smtp_status = smtp_status.to_string();
+        self.status_code = status_code.to_string();
+        self.action = action.to_string();
+        self
+    }
+
+    pub fn with_service(
+        mut self,
+        service: &str
+    ) -> Self {
+        self.service = service.to_string();
+        self
+    }
+
+    pub fn with_diagnostic(
+        mut self,
+        diagnostic: &str
+    ) -> Self {
+        self.diagnostic = diagnostic.to_string();
+        self
+    }
+
+    pub fn with_unit(
+        mut self,
+        unit: &str
+    ) -> Self {
+        self.unit = Some(unit.to_string());
+        self
+    }
+
+    pub fn with_sequence(
+        mut self,
+        sequence: u64
+    ) -> Self {
+        self.sequence = Some(sequence);
+        self
+    }
+
+    pub fn with_tenant(
+        mut self,
+        tenant: &str
+    ) -> Self {
+        self.tenant = Some(tenant.to_string());
+        self
+    }
+
+    pub fn with_simulated(
+        mut self,
+        simulated: bool
+    ) -> Self {
+        self.simulated = simulated;
+        self
+    }
+
+    /// Drops CR and LF from every text field; log lines can carry them and
+    /// the fields end up in server log lines.
+    pub fn sanitized(mut self) -> Self {
+        let strip = |value: &mut String| value.retain(|c| c != '\r' && c != '\n');
+        for value in [
+            &mut self.source,
+            &mut self.hash,
+            &mut self.queue_id,
+            &mut self.service,
+            &mut self.recipient,
+            &mut self.status_code,
+            &mut self.action,
+            &mut self.diagnostic,
+            &mut self.smtp_status
+        ] {
+            strip(value);
+        }
+        if let Some(unit) = &mut self.unit {
+            strip(unit);
+        }
+        if let Some(tenant) = &mut self.tenant {
+            strip(tenant);
+        }
+        self
+    }
+
+    pub fn is_delivered(&self) -> bool {
+        self.action.eq_ignore_ascii_case("delivered")
+    }
+
+    /// Checks the fields the server needs to apply the event.
+    pub fn validate(&self) -> Result<(), EventError> {
+        let required = [
+            ("source", &self.source),
+            ("hash", &self.hash),
+            ("queue_id", &self.queue_id),
+            ("recipient", &self.recipient),
+            ("status_code", &self.status_code),
+            ("action", &self.action)
+        ];
+        if let Some((field, _)) = required.into_iter().find(|(_, value)| value.trim().is_empty()) {
+            return Err(EventError::EmptyField(field));
+        }
+        match &self.tenant {
+            Some(tenant) if !crate::valid_tenant(tenant) => {
+                Err(EventError::InvalidTenant(tenant.clone()))
+            }
+            _ => Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EventError {
+    #[error("invalid observer event: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("observer event schema_version {0} is newer than supported {SCHEMA_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("observer event field `{0}` is empty")]
+    EmptyField(&'static str),
+    #[error("observer event tenant is invalid: {0:?}")]
+    InvalidTenant(String)
+}
+
+/// Encodes a validated event.
+pub fn encode_delivery_event(event: &DeliveryEvent) -> Result<Vec<u8>, EventError> {
+    event.validate()?;
+    Ok(serde_json::to_vec(event)?)
+}
+
+/// Decodes an `observer_event` body under the compatibility policy above.
+pub fn decode_delivery_event(body: &[u8]) -> Result<DeliveryEvent, EventError> {
+    let event: DeliveryEvent = serde_json::from_slice(body)?;
+    if event.schema_version > SCHEMA_VERSION {
+        return Err(EventError::UnsupportedVersion(event.schema_version));
+    }
+    event.validate()?;
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DeliveryEvent, EventError, SCHEMA_VERSION, decode_delivery_event, encode_delivery_event
+    };
+
+    fn event() -> DeliveryEvent {
+        DeliveryEvent::new(
+            "observer-1",
+            "9f0123456789abcdef0123456789abcd",
+            "4ABC",
+            "user@example.com",
+            1_700_000_000
+        )
+        .with_outcome("bounced", "5.1.1", "failed")
+        .with_service("smtp")
+        .with_diagnostic("550 5.1.1 user unknown")
+        .with_sequence(7)
+    }
+
+    #[test]
+    fn round_trips_current_events() {
+        let journal = event().with_unit("postfix@-.service");
+        let tenant = event().with_tenant("shop-eu");
+        let simulated = event().with_simulated(true);
+        for event in [event(), journal, tenant, simulated] {
+            let body = encode_delivery_event(&event).unwrap();
+            assert_eq!(decode_delivery_event(&body).unwrap(), event);
+        }
+        let body = String::from_utf8(encode_delivery_event(&event()).unwrap()).unwrap();
+        assert!(!body.contains("\"unit\"") && !body.contains("\"tenant\""), "{body}");
+        assert!(!body.contains("\"simulated\""), "{body}");
+        assert!(body.contains(&format!("\"schema_version\":{SCHEMA_VERSION}")), "{body}");
+    }
+
+    #[test]
+    fn decodes_events_from_older_publishers() {
+        // The first published shape: no schema_version, service or sequence.
+        let body = br#"{"source":"observer-1","hash":"a1b2","queue_id":"Q1","recipient":"user@example.com","status_code":"5.1.1","action":"failed","diagnostic":"550 5.1.1 user unknown","smtp_status":"bounced","observed_at_unix":1}"#;
+        let event = decode_delivery_event(body).unwrap();
+        assert_eq!((event.schema_version, event.sequence), (0, None));
+        assert_eq!((event.service.as_str(), event.unit), ("", None));
+    }
+
+    #[test]
+    fn ignores_fields_added_by_newer_publishers() {
+        let mut body = serde_json::to_value(event()).unwrap();
+        body["mx_host"] = "mx.example.net".into();
+        body["retries"] = serde_json::json!({ "count": 2 });
+        let decoded = decode_delivery_event(&serde_json::to_vec(&body).unwrap()).unwrap();
+        assert_eq!(decoded, event());
+    }
+
+    #[test]
+    fn refuses_newer_versions_and_missing_or_empty_fields() {
+        let newer = DeliveryEvent { schema_version: SCHEMA_VERSION + 1, ..event() };
+        let body = serde_json::to_vec(&newer).unwrap();
+        assert!(matches!(
+            decode_delivery_event(&body),
+            Err(EventError::UnsupportedVersion(version)) if version == SCHEMA_VERSION + 1
+        ));
+
+        let mut body = serde_json::to_value(event()).unwrap();
+        body.as_object_mut().unwrap().remove("hash");
+        assert!(matches!(
+            decode_delivery_event(&serde_json::to_vec(&body).unwrap()),
+            Err(EventError::Json(_))
+        ));
+
+        let blank = DeliveryEvent { queue_id: " ".into(), ..event() };
+        assert!(matches!(encode_delivery_event(&blank), Err(EventError::EmptyField("queue_id"))));
+        let body = serde_json::to_vec(&blank).unwrap();
+        assert!(matches!(decode_delivery_event(&body), Err(EventError::EmptyField("queue_id"))));
+
+        let body = serde_json::to_vec(&event().with_tenant("shop/eu")).unwrap();
+        assert!(matches!(decode_delivery_event(&body), Err(EventError::InvalidTenant(_))));
+    }
+
+    #[test]
+    fn sanitized_strips_line_breaks() {
+        let event =
+            event().with_diagnostic("550 user\r\nunknown").with_unit("postfix\n").sanitized();
+        assert_eq!(event.diagnostic, "550 userunknown");
+        assert_eq!(event.unit.as_deref(), Some("postfix"));
+        assert!(!event.is_delivered());
+        assert!(event.with_outcome("sent", "2.0.0", "delivered").is_delivered());
+    }
+}
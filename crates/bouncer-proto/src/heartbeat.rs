@@ -0,0 +1,57 @@
+//! Body of `kind=heartbeat` frames: `key=value` lines, starting with
+//! `ts=<unix secs>`. Receivers ignore keys they do not know.
+
+use serde::{Deserialize, Serialize};
+
+/// Occupancy of an agent's `queue_id -> hash` map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueMapOccupancy {
+    pub entries: u64,
+    pub capacity: u64,
+    /// Mappings evicted for capacity since the agent started.
+    pub evicted: u64
+}
+
+pub fn encode_heartbeat(
+    ts: u64,
+    queue_map: Option<QueueMapOccupancy>
+) -> Vec<u8> {
+    let mut body = format!("ts={ts}\n");
+    if let Some(QueueMapOccupancy { entries, capacity, evicted }) = queue_map {
+        body.push_str(&format!(
+            "queue_map_entries={entries}\nqueue_map_capacity={capacity}\nqueue_map_evicted={evicted}\n"
+        ));
+    }
+    body.into_bytes()
+}
+
+/// The queue map occupancy of a heartbeat body; `None` for agents that do
+/// not report it.
+pub fn decode_queue_map(body: &[u8]) -> Option<QueueMapOccupancy> {
+    let body = std::str::from_utf8(body).ok()?;
+    let field = |name: &str| {
+        body.lines()
+            .filter_map(|line| line.split_once('='))
+            .find(|(key, _)| key.trim() == name)
+            .and_then(|(_, value)| value.trim().parse::<u64>().ok())
+    };
+    Some(QueueMapOccupancy {
+        entries: field("queue_map_entries")?,
+        capacity: field("queue_map_capacity")?,
+        evicted: field("queue_map_evicted")?
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QueueMapOccupancy, decode_queue_map, encode_heartbeat};
+
+    #[test]
+    fn round_trips_queue_map_occupancy() {
+        let occupancy = QueueMapOccupancy { entries: 12, capacity: 100, evicted: 3 };
+        let body = encode_heartbeat(1_700_000_000, Some(occupancy));
+        assert!(body.starts_with(b"ts=1700000000\n"));
+        assert_eq!(decode_queue_map(&body), Some(occupancy));
+        assert_eq!(decode_queue_map(&encode_heartbeat(1_700_000_000, None)), None);
+    }
+}
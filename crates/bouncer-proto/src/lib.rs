@@ -1,12 +1,33 @@
 use std::io::{Read, Write};
+#[cfg(feature = "tokio")]
+use std::pin::Pin;
+#[cfg(feature = "tokio")]
+use std::task::{Context, Poll, ready};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 #[cfg(feature = "tokio")]
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf, Take};
+
+#[cfg(feature = "event")]
+pub mod event;
+pub mod heartbeat;
+pub mod query;
+pub mod status;
+pub mod verp;
 
 pub const MAGIC: [u8; 4] = *b"BNCE";
 pub const ACK: &[u8; 3] = b"OK\n";
+/// Sent instead of [`ACK`] when a frame exceeds the receiver's header or
+/// body limit; the receiver closes the connection right after.
+pub const TOO_LARGE: &[u8; 3] = b"TL\n";
+/// Sent instead of [`ACK`] when a frame fails its [`Header::checksum`], or
+/// has none while the receiver requires one; the receiver closes the
+/// connection right after.
+pub const BAD_CHECKSUM: &[u8; 3] = b"CK\n";
+
+/// Algorithm prefix of [`Header::checksum`] values.
+const CRC32_PREFIX: &str = "crc32:";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Header {
@@ -15,7 +36,44 @@ pub struct Header {
     #[serde(default)]
     pub kind: Option<String>,
     #[serde(default)]
-    pub source: Option<String>
+    pub source: Option<String>,
+    /// W3C trace context of the sending span, continued by the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traceparent: Option<String>,
+    /// Postfix queue id of a forwarded bounce, for logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_id: Option<String>,
+    /// Tenant (application) the frame belongs to; message hashes are only
+    /// unique within a tenant. See [`valid_tenant`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Checksum of the body, `crc32:<8 hex digits>`; see [`body_checksum`].
+    /// Receivers that predate it ignore the field, so setting it is always
+    /// safe. A receiver that knows it verifies the body and answers a
+    /// checksummed request with a checksummed reply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>
+}
+
+impl Header {
+    /// Sets [`Self::checksum`] for `body`.
+    pub fn with_checksum(
+        mut self,
+        body: &[u8]
+    ) -> Self {
+        self.checksum = Some(body_checksum(body));
+        self
+    }
+}
+
+/// Longest tenant name accepted.
+pub const MAX_TENANT_LEN: usize = 64;
+
+/// Tenant names are 1 to [`MAX_TENANT_LEN`] ASCII letters, digits, `_` or
+/// `-`, so they fit in spool file names, metric labels and log lines as is.
+pub fn valid_tenant(tenant: &str) -> bool {
+    (1..=MAX_TENANT_LEN).contains(&tenant.len())
+        && tenant.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
 }
 
 #[derive(Debug, Error)]
@@ -26,6 +84,16 @@ pub enum ProtoError {
     HeaderTooLarge(u32),
     #[error("body too large: {0} bytes")]
     BodyTooLarge(u64),
+    #[error("frame rejected by peer: too large")]
+    RejectedTooLarge,
+    #[error("frame checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error("unsupported frame checksum: {0}")]
+    UnsupportedChecksum(String),
+    #[error("frame checksum missing")]
+    ChecksumMissing,
+    #[error("frame rejected by peer: bad checksum")]
+    RejectedChecksum,
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
     #[error("header encode error: {0}")]
@@ -38,8 +106,84 @@ pub fn encode_header_json(header: &Header) -> Result<Vec<u8>, ProtoError> {
     serde_json::to_vec(header).map_err(|err| ProtoError::HeaderEncode(err.to_string()))
 }
 
+/// Decodes a frame header; a `tenant` that fails [`valid_tenant`] is a
+/// decode error.
 pub fn decode_header_json(bytes: &[u8]) -> Result<Header, ProtoError> {
-    serde_json::from_slice(bytes).map_err(|err| ProtoError::HeaderDecode(err.to_string()))
+    let header: Header =
+        serde_json::from_slice(bytes).map_err(|err| ProtoError::HeaderDecode(err.to_string()))?;
+    if let Some(tenant) = header.tenant.as_deref().filter(|tenant| !valid_tenant(tenant)) {
+        return Err(ProtoError::HeaderDecode(format!("invalid tenant: {tenant:?}")));
+    }
+    Ok(header)
+}
+
+/// [`Header::checksum`] value of `body`.
+pub fn body_checksum(body: &[u8]) -> String {
+    format!("{CRC32_PREFIX}{:08x}", crc32fast::hash(body))
+}
+
+/// Running check of a body against its [`Header::checksum`], for bodies
+/// read in pieces.
+#[derive(Debug, Clone)]
+pub struct BodyChecksum {
+    expected: u32,
+    hasher: crc32fast::Hasher
+}
+
+impl BodyChecksum {
+    /// `None` for a frame without a checksum.
+    pub fn from_header(checksum: Option<&str>) -> Result<Option<Self>, ProtoError> {
+        let Some(checksum) = checksum else {
+            return Ok(None);
+        };
+        let expected = checksum
+            .strip_prefix(CRC32_PREFIX)
+            .filter(|hex| hex.len() == 8)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| ProtoError::UnsupportedChecksum(checksum.to_string()))?;
+        Ok(Some(Self { expected, hasher: crc32fast::Hasher::new() }))
+    }
+
+    pub fn update(
+        &mut self,
+        bytes: &[u8]
+    ) {
+        self.hasher.update(bytes);
+    }
+
+    /// Compares the bytes seen so far with the expected checksum.
+    pub fn verify(&self) -> Result<(), ProtoError> {
+        let actual = self.hasher.clone().finalize();
+        if actual != self.expected {
+            return Err(ProtoError::ChecksumMismatch {
+                expected: format!("{CRC32_PREFIX}{:08x}", self.expected),
+                actual: format!("{CRC32_PREFIX}{actual:08x}")
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Checks `body` against the `checksum` of the undecoded `header`; frames
+/// without one, or whose header does not decode, pass.
+pub fn verify_frame_checksum(
+    header: &[u8],
+    body: &[u8]
+) -> Result<(), ProtoError> {
+    #[derive(Deserialize)]
+    struct ChecksumField {
+        #[serde(default)]
+        checksum: Option<String>
+    }
+
+    let Ok(ChecksumField { checksum }) = serde_json::from_slice(header) else {
+        return Ok(());
+    };
+    let Some(mut checksum) = BodyChecksum::from_header(checksum.as_deref())? else {
+        return Ok(());
+    };
+    checksum.update(body);
+    checksum.verify()
 }
 
 pub fn write_frame_sync<W: Write>(
@@ -83,6 +227,27 @@ pub async fn read_frame_async<R: AsyncRead + Unpin>(
     max_header_len: u32,
     max_body_len: u64
 ) -> Result<(Vec<u8>, Vec<u8>), ProtoError> {
+    let (header, mut body_reader) =
+        read_frame_streaming_async(reader, max_header_len, max_body_len).await?;
+
+    let mut body = vec![0_u8; body_reader.limit() as usize];
+    body_reader.read_exact(&mut body).await?;
+    verify_frame_checksum(&header, &body)?;
+
+    Ok((header, body))
+}
+
+/// Reads the frame header and hands the body to the caller as a reader
+/// limited to its length, so large payloads can be streamed instead of
+/// buffered. The body must be read to its end (`limit() == 0`) before the
+/// next frame; the reader ends early if the peer disconnects mid-body.
+/// The checksum is not verified; see [`ChecksummedBody`].
+#[cfg(feature = "tokio")]
+pub async fn read_frame_streaming_async<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_header_len: u32,
+    max_body_len: u64
+) -> Result<(Vec<u8>, Take<&mut R>), ProtoError> {
     let mut magic = [0_u8; 4];
     reader.read_exact(&mut magic).await?;
     if magic != MAGIC {
@@ -106,21 +271,226 @@ pub async fn read_frame_async<R: AsyncRead + Unpin>(
     let mut header = vec![0_u8; header_len as usize];
     reader.read_exact(&mut header).await?;
 
-    let mut body = vec![0_u8; body_len as usize];
-    reader.read_exact(&mut body).await?;
+    Ok((header, reader.take(body_len)))
+}
+
+/// Body reader that verifies a [`BodyChecksum`] once all `len` bytes went
+/// through: the read that reaches the end fails with an
+/// [`io::ErrorKind::InvalidData`](std::io::ErrorKind::InvalidData) error
+/// wrapping [`ProtoError::ChecksumMismatch`] instead. A body that ends
+/// early is left to the caller's length check.
+#[cfg(feature = "tokio")]
+pub struct ChecksummedBody<R> {
+    inner: R,
+    checksum: Option<BodyChecksum>,
+    left: u64,
+    mismatched: bool
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + Unpin> ChecksummedBody<R> {
+    /// `checksum` may already have seen a head of the body, which `len`
+    /// then excludes.
+    pub fn new(
+        inner: R,
+        checksum: Option<BodyChecksum>,
+        len: u64
+    ) -> Self {
+        Self { inner, checksum, left: len, mismatched: false }
+    }
+
+    /// The [`ProtoError::ChecksumMismatch`] a read failed with, if any.
+    pub fn mismatch(&self) -> Option<ProtoError> {
+        self.checksum.as_ref().filter(|_| self.mismatched)?.verify().err()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + Unpin> AsyncRead for ChecksummedBody<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        let Some(checksum) = &mut this.checksum else {
+            return Poll::Ready(Ok(()));
+        };
+        let read = &buf.filled()[before..];
+        checksum.update(read);
+        this.left = this.left.saturating_sub(read.len() as u64);
+        if this.left == 0
+            && let Err(err) = checksum.verify()
+        {
+            this.mismatched = true;
+            return Poll::Ready(Err(std::io::Error::new(std::io::ErrorKind::InvalidData, err)));
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// What a peer sent back for a frame: an [`ACK`], or a response frame for
+/// request kinds such as `query` and `status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    Ack,
+    Frame { header: Vec<u8>, body: Vec<u8> }
+}
 
+pub fn read_frame_sync<R: Read>(
+    reader: &mut R,
+    max_header_len: u32,
+    max_body_len: u64
+) -> Result<(Vec<u8>, Vec<u8>), ProtoError> {
+    let mut magic = [0_u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(ProtoError::InvalidMagic);
+    }
+    read_frame_rest_sync(reader, max_header_len, max_body_len)
+}
+
+/// Reads an [`ACK`], a [`TOO_LARGE`] rejection or a response frame.
+pub fn read_reply_sync<R: Read>(
+    reader: &mut R,
+    max_header_len: u32,
+    max_body_len: u64
+) -> Result<Reply, ProtoError> {
+    let mut head = [0_u8; 3];
+    reader.read_exact(&mut head)?;
+    if head != MAGIC[..3] {
+        return check_ack(&head).map(|()| Reply::Ack);
+    }
+    let mut last = [0_u8; 1];
+    reader.read_exact(&mut last)?;
+    if last[0] != MAGIC[3] {
+        return Err(ProtoError::InvalidMagic);
+    }
+    let (header, body) = read_frame_rest_sync(reader, max_header_len, max_body_len)?;
+    Ok(Reply::Frame { header, body })
+}
+
+/// Lengths, header and body of a frame whose magic was already read.
+fn read_frame_rest_sync<R: Read>(
+    reader: &mut R,
+    max_header_len: u32,
+    max_body_len: u64
+) -> Result<(Vec<u8>, Vec<u8>), ProtoError> {
+    let mut header_len_buf = [0_u8; 4];
+    reader.read_exact(&mut header_len_buf)?;
+    let header_len = u32::from_be_bytes(header_len_buf);
+    if header_len > max_header_len {
+        return Err(ProtoError::HeaderTooLarge(header_len));
+    }
+
+    let mut body_len_buf = [0_u8; 8];
+    reader.read_exact(&mut body_len_buf)?;
+    let body_len = u64::from_be_bytes(body_len_buf);
+    if body_len > max_body_len {
+        return Err(ProtoError::BodyTooLarge(body_len));
+    }
+
+    let mut header = vec![0_u8; header_len as usize];
+    reader.read_exact(&mut header)?;
+    let mut body = vec![0_u8; body_len as usize];
+    reader.read_exact(&mut body)?;
+    verify_frame_checksum(&header, &body)?;
     Ok((header, body))
 }
 
 pub fn read_ack_sync<R: Read>(reader: &mut R) -> Result<(), ProtoError> {
     let mut ack = [0_u8; 3];
     reader.read_exact(&mut ack)?;
-    if ack == *ACK { Ok(()) } else { Err(ProtoError::InvalidMagic) }
+    check_ack(&ack)
 }
 
 #[cfg(feature = "tokio")]
 pub async fn read_ack_async<R: AsyncRead + Unpin>(reader: &mut R) -> Result<(), ProtoError> {
     let mut ack = [0_u8; 3];
     reader.read_exact(&mut ack).await?;
-    if ack == *ACK { Ok(()) } else { Err(ProtoError::InvalidMagic) }
+    check_ack(&ack)
+}
+
+fn check_ack(ack: &[u8; 3]) -> Result<(), ProtoError> {
+    match ack {
+        ack if ack == ACK => Ok(()),
+        ack if ack == TOO_LARGE => Err(ProtoError::RejectedTooLarge),
+        ack if ack == BAD_CHECKSUM => Err(ProtoError::RejectedChecksum),
+        _ => Err(ProtoError::InvalidMagic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Header, ProtoError, decode_header_json, encode_header_json, read_ack_sync, read_frame_sync,
+        valid_tenant, write_frame_sync
+    };
+
+    fn header() -> Header {
+        decode_header_json(br#"{"from":"a","to":"b"}"#).unwrap()
+    }
+
+    fn frame(
+        header: &Header,
+        body: &[u8]
+    ) -> Vec<u8> {
+        let mut frame = Vec::new();
+        write_frame_sync(&mut frame, &encode_header_json(header).unwrap(), body).unwrap();
+        frame
+    }
+
+    #[test]
+    fn checksummed_frames_are_verified_and_plain_frames_pass() {
+        let body = b"Subject: bounce\r\n\r\nbody";
+        let plain = frame(&header(), body);
+        assert!(!String::from_utf8_lossy(&plain).contains("checksum"));
+        assert_eq!(read_frame_sync(&mut plain.as_slice(), 1024, 1024).unwrap().1, body);
+
+        let header = header().with_checksum(body);
+        assert_eq!(header.checksum.as_deref(), Some("crc32:78df7c4d"));
+        let checksummed = frame(&header, body);
+        assert_eq!(read_frame_sync(&mut checksummed.as_slice(), 1024, 1024).unwrap().1, body);
+
+        let mut corrupted = checksummed.clone();
+        *corrupted.last_mut().unwrap() ^= 0x20;
+        assert!(matches!(
+            read_frame_sync(&mut corrupted.as_slice(), 1024, 1024),
+            Err(ProtoError::ChecksumMismatch { .. })
+        ));
+
+        let unsupported = frame(&Header { checksum: Some("md5:00".to_string()), ..header }, body);
+        assert!(matches!(
+            read_frame_sync(&mut unsupported.as_slice(), 1024, 1024),
+            Err(ProtoError::UnsupportedChecksum(_))
+        ));
+        assert!(matches!(
+            read_ack_sync(&mut b"CK\n".as_slice()),
+            Err(ProtoError::RejectedChecksum)
+        ));
+    }
+
+    #[test]
+    fn tenant_is_optional_in_the_header() {
+        let header = decode_header_json(br#"{"from":"a","to":"b"}"#).unwrap();
+        assert_eq!(header.tenant, None);
+        let encoded = encode_header_json(&header).unwrap();
+        assert!(!String::from_utf8(encoded).unwrap().contains("tenant"));
+
+        let header = Header { tenant: Some("shop-eu".to_string()), ..header };
+        let decoded = decode_header_json(&encode_header_json(&header).unwrap()).unwrap();
+        assert_eq!(decoded.tenant.as_deref(), Some("shop-eu"));
+    }
+
+    #[test]
+    fn tenant_names_are_restricted() {
+        assert!(valid_tenant("shop_eu-2"));
+        assert!(!valid_tenant(""));
+        assert!(!valid_tenant("shop.eu"));
+        assert!(!valid_tenant("../x"));
+        assert!(!valid_tenant(&"a".repeat(65)));
+        assert!(decode_header_json(br#"{"from":"a","to":"b","tenant":"../x"}"#).is_err());
+    }
 }
@@ -0,0 +1,190 @@
+//! Request/response bodies for `kind=query` and `kind=query_status` frames.
+//!
+//! A client sends one `query` frame whose body is a JSON [`QueryRequest`].
+//! Instead of the plain `OK\n` ACK, the server answers with a single frame of
+//! kind [`QUERY_RESPONSE_KIND`] whose body is a JSON [`QueryResponse`].
+//!
+//! Sending applications that only need message states use `query_status`
+//! instead: the body is a JSON [`StatusQuery`] naming up to
+//! [`MAX_STATUS_QUERY_HASHES`] hashes, answered by one
+//! [`QUERY_STATUS_RESPONSE_KIND`] frame holding a [`StatusQueryResponse`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::heartbeat::QueueMapOccupancy;
+
+pub const QUERY_KIND: &str = "query";
+pub const QUERY_RESPONSE_KIND: &str = "query_response";
+pub const QUERY_STATUS_KIND: &str = "query_status";
+pub const QUERY_STATUS_RESPONSE_KIND: &str = "query_status_response";
+/// Most hashes one `query_status` frame may name.
+pub const MAX_STATUS_QUERY_HASHES: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum QueryRequest {
+    /// Latest known state of one message hash.
+    Status { hash: String },
+    /// Bounces recorded within the last `since_secs` seconds, newest first.
+    RecentBounces { since_secs: u64, limit: u32 },
+    /// Spool and database counters.
+    Stats,
+    /// Per-`source` frame counters, e.g. to spot observers that stopped reporting.
+    Sources,
+    /// Decodes and applies quarantined `observer_event` bodies again.
+    ReplayQuarantine,
+    /// Latest source transitions (registered, taken_over, connected,
+    /// disconnected, expired, recovered), newest first.
+    SourceEvents { limit: u32 }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum QueryResponse {
+    Status(MessageState),
+    RecentBounces { bounces: Vec<BounceRecord> },
+    Stats(ServerStats),
+    Sources { sources: Vec<SourceStats> },
+    ReplayQuarantine(QuarantineReplay),
+    SourceEvents { events: Vec<SourceEvent> },
+    Error { message: String }
+}
+
+/// Body of a `query_status` frame.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusQuery {
+    pub hashes: Vec<String>
+}
+
+/// Body of a `query_status_response` frame.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatusQueryResponse {
+    /// One state per requested hash, in request order. A hash the server
+    /// knows nothing about has no `message_id` and no `bounce`.
+    #[serde(default)]
+    pub messages: Vec<MessageState>,
+    /// Why the query was not answered; `messages` is empty then.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageState {
+    pub hash: String,
+    /// `mail_messages.id`, `None` when the hash is not a local message.
+    pub message_id: Option<u32>,
+    /// `mail_messages.status` (7 success, 3 pending, -2 suspended, -7 failed).
+    pub mail_status: Option<i32>,
+    pub updated_at_unix: Option<i64>,
+    /// Latest bounce row (`mail_message_bounces` or orphan `mail_bounces`).
+    pub bounce: Option<BounceRecord>,
+    /// Every outcome applied to the local message, oldest first
+    /// (`mail_message_bounce_events`).
+    #[serde(default)]
+    pub history: Vec<BounceEvent>
+}
+
+/// One row of `mail_message_bounce_events`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BounceEvent {
+    pub action: Option<String>,
+    pub status_code: String,
+    pub description: Option<String>,
+    pub occurred_at_unix: i64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BounceRecord {
+    pub hash: String,
+    /// True for `mail_message_bounces` rows, false for orphan `mail_bounces` rows.
+    pub tracked: bool,
+    pub recipient: Option<String>,
+    pub action: Option<String>,
+    pub status_code: String,
+    pub description: Option<String>,
+    /// Short human-readable reason (`mailbox full`, `user unknown`, ...).
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Normalized category: `hard`, `soft`, `block`, `full` or `spam`.
+    #[serde(default)]
+    pub category: Option<String>,
+    /// When the current diagnostics were first recorded.
+    pub created_at_unix: i64,
+    /// Latest repeat of the same diagnostics.
+    #[serde(default)]
+    pub last_seen_unix: Option<i64>,
+    /// How often the same diagnostics were recorded in a row.
+    #[serde(default)]
+    pub occurrence_count: Option<u32>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerStats {
+    pub uptime_secs: u64,
+    pub spool_incoming: u64,
+    pub spool_processing: u64,
+    pub spool_done: u64,
+    pub spool_failed: u64,
+    pub bounces_last_24h: i64,
+    /// `None` while the suppression list is disabled.
+    pub suppressed: Option<i64>,
+    /// TCP connections accepted since start.
+    #[serde(default)]
+    pub connections_accepted: u64,
+    /// TCP peers dropped because they are outside `listen_allow`.
+    #[serde(default)]
+    pub connections_rejected: u64,
+    /// 10s windows in which accepted connections crossed the storm threshold.
+    #[serde(default)]
+    pub connection_storms: u64,
+    #[serde(default)]
+    pub peak_connections_per_window: u64
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuarantineReplay {
+    /// Events applied and removed from the quarantine.
+    pub replayed: u64,
+    /// Bodies that still do not decode; they stay quarantined.
+    pub still_invalid: u64,
+    /// Decoded events whose database write failed; they stay quarantined.
+    pub apply_failed: u64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceStats {
+    /// Frame `source` header (falls back to `from` when absent).
+    pub source: String,
+    /// True once the source sent a `register` frame.
+    pub registered: bool,
+    /// Open client connections carrying frames of this source.
+    #[serde(default)]
+    pub connections: u32,
+    /// `observer_event` and mail frames accepted.
+    pub events: u64,
+    pub heartbeats: u64,
+    /// Frames whose body could not be decoded.
+    pub parse_failures: u64,
+    /// `delivered` events ACKed without a write (`ignore_delivered_events`).
+    #[serde(default)]
+    pub ignored_deliveries: u64,
+    pub last_heartbeat_secs_ago: Option<u64>,
+    pub last_event_secs_ago: Option<u64>,
+    /// Registered but silent beyond `sources.silent_after_secs`.
+    pub silent: bool,
+    /// From the latest heartbeat of agents that report it.
+    #[serde(default)]
+    pub queue_map: Option<QueueMapOccupancy>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceEvent {
+    pub source: String,
+    /// `registered`, `taken_over`, `connected`, `disconnected`, `expired` or
+    /// `recovered`.
+    pub transition: String,
+    /// E.g. `silent_secs=320` for `expired` and `recovered`, the old and new
+    /// peer for `taken_over`.
+    pub detail: Option<String>,
+    pub at_unix: u64
+}
@@ -0,0 +1,239 @@
+//! Response body for `kind=status` frames.
+//!
+//! A client sends one `status` frame with an empty body. Instead of the plain
+//! `OK\n` ACK, the server answers with a single frame of kind
+//! [`STATUS_RESPONSE_KIND`] whose body is a JSON [`ServerStatus`]: a point in
+//! time snapshot meant for schedulers and dashboards, cheap enough to poll.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+pub const STATUS_KIND: &str = "status";
+pub const STATUS_RESPONSE_KIND: &str = "status_response";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerStatus {
+    pub uptime_secs: u64,
+    /// Wall clock of the snapshot.
+    pub generated_at_unix: u64,
+    pub spool: SpoolStatus,
+    /// `None` until the dispatcher queues are created.
+    pub queue: Option<QueueStatus>,
+    pub workers: WorkerStatus,
+    /// `None` while IMAP polling is disabled.
+    pub imap: Option<ImapStatus>,
+    pub db: DbPoolStatus,
+    /// Spool file processing latency since start.
+    #[serde(default)]
+    pub processing: ProcessingStatus,
+    /// Traffic since start per tenant; frames without one are not counted.
+    #[serde(default)]
+    pub tenants: BTreeMap<String, TenantStatus>
+}
+
+/// Files per spool directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpoolStatus {
+    pub incoming: u64,
+    pub processing: u64,
+    pub done: u64,
+    pub failed: u64,
+    /// Bounces in `processing/` waiting for their message row or a database
+    /// retry.
+    pub deferred: u64,
+    /// Undecodable `observer_event` bodies in `quarantine/`.
+    #[serde(default)]
+    pub quarantined: u64
+}
+
+/// Paths queued between the watcher/scanner and the workers, per lane.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueStatus {
+    pub high: u64,
+    pub high_capacity: u64,
+    pub low: u64,
+    pub low_capacity: u64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerStatus {
+    pub total: u64,
+    /// Workers processing a spool file right now.
+    pub busy: u64
+}
+
+/// Totals over every polled mailbox; `last_poll` is the latest of any.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImapStatus {
+    pub polls: u64,
+    pub failed_polls: u64,
+    /// `None` before the first poll finished.
+    pub last_poll: Option<ImapPollStatus>,
+    #[serde(default)]
+    pub mailboxes: Vec<ImapMailboxStatus>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImapMailboxStatus {
+    /// `imap[].name`, or `user@host`.
+    pub account: String,
+    pub mailbox: String,
+    pub polls: u64,
+    pub failed_polls: u64,
+    pub last_poll: Option<ImapPollStatus>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImapPollStatus {
+    #[serde(default)]
+    pub account: String,
+    #[serde(default)]
+    pub mailbox: String,
+    pub finished_at_unix: u64,
+    pub duration_ms: u64,
+    /// Error of the poll when it failed as a whole.
+    pub error: Option<String>,
+    pub selected: u64,
+    pub parsed_ok: u64,
+    pub parse_failures: u64,
+    pub fetch_failures: u64,
+    pub db_failures: u64
+}
+
+/// Latency of the spool files the workers processed since start.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProcessingStatus {
+    /// `read`, `parse`, `upsert`, `finalize` and `total`, in that order.
+    pub stages: Vec<StageLatency>,
+    /// `total` latency per worker.
+    pub workers: Vec<WorkerLatency>,
+    /// Files slower than `dispatcher.slow_file_threshold`.
+    pub slow_files: u64,
+    /// Files moved to `failed/`, by reason (`missing_hash`, `db_error`, ...).
+    #[serde(default)]
+    pub failed_by_reason: BTreeMap<String, u64>
+}
+
+/// Counters of one tenant since start.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantStatus {
+    /// Bounce mails spooled from frames of the tenant.
+    pub mails: u64,
+    /// Observer events applied.
+    pub observer_events: u64,
+    /// Spool files of the tenant moved to `done/`.
+    pub processed: u64,
+    /// Spool files of the tenant moved to `failed/`.
+    pub failed: u64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageLatency {
+    pub stage: String,
+    #[serde(flatten)]
+    pub latency: LatencyHistogram
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerLatency {
+    pub worker: u64,
+    #[serde(flatten)]
+    pub latency: LatencyHistogram
+}
+
+/// Millisecond histogram: `counts[i]` observations fell at or below
+/// `bounds_ms[i]` and above the previous bound; the extra last count holds
+/// those above every bound.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    pub bounds_ms: Vec<u64>,
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum_ms: u64,
+    pub max_ms: u64
+}
+
+impl LatencyHistogram {
+    pub fn new(bounds_ms: &[u64]) -> Self {
+        Self {
+            bounds_ms: bounds_ms.to_vec(),
+            counts: vec![0; bounds_ms.len() + 1],
+            ..Self::default()
+        }
+    }
+
+    pub fn observe(
+        &mut self,
+        ms: u64
+    ) {
+        let bucket = self.bounds_ms.partition_point(|bound| *bound < ms);
+        if let Some(count) = self.counts.get_mut(bucket) {
+            *count += 1;
+        }
+        self.count += 1;
+        self.sum_ms = self.sum_ms.saturating_add(ms);
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile (`max_ms` past the
+    /// last bound); `None` before the first observation.
+    pub fn quantile_ms(
+        &self,
+        q: f64
+    ) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((self.count as f64) * q.clamp(0.0, 1.0)).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(
+                    self.bounds_ms
+                        .get(bucket)
+                        .map_or(self.max_ms, |bound| (*bound).min(self.max_ms))
+                );
+            }
+        }
+        Some(self.max_ms)
+    }
+
+    pub fn mean_ms(&self) -> Option<u64> {
+        self.sum_ms.checked_div(self.count)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DbPoolStatus {
+    pub backend: String,
+    /// Open connections, idle ones included.
+    pub connections: u32,
+    pub idle: u32,
+    pub max_connections: u32,
+    /// Writes fail fast and spool workers wait until the database answers.
+    #[serde(default)]
+    pub breaker_open: bool
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LatencyHistogram;
+
+    #[test]
+    fn histogram_buckets_and_quantiles() {
+        let mut histogram = LatencyHistogram::new(&[10, 100]);
+        assert_eq!(histogram.quantile_ms(0.5), None);
+        for ms in [3, 10, 40, 90, 250] {
+            histogram.observe(ms);
+        }
+
+        assert_eq!(histogram.counts, [2, 2, 1]);
+        assert_eq!((histogram.count, histogram.sum_ms, histogram.max_ms), (5, 393, 250));
+        assert_eq!(histogram.quantile_ms(0.4), Some(10));
+        assert_eq!(histogram.quantile_ms(0.5), Some(100));
+        assert_eq!(histogram.quantile_ms(0.99), Some(250));
+        assert_eq!(histogram.mean_ms(), Some(78));
+    }
+}
@@ -0,0 +1,103 @@
+//! VERP return paths that carry the message hash, e.g.
+//! `bounce-{hash}@example.com`.
+//!
+//! The sending application encodes each message's hash into its envelope
+//! sender; the bounce comes back to that address and the server decodes the
+//! hash from the envelope recipient, no matter what the remote MTA did to
+//! the headers of the original message.
+
+use thiserror::Error;
+
+pub const HASH_PLACEHOLDER: &str = "{hash}";
+
+#[derive(Debug, Error)]
+#[error("invalid VERP template `{0}`: expected one `{{hash}}` in the local part and an `@domain`")]
+pub struct VerpTemplateError(String);
+
+/// A VERP address template with a single `{hash}` placeholder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerpTemplate {
+    prefix: String,
+    suffix: String
+}
+
+impl VerpTemplate {
+    pub fn parse(template: &str) -> Result<Self, VerpTemplateError> {
+        let template = template.trim();
+        let invalid = || VerpTemplateError(template.to_string());
+        let (prefix, suffix) = template.split_once(HASH_PLACEHOLDER).ok_or_else(invalid)?;
+        if suffix.contains(HASH_PLACEHOLDER) || prefix.contains('@') {
+            return Err(invalid());
+        }
+        match suffix.split_once('@') {
+            Some((_, domain)) if !domain.is_empty() && !domain.contains('@') => {}
+            _ => return Err(invalid())
+        }
+
+        Ok(Self { prefix: prefix.to_string(), suffix: suffix.to_string() })
+    }
+
+    /// Return path for `hash`.
+    pub fn encode(
+        &self,
+        hash: &str
+    ) -> String {
+        format!("{}{hash}{}", self.prefix, self.suffix)
+    }
+
+    /// Hash encoded in `address` (angle brackets allowed), or `None` when the
+    /// address does not match the template. Matching ignores ASCII case.
+    pub fn decode<'a>(
+        &self,
+        address: &'a str
+    ) -> Option<&'a str> {
+        let address = address.trim().trim_start_matches('<').trim_end_matches('>').trim();
+        let rest = strip_prefix_ignore_case(address, &self.prefix)?;
+        let hash = strip_suffix_ignore_case(rest, &self.suffix)?;
+        if hash.is_empty() || hash.contains('@') { None } else { Some(hash) }
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(
+    value: &'a str,
+    prefix: &str
+) -> Option<&'a str> {
+    let head = value.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &value[prefix.len()..])
+}
+
+fn strip_suffix_ignore_case<'a>(
+    value: &'a str,
+    suffix: &str
+) -> Option<&'a str> {
+    let split = value.len().checked_sub(suffix.len())?;
+    let tail = value.get(split..)?;
+    tail.eq_ignore_ascii_case(suffix).then(|| &value[..split])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VerpTemplate;
+
+    #[test]
+    fn encodes_and_decodes_hash() {
+        let template = VerpTemplate::parse("bounce-{hash}@example.com").unwrap();
+        assert_eq!(template.encode("a1b2"), "bounce-a1b2@example.com");
+        assert_eq!(template.decode("<Bounce-a1b2@Example.COM>"), Some("a1b2"));
+        assert_eq!(template.decode("bounce-@example.com"), None);
+        assert_eq!(template.decode("bounce-a1b2@other.example"), None);
+        assert_eq!(template.decode("noreply@example.com"), None);
+
+        let suffixed = VerpTemplate::parse("b+{hash}.v1@bounces.example.com").unwrap();
+        assert_eq!(suffixed.decode("b+ff00.v1@bounces.example.com"), Some("ff00"));
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for template in
+            ["bounce@example.com", "{hash}-{hash}@example.com", "x@{hash}.com", "{hash}@"]
+        {
+            assert!(VerpTemplate::parse(template).is_err(), "{template}");
+        }
+    }
+}
@@ -1,12 +0,0 @@
-mod database;
-mod dispatcher;
-mod imap;
-mod parser;
-mod server;
-mod spool;
-
-pub use database::{Database, UpsertBounceOutcome};
-pub use dispatcher::{spawn_notify_watcher, spawn_periodic_scan, spawn_worker_dispatcher};
-pub use imap::run_imap_poll_loop;
-pub use server::run_tcp_server;
-pub use spool::Spool;
@@ -1,55 +1,65 @@
-mod app;
-mod config;
-mod core;
-
-use core::{
-    Database, Spool, run_imap_poll_loop, run_tcp_server, spawn_notify_watcher, spawn_periodic_scan,
-    spawn_worker_dispatcher
-};
-use std::sync::Arc;
-
-use anyhow::{Context, Result};
-use app::AppState;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+use bouncer_core::Config;
 use bouncer_helpers::{logging, shutdown};
-use config::Config;
-use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
-use tracing::info;
+
+const CHECK_USAGE: &str = "usage: bouncer-server check <dir> [config-path]";
+const CHECK_CONFIG_USAGE: &str = "usage: bouncer-server --check-config [config-path]";
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
-    logging::init_logging(
-        "bouncer_server=info,notify=warn,tokio=warn",
+    let _logging = logging::init_logging(
+        "bouncer_server=info,bouncer_core=info,notify=warn,tokio=warn",
         "BOUNCER_LOG",
         "bouncer-server"
     );
 
-    let config = Config::load().context("failed to load configuration")?;
-    let spool = Arc::new(Spool::new(config.spool.clone()));
-    spool.ensure_dirs().await?;
+    let mut args = std::env::args().skip(1).peekable();
+    if matches!(args.peek().map(String::as_str), Some("check" | "--dry-run")) {
+        args.next();
+        return check(args);
+    }
+    if matches!(args.peek().map(String::as_str), Some("--check-config")) {
+        args.next();
+        return check_config(args);
+    }
 
-    let db = Arc::new(
-        Database::connect(&config.database_url).await.context("failed to connect database")?
-    );
+    let config = Config::load().context("failed to load configuration")?;
+    let shutdown_token = CancellationToken::new();
+    tokio::spawn(shutdown::listen_shutdown(shutdown_token.clone()));
 
-    let state = AppState { spool, db, shutdown: CancellationToken::new() };
+    bouncer_core::run(config, shutdown_token).await
+}
 
-    info!("server starting: listen={}, spool={}", config.listen, config.spool.display());
+/// `bouncer-server --check-config [config-path]`: loads, normalizes and
+/// validates the config without opening anything, then prints it.
+fn check_config(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let config_path = args.next().map(PathBuf::from);
+    if let Some(arg) = args.next() {
+        bail!("too many arguments: {arg} ({CHECK_CONFIG_USAGE})");
+    }
 
-    let process_queue_capacity =
-        config.worker_concurrency.max(1).saturating_mul(config.process_queue_per_worker);
-    let (process_tx, process_rx) = mpsc::channel(process_queue_capacity);
-    info!("process queue configured: capacity={}", process_queue_capacity);
+    let config = Config::load_from(config_path).context("failed to load configuration")?;
+    print!("{}", config.dump()?);
+    Ok(())
+}
 
-    tokio::spawn(shutdown::listen_shutdown(state.shutdown.clone()));
-    tokio::spawn(spawn_notify_watcher(state.clone(), process_tx.clone()));
-    tokio::spawn(spawn_periodic_scan(state.clone(), process_tx.clone(), config.incoming_scan_secs));
-    tokio::spawn(spawn_worker_dispatcher(state.clone(), process_rx, config.worker_concurrency));
-    if let Some(imap) = config.imap.clone() {
-        tokio::spawn(run_imap_poll_loop(imap, state.db.clone(), state.shutdown.clone()));
-    } else {
-        info!("imap fallback disabled (imap config missing)");
+/// `bouncer-server check <dir> [config-path]`: one JSON report per file on
+/// stdout, totals on stderr; the database and spool are never opened.
+fn check(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let dir = PathBuf::from(args.next().context(CHECK_USAGE)?);
+    let config_path = args.next().map(PathBuf::from);
+    if let Some(arg) = args.next() {
+        bail!("too many arguments: {arg} ({CHECK_USAGE})");
     }
 
-    run_tcp_server(&config.listen, state).await
+    let config = Config::load_from(config_path).context("failed to load configuration")?;
+    let summary = bouncer_core::check_dir(&config, &dir, &mut std::io::stdout().lock())?;
+    eprintln!(
+        "bouncer-server check: files={}, parsed={}, failed={}",
+        summary.files, summary.parsed, summary.failed
+    );
+    Ok(())
 }
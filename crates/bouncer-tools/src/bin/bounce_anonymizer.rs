@@ -0,0 +1,398 @@
+//! `bounce_anonymizer`: rewrites real bounces into fixtures that can be
+//! shared, e.g. under `tests/bounces/corpus/`.
+//!
+//! Email addresses, hostnames and IP addresses are replaced by stand-ins
+//! derived from a salted hash, so one value maps to the same stand-in in
+//! every header and every file anonymized with the same `--salt`: the
+//! `Final-Recipient` still matches the `To:` of the returned headers and
+//! `In-Reply-To` still names the `Message-ID` it answers. Message hashes
+//! (hex local parts of 16 or more digits), role addresses (`postmaster`,
+//! `mailer-daemon`, ...) and the hosts of large mailbox providers are kept,
+//! since the parser and the fixtures depend on them.
+//!
+//! Internationalized addresses and hosts (`jörg@bücher.de`) are matched as
+//! raw UTF-8 too. Only the raw text is rewritten: base64 parts are not decoded, so the
+//! tool warns about them and they need a look by hand.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use anyhow::{Context, Result, bail};
+use regex::bytes::{Captures, Regex};
+use sha2::{Digest, Sha256};
+
+/// Hosts kept as they are, with their subdomains; `--keep-domain` adds more.
+const KEPT_DOMAINS: [&str; 14] = [
+    "example",
+    "example.com",
+    "example.net",
+    "example.org",
+    "invalid",
+    "gmail.com",
+    "googlemail.com",
+    "google.com",
+    "outlook.com",
+    "hotmail.com",
+    "live.com",
+    "yahoo.com",
+    "icloud.com",
+    "gmx.net"
+];
+
+/// Local parts that name a role rather than a person.
+const ROLE_LOCAL_PARTS: [&str; 7] =
+    ["mailer-daemon", "postmaster", "abuse", "noreply", "no-reply", "bounce", "bounces"];
+
+/// Top-level domains, next to every two-letter and non-ASCII one, a bare
+/// hostname must end in; other dotted words (`RESOLVER.ADR.RecipientNotFound`,
+/// `notification.eml`) are left alone.
+const HOST_TLDS: [&str; 16] = [
+    "com", "net", "org", "edu", "gov", "mil", "int", "info", "biz", "app", "dev", "cloud", "email",
+    "online", "site", "example"
+];
+
+/// Letters and digits of any script, so EAI addresses are caught as well.
+/// Label lengths are not bounded: counted Unicode classes blow the regex
+/// size limit, and a too-long label is no reason to leave a host in place.
+const HOST: &str = r"(?:[\p{L}\p{N}](?:[\p{L}\p{N}-]*[\p{L}\p{N}])?\.)+\p{L}{2,}";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Counts {
+    addresses: usize,
+    hosts: usize,
+    ips: usize
+}
+
+struct Anonymizer {
+    salt: String,
+    kept_domains: Vec<String>,
+    pattern: Regex
+}
+
+impl Anonymizer {
+    fn new(
+        salt: &str,
+        extra_domains: &[String]
+    ) -> Self {
+        let pattern = format!(
+            r"(?P<address>(?P<local>[\p{{L}}\p{{N}}!#$%&'*+=^_`{{|}}~.-]+)@(?P<domain>{HOST}))|(?P<ipv4>\b(?:\d{{1,3}}\.){{3}}\d{{1,3}}\b)|(?P<ipv6>[0-9A-Fa-f]{{0,4}}(?::[0-9A-Fa-f]{{0,4}}){{2,7}})|(?P<host>\b{HOST}\b)"
+        );
+        Self {
+            salt: salt.to_string(),
+            kept_domains: KEPT_DOMAINS
+                .iter()
+                .map(|domain| domain.to_string())
+                .chain(extra_domains.iter().map(|domain| domain.to_lowercase()))
+                .collect(),
+            pattern: Regex::new(&pattern).expect("valid anonymizer pattern")
+        }
+    }
+
+    fn rewrite(
+        &self,
+        input: &[u8],
+        counts: &mut Counts
+    ) -> Vec<u8> {
+        self.pattern
+            .replace_all(input, |caps: &Captures<'_>| {
+                let text = |name: &str| {
+                    caps.name(name).map(|m| String::from_utf8_lossy(m.as_bytes()).into_owned())
+                };
+                let original = String::from_utf8_lossy(&caps[0]).into_owned();
+                let replaced = if let (Some(local), Some(domain)) = (text("local"), text("domain"))
+                {
+                    counts.addresses += 1;
+                    Some(format!("{}@{}", self.local_part(&local), self.host(&domain)))
+                } else if let Some(ip) = text("ipv4").and_then(|ip| ip.parse::<Ipv4Addr>().ok()) {
+                    self.ipv4(ip).inspect(|_| counts.ips += 1)
+                } else if let Some(ip) = text("ipv6").and_then(|ip| ip.parse::<Ipv6Addr>().ok()) {
+                    self.ipv6(ip).inspect(|_| counts.ips += 1)
+                } else if let Some(host) = text("host").filter(|host| is_hostname(host)) {
+                    let replaced = self.host(&host);
+                    (replaced != host).then(|| {
+                        counts.hosts += 1;
+                        replaced
+                    })
+                } else {
+                    None
+                };
+                replaced.unwrap_or(original).into_bytes()
+            })
+            .into_owned()
+    }
+
+    fn local_part(
+        &self,
+        local: &str
+    ) -> String {
+        let is_hash = local.len() >= 16 && local.bytes().all(|b| b.is_ascii_hexdigit());
+        if is_hash || ROLE_LOCAL_PARTS.iter().any(|role| role.eq_ignore_ascii_case(local)) {
+            return local.to_string();
+        }
+        format!("u{}", self.tag("local", local, 4))
+    }
+
+    /// `mx1.mail.customer.com` becomes `h<tag>.h<tag>.d<tag>.example`, each
+    /// label on its own, so hosts of one domain stay recognizable as such.
+    fn host(
+        &self,
+        host: &str
+    ) -> String {
+        let lower = host.to_lowercase();
+        if self
+            .kept_domains
+            .iter()
+            .any(|kept| lower == *kept || lower.ends_with(&format!(".{kept}")))
+        {
+            return host.to_string();
+        }
+        let labels: Vec<&str> = lower.split('.').collect();
+        let (subdomain, domain) = labels.split_at(labels.len().saturating_sub(2));
+        let mut out: Vec<String> =
+            subdomain.iter().map(|label| format!("h{}", self.tag("label", label, 2))).collect();
+        out.push(format!("d{}.example", self.tag("domain", &domain.join("."), 3)));
+        out.join(".")
+    }
+
+    /// Public addresses map into `10.0.0.0/8`; loopback, private and
+    /// documentation addresses are kept.
+    fn ipv4(
+        &self,
+        ip: Ipv4Addr
+    ) -> Option<String> {
+        let documentation =
+            matches!(ip.octets(), [192, 0, 2, _] | [198, 51, 100, _] | [203, 0, 113, _]);
+        if ip.is_loopback() || ip.is_private() || ip.is_unspecified() || documentation {
+            return None;
+        }
+        let [a, b, c, ..] = self.digest("ipv4", &ip.to_string());
+        Some(Ipv4Addr::new(10, a, b, c).to_string())
+    }
+
+    /// Addresses map into `2001:db8::/32`, which is kept like loopback.
+    fn ipv6(
+        &self,
+        ip: Ipv6Addr
+    ) -> Option<String> {
+        let segments = ip.segments();
+        if ip.is_loopback() || ip.is_unspecified() || segments[..2] == [0x2001, 0xdb8] {
+            return None;
+        }
+        let digest = self.digest("ipv6", &ip.to_string());
+        let word = |i: usize| u16::from_be_bytes([digest[i], digest[i + 1]]);
+        Some(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, word(0), word(2), word(4), word(6)).to_string())
+    }
+
+    /// First `bytes` of the digest as hex.
+    fn tag(
+        &self,
+        kind: &str,
+        value: &str,
+        bytes: usize
+    ) -> String {
+        self.digest(kind, value)[..bytes].iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    fn digest(
+        &self,
+        kind: &str,
+        value: &str
+    ) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for part in [self.salt.as_str(), kind, &value.to_lowercase()] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        hasher.finalize().into()
+    }
+}
+
+fn is_hostname(host: &str) -> bool {
+    let tld = host.rsplit('.').next().unwrap_or_default().to_lowercase();
+    tld.chars().count() == 2 || !tld.is_ascii() || HOST_TLDS.contains(&tld.as_str())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse(env::args().skip(1))?;
+    let anonymizer = Anonymizer::new(&args.salt, &args.keep_domains);
+    fs::create_dir_all(&args.output_dir)
+        .with_context(|| format!("failed to create {}", args.output_dir.display()))?;
+
+    for input in &args.inputs {
+        let output = output_path(input, &args.output_dir)?;
+        let raw = fs::read(input).with_context(|| format!("failed to read {}", input.display()))?;
+        let mut counts = Counts::default();
+        let anonymized = anonymizer.rewrite(&raw, &mut counts);
+        fs::write(&output, &anonymized)
+            .with_context(|| format!("failed to write {}", output.display()))?;
+        println!(
+            "anonymized: {} -> {}, addresses={}, hosts={}, ips={}",
+            input.display(),
+            output.display(),
+            counts.addresses,
+            counts.hosts,
+            counts.ips
+        );
+        let base64_parts = String::from_utf8_lossy(&raw)
+            .lines()
+            .filter(|line| {
+                line.to_ascii_lowercase().starts_with("content-transfer-encoding:")
+                    && line.to_ascii_lowercase().contains("base64")
+            })
+            .count();
+        if base64_parts > 0 {
+            eprintln!(
+                "warning: {} has {} base64 part(s) that were not rewritten; check them by hand",
+                output.display(),
+                base64_parts
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `<output_dir>/<file name>`; never the input itself.
+fn output_path(
+    input: &Path,
+    output_dir: &Path
+) -> Result<PathBuf> {
+    let name = input.file_name().with_context(|| format!("not a file: {}", input.display()))?;
+    let output = output_dir.join(name);
+    if fs::canonicalize(input).ok() == fs::canonicalize(&output).ok() {
+        bail!("refusing to overwrite the input {}; pick another --output-dir", input.display());
+    }
+    Ok(output)
+}
+
+#[derive(Debug)]
+struct Args {
+    salt: String,
+    keep_domains: Vec<String>,
+    output_dir: PathBuf,
+    inputs: Vec<PathBuf>
+}
+
+impl Args {
+    fn parse<I>(mut it: I) -> Result<Self>
+    where
+        I: Iterator<Item = String>
+    {
+        let mut salt = None;
+        let mut keep_domains = Vec::new();
+        let mut output_dir = PathBuf::from("tests/bounces/corpus");
+        let mut inputs = Vec::new();
+
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "--salt" => salt = Some(it.next().context("missing value for --salt")?),
+                "--keep-domain" => {
+                    keep_domains.push(it.next().context("missing value for --keep-domain")?);
+                }
+                "--output-dir" => {
+                    output_dir =
+                        PathBuf::from(it.next().context("missing value for --output-dir")?);
+                }
+                "-h" | "--help" => {
+                    print_usage();
+                    std::process::exit(0);
+                }
+                _ if arg.starts_with("--") => bail!("unknown argument: {arg}"),
+                _ => inputs.push(PathBuf::from(arg))
+            }
+        }
+
+        // Without a secret salt, common addresses could be recovered by
+        // hashing guesses.
+        let salt = salt.filter(|salt| !salt.is_empty()).context("missing --salt")?;
+        if inputs.is_empty() {
+            print_usage();
+            bail!("no .eml files given");
+        }
+        Ok(Self { salt, keep_domains, output_dir, inputs })
+    }
+}
+
+fn print_usage() {
+    eprintln!(
+        "usage: bounce_anonymizer --salt SALT [--keep-domain DOMAIN]... [--output-dir tests/bounces/corpus] FILE.eml..."
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Anonymizer, Counts};
+
+    fn rewrite(input: &str) -> (String, Counts) {
+        let mut counts = Counts::default();
+        let anonymizer = Anonymizer::new("test-salt", &["claviron.app".to_string()]);
+        let out = anonymizer.rewrite(input.as_bytes(), &mut counts);
+        (String::from_utf8(out).unwrap(), counts)
+    }
+
+    #[test]
+    fn rewrites_addresses_consistently_and_keeps_hashes() {
+        let (out, counts) = rewrite(concat!(
+            "To: Jane <Jane.Doe@Customer.org>\n",
+            "Message-ID: <0123456789abcdef0123456789abcdef@mail.customer.org>\n",
+            "Final-Recipient: rfc822; jane.doe@customer.org\n",
+            "From: MAILER-DAEMON@mx.customer.org\n",
+            "Return-Path: <noreply@claviron.app>\n",
+            "X-Forward: janedoe@gmail.com\n"
+        ));
+        let lines: Vec<&str> = out.lines().collect();
+        let address = lines[0].trim_start_matches("To: Jane <").trim_end_matches('>');
+        assert!(
+            !out.to_ascii_lowercase().contains("customer") && !out.contains("janedoe"),
+            "{out}"
+        );
+        assert_eq!(lines[2], format!("Final-Recipient: rfc822; {address}"));
+        let domain = address.split_once('@').unwrap().1;
+        assert!(domain.starts_with('d') && domain.ends_with(".example"), "{domain}");
+        assert!(lines[1].starts_with("Message-ID: <0123456789abcdef0123456789abcdef@h"), "{out}");
+        assert!(lines[1].ends_with(&format!(".{domain}>")), "{out}");
+        assert!(lines[3].starts_with("From: MAILER-DAEMON@h"), "{out}");
+        assert_eq!(lines[4], "Return-Path: <noreply@claviron.app>");
+        assert!(lines[5].starts_with("X-Forward: u") && lines[5].ends_with("@gmail.com"), "{out}");
+        assert_eq!(counts, Counts { addresses: 6, hosts: 0, ips: 0 });
+    }
+
+    #[test]
+    fn rewrites_internationalized_addresses() {
+        let (out, counts) = rewrite(concat!(
+            "To: Jörg <jörg@bücher.de>\n",
+            "Final-Recipient: rfc822; Jörg@Bücher.de\n",
+            "Received: from mx.bücher.de\n"
+        ));
+        assert!(!out.to_lowercase().contains("bücher") && !out.contains("jörg@"), "{out}");
+        let lines: Vec<&str> = out.lines().collect();
+        let address = lines[0].trim_start_matches("To: Jörg <").trim_end_matches('>');
+        assert!(address.starts_with('u'), "{out}");
+        assert_eq!(lines[1], format!("Final-Recipient: rfc822; {address}"));
+        let domain = address.split_once('@').unwrap().1;
+        assert!(lines[2].ends_with(&format!(".{domain}")), "{out}");
+        assert_eq!(counts, Counts { addresses: 2, hosts: 1, ips: 0 });
+    }
+
+    #[test]
+    fn rewrites_hosts_and_public_ips_only() {
+        let (out, counts) = rewrite(concat!(
+            "Received: from mx1.customer.org (mx1.customer.org [203.45.67.89])\n",
+            "\tby localhost (127.0.0.1) with ESMTP; Thu, 04 Sep 2025 02:36:16 +0300\n",
+            "Remote-MTA: dns; 2a00:1450:4001:81c::1b\n",
+            "Diagnostic-Code: smtp; 550 5.1.10 RESOLVER.ADR.RecipientNotFound\n",
+            "Content-Disposition: attachment; filename=notification.eml\n"
+        ));
+        assert!(
+            !out.contains("customer") && !out.contains("203.45") && !out.contains("2a00"),
+            "{out}"
+        );
+        assert!(out.contains("127.0.0.1") && out.contains("02:36:16"), "{out}");
+        assert!(out.contains("RESOLVER.ADR.RecipientNotFound") && out.contains("notification.eml"));
+        assert!(out.contains("Remote-MTA: dns; 2001:db8::"), "{out}");
+        assert_eq!(counts, Counts { addresses: 0, hosts: 2, ips: 2 });
+
+        let host = out.split_whitespace().nth(2).unwrap();
+        assert_eq!(out.matches(host).count(), 2, "{out}");
+    }
+}
@@ -0,0 +1,632 @@
+//! `bounce_importer`: backfills historical bounce mail into bouncer.
+//!
+//! Inputs are mbox files, Maildir folders and directories of `.eml` files
+//! (gzipped ones too, such as an archived `done/` or the output of
+//! `imap_fetcher`). Mbox files are read one message at a time, so archives
+//! of any size stream through without being loaded whole.
+//!
+//! Every message goes through the same parser chain the server runs:
+//! - `--server ADDR` sends it as a `mail` frame to a running server, which
+//!   spools, parses and applies it like live mail.
+//! - otherwise it is parsed here, checked against `authentication` like a
+//!   spooled bounce, and written to the `database_url` of the server config
+//!   directly; `hooks` and `alerts` do not fire for it.
+//! - `--dry-run` parses it and prints one JSON report per message, like
+//!   `bouncer-server check`, without sending or writing anything.
+//!
+//! Both import paths key each mail on its content hash, so an interrupted
+//! import can simply be run again.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{env, fmt};
+
+use anyhow::{Context, Result, bail};
+use bouncer_core::{
+    BounceAuthenticator, Config, Database, Faults, ParserChain, UpsertBounceOutcome,
+    idempotency_key
+};
+use bouncer_proto::{Header, ProtoError, encode_header_json, read_ack_async, write_frame_async};
+use flate2::read::GzDecoder;
+use serde_json::json;
+use tokio::net::TcpStream;
+use tokio::time::{MissedTickBehavior, interval};
+
+const SOURCE: &str = "bounce-importer";
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// Separator line of a message in an mbox file.
+const MBOX_FROM: &[u8] = b"From ";
+/// Highest `--rate`; a faster pace would round the tick period down to zero.
+const MAX_RATE: u32 = 1_000_000;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let args = Args::parse(env::args().skip(1))?;
+    eprintln!("bounce_importer start: {args}");
+
+    let mut sink = Sink::open(&args).await?;
+    let mut progress = Progress::new(sink.label(), args.progress);
+    let mut pace = (args.rate > 0).then(|| {
+        let mut pace = interval(Duration::from_secs(1) / args.rate);
+        pace.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        pace
+    });
+
+    for path in mail_sources(&args.inputs)? {
+        let display = path.display().to_string();
+        for (index, raw) in read_messages(&path)?.enumerate() {
+            let raw = raw.with_context(|| format!("failed to read {display}"))?;
+            if let Some(pace) = pace.as_mut() {
+                pace.tick().await;
+            }
+            let outcome = sink.import(&display, index, raw).await?;
+            progress.record(outcome);
+        }
+    }
+
+    progress.finish();
+    Ok(())
+}
+
+/// What happened to one message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// Applied, accepted by the server, or parsed in a dry run.
+    Imported,
+    /// Already applied by an earlier run or another path.
+    Skipped,
+    Failed
+}
+
+/// Where the messages go; see the module docs.
+enum Sink {
+    Server {
+        addr: String,
+        header: Vec<u8>,
+        stream: Option<TcpStream>
+    },
+    Database {
+        parsers: ParserChain,
+        db: Box<Database>,
+        authenticator: Box<BounceAuthenticator>,
+        tenant: Option<String>
+    },
+    DryRun {
+        parsers: ParserChain
+    }
+}
+
+impl Sink {
+    async fn open(args: &Args) -> Result<Self> {
+        if let (Some(addr), false) = (&args.server, args.dry_run) {
+            let header = Header {
+                from: SOURCE.to_string(),
+                to: addr.clone(),
+                kind: Some("mail".to_string()),
+                source: Some(SOURCE.to_string()),
+                traceparent: None,
+                queue_id: None,
+                tenant: args.tenant.clone(),
+                checksum: None
+            };
+            let header = encode_header_json(&header).context("failed to encode header")?;
+            return Ok(Self::Server { addr: addr.clone(), header, stream: None });
+        }
+
+        let config = Config::load_from(args.config.clone()).context("failed to load config")?;
+        let parsers = ParserChain::from_config(&config.parser)?;
+        if args.dry_run {
+            return Ok(Self::DryRun { parsers });
+        }
+        let db = Database::connect(
+            &config.database_url,
+            config.migrate,
+            config.suppression.clone(),
+            Arc::new(Faults)
+        )
+        .await
+        .context("failed to connect database")?
+        .with_bounce_dedup(config.bounce_dedup_window)
+        .with_resilience(config.database_resilience.clone())
+        .with_recipients(config.recipients.clone())
+        .with_classification(config.classification.clone());
+        let authenticator = BounceAuthenticator::new(config.authentication.clone())
+            .context("invalid authentication config")?;
+        Ok(Self::Database {
+            parsers,
+            db: Box::new(db),
+            authenticator: Box::new(authenticator),
+            tenant: args.tenant.clone()
+        })
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Server { .. } => "accepted",
+            Self::Database { .. } => "applied",
+            Self::DryRun { .. } => "parsed"
+        }
+    }
+
+    async fn import(
+        &mut self,
+        input: &str,
+        index: usize,
+        raw: Vec<u8>
+    ) -> Result<Outcome> {
+        match self {
+            Self::Server { addr, header, stream } => {
+                if stream.is_none() {
+                    let connected = TcpStream::connect(addr.as_str())
+                        .await
+                        .with_context(|| format!("tcp connect failed: {addr}"))?;
+                    *stream = Some(connected);
+                }
+                let Some(connected) = stream.as_mut() else {
+                    unreachable!("connected above");
+                };
+                let sent = match write_frame_async(connected, header, &raw).await {
+                    Ok(()) => read_ack_async(connected).await,
+                    Err(err) => Err(err)
+                };
+                match sent {
+                    Ok(()) => Ok(Outcome::Imported),
+                    Err(ProtoError::RejectedTooLarge) => {
+                        // The server closes the connection after TOO_LARGE.
+                        *stream = None;
+                        eprintln!(
+                            "message too large for the server: input={input}, message={index}, bytes={}",
+                            raw.len()
+                        );
+                        Ok(Outcome::Failed)
+                    }
+                    Err(err) => Err(err).with_context(|| {
+                        format!("failed to send message {index} of {input} to {addr}")
+                    })
+                }
+            }
+            Self::Database { parsers, db, authenticator, tenant } => {
+                let mut parsed = match parsers.parse_detailed(&raw) {
+                    Ok(parsed) => parsed,
+                    Err(err) => {
+                        eprintln!(
+                            "parse failed: input={input}, message={index}, code={}, error={err}",
+                            err.code()
+                        );
+                        return Ok(Outcome::Failed);
+                    }
+                };
+                if tenant.is_some() {
+                    parsed.tenant = tenant.clone();
+                }
+                let key = idempotency_key(&raw);
+                if authenticator.enabled() {
+                    let outcome = authenticator.check(&raw).await;
+                    db.record_authentication(&key, &parsed.hash, &outcome).await.with_context(
+                        || format!("failed to record authentication of message {index} of {input}")
+                    )?;
+                    if !outcome.passed() {
+                        eprintln!(
+                            "bounce failed authentication: input={input}, message={index}, hash={}, dkim={}, relay={}, relay_host={}, rejected={}",
+                            parsed.hash,
+                            outcome.dkim.as_str(),
+                            outcome.relay.as_str(),
+                            outcome.relay_host.as_deref().unwrap_or("-"),
+                            authenticator.rejects()
+                        );
+                        if authenticator.rejects() {
+                            return Ok(Outcome::Failed);
+                        }
+                    }
+                }
+                let applied = db
+                    .upsert_bounce_once(&parsed, &key)
+                    .await
+                    .with_context(|| format!("failed to apply message {index} of {input}"))?;
+                Ok(match applied {
+                    Some(UpsertBounceOutcome::Duplicate) | None => Outcome::Skipped,
+                    Some(_) => Outcome::Imported
+                })
+            }
+            Self::DryRun { parsers } => {
+                let (report, outcome) = match parsers.parse_detailed(&raw) {
+                    Ok(parsed) => (
+                        json!({
+                            "input": input,
+                            "message": index,
+                            "ok": true,
+                            "kind": parsed.kind.as_str(),
+                            "hash": parsed.hash,
+                            "status_code": parsed.status_code,
+                            "action": parsed.action,
+                            "recipient": parsed.recipient,
+                            "reason": parsed.reason()
+                        }),
+                        Outcome::Imported
+                    ),
+                    Err(err) => (
+                        json!({
+                            "input": input,
+                            "message": index,
+                            "ok": false,
+                            "code": err.code(),
+                            "error": err.to_string()
+                        }),
+                        Outcome::Failed
+                    )
+                };
+                println!("{report}");
+                Ok(outcome)
+            }
+        }
+    }
+}
+
+/// Running totals, printed to stderr every `every` and at the end.
+struct Progress {
+    label: &'static str,
+    every: Duration,
+    started: Instant,
+    last_report: Instant,
+    messages: u64,
+    imported: u64,
+    skipped: u64,
+    failed: u64
+}
+
+impl Progress {
+    fn new(
+        label: &'static str,
+        every: Duration
+    ) -> Self {
+        let now = Instant::now();
+        Self {
+            label,
+            every,
+            started: now,
+            last_report: now,
+            messages: 0,
+            imported: 0,
+            skipped: 0,
+            failed: 0
+        }
+    }
+
+    fn record(
+        &mut self,
+        outcome: Outcome
+    ) {
+        self.messages += 1;
+        match outcome {
+            Outcome::Imported => self.imported += 1,
+            Outcome::Skipped => self.skipped += 1,
+            Outcome::Failed => self.failed += 1
+        }
+        if !self.every.is_zero() && self.last_report.elapsed() >= self.every {
+            self.last_report = Instant::now();
+            eprintln!("progress: {self}");
+        }
+    }
+
+    fn finish(&self) {
+        eprintln!("completed: {self}");
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        let secs = self.started.elapsed().as_secs_f64();
+        write!(
+            f,
+            "messages={}, {}={}, skipped={}, failed={}, elapsed={secs:.1}s, rate={:.1}/s",
+            self.messages,
+            self.label,
+            self.imported,
+            self.skipped,
+            self.failed,
+            if secs > 0.0 { self.messages as f64 / secs } else { 0.0 }
+        )
+    }
+}
+
+/// The files to read, in order: every input path that is a file, the
+/// `cur/` and `new/` messages of a Maildir, and every file under any other
+/// directory (recursively, sorted by path, dotfiles skipped).
+fn mail_sources(inputs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+    for input in inputs {
+        let meta = std::fs::metadata(input)
+            .with_context(|| format!("failed to stat {}", input.display()))?;
+        if !meta.is_dir() {
+            sources.push(input.clone());
+            continue;
+        }
+        let maildir = ["cur", "new"].map(|sub| input.join(sub));
+        let dirs: Vec<PathBuf> = if maildir.iter().any(|dir| dir.is_dir()) {
+            maildir.into_iter().filter(|dir| dir.is_dir()).collect()
+        } else {
+            vec![input.clone()]
+        };
+        for dir in dirs {
+            sources.extend(files_under(&dir)?);
+        }
+    }
+    Ok(sources)
+}
+
+fn files_under(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = std::fs::read_dir(&dir)
+            .with_context(|| format!("failed to read dir {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry.with_context(|| format!("failed to read dir {}", dir.display()))?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let kind = entry.file_type().context("failed to stat input file")?;
+            if kind.is_dir() {
+                dirs.push(entry.path());
+            } else if kind.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The messages of the file at `path`, gunzipped if needed: one per entry
+/// of an mbox, else the whole file as one message.
+fn read_messages(path: &Path) -> Result<Messages<Box<dyn BufRead>>> {
+    let mut file = BufReader::new(
+        File::open(path).with_context(|| format!("failed to open {}", path.display()))?
+    );
+    let gzipped = file
+        .fill_buf()
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .starts_with(&GZIP_MAGIC);
+    let reader: Box<dyn BufRead> =
+        if gzipped { Box::new(BufReader::new(GzDecoder::new(file))) } else { Box::new(file) };
+    Messages::new(reader).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Splits an mbox into its messages, undoing the `>From ` quoting of body
+/// lines (mboxrd). Input that does not start with a `From ` line is one
+/// message.
+struct Messages<R> {
+    reader: R,
+    mbox: bool,
+    /// First line of a plain message, read to tell it from an mbox.
+    first: Option<Vec<u8>>,
+    done: bool
+}
+
+impl<R: BufRead> Messages<R> {
+    fn new(mut reader: R) -> std::io::Result<Self> {
+        let mut first = Vec::new();
+        reader.read_until(b'\n', &mut first)?;
+        let mbox = first.starts_with(MBOX_FROM);
+        Ok(Self { reader, mbox, first: (!mbox).then_some(first), done: false })
+    }
+
+    fn next_mbox_message(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let mut message = Vec::new();
+        let mut line = Vec::new();
+        let mut after_blank = true;
+        loop {
+            line.clear();
+            if self.reader.read_until(b'\n', &mut line)? == 0 {
+                self.done = true;
+                break;
+            }
+            if after_blank && line.starts_with(MBOX_FROM) {
+                if message.is_empty() {
+                    continue;
+                }
+                break;
+            }
+            after_blank = matches!(line.as_slice(), b"\n" | b"\r\n");
+            let quoted = line.iter().take_while(|&&byte| byte == b'>').count();
+            if quoted > 0 && line[quoted..].starts_with(MBOX_FROM) {
+                message.extend_from_slice(&line[1..]);
+            } else {
+                message.extend_from_slice(&line);
+            }
+        }
+        // The blank line before the next `From ` belongs to the mbox.
+        if message.ends_with(b"\r\n\r\n") {
+            message.truncate(message.len() - 2);
+        } else if message.ends_with(b"\n\n") {
+            message.truncate(message.len() - 1);
+        }
+        Ok((!message.is_empty()).then_some(message))
+    }
+}
+
+impl<R: BufRead> Iterator for Messages<R> {
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if !self.mbox {
+            self.done = true;
+            let mut message = self.first.take().unwrap_or_default();
+            return match self.reader.read_to_end(&mut message) {
+                Ok(_) if message.is_empty() => None,
+                Ok(_) => Some(Ok(message)),
+                Err(err) => Some(Err(err))
+            };
+        }
+        loop {
+            match self.next_mbox_message() {
+                Ok(Some(message)) => return Some(Ok(message)),
+                Ok(None) if self.done => return None,
+                Ok(None) => continue,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Args {
+    server: Option<String>,
+    config: Option<PathBuf>,
+    dry_run: bool,
+    rate: u32,
+    tenant: Option<String>,
+    progress: Duration,
+    inputs: Vec<PathBuf>
+}
+
+impl Args {
+    fn parse<I>(mut it: I) -> Result<Self>
+    where
+        I: Iterator<Item = String>
+    {
+        let mut server = None;
+        let mut config = None;
+        let mut dry_run = false;
+        let mut rate = 0u32;
+        let mut tenant = None;
+        let mut progress = Duration::from_secs(10);
+        let mut inputs = Vec::new();
+
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "--server" => server = Some(it.next().context("missing value for --server")?),
+                "--config" => {
+                    config = Some(PathBuf::from(it.next().context("missing value for --config")?));
+                }
+                "--dry-run" => dry_run = true,
+                "--rate" => {
+                    let raw = it.next().context("missing value for --rate")?;
+                    rate = raw.parse::<u32>().context("invalid --rate value")?;
+                    if rate > MAX_RATE {
+                        bail!("--rate is at most {MAX_RATE} messages per second");
+                    }
+                }
+                "--tenant" => tenant = Some(it.next().context("missing value for --tenant")?),
+                "--progress" => {
+                    let raw = it.next().context("missing value for --progress")?;
+                    progress =
+                        humantime::parse_duration(&raw).context("invalid --progress value")?;
+                }
+                "-h" | "--help" => {
+                    print_usage();
+                    std::process::exit(0);
+                }
+                _ if arg.starts_with("--") => bail!("unknown argument: {arg}"),
+                _ => inputs.push(PathBuf::from(arg))
+            }
+        }
+
+        if inputs.is_empty() {
+            print_usage();
+            bail!("no mbox, Maildir or .eml inputs given");
+        }
+        if tenant.as_deref().is_some_and(|tenant| !bouncer_proto::valid_tenant(tenant)) {
+            bail!("invalid --tenant value");
+        }
+        Ok(Self { server, config, dry_run, rate, tenant, progress, inputs })
+    }
+}
+
+fn print_usage() {
+    eprintln!(
+        "usage: bounce_importer [--server HOST:PORT | --config bouncer.yaml] [--dry-run] [--rate MSGS_PER_SEC] [--tenant NAME] [--progress 10s] INPUT..."
+    );
+    eprintln!("  INPUT: mbox file (gzipped too), Maildir, or directory of .eml/.eml.gz files");
+}
+
+impl fmt::Display for Args {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        let target = match (&self.server, self.dry_run) {
+            (_, true) => "dry_run".to_string(),
+            (Some(server), false) => format!("server={server}"),
+            (None, false) => "database".to_string()
+        };
+        write!(
+            f,
+            "target={target}, rate={}, tenant={}, inputs={}",
+            if self.rate > 0 { format!("{}/s", self.rate) } else { "unlimited".to_string() },
+            self.tenant.as_deref().unwrap_or("-"),
+            self.inputs.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Args, Messages};
+
+    fn split(input: &str) -> Vec<String> {
+        Messages::new(input.as_bytes())
+            .unwrap()
+            .map(|message| String::from_utf8(message.unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn splits_mbox_and_unquotes_from_lines() {
+        let messages = split(concat!(
+            "From MAILER-DAEMON Thu Sep  4 02:36:16 2025\n",
+            "Subject: one\n",
+            "\n",
+            ">From the report:\n",
+            ">>From kept once quoted\n",
+            "From inside a paragraph stays\n",
+            "\n",
+            "From MAILER-DAEMON Fri Sep  5 10:00:00 2025\n",
+            "Subject: two\n",
+            "\n",
+            "body\n"
+        ));
+        assert_eq!(
+            messages,
+            [
+                concat!(
+                    "Subject: one\n",
+                    "\n",
+                    "From the report:\n",
+                    ">From kept once quoted\n",
+                    "From inside a paragraph stays\n"
+                ),
+                "Subject: two\n\nbody\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_a_rate_without_a_tick_period() {
+        let args = |rate: &str| {
+            Args::parse(["--rate", rate, "old.mbox"].into_iter().map(String::from))
+                .map(|args| args.rate)
+        };
+        assert_eq!(args("1000000").unwrap(), 1_000_000);
+        assert!(args("1000001").is_err());
+    }
+
+    #[test]
+    fn reads_a_plain_mail_as_one_message() {
+        let mail = "Subject: bounce\r\n\r\nFrom here on\r\n";
+        assert_eq!(split(mail), [mail]);
+        assert!(split("").is_empty());
+    }
+}
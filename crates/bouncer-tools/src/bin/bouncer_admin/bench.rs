@@ -0,0 +1,255 @@
+//! `bouncer-admin bench`: drives synthetic load through a running server and
+//! reports end-to-end throughput, so releases can be compared.
+//!
+//! Observer events are applied to the database before the server ACKs them,
+//! so their rate is end-to-end as sent. Mail frames are ACKed once spooled;
+//! their end-to-end rate runs until the workers have moved all of them to
+//! `done/` or `failed/`, as reported by `server-status`.
+//!
+//! Every run uses fresh hashes, so dedup never short-circuits the work. The
+//! bounce rows it writes are orphans; point it at a throwaway database.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use bouncer_proto::event::DeliveryEvent;
+use bouncer_proto::{Header, encode_header_json, read_ack_async, write_frame_async};
+use serde_json::{Value, json};
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, timeout};
+
+use crate::{Args, server_status};
+
+const BENCH_SOURCE: &str = "bouncer-bench";
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub mails: u64,
+    pub events: u64,
+    pub connections: usize,
+    pub drain_timeout: Duration
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            mails: 1000,
+            events: 1000,
+            connections: 4,
+            drain_timeout: Duration::from_secs(300)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FrameKind {
+    ObserverEvent,
+    Mail
+}
+
+impl FrameKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::ObserverEvent => "observer_event",
+            Self::Mail => "mail"
+        }
+    }
+
+    fn body(
+        self,
+        run_id: u64,
+        seq: u64
+    ) -> Vec<u8> {
+        // 32 hex digits; the kind digit keeps event and mail hashes apart.
+        let hash = format!("{:015x}{:x}{seq:016x}", run_id >> 4, self as u8);
+        match self {
+            Self::ObserverEvent => {
+                let event = DeliveryEvent::new(
+                    BENCH_SOURCE,
+                    &hash,
+                    &format!("B{seq:X}"),
+                    &format!("user{seq}@bench.invalid"),
+                    unix_now().as_secs()
+                )
+                .with_outcome("bounced", "5.1.1", "failed")
+                .with_diagnostic("smtp; 550 5.1.1 user unknown");
+                serde_json::to_vec(&event).expect("delivery event encodes")
+            }
+            Self::Mail => format!(
+                "From: Mail Delivery System <mailer-daemon@bench.invalid>\r\n\
+                 To: bounces@bench.invalid\r\n\
+                 Subject: Undelivered Mail Returned to Sender\r\n\
+                 Content-Type: multipart/report; report-type=delivery-status; boundary=\"b\"\r\n\
+                 \r\n\
+                 --b\r\n\
+                 Content-Type: message/delivery-status\r\n\
+                 \r\n\
+                 Final-Recipient: rfc822; user{seq}@bench.invalid\r\n\
+                 Action: failed\r\n\
+                 Status: 5.1.1\r\n\
+                 \r\n\
+                 --b\r\n\
+                 Content-Type: text/rfc822-headers\r\n\
+                 \r\n\
+                 Message-ID: <{hash}@bench.invalid>\r\n\
+                 \r\n\
+                 --b--\r\n"
+            )
+            .into_bytes()
+        }
+    }
+}
+
+/// Sends the configured events, then the mails, and waits for the spool to
+/// drain. Returns the report as JSON; `print_report` renders it as rows.
+pub async fn run_bench(
+    args: &Args,
+    options: &BenchOptions
+) -> Result<Value> {
+    if options.connections == 0 {
+        bail!("--connections must be at least 1");
+    }
+    let run_id = unix_now().as_nanos() as u64;
+    let mut report = json!({
+        "connections": options.connections,
+        "events": options.events,
+        "mails": options.mails
+    });
+
+    if options.events > 0 {
+        let elapsed = send_frames(args, options, FrameKind::ObserverEvent, run_id).await?;
+        report["events_secs"] = json!(elapsed.as_secs_f64());
+        report["events_per_sec"] = json!(rate(options.events, elapsed));
+    }
+
+    if options.mails > 0 {
+        let baseline = spool_finished(args).await?;
+        let started = Instant::now();
+        let accepted = send_frames(args, options, FrameKind::Mail, run_id).await?;
+        report["mails_accept_secs"] = json!(accepted.as_secs_f64());
+        report["mails_accept_per_sec"] = json!(rate(options.mails, accepted));
+
+        loop {
+            let finished = spool_finished(args).await?.saturating_sub(baseline);
+            if finished >= options.mails {
+                break;
+            }
+            if started.elapsed() > options.drain_timeout {
+                bail!(
+                    "spool did not drain within {:?}: {finished}/{} mails processed",
+                    options.drain_timeout,
+                    options.mails
+                );
+            }
+            sleep(DRAIN_POLL_INTERVAL).await;
+        }
+        let elapsed = started.elapsed();
+        report["mails_secs"] = json!(elapsed.as_secs_f64());
+        report["mails_per_sec"] = json!(rate(options.mails, elapsed));
+    }
+
+    Ok(report)
+}
+
+pub fn print_report(report: &Value) {
+    let keys = [
+        "connections",
+        "events",
+        "events_secs",
+        "events_per_sec",
+        "mails",
+        "mails_accept_secs",
+        "mails_accept_per_sec",
+        "mails_secs",
+        "mails_per_sec"
+    ];
+    let width = keys.iter().map(|key| key.len()).max().unwrap_or(0);
+    for key in keys {
+        let value = match &report[key] {
+            Value::Null => continue,
+            Value::Number(n) if n.is_f64() => format!("{:.2}", n.as_f64().unwrap_or_default()),
+            value => value.to_string()
+        };
+        println!("{key:<width$}  {value}");
+    }
+}
+
+/// Spreads `count` frames of `kind` over `options.connections` connections,
+/// each sending its next frame once the previous one is ACKed.
+async fn send_frames(
+    args: &Args,
+    options: &BenchOptions,
+    kind: FrameKind,
+    run_id: u64
+) -> Result<Duration> {
+    let header = Header {
+        from: BENCH_SOURCE.to_string(),
+        to: args.server.clone(),
+        kind: Some(kind.name().to_string()),
+        source: Some(BENCH_SOURCE.to_string()),
+        traceparent: None,
+        queue_id: None,
+        tenant: None,
+        checksum: None
+    };
+    let header_bytes = encode_header_json(&header).context("failed to encode header")?;
+    let connections = options.connections as u64;
+    let count = match kind {
+        FrameKind::ObserverEvent => options.events,
+        FrameKind::Mail => options.mails
+    };
+
+    let started = Instant::now();
+    let mut tasks = JoinSet::new();
+    for lane in 0..connections.min(count) {
+        let server = args.server.clone();
+        let header_bytes = header_bytes.clone();
+        let io_timeout = args.timeout;
+        tasks.spawn(async move {
+            let mut stream = TcpStream::connect(&server)
+                .await
+                .with_context(|| format!("tcp connect failed: {server}"))?;
+            // A frame goes out in several writes; without this Nagle holds
+            // each one for the server's delayed ACK and caps every connection
+            // at ~25 frames/sec.
+            stream.set_nodelay(true).context("failed to set TCP_NODELAY")?;
+            for seq in (lane..count).step_by(connections as usize) {
+                let body = kind.body(run_id, seq);
+                timeout(io_timeout, async {
+                    write_frame_async(&mut stream, &header_bytes, &body).await?;
+                    read_ack_async(&mut stream).await
+                })
+                .await
+                .with_context(|| format!("{} frame timed out after {io_timeout:?}", kind.name()))?
+                .with_context(|| format!("failed to send {} frame", kind.name()))?;
+            }
+            anyhow::Ok(())
+        });
+    }
+    while let Some(result) = tasks.join_next().await {
+        result.context("bench connection task failed")??;
+    }
+    Ok(started.elapsed())
+}
+
+/// Spool files the workers are done with.
+async fn spool_finished(args: &Args) -> Result<u64> {
+    let status =
+        timeout(args.timeout, server_status(&args.server)).await.with_context(|| {
+            format!("status timed out after {:?}: server={}", args.timeout, args.server)
+        })??;
+    Ok(status.spool.done + status.spool.failed)
+}
+
+fn rate(
+    count: u64,
+    elapsed: Duration
+) -> f64 {
+    count as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+fn unix_now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
@@ -0,0 +1,568 @@
+mod bench;
+mod support_bundle;
+
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use bouncer_proto::query::{
+    BounceRecord, MessageState, QUERY_KIND, QUERY_RESPONSE_KIND, QueryRequest, QueryResponse,
+    ServerStats, SourceEvent, SourceStats
+};
+use bouncer_proto::status::{LatencyHistogram, STATUS_KIND, STATUS_RESPONSE_KIND, ServerStatus};
+use bouncer_proto::{
+    Header, decode_header_json, encode_header_json, read_frame_async, write_frame_async
+};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::bench::BenchOptions;
+use crate::support_bundle::BundleOptions;
+
+const MAX_HEADER_LEN: u32 = 64 * 1024;
+const MAX_BODY_LEN: u64 = 16 * 1024 * 1024;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let args = Args::parse(env::args().skip(1))?;
+
+    let request = match &args.command {
+        Command::Query(request) => request,
+        Command::ServerStatus => {
+            let status =
+                timeout(args.timeout, server_status(&args.server)).await.with_context(|| {
+                    format!("status timed out after {:?}: server={}", args.timeout, args.server)
+                })??;
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&status).context("failed to encode json")?
+                );
+            } else {
+                print_server_status(&status);
+            }
+            return Ok(());
+        }
+        Command::Bench(options) => {
+            let report = bench::run_bench(&args, options).await?;
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).context("failed to encode json")?
+                );
+            } else {
+                bench::print_report(&report);
+            }
+            return Ok(());
+        }
+        Command::SupportBundle(options) => {
+            let output = support_bundle::write_bundle(&args, options).await?;
+            println!("support bundle written: {}", output.display());
+            return Ok(());
+        }
+    };
+    let response = query_with_timeout(&args, request).await?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&response).context("failed to encode json")?);
+        return Ok(());
+    }
+
+    match response {
+        QueryResponse::Status(state) => print_status(&state),
+        QueryResponse::RecentBounces { bounces } => print_bounces(&bounces),
+        QueryResponse::Stats(stats) => print_stats(&stats),
+        QueryResponse::Sources { sources } => print_sources(&sources),
+        QueryResponse::SourceEvents { events } => print_source_events(&events),
+        QueryResponse::ReplayQuarantine(summary) => print_rows(&[
+            ("replayed", Some(summary.replayed.to_string().as_str())),
+            ("still_invalid", Some(summary.still_invalid.to_string().as_str())),
+            ("apply_failed", Some(summary.apply_failed.to_string().as_str()))
+        ]),
+        QueryResponse::Error { message } => bail!("server error: {message}")
+    }
+    Ok(())
+}
+
+async fn query_with_timeout(
+    args: &Args,
+    request: &QueryRequest
+) -> Result<QueryResponse> {
+    timeout(args.timeout, query(&args.server, request)).await.with_context(|| {
+        format!("query timed out after {:?}: server={}", args.timeout, args.server)
+    })?
+}
+
+async fn query(
+    server: &str,
+    request: &QueryRequest
+) -> Result<QueryResponse> {
+    let body = serde_json::to_vec(request).context("failed to encode query")?;
+    let body = request_frame(server, QUERY_KIND, &body, QUERY_RESPONSE_KIND).await?;
+    serde_json::from_slice(&body).context("failed to decode query response")
+}
+
+async fn server_status(server: &str) -> Result<ServerStatus> {
+    let body = request_frame(server, STATUS_KIND, &[], STATUS_RESPONSE_KIND).await?;
+    serde_json::from_slice(&body).context("failed to decode status response")
+}
+
+/// Sends one frame of `kind` and returns the body of the `response_kind` answer.
+async fn request_frame(
+    server: &str,
+    kind: &str,
+    body: &[u8],
+    response_kind: &str
+) -> Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(server)
+        .await
+        .with_context(|| format!("tcp connect failed: {server}"))?;
+
+    // Checksummed so `require_frame_checksum` listeners accept it; the reply
+    // then comes back checksummed too.
+    let header = Header {
+        from: "bouncer-admin".to_string(),
+        to: server.to_string(),
+        kind: Some(kind.to_string()),
+        source: None,
+        traceparent: None,
+        queue_id: None,
+        tenant: None,
+        checksum: None
+    }
+    .with_checksum(body);
+    let header_bytes = encode_header_json(&header).context("failed to encode header")?;
+    write_frame_async(&mut stream, &header_bytes, body)
+        .await
+        .with_context(|| format!("failed to send {kind} frame"))?;
+
+    let (header_bytes, body) = read_frame_async(&mut stream, MAX_HEADER_LEN, MAX_BODY_LEN)
+        .await
+        .with_context(|| format!("failed to read {response_kind}"))?;
+    let header = decode_header_json(&header_bytes).context("failed to decode response header")?;
+    if header.kind.as_deref() != Some(response_kind) {
+        bail!("unexpected response kind: {}", header.kind.as_deref().unwrap_or("-"));
+    }
+
+    Ok(body)
+}
+
+fn print_status(state: &MessageState) {
+    let status = state.mail_status.map(|status| status.to_string());
+    let updated_at = state.updated_at_unix.map(|ts| ts.to_string());
+    let message_id = state.message_id.map(|id| id.to_string());
+    print_rows(&[
+        ("hash", Some(state.hash.as_str())),
+        ("message_id", message_id.as_deref()),
+        ("mail_status", status.as_deref()),
+        ("updated_at", updated_at.as_deref())
+    ]);
+
+    match &state.bounce {
+        Some(bounce) => {
+            println!();
+            print_bounces(std::slice::from_ref(bounce));
+        }
+        None if state.message_id.is_none() => println!("\nno message or bounce found"),
+        None => println!("\nno bounce recorded")
+    }
+
+    if !state.history.is_empty() {
+        println!("\nhistory:");
+        for event in &state.history {
+            println!(
+                "  {}  {:<9} {:<7} {}",
+                event.occurred_at_unix,
+                event.action.as_deref().unwrap_or("-"),
+                event.status_code,
+                event.description.as_deref().unwrap_or("-")
+            );
+        }
+    }
+}
+
+fn print_stats(stats: &ServerStats) {
+    let rows = [
+        ("uptime_secs", stats.uptime_secs.to_string()),
+        ("spool_incoming", stats.spool_incoming.to_string()),
+        ("spool_processing", stats.spool_processing.to_string()),
+        ("spool_done", stats.spool_done.to_string()),
+        ("spool_failed", stats.spool_failed.to_string()),
+        ("bounces_last_24h", stats.bounces_last_24h.to_string()),
+        ("suppressed", stats.suppressed.map_or_else(|| "disabled".to_string(), |n| n.to_string())),
+        ("connections_accepted", stats.connections_accepted.to_string()),
+        ("connections_rejected", stats.connections_rejected.to_string()),
+        ("connection_storms", stats.connection_storms.to_string()),
+        ("peak_connections_per_window", stats.peak_connections_per_window.to_string())
+    ];
+    print_rows(&rows.iter().map(|(key, value)| (*key, Some(value.as_str()))).collect::<Vec<_>>());
+}
+
+fn print_server_status(status: &ServerStatus) {
+    let mut rows = vec![
+        ("uptime_secs", status.uptime_secs.to_string()),
+        ("spool_incoming", status.spool.incoming.to_string()),
+        ("spool_processing", status.spool.processing.to_string()),
+        ("spool_done", status.spool.done.to_string()),
+        ("spool_failed", status.spool.failed.to_string()),
+        ("spool_deferred", status.spool.deferred.to_string()),
+        ("spool_quarantined", status.spool.quarantined.to_string()),
+        ("workers_busy", format!("{}/{}", status.workers.busy, status.workers.total)),
+        (
+            "db_connections",
+            format!(
+                "{} open, {} idle, max {} ({})",
+                status.db.connections, status.db.idle, status.db.max_connections, status.db.backend
+            )
+        ),
+        ("db_breaker", if status.db.breaker_open { "open" } else { "closed" }.to_string()),
+    ];
+    if let Some(queue) = &status.queue {
+        rows.push(("queue_high", format!("{}/{}", queue.high, queue.high_capacity)));
+        rows.push(("queue_low", format!("{}/{}", queue.low, queue.low_capacity)));
+    }
+    match &status.imap {
+        Some(imap) => {
+            rows.push(("imap_polls", format!("{} ({} failed)", imap.polls, imap.failed_polls)));
+            if let Some(poll) = &imap.last_poll {
+                rows.push(("imap_last_poll_at", poll.finished_at_unix.to_string()));
+                rows.push((
+                    "imap_last_poll",
+                    match &poll.error {
+                        Some(error) => format!("failed: {error}"),
+                        None => format!(
+                            "selected={}, parsed_ok={}, parse_failures={}, fetch_failures={}, db_failures={}",
+                            poll.selected,
+                            poll.parsed_ok,
+                            poll.parse_failures,
+                            poll.fetch_failures,
+                            poll.db_failures
+                        )
+                    }
+                ));
+            }
+            for mailbox in &imap.mailboxes {
+                rows.push((
+                    "imap_mailbox",
+                    format!(
+                        "{}/{}: {} polls ({} failed){}",
+                        mailbox.account,
+                        mailbox.mailbox,
+                        mailbox.polls,
+                        mailbox.failed_polls,
+                        match mailbox.last_poll.as_ref().and_then(|poll| poll.error.as_deref()) {
+                            Some(error) => format!(", last failed: {error}"),
+                            None => String::new()
+                        }
+                    )
+                ));
+            }
+        }
+        None => rows.push(("imap", "disabled".to_string()))
+    }
+    for stage in &status.processing.stages {
+        rows.push(("latency", format!("{}: {}", stage.stage, format_latency(&stage.latency))));
+    }
+    for worker in &status.processing.workers {
+        rows.push((
+            "worker_latency",
+            format!("{}: {}", worker.worker, format_latency(&worker.latency))
+        ));
+    }
+    rows.push(("slow_files", status.processing.slow_files.to_string()));
+    for (reason, count) in &status.processing.failed_by_reason {
+        rows.push(("failed_reason", format!("{reason}: {count}")));
+    }
+    for (name, tenant) in &status.tenants {
+        rows.push((
+            "tenant",
+            format!(
+                "{name}: mails={}, observer_events={}, processed={}, failed={}",
+                tenant.mails, tenant.observer_events, tenant.processed, tenant.failed
+            )
+        ));
+    }
+    print_rows(&rows.iter().map(|(key, value)| (*key, Some(value.as_str()))).collect::<Vec<_>>());
+}
+
+/// `files=N, avg=..ms, p50<=..ms, p95<=..ms, max=..ms`; quantiles are bucket bounds.
+fn format_latency(latency: &LatencyHistogram) -> String {
+    let ms =
+        |value: Option<u64>| value.map_or_else(|| "-".to_string(), |value| format!("{value}ms"));
+    format!(
+        "files={}, avg={}, p50<={}, p95<={}, max={}",
+        latency.count,
+        ms(latency.mean_ms()),
+        ms(latency.quantile_ms(0.5)),
+        ms(latency.quantile_ms(0.95)),
+        ms((latency.count > 0).then_some(latency.max_ms))
+    )
+}
+
+fn print_rows(rows: &[(&str, Option<&str>)]) {
+    let width = rows.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, value) in rows {
+        println!("{key:<width$}  {}", value.unwrap_or("-"));
+    }
+}
+
+fn print_bounces(bounces: &[BounceRecord]) {
+    if bounces.is_empty() {
+        println!("no bounces");
+        return;
+    }
+
+    let headers = [
+        "created_at",
+        "last_seen",
+        "count",
+        "hash",
+        "tracked",
+        "status_code",
+        "reason",
+        "category",
+        "action",
+        "recipient",
+        "description"
+    ];
+    let rows = bounces
+        .iter()
+        .map(|bounce| {
+            [
+                bounce.created_at_unix.to_string(),
+                bounce.last_seen_unix.map_or_else(|| "-".to_string(), |unix| unix.to_string()),
+                bounce.occurrence_count.map_or_else(|| "-".to_string(), |count| count.to_string()),
+                bounce.hash.clone(),
+                bounce.tracked.to_string(),
+                bounce.status_code.clone(),
+                bounce.reason.clone().unwrap_or_else(|| "-".to_string()),
+                bounce.category.clone().unwrap_or_else(|| "-".to_string()),
+                bounce.action.clone().unwrap_or_else(|| "-".to_string()),
+                bounce.recipient.clone().unwrap_or_else(|| "-".to_string()),
+                bounce.description.clone().unwrap_or_else(|| "-".to_string())
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print_table(headers, &rows);
+}
+
+fn print_sources(sources: &[SourceStats]) {
+    if sources.is_empty() {
+        println!("no sources");
+        return;
+    }
+
+    let age = |secs: Option<u64>| secs.map_or_else(|| "-".to_string(), |secs| format!("{secs}s"));
+    let headers = [
+        "source",
+        "registered",
+        "connections",
+        "events",
+        "heartbeats",
+        "parse_failures",
+        "ignored_deliveries",
+        "last_heartbeat",
+        "last_event",
+        "silent",
+        "queue_map"
+    ];
+    let rows = sources
+        .iter()
+        .map(|source| {
+            [
+                source.source.clone(),
+                source.registered.to_string(),
+                source.connections.to_string(),
+                source.events.to_string(),
+                source.heartbeats.to_string(),
+                source.parse_failures.to_string(),
+                source.ignored_deliveries.to_string(),
+                age(source.last_heartbeat_secs_ago),
+                age(source.last_event_secs_ago),
+                source.silent.to_string(),
+                source.queue_map.map_or_else(
+                    || "-".to_string(),
+                    |map| format!("{}/{} evicted={}", map.entries, map.capacity, map.evicted)
+                )
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print_table(headers, &rows);
+}
+
+fn print_source_events(events: &[SourceEvent]) {
+    if events.is_empty() {
+        println!("no source events");
+        return;
+    }
+
+    let headers = ["at", "source", "transition", "detail"];
+    let rows = events
+        .iter()
+        .map(|event| {
+            [
+                event.at_unix.to_string(),
+                event.source.clone(),
+                event.transition.clone(),
+                event.detail.clone().unwrap_or_else(|| "-".to_string())
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print_table(headers, &rows);
+}
+
+fn print_table<const N: usize>(
+    headers: [&str; N],
+    rows: &[[String; N]]
+) {
+    let mut widths = headers.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: &[&str]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", line(&headers));
+    for row in rows {
+        println!("{}", line(&row.iter().map(String::as_str).collect::<Vec<_>>()));
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Args {
+    server: String,
+    json: bool,
+    timeout: Duration,
+    command: Command
+}
+
+#[derive(Debug, Clone)]
+enum Command {
+    Query(QueryRequest),
+    ServerStatus,
+    Bench(BenchOptions),
+    SupportBundle(BundleOptions)
+}
+
+impl Args {
+    fn parse<I>(mut it: I) -> Result<Self>
+    where
+        I: Iterator<Item = String>
+    {
+        let mut server = "127.0.0.1:2147".to_string();
+        let mut json = false;
+        let mut timeout = Duration::from_secs(10);
+        let mut command = None;
+        let mut hash = None;
+        let mut since = Duration::from_secs(60 * 60);
+        let mut limit = 50u32;
+        let mut bundle = BundleOptions::default();
+        let mut bench = BenchOptions::default();
+
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "--server" => server = it.next().context("missing value for --server")?,
+                "--json" => json = true,
+                "--timeout" => {
+                    let raw = it.next().context("missing value for --timeout")?;
+                    timeout = humantime::parse_duration(&raw).context("invalid --timeout value")?;
+                }
+                "--since" => {
+                    let raw = it.next().context("missing value for --since")?;
+                    since = humantime::parse_duration(&raw).context("invalid --since value")?;
+                }
+                "--limit" => {
+                    let raw = it.next().context("missing value for --limit")?;
+                    limit = raw.parse::<u32>().context("invalid --limit value")?;
+                }
+                "--config" => {
+                    bundle.config =
+                        Some(PathBuf::from(it.next().context("missing value for --config")?))
+                }
+                "--log-file" => {
+                    bundle.log_file =
+                        Some(PathBuf::from(it.next().context("missing value for --log-file")?))
+                }
+                "--unit" => bundle.unit = it.next().context("missing value for --unit")?,
+                "--lines" => {
+                    let raw = it.next().context("missing value for --lines")?;
+                    bundle.lines = raw.parse::<usize>().context("invalid --lines value")?;
+                }
+                "--output" => {
+                    bundle.output =
+                        Some(PathBuf::from(it.next().context("missing value for --output")?))
+                }
+                "--mails" => {
+                    let raw = it.next().context("missing value for --mails")?;
+                    bench.mails = raw.parse::<u64>().context("invalid --mails value")?;
+                }
+                "--events" => {
+                    let raw = it.next().context("missing value for --events")?;
+                    bench.events = raw.parse::<u64>().context("invalid --events value")?;
+                }
+                "--connections" => {
+                    let raw = it.next().context("missing value for --connections")?;
+                    bench.connections =
+                        raw.parse::<usize>().context("invalid --connections value")?;
+                }
+                "--drain-timeout" => {
+                    let raw = it.next().context("missing value for --drain-timeout")?;
+                    bench.drain_timeout =
+                        humantime::parse_duration(&raw).context("invalid --drain-timeout value")?;
+                }
+                "-h" | "--help" => {
+                    print_usage();
+                    std::process::exit(0);
+                }
+                _ if arg.starts_with('-') => bail!("unknown argument: {arg}"),
+                _ if command.is_none() => command = Some(arg),
+                _ if hash.is_none() => hash = Some(arg),
+                _ => bail!("unexpected argument: {arg}")
+            }
+        }
+
+        let command = match command.as_deref() {
+            Some("status") => Command::Query(QueryRequest::Status {
+                hash: hash.context("status requires a message hash")?
+            }),
+            Some("recent-bounces") => {
+                Command::Query(QueryRequest::RecentBounces { since_secs: since.as_secs(), limit })
+            }
+            Some("stats") => Command::Query(QueryRequest::Stats),
+            Some("sources") => Command::Query(QueryRequest::Sources),
+            Some("source-events") => Command::Query(QueryRequest::SourceEvents { limit }),
+            Some("server-status") => Command::ServerStatus,
+            Some("replay-quarantine") => Command::Query(QueryRequest::ReplayQuarantine),
+            Some("bench") => Command::Bench(bench),
+            Some("support-bundle") => Command::SupportBundle(bundle),
+            Some(other) => bail!("unknown command: {other}"),
+            None => {
+                print_usage();
+                bail!("missing command")
+            }
+        };
+
+        Ok(Self { server, json, timeout, command })
+    }
+}
+
+fn print_usage() {
+    eprintln!(
+        "usage: bouncer-admin [--server 127.0.0.1:2147] [--json] [--timeout 10s] <status HASH | recent-bounces [--since 1h] [--limit 50] | stats | sources | source-events [--limit 50] | server-status | replay-quarantine | bench [--events 1000] [--mails 1000] [--connections 4] [--drain-timeout 5m] | support-bundle [--config bouncer.yaml] [--log-file PATH | --unit bouncer-server] [--lines 200] [--output FILE]>"
+    );
+}
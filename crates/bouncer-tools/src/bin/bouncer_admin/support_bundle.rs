@@ -0,0 +1,192 @@
+//! `bouncer-admin support-bundle`: collects what issue triage usually asks
+//! for into one `.tar.gz`.
+//!
+//! Every part is best effort. A part that cannot be collected (server down,
+//! no config file, no journal access) is listed under `errors` in
+//! `manifest.json` instead of failing the whole bundle.
+
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use bouncer_helpers::config_file;
+use bouncer_proto::query::QueryRequest;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde_json::json;
+use serde_yaml::Value;
+
+use crate::{Args, query_with_timeout};
+
+/// Journal lines scanned for `ERROR_CODE=` entries when no log file is given.
+const JOURNAL_SCAN_LINES: &str = "20000";
+
+#[derive(Debug, Clone)]
+pub struct BundleOptions {
+    pub config: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+    pub unit: String,
+    pub lines: usize,
+    pub output: Option<PathBuf>
+}
+
+impl Default for BundleOptions {
+    fn default() -> Self {
+        Self {
+            config: None,
+            log_file: None,
+            unit: "bouncer-server".to_string(),
+            lines: 200,
+            output: None
+        }
+    }
+}
+
+pub async fn write_bundle(
+    args: &Args,
+    options: &BundleOptions
+) -> Result<PathBuf> {
+    let created_at_unix = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let mut errors = Vec::new();
+    let mut entries: Vec<(&str, Vec<u8>)> = Vec::new();
+
+    for (name, request) in
+        [("stats.json", QueryRequest::Stats), ("sources.json", QueryRequest::Sources)]
+    {
+        match query_with_timeout(args, &request).await.and_then(|response| {
+            serde_json::to_vec_pretty(&response).context("failed to encode response")
+        }) {
+            Ok(body) => entries.push((name, body)),
+            Err(err) => errors.push(format!("{name}: {err:#}"))
+        }
+    }
+
+    let config_path = options.config.clone().or_else(default_config_path);
+    match config_path.as_deref().map(read_sanitized_config) {
+        Some(Ok(config)) => entries.push(("config.yaml", config.into_bytes())),
+        Some(Err(err)) => errors.push(format!("config.yaml: {err:#}")),
+        None => errors.push("config.yaml: no config path (use --config)".to_string())
+    }
+
+    let (log_source, log) = match &options.log_file {
+        Some(path) => {
+            (path.display().to_string(), std::fs::read_to_string(path).map_err(Into::into))
+        }
+        None => (format!("journalctl -u {}", options.unit), read_journal(&options.unit).await)
+    };
+    match log {
+        Ok(text) => {
+            let mut lines = error_code_lines(&text, options.lines).join("\n");
+            lines.push('\n');
+            entries.push(("error_log.txt", lines.into_bytes()));
+        }
+        Err(err) => errors.push(format!("error_log.txt ({log_source}): {err:#}"))
+    }
+
+    let manifest = json!({
+        "created_at_unix": created_at_unix,
+        "bouncer_admin_version": env!("CARGO_PKG_VERSION"),
+        "server": args.server,
+        "config_path": config_path.as_ref().map(|path| path.display().to_string()),
+        "log_source": log_source,
+        "error_log_lines": options.lines,
+        "errors": errors
+    });
+    entries.insert(
+        0,
+        (
+            "manifest.json",
+            serde_json::to_vec_pretty(&manifest).context("failed to encode manifest")?
+        )
+    );
+
+    let output = options
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("bouncer-support-{created_at_unix}.tar.gz")));
+    write_tarball(&output, created_at_unix, &entries)?;
+
+    for error in &errors {
+        eprintln!("support bundle: skipped {error}");
+    }
+    Ok(output)
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    env::var_os("BOUNCER_CONFIG_PATH")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from("bouncer.yaml")).filter(|path| path.is_file()))
+}
+
+fn read_sanitized_config(path: &Path) -> Result<String> {
+    let raw = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut config: Value = serde_yaml::from_slice(&raw)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    config_file::redact_secrets(&mut config);
+    serde_yaml::to_string(&config).context("failed to encode sanitized config")
+}
+
+async fn read_journal(unit: &str) -> Result<String> {
+    let output = tokio::process::Command::new("journalctl")
+        .args(["-u", unit, "--no-pager", "-o", "short-iso", "-n", JOURNAL_SCAN_LINES])
+        .output()
+        .await
+        .context("failed to run journalctl (use --log-file)")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "journalctl exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Last `limit` lines carrying an `ERROR_CODE=` marker, oldest first.
+fn error_code_lines(
+    text: &str,
+    limit: usize
+) -> Vec<&str> {
+    let mut lines: Vec<&str> =
+        text.lines().rev().filter(|line| line.contains("ERROR_CODE=")).take(limit).collect();
+    lines.reverse();
+    lines
+}
+
+fn write_tarball(
+    output: &Path,
+    mtime: u64,
+    entries: &[(&str, Vec<u8>)]
+) -> Result<()> {
+    let file =
+        File::create(output).with_context(|| format!("failed to create {}", output.display()))?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    for (name, data) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o600);
+        header.set_mtime(mtime);
+        tar.append_data(&mut header, format!("bouncer-support/{name}"), data.as_slice())
+            .with_context(|| format!("failed to add {name} to bundle"))?;
+    }
+
+    tar.into_inner()
+        .context("failed to finish bundle archive")?
+        .finish()
+        .context("failed to finish bundle compression")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::error_code_lines;
+
+    #[test]
+    fn keeps_last_error_code_lines_in_order() {
+        let log = "a ERROR_CODE=X one\nplain\nb ERROR_CODE=Y two\nc ERROR_CODE=Z three\n";
+        assert_eq!(error_code_lines(log, 2), ["b ERROR_CODE=Y two", "c ERROR_CODE=Z three"]);
+    }
+}
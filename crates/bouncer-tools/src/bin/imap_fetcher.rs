@@ -5,6 +5,7 @@ use anyhow::{Context, Result};
 use async_imap::Client;
 use async_imap::types::Uid;
 use async_native_tls::TlsConnector;
+use bouncer_helpers::oauth2::{OAuth2Config, TokenSource, XOAuth2};
 use futures_util::TryStreamExt;
 use tokio::net::TcpStream;
 
@@ -32,11 +33,34 @@ async fn main() -> Result<()> {
         .context("failed to read imap greeting")?
         .context("unexpected EOF while waiting IMAP greeting")?;
 
-    let mut session = client
-        .login(args.user.as_str(), args.pass.as_str())
-        .await
-        .map_err(|(err, _client)| err)
-        .with_context(|| format!("imap login failed: host={}, user={}", args.host, args.user))?;
+    let mut session = match (&args.oauth2, &args.pass) {
+        (Some(oauth2), _) => {
+            let token = TokenSource::new(oauth2.clone())
+                .context("imap oauth2 client setup failed")?
+                .access_token()
+                .await
+                .context("imap oauth2 token failed")?;
+            let authenticator = XOAuth2::new(&args.user, &token);
+            client
+                .authenticate("XOAUTH2", authenticator)
+                .await
+                .map_err(|(err, _client)| err)
+                .with_context(|| {
+                    format!(
+                        "imap XOAUTH2 authentication failed: host={}, user={}",
+                        args.host, args.user
+                    )
+                })?
+        }
+        (None, Some(pass)) => client
+            .login(args.user.as_str(), pass.as_str())
+            .await
+            .map_err(|(err, _client)| err)
+            .with_context(|| {
+                format!("imap login failed: host={}, user={}", args.host, args.user)
+            })?,
+        (None, None) => unreachable!("Args::parse requires --pass or an oauth2 flag")
+    };
 
     session
         .select(&args.mailbox)
@@ -103,7 +127,9 @@ struct Args {
     host: String,
     port: u16,
     user: String,
-    pass: String,
+    pass: Option<String>,
+    /// Set by any `--oauth2-*` flag; used instead of `pass`.
+    oauth2: Option<OAuth2Config>,
     mailbox: String,
     search: String,
     limit: usize,
@@ -119,6 +145,7 @@ impl Args {
         let mut port = 993u16;
         let mut user = None;
         let mut pass = None;
+        let mut oauth2 = OAuth2Config::default();
         let mut mailbox = "INBOX".to_string();
         let mut search = "UNSEEN".to_string();
         let mut limit = 50usize;
@@ -133,6 +160,12 @@ impl Args {
                 }
                 "--user" => user = it.next(),
                 "--pass" => pass = it.next(),
+                "--oauth2-token-url" => oauth2.token_url = it.next(),
+                "--oauth2-client-id" => oauth2.client_id = it.next(),
+                "--oauth2-client-secret" => oauth2.client_secret = it.next(),
+                "--oauth2-refresh-token" => oauth2.refresh_token = it.next(),
+                "--oauth2-scope" => oauth2.scope = it.next(),
+                "--oauth2-token-command" => oauth2.token_command = it.next(),
                 "--mailbox" => {
                     mailbox = it.next().context("missing value for --mailbox")?;
                 }
@@ -155,11 +188,32 @@ impl Args {
             }
         }
 
+        let oauth2 = match oauth2 {
+            OAuth2Config {
+                token_url: None,
+                client_id: None,
+                client_secret: None,
+                refresh_token: None,
+                scope: None,
+                token_command: None
+            } => None,
+            oauth2 => {
+                if let Some(problem) = oauth2.problem() {
+                    anyhow::bail!("oauth2 login {problem} (as --oauth2-* flags)");
+                }
+                Some(oauth2)
+            }
+        };
+        if oauth2.is_none() && pass.is_none() {
+            anyhow::bail!("missing --pass (or --oauth2-* flags)");
+        }
+
         Ok(Self {
             host: host.context("missing --host")?,
             port,
             user: user.context("missing --user")?,
-            pass: pass.context("missing --pass")?,
+            pass,
+            oauth2,
             mailbox,
             search,
             limit,
@@ -170,7 +224,10 @@ impl Args {
 
 fn print_usage() {
     eprintln!(
-        "usage: imap_fetcher --host HOST --user USER --pass PASS [--port 993] [--mailbox INBOX] [--search UNSEEN] [--limit 50] [--output-dir tests/bounces]"
+        "usage: imap_fetcher --host HOST --user USER (--pass PASS | OAUTH2) [--port 993] [--mailbox INBOX] [--search UNSEEN] [--limit 50] [--output-dir tests/bounces]"
+    );
+    eprintln!(
+        "  OAUTH2: --oauth2-token-command CMD | --oauth2-token-url URL --oauth2-client-id ID --oauth2-refresh-token TOKEN [--oauth2-client-secret SECRET] [--oauth2-scope SCOPE]"
     );
 }
 
@@ -181,10 +238,11 @@ impl fmt::Display for Args {
     ) -> fmt::Result {
         write!(
             f,
-            "host={}, port={}, user={}, mailbox={}, search={}, limit={}, output_dir={}",
+            "host={}, port={}, user={}, auth={}, mailbox={}, search={}, limit={}, output_dir={}",
             self.host,
             self.port,
             self.user,
+            if self.oauth2.is_some() { "xoauth2" } else { "password" },
             self.mailbox,
             self.search,
             self.limit,
@@ -0,0 +1,15 @@
+#![no_main]
+
+use std::sync::LazyLock;
+
+use bouncer_core::ParserChain;
+use libfuzzer_sys::fuzz_target;
+
+static PARSERS: LazyLock<ParserChain> = LazyLock::new(ParserChain::default);
+
+// Raw mail from the network: any bytes must parse or fail, never panic or hang.
+fuzz_target!(|raw_mail: &[u8]| {
+    if let Ok(parsed) = PARSERS.parse_detailed(raw_mail) {
+        let _ = parsed.reason();
+    }
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use bouncer_observer::{LogFormat, fuzz_parse_line};
+use libfuzzer_sys::fuzz_target;
+
+// The first byte picks the log format, the rest is the line.
+fuzz_target!(|data: &[u8]| {
+    let Some((selector, line)) = data.split_first() else {
+        return;
+    };
+    let format = match selector % 3 {
+        0 => LogFormat::Postfix,
+        1 => LogFormat::Exim,
+        _ => LogFormat::Sendmail
+    };
+    fuzz_parse_line(format, &String::from_utf8_lossy(line));
+});